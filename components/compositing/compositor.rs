@@ -7,7 +7,7 @@ use compositor_layer::WantsScrollEvents;
 use compositor_task::{ChangeReadyState, ChangeRenderState, CompositorEventListener};
 use compositor_task::{CompositorProxy, CompositorReceiver, CompositorTask};
 use compositor_task::{CreateOrUpdateDescendantLayer, CreateOrUpdateRootLayer, Exit};
-use compositor_task::{FrameTreeUpdateMsg, GetGraphicsMetadata, LayerProperties};
+use compositor_task::{FrameTreeUpdateMsg, GetGraphicsMetadata, InvalidateLayers, LayerProperties};
 use compositor_task::{LoadComplete, Msg, Paint, RenderMsgDiscarded, ScrollFragmentPoint};
 use compositor_task::{ScrollTimeout, SetIds, SetLayerOrigin, ShutdownComplete};
 use constellation::{SendableFrameTree, FrameTreeDiff};
@@ -25,6 +25,7 @@ use azure::azure_hl;
 use std::cmp;
 use std::mem;
 use std::num::Zero;
+use geom::matrix::identity;
 use geom::point::{Point2D, TypedPoint2D};
 use geom::rect::{Rect, TypedRect};
 use geom::size::TypedSize2D;
@@ -301,6 +302,10 @@ impl<Window: WindowMethods> IOCompositor<Window> {
                 self.remove_outstanding_render_msg();
             }
 
+            (InvalidateLayers(pipeline_id, epoch, layer_ids), NotShuttingDown) => {
+                self.invalidate_layers(pipeline_id, epoch, layer_ids);
+            }
+
             (ScrollFragmentPoint(pipeline_id, layer_id, point), NotShuttingDown) => {
                 self.scroll_fragment_to_point(pipeline_id, layer_id, point);
             }
@@ -611,6 +616,27 @@ impl<Window: WindowMethods> IOCompositor<Window> {
         }
     }
 
+    fn invalidate_layers(&mut self,
+                         pipeline_id: PipelineId,
+                         epoch: Epoch,
+                         layer_ids: Vec<LayerId>) {
+        let mut invalidated = false;
+        for layer_id in layer_ids.into_iter() {
+            match self.find_layer_with_pipeline_and_layer_id(pipeline_id, layer_id) {
+                // Layers from an older epoch have been rebuilt and repainted since.
+                Some(ref layer) if layer.extra_data.borrow().epoch == epoch => {
+                    layer.contents_changed();
+                    invalidated = true;
+                }
+                Some(_) | None => {}
+            }
+        }
+
+        if invalidated {
+            self.send_buffer_requests_for_all_layers();
+        }
+    }
+
     fn scroll_fragment_to_point(&mut self,
                                 pipeline_id: PipelineId,
                                 layer_id: LayerId,
@@ -1128,6 +1154,7 @@ fn create_root_layer_for_pipeline_and_rect(pipeline: &CompositionPipeline,
         rect: Rect::zero(),
         background_color: azure_hl::Color::new(0., 0., 0., 0.),
         scroll_policy: Scrollable,
+        transform: identity(),
     };
 
     let root_layer = CompositorData::new_layer(pipeline.clone(),
@@ -10,7 +10,7 @@ use windowing::WindowMethods;
 
 use azure::azure_hl;
 use geom::length::Length;
-use geom::matrix::identity;
+use geom::matrix::{Matrix4, identity};
 use geom::point::{Point2D, TypedPoint2D};
 use geom::size::{Size2D, TypedSize2D};
 use geom::rect::Rect;
@@ -44,6 +44,10 @@ pub struct CompositorData {
     /// The scroll offset originating from this scrolling root. This allows scrolling roots
     /// to track their current scroll position even while their content_offset does not change.
     pub scroll_offset: TypedPoint2D<LayerPixel, f32>,
+
+    /// The CSS transform of this layer. The transform of the layer itself also includes the
+    /// scroll offset, which applies after this one.
+    pub transform: Matrix4<f32>,
 }
 
 impl CompositorData {
@@ -59,12 +63,15 @@ impl CompositorData {
             scroll_policy: layer_properties.scroll_policy,
             epoch: layer_properties.epoch,
             scroll_offset: TypedPoint2D(0., 0.),
+            transform: layer_properties.transform,
         };
 
-        Rc::new(Layer::new(Rect::from_untyped(&layer_properties.rect),
-                           tile_size,
-                           to_layers_color(&layer_properties.background_color),
-                           new_compositor_data))
+        let layer = Rc::new(Layer::new(Rect::from_untyped(&layer_properties.rect),
+                                       tile_size,
+                                       to_layers_color(&layer_properties.background_color),
+                                       new_compositor_data));
+        *layer.transform.borrow_mut() = layer_properties.transform;
+        layer
     }
 }
 
@@ -167,6 +174,13 @@ impl CompositorLayer for Layer<CompositorData> {
         self.extra_data.borrow_mut().epoch = layer_properties.epoch;
         self.extra_data.borrow_mut().scroll_policy = layer_properties.scroll_policy;
 
+        self.extra_data.borrow_mut().transform = layer_properties.transform;
+        let content_offset = self.content_offset.borrow().to_untyped();
+        *self.transform.borrow_mut() = identity().translate(content_offset.x,
+                                                            content_offset.y,
+                                                            0.0)
+                                                 .mul(&layer_properties.transform);
+
         *self.background_color.borrow_mut() = to_layers_color(&layer_properties.background_color);
 
         self.contents_changed();
@@ -343,7 +357,8 @@ impl CompositorLayer for Layer<CompositorData> {
             let new_offset = new_offset.to_untyped();
             *self.transform.borrow_mut() = identity().translate(new_offset.x,
                                                                  new_offset.y,
-                                                                 0.0);
+                                                                 0.0)
+                                                      .mul(&self.extra_data.borrow().transform);
             *self.content_offset.borrow_mut() = Point2D::from_untyped(&new_offset);
             result = true
         }
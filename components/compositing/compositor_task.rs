@@ -12,6 +12,7 @@ use headless;
 use windowing::{WindowEvent, WindowMethods};
 
 use azure::azure_hl::{SourceSurfaceMethods, Color};
+use geom::matrix::Matrix4;
 use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
@@ -90,6 +91,7 @@ pub struct LayerProperties {
     pub rect: Rect<f32>,
     pub background_color: Color,
     pub scroll_policy: ScrollPolicy,
+    pub transform: Matrix4<f32>,
 }
 
 impl LayerProperties {
@@ -104,6 +106,7 @@ impl LayerProperties {
                               metadata.position.size.height as f32)),
             background_color: metadata.background_color,
             scroll_policy: metadata.scroll_policy,
+            transform: metadata.transform,
         }
     }
 }
@@ -142,6 +145,13 @@ impl RenderListener for Box<CompositorProxy+'static+Send> {
         }
     }
 
+    fn invalidate_layers(&mut self,
+                         pipeline_id: PipelineId,
+                         epoch: Epoch,
+                         layer_ids: Vec<LayerId>) {
+        self.send(InvalidateLayers(pipeline_id, epoch, layer_ids));
+    }
+
     fn render_msg_discarded(&mut self) {
         self.send(RenderMsgDiscarded);
     }
@@ -180,6 +190,9 @@ pub enum Msg {
     ScrollFragmentPoint(PipelineId, LayerId, Point2D<f32>),
     /// Requests that the compositor paint the given layer buffer set for the given page size.
     Paint(PipelineId, Epoch, Vec<(LayerId, Box<LayerBufferSet>)>),
+    /// Tells the compositor that the contents of the given layers changed, so that it requests
+    /// new tiles for them.
+    InvalidateLayers(PipelineId, Epoch, Vec<LayerId>),
     /// Alerts the compositor to the current status of page loading.
     ChangeReadyState(PipelineId, ReadyState),
     /// Alerts the compositor to the current status of rendering.
@@ -208,6 +221,7 @@ impl Show for Msg {
             SetLayerOrigin(..) => write!(f, "SetLayerOrigin"),
             ScrollFragmentPoint(..) => write!(f, "ScrollFragmentPoint"),
             Paint(..) => write!(f, "Paint"),
+            InvalidateLayers(..) => write!(f, "InvalidateLayers"),
             ChangeReadyState(..) => write!(f, "ChangeReadyState"),
             ChangeRenderState(..) => write!(f, "ChangeRenderState"),
             RenderMsgDiscarded(..) => write!(f, "RenderMsgDiscarded"),
@@ -23,9 +23,10 @@ use servo_msg::constellation_msg::{IFrameSandboxState, IFrameUnsandboxed, InitLo
 use servo_msg::constellation_msg::{LoadCompleteMsg, LoadUrlMsg, LoadData, Msg, NavigateMsg};
 use servo_msg::constellation_msg::{NavigationType, PipelineId, RendererReadyMsg, ResizedWindowMsg};
 use servo_msg::constellation_msg::{ScriptLoadedURLInIFrameMsg, SubpageId, WindowSizeData};
+use servo_msg::constellation_msg::TopLevelBrowsingContextId;
 use servo_msg::constellation_msg::{KeyEvent, Key, KeyState, KeyModifiers};
 use servo_msg::constellation_msg;
-use servo_net::image_cache_task::{ImageCacheTask, ImageCacheTaskClient};
+use servo_net::image_cache_task::{ImageCacheTask, ImageCacheTaskClient, ReleaseOwner};
 use servo_net::resource_task::ResourceTask;
 use servo_net::resource_task;
 use servo_net::storage_task::StorageTask;
@@ -73,6 +74,9 @@ pub struct Constellation<LTF, STF> {
 
     navigation_context: NavigationContext,
 
+    /// The single top-level browsing context whose pipelines this constellation manages.
+    browsing_context_id: TopLevelBrowsingContextId,
+
     /// The next free ID to assign to a pipeline.
     next_pipeline_id: PipelineId,
 
@@ -333,6 +337,7 @@ impl<LTF: LayoutTaskFactory, STF: ScriptTaskFactory> Constellation<LTF, STF> {
                 storage_task: storage_task,
                 pipelines: HashMap::new(),
                 navigation_context: NavigationContext::new(),
+                browsing_context_id: TopLevelBrowsingContextId::next(),
                 next_pipeline_id: PipelineId(0),
                 pending_frames: vec!(),
                 pending_sizes: HashMap::new(),
@@ -366,6 +371,7 @@ impl<LTF: LayoutTaskFactory, STF: ScriptTaskFactory> Constellation<LTF, STF> {
                     -> Rc<Pipeline> {
             let pipe = Pipeline::create::<LTF, STF>(id,
                                                     subpage_id,
+                                                    self.browsing_context_id,
                                                     self.chan.clone(),
                                                     self.compositor_proxy.clone_compositor_proxy(),
                                                     self.devtools_chan.clone(),
@@ -473,6 +479,7 @@ impl<LTF: LayoutTaskFactory, STF: ScriptTaskFactory> Constellation<LTF, STF> {
         for (_id, ref pipeline) in self.pipelines.iter() {
             pipeline.exit();
         }
+        self.storage_task.send(storage_task::ClearSessionStorage(self.browsing_context_id));
         self.image_cache_task.exit();
         self.resource_task.send(resource_task::Exit);
         self.devtools_chan.as_ref().map(|chan| {
@@ -512,6 +519,7 @@ impl<LTF: LayoutTaskFactory, STF: ScriptTaskFactory> Constellation<LTF, STF> {
         }
         force_pipeline_exit(&old_pipeline);
         self.pipelines.remove(&pipeline_id);
+        self.release_pipeline_images(pipeline_id);
 
         loop {
             let idx = self.pending_frames.iter().position(|pending| {
@@ -916,9 +924,16 @@ impl<LTF: LayoutTaskFactory, STF: ScriptTaskFactory> Constellation<LTF, STF> {
         for frame_tree in frame_tree.iter() {
             frame_tree.pipeline.exit();
             self.pipelines.remove(&frame_tree.pipeline.id);
+            self.release_pipeline_images(frame_tree.pipeline.id);
         }
     }
 
+    /// Lets the image cache drop the images that only an exited pipeline was using.
+    fn release_pipeline_images(&self, pipeline_id: PipelineId) {
+        let PipelineId(owner) = pipeline_id;
+        self.image_cache_task.send(ReleaseOwner(owner));
+    }
+
     fn handle_evicted_frames(&mut self, evicted: Vec<Rc<FrameTree>>) {
         for frame_tree in evicted.into_iter() {
             if !self.navigation_context.contains(frame_tree.pipeline.id) {
@@ -6,6 +6,7 @@ use compositor_task::{GetGraphicsMetadata, CreateOrUpdateRootLayer, CreateOrUpda
 use compositor_task::{Exit, ChangeReadyState, LoadComplete, Paint, ScrollFragmentPoint, SetIds};
 use compositor_task::{SetLayerOrigin, ShutdownComplete, ChangeRenderState, RenderMsgDiscarded};
 use compositor_task::{CompositorEventListener, CompositorReceiver, ScrollTimeout, FrameTreeUpdateMsg};
+use compositor_task::InvalidateLayers;
 use windowing::WindowEvent;
 
 use geom::scale_factor::ScaleFactor;
@@ -102,7 +103,7 @@ impl CompositorEventListener for NullCompositor {
 
             CreateOrUpdateRootLayer(..) |
             CreateOrUpdateDescendantLayer(..) |
-            SetLayerOrigin(..) | Paint(..) |
+            SetLayerOrigin(..) | Paint(..) | InvalidateLayers(..) |
             ChangeReadyState(..) | ChangeRenderState(..) | ScrollFragmentPoint(..) |
             LoadComplete | RenderMsgDiscarded(..) | ScrollTimeout(..) => ()
         }
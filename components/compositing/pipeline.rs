@@ -11,7 +11,7 @@ use devtools_traits::DevtoolsControlChan;
 use gfx::render_task::{PaintPermissionGranted, PaintPermissionRevoked};
 use gfx::render_task::{RenderChan, RenderTask};
 use servo_msg::constellation_msg::{ConstellationChan, Failure, PipelineId, SubpageId};
-use servo_msg::constellation_msg::{LoadData, WindowSizeData};
+use servo_msg::constellation_msg::{LoadData, TopLevelBrowsingContextId, WindowSizeData};
 use servo_net::image_cache_task::ImageCacheTask;
 use gfx::font_cache_task::FontCacheTask;
 use servo_net::resource_task::ResourceTask;
@@ -47,6 +47,7 @@ impl Pipeline {
     pub fn create<LTF:LayoutTaskFactory, STF:ScriptTaskFactory>(
                       id: PipelineId,
                       subpage_id: Option<SubpageId>,
+                      browsing_context_id: TopLevelBrowsingContextId,
                       constellation_chan: ConstellationChan,
                       compositor_proxy: Box<CompositorProxy+'static+Send>,
                       devtools_chan: Option<DevtoolsControlChan>,
@@ -75,6 +76,7 @@ impl Pipeline {
                 let (script_chan, script_port) = channel();
                 ScriptTaskFactory::create(None::<&mut STF>,
                                           id,
+                                          browsing_context_id,
                                           compositor_proxy.clone_compositor_proxy(),
                                           &layout_pair,
                                           ScriptControlChan(script_chan.clone()),
@@ -16,13 +16,14 @@
 
 use color::Color;
 use display_list::optimizer::DisplayListOptimizer;
-use render_context::{RenderContext, ToAzureRect};
+use render_context::{RenderContext, ToAzureMatrix, ToAzurePoint, ToAzureRect};
 use text::glyph::CharIndex;
 use text::TextRun;
 
 use azure::azure::AzFloat;
 use collections::dlist::{mod, DList};
 use geom::{Point2D, Rect, SideOffsets2D, Size2D, Matrix2D};
+use geom::matrix::Matrix4;
 use libc::uintptr_t;
 use render_task::RenderLayer;
 use script_traits::UntrustedNodeAddress;
@@ -32,8 +33,10 @@ use servo_util::dlist as servo_dlist;
 use servo_util::geometry::{mod, Au};
 use servo_util::range::Range;
 use servo_util::smallvec::{SmallVec, SmallVec8};
+use std::cmp;
 use std::fmt;
 use std::mem;
+use std::num::Zero;
 use std::slice::Items;
 use style::computed_values::border_style;
 use sync::Arc;
@@ -50,7 +53,7 @@ pub mod optimizer;
 /// Because the script task's GC does not trace layout, node data cannot be safely stored in layout
 /// data structures. Also, layout code tends to be faster when the DOM is not being accessed, for
 /// locality reasons. Using `OpaqueNode` enforces this invariant.
-#[deriving(Clone, PartialEq)]
+#[deriving(Clone, PartialEq, Eq, Hash)]
 pub struct OpaqueNode(pub uintptr_t);
 
 impl OpaqueNode {
@@ -146,34 +149,109 @@ pub struct StackingContext {
     pub clip_rect: Rect<Au>,
     /// The `z-index` for this stacking context.
     pub z_index: i32,
+    /// The transform of this stacking context, in its own coordinate system. It applies after the
+    /// translation to the origin of `bounds`.
+    pub transform: Matrix4<AzFloat>,
+    /// The opacity with which this stacking context is composited as a group.
+    pub opacity: AzFloat,
 }
 
 impl StackingContext {
-    /// Creates a new stacking context.
+    /// Creates a new stacking context. Its content is clipped to `overflow`, which is relative to
+    /// the origin of `bounds` and may extend past them, as box shadows do.
     ///
     /// TODO(pcwalton): Stacking contexts should not always be clipped to their bounds, to handle
     /// overflow properly.
     #[inline]
     pub fn new(display_list: Box<DisplayList>,
                bounds: Rect<Au>,
+               overflow: Rect<Au>,
                z_index: i32,
+               transform: Matrix4<AzFloat>,
+               opacity: AzFloat,
                layer: Option<Arc<RenderLayer>>)
                -> StackingContext {
+        // Content is clipped to the overflow as the parent sees it once it is transformed.
+        let local_bounds =
+            Rect(Point2D(geometry::to_frac_px(overflow.origin.x) as AzFloat,
+                         geometry::to_frac_px(overflow.origin.y) as AzFloat),
+                 Size2D(geometry::to_frac_px(overflow.size.width) as AzFloat,
+                        geometry::to_frac_px(overflow.size.height) as AzFloat));
+        let transformed_bounds = transform_rect(&transform, &local_bounds);
         StackingContext {
             display_list: display_list,
             layer: layer,
             bounds: bounds,
-            clip_rect: bounds,
+            clip_rect: geometry::f32_rect_to_au_rect(transformed_bounds).translate(&bounds.origin),
             z_index: z_index,
+            transform: transform,
+            opacity: opacity,
         }
     }
 
-    /// Draws the stacking context in the proper order according to the steps in CSS 2.1 § E.2.
+    /// Draws the stacking context, as a group if it is not opaque.
     pub fn optimize_and_draw_into_context(&self,
                                           render_context: &mut RenderContext,
                                           tile_bounds: &Rect<AzFloat>,
                                           current_transform: &Matrix2D<AzFloat>,
                                           current_clip_stack: &mut Vec<Rect<Au>>) {
+        if self.opacity == 0.0 {
+            return
+        }
+
+        let opacity = self.opacity;
+        if opacity == 1.0 {
+            return self.optimize_and_draw_contents_into_context(render_context,
+                                                                tile_bounds,
+                                                                current_transform,
+                                                                current_clip_stack)
+        }
+
+        // The group only needs to cover the part of the tile that it is drawn in.
+        let device_rect = match render_context.device_rect_for(tile_bounds, current_transform) {
+            Some(device_rect) => device_rect,
+            None => return,
+        };
+        let temporary_draw_target = render_context.create_temporary_draw_target(&device_rect);
+        let identity: Matrix2D<AzFloat> = Matrix2D::identity();
+        let group_transform = identity.translate(-device_rect.origin.x, -device_rect.origin.y)
+                                      .mul(current_transform);
+
+        {
+            let mut group_render_context = RenderContext {
+                draw_target: &temporary_draw_target,
+                font_ctx: &mut *render_context.font_ctx,
+                page_rect: render_context.page_rect,
+                screen_rect: Rect(render_context.screen_rect.origin +
+                                      Point2D(device_rect.origin.x as uint,
+                                              device_rect.origin.y as uint),
+                                  Size2D(device_rect.size.width as uint,
+                                         device_rect.size.height as uint)),
+                animation_time: render_context.animation_time,
+            };
+            let mut group_clip_stack = Vec::new();
+            self.optimize_and_draw_contents_into_context(&mut group_render_context,
+                                                         tile_bounds,
+                                                         &group_transform,
+                                                         &mut group_clip_stack);
+        }
+
+        // The group was clipped as it was drawn, so the last clip of the parent must not apply to
+        // it too.
+        while current_clip_stack.len() != 0 {
+            render_context.draw_pop_clip();
+            drop(current_clip_stack.pop());
+        }
+        render_context.draw_temporary_draw_target(&temporary_draw_target, &device_rect, opacity)
+    }
+
+    /// Draws the contents of the stacking context in the proper order according to the steps in
+    /// CSS 2.1 § E.2.
+    fn optimize_and_draw_contents_into_context(&self,
+                                               render_context: &mut RenderContext,
+                                               tile_bounds: &Rect<AzFloat>,
+                                               current_transform: &Matrix2D<AzFloat>,
+                                               current_clip_stack: &mut Vec<Rect<Au>>) {
         // Optimize the display list to throw out out-of-bounds display items and so forth.
         let display_list = DisplayListOptimizer::new(tile_bounds).optimize(&*self.display_list);
 
@@ -199,7 +277,8 @@ impl StackingContext {
                     current_transform.translate(positioned_kid.bounds.origin.x.to_nearest_px()
                                                     as AzFloat,
                                                 positioned_kid.bounds.origin.y.to_nearest_px()
-                                                    as AzFloat);
+                                                    as AzFloat)
+                                     .mul(&positioned_kid.transform.to_azure_matrix());
                 let new_tile_rect =
                     self.compute_tile_rect_for_child_stacking_context(tile_bounds,
                                                                       &**positioned_kid);
@@ -238,7 +317,8 @@ impl StackingContext {
                     current_transform.translate(positioned_kid.bounds.origin.x.to_nearest_px()
                                                     as AzFloat,
                                                 positioned_kid.bounds.origin.y.to_nearest_px()
-                                                    as AzFloat);
+                                                    as AzFloat)
+                                     .mul(&positioned_kid.transform.to_azure_matrix());
                 let new_tile_rect =
                     self.compute_tile_rect_for_child_stacking_context(tile_bounds,
                                                                       &**positioned_kid);
@@ -257,22 +337,30 @@ impl StackingContext {
                                                     tile_bounds: &Rect<AzFloat>,
                                                     child_stacking_context: &StackingContext)
                                                     -> Rect<AzFloat> {
-        static ZERO_AZURE_RECT: Rect<f32> = Rect {
-            origin: Point2D {
-                x: 0.0,
-                y: 0.0,
-            },
-            size: Size2D {
-                width: 0.0,
-                height: 0.0
-            }
-        };
-
-        let child_stacking_context_bounds = child_stacking_context.bounds.to_azure_rect();
+        let child_stacking_context_bounds = child_stacking_context.clip_rect.to_azure_rect();
         let tile_subrect = tile_bounds.intersection(&child_stacking_context_bounds)
                                       .unwrap_or(ZERO_AZURE_RECT);
-        let offset = tile_subrect.origin - child_stacking_context_bounds.origin;
-        Rect(offset, tile_subrect.size)
+        let offset = tile_subrect.origin - child_stacking_context.bounds.origin.to_azure_point();
+        match invert(&child_stacking_context.transform) {
+            Some(inverse) => untransform_rect(&inverse, &Rect(offset, tile_subrect.size)),
+            None => ZERO_AZURE_RECT,
+        }
+    }
+
+    /// Translates the given point from the coordinate system of the parent stacking context into
+    /// that of this one. Returns `None` if the transform of this stacking context flattens it, so
+    /// that nothing in it can be hit.
+    fn to_local_point(&self, point: Point2D<Au>) -> Option<Point2D<Au>> {
+        let offset = point - self.bounds.origin;
+        let offset = Point2D(geometry::to_frac_px(offset.x) as AzFloat,
+                             geometry::to_frac_px(offset.y) as AzFloat);
+        let local_point = match invert(&self.transform) {
+            Some(inverse) => untransform_point(&inverse, &offset),
+            None => None,
+        };
+        local_point.map(|local_point| {
+            Point2D(Au::from_frac32_px(local_point.x), Au::from_frac32_px(local_point.y))
+        })
     }
 
     /// Places all nodes containing the point of interest into `result`, topmost first. If
@@ -300,15 +388,26 @@ impl StackingContext {
 
         debug_assert!(!topmost_only || result.is_empty());
 
+        // Sort positioned children according to z-index, so that they are hit in the order in
+        // which they are painted.
+        let mut positioned_children = SmallVec8::new();
+        for kid in self.display_list.children.iter() {
+            positioned_children.push((*kid).clone());
+        }
+        positioned_children.as_slice_mut().sort_by(|this, other| this.z_index.cmp(&other.z_index));
+
         // Iterate through display items in reverse stacking order. Steps here refer to the
         // painting steps in CSS 2.1 Appendix E.
         //
         // Steps 9 and 8: Positioned descendants with nonnegative z-indices.
-        for kid in self.display_list.children.iter().rev() {
+        for kid in positioned_children.iter().rev() {
             if kid.z_index < 0 {
                 continue
             }
-            kid.hit_test(point, result, topmost_only);
+            match kid.to_local_point(point) {
+                Some(local_point) => kid.hit_test(local_point, result, topmost_only),
+                None => continue,
+            }
             if topmost_only && !result.is_empty() {
                 return
             }
@@ -329,11 +428,14 @@ impl StackingContext {
         }
 
         // Step 3: Positioned descendants with negative z-indices.
-        for kid in self.display_list.children.iter().rev() {
+        for kid in positioned_children.iter().rev() {
             if kid.z_index >= 0 {
                 continue
             }
-            kid.hit_test(point, result, topmost_only);
+            match kid.to_local_point(point) {
+                Some(local_point) => kid.hit_test(local_point, result, topmost_only),
+                None => continue,
+            }
             if topmost_only && !result.is_empty() {
                 return
             }
@@ -347,6 +449,142 @@ impl StackingContext {
     }
 }
 
+static ZERO_AZURE_RECT: Rect<AzFloat> = Rect {
+    origin: Point2D {
+        x: 0.0,
+        y: 0.0,
+    },
+    size: Size2D {
+        width: 0.0,
+        height: 0.0
+    }
+};
+
+/// Applies a transform to a point in the plane of the page, projecting the result back onto it.
+fn transform_point(matrix: &Matrix4<AzFloat>, point: &Point2D<AzFloat>) -> Point2D<AzFloat> {
+    let w = point.x * matrix.m14 + point.y * matrix.m24 + matrix.m44;
+    Point2D((point.x * matrix.m11 + point.y * matrix.m21 + matrix.m41) / w,
+            (point.x * matrix.m12 + point.y * matrix.m22 + matrix.m42) / w)
+}
+
+/// Finds the point in the plane of a transformed stacking context that is drawn at the given
+/// point, given the inverse of its transform. Returns `None` if the plane is seen edge-on.
+fn untransform_point(inverse: &Matrix4<AzFloat>, point: &Point2D<AzFloat>)
+                     -> Option<Point2D<AzFloat>> {
+    // Every point along the line of sight through the given point is drawn there. Find the one
+    // that the inverse transform puts back at z = 0, in homogeneous coordinates.
+    let project = |z: AzFloat| {
+        [point.x * inverse.m11 + point.y * inverse.m21 + z * inverse.m31 + inverse.m41,
+         point.x * inverse.m12 + point.y * inverse.m22 + z * inverse.m32 + inverse.m42,
+         point.x * inverse.m13 + point.y * inverse.m23 + z * inverse.m33 + inverse.m43,
+         point.x * inverse.m14 + point.y * inverse.m24 + z * inverse.m34 + inverse.m44]
+    };
+    let (near, far) = (project(0.0), project(1.0));
+    let dz = far[2] - near[2];
+    if dz == 0.0 {
+        return if near[2] == 0.0 && near[3] != 0.0 {
+            Some(Point2D(near[0] / near[3], near[1] / near[3]))
+        } else {
+            None
+        }
+    }
+    let t = -near[2] / dz;
+    let w = near[3] + t * (far[3] - near[3]);
+    if w == 0.0 {
+        return None
+    }
+    Some(Point2D((near[0] + t * (far[0] - near[0])) / w, (near[1] + t * (far[1] - near[1])) / w))
+}
+
+/// Returns the bounding box of the given points.
+fn bounding_rect(points: &[Point2D<AzFloat>]) -> Rect<AzFloat> {
+    let (mut min, mut max) = (points[0], points[0]);
+    for point in points.iter() {
+        min = Point2D(min.x.min(point.x), min.y.min(point.y));
+        max = Point2D(max.x.max(point.x), max.y.max(point.y));
+    }
+    Rect(min, Size2D(max.x - min.x, max.y - min.y))
+}
+
+fn rect_corners(rect: &Rect<AzFloat>) -> [Point2D<AzFloat>, ..4] {
+    [
+        rect.origin,
+        Point2D(rect.max_x(), rect.origin.y),
+        Point2D(rect.origin.x, rect.max_y()),
+        Point2D(rect.max_x(), rect.max_y()),
+    ]
+}
+
+/// Returns the bounding box of a rect after applying a transform to it.
+fn transform_rect(matrix: &Matrix4<AzFloat>, rect: &Rect<AzFloat>) -> Rect<AzFloat> {
+    let corners = rect_corners(rect);
+    let transformed: Vec<Point2D<AzFloat>> =
+        corners.iter().map(|corner| transform_point(matrix, corner)).collect();
+    bounding_rect(transformed.as_slice())
+}
+
+/// Returns the bounding box of the part of the plane of a transformed stacking context that is
+/// drawn in the given rect, given the inverse of its transform.
+fn untransform_rect(inverse: &Matrix4<AzFloat>, rect: &Rect<AzFloat>) -> Rect<AzFloat> {
+    let corners = rect_corners(rect);
+    let mut untransformed = vec!();
+    for corner in corners.iter() {
+        match untransform_point(inverse, corner) {
+            Some(point) => untransformed.push(point),
+            None => return ZERO_AZURE_RECT,
+        }
+    }
+    bounding_rect(untransformed.as_slice())
+}
+
+/// Returns the inverse of a transform, or `None` if it cannot be inverted.
+fn invert(matrix: &Matrix4<AzFloat>) -> Option<Matrix4<AzFloat>> {
+    let mut m = [[matrix.m11, matrix.m12, matrix.m13, matrix.m14],
+                 [matrix.m21, matrix.m22, matrix.m23, matrix.m24],
+                 [matrix.m31, matrix.m32, matrix.m33, matrix.m34],
+                 [matrix.m41, matrix.m42, matrix.m43, matrix.m44]];
+    let mut inverse = [[1.0, 0.0, 0.0, 0.0],
+                       [0.0, 1.0, 0.0, 0.0],
+                       [0.0, 0.0, 1.0, 0.0],
+                       [0.0, 0.0, 0.0, 1.0]];
+
+    // Gauss-Jordan elimination, pivoting on the largest entry of each column.
+    for column in range(0u, 4) {
+        let mut pivot = column;
+        for row in range(column + 1, 4) {
+            if m[row][column].abs() > m[pivot][column].abs() {
+                pivot = row
+            }
+        }
+        if m[pivot][column] == 0.0 {
+            return None
+        }
+        m.swap(column, pivot);
+        inverse.swap(column, pivot);
+
+        let scale = 1.0 / m[column][column];
+        for i in range(0u, 4) {
+            m[column][i] *= scale;
+            inverse[column][i] *= scale;
+        }
+        for row in range(0u, 4) {
+            if row == column {
+                continue
+            }
+            let factor = m[row][column];
+            for i in range(0u, 4) {
+                m[row][i] -= factor * m[column][i];
+                inverse[row][i] -= factor * inverse[column][i];
+            }
+        }
+    }
+
+    Some(Matrix4(inverse[0][0], inverse[0][1], inverse[0][2], inverse[0][3],
+                 inverse[1][0], inverse[1][1], inverse[1][2], inverse[1][3],
+                 inverse[2][0], inverse[2][1], inverse[2][2], inverse[2][3],
+                 inverse[3][0], inverse[3][1], inverse[3][2], inverse[3][3]))
+}
+
 /// Returns the stacking context in the given tree of stacking contexts with a specific layer ID.
 pub fn find_stacking_context_with_layer_id(this: &Arc<StackingContext>, layer_id: LayerId)
                                            -> Option<Arc<StackingContext>> {
@@ -365,6 +603,53 @@ pub fn find_stacking_context_with_layer_id(this: &Arc<StackingContext>, layer_id
     None
 }
 
+/// Collects the layers that show an animated image, along with the time, in nanoseconds, at which
+/// the next frame of one of their images is due after `time`. Stacking contexts without a layer
+/// are rendered into the layer of their parent.
+pub fn find_animated_layers(this: &StackingContext,
+                            parent_layer_id: Option<LayerId>,
+                            time: u64,
+                            result: &mut Vec<(LayerId, u64)>) {
+    let layer_id = match this.layer {
+        Some(ref layer) => Some(layer.id),
+        None => parent_layer_id,
+    };
+
+    match layer_id {
+        Some(layer_id) => {
+            let display_list = &*this.display_list;
+            let display_items = display_list.background_and_borders.iter()
+                .chain(display_list.block_backgrounds_and_borders.iter())
+                .chain(display_list.floats.iter())
+                .chain(display_list.content.iter());
+            for display_item in display_items {
+                let next_frame_time = match *display_item {
+                    ImageDisplayItemClass(ref image_item) => {
+                        match image_item.image.frame_at(time) {
+                            (_, Some(next_frame_time)) => next_frame_time,
+                            (_, None) => continue,
+                        }
+                    }
+                    _ => continue,
+                };
+
+                match result.iter().position(|&(id, _)| id == layer_id) {
+                    Some(index) => {
+                        let (_, due) = result[index];
+                        result[index] = (layer_id, cmp::min(due, next_frame_time));
+                    }
+                    None => result.push((layer_id, next_frame_time)),
+                }
+            }
+        }
+        None => {}
+    }
+
+    for kid in this.display_list.children.iter() {
+        find_animated_layers(&**kid, layer_id, time, result)
+    }
+}
+
 /// One drawing command in the list.
 #[deriving(Clone)]
 pub enum DisplayItem {
@@ -374,6 +659,7 @@ pub enum DisplayItem {
     BorderDisplayItemClass(Box<BorderDisplayItem>),
     GradientDisplayItemClass(Box<GradientDisplayItem>),
     LineDisplayItemClass(Box<LineDisplayItem>),
+    BoxShadowDisplayItemClass(Box<BoxShadowDisplayItem>),
 
     /// A pseudo-display item that exists only so that queries like `ContentBoxQuery` and
     /// `ContentBoxesQuery` can be answered.
@@ -393,10 +679,11 @@ pub struct BaseDisplayItem {
     pub node: OpaqueNode,
 
     /// The rectangle to clip to.
-    ///
-    /// TODO(pcwalton): Eventually, to handle `border-radius`, this will (at least) need to grow
-    /// the ability to describe rounded rectangles.
     pub clip_rect: Rect<Au>,
+
+    /// The rounded rectangle to clip to in addition to `clip_rect`, if any. Backgrounds of boxes
+    /// with `border-radius` are clipped to their rounded border box this way.
+    pub rounded_clip: Option<RoundedRect>,
 }
 
 impl BaseDisplayItem {
@@ -406,8 +693,44 @@ impl BaseDisplayItem {
             bounds: bounds,
             node: node,
             clip_rect: clip_rect,
+            rounded_clip: None,
+        }
+    }
+}
+
+/// The radii of the corners of a rounded rectangle. The width of each size is the horizontal
+/// radius and the height the vertical one, which differ if the corner is elliptical.
+#[deriving(Clone, PartialEq, Show)]
+pub struct BorderRadii<T> {
+    pub top_left: Size2D<T>,
+    pub top_right: Size2D<T>,
+    pub bottom_right: Size2D<T>,
+    pub bottom_left: Size2D<T>,
+}
+
+impl<T: Zero> BorderRadii<T> {
+    /// Returns radii that make all corners square.
+    pub fn zero() -> BorderRadii<T> {
+        BorderRadii {
+            top_left: Size2D(Zero::zero(), Zero::zero()),
+            top_right: Size2D(Zero::zero(), Zero::zero()),
+            bottom_right: Size2D(Zero::zero(), Zero::zero()),
+            bottom_left: Size2D(Zero::zero(), Zero::zero()),
         }
     }
+
+    /// Returns true if all corners are square, which they are if either of their radii is zero.
+    pub fn is_square(&self) -> bool {
+        let corners = [&self.top_left, &self.top_right, &self.bottom_right, &self.bottom_left];
+        corners.iter().all(|radii| radii.width.is_zero() || radii.height.is_zero())
+    }
+}
+
+/// A rectangle with rounded corners.
+#[deriving(Clone)]
+pub struct RoundedRect {
+    pub rect: Rect<Au>,
+    pub radii: BorderRadii<Au>,
 }
 
 /// Renders a solid color.
@@ -483,7 +806,10 @@ pub struct BorderDisplayItem {
     pub color: SideOffsets2D<Color>,
 
     /// The border styles.
-    pub style: SideOffsets2D<border_style::T>
+    pub style: SideOffsets2D<border_style::T>,
+
+    /// The radii of the corners of the border box.
+    pub radius: BorderRadii<Au>,
 }
 
 /// Renders a line segment.
@@ -498,6 +824,36 @@ pub struct LineDisplayItem {
     pub style: border_style::T
 }
 
+/// Paints one shadow of the `box-shadow` property.
+#[deriving(Clone)]
+pub struct BoxShadowDisplayItem {
+    /// Fields common to all display items. The bounds cover everything that the shadow, including
+    /// its blur, may paint.
+    pub base: BaseDisplayItem,
+
+    /// The border box of the box casting the shadow for an outer shadow, or its padding box for an
+    /// inset one. The shadow is only painted outside or inside it, respectively.
+    pub box_bounds: Rect<Au>,
+
+    /// The radii of the corners of `box_bounds`.
+    pub radii: BorderRadii<Au>,
+
+    /// The offset of the shadow from the box.
+    pub offset: Point2D<Au>,
+
+    /// The color of the shadow.
+    pub color: Color,
+
+    /// The blur radius of the shadow.
+    pub blur_radius: Au,
+
+    /// The distance by which the shadow is grown (or, if negative, shrunk) before it is blurred.
+    pub spread_radius: Au,
+
+    /// Whether this is an inset shadow.
+    pub inset: bool,
+}
+
 pub enum DisplayItemIterator<'a> {
     EmptyDisplayItemIterator,
     ParentDisplayItemIterator(dlist::Items<'a,DisplayItem>),
@@ -532,6 +888,13 @@ impl DisplayItem {
 
         render_context.draw_target.set_transform(current_transform);
 
+        match self.base().rounded_clip {
+            Some(ref rounded_clip) => {
+                render_context.draw_push_rounded_clip(&rounded_clip.rect, &rounded_clip.radii)
+            }
+            None => {}
+        }
+
         match *self {
             SolidColorDisplayItemClass(ref solid_color) => {
                 render_context.draw_solid_color(&solid_color.base.bounds, solid_color.color)
@@ -566,6 +929,7 @@ impl DisplayItem {
             BorderDisplayItemClass(ref border) => {
                 render_context.draw_border(&border.base.bounds,
                                            border.border,
+                                           &border.radius,
                                            border.color,
                                            border.style)
             }
@@ -583,8 +947,23 @@ impl DisplayItem {
                                           line.style)
             }
 
+            BoxShadowDisplayItemClass(ref box_shadow) => {
+                render_context.draw_box_shadow(&box_shadow.base.bounds,
+                                               &box_shadow.box_bounds,
+                                               &box_shadow.radii,
+                                               &box_shadow.offset,
+                                               box_shadow.color,
+                                               box_shadow.blur_radius,
+                                               box_shadow.spread_radius,
+                                               box_shadow.inset)
+            }
+
             PseudoDisplayItemClass(_) => {}
         }
+
+        if self.base().rounded_clip.is_some() {
+            render_context.draw_pop_clip()
+        }
     }
 
     pub fn base<'a>(&'a self) -> &'a BaseDisplayItem {
@@ -595,6 +974,7 @@ impl DisplayItem {
             BorderDisplayItemClass(ref border) => &border.base,
             GradientDisplayItemClass(ref gradient) => &gradient.base,
             LineDisplayItemClass(ref line) => &line.base,
+            BoxShadowDisplayItemClass(ref box_shadow) => &box_shadow.base,
             PseudoDisplayItemClass(ref base) => &**base,
         }
     }
@@ -607,6 +987,7 @@ impl DisplayItem {
             BorderDisplayItemClass(ref mut border) => &mut border.base,
             GradientDisplayItemClass(ref mut gradient) => &mut gradient.base,
             LineDisplayItemClass(ref mut line) => &mut line.base,
+            BoxShadowDisplayItemClass(ref mut box_shadow) => &mut box_shadow.base,
             PseudoDisplayItemClass(ref mut base) => &mut **base,
         }
     }
@@ -634,6 +1015,7 @@ impl fmt::Show for DisplayItem {
                 BorderDisplayItemClass(_) => "Border",
                 GradientDisplayItemClass(_) => "Gradient",
                 LineDisplayItemClass(_) => "Line",
+                BoxShadowDisplayItemClass(_) => "BoxShadow",
                 PseudoDisplayItemClass(_) => "Pseudo",
             },
             self.base().bounds,
@@ -659,3 +1041,80 @@ impl OpaqueNodeMethods for OpaqueNode {
     }
 }
 
+
+#[cfg(test)]
+fn animated_image_item(delays: Vec<u32>, start_time: u64) -> DisplayItem {
+    use png;
+    use servo_net::image::base::{Animation, AnimationFrame};
+
+    let mut image = box Image::new(1, 1, png::RGBA8(vec!(0, 0, 0, 255)));
+    let frames = range(1, delays.len()).map(|_| {
+        AnimationFrame {
+            rect: Rect(Point2D(0, 0), Size2D(1, 1)),
+            pixels: vec!(255, 255, 255, 255),
+        }
+    }).collect();
+    image.animation = Some(Animation::new(frames, delays, 0, start_time, &image.pixels));
+    let bounds = Rect(Point2D(Au(0), Au(0)), Size2D(Au(60), Au(60)));
+    ImageDisplayItemClass(box ImageDisplayItem {
+        base: BaseDisplayItem::new(bounds, OpaqueNode(0), bounds),
+        image: Arc::new(image),
+        stretch_size: bounds.size,
+    })
+}
+
+#[test]
+fn test_find_animated_layers() {
+    use color;
+    use geom::matrix::identity;
+    use servo_msg::compositor_msg::Scrollable;
+
+    let bounds = Rect(Point2D(Au(0), Au(0)), Size2D(Au(60), Au(60)));
+    let layer = Arc::new(RenderLayer::new(LayerId(1, 0), color::rgb(0, 0, 0), Scrollable));
+
+    // A stacking context without a layer of its own is drawn into its parent's layer.
+    let mut child_list = box DisplayList::new();
+    child_list.content.push_back(animated_image_item(vec!(20, 20), 0));
+    let child = StackingContext::new(child_list, bounds, bounds, 0, identity(), 1.0, None);
+
+    let mut display_list = box DisplayList::new();
+    display_list.content.push_back(animated_image_item(vec!(100, 50), 0));
+    display_list.children.push_back(Arc::new(child));
+    let root = StackingContext::new(display_list, bounds, bounds, 0, identity(), 1.0, Some(layer));
+
+    // The layer is due again when the earliest of its images shows another frame.
+    let mut result = vec!();
+    find_animated_layers(&root, None, 5000000, &mut result);
+    assert_eq!(result, vec!((LayerId(1, 0), 20000000)));
+
+    let mut result = vec!();
+    find_animated_layers(&root, None, 30000000, &mut result);
+    assert_eq!(result, vec!((LayerId(1, 0), 40000000)));
+
+    // Images outside of any layer are not scheduled.
+    let mut result = vec!();
+    find_animated_layers(&**root.display_list.children.front().unwrap(), None, 0, &mut result);
+    assert!(result.is_empty());
+}
+
+#[test]
+fn test_untransform_point() {
+    // A transform with perspective, that tilts the plane of the page away from the viewer.
+    let matrix = Matrix4(2.0, 0.5, 0.0, 0.0,
+                         0.0, 1.0, 0.3, 0.002,
+                         0.0, 0.0, 1.0, 0.0,
+                         10.0, -5.0, 0.0, 1.0);
+    let inverse = invert(&matrix).unwrap();
+    let point = Point2D(30.0, 40.0);
+    let drawn_at = transform_point(&matrix, &point);
+    let found = untransform_point(&inverse, &drawn_at).unwrap();
+    assert!((found.x - point.x).abs() < 0.01 && (found.y - point.y).abs() < 0.01);
+
+    // A plane that is seen edge-on cannot be hit.
+    let edge_on = Matrix4(1.0, 0.0, 0.0, 0.0,
+                          0.0, 0.0, 1.0, 0.0,
+                          0.0, 1.0, 0.0, 0.0,
+                          0.0, 0.0, 0.0, 1.0);
+    let inverse = invert(&edge_on).unwrap();
+    assert!(untransform_point(&inverse, &Point2D(5.0, 5.0)).is_none());
+}
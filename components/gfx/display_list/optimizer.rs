@@ -53,13 +53,13 @@ impl DisplayListOptimizer {
     }
 
     /// Adds child stacking contexts whose boundaries intersect the visible rect to `result_list`.
+    /// The clip rect of a stacking context covers its bounds after they are transformed.
     fn add_in_bounds_stacking_contexts<'a,I>(&self,
                                              result_list: &mut DList<Arc<StackingContext>>,
                                              mut stacking_contexts: I)
                                              where I: Iterator<&'a Arc<StackingContext>> {
         for stacking_context in stacking_contexts {
-            if self.visible_rect.intersects(&stacking_context.bounds) &&
-                    self.visible_rect.intersects(&stacking_context.clip_rect) {
+            if self.visible_rect.intersects(&stacking_context.clip_rect) {
                 result_list.push_back((*stacking_context).clone())
             }
         }
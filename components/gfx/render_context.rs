@@ -6,12 +6,14 @@
 
 use azure::azure_hl::{B8G8R8A8, A8, Color, ColorPattern, ColorPatternRef, DrawOptions};
 use azure::azure_hl::{DrawSurfaceOptions, DrawTarget, ExtendClamp, GradientStop, Linear};
-use azure::azure_hl::{LinearGradientPattern, LinearGradientPatternRef, SourceOp, StrokeOptions};
+use azure::azure_hl::{LinearGradientPattern, LinearGradientPatternRef, Path, PathBuilder};
+use azure::azure_hl::{SourceOp, StrokeOptions};
 use azure::scaled_font::ScaledFont;
 use azure::{AZ_CAP_BUTT, AzFloat, struct__AzDrawOptions, struct__AzGlyph};
 use azure::{struct__AzGlyphBuffer, struct__AzPoint, AzDrawTargetFillGlyphs};
-use display_list::{SidewaysLeft, SidewaysRight, TextDisplayItem, Upright};
+use display_list::{BorderRadii, SidewaysLeft, SidewaysRight, TextDisplayItem, Upright};
 use font_context::FontContext;
+use geom::matrix::Matrix4;
 use geom::matrix2d::Matrix2D;
 use geom::point::Point2D;
 use geom::rect::Rect;
@@ -24,6 +26,8 @@ use servo_net::image::base::Image;
 use servo_util::geometry::Au;
 use servo_util::opts;
 use servo_util::range::Range;
+use std::cmp;
+use std::f32::consts::{FRAC_PI_2, PI};
 use std::num::Zero;
 use std::ptr;
 use style::computed_values::border_style;
@@ -38,6 +42,8 @@ pub struct RenderContext<'a> {
     pub page_rect: Rect<f32>,
     /// The rectangle that this context encompasses in screen coordinates (pixels).
     pub screen_rect: Rect<uint>,
+    /// The time at which animated images are drawn, in nanoseconds.
+    pub animation_time: u64,
 }
 
 enum Direction {
@@ -52,6 +58,12 @@ enum DashSize {
     DashedBorder = 3
 }
 
+/// The number of line segments that each rounded corner is approximated with.
+static CORNER_SEGMENTS: uint = 8;
+
+/// The largest number of layers that a blurred box shadow is drawn with.
+static MAX_BOX_SHADOW_BLUR_STEPS: uint = 16;
+
 impl<'a> RenderContext<'a>  {
     pub fn get_draw_target(&self) -> &'a DrawTarget {
         self.draw_target
@@ -67,11 +79,22 @@ impl<'a> RenderContext<'a>  {
     pub fn draw_border(&self,
                        bounds: &Rect<Au>,
                        border: SideOffsets2D<Au>,
+                       radius: &BorderRadii<Au>,
                        color: SideOffsets2D<Color>,
                        style: SideOffsets2D<border_style::T>) {
         let border = border.to_float_px();
         self.draw_target.make_current();
 
+        if !radius.is_square() {
+            let rect = bounds.to_azure_rect();
+            let radius = radius.to_float_px();
+            self.draw_rounded_border_segment(Top, &rect, border, &radius, color, style);
+            self.draw_rounded_border_segment(Right, &rect, border, &radius, color, style);
+            self.draw_rounded_border_segment(Bottom, &rect, border, &radius, color, style);
+            self.draw_rounded_border_segment(Left, &rect, border, &radius, color, style);
+            return
+        }
+
         self.draw_border_segment(Top, bounds, border, color, style);
         self.draw_border_segment(Right, bounds, border, color, style);
         self.draw_border_segment(Bottom, bounds, border, color, style);
@@ -105,26 +128,110 @@ impl<'a> RenderContext<'a>  {
         self.draw_target.push_clip(&path);
     }
 
+    /// Pushes a clip to the given rectangle with rounded corners.
+    pub fn draw_push_rounded_clip(&self, bounds: &Rect<Au>, radii: &BorderRadii<Au>) {
+        let path_builder = self.draw_target.create_path_builder();
+        add_rounded_rect_to_path(&path_builder,
+                                 &bounds.to_azure_rect(),
+                                 &radii.to_float_px(),
+                                 false);
+        let path = path_builder.finish();
+        self.draw_target.push_clip(&path);
+    }
+
     pub fn draw_pop_clip(&self) {
         self.draw_target.pop_clip();
     }
 
-    pub fn draw_image(&self, bounds: Rect<Au>, image: Arc<Box<Image>>) {
-        let size = Size2D(image.width as i32, image.height as i32);
-        let (pixel_width, pixels, source_format) = match image.pixels {
-            RGBA8(ref pixels) => (4, pixels.as_slice(), B8G8R8A8),
-            K8(ref pixels) => (1, pixels.as_slice(), A8),
-            RGB8(_) => panic!("RGB8 color type not supported"),
-            KA8(_) => panic!("KA8 color type not supported"),
+    /// Draws a box shadow, per CSS-BACKGROUNDS § 7.1. An outer shadow is only drawn outside
+    /// `box_bounds` and an inset one only inside it. `bounds` must contain both `box_bounds` and
+    /// everything the shadow paints.
+    ///
+    /// The blur is approximated by stacking translucent copies of the shadow whose edges are
+    /// spread evenly from `blur_radius` inside the edge of the shadow to `blur_radius` outside it.
+    /// The shadow therefore fades out linearly rather than along a Gaussian curve.
+    pub fn draw_box_shadow(&self,
+                           bounds: &Rect<Au>,
+                           box_bounds: &Rect<Au>,
+                           radii: &BorderRadii<Au>,
+                           offset: &Point2D<Au>,
+                           color: Color,
+                           blur_radius: Au,
+                           spread_radius: Au,
+                           inset: bool) {
+        let rect = bounds.to_azure_rect();
+        let box_rect = box_bounds.to_azure_rect();
+        let radii = radii.to_float_px();
+        let shadow_rect = box_rect.translate(&offset.to_azure_point());
+        let blur_radius = blur_radius.to_nearest_px() as AzFloat;
+        let spread_radius = spread_radius.to_nearest_px() as AzFloat;
+        self.draw_target.make_current();
+
+        // Clip to the area outside or inside the box. A counterclockwise rounded rectangle inside
+        // a clockwise one cuts a hole into it.
+        let clip_path_builder = self.draw_target.create_path_builder();
+        if !inset {
+            add_rounded_rect_to_path(&clip_path_builder, &rect, &BorderRadii::zero(), false);
+        }
+        add_rounded_rect_to_path(&clip_path_builder, &box_rect, &radii, !inset);
+        self.draw_target.push_clip(&clip_path_builder.finish());
+
+        // Stack copies of the shadow so that their alpha adds up to that of the color.
+        let steps = if blur_radius > 0.0 {
+            cmp::max(1, cmp::min(blur_radius.ceil() as uint, MAX_BOX_SHADOW_BLUR_STEPS))
+        } else {
+            1
         };
-        let stride = image.width * pixel_width;
+        let step_alpha = 1.0 - (1.0 - color.a).powf(1.0 / (steps as AzFloat));
+        let pattern = ColorPattern::new(Color::new(color.r, color.g, color.b, step_alpha));
+        let draw_options = DrawOptions::new(1.0, 0);
+        for step in range(0, steps) {
+            let fraction = ((2 * step + 1) as AzFloat) / (steps as AzFloat);
+            let blur_offset = blur_radius * (fraction - 1.0);
+            let path_builder = self.draw_target.create_path_builder();
+            if inset {
+                // An inset shadow covers everything in the box except a hole in the shape of the
+                // box, moved by the offset and shrunk by the spread radius.
+                let (hole_rect, hole_radii) =
+                    inset_rounded_rect(&shadow_rect,
+                                       &radii,
+                                       &SideOffsets2D::new_all_same(spread_radius - blur_offset));
+                add_rounded_rect_to_path(&path_builder, &box_rect, &BorderRadii::zero(), false);
+                add_rounded_rect_to_path(&path_builder, &hole_rect, &hole_radii, true);
+            } else {
+                let (shape_rect, shape_radii) =
+                    inset_rounded_rect(&shadow_rect,
+                                       &radii,
+                                       &SideOffsets2D::new_all_same(-spread_radius - blur_offset));
+                add_rounded_rect_to_path(&path_builder, &shape_rect, &shape_radii, false);
+            }
+            self.draw_target.fill(&path_builder.finish(), &pattern, &draw_options);
+        }
+
+        self.draw_target.pop_clip();
+    }
 
+    pub fn draw_image(&self, bounds: Rect<Au>, image: Arc<Box<Image>>) {
+        if image.is_blank() {
+            return
+        }
+        let size = Size2D(image.width as i32, image.height as i32);
+        let (frame, _) = image.frame_at(self.animation_time);
         self.draw_target.make_current();
         let draw_target_ref = &self.draw_target;
-        let azure_surface = draw_target_ref.create_source_surface_from_data(pixels,
-                                                                            size,
-                                                                            stride as i32,
-                                                                            source_format);
+        let azure_surface = image.with_frame(frame, |pixels| {
+            let (pixel_width, pixels, source_format) = match *pixels {
+                RGBA8(ref pixels) => (4, pixels.as_slice(), B8G8R8A8),
+                K8(ref pixels) => (1, pixels.as_slice(), A8),
+                RGB8(_) => panic!("RGB8 color type not supported"),
+                KA8(_) => panic!("KA8 color type not supported"),
+            };
+            let stride = image.width * pixel_width;
+            draw_target_ref.create_source_surface_from_data(pixels,
+                                                            size,
+                                                            stride as i32,
+                                                            source_format)
+        });
         let source_rect = Rect(Point2D(0u as AzFloat, 0u as AzFloat),
                                Size2D(image.width as AzFloat, image.height as AzFloat));
         let dest_rect = bounds.to_azure_rect();
@@ -149,6 +256,76 @@ impl<'a> RenderContext<'a>  {
         self.draw_target.fill_rect(&rect, ColorPatternRef(&pattern), Some(&draw_options));
     }
 
+    /// Returns the whole pixels of this context that the given rectangle covers once it is
+    /// transformed, or `None` if it covers none of them.
+    pub fn device_rect_for(&self, rect: &Rect<AzFloat>, transform: &Matrix2D<AzFloat>)
+                           -> Option<Rect<AzFloat>> {
+        let corners = [
+            rect.origin,
+            Point2D(rect.max_x(), rect.origin.y),
+            Point2D(rect.origin.x, rect.max_y()),
+            Point2D(rect.max_x(), rect.max_y()),
+        ];
+        let transformed: Vec<Point2D<AzFloat>> = corners.iter().map(|corner| {
+            Point2D(corner.x * transform.m11 + corner.y * transform.m21 + transform.m31,
+                    corner.x * transform.m12 + corner.y * transform.m22 + transform.m32)
+        }).collect();
+        let (mut min, mut max) = (transformed[0], transformed[0]);
+        for point in transformed.iter() {
+            min = Point2D(min.x.min(point.x), min.y.min(point.y));
+            max = Point2D(max.x.max(point.x), max.y.max(point.y));
+        }
+        let origin = Point2D(min.x.floor(), min.y.floor());
+        let rect = Rect(origin, Size2D(max.x.ceil() - origin.x, max.y.ceil() - origin.y));
+        match rect.intersection(&self.device_rect()) {
+            Some(ref rect) if rect.size.width > 0.0 && rect.size.height > 0.0 => Some(*rect),
+            _ => None,
+        }
+    }
+
+    /// Creates a cleared draw target covering the given pixels of this context, from
+    /// `device_rect_for()`, for a translucent group to be drawn into.
+    pub fn create_temporary_draw_target(&self, device_rect: &Rect<AzFloat>) -> DrawTarget {
+        let size = Size2D(device_rect.size.width as i32, device_rect.size.height as i32);
+        let temporary_draw_target = self.draw_target.create_similar_draw_target(&size, B8G8R8A8);
+        let pattern = ColorPattern::new(Color::new(0.0, 0.0, 0.0, 0.0));
+        let mut draw_options = DrawOptions::new(1.0, 0);
+        draw_options.set_composition_op(SourceOp);
+        temporary_draw_target.make_current();
+        temporary_draw_target.fill_rect(&Rect(Point2D(0.0, 0.0), device_rect.size),
+                                        ColorPatternRef(&pattern),
+                                        Some(&draw_options));
+        temporary_draw_target
+    }
+
+    /// Composites a group drawn into a draw target from `create_temporary_draw_target()` onto
+    /// the pixels of this context that it covers, with the given opacity.
+    pub fn draw_temporary_draw_target(&self,
+                                      temporary_draw_target: &DrawTarget,
+                                      device_rect: &Rect<AzFloat>,
+                                      opacity: AzFloat) {
+        // The group is already in pixels, so it is drawn without any transform.
+        let identity: Matrix2D<AzFloat> = Matrix2D::identity();
+        let source_rect = Rect(Point2D(0.0, 0.0), device_rect.size);
+        let draw_surface_options = DrawSurfaceOptions::new(Linear, true);
+        let draw_options = DrawOptions::new(opacity, 0);
+        temporary_draw_target.flush();
+        self.draw_target.make_current();
+        self.draw_target.set_transform(&identity);
+        self.draw_target.draw_surface(temporary_draw_target.snapshot(),
+                                      *device_rect,
+                                      source_rect,
+                                      draw_surface_options,
+                                      draw_options);
+    }
+
+    /// Returns the rectangle that the draw target of this context covers, in pixels.
+    fn device_rect(&self) -> Rect<AzFloat> {
+        Rect(Point2D(0.0, 0.0),
+             Size2D(self.screen_rect.size.width as AzFloat,
+                    self.screen_rect.size.height as AzFloat))
+    }
+
     fn draw_border_segment(&self, direction: Direction, bounds: &Rect<Au>, border: SideOffsets2D<f32>, color: SideOffsets2D<Color>, style: SideOffsets2D<border_style::T>) {
         let (style_select, color_select) = match direction {
             Top => (style.top, color.top),
@@ -215,11 +392,20 @@ impl<'a> RenderContext<'a>  {
                         direction: Direction,
                         border:    SideOffsets2D<f32>,
                         color:     Color) {
+        let draw_opts = DrawOptions::new(1.0, 0);
+        let path      = self.create_border_path(bounds, direction, border);
+        self.draw_target.fill(&path, &ColorPattern::new(color), &draw_opts);
+    }
+
+    /// Returns the trapezoid that the given side of a border with square corners fills.
+    fn create_border_path(&self,
+                          bounds:    Rect<f32>,
+                          direction: Direction,
+                          border:    SideOffsets2D<f32>) -> Path {
         let left_top     = bounds.origin;
         let right_top    = left_top + Point2D(bounds.size.width, 0.0);
         let left_bottom  = left_top + Point2D(0.0, bounds.size.height);
         let right_bottom = left_top + Point2D(bounds.size.width, bounds.size.height);
-        let draw_opts    = DrawOptions::new(1.0, 0);
         let path_builder = self.draw_target.create_path_builder();
          match direction {
              Top    => {
@@ -247,9 +433,7 @@ impl<'a> RenderContext<'a>  {
                  path_builder.line_to(right_bottom);
              }
          }
-         let path = path_builder.finish();
-         self.draw_target.fill(&path, &ColorPattern::new(color), &draw_opts);
-
+         path_builder.finish()
      }
 
     fn draw_dashed_border_segment(&self,
@@ -363,16 +547,7 @@ impl<'a> RenderContext<'a>  {
                                                             0.5 * border.right,
                                                             0.5 * border.bottom,
                                                             0.5 * border.left);
-        let is_groove = match style {
-                border_style::groove =>  true,
-                border_style::ridge  =>  false,
-                _                    =>  panic!("invalid border style")
-        };
-        let darker_color               = self.scale_color(color, if is_groove { 1.0/3.0 } else { 2.0/3.0 });
-        let (outer_color, inner_color) = match (direction, is_groove) {
-            (Top, true)  | (Left, true)  | (Right, false) | (Bottom, false) => (darker_color, color),
-            (Top, false) | (Left, false) | (Right, true)  | (Bottom, true)  => (color, darker_color)
-        };
+        let (outer_color, inner_color) = self.groove_ridge_colors(direction, color, style);
         // outer portion of the border
         self.draw_border_path(original_bounds, direction, scaled_border, outer_color);
         // inner portion of the border
@@ -385,20 +560,159 @@ impl<'a> RenderContext<'a>  {
                                         border:    SideOffsets2D<f32>,
                                         style:     border_style::T,
                                         color:     Color) {
+        // original bounds as a Rect<f32>
+        let original_bounds = self.get_scaled_bounds(bounds, border, 0.0);
+        let scaled_color    = self.inset_outset_color(direction, color, style);
+        self.draw_border_path(original_bounds, direction, border, scaled_color);
+    }
+
+    /// Returns the colors of the outer and inner halves of a side of a groove or ridge border.
+    fn groove_ridge_colors(&self, direction: Direction, color: Color, style: border_style::T)
+                           -> (Color, Color) {
+        let is_groove = match style {
+                border_style::groove =>  true,
+                border_style::ridge  =>  false,
+                _                    =>  panic!("invalid border style")
+        };
+        let darker_color = self.scale_color(color, if is_groove { 1.0/3.0 } else { 2.0/3.0 });
+        match (direction, is_groove) {
+            (Top, true)  | (Left, true)  | (Right, false) | (Bottom, false) => (darker_color, color),
+            (Top, false) | (Left, false) | (Right, true)  | (Bottom, true)  => (color, darker_color)
+        }
+    }
+
+    /// Returns the color of a side of an inset or outset border.
+    fn inset_outset_color(&self, direction: Direction, color: Color, style: border_style::T)
+                          -> Color {
         let is_inset = match style {
                 border_style::inset  =>  true,
                 border_style::outset =>  false,
                 _                    =>  panic!("invalid border style")
         };
-        // original bounds as a Rect<f32>
-        let original_bounds = self.get_scaled_bounds(bounds, border, 0.0);
         // select and scale the color appropriately.
-        let scaled_color    = match direction {
+        match direction {
             Top             => self.scale_color(color, if is_inset { 2.0/3.0 } else { 1.0     }),
             Left            => self.scale_color(color, if is_inset { 1.0/6.0 } else { 0.5     }),
             Right | Bottom  => self.scale_color(color, if is_inset { 1.0     } else { 2.0/3.0 })
+        }
+    }
+
+    /// Draws one side of a border with rounded corners. The side is the part of the ring between
+    /// the outer and inner edges of the border that lies in the trapezoid that a side of a border
+    /// with square corners fills.
+    fn draw_rounded_border_segment(&self,
+                                   direction: Direction,
+                                   bounds:    &Rect<f32>,
+                                   border:    SideOffsets2D<f32>,
+                                   radius:    &BorderRadii<f32>,
+                                   color:     SideOffsets2D<Color>,
+                                   style:     SideOffsets2D<border_style::T>) {
+        let (style_select, color_select) = match direction {
+            Top => (style.top, color.top),
+            Left => (style.left, color.left),
+            Right => (style.right, color.right),
+            Bottom => (style.bottom, color.bottom)
         };
-        self.draw_border_path(original_bounds, direction, border, scaled_color);
+
+        self.draw_target.push_clip(&self.create_border_path(*bounds, direction, border));
+
+        match style_select {
+            border_style::none | border_style::hidden => {}
+            border_style::double => {
+                self.draw_rounded_ring(bounds, border, radius, 0.0, 1.0/3.0, color_select);
+                self.draw_rounded_ring(bounds, border, radius, 2.0/3.0, 1.0, color_select);
+            }
+            border_style::groove | border_style::ridge => {
+                let (outer_color, inner_color) =
+                    self.groove_ridge_colors(direction, color_select, style_select);
+                self.draw_rounded_ring(bounds, border, radius, 0.0, 0.5, outer_color);
+                self.draw_rounded_ring(bounds, border, radius, 0.5, 1.0, inner_color);
+            }
+            border_style::inset | border_style::outset => {
+                let scaled_color = self.inset_outset_color(direction, color_select, style_select);
+                self.draw_rounded_ring(bounds, border, radius, 0.0, 1.0, scaled_color);
+            }
+            border_style::dotted => {
+                self.draw_dashed_rounded_ring(direction, bounds, border, radius, color_select,
+                                              DottedBorder);
+            }
+            border_style::dashed => {
+                self.draw_dashed_rounded_ring(direction, bounds, border, radius, color_select,
+                                              DashedBorder);
+            }
+            border_style::solid => {
+                self.draw_rounded_ring(bounds, border, radius, 0.0, 1.0, color_select);
+            }
+        }
+
+        self.draw_target.pop_clip();
+    }
+
+    /// Draws one side of a dotted or dashed border with rounded corners. The ring is cut into
+    /// strips across the side, as long as the dashes of a border with square corners, so the
+    /// dashes on the corners are the parts of those strips that the curve passes through.
+    fn draw_dashed_rounded_ring(&self,
+                                direction: Direction,
+                                bounds:    &Rect<f32>,
+                                border:    SideOffsets2D<f32>,
+                                radius:    &BorderRadii<f32>,
+                                color:     Color,
+                                dash_size: DashSize) {
+        let border_width = match direction {
+            Top => border.top,
+            Left => border.left,
+            Right => border.right,
+            Bottom => border.bottom
+        };
+        let dash_length = border_width * (dash_size as int) as AzFloat;
+        if dash_length <= 0.0 {
+            return
+        }
+
+        let (start, length) = match direction {
+            Top | Bottom => (bounds.origin.x, bounds.size.width),
+            Left | Right => (bounds.origin.y, bounds.size.height),
+        };
+        let path_builder = self.draw_target.create_path_builder();
+        let mut offset = 0.0;
+        while offset < length {
+            let strip = match direction {
+                Top | Bottom => Rect(Point2D(start + offset, bounds.origin.y),
+                                     Size2D(dash_length, bounds.size.height)),
+                Left | Right => Rect(Point2D(bounds.origin.x, start + offset),
+                                     Size2D(bounds.size.width, dash_length)),
+            };
+            add_rounded_rect_to_path(&path_builder, &strip, &BorderRadii::zero(), false);
+            offset += 2.0 * dash_length;
+        }
+
+        self.draw_target.push_clip(&path_builder.finish());
+        self.draw_rounded_ring(bounds, border, radius, 0.0, 1.0, color);
+        self.draw_target.pop_clip();
+    }
+
+    /// Fills the part of a border with rounded corners that lies between the given fractions of
+    /// the border widths, measured from the outer edge of the border.
+    fn draw_rounded_ring(&self,
+                         bounds: &Rect<f32>,
+                         border: SideOffsets2D<f32>,
+                         radius: &BorderRadii<f32>,
+                         outer_fraction: f32,
+                         inner_fraction: f32,
+                         color: Color) {
+        let scale = |fraction: f32| {
+            SideOffsets2D::new(fraction * border.top,
+                               fraction * border.right,
+                               fraction * border.bottom,
+                               fraction * border.left)
+        };
+        let (outer_rect, outer_radii) = inset_rounded_rect(bounds, radius, &scale(outer_fraction));
+        let (inner_rect, inner_radii) = inset_rounded_rect(bounds, radius, &scale(inner_fraction));
+        let path_builder = self.draw_target.create_path_builder();
+        add_rounded_rect_to_path(&path_builder, &outer_rect, &outer_radii, false);
+        add_rounded_rect_to_path(&path_builder, &inner_rect, &inner_radii, true);
+        let path = path_builder.finish();
+        self.draw_target.fill(&path, &ColorPattern::new(color), &DrawOptions::new(1.0, 0));
     }
 
     pub fn draw_text(&mut self,
@@ -489,6 +803,18 @@ impl ToAzureRect for Rect<Au> {
     }
 }
 
+pub trait ToAzureMatrix {
+    fn to_azure_matrix(&self) -> Matrix2D<AzFloat>;
+}
+
+impl ToAzureMatrix for Matrix4<AzFloat> {
+    /// Azure only draws in two dimensions, so this drops everything that moves content along the
+    /// z axis. Content with 3D transforms is drawn by the compositor instead.
+    fn to_azure_matrix(&self) -> Matrix2D<AzFloat> {
+        Matrix2D::new(self.m11, self.m12, self.m21, self.m22, self.m41, self.m42)
+    }
+}
+
 trait ToSideOffsetsPx {
     fn to_float_px(&self) -> SideOffsets2D<AzFloat>;
 }
@@ -502,6 +828,96 @@ impl ToSideOffsetsPx for SideOffsets2D<Au> {
     }
 }
 
+trait ToBorderRadiiPx {
+    fn to_float_px(&self) -> BorderRadii<AzFloat>;
+}
+
+impl ToBorderRadiiPx for BorderRadii<Au> {
+    fn to_float_px(&self) -> BorderRadii<AzFloat> {
+        let to_float_px = |size: Size2D<Au>| {
+            Size2D(size.width.to_subpx() as AzFloat, size.height.to_subpx() as AzFloat)
+        };
+        BorderRadii {
+            top_left: to_float_px(self.top_left),
+            top_right: to_float_px(self.top_right),
+            bottom_right: to_float_px(self.bottom_right),
+            bottom_left: to_float_px(self.bottom_left),
+        }
+    }
+}
+
+/// Moves the edges of a rounded rectangle inward by the given distances, or outward if they are
+/// negative. Its corners stay concentric with the old ones, except that square corners stay
+/// square.
+fn inset_rounded_rect(rect: &Rect<AzFloat>,
+                      radii: &BorderRadii<AzFloat>,
+                      inset: &SideOffsets2D<AzFloat>)
+                      -> (Rect<AzFloat>, BorderRadii<AzFloat>) {
+    fn inset_radius(radius: AzFloat, distance: AzFloat) -> AzFloat {
+        if radius == 0.0 {
+            0.0
+        } else {
+            (radius - distance).max(0.0)
+        }
+    }
+    let inset_corner = |radii: Size2D<AzFloat>, horizontal: AzFloat, vertical: AzFloat| {
+        Size2D(inset_radius(radii.width, horizontal), inset_radius(radii.height, vertical))
+    };
+    let rect = Rect(rect.origin + Point2D(inset.left, inset.top),
+                    Size2D((rect.size.width - inset.left - inset.right).max(0.0),
+                           (rect.size.height - inset.top - inset.bottom).max(0.0)));
+    let radii = BorderRadii {
+        top_left: inset_corner(radii.top_left, inset.left, inset.top),
+        top_right: inset_corner(radii.top_right, inset.right, inset.top),
+        bottom_right: inset_corner(radii.bottom_right, inset.right, inset.bottom),
+        bottom_left: inset_corner(radii.bottom_left, inset.left, inset.bottom),
+    };
+    (rect, radii)
+}
+
+/// Adds a rectangle with rounded corners to a path as a new figure, clockwise or, if `reverse` is
+/// true, counterclockwise. The corners are approximated with line segments.
+fn add_rounded_rect_to_path(path_builder: &PathBuilder,
+                            rect: &Rect<AzFloat>,
+                            radii: &BorderRadii<AzFloat>,
+                            reverse: bool) {
+    // The center of each corner's ellipse, its radii, and the angle at which the corner starts.
+    let corners = [
+        (Point2D(rect.origin.x + radii.top_left.width, rect.origin.y + radii.top_left.height),
+         radii.top_left,
+         PI),
+        (Point2D(rect.max_x() - radii.top_right.width, rect.origin.y + radii.top_right.height),
+         radii.top_right,
+         PI + FRAC_PI_2),
+        (Point2D(rect.max_x() - radii.bottom_right.width,
+                 rect.max_y() - radii.bottom_right.height),
+         radii.bottom_right,
+         0.0),
+        (Point2D(rect.origin.x + radii.bottom_left.width,
+                 rect.max_y() - radii.bottom_left.height),
+         radii.bottom_left,
+         FRAC_PI_2),
+    ];
+
+    let mut points = vec![];
+    for &(center, corner_radii, start_angle) in corners.iter() {
+        for segment in range(0, CORNER_SEGMENTS + 1) {
+            let angle = start_angle +
+                FRAC_PI_2 * (segment as AzFloat) / (CORNER_SEGMENTS as AzFloat);
+            points.push(Point2D(center.x + corner_radii.width * angle.cos(),
+                                center.y + corner_radii.height * angle.sin()));
+        }
+    }
+    if reverse {
+        points.as_mut_slice().reverse()
+    }
+
+    path_builder.move_to(points[0]);
+    for point in points.slice_from(1).iter() {
+        path_builder.line_to(*point)
+    }
+}
+
 trait ScaledFontExtensionMethods {
     fn draw_text_into_context(&self,
                               rctx: &RenderContext,
@@ -33,10 +33,14 @@ use servo_util::task::spawn_named_with_send_on_failure;
 use servo_util::task_state;
 use servo_util::time::{TimeProfilerChan, profile};
 use servo_util::time;
+use std::cmp;
 use std::comm::{Receiver, Sender, channel};
+use std::io::timer::Timer;
 use std::mem;
 use std::task::TaskBuilder;
+use std::time::duration::Duration;
 use sync::Arc;
+use time::precise_time_ns;
 
 /// Information about a hardware graphics layer that layout sends to the painting task.
 #[deriving(Clone)]
@@ -122,6 +126,12 @@ pub struct RenderTask<C> {
 
     /// Communication handles to each of the worker threads.
     worker_threads: Vec<WorkerThreadProxy>,
+
+    /// The layers that show animated images, and when the next frame of each is due.
+    animated_layers: Vec<(LayerId, u64)>,
+
+    /// The timer that wakes the task up when an animated image moves to its next frame.
+    animation_timer: Timer,
 }
 
 // If we implement this as a function, we get borrowck errors from borrowing
@@ -141,6 +151,8 @@ fn initialize_layers<C>(compositor: &mut C,
     build(&mut metadata, root_stacking_context, &ZERO_POINT);
     compositor.initialize_layers_for_pipeline(pipeline_id, metadata, epoch);
 
+    // FIXME: Layers inside stacking contexts that are transformed or translucent but have no layer
+    // of their own are placed as if those were not transformed, and drawn without their opacity.
     fn build(metadata: &mut Vec<LayerMetadata>,
              stacking_context: &StackingContext,
              page_position: &Point2D<Au>) {
@@ -157,6 +169,7 @@ fn initialize_layers<C>(compositor: &mut C,
                                     stacking_context.bounds.size.height.to_nearest_px() as uint)),
                     background_color: render_layer.background_color,
                     scroll_policy: render_layer.scroll_policy,
+                    transform: stacking_context.transform,
                 })
             }
         }
@@ -201,6 +214,8 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
                     epoch: Epoch(0),
                     buffer_map: BufferMap::new(10000000),
                     worker_threads: worker_threads,
+                    animated_layers: Vec::new(),
+                    animation_timer: Timer::new().unwrap(),
                 };
 
                 render_task.start();
@@ -226,10 +241,21 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
         debug!("render_task: beginning rendering loop");
 
         loop {
-            match self.port.recv() {
+            let msg = match self.recv_or_animation_timeout() {
+                Some(msg) => msg,
+                None => {
+                    self.invalidate_animated_layers();
+                    continue
+                }
+            };
+
+            match msg {
                 RenderInitMsg(stacking_context) => {
                     self.epoch.next();
                     self.root_stacking_context = Some(stacking_context.clone());
+                    // The layers are rendered again for the new epoch, which schedules their
+                    // animations.
+                    self.animated_layers.clear();
 
                     if !self.paint_permission {
                         debug!("render_task: render ready msg");
@@ -253,15 +279,21 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
                     }
 
                     let mut replies = Vec::new();
+                    let animation_time = precise_time_ns();
                     self.compositor.set_render_state(self.id, RenderingRenderState);
                     for RenderRequest { buffer_requests, scale, layer_id, epoch }
                           in requests.into_iter() {
                         if self.epoch == epoch {
-                            self.render(&mut replies, buffer_requests, scale, layer_id);
+                            self.render(&mut replies,
+                                        buffer_requests,
+                                        scale,
+                                        layer_id,
+                                        animation_time);
                         } else {
                             debug!("renderer epoch mismatch: {} != {}", self.epoch, epoch);
                         }
                     }
+                    self.schedule_animations(animation_time);
 
                     self.compositor.set_render_state(self.id, IdleRenderState);
 
@@ -289,6 +321,7 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
                 }
                 PaintPermissionRevoked => {
                     self.paint_permission = false;
+                    self.animated_layers.clear();
                 }
                 ExitMsg(response_ch) => {
                     debug!("render_task: exitmsg response send");
@@ -299,6 +332,51 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
         }
     }
 
+    /// Waits for the next message, or returns `None` once the next frame of an animated image is
+    /// due.
+    fn recv_or_animation_timeout(&mut self) -> Option<Msg> {
+        let next_frame_time = match self.animated_layers.iter().map(|&(_, time)| time).min() {
+            Some(next_frame_time) => next_frame_time,
+            None => return Some(self.port.recv()),
+        };
+
+        let delay = cmp::max(next_frame_time as i64 - precise_time_ns() as i64, 0);
+        let timeout = self.animation_timer.oneshot(Duration::nanoseconds(delay));
+        let port = &self.port;
+        select! (
+            msg = port.recv() => Some(msg),
+            () = timeout.recv() => None
+        )
+    }
+
+    /// Finds the layers whose animated images will show another frame after the given time.
+    fn schedule_animations(&mut self, time: u64) {
+        self.animated_layers.clear();
+        match self.root_stacking_context {
+            Some(ref stacking_context) => {
+                display_list::find_animated_layers(&**stacking_context,
+                                                   None,
+                                                   time,
+                                                   &mut self.animated_layers)
+            }
+            None => {}
+        }
+    }
+
+    /// Asks the compositor to repaint the layers whose next animation frame is due. They are
+    /// scheduled again once they have been rendered.
+    fn invalidate_animated_layers(&mut self) {
+        let now = precise_time_ns();
+        let animated_layers = mem::replace(&mut self.animated_layers, Vec::new());
+        let (due, pending) = animated_layers.partition(|&(_, time)| time <= now);
+        self.animated_layers = pending;
+
+        if !due.is_empty() && self.paint_permission {
+            let layer_ids = due.into_iter().map(|(layer_id, _)| layer_id).collect();
+            self.compositor.invalidate_layers(self.id, self.epoch, layer_ids);
+        }
+    }
+
     /// Retrieves an appropriately-sized layer buffer from the cache to match the requirements of
     /// the given tile, or creates one if a suitable one cannot be found.
     fn find_or_create_layer_buffer_for_tile(&mut self, tile: &BufferRequest, scale: f32)
@@ -347,7 +425,8 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
               replies: &mut Vec<(LayerId, Box<LayerBufferSet>)>,
               mut tiles: Vec<BufferRequest>,
               scale: f32,
-              layer_id: LayerId) {
+              layer_id: LayerId,
+              animation_time: u64) {
         time::profile(time::PaintingCategory, None, self.time_profiler_chan.clone(), || {
             // Bail out if there is no appropriate stacking context.
             let stacking_context = match self.root_stacking_context {
@@ -371,7 +450,8 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
                 self.worker_threads[thread_id].paint_tile(tile,
                                                           layer_buffer,
                                                           stacking_context.clone(),
-                                                          scale);
+                                                          scale,
+                                                          animation_time);
             }
             let new_buffers = Vec::from_fn(tile_count, |i| {
                 let thread_id = i % self.worker_threads.len();
@@ -426,8 +506,13 @@ impl WorkerThreadProxy {
                   tile: BufferRequest,
                   layer_buffer: Option<Box<LayerBuffer>>,
                   stacking_context: Arc<StackingContext>,
-                  scale: f32) {
-        self.sender.send(PaintTileMsgToWorkerThread(tile, layer_buffer, stacking_context, scale))
+                  scale: f32,
+                  animation_time: u64) {
+        self.sender.send(PaintTileMsgToWorkerThread(tile,
+                                                    layer_buffer,
+                                                    stacking_context,
+                                                    scale,
+                                                    animation_time))
     }
 
     fn get_painted_tile_buffer(&mut self) -> Box<LayerBuffer> {
@@ -471,8 +556,15 @@ impl WorkerThread {
         loop {
             match self.receiver.recv() {
                 ExitMsgToWorkerThread => break,
-                PaintTileMsgToWorkerThread(tile, layer_buffer, stacking_context, scale) => {
-                    let draw_target = self.optimize_and_paint_tile(&tile, stacking_context, scale);
+                PaintTileMsgToWorkerThread(tile,
+                                           layer_buffer,
+                                           stacking_context,
+                                           scale,
+                                           animation_time) => {
+                    let draw_target = self.optimize_and_paint_tile(&tile,
+                                                                   stacking_context,
+                                                                   scale,
+                                                                   animation_time);
                     let buffer = self.create_layer_buffer_for_painted_tile(&tile,
                                                                            layer_buffer,
                                                                            draw_target,
@@ -486,7 +578,8 @@ impl WorkerThread {
     fn optimize_and_paint_tile(&mut self,
                                tile: &BufferRequest,
                                stacking_context: Arc<StackingContext>,
-                               scale: f32)
+                               scale: f32,
+                               animation_time: u64)
                                -> DrawTarget {
         let size = Size2D(tile.screen_rect.size.width as i32, tile.screen_rect.size.height as i32);
         let draw_target = if !opts::get().gpu_painting {
@@ -509,6 +602,7 @@ impl WorkerThread {
                 font_ctx: &mut self.font_context,
                 page_rect: tile.page_rect,
                 screen_rect: tile.screen_rect,
+                animation_time: animation_time,
             };
 
             // Apply the translation to render the tile we want.
@@ -584,7 +678,11 @@ impl WorkerThread {
 
 enum MsgToWorkerThread {
     ExitMsgToWorkerThread,
-    PaintTileMsgToWorkerThread(BufferRequest, Option<Box<LayerBuffer>>, Arc<StackingContext>, f32),
+    PaintTileMsgToWorkerThread(BufferRequest,
+                               Option<Box<LayerBuffer>>,
+                               Arc<StackingContext>,
+                               f32,
+                               u64),
 }
 
 enum MsgFromWorkerThread {
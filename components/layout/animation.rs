@@ -0,0 +1,622 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! CSS transitions and animations.
+//!
+//! Running animations are kept by the layout task, per node. While styles are recalculated, new
+//! transitions and animations are started from the old and new styles of each node, and the
+//! running ones are applied to the new style at the time of the reflow. The restyle damage is
+//! then computed as usual, so animations of properties that only affect painting do not cause
+//! reflows.
+
+use context::SharedLayoutContext;
+
+use gfx::display_list::OpaqueNode;
+use servo_util::geometry::Au;
+use std::num::Float;
+use style::{mod, CSSFloat, ComputedValues, DeclarationBlock, RGBA, cascade};
+use style::computed::{LP_Length, LP_Percentage, LPA_Length, LPA_Percentage};
+use style::computed::{LengthOrPercentage, LengthOrPercentageOrAuto};
+use style::computed_values::{animation_direction, animation_fill_mode, transition_property};
+use style::computed_values::transition_timing_function::{CubicBezier, StepEnd, StepStart};
+use style::computed_values::transition_timing_function::{Steps, TimingFunction};
+use sync::Arc;
+
+/// A transition or an animation of a node.
+#[deriving(Clone)]
+pub struct Animation {
+    /// The node being animated.
+    pub node: OpaqueNode,
+    pub kind: AnimationKind,
+    /// The time at which the animation starts, after its delay, in seconds.
+    pub start_time: f64,
+    /// The duration of one iteration, in seconds.
+    pub duration: f64,
+    pub timing_function: TimingFunction,
+    pub iteration_count: CSSFloat,
+    pub direction: animation_direction::SingleComputedValue,
+    pub fill_mode: animation_fill_mode::SingleComputedValue,
+    /// Whether the animation has ended and its end event has been sent. Only animations that
+    /// keep applying their last frame are kept once they have finished.
+    pub finished: bool,
+}
+
+#[deriving(Clone)]
+pub enum AnimationKind {
+    /// A transition of a property between the styles of a node before and after a change.
+    TransitionAnimation(AnimatedProperty, Arc<ComputedValues>, Arc<ComputedValues>),
+    /// An animation of the `@keyframes` rule with the given name, with the style of each keyframe
+    /// sorted by offset. There are keyframes at the offsets 0 and 1.
+    KeyframesAnimation(String, Vec<(CSSFloat, Arc<ComputedValues>)>),
+}
+
+impl Animation {
+    /// Returns the time at which the active duration of the animation ends.
+    pub fn end_time(&self) -> f64 {
+        if self.duration == 0.0 {
+            return self.start_time
+        }
+        self.start_time + self.duration * self.iteration_count
+    }
+
+    /// Returns the time that had elapsed at the end of the animation, not counting its delay, as
+    /// the `elapsedTime` of end events has it.
+    pub fn elapsed_time(&self) -> f64 {
+        self.end_time() - self.start_time
+    }
+
+    /// Returns true if the animation is kept once it has ended, to keep its last frame applied.
+    /// Transitions are not, as their last frame is the style of their node anyway.
+    pub fn keeps_last_frame(&self) -> bool {
+        match self.kind {
+            TransitionAnimation(..) => false,
+            KeyframesAnimation(..) => self.fills_forwards(),
+        }
+    }
+
+    fn fills_forwards(&self) -> bool {
+        match self.fill_mode {
+            animation_fill_mode::forwards | animation_fill_mode::both => true,
+            animation_fill_mode::none | animation_fill_mode::backwards => false,
+        }
+    }
+
+    /// Returns the position in the current iteration at the given time, from 0 to 1 and with the
+    /// direction of the animation taken into account, or `None` if the animation does not apply
+    /// at that time. The timing function is not applied.
+    fn directed_progress(&self, now: f64) -> Option<f64> {
+        let elapsed = now - self.start_time;
+        let (iteration, progress) = if elapsed < 0.0 {
+            match self.fill_mode {
+                animation_fill_mode::backwards | animation_fill_mode::both => (0.0, 0.0),
+                animation_fill_mode::none | animation_fill_mode::forwards => return None,
+            }
+        } else if elapsed >= self.end_time() - self.start_time {
+            if !self.fills_forwards() {
+                return None
+            }
+            let count = if self.iteration_count.is_infinite() { 1.0 } else { self.iteration_count };
+            if count > 0.0 && count.fract() == 0.0 {
+                (count - 1.0, 1.0)
+            } else {
+                (count.floor(), count.fract())
+            }
+        } else {
+            let iterations = elapsed / self.duration;
+            (iterations.floor(), iterations.fract())
+        };
+
+        let odd_iteration = (iteration as u64) % 2 == 1;
+        let reversed = match self.direction {
+            animation_direction::normal => false,
+            animation_direction::reverse => true,
+            animation_direction::alternate => odd_iteration,
+            animation_direction::alternate_reverse => !odd_iteration,
+        };
+        Some(if reversed { 1.0 - progress } else { progress })
+    }
+
+    /// Sets the animated properties of the given style to their values at the given time.
+    pub fn apply(&self, style: &mut ComputedValues, now: f64) {
+        let progress = match self.directed_progress(now) {
+            None => return,
+            Some(progress) => progress,
+        };
+        match self.kind {
+            TransitionAnimation(property, ref from, ref to) => {
+                let progress = evaluate_timing_function(&self.timing_function, progress);
+                property.update(style, &**from, &**to, progress)
+            }
+            KeyframesAnimation(_, ref keyframes) => {
+                // Find the keyframes on either side of the current position.
+                let index = keyframes.iter()
+                                     .rposition(|&(offset, _)| offset <= progress)
+                                     .unwrap_or(0);
+                let next_index = if index + 1 < keyframes.len() { index + 1 } else { index };
+                let (from_offset, ref from) = keyframes[index];
+                let (to_offset, ref to) = keyframes[next_index];
+                let local_progress = if to_offset > from_offset {
+                    (progress - from_offset) / (to_offset - from_offset)
+                } else {
+                    1.0
+                };
+                let local_progress = evaluate_timing_function(&self.timing_function,
+                                                              local_progress);
+                for property in AnimatedProperty::all().into_iter() {
+                    if property.differs(&**from, &**to) || property.differs(style, &**from) {
+                        property.update(style, &**from, &**to, local_progress)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if this is an animation of the given property by a `@keyframes` rule.
+    fn animates_with_keyframes(&self, property: AnimatedProperty, style: &ComputedValues)
+                               -> bool {
+        match self.kind {
+            TransitionAnimation(..) => false,
+            KeyframesAnimation(_, ref keyframes) => {
+                keyframes.iter().any(|&(_, ref keyframe)| property.differs(&**keyframe, style))
+            }
+        }
+    }
+}
+
+/// Returns true if styles with the given values may start transitions or animations. Nodes with
+/// such styles do not share them, as what happens depends on the previous style of each node.
+pub fn style_may_animate(style: &ComputedValues) -> bool {
+    let box_style = style.get_box();
+    !box_style.animation_name.is_empty() ||
+        (!box_style.transition_property.is_empty() &&
+         box_style.transition_duration.iter().zip(box_style.transition_delay.iter()).any(
+            |(duration, delay)| duration.seconds() + delay.seconds() > 0.0))
+}
+
+/// Starts the transitions and animations that the new style of a node calls for, and applies
+/// the running ones to it. Changes to the animations of the node are sent to the layout task.
+pub fn process_style(context: &SharedLayoutContext,
+                     node: OpaqueNode,
+                     old_style: Option<&Arc<ComputedValues>>,
+                     new_style: &mut Arc<ComputedValues>,
+                     parent_style: Option<&ComputedValues>,
+                     applicable_declarations: &[DeclarationBlock]) {
+    let running = context.running_animations.get(&node);
+    if running.is_none() && !style_may_animate(&**new_style) {
+        return
+    }
+
+    let now = context.animation_time;
+    let mut animations = match running {
+        None => vec!(),
+        Some(animations) => animations.clone(),
+    };
+    let mut changed = false;
+
+    // Stop the animations that are no longer listed in `animation-name`.
+    let animation_count = animations.len();
+    animations.retain(|animation| {
+        match animation.kind {
+            TransitionAnimation(..) => true,
+            KeyframesAnimation(ref name, _) => {
+                new_style.get_box().animation_name.contains(name)
+            }
+        }
+    });
+    changed |= animations.len() != animation_count;
+
+    match old_style {
+        Some(old_style) => {
+            changed |= start_transitions(node, &**old_style, new_style, &mut animations, now)
+        }
+        None => {}
+    }
+    changed |= start_keyframes_animations(context,
+                                          node,
+                                          old_style.map(|style| &**style),
+                                          new_style,
+                                          parent_style,
+                                          applicable_declarations,
+                                          &mut animations,
+                                          now);
+
+    // The layout task also needs to know which nodes had their animations applied.
+    if changed || running.is_some() {
+        let sender = context.new_animations_sender.lock();
+        sender.send((node, animations.clone()))
+    }
+
+    if !animations.is_empty() {
+        let mut style = (**new_style).clone();
+        for animation in animations.iter() {
+            animation.apply(&mut style, now)
+        }
+        *new_style = Arc::new(style)
+    }
+}
+
+/// Starts transitions, according to CSS-TRANSITIONS § 3. Returns true if any was started.
+fn start_transitions(node: OpaqueNode,
+                     old_style: &ComputedValues,
+                     new_style: &Arc<ComputedValues>,
+                     animations: &mut Vec<Animation>,
+                     now: f64)
+                     -> bool {
+    let box_style = new_style.get_box();
+    let mut started = false;
+    for (i, transition_property) in box_style.transition_property.iter().enumerate() {
+        let duration = box_style.transition_duration[i % box_style.transition_duration.len()];
+        let delay = box_style.transition_delay[i % box_style.transition_delay.len()];
+        if duration.seconds() + delay.seconds() <= 0.0 {
+            continue
+        }
+        let timing_function = &box_style.transition_timing_function[
+            i % box_style.transition_timing_function.len()];
+
+        let properties = match *transition_property {
+            transition_property::TransitionAll => AnimatedProperty::all(),
+            transition_property::TransitionProperty(ref name) => {
+                AnimatedProperty::from_name(name.as_slice()).into_iter().collect()
+            }
+        };
+        for property in properties.into_iter() {
+            if !property.differs(old_style, &**new_style) {
+                continue
+            }
+
+            // Changes made by animations are not transitioned.
+            if animations.iter().any(|animation| {
+                animation.animates_with_keyframes(property, &**new_style)
+            }) {
+                continue
+            }
+
+            // A running transition to the new value is kept, and one to another value is
+            // replaced.
+            let running = animations.iter().position(|animation| {
+                match animation.kind {
+                    TransitionAnimation(running_property, _, _) => running_property == property,
+                    KeyframesAnimation(..) => false,
+                }
+            });
+            match running {
+                Some(index) => {
+                    match animations[index].kind {
+                        TransitionAnimation(_, _, ref to) if
+                                !property.differs(&**to, &**new_style) => continue,
+                        _ => {}
+                    }
+                    animations.remove(index);
+                }
+                None => {}
+            }
+
+            if !property.can_interpolate(old_style, &**new_style) {
+                continue
+            }
+            animations.push(Animation {
+                node: node,
+                kind: TransitionAnimation(property,
+                                          Arc::new(old_style.clone()),
+                                          new_style.clone()),
+                start_time: now + delay.seconds(),
+                duration: duration.seconds(),
+                timing_function: timing_function.clone(),
+                iteration_count: 1.0,
+                direction: animation_direction::normal,
+                // Until it starts, a transition shows the old value, and the new one once it has
+                // ended.
+                fill_mode: animation_fill_mode::both,
+                finished: false,
+            });
+            started = true;
+        }
+    }
+    started
+}
+
+/// Starts the animations of the names that have been added to `animation-name`, according to
+/// CSS-ANIMATIONS § 3. Returns true if any was started.
+fn start_keyframes_animations(context: &SharedLayoutContext,
+                              node: OpaqueNode,
+                              old_style: Option<&ComputedValues>,
+                              new_style: &Arc<ComputedValues>,
+                              parent_style: Option<&ComputedValues>,
+                              applicable_declarations: &[DeclarationBlock],
+                              animations: &mut Vec<Animation>,
+                              now: f64)
+                              -> bool {
+    let stylist = unsafe { &*context.stylist };
+    let box_style = new_style.get_box();
+    let mut started = false;
+    for (i, name) in box_style.animation_name.iter().enumerate() {
+        let already_listed = match old_style {
+            Some(old_style) => old_style.get_box().animation_name.contains(name),
+            None => false,
+        };
+        if already_listed {
+            continue
+        }
+        let rule = match stylist.get_keyframes(name.as_slice()) {
+            Some(rule) => rule,
+            None => continue,
+        };
+
+        // Compute the style of each keyframe, as if its declarations came last in the cascade.
+        let mut keyframes = vec!();
+        for keyframe in rule.keyframes.iter() {
+            let mut declarations = applicable_declarations.to_vec();
+            declarations.push(DeclarationBlock::from_declarations(keyframe.declarations.clone()));
+            let (style, _) = cascade(declarations.as_slice(), false, parent_style, None);
+            let style = Arc::new(style);
+            for &offset in keyframe.offsets.iter() {
+                keyframes.push((offset, style.clone()))
+            }
+        }
+        keyframes.sort_by(|&(a, _), &(b, _)| a.partial_cmp(&b).unwrap());
+        // Missing keyframes at the start and the end use the style of the node.
+        if !keyframes.iter().any(|&(offset, _)| offset == 0.0) {
+            keyframes.insert(0, (0.0, new_style.clone()))
+        }
+        if !keyframes.iter().any(|&(offset, _)| offset == 1.0) {
+            keyframes.push((1.0, new_style.clone()))
+        }
+
+        macro_rules! nth(
+            ($list: ident) => (
+                box_style.$list[i % box_style.$list.len()].clone()
+            );
+        )
+        animations.push(Animation {
+            node: node,
+            kind: KeyframesAnimation(name.clone(), keyframes),
+            start_time: now + nth!(animation_delay).seconds(),
+            duration: nth!(animation_duration).seconds(),
+            timing_function: nth!(animation_timing_function),
+            iteration_count: nth!(animation_iteration_count),
+            direction: nth!(animation_direction),
+            fill_mode: nth!(animation_fill_mode),
+            finished: false,
+        });
+        started = true;
+    }
+    started
+}
+
+/// Returns the output of a timing function for the given input progress, according to
+/// CSS-TRANSITIONS § 2.3.
+fn evaluate_timing_function(timing_function: &TimingFunction, progress: f64) -> f64 {
+    match *timing_function {
+        CubicBezier(x1, y1, x2, y2) => evaluate_cubic_bezier(x1, y1, x2, y2, progress),
+        Steps(steps, position) => {
+            let steps = steps as f64;
+            let step = match position {
+                StepStart => (progress * steps).ceil(),
+                StepEnd => (progress * steps).floor(),
+            };
+            step.min(steps) / steps
+        }
+    }
+}
+
+/// Returns the y coordinate of the point of the curve with control points (0, 0), (x1, y1),
+/// (x2, y2) and (1, 1) whose x coordinate is given.
+fn evaluate_cubic_bezier(x1: f64, y1: f64, x2: f64, y2: f64, x: f64) -> f64 {
+    fn bezier(p1: f64, p2: f64, t: f64) -> f64 {
+        let u = 1.0 - t;
+        3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t
+    }
+    fn bezier_derivative(p1: f64, p2: f64, t: f64) -> f64 {
+        let u = 1.0 - t;
+        3.0 * u * u * p1 + 6.0 * u * t * (p2 - p1) + 3.0 * t * t * (1.0 - p2)
+    }
+
+    static EPSILON: f64 = 1e-6;
+    if x <= 0.0 || x >= 1.0 {
+        return x
+    }
+
+    // Newton's method converges quickly for most curves.
+    let mut t = x;
+    for _ in range(0u, 8) {
+        let error = bezier(x1, x2, t) - x;
+        if error.abs() < EPSILON {
+            return bezier(y1, y2, t)
+        }
+        let derivative = bezier_derivative(x1, x2, t);
+        if derivative.abs() < EPSILON {
+            break
+        }
+        t -= error / derivative;
+    }
+
+    // Otherwise, fall back to bisection, as x grows monotonically with t.
+    let (mut low, mut high) = (0.0, 1.0);
+    t = x;
+    while high - low > EPSILON {
+        if bezier(x1, x2, t) < x {
+            low = t
+        } else {
+            high = t
+        }
+        t = (low + high) / 2.0;
+    }
+    bezier(y1, y2, t)
+}
+
+/// A computed value that can be interpolated.
+trait Interpolate {
+    /// Returns the value at the given progress from this value to the other one, or `None` if
+    /// the two values cannot be interpolated.
+    fn interpolate(&self, other: &Self, progress: f64) -> Option<Self>;
+}
+
+impl Interpolate for Au {
+    fn interpolate(&self, other: &Au, progress: f64) -> Option<Au> {
+        let (Au(from), Au(to)) = (*self, *other);
+        Some(Au((from as f64 + (to - from) as f64 * progress).round() as i32))
+    }
+}
+
+impl Interpolate for CSSFloat {
+    fn interpolate(&self, other: &CSSFloat, progress: f64) -> Option<CSSFloat> {
+        Some(*self + (*other - *self) * progress)
+    }
+}
+
+impl Interpolate for LengthOrPercentage {
+    fn interpolate(&self, other: &LengthOrPercentage, progress: f64)
+                   -> Option<LengthOrPercentage> {
+        match (*self, *other) {
+            (LP_Length(from), LP_Length(to)) => {
+                from.interpolate(&to, progress).map(LP_Length)
+            }
+            (LP_Percentage(from), LP_Percentage(to)) => {
+                from.interpolate(&to, progress).map(LP_Percentage)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Interpolate for LengthOrPercentageOrAuto {
+    fn interpolate(&self, other: &LengthOrPercentageOrAuto, progress: f64)
+                   -> Option<LengthOrPercentageOrAuto> {
+        match (*self, *other) {
+            (LPA_Length(from), LPA_Length(to)) => {
+                from.interpolate(&to, progress).map(LPA_Length)
+            }
+            (LPA_Percentage(from), LPA_Percentage(to)) => {
+                from.interpolate(&to, progress).map(LPA_Percentage)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Interpolate for RGBA {
+    fn interpolate(&self, other: &RGBA, progress: f64) -> Option<RGBA> {
+        fn channel(from: f32, to: f32, progress: f64) -> f32 {
+            (from as f64 + (to - from) as f64 * progress) as f32
+        }
+        Some(RGBA {
+            red: channel(self.red, other.red, progress),
+            green: channel(self.green, other.green, progress),
+            blue: channel(self.blue, other.blue, progress),
+            alpha: channel(self.alpha, other.alpha, progress),
+        })
+    }
+}
+
+impl Interpolate for style::computed::CSSColor {
+    fn interpolate(&self, other: &style::computed::CSSColor, progress: f64)
+                   -> Option<style::computed::CSSColor> {
+        match (*self, *other) {
+            (style::RGBAColor(ref from), style::RGBAColor(ref to)) => {
+                from.interpolate(to, progress).map(style::RGBAColor)
+            }
+            _ => None,
+        }
+    }
+}
+
+macro_rules! animated_properties(
+    ($($name: expr => $variant: ident: $getter: ident, $mutator: ident, $field: ident),+) => (
+        /// A property that can be transitioned and animated.
+        #[deriving(Clone, PartialEq, Show)]
+        pub enum AnimatedProperty {
+            $($variant),+
+        }
+
+        impl AnimatedProperty {
+            /// Returns the property with the given lower-case name, if it can be animated.
+            pub fn from_name(name: &str) -> Option<AnimatedProperty> {
+                $(
+                    if name == $name {
+                        return Some($variant)
+                    }
+                )+
+                None
+            }
+
+            pub fn all() -> Vec<AnimatedProperty> {
+                vec!($($variant),+)
+            }
+
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $($variant => $name),+
+                }
+            }
+
+            /// Returns true if the property has different values in the two styles.
+            pub fn differs(&self, a: &ComputedValues, b: &ComputedValues) -> bool {
+                match *self {
+                    $($variant => a.$getter().$field != b.$getter().$field),+
+                }
+            }
+
+            /// Returns true if the values of the property in the two styles can be interpolated.
+            pub fn can_interpolate(&self, from: &ComputedValues, to: &ComputedValues) -> bool {
+                match *self {
+                    $(
+                        $variant => {
+                            from.$getter().$field.interpolate(&to.$getter().$field, 0.0).is_some()
+                        }
+                    ),+
+                }
+            }
+
+            /// Sets the property in the given style to its value at the given progress between
+            /// two styles. Values that cannot be interpolated switch halfway through.
+            pub fn update(&self,
+                          style: &mut ComputedValues,
+                          from: &ComputedValues,
+                          to: &ComputedValues,
+                          progress: f64) {
+                match *self {
+                    $(
+                        $variant => {
+                            let from = &from.$getter().$field;
+                            let to = &to.$getter().$field;
+                            style.$mutator().$field = match from.interpolate(to, progress) {
+                                Some(value) => value,
+                                None if progress < 0.5 => from.clone(),
+                                None => to.clone(),
+                            }
+                        }
+                    ),+
+                }
+            }
+        }
+    );
+)
+
+animated_properties!(
+    "top" => Top: get_positionoffsets, mutate_positionoffsets, top,
+    "right" => Right: get_positionoffsets, mutate_positionoffsets, right,
+    "bottom" => Bottom: get_positionoffsets, mutate_positionoffsets, bottom,
+    "left" => Left: get_positionoffsets, mutate_positionoffsets, left,
+    "width" => Width: get_box, mutate_box, width,
+    "height" => Height: get_box, mutate_box, height,
+    "opacity" => Opacity: get_box, mutate_box, opacity,
+    "margin-top" => MarginTop: get_margin, mutate_margin, margin_top,
+    "margin-right" => MarginRight: get_margin, mutate_margin, margin_right,
+    "margin-bottom" => MarginBottom: get_margin, mutate_margin, margin_bottom,
+    "margin-left" => MarginLeft: get_margin, mutate_margin, margin_left,
+    "padding-top" => PaddingTop: get_padding, mutate_padding, padding_top,
+    "padding-right" => PaddingRight: get_padding, mutate_padding, padding_right,
+    "padding-bottom" => PaddingBottom: get_padding, mutate_padding, padding_bottom,
+    "padding-left" => PaddingLeft: get_padding, mutate_padding, padding_left,
+    "border-top-width" => BorderTopWidth: get_border, mutate_border, border_top_width,
+    "border-right-width" => BorderRightWidth: get_border, mutate_border, border_right_width,
+    "border-bottom-width" => BorderBottomWidth: get_border, mutate_border, border_bottom_width,
+    "border-left-width" => BorderLeftWidth: get_border, mutate_border, border_left_width,
+    "border-top-color" => BorderTopColor: get_border, mutate_border, border_top_color,
+    "border-right-color" => BorderRightColor: get_border, mutate_border, border_right_color,
+    "border-bottom-color" => BorderBottomColor: get_border, mutate_border, border_bottom_color,
+    "border-left-color" => BorderLeftColor: get_border, mutate_border, border_left_color,
+    "background-color" => BackgroundColor: get_background, mutate_background, background_color,
+    "color" => Color: get_color, mutate_color, color
+)
@@ -502,13 +502,12 @@ enum FormattingContextType {
 // Propagates the `layers_needed_for_descendants` flag appropriately from a child. This is called
 // as part of block-size assignment.
 //
-// If any fixed descendants of kids are present, this kid needs a layer.
+// If any fixed or 3D-transformed descendants of kids are present, this kid needs a layer.
 //
 // FIXME(#2006, pcwalton): This is too layer-happy. Like WebKit, we shouldn't do this unless
 // the positioned descendants are actually on top of the fixed kids.
 //
-// TODO(#1244, #2007, pcwalton): Do this for CSS transforms and opacity too, at least if they're
-// animating.
+// TODO(#1244, #2007, pcwalton): Do this for opacity too, at least if it's animating.
 fn propagate_layer_flag_from_child(layers_needed_for_descendants: &mut bool, kid: &mut Flow) {
     if kid.is_absolute_containing_block() {
         let kid_base = flow::mut_base(kid);
@@ -517,7 +516,7 @@ fn propagate_layer_flag_from_child(layers_needed_for_descendants: &mut bool, kid
         }
     } else {
         let kid_base = flow::mut_base(kid);
-        if kid_base.flags.contains(LAYERS_NEEDED_FOR_DESCENDANTS) {
+        if kid_base.flags.intersects(NEEDS_LAYER | LAYERS_NEEDED_FOR_DESCENDANTS) {
             *layers_needed_for_descendants = true
         }
     }
@@ -993,6 +992,11 @@ impl BlockFlow {
                 block_size = block_size + floats.clearance(ClearBoth);
             }
 
+            // 3D transforms are drawn by the compositor, so they need layers.
+            if self.fragment.has_3d_transform() {
+                self.base.flags.insert(NEEDS_LAYER);
+            }
+
             if self.base.flags.contains(IS_ABSOLUTELY_POSITIONED) {
                 // Fixed position layers get layers.
                 if self.is_fixed() {
@@ -1269,7 +1273,7 @@ impl BlockFlow {
         let mut inline_end_floats_impact_child =
             self.base.flags.contains(IMPACTED_BY_RIGHT_FLOATS);
 
-        let absolute_static_i_offset = if self.is_positioned() {
+        let absolute_static_i_offset = if self.is_absolute_containing_block() {
             // This flow is the containing block. The static inline offset will be the inline-start
             // padding edge.
             self.fragment.border_padding.inline_start
@@ -1707,12 +1711,12 @@ impl Flow for BlockFlow {
 
         // For relatively-positioned descendants, the containing block formed by a block is just
         // the content box. The containing block for absolutely-positioned descendants, on the
-        // other hand, is only established if we are positioned.
+        // other hand, is only established if we are positioned or transformed.
         let relative_offset =
             self.fragment.relative_position(&self.base
                                                  .absolute_position_info
                                                  .relative_containing_block_size);
-        if self.is_positioned() {
+        if self.is_absolute_containing_block() {
             self.base
                 .absolute_position_info
                 .stacking_relative_position_of_absolute_containing_block =
@@ -1724,7 +1728,11 @@ impl Flow for BlockFlow {
         // Compute absolute position info for children.
         let absolute_position_info_for_children = AbsolutePositionInfo {
             stacking_relative_position_of_absolute_containing_block:
-                if self.fragment.establishes_stacking_context() {
+                if !self.fragment.establishes_stacking_context() {
+                    self.base
+                        .absolute_position_info
+                        .stacking_relative_position_of_absolute_containing_block
+                } else if self.is_absolute_containing_block() {
                     let logical_border_width = self.fragment.style().logical_border_width();
                     LogicalPoint::new(self.base.writing_mode,
                                       logical_border_width.inline_start,
@@ -1732,20 +1740,26 @@ impl Flow for BlockFlow {
                                           self.base.writing_mode,
                                           container_size)
                 } else {
+                    // A stacking context that is not a containing block, such as that of a
+                    // static translucent block, only moves the containing block of its absolute
+                    // descendants into its own coordinates.
                     self.base
                         .absolute_position_info
-                        .stacking_relative_position_of_absolute_containing_block
+                        .stacking_relative_position_of_absolute_containing_block -
+                        self.base.stacking_relative_position
                 },
             relative_containing_block_size: self.fragment.content_box().size,
             layers_needed_for_positioned_flows: self.base.flags.contains(LAYERS_NEEDED_FOR_DESCENDANTS),
         };
 
-        // Compute the origin and clipping rectangle for children.
-        let origin_for_children = if self.fragment.establishes_stacking_context() {
-            ZERO_POINT
-        } else {
-            self.base.stacking_relative_position
-        };
+        // Compute the origin and clipping rectangle for children. A stacking context is placed at
+        // our relative position already, so its children must not be offset again.
+        let (origin_for_children, relative_offset_for_children) =
+            if self.fragment.establishes_stacking_context() {
+                (ZERO_POINT, LogicalSize::zero(self.base.writing_mode))
+            } else {
+                (self.base.stacking_relative_position, relative_offset)
+            };
         let clip_rect = self.fragment.clip_rect_for_children(self.base.clip_rect,
                                                              origin_for_children);
 
@@ -1756,8 +1770,8 @@ impl Flow for BlockFlow {
                 let kid_base = flow::mut_base(kid);
                 kid_base.stacking_relative_position =
                     origin_for_children +
-                    (kid_base.position.start + relative_offset).to_physical(writing_mode,
-                                                                            container_size);
+                    (kid_base.position.start +
+                     relative_offset_for_children).to_physical(writing_mode, container_size);
                 kid_base.absolute_position_info = absolute_position_info_for_children
             }
 
@@ -1793,9 +1807,12 @@ impl Flow for BlockFlow {
 
     /// Return true if this is the root of an Absolute flow tree.
     ///
-    /// It has to be either relatively positioned or the Root flow.
+    /// It has to be either the Root flow or a containing block for absolutely-positioned flows
+    /// that is not absolutely positioned itself, such as a relatively-positioned or transformed
+    /// flow.
     fn is_root_of_absolute_flow_tree(&self) -> bool {
-        self.is_relatively_positioned() || self.is_root()
+        (self.is_absolute_containing_block() &&
+         !self.base.flags.contains(IS_ABSOLUTELY_POSITIONED)) || self.is_root()
     }
 
     /// Return the dimensions of the containing block generated by this flow for absolutely-
@@ -1811,8 +1828,11 @@ impl Flow for BlockFlow {
         LayerId(self.fragment.node.id() as uint, fragment_index)
     }
 
+    /// Transformed blocks contain absolutely-positioned descendants like positioned ones do.
+    ///
+    /// FIXME: They should contain fixed-position descendants too.
     fn is_absolute_containing_block(&self) -> bool {
-        self.is_positioned()
+        self.is_positioned() || self.fragment.is_transformed()
     }
 
     fn update_late_computed_inline_position_if_necessary(&mut self, inline_position: Au) {
@@ -1832,12 +1852,12 @@ impl Flow for BlockFlow {
     }
 
     fn build_display_list(&mut self, layout_context: &LayoutContext) {
-        if self.base.flags.is_float() {
+        if self.fragment.establishes_stacking_context() {
+            self.build_display_list_for_stacking_context_block(layout_context)
+        } else if self.base.flags.is_float() {
             // TODO(#2009, pcwalton): This is a pseudo-stacking context. We need to merge `z-index:
             // auto` kids into the parent stacking context, when that is supported.
             self.build_display_list_for_floating_block(layout_context)
-        } else if self.base.flags.contains(IS_ABSOLUTELY_POSITIONED) {
-            self.build_display_list_for_absolutely_positioned_block(layout_context)
         } else {
             self.build_display_list_for_block(layout_context, BlockLevel)
         }
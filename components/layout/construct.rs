@@ -527,9 +527,9 @@ impl<'a> FlowConstructor<'a> {
         flow.finish();
 
         // Set up the absolute descendants.
-        let is_positioned = flow.as_block().is_positioned();
+        let is_containing_block = flow.as_block().is_absolute_containing_block();
         let is_absolutely_positioned = flow::base(&*flow).flags.contains(IS_ABSOLUTELY_POSITIONED);
-        if is_positioned {
+        if is_containing_block {
             // This is the containing block for all the absolute descendants.
             flow.set_absolute_descendants(abs_descendants);
 
@@ -841,10 +841,10 @@ impl<'a> FlowConstructor<'a> {
 
         // The flow is done.
         wrapper_flow.finish();
-        let is_positioned = wrapper_flow.as_block().is_positioned();
+        let is_containing_block = wrapper_flow.as_block().is_absolute_containing_block();
         let is_fixed_positioned = wrapper_flow.as_block().is_fixed();
         let is_absolutely_positioned = flow::base(&*wrapper_flow).flags.contains(IS_ABSOLUTELY_POSITIONED);
-        if is_positioned {
+        if is_containing_block {
             // This is the containing block for all the absolute descendants.
             wrapper_flow.set_absolute_descendants(abs_descendants);
 
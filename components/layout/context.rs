@@ -4,6 +4,7 @@
 
 //! Data needed by the layout task.
 
+use animation::Animation;
 use css::matching::{ApplicableDeclarationsCache, StyleSharingCandidateCache};
 
 use geom::{Rect, Size2D};
@@ -16,6 +17,8 @@ use servo_msg::constellation_msg::ConstellationChan;
 use servo_net::local_image_cache::LocalImageCache;
 use servo_util::geometry::Au;
 use sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::comm::Sender;
 use std::mem;
 use style::Stylist;
 use url::Url;
@@ -80,6 +83,16 @@ pub struct SharedLayoutContext {
     /// Starts at zero, and increased by one every time a layout completes.
     /// This can be used to easily check for invalid stale data.
     pub generation: uint,
+
+    /// The transitions and animations that were running when this layout started, by node.
+    pub running_animations: Arc<HashMap<OpaqueNode, Vec<Animation>>>,
+
+    /// A channel on which the new list of animations of a node is sent to the layout task when
+    /// its style changes them.
+    pub new_animations_sender: Mutex<Sender<(OpaqueNode, Vec<Animation>)>>,
+
+    /// The time at which animations are sampled during this layout, in seconds.
+    pub animation_time: f64,
 }
 
 pub struct LayoutContext<'a> {
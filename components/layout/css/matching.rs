@@ -4,12 +4,15 @@
 
 //! High-level interface to CSS selector matching.
 
+use animation;
+use context::SharedLayoutContext;
 use css::node_style::StyledNode;
 use incremental::{mod, RestyleDamage};
-use util::{LayoutDataAccess, LayoutDataWrapper};
+use util::{LayoutDataAccess, LayoutDataWrapper, OpaqueNodeMethods};
 use wrapper::{LayoutElement, LayoutNode, TLayoutNode};
 
 use script::dom::node::{TextNodeTypeId};
+use gfx::display_list::OpaqueNode;
 use servo_util::bloom::BloomFilter;
 use servo_util::cache::{Cache, LRUCache, SimpleHashCache};
 use servo_util::smallvec::{SmallVec, SmallVec16};
@@ -236,6 +239,11 @@ impl StyleSharingCandidate {
             return None
         }
 
+        // What such styles do depends on the previous style of the node.
+        if animation::style_may_animate(&*style) {
+            return None
+        }
+
         Some(StyleSharingCandidate {
             style: style,
             parent_style: parent_style,
@@ -372,6 +380,7 @@ pub trait MatchMethods {
 
     unsafe fn cascade_node(&self,
                            parent: Option<LayoutNode>,
+                           shared_context: &SharedLayoutContext,
                            applicable_declarations: &ApplicableDeclarations,
                            applicable_declarations_cache: &mut ApplicableDeclarationsCache);
 }
@@ -383,7 +392,8 @@ trait PrivateMatchMethods {
                                    style: &mut Option<Arc<ComputedValues>>,
                                    applicable_declarations_cache: &mut
                                    ApplicableDeclarationsCache,
-                                   shareable: bool)
+                                   shareable: bool,
+                                   animation_context: Option<&SharedLayoutContext>)
                                    -> RestyleDamage;
 
     fn share_style_with_candidate_if_possible(&self,
@@ -399,9 +409,10 @@ impl<'ln> PrivateMatchMethods for LayoutNode<'ln> {
                                    style: &mut Option<Arc<ComputedValues>>,
                                    applicable_declarations_cache: &mut
                                    ApplicableDeclarationsCache,
-                                   shareable: bool)
+                                   shareable: bool,
+                                   animation_context: Option<&SharedLayoutContext>)
                                    -> RestyleDamage {
-        let this_style;
+        let mut this_style;
         let cacheable;
         match parent_style {
             Some(ref parent_style) => {
@@ -432,6 +443,20 @@ impl<'ln> PrivateMatchMethods for LayoutNode<'ln> {
             applicable_declarations_cache.insert(applicable_declarations, this_style.clone());
         }
 
+        // Start transitions and animations, and apply the running ones.
+        match animation_context {
+            Some(context) => {
+                let node: OpaqueNode = OpaqueNodeMethods::from_layout_node(self);
+                animation::process_style(context,
+                                         node,
+                                         style.as_ref(),
+                                         &mut this_style,
+                                         parent_style.map(|style| &**style),
+                                         applicable_declarations)
+            }
+            None => {}
+        }
+
         // Calculate style difference and write.
         let damage = incremental::compute_damage(style, &*this_style);
         *style = Some(this_style);
@@ -583,6 +608,7 @@ impl<'ln> MatchMethods for LayoutNode<'ln> {
 
     unsafe fn cascade_node(&self,
                            parent: Option<LayoutNode>,
+                           shared_context: &SharedLayoutContext,
                            applicable_declarations: &ApplicableDeclarations,
                            applicable_declarations_cache: &mut ApplicableDeclarationsCache) {
         // Get our parent's style. This must be unsafe so that we don't touch the parent's
@@ -619,14 +645,16 @@ impl<'ln> MatchMethods for LayoutNode<'ln> {
                             applicable_declarations.normal.as_slice(),
                             &mut layout_data.shared_data.style,
                             applicable_declarations_cache,
-                            applicable_declarations.normal_shareable);
+                            applicable_declarations.normal_shareable,
+                            Some(shared_context));
                         if applicable_declarations.before.len() > 0 {
                            damage = damage | self.cascade_node_pseudo_element(
                                Some(layout_data.shared_data.style.as_ref().unwrap()),
                                applicable_declarations.before.as_slice(),
                                &mut layout_data.data.before_style,
                                applicable_declarations_cache,
-                               false);
+                               false,
+                               None);
                         }
                         if applicable_declarations.after.len() > 0 {
                            damage = damage | self.cascade_node_pseudo_element(
@@ -634,7 +662,8 @@ impl<'ln> MatchMethods for LayoutNode<'ln> {
                                applicable_declarations.after.as_slice(),
                                &mut layout_data.data.after_style,
                                applicable_declarations_cache,
-                               false);
+                               false,
+                               None);
                         }
                         layout_data.data.restyle_damage = damage;
                     }
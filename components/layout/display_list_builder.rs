@@ -23,14 +23,16 @@ use model;
 use util::{OpaqueNodeMethods, ToGfxColor};
 
 use geom::approxeq::ApproxEq;
+use geom::matrix::{Matrix4, identity};
 use geom::{Point2D, Rect, Size2D, SideOffsets2D};
 use gfx::color;
-use gfx::display_list::{BaseDisplayItem, BorderDisplayItem, BorderDisplayItemClass, DisplayItem};
+use gfx::display_list::{BaseDisplayItem, BorderDisplayItem, BorderDisplayItemClass, BorderRadii};
+use gfx::display_list::{BoxShadowDisplayItem, BoxShadowDisplayItemClass, DisplayItem};
 use gfx::display_list::{DisplayList, GradientDisplayItem, GradientDisplayItemClass, GradientStop};
 use gfx::display_list::{ImageDisplayItem, ImageDisplayItemClass, LineDisplayItem};
-use gfx::display_list::{LineDisplayItemClass, PseudoDisplayItemClass, SidewaysLeft, SidewaysRight};
-use gfx::display_list::{SolidColorDisplayItem, SolidColorDisplayItemClass, StackingContext};
-use gfx::display_list::{TextDisplayItem, TextDisplayItemClass, Upright};
+use gfx::display_list::{LineDisplayItemClass, PseudoDisplayItemClass, RoundedRect, SidewaysLeft};
+use gfx::display_list::{SidewaysRight, SolidColorDisplayItem, SolidColorDisplayItemClass};
+use gfx::display_list::{StackingContext, TextDisplayItem, TextDisplayItemClass, Upright};
 use gfx::render_task::RenderLayer;
 use servo_msg::compositor_msg::{FixedPosition, Scrollable};
 use servo_msg::constellation_msg::{ConstellationChan, FrameRectMsg};
@@ -41,8 +43,9 @@ use servo_util::opts;
 use style::computed::{AngleAoc, CornerAoc, LP_Length, LP_Percentage, LengthOrPercentage};
 use style::computed::{LinearGradient, LinearGradientImage, UrlImage};
 use style::computed_values::{background_attachment, background_repeat, border_style, overflow};
-use style::computed_values::{visibility};
-use style::{ComputedValues, Bottom, Left, RGBA, Right, Top};
+use style::computed_values::{border_top_left_radius, transform, visibility};
+use style::{CSSFloat, ComputedValues, Bottom, Left, RGBA, Right, Top};
+use std::cmp;
 use sync::Arc;
 use url::Url;
 
@@ -102,6 +105,16 @@ pub trait FragmentDisplayListBuilding {
                                                          gradient: &LinearGradient,
                                                          style: &ComputedValues);
 
+    /// Adds the display items necessary to paint the outer shadows of this fragment or, if `inset`
+    /// is true, its inset shadows to the display list, if it has any.
+    fn build_display_list_for_box_shadows_if_applicable(&self,
+                                                        style: &ComputedValues,
+                                                        display_list: &mut DisplayList,
+                                                        level: StackingLevel,
+                                                        absolute_bounds: &Rect<Au>,
+                                                        clip_rect: &Rect<Au>,
+                                                        inset: bool);
+
     /// Adds the display items necessary to paint the borders of this fragment to a display list if
     /// necessary.
     fn build_display_list_for_borders_if_applicable(&self,
@@ -147,6 +160,10 @@ pub trait FragmentDisplayListBuilding {
 
     fn clip_rect_for_children(&self, current_clip_rect: Rect<Au>, flow_origin: Point2D<Au>)
                               -> Rect<Au>;
+
+    /// Returns the matrix that the `transform` and `transform-origin` properties of this fragment
+    /// describe, given its border box in the coordinate system of its stacking context.
+    fn transform_matrix(&self, border_box: &Rect<Au>) -> Matrix4<f32>;
 }
 
 impl FragmentDisplayListBuilding for Fragment {
@@ -163,8 +180,10 @@ impl FragmentDisplayListBuilding for Fragment {
         // doesn't have a fragment".
         let background_color = style.resolve_color(style.get_background().background_color);
         if !background_color.alpha.approx_eq(&0.0) {
+            let mut base = BaseDisplayItem::new(*absolute_bounds, self.node, *clip_rect);
+            base.rounded_clip = background_rounded_clip(style, absolute_bounds);
             display_list.push(SolidColorDisplayItemClass(box SolidColorDisplayItem {
-                base: BaseDisplayItem::new(*absolute_bounds, self.node, *clip_rect),
+                base: base,
                 color: background_color.to_gfx_color(),
             }), level);
         }
@@ -275,8 +294,10 @@ impl FragmentDisplayListBuilding for Fragment {
         };
 
         // Create the image display item.
+        let mut base = BaseDisplayItem::new(bounds, self.node, clip_rect);
+        base.rounded_clip = background_rounded_clip(style, absolute_bounds);
         display_list.push(ImageDisplayItemClass(box ImageDisplayItem {
-            base: BaseDisplayItem::new(bounds, self.node, clip_rect),
+            base: base,
             image: image.clone(),
             stretch_size: Size2D(Au::from_px(image.width as int),
                                  Au::from_px(image.height as int)),
@@ -383,8 +404,10 @@ impl FragmentDisplayListBuilding for Fragment {
         let center = Point2D(absolute_bounds.origin.x + absolute_bounds.size.width / 2,
                              absolute_bounds.origin.y + absolute_bounds.size.height / 2);
 
+        let mut base = BaseDisplayItem::new(*absolute_bounds, self.node, clip_rect);
+        base.rounded_clip = background_rounded_clip(style, absolute_bounds);
         let gradient_display_item = GradientDisplayItemClass(box GradientDisplayItem {
-            base: BaseDisplayItem::new(*absolute_bounds, self.node, clip_rect),
+            base: base,
             start_point: center - delta,
             end_point: center + delta,
             stops: stops,
@@ -393,6 +416,59 @@ impl FragmentDisplayListBuilding for Fragment {
         display_list.push(gradient_display_item, level)
     }
 
+    fn build_display_list_for_box_shadows_if_applicable(&self,
+                                                        style: &ComputedValues,
+                                                        display_list: &mut DisplayList,
+                                                        level: StackingLevel,
+                                                        absolute_bounds: &Rect<Au>,
+                                                        clip_rect: &Rect<Au>,
+                                                        inset: bool) {
+        let box_shadows = &style.get_border().box_shadow;
+        if box_shadows.iter().all(|box_shadow| box_shadow.inset != inset) {
+            return
+        }
+
+        // Outer shadows are cast by the border box and inset ones by the padding box, per
+        // CSS-BACKGROUNDS § 7.1.
+        let border_radii = border_radii(style, absolute_bounds);
+        let (box_bounds, radii) = if inset {
+            let border = style.logical_border_width().to_physical(style.writing_mode);
+            padding_box(absolute_bounds, &border_radii, &border)
+        } else {
+            (*absolute_bounds, border_radii)
+        };
+
+        // The first shadow is the topmost one.
+        for box_shadow in box_shadows.iter().rev() {
+            if box_shadow.inset != inset {
+                continue
+            }
+
+            let offset = Point2D(box_shadow.offset_x, box_shadow.offset_y);
+            let bounds = if inset {
+                box_bounds
+            } else {
+                let inflation = cmp::max(box_shadow.spread_radius, Au(0)) +
+                    box_shadow.blur_radius;
+                let shadow_bounds = Rect(box_bounds.origin + offset - Point2D(inflation, inflation),
+                                         Size2D(box_bounds.size.width + inflation + inflation,
+                                                box_bounds.size.height + inflation + inflation));
+                union_rect(&box_bounds, &shadow_bounds)
+            };
+
+            display_list.push(BoxShadowDisplayItemClass(box BoxShadowDisplayItem {
+                base: BaseDisplayItem::new(bounds, self.node, *clip_rect),
+                box_bounds: box_bounds,
+                radii: radii.clone(),
+                offset: offset,
+                color: style.resolve_color(box_shadow.color).to_gfx_color(),
+                blur_radius: box_shadow.blur_radius,
+                spread_radius: box_shadow.spread_radius,
+                inset: inset,
+            }), level);
+        }
+    }
+
     fn build_display_list_for_borders_if_applicable(&self,
                                                     style: &ComputedValues,
                                                     display_list: &mut DisplayList,
@@ -420,7 +496,8 @@ impl FragmentDisplayListBuilding for Fragment {
             style: SideOffsets2D::new(style.get_border().border_top_style,
                                       style.get_border().border_right_style,
                                       style.get_border().border_bottom_style,
-                                      style.get_border().border_left_style)
+                                      style.get_border().border_left_style),
+            radius: border_radii(style, abs_bounds),
         }), level);
     }
 
@@ -442,7 +519,8 @@ impl FragmentDisplayListBuilding for Fragment {
             base: BaseDisplayItem::new(absolute_fragment_bounds, self.node, *clip_rect),
             border: SideOffsets2D::new_all_same(Au::from_px(1)),
             color: SideOffsets2D::new_all_same(color::rgb(0, 0, 200)),
-            style: SideOffsets2D::new_all_same(border_style::solid)
+            style: SideOffsets2D::new_all_same(border_style::solid),
+            radius: BorderRadii::zero(),
         }));
 
         // Draw a rectangle representing the baselines.
@@ -478,7 +556,8 @@ impl FragmentDisplayListBuilding for Fragment {
             base: BaseDisplayItem::new(absolute_fragment_bounds, self.node, *clip_rect),
             border: SideOffsets2D::new_all_same(Au::from_px(1)),
             color: SideOffsets2D::new_all_same(color::rgb(0, 0, 200)),
-            style: SideOffsets2D::new_all_same(border_style::solid)
+            style: SideOffsets2D::new_all_same(border_style::solid),
+            radius: BorderRadii::zero(),
         }));
     }
 
@@ -539,6 +618,34 @@ impl FragmentDisplayListBuilding for Fragment {
                                                              *clip_rect);
             display_list.push(PseudoDisplayItemClass(base_display_item), level);
 
+            // Add the outer shadows, which are painted below the background, if applicable.
+            match self.inline_context {
+                Some(ref inline_context) => {
+                    for style in inline_context.styles.iter().rev() {
+                        self.build_display_list_for_box_shadows_if_applicable(
+                            &**style,
+                            display_list,
+                            level,
+                            &absolute_fragment_bounds,
+                            clip_rect,
+                            false);
+                    }
+                }
+                None => {}
+            }
+            match self.specific {
+                ScannedTextFragment(_) => {},
+                _ => {
+                    self.build_display_list_for_box_shadows_if_applicable(
+                        &*self.style,
+                        display_list,
+                        level,
+                        &absolute_fragment_bounds,
+                        clip_rect,
+                        false);
+                }
+            }
+
             // Add the background to the list, if applicable.
             match self.inline_context {
                 Some(ref inline_context) => {
@@ -567,6 +674,34 @@ impl FragmentDisplayListBuilding for Fragment {
                 }
             }
 
+            // Add the inset shadows, which are painted above the background, if applicable.
+            match self.inline_context {
+                Some(ref inline_context) => {
+                    for style in inline_context.styles.iter().rev() {
+                        self.build_display_list_for_box_shadows_if_applicable(
+                            &**style,
+                            display_list,
+                            level,
+                            &absolute_fragment_bounds,
+                            clip_rect,
+                            true);
+                    }
+                }
+                None => {}
+            }
+            match self.specific {
+                ScannedTextFragment(_) => {},
+                _ => {
+                    self.build_display_list_for_box_shadows_if_applicable(
+                        &*self.style,
+                        display_list,
+                        level,
+                        &absolute_fragment_bounds,
+                        clip_rect,
+                        true);
+                }
+            }
+
             // Add a border, if applicable.
             //
             // TODO: Outlines.
@@ -780,6 +915,90 @@ impl FragmentDisplayListBuilding for Fragment {
         current_clip_rect.intersection(&Rect(physical_rect.origin + flow_origin,
                                              physical_rect.size)).unwrap_or(ZERO_RECT)
     }
+
+    fn transform_matrix(&self, border_box: &Rect<Au>) -> Matrix4<f32> {
+        let operations = match self.style.get_box().transform {
+            None => return identity(),
+            Some(ref operations) => operations,
+        };
+
+        let transform_origin = &self.style.get_box().transform_origin;
+        let origin_x = to_px(border_box.origin.x +
+                             model::specified(transform_origin.horizontal,
+                                              border_box.size.width));
+        let origin_y = to_px(border_box.origin.y +
+                             model::specified(transform_origin.vertical,
+                                              border_box.size.height));
+        let origin_z = to_px(transform_origin.depth);
+
+        // The operations apply from right to left, about the transform origin.
+        let mut matrix = identity().translate(origin_x, origin_y, origin_z);
+        for operation in operations.iter() {
+            let operation_matrix = match *operation {
+                transform::Matrix(ref m) => {
+                    Matrix4(m.m11 as f32, m.m12 as f32, m.m13 as f32, m.m14 as f32,
+                            m.m21 as f32, m.m22 as f32, m.m23 as f32, m.m24 as f32,
+                            m.m31 as f32, m.m32 as f32, m.m33 as f32, m.m34 as f32,
+                            m.m41 as f32, m.m42 as f32, m.m43 as f32, m.m44 as f32)
+                }
+                transform::Translate(x, y, z) => {
+                    let x = model::specified(x, border_box.size.width);
+                    let y = model::specified(y, border_box.size.height);
+                    identity().translate(to_px(x), to_px(y), to_px(z))
+                }
+                transform::Scale(x, y, z) => identity().scale(x as f32, y as f32, z as f32),
+                transform::Rotate(x, y, z, angle) => rotation_matrix(x, y, z, angle.radians()),
+                transform::Skew(x, y) => {
+                    let (tan_x, tan_y) = (x.radians().tan() as f32, y.radians().tan() as f32);
+                    Matrix4(1.0,   tan_y, 0.0, 0.0,
+                            tan_x, 1.0,   0.0, 0.0,
+                            0.0,   0.0,   1.0, 0.0,
+                            0.0,   0.0,   0.0, 1.0)
+                }
+                transform::Perspective(depth) if depth > Au(0) => {
+                    Matrix4(1.0, 0.0, 0.0, 0.0,
+                            0.0, 1.0, 0.0, 0.0,
+                            0.0, 0.0, 1.0, -1.0 / to_px(depth),
+                            0.0, 0.0, 0.0, 1.0)
+                }
+                transform::Perspective(_) => continue,
+            };
+            matrix = matrix.mul(&operation_matrix)
+        }
+        matrix.translate(-origin_x, -origin_y, -origin_z)
+    }
+}
+
+fn to_px(length: Au) -> f32 {
+    geometry::to_frac_px(length) as f32
+}
+
+/// Returns the matrix of `rotate3d(x, y, z, angle)`, as given by CSS-TRANSFORMS § 13.
+fn rotation_matrix(x: CSSFloat, y: CSSFloat, z: CSSFloat, angle: CSSFloat) -> Matrix4<f32> {
+    let length = (x * x + y * y + z * z).sqrt();
+    if length == 0.0 {
+        return identity()
+    }
+    let (x, y, z) = (x / length, y / length, z / length);
+    let half_angle = angle / 2.0;
+    let sc = half_angle.sin() * half_angle.cos();
+    let sq = half_angle.sin() * half_angle.sin();
+    Matrix4((1.0 - 2.0 * (y * y + z * z) * sq) as f32,
+            (2.0 * (x * y * sq + z * sc)) as f32,
+            (2.0 * (x * z * sq - y * sc)) as f32,
+            0.0,
+            (2.0 * (x * y * sq - z * sc)) as f32,
+            (1.0 - 2.0 * (x * x + z * z) * sq) as f32,
+            (2.0 * (y * z * sq + x * sc)) as f32,
+            0.0,
+            (2.0 * (x * z * sq + y * sc)) as f32,
+            (2.0 * (y * z * sq - x * sc)) as f32,
+            (1.0 - 2.0 * (x * x + y * y) * sq) as f32,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            1.0)
 }
 
 pub trait BlockFlowDisplayListBuilding {
@@ -790,8 +1009,8 @@ pub trait BlockFlowDisplayListBuilding {
     fn build_display_list_for_block(&mut self,
                                     layout_context: &LayoutContext,
                                     background_border_level: BackgroundAndBorderLevel);
-    fn build_display_list_for_absolutely_positioned_block(&mut self,
-                                                          layout_context: &LayoutContext);
+    fn build_display_list_for_stacking_context_block(&mut self,
+                                                     layout_context: &LayoutContext);
     fn build_display_list_for_floating_block(&mut self, layout_context: &LayoutContext);
 }
 
@@ -835,24 +1054,44 @@ impl BlockFlowDisplayListBuilding for BlockFlow {
         self.base.display_list_building_result = DisplayListResult(display_list);
     }
 
-    fn build_display_list_for_absolutely_positioned_block(&mut self,
-                                                          layout_context: &LayoutContext) {
+    fn build_display_list_for_stacking_context_block(&mut self,
+                                                     layout_context: &LayoutContext) {
         let mut display_list = box DisplayList::new();
         self.build_display_list_for_block_base(&mut *display_list,
                                                layout_context,
                                                RootOfStackingContextLevel);
 
-        let bounds = Rect(self.base.stacking_relative_position,
-                          self.base.overflow.size.to_physical(self.base.writing_mode));
+        let bounds =
+            Rect(self.base.stacking_relative_position_of_child_fragment(&self.fragment),
+                 self.base.overflow.size.to_physical(self.base.writing_mode));
+
+        // The overflow is relative to the flow's own position, and extends above or to the left of
+        // it where, for example, box shadows do.
+        let writing_mode = self.base.writing_mode;
+        let overflow = LogicalRect::new(writing_mode,
+                                        self.base.overflow.start.i - self.base.position.start.i,
+                                        self.base.overflow.start.b - self.base.position.start.b,
+                                        self.base.overflow.size.inline,
+                                        self.base.overflow.size.block)
+                           .to_physical(writing_mode,
+                                        self.base.position.size.to_physical(writing_mode));
         let z_index = self.fragment.style().get_box().z_index.number_or_zero();
 
+        // The fragment draws its border box relative to the origin of the stacking context.
+        let border_box = self.fragment.stacking_relative_bounds(&ZERO_POINT);
+        let transform = self.fragment.transform_matrix(&border_box);
+        let opacity = self.fragment.style().get_box().opacity as f32;
+
         if !self.base.absolute_position_info.layers_needed_for_positioned_flows &&
                 !self.base.flags.contains(NEEDS_LAYER) {
             // We didn't need a layer.
             self.base.display_list_building_result =
                 StackingContextResult(Arc::new(StackingContext::new(display_list,
                                                                     bounds,
+                                                                    overflow,
                                                                     z_index,
+                                                                    transform,
+                                                                    opacity,
                                                                     None)));
             return
         }
@@ -868,7 +1107,10 @@ impl BlockFlowDisplayListBuilding for BlockFlow {
         let stacking_context =
             Arc::new(StackingContext::new(display_list,
                                           bounds,
+                                          overflow,
                                           z_index,
+                                          transform,
+                                          opacity,
                                           Some(Arc::new(RenderLayer::new(self.layer_id(0),
                                                                          transparent,
                                                                          scroll_policy)))));
@@ -886,6 +1128,89 @@ impl BlockFlowDisplayListBuilding for BlockFlow {
     }
 }
 
+/// Returns the radii of the corners of the given border box. If adjacent corners would overlap,
+/// all radii are scaled down until they do not, per CSS-BACKGROUNDS § 5.5.
+fn border_radii(style: &ComputedValues, border_box: &Rect<Au>) -> BorderRadii<Au> {
+    let border = style.get_border();
+    let corner = |radius: &border_top_left_radius::T| {
+        Size2D(model::specified(radius.horizontal, border_box.size.width),
+               model::specified(radius.vertical, border_box.size.height))
+    };
+    let radii = BorderRadii {
+        top_left: corner(&border.border_top_left_radius),
+        top_right: corner(&border.border_top_right_radius),
+        bottom_right: corner(&border.border_bottom_right_radius),
+        bottom_left: corner(&border.border_bottom_left_radius),
+    };
+
+    let sides = [
+        (border_box.size.width, radii.top_left.width + radii.top_right.width),
+        (border_box.size.height, radii.top_right.height + radii.bottom_right.height),
+        (border_box.size.width, radii.bottom_right.width + radii.bottom_left.width),
+        (border_box.size.height, radii.bottom_left.height + radii.top_left.height),
+    ];
+    let mut factor = 1.0f64;
+    for &(length, sum) in sides.iter() {
+        if sum > length {
+            factor = factor.min(length.to_f64().unwrap() / sum.to_f64().unwrap())
+        }
+    }
+    if factor == 1.0 {
+        return radii
+    }
+    let scale = |size: Size2D<Au>| {
+        Size2D(size.width.scale_by(factor), size.height.scale_by(factor))
+    };
+    BorderRadii {
+        top_left: scale(radii.top_left),
+        top_right: scale(radii.top_right),
+        bottom_right: scale(radii.bottom_right),
+        bottom_left: scale(radii.bottom_left),
+    }
+}
+
+/// Returns the rounded rectangle that the backgrounds of a box with the given border box are
+/// clipped to, or `None` if its corners are square.
+fn background_rounded_clip(style: &ComputedValues, border_box: &Rect<Au>) -> Option<RoundedRect> {
+    let radii = border_radii(style, border_box);
+    if radii.is_square() {
+        None
+    } else {
+        Some(RoundedRect {
+            rect: *border_box,
+            radii: radii,
+        })
+    }
+}
+
+/// Returns the padding box of a box with the given border box and border widths, together with
+/// the radii of its corners, which are those of the border box reduced by the border widths.
+fn padding_box(border_box: &Rect<Au>, radii: &BorderRadii<Au>, border: &SideOffsets2D<Au>)
+               -> (Rect<Au>, BorderRadii<Au>) {
+    let corner = |size: Size2D<Au>, horizontal: Au, vertical: Au| {
+        Size2D(cmp::max(size.width - horizontal, Au(0)),
+               cmp::max(size.height - vertical, Au(0)))
+    };
+    let padding_box =
+        Rect(border_box.origin + Point2D(border.left, border.top),
+             Size2D(cmp::max(border_box.size.width - border.left - border.right, Au(0)),
+                    cmp::max(border_box.size.height - border.top - border.bottom, Au(0))));
+    let padding_radii = BorderRadii {
+        top_left: corner(radii.top_left, border.left, border.top),
+        top_right: corner(radii.top_right, border.right, border.top),
+        bottom_right: corner(radii.bottom_right, border.right, border.bottom),
+        bottom_left: corner(radii.bottom_left, border.left, border.bottom),
+    };
+    (padding_box, padding_radii)
+}
+
+/// Returns the smallest rectangle that contains both of the given rectangles.
+fn union_rect(a: &Rect<Au>, b: &Rect<Au>) -> Rect<Au> {
+    let origin = Point2D(cmp::min(a.origin.x, b.origin.x), cmp::min(a.origin.y, b.origin.y));
+    Rect(origin, Size2D(cmp::max(a.max_x(), b.max_x()) - origin.x,
+                        cmp::max(a.max_y(), b.max_y()) - origin.y))
+}
+
 // A helper data structure for gradients.
 struct StopRun {
     start_offset: f32,
@@ -1178,6 +1178,17 @@ impl<'a> MutableFlowUtils for &'a mut Flow + 'a {
         let my_position = mut_base(self).position;
         let mut overflow = my_position;
 
+        // Outer box shadows paint outside the border boxes of fragments.
+        if self.is_block_like() {
+            let shadow_overflow = self.as_block().fragment.box_shadow_overflow();
+            overflow = overflow.union(&shadow_overflow.translate(&my_position.start))
+        } else if self.is_inline_flow() {
+            for fragment in self.as_inline().fragments.fragments.iter() {
+                let shadow_overflow = fragment.box_shadow_overflow();
+                overflow = overflow.union(&shadow_overflow.translate(&my_position.start))
+            }
+        }
+
         if self.is_block_container() {
             for kid in child_iter(self) {
                 if kid.is_store_overflow_delayed() {
@@ -1222,7 +1233,7 @@ impl<'a> MutableFlowUtils for &'a mut Flow + 'a {
                     // Give the offset for the current absolute flow alone.
                     absolute_descendant_block_offsets.push(
                         kid_block.get_hypothetical_block_start_edge());
-                } else if kid_block.is_positioned() {
+                } else if kid_block.is_absolute_containing_block() {
                     // It won't contribute any offsets because it would be the containing block
                     // for the descendants.
                     gives_absolute_offsets = false;
@@ -22,7 +22,7 @@ use text;
 use util::OpaqueNodeMethods;
 use wrapper::{TLayoutNode, ThreadSafeLayoutNode};
 
-use geom::{Point2D, Rect, Size2D};
+use geom::{Point2D, Rect, SideOffsets2D, Size2D};
 use gfx::display_list::OpaqueNode;
 use gfx::text::glyph::CharIndex;
 use gfx::text::text_run::TextRun;
@@ -33,7 +33,7 @@ use servo_net::image::holder::ImageHolder;
 use servo_net::local_image_cache::LocalImageCache;
 use servo_util::geometry::Au;
 use servo_util::geometry;
-use servo_util::logical_geometry::{LogicalRect, LogicalSize, LogicalMargin};
+use servo_util::logical_geometry::{LogicalRect, LogicalSize, LogicalMargin, WritingMode};
 use servo_util::range::*;
 use servo_util::smallvec::SmallVec;
 use servo_util::str::is_whitespace;
@@ -44,7 +44,7 @@ use string_cache::Atom;
 use style::{ComputedValues, TElement, TNode, cascade_anonymous};
 use style::computed_values::{LengthOrPercentage, LengthOrPercentageOrAuto};
 use style::computed_values::{LengthOrPercentageOrNone};
-use style::computed_values::{LPA_Auto, clear, position, text_align, text_decoration};
+use style::computed_values::{LPA_Auto, clear, display, position, text_align, text_decoration};
 use style::computed_values::{vertical_align, white_space};
 use sync::{Arc, Mutex};
 use url::Url;
@@ -1053,6 +1053,31 @@ impl Fragment {
         self.border_box - self.border_padding
     }
 
+    /// Returns the area that the outer box shadows of this fragment and of the inline elements
+    /// it belongs to paint into, including its border box, in the coordinate system of its border
+    /// box.
+    pub fn box_shadow_overflow(&self) -> LogicalRect<Au> {
+        let writing_mode = self.style.writing_mode;
+        let mut overflow = self.border_box;
+        match self.inline_context {
+            Some(ref inline_context) => {
+                for style in inline_context.styles.iter() {
+                    overflow = overflow.union(&(self.border_box +
+                                                box_shadow_extents(&**style, writing_mode)))
+                }
+            }
+            None => {}
+        }
+        match self.specific {
+            ScannedTextFragment(_) => {}
+            _ => {
+                overflow = overflow.union(&(self.border_box +
+                                            box_shadow_extents(&*self.style, writing_mode)))
+            }
+        }
+        overflow
+    }
+
     /// Find the split of a fragment that includes a new-line character.
     ///
     /// A return value of `None` indicates that the fragment is not splittable.
@@ -1498,15 +1523,66 @@ impl Fragment {
             position::absolute | position::fixed => {
                 // FIXME(pcwalton): This should only establish a new stacking context when
                 // `z-index` is not `auto`. But this matches what we did before.
-                true
+                return true
             }
             position::relative | position::static_ => {
                 // FIXME(pcwalton): `position: relative` establishes a new stacking context if
                 // `z-index` is not `auto`. But this matches what we did before.
-                false
             }
         }
+        self.is_transformed() || self.is_translucent()
+    }
+
+    /// Returns true if this fragment is drawn with a transform. Only the fragments of block flows
+    /// are transformed.
+    ///
+    /// FIXME: Transforms apply to atomic inline-level boxes too, but inline flows cannot hold
+    /// stacking contexts yet.
+    pub fn is_transformed(&self) -> bool {
+        match self.style().get_box().display {
+            display::block | display::list_item => self.style().get_box().transform.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if this fragment and its descendants are drawn as a group with an `opacity`
+    /// below 1. As with transforms, this is only done for the fragments of block flows.
+    pub fn is_translucent(&self) -> bool {
+        match self.style().get_box().display {
+            display::block | display::list_item => self.style().get_box().opacity < 1.0,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this fragment is transformed out of the plane of the page, so that it has
+    /// to be drawn by the compositor.
+    pub fn has_3d_transform(&self) -> bool {
+        if !self.is_transformed() {
+            return false
+        }
+        match self.style().get_box().transform {
+            Some(ref operations) => operations.iter().any(|operation| operation.is_3d()),
+            None => false,
+        }
+    }
+}
+
+/// Returns how far the outer box shadows of the given style paint past the border box on each
+/// side. Per CSS-BACKGROUNDS § 7.1, each shadow is the border box moved by its offset and grown by
+/// its spread radius, and its blur paints up to the blur radius past that.
+fn box_shadow_extents(style: &ComputedValues, writing_mode: WritingMode) -> LogicalMargin<Au> {
+    let mut extents = SideOffsets2D::new_all_same(Au(0));
+    for box_shadow in style.get_border().box_shadow.iter() {
+        if box_shadow.inset {
+            continue
+        }
+        let inflation = box_shadow.spread_radius + box_shadow.blur_radius;
+        extents.top = max(extents.top, inflation - box_shadow.offset_y);
+        extents.right = max(extents.right, inflation + box_shadow.offset_x);
+        extents.bottom = max(extents.bottom, inflation + box_shadow.offset_y);
+        extents.left = max(extents.left, inflation - box_shadow.offset_x);
     }
+    LogicalMargin::from_physical(writing_mode, extents)
 }
 
 impl fmt::Show for Fragment {
@@ -145,7 +145,10 @@ pub fn compute_damage(old: &Option<Arc<ComputedValues>>, new: &ComputedValues) -
                       [ REPAINT ], [
         get_color.color, get_background.background_color,
         get_border.border_top_color, get_border.border_right_color,
-        get_border.border_bottom_color, get_border.border_left_color
+        get_border.border_bottom_color, get_border.border_left_color,
+        get_border.border_top_left_radius, get_border.border_top_right_radius,
+        get_border.border_bottom_right_radius, get_border.border_bottom_left_radius,
+        get_border.box_shadow, get_box.transform_origin, get_box.opacity
     ]);
 
     add_if_not_equal!(old, new, damage,
@@ -154,6 +157,10 @@ pub fn compute_damage(old: &Option<Arc<ComputedValues>>, new: &ComputedValues) -
         get_positionoffsets.right, get_positionoffsets.bottom
     ]);
 
+    // A transform may need a layer, which is decided when block sizes are assigned.
+    add_if_not_equal!(old, new, damage,
+                      [ REPAINT, REFLOW_OUT_OF_FLOW, REFLOW ], [ get_box.transform ]);
+
     add_if_not_equal!(old, new, damage,
                       [ REPAINT, BUBBLE_ISIZES, REFLOW_OUT_OF_FLOW, REFLOW ], [
         get_border.border_top_width, get_border.border_right_width,
@@ -5,6 +5,7 @@
 //! The layout task. Performs layout on the DOM, builds display lists and sends them to be
 //! rendered.
 
+use animation::{Animation, KeyframesAnimation, TransitionAnimation};
 use css::node_style::StyledNode;
 use construct::FlowConstructionResult;
 use context::SharedLayoutContext;
@@ -21,6 +22,7 @@ use wrapper::{LayoutNode, TLayoutNode, ThreadSafeLayoutNode};
 
 use encoding::EncodingRef;
 use encoding::all::UTF_8;
+use geom::matrix::identity;
 use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
@@ -35,13 +37,15 @@ use script::dom::bindings::js::JS;
 use script::dom::node::{ElementNodeTypeId, LayoutDataRef, Node};
 use script::dom::element::{HTMLBodyElementTypeId, HTMLHtmlElementTypeId};
 use script::layout_interface::{
-    AddStylesheetMsg, ContentBoxResponse, ContentBoxesResponse, ContentBoxesQuery,
-    ContentBoxQuery, ExitNowMsg, GetRPCMsg, HitTestResponse, LayoutChan, LayoutRPC,
-    LoadStylesheetMsg, MouseOverResponse, Msg, NoQuery, PrepareToExitMsg, ReapLayoutDataMsg,
-    Reflow, ReflowForDisplay, ReflowMsg, ScriptLayoutChan, TrustedNodeAddress,
+    AddImportedStylesheetMsg, AddStylesheetMsg, ContentBoxResponse, ContentBoxesResponse,
+    ContentBoxesQuery, ContentBoxQuery, ExitNowMsg, GetRPCMsg, HitTestResponse,
+    ImportedStylesheet, LayoutChan, LayoutRPC, LoadStylesheetMsg, MouseOverResponse, Msg,
+    NoQuery, PrepareToExitMsg, ReapLayoutDataMsg, Reflow, ReflowForDisplay, ReflowMsg,
+    ScriptLayoutChan, TickAnimationsMsg, TrustedNodeAddress,
 };
 use script_traits::{SendEventMsg, ReflowEvent, ReflowCompleteMsg, OpaqueScriptLayoutChannel};
 use script_traits::{ScriptControlChan, UntrustedNodeAddress};
+use script_traits::{AnimationEndMsg, AnimationsStartedMsg, TransitionEndMsg};
 use servo_msg::compositor_msg::Scrollable;
 use servo_msg::constellation_msg::{ConstellationChan, PipelineId, Failure, FailureMsg};
 use servo_net::image_cache_task::{ImageCacheTask, ImageResponseMsg};
@@ -52,20 +56,49 @@ use servo_util::geometry::Au;
 use servo_util::logical_geometry::LogicalPoint;
 use servo_util::opts;
 use servo_util::smallvec::{SmallVec, SmallVec1, VecLike};
-use servo_util::task::spawn_named_with_send_on_failure;
+use servo_util::task::{spawn_named, spawn_named_with_send_on_failure};
 use servo_util::task_state;
 use servo_util::time::{TimeProfilerChan, profile};
 use servo_util::time;
 use servo_util::workqueue::WorkQueue;
 use std::cell::Cell;
-use std::comm::{channel, Sender, Receiver, Select};
+use std::collections::{HashMap, HashSet};
+use std::comm::{channel, Disconnected, Sender, Receiver, Select};
+use std::io::timer::Timer;
 use std::mem;
 use std::ptr;
+use std::time::duration::Duration;
 use style::{AuthorOrigin, Stylesheet, Stylist, TNode, iter_font_face_rules};
+use style::iter_stylesheet_import_rules;
 use style::{Device, Screen};
 use sync::{Arc, Mutex, MutexGuard};
+use time::precise_time_s;
 use url::Url;
 
+/// Loads and parses a stylesheet. Also returns the URL it was loaded from after redirects.
+fn load_stylesheet(resource_task: &ResourceTask, url: Url) -> (Url, Stylesheet) {
+    // TODO: Get the actual value. http://dev.w3.org/csswg/css-syntax/#environment-encoding
+    let environment_encoding = UTF_8 as EncodingRef;
+
+    let (metadata, iter) = load_bytes_iter(resource_task, url);
+    let protocol_encoding_label = metadata.charset.as_ref().map(|s| s.as_slice());
+    let final_url = metadata.final_url;
+
+    let sheet = Stylesheet::from_bytes_iter(iter,
+                                            final_url.clone(),
+                                            protocol_encoding_label,
+                                            Some(environment_encoding),
+                                            AuthorOrigin);
+    (final_url, sheet)
+}
+
+/// Returns the index and URL of each `@import` rule of a stylesheet.
+fn import_urls(sheet: &Stylesheet) -> Vec<(uint, Url)> {
+    let mut imports = vec!();
+    iter_stylesheet_import_rules(sheet, |index, rule| imports.push((index, rule.url.clone())));
+    imports
+}
+
 /// Mutable data belonging to the LayoutTask.
 ///
 /// This needs to be protected by a mutex so we can do fast RPCs.
@@ -96,6 +129,13 @@ pub struct LayoutTaskData {
 
     /// A queued response for the content boxes of a node.
     pub content_boxes_response: Vec<Rect<Au>>,
+
+    /// The transitions and animations of each node, as of the last style recalculation.
+    pub running_animations: Arc<HashMap<OpaqueNode, Vec<Animation>>>,
+
+    /// While transitions or animations are running, a channel to the task that ticks them.
+    /// Dropping it stops that task.
+    pub animation_timer: Option<Sender<()>>,
 }
 
 /// Information needed by the layout task.
@@ -136,6 +176,12 @@ pub struct LayoutTask {
     /// Is this the first reflow in this LayoutTask?
     pub first_reflow: Cell<bool>,
 
+    /// The channel on which style recalculation sends the new animations of nodes.
+    pub new_animations_sender: Sender<(OpaqueNode, Vec<Animation>)>,
+
+    /// The port on which we receive the new animations of nodes.
+    pub new_animations_receiver: Receiver<(OpaqueNode, Vec<Animation>)>,
+
     /// A mutex to allow for fast, read-only RPC of layout's internal data
     /// structures, while still letting the LayoutTask modify them.
     ///
@@ -246,8 +292,9 @@ impl LayoutTask {
            font_cache_task: FontCacheTask,
            time_profiler_chan: TimeProfilerChan)
            -> LayoutTask {
+        let PipelineId(image_owner) = id;
         let local_image_cache =
-            Arc::new(Mutex::new(LocalImageCache::new(image_cache_task.clone())));
+            Arc::new(Mutex::new(LocalImageCache::new(image_cache_task.clone(), image_owner)));
         let screen_size = Size2D(Au(0), Au(0));
         let device = Device::new(Screen, opts::get().initial_window_size.as_f32() * ScaleFactor(1.0));
         let parallel_traversal = if opts::get().layout_threads != 1 {
@@ -256,6 +303,7 @@ impl LayoutTask {
         } else {
             None
         };
+        let (new_animations_sender, new_animations_receiver) = channel();
 
         LayoutTask {
             id: id,
@@ -270,6 +318,8 @@ impl LayoutTask {
             image_cache_task: image_cache_task.clone(),
             font_cache_task: font_cache_task,
             first_reflow: Cell::new(true),
+            new_animations_sender: new_animations_sender,
+            new_animations_receiver: new_animations_receiver,
             rw_data: Arc::new(Mutex::new(
                 LayoutTaskData {
                     local_image_cache: local_image_cache,
@@ -281,6 +331,8 @@ impl LayoutTask {
                     generation: 0,
                     content_box_response: Rect::zero(),
                     content_boxes_response: Vec::new(),
+                    running_animations: Arc::new(HashMap::new()),
+                    animation_timer: None,
               })),
         }
     }
@@ -310,6 +362,9 @@ impl LayoutTask {
             reflow_root: OpaqueNodeMethods::from_layout_node(reflow_root),
             dirty: Rect::zero(),
             generation: rw_data.generation,
+            running_animations: rw_data.running_animations.clone(),
+            new_animations_sender: Mutex::new(self.new_animations_sender.clone()),
+            animation_time: precise_time_s(),
         }
     }
 
@@ -388,8 +443,14 @@ impl LayoutTask {
                                                                                  LayoutTaskData>>)
                                  -> bool {
         match request {
-            AddStylesheetMsg(sheet) => self.handle_add_stylesheet(sheet, possibly_locked_rw_data),
+            AddStylesheetMsg(sheet) => {
+                self.handle_add_stylesheet(sheet, vec!(), possibly_locked_rw_data)
+            }
             LoadStylesheetMsg(url) => self.handle_load_stylesheet(url, possibly_locked_rw_data),
+            AddImportedStylesheetMsg(imported) => {
+                self.handle_add_imported_stylesheet(imported, possibly_locked_rw_data)
+            }
+            TickAnimationsMsg => self.tick_animations(possibly_locked_rw_data),
             GetRPCMsg(response_chan) => {
                 response_chan.send(box LayoutRPCImpl(self.rw_data.clone()) as
                                    Box<LayoutRPC + Send>);
@@ -402,7 +463,7 @@ impl LayoutTask {
             },
             ReapLayoutDataMsg(dead_layout_data) => {
                 unsafe {
-                    LayoutTask::handle_reap_layout_data(dead_layout_data)
+                    self.handle_reap_layout_data(dead_layout_data, possibly_locked_rw_data)
                 }
             },
             PrepareToExitMsg(response_chan) => {
@@ -431,7 +492,7 @@ impl LayoutTask {
             match self.port.recv() {
                 ReapLayoutDataMsg(dead_layout_data) => {
                     unsafe {
-                        LayoutTask::handle_reap_layout_data(dead_layout_data)
+                        self.handle_reap_layout_data(dead_layout_data, possibly_locked_rw_data)
                     }
                 }
                 ExitNowMsg => {
@@ -439,6 +500,9 @@ impl LayoutTask {
                     self.exit_now(possibly_locked_rw_data);
                     break
                 }
+                // Imports may finish loading and animations tick at any time; they no longer
+                // matter.
+                AddImportedStylesheetMsg(_) | TickAnimationsMsg => {}
                 _ => {
                     panic!("layout: message that wasn't `ExitNowMsg` received after \
                            `PrepareToExitMsg`")
@@ -469,23 +533,15 @@ impl LayoutTask {
                                   url: Url,
                                   possibly_locked_rw_data:
                                     &mut Option<MutexGuard<'a, LayoutTaskData>>) {
-        // TODO: Get the actual value. http://dev.w3.org/csswg/css-syntax/#environment-encoding
-        let environment_encoding = UTF_8 as EncodingRef;
-
-        let (metadata, iter) = load_bytes_iter(&self.resource_task, url);
-        let protocol_encoding_label = metadata.charset.as_ref().map(|s| s.as_slice());
-        let final_url = metadata.final_url;
-
-        let sheet = Stylesheet::from_bytes_iter(iter,
-                                                final_url,
-                                                protocol_encoding_label,
-                                                Some(environment_encoding),
-                                                AuthorOrigin);
-        self.handle_add_stylesheet(sheet, possibly_locked_rw_data);
+        let (final_url, sheet) = load_stylesheet(&self.resource_task, url.clone());
+        self.handle_add_stylesheet(sheet, vec!(url, final_url), possibly_locked_rw_data);
     }
 
+    /// Adds a stylesheet of the document, and starts loading the stylesheets it imports.
+    /// `ancestors` holds the URLs that the stylesheet was loaded from, if any.
     fn handle_add_stylesheet<'a>(&'a self,
                                  sheet: Stylesheet,
+                                 ancestors: Vec<Url>,
                                  possibly_locked_rw_data:
                                     &mut Option<MutexGuard<'a, LayoutTaskData>>) {
         // Find all font-face rules and notify the font cache of them.
@@ -494,8 +550,197 @@ impl LayoutTask {
         iter_font_face_rules(&sheet, &rw_data.stylist.device, |family, src| {
             self.font_cache_task.add_web_font(family.to_string(), (*src).clone());
         });
-        rw_data.stylist.add_stylesheet(sheet);
+        let imports = import_urls(&sheet);
+        let index = rw_data.stylist.add_stylesheet(sheet);
+        LayoutTask::return_rw_data(possibly_locked_rw_data, rw_data);
+        self.load_imports(imports, vec!(index), ancestors);
+    }
+
+    /// Puts a stylesheet that has finished loading in place of its `@import` rule, starts loading
+    /// the stylesheets it imports in turn, and has script reflow to apply it.
+    fn handle_add_imported_stylesheet<'a>(&'a self,
+                                          imported: ImportedStylesheet,
+                                          possibly_locked_rw_data:
+                                            &mut Option<MutexGuard<'a, LayoutTaskData>>) {
+        let ImportedStylesheet { stylesheet, rule_path, ancestors } = imported;
+        let mut rw_data = self.lock_rw_data(possibly_locked_rw_data);
+        iter_font_face_rules(&stylesheet, &rw_data.stylist.device, |family, src| {
+            self.font_cache_task.add_web_font(family.to_string(), (*src).clone());
+        });
+        let imports = import_urls(&stylesheet);
+        let added = rw_data.stylist.add_imported_stylesheet(rule_path.as_slice(), stylesheet);
+        LayoutTask::return_rw_data(possibly_locked_rw_data, rw_data);
+        if !added {
+            return
+        }
+
+        self.load_imports(imports, rule_path, ancestors);
+        let ScriptControlChan(ref chan) = self.script_chan;
+        drop(chan.send_opt(SendEventMsg(self.id.clone(), ReflowEvent(SmallVec1::new()))));
+    }
+
+    /// Loads the stylesheets of the given `@import` rules of a stylesheet on tasks of their own.
+    /// Each one is sent back to this task in an `AddImportedStylesheetMsg` once it is loaded.
+    fn load_imports(&self, imports: Vec<(uint, Url)>, parent_path: Vec<uint>,
+                    ancestors: Vec<Url>) {
+        for (index, url) in imports.into_iter() {
+            if ancestors.contains(&url) {
+                debug!("layout: not importing {:s}, which would import itself", url.serialize());
+                continue
+            }
+            let mut rule_path = parent_path.clone();
+            rule_path.push(index);
+            let mut ancestors = ancestors.clone();
+            let resource_task = self.resource_task.clone();
+            let LayoutChan(ref chan) = self.chan;
+            let chan = chan.clone();
+            spawn_named("StylesheetImportLoader", proc() {
+                let (final_url, stylesheet) = load_stylesheet(&resource_task, url.clone());
+                ancestors.push(url);
+                ancestors.push(final_url);
+                drop(chan.send_opt(AddImportedStylesheetMsg(ImportedStylesheet {
+                    stylesheet: stylesheet,
+                    rule_path: rule_path,
+                    ancestors: ancestors,
+                })));
+            });
+        }
+    }
+
+    /// Takes in the animations that style recalculation started, changed or applied, then ends
+    /// those of the restyled nodes whose end the given time is past, firing their end events.
+    /// Finally, starts the animation timer if animations are running, and stops it otherwise.
+    fn update_animations(&self, rw_data: &mut LayoutTaskData, now: f64) {
+        let mut running_animations = (*rw_data.running_animations).clone();
+        let mut restyled_nodes = HashSet::new();
+        let mut started_nodes = vec!();
+        loop {
+            match self.new_animations_receiver.try_recv() {
+                Ok((node, animations)) => {
+                    restyled_nodes.insert(node.clone());
+                    if animations.is_empty() {
+                        running_animations.remove(&node);
+                    } else {
+                        if !running_animations.contains_key(&node) {
+                            started_nodes.push(node.to_untrusted_node_address());
+                        }
+                        running_animations.insert(node, animations);
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+
+        // Script only fires end events at nodes it was told about beforehand.
+        let ScriptControlChan(ref script_chan) = self.script_chan;
+        if !started_nodes.is_empty() {
+            drop(script_chan.send_opt(AnimationsStartedMsg(self.id, started_nodes)));
+        }
+
+        // Animations of nodes that were not restyled have not had their last frame applied yet.
+        let mut ended_nodes = vec!();
+        for (node, animations) in running_animations.iter_mut() {
+            if !restyled_nodes.contains(node) {
+                continue
+            }
+            for animation in animations.iter_mut() {
+                if animation.finished || animation.end_time() > now {
+                    continue
+                }
+                animation.finished = true;
+                let address = node.to_untrusted_node_address();
+                let msg = match animation.kind {
+                    TransitionAnimation(property, _, _) => {
+                        TransitionEndMsg(self.id, address, property.name().to_string(),
+                                         animation.elapsed_time())
+                    }
+                    KeyframesAnimation(ref name, _) => {
+                        AnimationEndMsg(self.id, address, name.clone(), animation.elapsed_time())
+                    }
+                };
+                drop(script_chan.send_opt(msg));
+            }
+            animations.retain(|animation| !animation.finished || animation.keeps_last_frame());
+            if animations.is_empty() {
+                ended_nodes.push(node.clone())
+            }
+        }
+        for node in ended_nodes.iter() {
+            running_animations.remove(node);
+        }
+
+        rw_data.running_animations = Arc::new(running_animations);
+        self.update_animation_timer(rw_data);
+    }
+
+    /// Ends the transitions and animations of the given node, which has been destroyed or removed
+    /// from the document, without firing their end events.
+    fn end_animations(&self, rw_data: &mut LayoutTaskData, node: &OpaqueNode) {
+        if !rw_data.running_animations.contains_key(node) {
+            return
+        }
+        let mut running_animations = (*rw_data.running_animations).clone();
+        running_animations.remove(node);
+        rw_data.running_animations = Arc::new(running_animations);
+        self.update_animation_timer(rw_data);
+    }
+
+    /// Starts the animation timer if transitions or animations are running, and stops it
+    /// otherwise, including when no node has any left.
+    fn update_animation_timer(&self, rw_data: &mut LayoutTaskData) {
+        let animating = rw_data.running_animations.values().any(|animations| {
+            animations.iter().any(|animation| !animation.finished)
+        });
+        if !animating {
+            rw_data.animation_timer = None
+        } else if rw_data.animation_timer.is_none() {
+            rw_data.animation_timer = Some(self.start_animation_timer())
+        }
+    }
+
+    /// Starts a task that sends a `TickAnimationsMsg` to this task every frame, until the
+    /// returned channel is dropped.
+    fn start_animation_timer(&self) -> Sender<()> {
+        let (stop_chan, stop_port) = channel();
+        let LayoutChan(ref chan) = self.chan;
+        let chan = chan.clone();
+        spawn_named("AnimationTimer", proc() {
+            let mut timer = Timer::new().unwrap();
+            let ticks = timer.periodic(Duration::milliseconds(16));
+            loop {
+                ticks.recv();
+                match stop_port.try_recv() {
+                    Err(Disconnected) => break,
+                    _ => {}
+                }
+                if chan.send_opt(TickAnimationsMsg).is_err() {
+                    break
+                }
+            }
+        });
+        stop_chan
+    }
+
+    /// Has script reflow the nodes whose transitions and animations are running, so that their
+    /// styles are recalculated at the current time.
+    fn tick_animations<'a>(&'a self,
+                           possibly_locked_rw_data: &mut Option<MutexGuard<'a, LayoutTaskData>>) {
+        let mut rw_data = self.lock_rw_data(possibly_locked_rw_data);
+        let mut nodes = SmallVec1::new();
+        for (node, animations) in rw_data.running_animations.iter() {
+            if animations.iter().any(|animation| !animation.finished) {
+                nodes.vec_push(node.to_untrusted_node_address())
+            }
+        }
+        if nodes.len() == 0 {
+            rw_data.animation_timer = None
+        }
         LayoutTask::return_rw_data(possibly_locked_rw_data, rw_data);
+
+        if nodes.len() > 0 {
+            let ScriptControlChan(ref chan) = self.script_chan;
+            drop(chan.send_opt(SendEventMsg(self.id.clone(), ReflowEvent(nodes))));
+        }
     }
 
     /// Retrieves the flow tree root from the root node.
@@ -677,8 +922,11 @@ impl LayoutTask {
                                                          Scrollable));
             let origin = Rect(Point2D(Au(0), Au(0)), root_size);
             let stacking_context = Arc::new(StackingContext::new(display_list,
+                                                                 origin,
                                                                  origin,
                                                                  0,
+                                                                 identity(),
+                                                                 1.0,
                                                                  Some(render_layer)));
 
             rw_data.stacking_context = Some(stacking_context.clone());
@@ -775,6 +1023,8 @@ impl LayoutTask {
             self.get_layout_root((*node).clone())
         });
 
+        self.update_animations(rw_data.deref_mut(), shared_layout_ctx.animation_time);
+
         profile(time::LayoutRestyleDamagePropagation,
                 Some((&data.url, data.iframe, self.first_reflow.get())),
                 self.time_profiler_chan.clone(),
@@ -890,11 +1140,24 @@ impl LayoutTask {
     }
 
     /// Handles a message to destroy layout data. Layout data must be destroyed on *this* task
-    /// because it contains local managed pointers.
-    unsafe fn handle_reap_layout_data(layout_data: LayoutDataRef) {
+    /// because it contains local managed pointers. The transitions and animations of the node that
+    /// the data belonged to end with it, as script sends the data when the node is destroyed or
+    /// removed from the document.
+    unsafe fn handle_reap_layout_data<'a>(&'a self,
+                                          layout_data: LayoutDataRef,
+                                          possibly_locked_rw_data:
+                                            &mut Option<MutexGuard<'a, LayoutTaskData>>) {
         let mut layout_data_ref = layout_data.borrow_mut();
-        let _: Option<LayoutDataWrapper> = mem::transmute(
+        let dead_layout_data: Option<LayoutDataWrapper> = mem::transmute(
             mem::replace(&mut *layout_data_ref, None));
+        match dead_layout_data {
+            None => {}
+            Some(layout_data) => {
+                let mut rw_data = self.lock_rw_data(possibly_locked_rw_data);
+                self.end_animations(rw_data.deref_mut(), &layout_data.data.node);
+                LayoutTask::return_rw_data(possibly_locked_rw_data, rw_data);
+            }
+        }
     }
 }
 
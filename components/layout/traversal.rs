@@ -12,10 +12,12 @@ use flow::{Flow, MutableFlowUtils};
 use flow::{PreorderFlowTraversal, PostorderFlowTraversal};
 use flow;
 use incremental::{RestyleDamage, BUBBLE_ISIZES, REFLOW, REFLOW_OUT_OF_FLOW};
+use util::OpaqueNodeMethods;
 use wrapper::{layout_node_to_unsafe_layout_node, LayoutNode};
 use wrapper::{PostorderNodeMutTraversal, ThreadSafeLayoutNode, UnsafeLayoutNode};
 use wrapper::{PreorderDomTraversal, PostorderDomTraversal};
 
+use gfx::display_list::OpaqueNode;
 use servo_util::bloom::BloomFilter;
 use servo_util::opts;
 use servo_util::tid::tid;
@@ -143,12 +145,20 @@ impl<'a> PreorderDomTraversal for RecalcStyleForNode<'a> {
                 node.unstyle();
             }
 
-            // Check to see whether we can share a style with someone.
+            // Check to see whether we can share a style with someone. Animated nodes cannot,
+            // as their styles change on their own.
+            let opaque_node: OpaqueNode = OpaqueNodeMethods::from_layout_node(&node);
+            let animating = self.layout_context.shared.running_animations
+                                                      .contains_key(&opaque_node);
             let style_sharing_candidate_cache =
                 self.layout_context.style_sharing_candidate_cache();
-            let sharing_result = unsafe {
-                node.share_style_if_possible(style_sharing_candidate_cache,
-                                             parent_opt.clone())
+            let sharing_result = if animating {
+                CannotShare(false)
+            } else {
+                unsafe {
+                    node.share_style_if_possible(style_sharing_candidate_cache,
+                                                 parent_opt.clone())
+                }
             };
             // Otherwise, match and cascade selectors.
             match sharing_result {
@@ -169,12 +179,13 @@ impl<'a> PreorderDomTraversal for RecalcStyleForNode<'a> {
                     // Perform the CSS cascade.
                     unsafe {
                         node.cascade_node(parent_opt,
+                                          self.layout_context.shared,
                                           &applicable_declarations,
                                           self.layout_context.applicable_declarations_cache());
                     }
 
                     // Add ourselves to the LRU cache.
-                    if shareable {
+                    if shareable && !animating {
                         style_sharing_candidate_cache.insert_if_possible(&node);
                     }
                 }
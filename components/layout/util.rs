@@ -45,11 +45,15 @@ pub struct PrivateLayoutData {
 
     /// Various flags.
     pub flags: LayoutDataFlags,
+
+    /// The node that this data belongs to, so that its transitions and animations can be ended
+    /// when the data is reaped.
+    pub node: OpaqueNode,
 }
 
 impl PrivateLayoutData {
-    /// Creates new layout data.
-    pub fn new() -> PrivateLayoutData {
+    /// Creates new layout data for the given node.
+    pub fn new(node: OpaqueNode) -> PrivateLayoutData {
         PrivateLayoutData {
             before_style: None,
             after_style: None,
@@ -59,6 +63,7 @@ impl PrivateLayoutData {
             after_flow_construction_result: NoConstructionResult,
             parallel: DomParallelInfo::new(),
             flags: LayoutDataFlags::empty(),
+            node: node,
         }
     }
 }
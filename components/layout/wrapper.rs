@@ -272,13 +272,14 @@ impl<'ln> LayoutNode<'ln> {
     ///
     /// FIXME(pcwalton): Do this as part of fragment building instead of in a traversal.
     pub fn initialize_layout_data(self, chan: LayoutChan) {
+        let node = OpaqueNodeMethods::from_layout_node(&self);
         let mut layout_data_ref = self.mutate_layout_data();
         match *layout_data_ref {
             None => {
                 *layout_data_ref = Some(LayoutDataWrapper {
                     chan: Some(chan),
                     shared_data: SharedLayoutData { style: None },
-                    data: box PrivateLayoutData::new(),
+                    data: box PrivateLayoutData::new(node),
                 });
             }
             Some(_) => {}
@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use azure::azure_hl::Color;
+use geom::matrix::Matrix4;
 use geom::point::Point2D;
 use geom::rect::Rect;
 use layers::platform::surface::NativeGraphicsMetadata;
@@ -79,6 +80,8 @@ pub struct LayerMetadata {
     pub background_color: Color,
     /// The scrolling policy of this layer.
     pub scroll_policy: ScrollPolicy,
+    /// The transform of this layer, relative to its origin.
+    pub transform: Matrix4<f32>,
 }
 
 /// The interface used by the renderer to acquire draw targets for each render frame and
@@ -99,6 +102,10 @@ pub trait RenderListener for Sized? {
              epoch: Epoch,
              replies: Vec<(LayerId, Box<LayerBufferSet>)>);
 
+    /// Informs the compositor that the given layers show a new frame of an animated image, so
+    /// their tiles have to be rendered again.
+    fn invalidate_layers(&mut self, pipeline_id: PipelineId, epoch: Epoch, layer_ids: Vec<LayerId>);
+
     fn render_msg_discarded(&mut self);
     fn set_render_state(&mut self, PipelineId, RenderState);
 }
@@ -13,6 +13,7 @@ use http::method::{Method, Get};
 use layers::geometry::DevicePixel;
 use servo_util::geometry::{PagePx, ViewportPx};
 use std::comm::{channel, Sender, Receiver};
+use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
 use url::Url;
 
 #[deriving(Clone)]
@@ -247,3 +248,16 @@ pub struct PipelineId(pub uint);
 
 #[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub struct SubpageId(pub uint);
+
+/// Identifies a top-level browsing context, which keeps the same id across navigations.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub struct TopLevelBrowsingContextId(pub uint);
+
+static mut NEXT_BROWSING_CONTEXT_ID: AtomicUint = INIT_ATOMIC_UINT;
+
+impl TopLevelBrowsingContextId {
+    /// Allocate an id that no other top-level browsing context in this process uses.
+    pub fn next() -> TopLevelBrowsingContextId {
+        TopLevelBrowsingContextId(unsafe { NEXT_BROWSING_CONTEXT_ID.fetch_add(1, SeqCst) })
+    }
+}
@@ -2,29 +2,79 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use resource_task::{TargetedLoadResponse, Metadata, Done, LoadData, start_sending, ResponseSenders};
+//! Loads about: URLs. Besides about:blank, about:crash and about:failure, the pages are generated
+//! when they are loaded by the `AboutPage`s registered with the resource task, so that a running
+//! instance can be inspected from the browser itself.
+
+use image_cache_task::{ImageCacheTask, ListEntries};
+use resource_task::{TargetedLoadResponse, Metadata, Done, LoadData, Payload, start_sending};
+use resource_task::ResponseSenders;
 use file_loader;
 
+use std::collections::HashMap;
+use std::comm::channel;
 use std::io::fs::PathExtensions;
+use std::os;
 use url::Url;
 use http::status::Ok as StatusOk;
+use servo_util::memory::{CollectReportsMsg, MemoryProfilerChan};
+use servo_util::opts::NetworkOpts;
 use servo_util::resource_files::resources_dir_path;
+use servo_util::str::escape_html;
+use servo_util::task::spawn_named;
+use servo_util::time::{CollectMsg, Formatable, TimeProfilerChan};
+
+/// An internal page whose HTML is generated each time it is loaded.
+pub trait AboutPage {
+    /// Returns the procedure that generates the HTML of the page. It runs on a task of its own,
+    /// so it may wait on other tasks without holding up the resource task.
+    fn generator(&self) -> proc():Send -> String;
+}
 
+/// The pages of about: URLs, by name.
+pub struct AboutPages {
+    pages: HashMap<String, Box<AboutPage + Send>>,
+}
+
+impl AboutPages {
+    /// Creates a registry of the pages that need nothing but the network settings.
+    pub fn new(network_opts: &NetworkOpts) -> AboutPages {
+        let mut about_pages = AboutPages {
+            pages: HashMap::new(),
+        };
+        about_pages.register("version".to_string(), box VersionPage {
+            user_agent: network_opts.user_agent.clone(),
+        });
+        about_pages
+    }
 
-pub fn factory(mut load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
+    /// Adds a page, replacing any page of the same name.
+    pub fn register(&mut self, name: String, page: Box<AboutPage + Send>) {
+        self.pages.insert(name, page);
+    }
+}
+
+fn html_metadata(url: Url) -> Metadata {
+    Metadata {
+        final_url: url,
+        content_type: Some(("text".to_string(), "html".to_string())),
+        charset: Some("utf-8".to_string()),
+        headers: None,
+        status: Some(StatusOk),
+    }
+}
+
+pub fn factory(mut load_data: LoadData, start_chan: Sender<TargetedLoadResponse>,
+               about_pages: &AboutPages) {
     let senders = ResponseSenders {
         immediate_consumer: start_chan.clone(),
         eventual_consumer: load_data.consumer.clone(),
     };
+    let generator = about_pages.pages.find_equiv(load_data.url.non_relative_scheme_data().unwrap())
+                                     .map(|page| page.generator());
     match load_data.url.non_relative_scheme_data().unwrap() {
         "blank" => {
-            let chan = start_sending(senders, Metadata {
-                final_url: load_data.url,
-                content_type: Some(("text".to_string(), "html".to_string())),
-                charset: Some("utf-8".to_string()),
-                headers: None,
-                status: Some(StatusOk),
-            });
+            let chan = start_sending(senders, html_metadata(load_data.url));
             chan.send(Done(Ok(())));
             return
         }
@@ -36,10 +86,184 @@ pub fn factory(mut load_data: LoadData, start_chan: Sender<TargetedLoadResponse>
             load_data.url = Url::from_file_path(&path).unwrap();
         }
         _ => {
-            start_sending(senders, Metadata::default(load_data.url))
-                .send(Done(Err("Unknown about: URL.".to_string())));
+            match generator {
+                Some(generator) => {
+                    let url = load_data.url;
+                    spawn_named("about: page", proc() {
+                        let html = generator();
+                        let chan = start_sending(senders, html_metadata(url));
+                        chan.send(Payload(html.into_bytes()));
+                        chan.send(Done(Ok(())));
+                    });
+                }
+                None => {
+                    start_sending(senders, Metadata::default(load_data.url))
+                        .send(Done(Err("Unknown about: URL.".to_string())));
+                }
+            }
             return
         }
     };
     file_loader::factory(load_data, start_chan)
 }
+
+/// Lays out a page with a heading and the given HTML body.
+fn html_page(name: &str, body: String) -> String {
+    format!("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>about:{}</title></head>\n\
+             <body><h1>about:{}</h1>\n{}</body></html>\n", name, name, body)
+}
+
+/// Lays out a table, escaping the text of its cells.
+fn html_table(headers: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut table = "<table>\n<tr>".to_string();
+    for header in headers.iter() {
+        table.push_str(format!("<th>{}</th>", escape_html(*header)).as_slice());
+    }
+    table.push_str("</tr>\n");
+    for row in rows.iter() {
+        table.push_str("<tr>");
+        for cell in row.iter() {
+            table.push_str(format!("<td>{}</td>", escape_html(cell.as_slice())).as_slice());
+        }
+        table.push_str("</tr>\n");
+    }
+    table.push_str("</table>\n");
+    table
+}
+
+fn format_mebibytes(nbytes: Option<u64>) -> String {
+    match nbytes {
+        Some(nbytes) => format!("{:.2f}", (nbytes as f64) / (1024f64 * 1024f64)),
+        None => "unknown".to_string(),
+    }
+}
+
+/// about:version, which shows the version of Servo and what it runs on.
+struct VersionPage {
+    user_agent: Option<String>,
+}
+
+impl AboutPage for VersionPage {
+    fn generator(&self) -> proc():Send -> String {
+        let user_agent = self.user_agent.clone();
+        proc() {
+            let user_agent = user_agent.unwrap_or("the default of the HTTP library".to_string());
+            let rows = vec!(
+                vec!("Version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+                vec!("Platform".to_string(), format!("{} {}", os::consts::SYSNAME, os::consts::ARCH)),
+                vec!("User agent".to_string(), user_agent),
+            );
+            html_page("version", html_table(&["", ""], rows))
+        }
+    }
+}
+
+/// about:memory, which shows the measurements of a report of the memory profiler.
+impl AboutPage for MemoryProfilerChan {
+    fn generator(&self) -> proc():Send -> String {
+        let memory_profiler_chan = self.clone();
+        proc() {
+            let (response_chan, response_port) = channel();
+            memory_profiler_chan.send(CollectReportsMsg(response_chan));
+            let rows = response_port.recv().into_iter().map(|(path, nbytes)| {
+                vec!(path, format_mebibytes(nbytes))
+            }).collect();
+            html_page("memory", html_table(&["Category", "Size (MiB)"], rows))
+        }
+    }
+}
+
+/// about:profiling, which shows the times recorded by the time profiler.
+impl AboutPage for TimeProfilerChan {
+    fn generator(&self) -> proc():Send -> String {
+        let time_profiler_chan = self.clone();
+        proc() {
+            let (response_chan, response_port) = channel();
+            time_profiler_chan.send(CollectMsg(response_chan));
+            // An inactive profiler drops the response channel.
+            let buckets = match response_port.recv_opt() {
+                Ok(buckets) => buckets,
+                Err(()) => {
+                    return html_page("profiling", "<p>The time profiler is not running. Start \
+                                                   Servo with <code>-p</code> to record \
+                                                   times.</p>\n".to_string())
+                }
+            };
+            let rows = buckets.into_iter().map(|bucket| {
+                let (url, incremental, iframe) = match bucket.metadata {
+                    Some(metadata) => {
+                        (metadata.url,
+                         if metadata.incremental { "yes" } else { "no" }.to_string(),
+                         if metadata.iframe { "yes" } else { "no" }.to_string())
+                    }
+                    None => ("N/A".to_string(), "N/A".to_string(), "N/A".to_string()),
+                };
+                vec!(bucket.category.format(), incremental, iframe, url,
+                     format!("{:.4f}", bucket.mean), format!("{:.4f}", bucket.median),
+                     format!("{:.4f}", bucket.min), format!("{:.4f}", bucket.max),
+                     bucket.events.to_string())
+            }).collect();
+            html_page("profiling", html_table(&["Category", "Incremental?", "Iframe?", "URL",
+                                                "Mean (ms)", "Median (ms)", "Min (ms)",
+                                                "Max (ms)", "Events"], rows))
+        }
+    }
+}
+
+/// about:cache, which lists the images in the image cache.
+impl AboutPage for ImageCacheTask {
+    fn generator(&self) -> proc():Send -> String {
+        let image_cache_task = self.clone();
+        proc() {
+            let (response_chan, response_port) = channel();
+            image_cache_task.send(ListEntries(response_chan));
+            let mut entries = response_port.recv();
+            entries.sort_by(|a, b| a.url.serialize().cmp(&b.url.serialize()));
+
+            let (mut data_size, mut decoded_size) = (0, 0);
+            let rows = entries.into_iter().map(|entry| {
+                data_size += entry.data_size;
+                decoded_size += entry.decoded_size;
+                vec!(entry.url.serialize(), entry.state.to_string(), entry.data_size.to_string(),
+                     entry.decoded_size.to_string(), entry.owners.to_string())
+            }).collect();
+            let table = html_table(&["URL", "State", "Data (bytes)", "Decoded (bytes)",
+                                     "Pipelines"], rows);
+            html_page("cache", format!("<p>{} bytes of image data, {} bytes of decoded \
+                                        images.</p>\n{}", data_size, decoded_size, table))
+        }
+    }
+}
+
+#[cfg(test)]
+struct TestPage;
+
+#[cfg(test)]
+impl AboutPage for TestPage {
+    fn generator(&self) -> proc():Send -> String {
+        proc() {
+            "<p>test</p>".to_string()
+        }
+    }
+}
+
+#[test]
+fn test_registered_page() {
+    use resource_task::{Exit, RegisterAboutPage, load_whole_resource, new_resource_task};
+    use servo_util::opts;
+
+    let resource_task = new_resource_task(opts::default_network_opts());
+    resource_task.send(RegisterAboutPage("test".to_string(), box TestPage));
+
+    let (metadata, body) = load_whole_resource(&resource_task,
+                                               Url::parse("about:test").unwrap()).unwrap();
+    assert_eq!(metadata.content_type, Some(("text".to_string(), "html".to_string())));
+    assert_eq!(body, b"<p>test</p>".to_vec());
+
+    let (_, body) = load_whole_resource(&resource_task,
+                                        Url::parse("about:version").unwrap()).unwrap();
+    assert!(String::from_utf8(body).unwrap().as_slice().contains(env!("CARGO_PKG_VERSION")));
+
+    assert!(load_whole_resource(&resource_task, Url::parse("about:nothing").unwrap()).is_err());
+    resource_task.send(Exit);
+}
@@ -0,0 +1,163 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Loads blob: URLs from the store that `URL.createObjectURL` adds blobs to.
+//!
+//! http://dev.w3.org/2006/webapi/FileAPI/#url
+
+use fetch::request::serialize_origin;
+use resource_task::{Done, Payload, Metadata, LoadData, TargetedLoadResponse, start_sending};
+use resource_task::ResponseSenders;
+
+use std::collections::HashMap;
+use http::headers::content_type::MediaType;
+use http::headers::test_utils::from_stream_with_str;
+use http::method::Get;
+use sync::Arc;
+use url::Url;
+
+/// The contents of a blob, shared with the script task that created it.
+#[deriving(Clone)]
+pub struct BlobData {
+    pub bytes: Arc<Vec<u8>>,
+    /// The type of the blob, lowercased, or the empty string if it is unknown.
+    pub content_type: String,
+}
+
+/// The blobs that blob: URLs point to, by origin and then by identifier.
+pub struct BlobStore {
+    origins: HashMap<String, HashMap<String, BlobData>>,
+}
+
+impl BlobStore {
+    pub fn new() -> BlobStore {
+        BlobStore {
+            origins: HashMap::new(),
+        }
+    }
+
+    /// Makes a blob: URL point to a blob. Does nothing if the URL is not a valid blob: URL.
+    pub fn add(&mut self, url: &Url, data: BlobData) {
+        match split_blob_url(url) {
+            Some((origin, id)) => {
+                let blobs = self.origins.find_or_insert_with(origin, |_| HashMap::new());
+                blobs.insert(id, data);
+            }
+            None => debug!("blob_loader: not adding invalid blob URL {:s}", url.serialize()),
+        }
+    }
+
+    /// Forgets the blob a blob: URL points to.
+    pub fn revoke(&mut self, url: &Url) {
+        let (origin, id) = match split_blob_url(url) {
+            Some(parts) => parts,
+            None => return,
+        };
+        let is_empty = match self.origins.find_mut(&origin) {
+            Some(blobs) => {
+                blobs.remove(&id);
+                blobs.is_empty()
+            }
+            None => false,
+        };
+        if is_empty {
+            self.origins.remove(&origin);
+        }
+    }
+
+    pub fn get(&self, url: &Url) -> Option<&BlobData> {
+        split_blob_url(url).and_then(|(origin, id)| {
+            self.origins.find(&origin).and_then(|blobs| blobs.find(&id))
+        })
+    }
+}
+
+/// Splits a blob: URL into the origin of the document that created it and its identifier.
+fn split_blob_url(url: &Url) -> Option<(String, String)> {
+    if url.scheme.as_slice() != "blob" {
+        return None;
+    }
+    url.non_relative_scheme_data().and_then(|data| {
+        data.rfind('/').map(|position| {
+            (data.slice_to(position).to_string(), data.slice_from(position + 1).to_string())
+        })
+    })
+}
+
+/// Returns the serialized origin of the document that created a blob: URL.
+pub fn blob_url_origin(url: &Url) -> Option<String> {
+    split_blob_url(url).map(|(origin, _)| origin)
+}
+
+/// Builds a new blob: URL in the origin of the given document URL.
+pub fn new_blob_url(creator: &Url, id: &str) -> Url {
+    Url::parse(format!("blob:{}/{}", serialize_origin(creator), id).as_slice()).unwrap()
+}
+
+pub fn factory(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>,
+               blob_store: &BlobStore) {
+    let senders = ResponseSenders {
+        immediate_consumer: start_chan,
+        eventual_consumer: load_data.consumer,
+    };
+    let mut metadata = Metadata::default(load_data.url.clone());
+    // Blob URLs can only be fetched, they do not support other methods.
+    let data = match blob_store.get(&load_data.url) {
+        Some(data) if load_data.method == Get => data.clone(),
+        _ => {
+            start_sending(senders, metadata).send(Done(Err("Unknown blob: URL.".to_string())));
+            return
+        }
+    };
+
+    if !data.content_type.is_empty() {
+        let content_type: Option<MediaType> = from_stream_with_str(data.content_type.as_slice());
+        metadata.set_content_type(&content_type);
+    }
+    let progress_chan = start_sending(senders, metadata);
+    progress_chan.send(Payload((*data.bytes).clone()));
+    progress_chan.send(Done(Ok(())));
+}
+
+#[test]
+fn test_blob_store() {
+    let creator = Url::parse("http://example.com:8000/page.html").unwrap();
+    let url = new_blob_url(&creator, "4bd27a64");
+    assert_eq!(url.serialize().as_slice(), "blob:http://example.com:8000/4bd27a64");
+    assert_eq!(blob_url_origin(&url), Some("http://example.com:8000".to_string()));
+
+    let mut store = BlobStore::new();
+    store.add(&url, BlobData {
+        bytes: Arc::new(b"abc".to_vec()),
+        content_type: "text/plain".to_string(),
+    });
+    assert_eq!(store.get(&url).map(|data| (*data.bytes).clone()), Some(b"abc".to_vec()));
+    assert!(store.get(&new_blob_url(&creator, "other")).is_none());
+    store.revoke(&url);
+    assert!(store.get(&url).is_none());
+    assert!(store.origins.is_empty());
+}
+
+#[test]
+fn test_load_blob_url() {
+    use resource_task::{AddBlobUrl, Exit, RevokeBlobUrl, load_whole_resource};
+    use resource_task::new_resource_task;
+    use servo_util::opts;
+
+    let resource_task = new_resource_task(opts::default_network_opts());
+    let url = new_blob_url(&Url::parse("http://example.com/").unwrap(), "test");
+    resource_task.send(AddBlobUrl(url.clone(), BlobData {
+        bytes: Arc::new(b"<p>test</p>".to_vec()),
+        content_type: "text/html;charset=utf-8".to_string(),
+    }));
+
+    let (metadata, body) = load_whole_resource(&resource_task, url.clone()).unwrap();
+    assert_eq!(metadata.content_type, Some(("text".to_string(), "html".to_string())));
+    assert_eq!(metadata.charset, Some("utf-8".to_string()));
+    assert_eq!(body, b"<p>test</p>".to_vec());
+
+    resource_task.send(RevokeBlobUrl(url.clone()));
+    assert!(load_whole_resource(&resource_task, url).is_err());
+    resource_task.send(Exit);
+}
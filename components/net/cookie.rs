@@ -0,0 +1,424 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Implementation of cookie creation and matching as specified by
+//! http://tools.ietf.org/html/rfc6265
+
+use std::ascii::AsciiExt;
+use std::i64;
+use time;
+use url::Url;
+
+/// Where a cookie operation originates from. Cookies marked `HttpOnly`
+/// can only be read or written by `HTTP` sources.
+#[deriving(Clone, PartialEq, Show)]
+pub enum CookieSource {
+    /// The cookie is being set or read by the network stack.
+    HTTP,
+    /// The cookie is being set or read by script (`document.cookie`).
+    NonHTTP,
+}
+
+/// A stored cookie, as described in
+/// http://tools.ietf.org/html/rfc6265#section-5.3
+#[deriving(Clone, Show)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub path: String,
+    /// Expiry time in seconds since the epoch. `None` for session cookies.
+    pub expiry_time: Option<i64>,
+    pub creation_time: i64,
+    pub last_access_time: i64,
+    pub host_only: bool,
+    pub secure_only: bool,
+    pub http_only: bool,
+}
+
+impl Cookie {
+    /// Parse a `Set-Cookie` header value received for `request`, following
+    /// http://tools.ietf.org/html/rfc6265#section-5.2 and the storage model
+    /// from section 5.3. Returns `None` if the cookie must be ignored.
+    pub fn new_wrapped(header: &str, request: &Url, source: CookieSource) -> Option<Cookie> {
+        // Step 1
+        let (name_value_pair, attributes) = match header.find(';') {
+            Some(index) => (header.slice_to(index), header.slice_from(index + 1)),
+            None => (header, ""),
+        };
+
+        // Steps 2-5
+        let (name, value) = match name_value_pair.find('=') {
+            Some(index) => (name_value_pair.slice_to(index).trim(),
+                            name_value_pair.slice_from(index + 1).trim()),
+            None => return None,
+        };
+        if name.is_empty() {
+            return None;
+        }
+
+        let now = time::get_time().sec;
+        let mut expires = None;
+        let mut max_age = None;
+        let mut domain = None;
+        let mut path = None;
+        let mut secure_only = false;
+        let mut http_only = false;
+
+        // http://tools.ietf.org/html/rfc6265#section-5.2 step 6
+        for attribute in attributes.split(';') {
+            let (key, value) = match attribute.find('=') {
+                Some(index) => (attribute.slice_to(index).trim(),
+                                attribute.slice_from(index + 1).trim()),
+                None => (attribute.trim(), ""),
+            };
+            match key.to_ascii_lower().as_slice() {
+                "expires" => {
+                    // http://tools.ietf.org/html/rfc6265#section-5.2.1
+                    match parse_cookie_date(value) {
+                        Some(time) => expires = Some(time),
+                        None => (),
+                    }
+                }
+                "max-age" => {
+                    // http://tools.ietf.org/html/rfc6265#section-5.2.2
+                    match parse_max_age(value) {
+                        Some(delta) if delta <= 0 => max_age = Some(i64::MIN),
+                        Some(delta) if delta > i64::MAX - now => max_age = Some(i64::MAX),
+                        Some(delta) => max_age = Some(now + delta),
+                        None => (),
+                    }
+                }
+                "domain" => {
+                    // http://tools.ietf.org/html/rfc6265#section-5.2.3
+                    if !value.is_empty() {
+                        let value = if value.starts_with(".") { value.slice_from(1) } else { value };
+                        domain = Some(value.to_ascii_lower());
+                    }
+                }
+                "path" => {
+                    // http://tools.ietf.org/html/rfc6265#section-5.2.4
+                    if value.starts_with("/") {
+                        path = Some(value.to_string());
+                    }
+                }
+                "secure" => secure_only = true,
+                "httponly" => http_only = true,
+                _ => (),
+            }
+        }
+
+        // http://tools.ietf.org/html/rfc6265#section-5.3 step 3
+        let expiry_time = max_age.or(expires);
+
+        // Steps 4-6
+        let request_host = match request.host() {
+            Some(host) => host.serialize().as_slice().to_ascii_lower(),
+            None => return None,
+        };
+        let host_only = domain.is_none();
+        let domain = match domain {
+            Some(domain) => {
+                if !Cookie::domain_match(request_host.as_slice(), domain.as_slice()) {
+                    return None;
+                }
+                domain
+            }
+            None => request_host,
+        };
+
+        // Step 7
+        let path = path.unwrap_or_else(|| {
+            let request_path = request.serialize_path().unwrap_or(String::new());
+            Cookie::default_path(request_path.as_slice()).to_string()
+        });
+
+        // Step 10
+        if http_only && source == NonHTTP {
+            return None;
+        }
+
+        Some(Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: domain,
+            path: path,
+            expiry_time: expiry_time,
+            creation_time: now,
+            last_access_time: now,
+            host_only: host_only,
+            secure_only: secure_only,
+            http_only: http_only,
+        })
+    }
+
+    /// Whether this cookie's expiry time has passed.
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expiry_time.map_or(false, |expiry| expiry <= now)
+    }
+
+    /// Whether this cookie should be included in the `Cookie` header for a
+    /// request to `url`, per http://tools.ietf.org/html/rfc6265#section-5.4
+    /// step 1.
+    pub fn appropriate_for_url(&self, url: &Url, source: CookieSource) -> bool {
+        let host = match url.host() {
+            Some(host) => host.serialize().as_slice().to_ascii_lower(),
+            None => return false,
+        };
+        if self.host_only {
+            if self.domain != host {
+                return false;
+            }
+        } else if !Cookie::domain_match(host.as_slice(), self.domain.as_slice()) {
+            return false;
+        }
+
+        let path = url.serialize_path().unwrap_or("/".to_string());
+        if !Cookie::path_match(path.as_slice(), self.path.as_slice()) {
+            return false;
+        }
+
+        if self.secure_only && url.scheme.as_slice() != "https" {
+            return false;
+        }
+        if self.http_only && source == NonHTTP {
+            return false;
+        }
+        true
+    }
+
+    /// http://tools.ietf.org/html/rfc6265#section-5.1.3
+    pub fn domain_match(string: &str, domain_string: &str) -> bool {
+        if string == domain_string {
+            return true;
+        }
+        string.ends_with(domain_string) &&
+            string.char_at(string.len() - domain_string.len() - 1) == '.' &&
+            !is_ip_address(string)
+    }
+
+    /// http://tools.ietf.org/html/rfc6265#section-5.1.4
+    pub fn path_match(request_path: &str, cookie_path: &str) -> bool {
+        request_path == cookie_path ||
+            (request_path.starts_with(cookie_path) &&
+             (cookie_path.ends_with("/") ||
+              request_path.char_at(cookie_path.len()) == '/'))
+    }
+
+    /// http://tools.ietf.org/html/rfc6265#section-5.1.4
+    pub fn default_path(request_path: &str) -> &str {
+        if !request_path.starts_with("/") {
+            return "/";
+        }
+        match request_path.rfind('/') {
+            Some(0) | None => "/",
+            Some(index) => request_path.slice_to(index),
+        }
+    }
+}
+
+fn is_ascii_digit(c: char) -> bool {
+    match c {
+        '0'...'9' => true,
+        _ => false,
+    }
+}
+
+fn is_ip_address(host: &str) -> bool {
+    host.starts_with("[") ||
+        (!host.is_empty() && host.chars().all(|c| c == '.' || is_ascii_digit(c)))
+}
+
+fn parse_max_age(value: &str) -> Option<i64> {
+    let digits = if value.starts_with("-") { value.slice_from(1) } else { value };
+    if digits.is_empty() || !digits.chars().all(is_ascii_digit) {
+        return None;
+    }
+    // Values too large to represent are treated as "far in the future".
+    match from_str::<i64>(value) {
+        Some(delta) => Some(delta),
+        None if value.starts_with("-") => Some(i64::MIN),
+        None => Some(i64::MAX),
+    }
+}
+
+/// Parse a date using the algorithm from
+/// http://tools.ietf.org/html/rfc6265#section-5.1.1, returning the number
+/// of seconds since the epoch.
+pub fn parse_cookie_date(date: &str) -> Option<i64> {
+    fn is_delimiter(c: char) -> bool {
+        match c {
+            '\x09' | '\x20'...'\x2F' | '\x3B'...'\x40' | '\x5B'...'\x60' | '\x7B'...'\x7E' => true,
+            _ => false,
+        }
+    }
+
+    // Parse up to `max` leading digits (at least `min`), ignoring the rest of the token.
+    fn leading_digits(token: &str, min: uint, max: uint) -> Option<(u32, &str)> {
+        let count = token.chars().take_while(|&c| is_ascii_digit(c)).count();
+        if count < min || count > max {
+            return None;
+        }
+        from_str::<u32>(token.slice_to(count)).map(|value| (value, token.slice_from(count)))
+    }
+
+    fn parse_time(token: &str) -> Option<(u32, u32, u32)> {
+        let parts: Vec<&str> = token.splitn(2, ':').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+        let hour = match leading_digits(parts[0], 1, 2) { Some((v, "")) => v, _ => return None };
+        let minute = match leading_digits(parts[1], 1, 2) { Some((v, "")) => v, _ => return None };
+        let second = match leading_digits(parts[2], 1, 2) { Some((v, _)) => v, _ => return None };
+        Some((hour, minute, second))
+    }
+
+    fn parse_month(token: &str) -> Option<u32> {
+        if token.len() < 3 {
+            return None;
+        }
+        let months = ["jan", "feb", "mar", "apr", "may", "jun",
+                      "jul", "aug", "sep", "oct", "nov", "dec"];
+        let prefix = token.slice_to(3).to_ascii_lower();
+        months.iter().position(|m| *m == prefix.as_slice()).map(|i| i as u32 + 1)
+    }
+
+    let mut time = None;
+    let mut day_of_month = None;
+    let mut month = None;
+    let mut year = None;
+
+    for token in date.split(is_delimiter).filter(|t| !t.is_empty()) {
+        if time.is_none() {
+            match parse_time(token) {
+                Some(t) => { time = Some(t); continue }
+                None => (),
+            }
+        }
+        if day_of_month.is_none() {
+            match leading_digits(token, 1, 2) {
+                Some((d, _)) => { day_of_month = Some(d); continue }
+                None => (),
+            }
+        }
+        if month.is_none() {
+            match parse_month(token) {
+                Some(m) => { month = Some(m); continue }
+                None => (),
+            }
+        }
+        if year.is_none() {
+            match leading_digits(token, 2, 4) {
+                Some((y, _)) => { year = Some(y); continue }
+                None => (),
+            }
+        }
+    }
+
+    let (hour, minute, second) = match time { Some(t) => t, None => return None };
+    let (day_of_month, month, year) = match (day_of_month, month, year) {
+        (Some(d), Some(m), Some(y)) => (d, m, y),
+        _ => return None,
+    };
+    let year = match year {
+        70...99 => year + 1900,
+        0...69 => year + 2000,
+        _ => year,
+    };
+    if day_of_month < 1 || day_of_month > 31 || year < 1601 ||
+       hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    Some(days_from_civil(year as i64, month as i64, day_of_month as i64) * 86400 +
+         hour as i64 * 3600 + minute as i64 * 60 + second as i64)
+}
+
+/// Number of days between 1970-01-01 and the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[test]
+fn test_domain_match() {
+    assert!(Cookie::domain_match("foo.com", "foo.com"));
+    assert!(Cookie::domain_match("bar.foo.com", "foo.com"));
+    assert!(Cookie::domain_match("baz.bar.foo.com", "foo.com"));
+
+    assert!(!Cookie::domain_match("bar.foo.com", "bar.com"));
+    assert!(!Cookie::domain_match("bar.com", "baz.bar.com"));
+    assert!(!Cookie::domain_match("foo.com", "bar.com"));
+    assert!(!Cookie::domain_match("barfoo.com", "foo.com"));
+    assert!(!Cookie::domain_match("1.2.3.4", "2.3.4"));
+}
+
+#[test]
+fn test_path_match() {
+    assert!(Cookie::path_match("/", "/"));
+    assert!(Cookie::path_match("/index.html", "/"));
+    assert!(Cookie::path_match("/w/index.html", "/"));
+    assert!(Cookie::path_match("/w/index.html", "/w/index.html"));
+    assert!(Cookie::path_match("/w/index.html", "/w/"));
+    assert!(Cookie::path_match("/w/index.html", "/w"));
+
+    assert!(!Cookie::path_match("/", "/w/"));
+    assert!(!Cookie::path_match("/a", "/w/"));
+    assert!(!Cookie::path_match("/", "/w"));
+    assert!(!Cookie::path_match("/wowzers", "/w"));
+}
+
+#[test]
+fn test_default_path() {
+    assert_eq!(Cookie::default_path("/foo/bar/baz/"), "/foo/bar/baz");
+    assert_eq!(Cookie::default_path("/foo/bar/baz"), "/foo/bar");
+    assert_eq!(Cookie::default_path("/foo/"), "/foo");
+    assert_eq!(Cookie::default_path("/foo"), "/");
+    assert_eq!(Cookie::default_path("/"), "/");
+    assert_eq!(Cookie::default_path(""), "/");
+    assert_eq!(Cookie::default_path("foo"), "/");
+}
+
+#[test]
+fn test_parse_cookie_date() {
+    assert_eq!(parse_cookie_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784111777));
+    assert_eq!(parse_cookie_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(784111777));
+    assert_eq!(parse_cookie_date("Sun Nov  6 08:49:37 1994"), Some(784111777));
+    assert_eq!(parse_cookie_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+    assert_eq!(parse_cookie_date("Sun, 06 Nov 1994"), None);
+    assert_eq!(parse_cookie_date("Sun, 32 Nov 1994 08:49:37 GMT"), None);
+}
+
+#[test]
+fn test_parse_set_cookie() {
+    let url = &Url::parse("http://example.com/foo/bar").unwrap();
+
+    assert!(Cookie::new_wrapped("", url, HTTP).is_none());
+    assert!(Cookie::new_wrapped("=bar", url, HTTP).is_none());
+    assert!(Cookie::new_wrapped("foo", url, HTTP).is_none());
+    assert!(Cookie::new_wrapped("foo=bar; Domain=other.com", url, HTTP).is_none());
+    assert!(Cookie::new_wrapped("foo=bar; HttpOnly", url, NonHTTP).is_none());
+
+    let cookie = Cookie::new_wrapped(" foo = bar ; Secure; HttpOnly", url, HTTP).unwrap();
+    assert_eq!(cookie.name.as_slice(), "foo");
+    assert_eq!(cookie.value.as_slice(), "bar");
+    assert_eq!(cookie.domain.as_slice(), "example.com");
+    assert_eq!(cookie.path.as_slice(), "/foo");
+    assert!(cookie.host_only);
+    assert!(cookie.secure_only);
+    assert!(cookie.http_only);
+    assert!(cookie.expiry_time.is_none());
+
+    let cookie = Cookie::new_wrapped("foo=bar; domain=.EXAMPLE.com; path=/; max-age=0",
+                                     url, HTTP).unwrap();
+    assert_eq!(cookie.domain.as_slice(), "example.com");
+    assert_eq!(cookie.path.as_slice(), "/");
+    assert!(!cookie.host_only);
+    assert!(cookie.is_expired(time::get_time().sec));
+}
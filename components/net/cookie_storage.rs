@@ -0,0 +1,207 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A task that stores cookies, implementing the storage model from
+//! http://tools.ietf.org/html/rfc6265#section-5.3
+
+use cookie::{Cookie, CookieSource, HTTP, NonHTTP};
+
+use std::cmp::Equal;
+use std::comm::{channel, Receiver, Sender};
+use time;
+use url::Url;
+
+use servo_util::task::spawn_named;
+
+/// Request operations on the cookie jar
+pub enum CookieStorageMsg {
+    /// Store the cookies from a `Set-Cookie` header (or `document.cookie` assignment) for a url
+    SetCookiesForUrl(Url, String, CookieSource),
+
+    /// Retrieve the value of the `Cookie` header to send for a url, if any
+    GetCookiesForUrl(Url, Sender<Option<String>>, CookieSource),
+
+    /// shut down this task
+    Exit
+}
+
+/// Handle to a cookie storage task
+pub type CookieStorageTask = Sender<CookieStorageMsg>;
+
+/// Create a CookieStorageTask
+pub fn new_cookie_storage_task() -> CookieStorageTask {
+    let (chan, port) = channel();
+    spawn_named("CookieStorage", proc() {
+        CookieStorageManager::new(port).start();
+    });
+    chan
+}
+
+struct CookieStorageManager {
+    port: Receiver<CookieStorageMsg>,
+    cookies: CookieStorage,
+}
+
+impl CookieStorageManager {
+    fn new(port: Receiver<CookieStorageMsg>) -> CookieStorageManager {
+        CookieStorageManager {
+            port: port,
+            cookies: CookieStorage::new(),
+        }
+    }
+
+    fn start(&mut self) {
+        loop {
+            match self.port.recv_opt() {
+                Ok(SetCookiesForUrl(url, header, source)) => {
+                    // A header value may hold several cookies separated by newlines when
+                    // multiple `Set-Cookie` headers were folded together, while a
+                    // `document.cookie` assignment only ever sets one.
+                    let lines: Vec<&str> = match source {
+                        HTTP => header.as_slice().split('\n').collect(),
+                        NonHTTP => vec!(header.as_slice()),
+                    };
+                    for line in lines.into_iter() {
+                        match Cookie::new_wrapped(line, &url, source.clone()) {
+                            Some(cookie) => self.cookies.push(cookie, source.clone()),
+                            None => (),
+                        }
+                    }
+                }
+                Ok(GetCookiesForUrl(url, sender, source)) => {
+                    sender.send(self.cookies.cookies_for_url(&url, source));
+                }
+                Ok(Exit) | Err(_) => break,
+            }
+        }
+    }
+}
+
+/// The cookie jar itself. Kept separate from the task so that it can be
+/// exercised directly.
+pub struct CookieStorage {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieStorage {
+    pub fn new() -> CookieStorage {
+        CookieStorage {
+            cookies: vec!(),
+        }
+    }
+
+    /// Remove and return the cookie with the same name, domain and path, if any.
+    /// Cookies set with `HttpOnly` cannot be removed by non-HTTP sources.
+    fn remove(&mut self, cookie: &Cookie, source: CookieSource) -> Result<Option<Cookie>, ()> {
+        let position = self.cookies.iter().position(|c| {
+            c.domain == cookie.domain && c.path == cookie.path && c.name == cookie.name
+        });
+        match position {
+            Some(index) => {
+                if self.cookies[index].http_only && source == NonHTTP {
+                    Err(())
+                } else {
+                    Ok(self.cookies.remove(index))
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// http://tools.ietf.org/html/rfc6265#section-5.3 steps 11-12
+    pub fn push(&mut self, mut cookie: Cookie, source: CookieSource) {
+        let old_cookie = match self.remove(&cookie, source) {
+            Ok(old_cookie) => old_cookie,
+            Err(()) => return,
+        };
+        match old_cookie {
+            Some(old_cookie) => cookie.creation_time = old_cookie.creation_time,
+            None => (),
+        }
+
+        // An already-expired cookie only serves to evict the one it replaces.
+        if cookie.is_expired(time::get_time().sec) {
+            return;
+        }
+        self.cookies.push(cookie);
+    }
+
+    /// http://tools.ietf.org/html/rfc6265#section-5.4
+    pub fn cookies_for_url(&mut self, url: &Url, source: CookieSource) -> Option<String> {
+        let now = time::get_time().sec;
+        self.cookies.retain(|c| !c.is_expired(now));
+
+        let mut matching: Vec<&mut Cookie> = self.cookies.iter_mut().filter(|c| {
+            c.appropriate_for_url(url, source.clone())
+        }).collect();
+        if matching.is_empty() {
+            return None;
+        }
+
+        // Longer paths first; among equal paths, earlier creation times first.
+        matching.sort_by(|a, b| {
+            match b.path.len().cmp(&a.path.len()) {
+                Equal => a.creation_time.cmp(&b.creation_time),
+                order => order,
+            }
+        });
+
+        let pairs: Vec<String> = matching.into_iter().map(|c| {
+            c.last_access_time = now;
+            format!("{}={}", c.name, c.value)
+        }).collect();
+        Some(pairs.connect("; "))
+    }
+}
+
+#[test]
+fn test_cookie_ordering_and_replacement() {
+    let url = Url::parse("http://example.com/a/b/c").unwrap();
+    let mut storage = CookieStorage::new();
+    fn cookie(header: &str) -> Cookie {
+        let url = Url::parse("http://example.com/a/b/c").unwrap();
+        Cookie::new_wrapped(header, &url, HTTP).unwrap()
+    }
+
+    storage.push(cookie("short=1; path=/"), HTTP);
+    storage.push(cookie("long=2; path=/a/b"), HTTP);
+    storage.push(cookie("other=3; path=/elsewhere"), HTTP);
+    assert_eq!(storage.cookies_for_url(&url, HTTP), Some("long=2; short=1".to_string()));
+
+    storage.push(cookie("short=4; path=/"), HTTP);
+    assert_eq!(storage.cookies_for_url(&url, HTTP), Some("long=2; short=4".to_string()));
+
+    storage.push(cookie("short=; path=/; max-age=0"), HTTP);
+    assert_eq!(storage.cookies_for_url(&url, HTTP), Some("long=2".to_string()));
+}
+
+#[test]
+fn test_cookie_security_flags() {
+    let url = Url::parse("http://example.com/").unwrap();
+    let secure_url = Url::parse("https://example.com/").unwrap();
+    let mut storage = CookieStorage::new();
+
+    storage.push(Cookie::new_wrapped("s=1; Secure", &secure_url, HTTP).unwrap(), HTTP);
+    storage.push(Cookie::new_wrapped("h=2; HttpOnly", &url, HTTP).unwrap(), HTTP);
+    assert_eq!(storage.cookies_for_url(&url, HTTP), Some("h=2".to_string()));
+    assert_eq!(storage.cookies_for_url(&url, NonHTTP), None);
+    assert_eq!(storage.cookies_for_url(&secure_url, NonHTTP), Some("s=1".to_string()));
+
+    // Script cannot overwrite an HttpOnly cookie.
+    storage.push(Cookie::new_wrapped("h=3", &url, NonHTTP).unwrap(), NonHTTP);
+    assert_eq!(storage.cookies_for_url(&url, HTTP), Some("h=2".to_string()));
+}
+
+#[test]
+fn test_set_cookies_for_url() {
+    let url = Url::parse("http://example.com/").unwrap();
+    let storage = new_cookie_storage_task();
+    storage.send(SetCookiesForUrl(url.clone(), "a=1\nb=2".to_string(), HTTP));
+    // Script sets a single cookie, even if its value holds a newline.
+    storage.send(SetCookiesForUrl(url.clone(), "c=3\nd=4".to_string(), NonHTTP));
+    let (sender, receiver) = channel();
+    storage.send(GetCookiesForUrl(url, sender, HTTP));
+    assert_eq!(receiver.recv(), Some("a=1; b=2; c=3\nd=4".to_string()));
+    storage.send(Exit);
+}
@@ -66,6 +66,7 @@ impl CORSCacheEntry {
 }
 
 /// Properties of Request required to cache match.
+#[deriving(Clone)]
 pub struct CacheRequestDetails {
     pub origin: Url,
     pub destination: Url,
@@ -136,7 +137,7 @@ impl CORSCache for BasicCORSCache {
     #[allow(dead_code)]
     fn clear (&mut self, request: CacheRequestDetails) {
         let BasicCORSCache(buf) = self.clone();
-        let new_buf: Vec<CORSCacheEntry> = buf.into_iter().filter(|e| e.origin == request.origin && request.destination == e.url).collect();
+        let new_buf: Vec<CORSCacheEntry> = buf.into_iter().filter(|e| !(e.origin == request.origin && request.destination == e.url)).collect();
         *self = BasicCORSCache(new_buf);
     }
 
@@ -144,7 +145,7 @@ impl CORSCache for BasicCORSCache {
     fn cleanup(&mut self) {
         let BasicCORSCache(buf) = self.clone();
         let now = time::now().to_timespec();
-        let new_buf: Vec<CORSCacheEntry> = buf.into_iter().filter(|e| now.sec > e.created.sec + e.max_age as i64).collect();
+        let new_buf: Vec<CORSCacheEntry> = buf.into_iter().filter(|e| now.sec <= e.created.sec + e.max_age as i64).collect();
         *self = BasicCORSCache(new_buf);
     }
 
@@ -314,3 +315,35 @@ impl CORSCacheTask {
         }
     }
 }
+
+#[test]
+fn test_basic_cors_cache() {
+    use http::method::{Put, Delete};
+
+    let origin = Url::parse("http://example.com").unwrap();
+    let destination = Url::parse("http://example.org/resource").unwrap();
+    let details = || CacheRequestDetails {
+        origin: origin.clone(),
+        destination: destination.clone(),
+        credentials: false
+    };
+    let mut cache = BasicCORSCache(vec!());
+    assert!(!cache.match_method_and_update(details(), Put, 60));
+    assert!(cache.match_method(details(), Put));
+    assert!(!cache.match_method(details(), Delete));
+    assert!(!cache.match_header_and_update(details(), "X-Custom", 60));
+    assert!(cache.match_header(details(), "x-custom"));
+
+    // Entries with no max-age left are dropped on the next lookup.
+    assert!(cache.match_method_and_update(details(), Put, 0));
+    {
+        let BasicCORSCache(ref mut entries) = cache;
+        for entry in entries.iter_mut() {
+            entry.created.sec -= 1;
+        }
+    }
+    assert!(!cache.match_method(details(), Put));
+
+    cache.clear(details());
+    assert!(!cache.match_header(details(), "X-Custom"));
+}
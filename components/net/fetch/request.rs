@@ -2,13 +2,30 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use url::Url;
-use http::method::{Get, Method};
+use url::{Url, UrlParser};
+use http::method::{Get, Head, Post, Options, Method};
+use http::headers::HeaderEnum;
+use http::headers::content_type::MediaType;
 use http::headers::request::HeaderCollection;
-use fetch::cors_cache::CORSCache;
-use fetch::response::Response;
+use http::headers::request::{Header, Accept, AcceptLanguage, ContentLanguage, ContentType};
+use http::headers::response::HeaderCollection as ResponseHeaderCollection;
+use fetch::cors_cache::{CORSCache, CacheRequestDetails};
+use fetch::response::{Response, Done};
+use fetch::response::Basic as BasicResponse;
+use fetch::response::CORS as CORSResponse;
+use fetch::response::Opaque as OpaqueResponse;
+use resource_task::{GetNetworkOpts, Load, LoadData, LoadResponse, Payload, ResourceTask};
+use resource_task::Done as LoadDone;
+use std::ascii::{AsciiExt, OwnedAsciiExt};
+use std::cmp;
+use std::comm::{channel, Receiver};
+use std::from_str::FromStr;
+
+/// The longest time a CORS preflight result is cached for, in seconds.
+static MAX_PREFLIGHT_AGE: uint = 86400;
 
 /// A [request context](http://fetch.spec.whatwg.org/#concept-request-context)
+#[deriving(Clone)]
 pub enum Context {
     Audio, Beacon, CSPreport, Download, Embed, Eventsource,
     Favicon, Fetch, Font, Form, Frame, Hyperlink, IFrame, Image,
@@ -18,6 +35,7 @@ pub enum Context {
 }
 
 /// A [request context frame type](http://fetch.spec.whatwg.org/#concept-request-context-frame-type)
+#[deriving(Clone)]
 pub enum ContextFrameType {
     Auxiliary,
     TopLevel,
@@ -26,6 +44,7 @@ pub enum ContextFrameType {
 }
 
 /// A [referer](http://fetch.spec.whatwg.org/#concept-request-referrer)
+#[deriving(Clone)]
 pub enum Referer {
     RefererNone,
     Client,
@@ -33,6 +52,7 @@ pub enum Referer {
 }
 
 /// A [request mode](http://fetch.spec.whatwg.org/#concept-request-mode)
+#[deriving(Clone, PartialEq)]
 pub enum RequestMode {
     SameOrigin,
     NoCORS,
@@ -41,6 +61,7 @@ pub enum RequestMode {
 }
 
 /// Request [credentials mode](http://fetch.spec.whatwg.org/#concept-request-credentials-mode)
+#[deriving(Clone, PartialEq)]
 pub enum CredentialsMode {
     Omit,
     CredentialsSameOrigin,
@@ -48,6 +69,7 @@ pub enum CredentialsMode {
 }
 
 /// [Response tainting](http://fetch.spec.whatwg.org/#concept-request-response-tainting)
+#[deriving(Clone, PartialEq)]
 pub enum ResponseTainting {
     Basic,
     CORSTainting,
@@ -79,7 +101,9 @@ pub struct Request {
     pub manual_redirect: bool,
     pub redirect_count: uint,
     pub response_tainting: ResponseTainting,
-    pub cache: Option<Box<CORSCache+'static>>
+    pub cache: Option<Box<CORSCache+'static>>,
+    /// The redirect limit of the resource task, asked for once per fetch
+    max_redirects: Option<uint>
 }
 
 impl Request {
@@ -106,44 +130,493 @@ impl Request {
             manual_redirect: false,
             redirect_count: 0,
             response_tainting: Basic,
-            cache: None
+            cache: None,
+            max_redirects: None
+        }
+    }
+
+
+    /// [Fetch](http://fetch.spec.whatwg.org#concept-fetch)
+    pub fn fetch(&mut self, resource_task: &ResourceTask, cors_flag: bool) -> Response {
+        // Step 1
+        if self.origin.is_none() {
+            self.origin = Some(self.url.clone());
+        }
+        // Step 2
+        if self.unsafe_request && self.method != Get && self.method != Head {
+            self.force_origin_header = true;
+        }
+        // Step 3
+        if !self.headers.iter().any(|h| h.header_name().as_slice().eq_ignore_ascii_case("accept")) {
+            let value = match self.context {
+                Favicon | Image | ImageSet => "image/png,image/*;q=0.8,*/*;q=0.5",
+                Style => "text/css,*/*;q=0.1",
+                Form | Frame | Hyperlink | IFrame | Location =>
+                    "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+                _ => "*/*"
+            };
+            let _ = self.headers.insert_raw("Accept".to_string(), value.as_bytes());
+        }
+        // Step 4: Accept-Language is set by the resource task (unimplemented)
+        // Step 5
+        self.main_fetch(resource_task, cors_flag, false)
+    }
+
+    /// [Main fetch](http://fetch.spec.whatwg.org#concept-main-fetch)
+    pub fn main_fetch(&mut self, resource_task: &ResourceTask, cors_flag: bool, recursive_flag: bool) -> Response {
+        // Step 1: Mixed content and CSP checks (unimplemented)
+        // Step 2: HSTS (unimplemented)
+        // Step 3
+        let origin = self.origin.clone().unwrap_or_else(|| self.url.clone());
+        // Step 4
+        let response = if (is_same_origin(&self.url, &origin) && !cors_flag) ||
+                          (self.url.scheme.as_slice() == "data" && self.same_origin_data) ||
+                          self.url.scheme.as_slice() == "about" {
+            self.response_tainting = Basic;
+            self.basic_fetch(resource_task)
+        } else if self.mode == SameOrigin {
+            Response::network_error()
+        } else if self.mode == NoCORS {
+            self.response_tainting = Opaque;
+            self.basic_fetch(resource_task)
+        } else if !is_http_scheme(&self.url) {
+            Response::network_error()
+        } else if self.mode == ForcedPreflightMode ||
+                  (self.unsafe_request && (!is_simple_method(&self.method) ||
+                                           self.headers.iter().any(|h| !is_simple_header(&h)))) {
+            self.response_tainting = CORSTainting;
+            self.http_fetch(resource_task, true, true, false)
+        } else {
+            self.response_tainting = CORSTainting;
+            self.http_fetch(resource_task, true, false, false)
+        };
+        // Step 5
+        if recursive_flag || response.is_network_error() {
+            return response;
+        }
+        // Step 6
+        match self.response_tainting {
+            Basic => response.to_filtered(BasicResponse),
+            CORSTainting => response.to_filtered(CORSResponse),
+            Opaque => response.to_filtered(OpaqueResponse)
         }
     }
 
     /// [Basic fetch](http://fetch.spec.whatwg.org#basic-fetch)
-    pub fn basic_fetch(&mut self) -> Response {
+    pub fn basic_fetch(&mut self, resource_task: &ResourceTask) -> Response {
         match self.url.scheme.as_slice() {
             "about" => match self.url.non_relative_scheme_data() {
                 Some(s) if s.as_slice() == "blank" => {
                     let mut response = Response::new();
                     let _ = response.headers.insert_raw("Content-Type".to_string(), b"text/html;charset=utf-8");
+                    response.url = Some(self.url.clone());
+                    response.body = Done(vec!());
                     response
                 },
                 _ => Response::network_error()
             },
             "http" | "https" => {
-                self.http_fetch(false, false, false)
+                self.http_fetch(resource_task, false, false, false)
             },
-            "blob" | "data" | "file" | "ftp" => {
-                // XXXManishearth handle these
-                panic!("Unimplemented scheme for Fetch")
+            "blob" | "data" | "file" => {
+                if self.method != Get {
+                    return Response::network_error();
+                }
+                let (load_data, start_port) = self.to_load_data();
+                load_whole_response(resource_task, load_data, start_port)
             },
+            // There is no FTP loader yet.
+            "ftp" => Response::network_error(),
 
             _ => Response::network_error()
         }
     }
 
-    // [HTTP fetch](http://fetch.spec.whatwg.org#http-fetch)
-    pub fn http_fetch(&mut self, _cors_flag: bool, cors_preflight_flag: bool, _authentication_fetch_flag: bool) -> Response {
-        let response = Response::new();
-        // TODO: Service worker fetch
+    /// [HTTP fetch](http://fetch.spec.whatwg.org#http-fetch)
+    pub fn http_fetch(&mut self, resource_task: &ResourceTask, cors_flag: bool, cors_preflight_flag: bool,
+                      authentication_fetch_flag: bool) -> Response {
+        // Step 1-2: Service worker fetch (unimplemented)
         // Step 3
         // Substep 1
         self.skip_service_worker = true;
         // Substep 2
-        if cors_preflight_flag {
-            // XXXManishearth stuff goes here
+        if cors_preflight_flag && self.needs_preflight() {
+            let preflight_result = self.preflight_fetch(resource_task);
+            if preflight_result.is_network_error() {
+                return preflight_result;
+            }
+        }
+        // Substep 3-4
+        let credentials_flag = self.credentials_flag();
+        let response = self.http_network_or_cache_fetch(resource_task, credentials_flag,
+                                                         authentication_fetch_flag);
+        // Substep 5
+        if cors_flag && !self.cors_check(&response) {
+            return Response::network_error();
+        }
+        // Step 4
+        let code = response.status.code();
+        match code {
+            301 | 302 | 303 | 307 | 308 => {
+                // Substep 1
+                if self.manual_redirect {
+                    return response;
+                }
+                // Substep 2
+                let location = match find_header(&response.headers, "location") {
+                    Some(location) => location,
+                    None => return response
+                };
+                // Substep 3
+                let location = match UrlParser::new().base_url(&self.url).parse(location.as_slice()) {
+                    Ok(url) => url,
+                    Err(_) => return Response::network_error()
+                };
+                // Substep 4
+                if !is_http_scheme(&location) {
+                    return Response::network_error();
+                }
+                // Substep 5-6
+                if self.redirect_count == self.max_redirects(resource_task) {
+                    return Response::network_error();
+                }
+                self.redirect_count += 1;
+                // Substep 7
+                self.same_origin_data = true;
+                // Substep 8
+                if cors_flag && (location.username().map_or(false, |u| !u.is_empty()) ||
+                                 location.password().is_some()) {
+                    return Response::network_error();
+                }
+                // Substep 9: Giving the request a unique origin after a cross-origin
+                // redirect needs an origin type that isn't a Url (unimplemented)
+                // Substep 10
+                if ((code == 301 || code == 302) && self.method == Post) || code == 303 {
+                    self.method = Get;
+                    self.body = None;
+                    self.headers.content_type = None;
+                    self.headers.content_length = None;
+                    self.headers.content_encoding = None;
+                    self.headers.content_language = None;
+                    self.headers.content_location = None;
+                }
+                // Substep 11
+                self.url = location;
+                // Substep 12
+                return self.main_fetch(resource_task, cors_flag, true);
+            },
+            // Prompting for credentials is unimplemented, so 401 and 407
+            // responses are returned as they are.
+            _ => {}
+        }
+        // Step 5: Authentication entries (unimplemented)
+        response
+    }
+
+    /// [HTTP network or cache fetch](http://fetch.spec.whatwg.org#http-network-or-cache-fetch)
+    ///
+    /// The resource task takes care of cookies, the HTTP cache and content codings.
+    pub fn http_network_or_cache_fetch(&mut self, resource_task: &ResourceTask, credentials_flag: bool,
+                                       _authentication_fetch_flag: bool) -> Response {
+        // Step 1
+        let (mut load_data, start_port) = self.to_load_data();
+        // Step 2
+        match self.referer {
+            RefererUrl(ref url) => load_data.headers.referer = Some(url.serialize()),
+            // The client's URL is filled in by whoever creates the request
+            RefererNone | Client => {}
         }
+        // Step 3
+        if self.force_origin_header || self.response_tainting == CORSTainting {
+            match self.origin {
+                Some(ref origin) => {
+                    let serialized = serialize_origin(origin);
+                    let _ = load_data.headers.insert_raw("Origin".to_string(), serialized.as_bytes());
+                }
+                None => {}
+            }
+        }
+        // Step 4: User-Agent is set by the resource task
+        // Step 5-6: Cache mode headers are left to the resource task's HTTP cache
+        // Step 7
+        load_data.credentials_flag = credentials_flag;
+        // Step 8-10: Authorization and proxy authorization headers (unimplemented)
+        // Step 11
+        load_whole_response(resource_task, load_data, start_port)
+    }
+
+    /// [CORS preflight fetch](http://fetch.spec.whatwg.org#cors-preflight-fetch)
+    fn preflight_fetch(&mut self, resource_task: &ResourceTask) -> Response {
+        // Step 1
+        let mut preflight = Request::new(self.url.clone(), self.context.clone());
+        preflight.method = Options;
+        preflight.origin = self.origin.clone();
+        preflight.referer = self.referer.clone();
+        preflight.response_tainting = CORSTainting;
+        // Step 2
+        let method = self.method.http_value();
+        let _ = preflight.headers.insert_raw("Access-Control-Request-Method".to_string(), method.as_bytes());
+        // Step 3-5
+        let mut header_names: Vec<String> = self.headers.iter()
+                                                .filter(|h| !is_simple_header(h))
+                                                .map(|h| h.header_name().into_ascii_lower())
+                                                .collect();
+        header_names.sort();
+        header_names.dedup();
+        if !header_names.is_empty() {
+            let header_list = header_names.connect(",");
+            let _ = preflight.headers.insert_raw("Access-Control-Request-Headers".to_string(),
+                                                 header_list.as_bytes());
+        }
+        // Step 6
+        let response = preflight.http_network_or_cache_fetch(resource_task, false, false);
+        // Step 7
+        match response.status.code() {
+            200 ... 299 if self.cors_check(&response) => {}
+            _ => return Response::network_error()
+        }
+        // Substep 1-3
+        let mut methods = header_list_values(&response.headers, "access-control-allow-methods");
+        let allowed_headers = header_list_values(&response.headers, "access-control-allow-headers");
+        // Substep 4
+        if methods.is_empty() && self.mode == ForcedPreflightMode {
+            methods = vec!(method.clone());
+        }
+        // Substep 5
+        if !is_simple_method(&self.method) &&
+           !methods.iter().any(|m| m.as_slice().eq_ignore_ascii_case(method.as_slice())) {
+            return Response::network_error();
+        }
+        // Substep 6
+        for h in self.headers.iter() {
+            if is_simple_header(&h) {
+                continue;
+            }
+            let name = h.header_name();
+            if !allowed_headers.iter().any(|a| a.as_slice().eq_ignore_ascii_case(name.as_slice())) {
+                return Response::network_error();
+            }
+        }
+        // Substep 7-9
+        let max_age: uint = find_header(&response.headers, "access-control-max-age")
+                                .and_then(|h| FromStr::from_str(h.as_slice().trim())).unwrap_or(0);
+        let max_age = cmp::min(max_age, MAX_PREFLIGHT_AGE);
+        // Substep 10-12
+        let details = self.cache_request_details();
+        match self.cache {
+            Some(ref mut cache) => {
+                for m in methods.iter() {
+                    let maybe_method: Option<Method> = FromStr::from_str(m.as_slice());
+                    match maybe_method {
+                        Some(m) => { cache.match_method_and_update(details.clone(), m, max_age); }
+                        None => {}
+                    }
+                }
+                for h in allowed_headers.iter() {
+                    cache.match_header_and_update(details.clone(), h.as_slice(), max_age);
+                }
+            }
+            None => {}
+        }
+        // Step 8
         response
     }
+
+    /// The most redirects that the resource task allows a fetch to follow.
+    fn max_redirects(&mut self, resource_task: &ResourceTask) -> uint {
+        match self.max_redirects {
+            Some(max_redirects) => max_redirects,
+            None => {
+                let (sender, receiver) = channel();
+                resource_task.send(GetNetworkOpts(sender));
+                let max_redirects = receiver.recv().max_redirects;
+                self.max_redirects = Some(max_redirects);
+                max_redirects
+            }
+        }
+    }
+
+    /// Whether the CORS preflight cache lacks an entry for this request's
+    /// method or one of its headers.
+    fn needs_preflight(&mut self) -> bool {
+        let method_needs_preflight = !is_simple_method(&self.method) || self.mode == ForcedPreflightMode;
+        let header_names: Vec<String> = self.headers.iter().filter(|h| !is_simple_header(h))
+                                                    .map(|h| h.header_name()).collect();
+        let method = self.method.clone();
+        let details = self.cache_request_details();
+        match self.cache {
+            Some(ref mut cache) => {
+                (method_needs_preflight && !cache.match_method(details.clone(), method)) ||
+                header_names.iter().any(|name| !cache.match_header(details.clone(), name.as_slice()))
+            }
+            None => method_needs_preflight || !header_names.is_empty()
+        }
+    }
+
+    fn cache_request_details(&self) -> CacheRequestDetails {
+        CacheRequestDetails {
+            origin: self.origin.clone().unwrap_or_else(|| self.url.clone()),
+            destination: self.url.clone(),
+            credentials: self.credentials_mode == Include
+        }
+    }
+
+    /// [CORS check](http://fetch.spec.whatwg.org#concept-cors-check)
+    fn cors_check(&self, response: &Response) -> bool {
+        // Step 1-2
+        let allow_origin = match find_header(&response.headers, "access-control-allow-origin") {
+            Some(value) => value,
+            None => return false
+        };
+        let allow_origin = allow_origin.as_slice().trim();
+        // Step 3
+        if allow_origin == "*" && self.credentials_mode != Include {
+            return true;
+        }
+        // Step 4-5
+        match self.origin {
+            Some(ref origin) if serialize_origin(origin).as_slice() == allow_origin => {}
+            _ => return false
+        }
+        // Step 6
+        if self.credentials_mode != Include {
+            return true;
+        }
+        // Step 7-8
+        match find_header(&response.headers, "access-control-allow-credentials") {
+            Some(ref value) => value.as_slice().trim() == "true",
+            None => false
+        }
+    }
+
+    /// Whether cookies may be sent with this request, and stored from its response
+    fn credentials_flag(&self) -> bool {
+        match self.credentials_mode {
+            Include => true,
+            CredentialsSameOrigin => self.response_tainting == Basic,
+            Omit => false
+        }
+    }
+
+    /// Build the `LoadData` that hands this request to the resource task. Redirects
+    /// are handled by the Fetch algorithm itself.
+    fn to_load_data(&self) -> (LoadData, Receiver<LoadResponse>) {
+        let (start_chan, start_port) = channel();
+        let mut load_data = LoadData::new(self.url.clone(), start_chan);
+        load_data.method = self.method.clone();
+        load_data.headers = self.headers.clone();
+        load_data.data = self.body.clone();
+        load_data.preserve_content_codings = self.preserve_content_codings;
+        load_data.credentials_flag = self.credentials_flag();
+        load_data.manual_redirect = true;
+        (load_data, start_port)
+    }
+}
+
+/// Run a load through the resource task and collect the whole response.
+fn load_whole_response(resource_task: &ResourceTask, load_data: LoadData,
+                       start_port: Receiver<LoadResponse>) -> Response {
+    resource_task.send(Load(load_data));
+    let LoadResponse { metadata, progress_port } = match start_port.recv_opt() {
+        Ok(load_response) => load_response,
+        Err(_) => return Response::network_error()
+    };
+
+    let mut body = vec!();
+    loop {
+        match progress_port.recv_opt() {
+            Ok(Payload(data)) => body.push_all(data.as_slice()),
+            Ok(LoadDone(Ok(()))) => break,
+            Ok(LoadDone(Err(_))) | Err(_) => return Response::network_error()
+        }
+    }
+
+    let mut response = Response::new();
+    match metadata.status {
+        Some(status) => response.status = status,
+        None => return Response::network_error()
+    }
+    match metadata.headers {
+        Some(headers) => response.headers = headers,
+        // Loaders for schemes other than HTTP only report a content type.
+        None => match metadata.content_type {
+            Some((type_, subtype)) => {
+                let value = match metadata.charset {
+                    Some(charset) => format!("{}/{};charset={}", type_, subtype, charset),
+                    None => format!("{}/{}", type_, subtype)
+                };
+                let _ = response.headers.insert_raw("Content-Type".to_string(), value.as_bytes());
+            }
+            None => {}
+        }
+    }
+    response.url = Some(metadata.final_url);
+    response.body = Done(body);
+    response
+}
+
+fn find_header(headers: &ResponseHeaderCollection, name: &str) -> Option<String> {
+    headers.iter().find(|h| h.header_name().as_slice().eq_ignore_ascii_case(name))
+                  .map(|h| h.header_value())
+}
+
+/// The values of a comma-separated header, with empty entries removed
+/// (http://fetch.spec.whatwg.org/#http-new-header-syntax)
+fn header_list_values(headers: &ResponseHeaderCollection, name: &str) -> Vec<String> {
+    match find_header(headers, name) {
+        Some(value) => value.as_slice().split(',').map(|s| s.trim())
+                            .filter(|s| s.len() > 0).map(|s| s.to_string()).collect(),
+        None => vec!()
+    }
+}
+
+fn is_http_scheme(url: &Url) -> bool {
+    match url.scheme.as_slice() {
+        "http" | "https" => true,
+        _ => false
+    }
+}
+
+fn is_same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme == b.scheme && a.host() == b.host() && a.port_or_default() == b.port_or_default()
+}
+
+/// [ASCII serialization of an origin](https://tools.ietf.org/html/rfc6454#section-6.2)
+pub fn serialize_origin(url: &Url) -> String {
+    let mut origin = url.scheme.clone();
+    origin.push_str("://");
+    url.serialize_host().map(|ref h| origin.push_str(h.as_slice()));
+    url.port().map(|port| origin.push_str(format!(":{:u}", port).as_slice()));
+    origin
+}
+
+/// [Simple header](http://fetch.spec.whatwg.org/#simple-header)
+fn is_simple_header(h: &Header) -> bool {
+    match *h {
+        Accept(_) | AcceptLanguage(_) | ContentLanguage(_) => true,
+        ContentType(MediaType {type_: ref t, subtype: ref s, ..}) => match (t.as_slice(), s.as_slice()) {
+            ("text", "plain") | ("application", "x-www-form-urlencoded") | ("multipart", "form-data") => true,
+            _ => false
+        },
+        _ => false
+    }
+}
+
+/// [Simple method](http://fetch.spec.whatwg.org/#simple-method)
+fn is_simple_method(m: &Method) -> bool {
+    match *m {
+        Get | Head | Post => true,
+        _ => false
+    }
+}
+
+#[test]
+fn test_serialize_origin() {
+    let url = Url::parse("http://example.com:8000/a/b?c#d").unwrap();
+    assert_eq!(serialize_origin(&url).as_slice(), "http://example.com:8000");
+    let url = Url::parse("https://example.com/").unwrap();
+    assert_eq!(serialize_origin(&url).as_slice(), "https://example.com");
+    assert!(is_same_origin(&url, &Url::parse("https://example.com/other").unwrap()));
+    assert!(!is_same_origin(&url, &Url::parse("http://example.com/").unwrap()));
+    assert!(is_same_origin(&url, &Url::parse("https://example.com:443/").unwrap()));
 }
@@ -7,7 +7,7 @@ use http::status::{Status, UnregisteredStatus};
 use http::status::Ok as StatusOk;
 use http::headers::HeaderEnum;
 use http::headers::response::HeaderCollection;
-use std::ascii::OwnedAsciiExt;
+use std::ascii::{AsciiExt, OwnedAsciiExt};
 use std::comm::Receiver;
 
 /// [Response type](http://fetch.spec.whatwg.org/#concept-response-type)
@@ -121,13 +121,22 @@ impl Response {
                 response.response_type = filter_type;
             },
             CORS => {
+                let exposed: Vec<String> = old_headers.iter().filter(|h| {
+                    h.header_name().as_slice().eq_ignore_ascii_case("access-control-expose-headers")
+                }).flat_map(|h| {
+                    let names: Vec<String> = h.header_value().as_slice().split(',')
+                                              .map(|name| name.trim().to_ascii_lower())
+                                              .filter(|name| name.len() > 0).collect();
+                    names.into_iter()
+                }).collect();
                 let mut headers = HeaderCollection::new();
                 for h in old_headers.iter() {
-                    match h.header_name().into_ascii_lower().as_slice() {
+                    let name = h.header_name().into_ascii_lower();
+                    match name.as_slice() {
                         "cache-control" | "content-language" |
-                        "content-type" | "expires" | "last-modified" | "Pragma" => {},
-                        // XXXManishearth handle Access-Control-Expose-Headers
-                        _ => headers.insert(h)
+                        "content-type" | "expires" | "last-modified" | "pragma" => headers.insert(h),
+                        _ if exposed.contains(&name) => headers.insert(h),
+                        _ => {}
                     }
                 }
                 response.headers = headers;
@@ -137,8 +146,29 @@ impl Response {
                 response.headers = HeaderCollection::new();
                 response.status = UnregisteredStatus(0, "".to_string());
                 response.body = Empty;
+                response.response_type = filter_type;
             }
         }
         response
     }
 }
+
+#[test]
+fn test_cors_filtered_headers() {
+    let mut response = Response::new();
+    let _ = response.headers.insert_raw("Content-Type".to_string(), b"text/plain");
+    let _ = response.headers.insert_raw("X-Secret".to_string(), b"1");
+    let _ = response.headers.insert_raw("X-Exposed".to_string(), b"2");
+    let _ = response.headers.insert_raw("Access-Control-Expose-Headers".to_string(), b"x-exposed");
+    let filtered = response.to_filtered(CORS);
+    let names: Vec<String> = filtered.headers.iter().map(|h| h.header_name().into_ascii_lower()).collect();
+    assert!(names.contains(&"content-type".to_string()));
+    assert!(names.contains(&"x-exposed".to_string()));
+    assert!(!names.contains(&"x-secret".to_string()));
+    assert!(filtered.response_type == CORS);
+    assert!(filtered.internal_response.is_some());
+
+    let opaque = Response::new().to_filtered(Opaque);
+    assert!(opaque.response_type == Opaque);
+    assert_eq!(opaque.status.code(), 0);
+}
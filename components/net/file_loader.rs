@@ -4,13 +4,58 @@
 
 use resource_task::{ProgressMsg, Metadata, Payload, Done, LoadData, start_sending, TargetedLoadResponse, ResponseSenders};
 
+use std::ascii::AsciiExt;
+use std::cmp::min;
 use std::io;
-use std::io::File;
+use std::io::{File, IoResult, Seek};
+use std::io::fs;
+use std::io::fs::PathExtensions;
+use std::io::util::LimitReader;
+use http::headers::HeaderEnum;
+use http::headers::request::HeaderCollection as RequestHeaderCollection;
+use http::headers::response::HeaderCollection as ResponseHeaderCollection;
+use http::status::{PartialContent, RequestedRangeNotSatisfiable};
+use servo_util::str::escape_html;
 use servo_util::task::spawn_named;
+use time;
+use time::Timespec;
+use url::Url;
 
 static READ_SIZE: uint = 8192;
 
-fn read_all(reader: &mut io::Stream, progress_chan: &Sender<ProgressMsg>)
+/// The content types of file extensions, which files have no other way to declare.
+static CONTENT_TYPES: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("bmp", "image", "bmp"),
+    ("css", "text", "css"),
+    ("gif", "image", "gif"),
+    ("htm", "text", "html"),
+    ("html", "text", "html"),
+    ("ico", "image", "x-icon"),
+    ("jpeg", "image", "jpeg"),
+    ("jpg", "image", "jpeg"),
+    ("js", "application", "javascript"),
+    ("json", "application", "json"),
+    ("mp3", "audio", "mpeg"),
+    ("mp4", "video", "mp4"),
+    ("oga", "audio", "ogg"),
+    ("ogg", "audio", "ogg"),
+    ("ogv", "video", "ogg"),
+    ("otf", "application", "font-sfnt"),
+    ("pdf", "application", "pdf"),
+    ("png", "image", "png"),
+    ("svg", "image", "svg+xml"),
+    ("ttf", "application", "font-sfnt"),
+    ("txt", "text", "plain"),
+    ("wav", "audio", "wav"),
+    ("webm", "video", "webm"),
+    ("webp", "image", "webp"),
+    ("woff", "application", "font-woff"),
+    ("xht", "application", "xhtml+xml"),
+    ("xhtml", "application", "xhtml+xml"),
+    ("xml", "text", "xml"),
+];
+
+fn read_all(reader: &mut io::Reader, progress_chan: &Sender<ProgressMsg>)
         -> Result<(), String> {
     loop {
         let mut buf = vec!();
@@ -29,31 +74,258 @@ fn read_all(reader: &mut io::Stream, progress_chan: &Sender<ProgressMsg>)
     }
 }
 
+/// Returns the content type of a file from its extension, leaving unknown types to the sniffer.
+fn content_type(path: &Path) -> Option<(String, String)> {
+    let extension = match path.extension_str() {
+        Some(extension) => extension.to_ascii_lower(),
+        None => return None,
+    };
+    CONTENT_TYPES.iter().find(|&&(known, _, _)| known == extension.as_slice())
+                        .map(|&(_, type_, subtype)| (type_.to_string(), subtype.to_string()))
+}
+
+/// Parses the value of a Range header into the first and last byte it asks for, or `Err` if the
+/// range starts after the end of the file. Anything but a single byte range gives `None`, so the
+/// whole file is sent, as HTTP allows.
+fn parse_range(range: &str, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let range = range.trim();
+    if !range.starts_with("bytes=") || range.contains_char(',') {
+        return None;
+    }
+    let range = range.slice_from("bytes=".len());
+    let dash = match range.find('-') {
+        Some(dash) => dash,
+        None => return None,
+    };
+    let (first, last) = (range.slice_to(dash).trim(), range.slice_from(dash + 1).trim());
+
+    // A range without a first byte counts from the end of the file.
+    if first.is_empty() {
+        return match from_str::<u64>(last) {
+            Some(0) => Some(Err(())),
+            Some(_) if size == 0 => Some(Err(())),
+            Some(length) => Some(Ok((size - min(length, size), size - 1))),
+            None => None,
+        };
+    }
+    let first = match from_str::<u64>(first) {
+        Some(first) => first,
+        None => return None,
+    };
+    let last = if last.is_empty() {
+        None
+    } else {
+        match from_str::<u64>(last) {
+            Some(last) if last >= first => Some(last),
+            _ => return None,
+        }
+    };
+    if first >= size {
+        return Some(Err(()));
+    }
+    Some(Ok((first, last.map_or(size - 1, |last| min(last, size - 1)))))
+}
+
+fn range_header(headers: &RequestHeaderCollection) -> Option<String> {
+    headers.iter().find(|header| header.header_name().as_slice().eq_ignore_ascii_case("range"))
+                  .map(|header| header.header_value())
+}
+
+/// Generates an HTML index of a directory, whose entries are sorted by name and link to their
+/// file URLs.
+fn directory_listing(path: &Path) -> IoResult<String> {
+    let mut entries = try!(fs::readdir(path));
+    entries.sort_by(|a, b| a.filename().cmp(&b.filename()));
+
+    let title = escape_html(path.display().to_string().as_slice());
+    let mut html = format!("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+                            <title>Index of {}</title></head>\n<body><h1>Index of {}</h1>\n\
+                            <table>\n<tr><th>Name</th><th>Size</th><th>Last modified</th></tr>\n",
+                           title, title);
+    let parent = path.dir_path();
+    if parent != *path {
+        html.push_str(format!("<tr><td><a href=\"{}\">..</a></td><td></td><td></td></tr>\n",
+                              escape_html(directory_url(&parent).as_slice())).as_slice());
+    }
+    for entry in entries.iter() {
+        // Entries such as broken symbolic links cannot be described.
+        let stat = match entry.stat() {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+        let is_directory = stat.kind == io::TypeDirectory;
+        let (name, href, size) = if is_directory {
+            (format!("{}/", entry.filename_display()), directory_url(entry), "".to_string())
+        } else {
+            (entry.filename_display().to_string(),
+             Url::from_file_path(entry).unwrap().serialize(),
+             stat.size.to_string())
+        };
+        let modified = time::at_utc(Timespec::new((stat.modified / 1000) as i64, 0));
+        html.push_str(format!("<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td></tr>\n",
+                              escape_html(href.as_slice()), escape_html(name.as_slice()), size,
+                              modified.strftime("%Y-%m-%d %H:%M").unwrap()).as_slice());
+    }
+    html.push_str("</table>\n</body></html>\n");
+    Ok(html)
+}
+
+/// The URL of a directory ends with a slash, so that relative URLs resolve inside it.
+fn directory_url(path: &Path) -> String {
+    let mut url = Url::from_file_path(path).unwrap().serialize();
+    if !url.as_slice().ends_with("/") {
+        url.push('/');
+    }
+    url
+}
+
+fn load_directory(url: Url, path: &Path, senders: ResponseSenders) {
+    let mut metadata = Metadata::default(url);
+    metadata.content_type = Some(("text".to_string(), "html".to_string()));
+    metadata.charset = Some("utf-8".to_string());
+    let progress_chan = start_sending(senders, metadata);
+    match directory_listing(path) {
+        Ok(html) => {
+            progress_chan.send(Payload(html.into_bytes()));
+            progress_chan.send(Done(Ok(())));
+        }
+        Err(e) => progress_chan.send(Done(Err(e.desc.to_string()))),
+    }
+}
+
+fn load_file(url: Url, path: &Path, range: Option<String>, senders: ResponseSenders) {
+    let mut metadata = Metadata::default(url);
+    metadata.content_type = content_type(path);
+    let mut file = match File::open_mode(path, io::Open, io::Read) {
+        Ok(file) => file,
+        Err(e) => {
+            start_sending(senders, metadata).send(Done(Err(e.desc.to_string())));
+            return;
+        }
+    };
+    let size = match file.stat() {
+        Ok(stat) => stat.size,
+        Err(e) => {
+            start_sending(senders, metadata).send(Done(Err(e.desc.to_string())));
+            return;
+        }
+    };
+
+    let range = range.and_then(|range| parse_range(range.as_slice(), size));
+    match range {
+        Some(Ok((first, last))) => {
+            let mut headers = ResponseHeaderCollection::new();
+            let content_range = format!("bytes {}-{}/{}", first, last, size);
+            let _ = headers.insert_raw("Content-Range".to_string(), content_range.as_bytes());
+            let length = (last - first + 1).to_string();
+            let _ = headers.insert_raw("Content-Length".to_string(), length.as_bytes());
+            metadata.headers = Some(headers);
+            metadata.status = Some(PartialContent);
+            let progress_chan = start_sending(senders, metadata);
+            let result = match file.seek(first as i64, io::SeekSet) {
+                Ok(()) => {
+                    let mut reader = LimitReader::new(file, (last - first + 1) as uint);
+                    read_all(&mut reader as &mut io::Reader, &progress_chan)
+                }
+                Err(e) => Err(e.desc.to_string()),
+            };
+            progress_chan.send(Done(result));
+        }
+        Some(Err(())) => {
+            let mut headers = ResponseHeaderCollection::new();
+            let content_range = format!("bytes */{}", size);
+            let _ = headers.insert_raw("Content-Range".to_string(), content_range.as_bytes());
+            metadata.headers = Some(headers);
+            metadata.status = Some(RequestedRangeNotSatisfiable);
+            start_sending(senders, metadata).send(Done(Ok(())));
+        }
+        None => {
+            let progress_chan = start_sending(senders, metadata);
+            let result = read_all(&mut file as &mut io::Reader, &progress_chan);
+            progress_chan.send(Done(result));
+        }
+    }
+}
+
 pub fn factory(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
     let url = load_data.url;
     assert!("file" == url.scheme.as_slice());
+    let range = range_header(&load_data.headers);
     let senders = ResponseSenders {
         immediate_consumer: start_chan,
         eventual_consumer: load_data.consumer,
     };
-    let progress_chan = start_sending(senders, Metadata::default(url.clone()));
     spawn_named("file_loader", proc() {
         let file_path: Result<Path, ()> = url.to_file_path();
         match file_path {
             Ok(file_path) => {
-                match File::open_mode(&Path::new(file_path), io::Open, io::Read) {
-                    Ok(ref mut reader) => {
-                        let res = read_all(reader as &mut io::Stream, &progress_chan);
-                        progress_chan.send(Done(res));
-                    }
-                    Err(e) => {
-                        progress_chan.send(Done(Err(e.desc.to_string())));
-                    }
+                if file_path.is_dir() {
+                    load_directory(url, &file_path, senders);
+                } else {
+                    load_file(url, &file_path, range, senders);
                 }
             }
             Err(_) => {
-                progress_chan.send(Done(Err(url.to_string())));
+                start_sending(senders, Metadata::default(url.clone()))
+                    .send(Done(Err(url.to_string())));
             }
         }
     });
 }
+
+#[test]
+fn test_parse_range() {
+    assert_eq!(parse_range("bytes=0-99", 1000), Some(Ok((0, 99))));
+    assert_eq!(parse_range("bytes=500-", 1000), Some(Ok((500, 999))));
+    assert_eq!(parse_range("bytes=900-2000", 1000), Some(Ok((900, 999))));
+    assert_eq!(parse_range("bytes=-100", 1000), Some(Ok((900, 999))));
+    assert_eq!(parse_range("bytes=-2000", 1000), Some(Ok((0, 999))));
+    assert_eq!(parse_range("bytes=1000-", 1000), Some(Err(())));
+    assert_eq!(parse_range("bytes=-0", 1000), Some(Err(())));
+    assert_eq!(parse_range("bytes=0-0", 0), Some(Err(())));
+    assert_eq!(parse_range("bytes=5-1", 1000), None);
+    assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+    assert_eq!(parse_range("items=0-1", 1000), None);
+}
+
+#[test]
+fn test_content_type() {
+    assert_eq!(content_type(&Path::new("/tmp/index.HTML")),
+               Some(("text".to_string(), "html".to_string())));
+    assert_eq!(content_type(&Path::new("/tmp/video.webm")),
+               Some(("video".to_string(), "webm".to_string())));
+    assert_eq!(content_type(&Path::new("/tmp/README")), None);
+}
+
+#[test]
+fn test_load_range_and_directory() {
+    use resource_task::{Exit, Load, load_whole_resource, new_resource_task};
+    use servo_util::opts;
+    use std::io::TempDir;
+
+    let directory = TempDir::new("file_loader").unwrap();
+    let path = directory.path().join("data.txt");
+    File::create(&path).write(b"0123456789").unwrap();
+
+    let resource_task = new_resource_task(opts::default_network_opts());
+    let url = Url::from_file_path(&path).unwrap();
+    let (metadata, body) = load_whole_resource(&resource_task, url.clone()).unwrap();
+    assert_eq!(metadata.content_type, Some(("text".to_string(), "plain".to_string())));
+    assert_eq!(body, b"0123456789".to_vec());
+
+    let (start_chan, start_port) = channel();
+    let mut load_data = LoadData::new(url, start_chan);
+    let _ = load_data.headers.insert_raw("Range".to_string(), b"bytes=2-4");
+    resource_task.send(Load(load_data));
+    let response = start_port.recv();
+    assert_eq!(response.metadata.status.map(|status| status.code()), Some(206));
+    assert_eq!(response.progress_port.recv(), Payload(b"234".to_vec()));
+    assert_eq!(response.progress_port.recv(), Done(Ok(())));
+
+    let url = Url::from_file_path(directory.path()).unwrap();
+    let (metadata, body) = load_whole_resource(&resource_task, url).unwrap();
+    assert_eq!(metadata.content_type, Some(("text".to_string(), "html".to_string())));
+    let html = String::from_utf8(body).unwrap();
+    assert!(html.as_slice().contains(">data.txt</a></td><td>10</td>"));
+    resource_task.send(Exit);
+}
@@ -0,0 +1,632 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A task implementing a private HTTP cache, as described in
+//! http://tools.ietf.org/html/rfc7234. Responses are kept in memory up to a
+//! byte budget, and optionally written through to a directory on disk.
+
+use cookie::parse_cookie_date;
+
+use http::headers::HeaderEnum;
+use http::headers::request::HeaderCollection as RequestHeaderCollection;
+use http::headers::response::HeaderCollection as ResponseHeaderCollection;
+use http::method::Method;
+use std::ascii::AsciiExt;
+use std::collections::HashMap;
+use std::comm::{channel, Receiver, Sender};
+use std::hash::hash;
+use std::io;
+use std::io::File;
+use std::io::fs;
+use std::io::fs::PathExtensions;
+use time;
+use url::Url;
+
+use servo_util::task::spawn_named;
+
+/// A request that a response is stored for or looked up with.
+#[deriving(Clone)]
+pub struct CacheRequest {
+    pub method: Method,
+    pub url: Url,
+    /// The headers sent with the request, which select among responses that have a `Vary`
+    /// header.
+    pub headers: RequestHeaderCollection,
+}
+
+impl CacheRequest {
+    /// Responses are stored under the method and URL of their request, per
+    /// http://tools.ietf.org/html/rfc7234#section-2.
+    fn key(&self) -> CacheKey {
+        (self.method.to_string(), self.url.clone())
+    }
+
+    fn header_value(&self, name: &str) -> Option<String> {
+        self.headers.iter().find(|h| h.header_name().as_slice().eq_ignore_ascii_case(name))
+                           .map(|h| h.header_value())
+    }
+}
+
+type CacheKey = (String, Url);
+
+/// A complete response, as stored in the cache.
+#[deriving(Clone)]
+pub struct CachedResponse {
+    pub headers: ResponseHeaderCollection,
+    /// The body, with any content coding removed.
+    pub body: Vec<u8>,
+    /// The request headers named by the `Vary` header, and their values in the request that
+    /// the response was stored for.
+    pub vary: Vec<(String, Option<String>)>,
+    /// When the response was received or last revalidated, in seconds since the epoch.
+    pub stored_at: i64,
+    /// How long the response stays fresh after `stored_at`, in seconds.
+    pub freshness_lifetime: i64,
+    /// The age the response already had when it was stored, in seconds.
+    pub initial_age: i64,
+    /// Whether the response must be revalidated before every use (`no-cache`).
+    pub no_cache: bool,
+}
+
+impl CachedResponse {
+    /// http://tools.ietf.org/html/rfc7234#section-4.2
+    pub fn is_fresh(&self, now: i64) -> bool {
+        let current_age = self.initial_age + (now - self.stored_at);
+        !self.no_cache && current_age < self.freshness_lifetime
+    }
+
+    /// The value to send as `If-None-Match` when revalidating.
+    pub fn etag(&self) -> Option<String> {
+        header_value(&self.headers, "etag")
+    }
+
+    /// The value to send as `If-Modified-Since` when revalidating.
+    pub fn last_modified(&self) -> Option<String> {
+        header_value(&self.headers, "last-modified")
+    }
+
+    /// Whether the response may be used for `request`, which has the same method and URL as the
+    /// one it was stored for, per http://tools.ietf.org/html/rfc7234#section-4.1.
+    fn matches(&self, request: &CacheRequest) -> bool {
+        self.vary.iter().all(|&(ref name, ref value)| {
+            request.header_value(name.as_slice()) == *value
+        })
+    }
+}
+
+/// The result of looking a request up in the cache.
+pub enum CacheLookup {
+    /// The response can be used without contacting the server.
+    Fresh(CachedResponse),
+    /// The response must be revalidated with the server before use.
+    Stale(CachedResponse),
+    Miss,
+}
+
+/// Messages understood by the HTTP cache task
+pub enum HttpCacheMsg {
+    /// Look up the response stored for a request
+    Lookup(CacheRequest, Sender<CacheLookup>),
+    /// Store a complete `200 OK` response, with its body decoded, for a request
+    Store(CacheRequest, ResponseHeaderCollection, Vec<u8>),
+    /// The server answered `304 Not Modified` to a request; refresh the stored response
+    Revalidated(CacheRequest, ResponseHeaderCollection),
+    /// shut down this task
+    Exit
+}
+
+/// Handle to an HTTP cache task
+pub type HttpCacheTask = Sender<HttpCacheMsg>;
+
+/// Create an HttpCacheTask holding at most `capacity` bytes of response
+/// bodies in memory, and writing responses to `disk_path` if provided.
+pub fn new_http_cache_task(capacity: uint, disk_path: Option<Path>) -> HttpCacheTask {
+    let (chan, port) = channel();
+    spawn_named("HttpCache", proc() {
+        HttpCacheManager::new(port, HttpCache::new(capacity, disk_path)).start();
+    });
+    chan
+}
+
+struct HttpCacheManager {
+    port: Receiver<HttpCacheMsg>,
+    cache: HttpCache,
+}
+
+impl HttpCacheManager {
+    fn new(port: Receiver<HttpCacheMsg>, cache: HttpCache) -> HttpCacheManager {
+        HttpCacheManager {
+            port: port,
+            cache: cache,
+        }
+    }
+
+    fn start(&mut self) {
+        loop {
+            match self.port.recv_opt() {
+                Ok(Lookup(request, sender)) => {
+                    sender.send(self.cache.lookup(&request, time::get_time().sec));
+                }
+                Ok(Store(request, headers, body)) => {
+                    self.cache.store(&request, headers, body, time::get_time().sec);
+                }
+                Ok(Revalidated(request, headers)) => {
+                    self.cache.revalidated(&request, headers, time::get_time().sec);
+                }
+                Ok(Exit) | Err(_) => break,
+            }
+        }
+    }
+}
+
+struct MemoryEntry {
+    response: CachedResponse,
+    /// Value of `HttpCache::clock` at the last use, for LRU eviction.
+    last_used: u64,
+}
+
+/// The cache storage, kept separate from the task so it can be exercised directly.
+pub struct HttpCache {
+    entries: HashMap<CacheKey, MemoryEntry>,
+    /// Total size of the bodies held in `entries`.
+    size: uint,
+    capacity: uint,
+    disk_path: Option<Path>,
+    clock: u64,
+}
+
+impl HttpCache {
+    pub fn new(capacity: uint, disk_path: Option<Path>) -> HttpCache {
+        match disk_path {
+            Some(ref path) if !path.exists() => {
+                match fs::mkdir_recursive(path, io::USER_RWX) {
+                    Ok(()) => (),
+                    Err(e) => error!("unable to create HTTP cache directory: {}", e),
+                }
+            }
+            _ => (),
+        }
+        HttpCache {
+            entries: HashMap::new(),
+            size: 0,
+            capacity: capacity,
+            disk_path: disk_path,
+            clock: 0,
+        }
+    }
+
+    pub fn lookup(&mut self, request: &CacheRequest, now: i64) -> CacheLookup {
+        self.clock += 1;
+        let clock = self.clock;
+        let key = request.key();
+        let response = match self.entries.get_mut(&key) {
+            Some(entry) => {
+                entry.last_used = clock;
+                Some(entry.response.clone())
+            }
+            None => None,
+        };
+        let response = match response {
+            Some(response) => response,
+            None => match self.read_from_disk(&key) {
+                Some(response) => {
+                    self.insert_in_memory(key, response.clone());
+                    response
+                }
+                None => return Miss,
+            },
+        };
+        if !response.matches(request) {
+            Miss
+        } else if response.is_fresh(now) {
+            Fresh(response)
+        } else {
+            Stale(response)
+        }
+    }
+
+    pub fn store(&mut self, request: &CacheRequest, mut headers: ResponseHeaderCollection,
+                 body: Vec<u8>, now: i64) {
+        let key = request.key();
+        self.remove(&key);
+        // The body is stored decoded, so the headers that described how it was encoded in
+        // transit must describe the stored body instead.
+        headers.content_encoding = None;
+        headers.transfer_encoding = None;
+        headers.content_length = Some(body.len());
+        let response = match cached_response(request, headers, body, now) {
+            Some(response) => response,
+            None => {
+                self.remove_from_disk(&key);
+                return
+            }
+        };
+        self.write_to_disk(&key, &response);
+        self.insert_in_memory(key, response);
+    }
+
+    /// Merge the headers of a `304 Not Modified` response into the stored one,
+    /// per http://tools.ietf.org/html/rfc7234#section-4.3.4
+    pub fn revalidated(&mut self, request: &CacheRequest, headers: ResponseHeaderCollection,
+                       now: i64) {
+        let stored = match self.lookup(request, now) {
+            Fresh(response) | Stale(response) => response,
+            Miss => return,
+        };
+        let mut merged = stored.headers.clone();
+        for header in headers.iter() {
+            merged.insert(header);
+        }
+        self.store(request, merged, stored.body, now);
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        match self.entries.remove(key) {
+            Some(entry) => self.size -= entry.response.body.len(),
+            None => (),
+        }
+    }
+
+    fn insert_in_memory(&mut self, key: CacheKey, response: CachedResponse) {
+        let size = response.body.len();
+        if size > self.capacity {
+            return;
+        }
+        self.remove(&key);
+        while self.size + size > self.capacity {
+            self.evict_least_recently_used();
+        }
+        self.clock += 1;
+        self.size += size;
+        self.entries.insert(key, MemoryEntry {
+            response: response,
+            last_used: self.clock,
+        });
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self.entries.iter().min_by(|&(_, entry)| entry.last_used)
+                                        .map(|(key, _)| key.clone());
+        match oldest {
+            Some(key) => self.remove(&key),
+            None => (),
+        }
+    }
+
+    fn path_for(&self, key: &CacheKey) -> Option<Path> {
+        self.disk_path.as_ref().map(|dir| dir.join(format!("{:016x}", hash(&disk_key(key)))))
+    }
+
+    /// Entries are stored as the method and URL, a line of freshness information, the request
+    /// headers named by `Vary` and the response headers one per line, each followed by a blank
+    /// line, and then the body. Request headers that were absent are stored without a colon.
+    fn write_to_disk(&self, key: &CacheKey, response: &CachedResponse) {
+        let path = match self.path_for(key) {
+            Some(path) => path,
+            None => return,
+        };
+        let mut contents = format!("{}\n{} {} {} {}\n", disk_key(key), response.stored_at,
+                                   response.freshness_lifetime, response.initial_age,
+                                   response.no_cache);
+        for &(ref name, ref value) in response.vary.iter() {
+            match *value {
+                Some(ref value) => contents.push_str(format!("{}: {}\n", name, value).as_slice()),
+                None => contents.push_str(format!("{}\n", name).as_slice()),
+            }
+        }
+        contents.push_str("\n");
+        for header in response.headers.iter() {
+            contents.push_str(format!("{}: {}\n", header.header_name(), header.header_value())
+                              .as_slice());
+        }
+        contents.push_str("\n");
+
+        let result = File::create(&path).and_then(|mut file| {
+            file.write(contents.as_bytes()).and_then(|_| file.write(response.body.as_slice()))
+        });
+        match result {
+            Ok(()) => (),
+            Err(e) => error!("unable to write HTTP cache entry for {}: {}", disk_key(key), e),
+        }
+    }
+
+    fn remove_from_disk(&self, key: &CacheKey) {
+        match self.path_for(key) {
+            Some(ref path) if path.exists() => {
+                let _ = fs::unlink(path);
+            }
+            _ => (),
+        }
+    }
+
+    fn read_from_disk(&self, key: &CacheKey) -> Option<CachedResponse> {
+        let path = match self.path_for(key) {
+            Some(ref path) if path.exists() => path.clone(),
+            _ => return None,
+        };
+        let bytes = match File::open(&path).read_to_end() {
+            Ok(bytes) => bytes,
+            Err(_) => return None,
+        };
+        // The head ends with the blank line after the response headers.
+        let vary_end = match bytes.as_slice().windows(2).position(|w| w == b"\n\n") {
+            Some(index) => index,
+            None => return None,
+        };
+        let header_end = match bytes.slice_from(vary_end + 1).windows(2).position(|w| {
+            w == b"\n\n"
+        }) {
+            Some(index) => vary_end + 1 + index,
+            None => return None,
+        };
+        let head = match String::from_utf8(bytes.slice_to(header_end).to_vec()) {
+            Ok(head) => head,
+            Err(_) => return None,
+        };
+        let mut lines = head.as_slice().lines();
+
+        // Different requests may hash to the same file name.
+        if lines.next() != Some(disk_key(key).as_slice()) {
+            return None;
+        }
+        let freshness: Vec<&str> = match lines.next() {
+            Some(line) => line.split(' ').collect(),
+            None => return None,
+        };
+        if freshness.len() != 4 {
+            return None;
+        }
+        let mut vary = vec!();
+        for line in lines.by_ref().take_while(|line| !line.is_empty()) {
+            vary.push(match line.find(':') {
+                Some(index) => (line.slice_to(index).to_string(),
+                                Some(line.slice_from(index + 1).trim().to_string())),
+                None => (line.to_string(), None),
+            });
+        }
+        let mut headers = ResponseHeaderCollection::new();
+        for line in lines {
+            match line.find(':') {
+                Some(index) => {
+                    let name = line.slice_to(index).to_string();
+                    let value = line.slice_from(index + 1).trim();
+                    let _ = headers.insert_raw(name, value.as_bytes());
+                }
+                None => return None,
+            }
+        }
+
+        match (from_str(freshness[0]), from_str(freshness[1]),
+               from_str(freshness[2]), from_str(freshness[3])) {
+            (Some(stored_at), Some(freshness_lifetime), Some(initial_age), Some(no_cache)) => {
+                Some(CachedResponse {
+                    headers: headers,
+                    body: bytes.slice_from(header_end + 2).to_vec(),
+                    vary: vary,
+                    stored_at: stored_at,
+                    freshness_lifetime: freshness_lifetime,
+                    initial_age: initial_age,
+                    no_cache: no_cache,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn disk_key(&(ref method, ref url): &CacheKey) -> String {
+    format!("{} {}", method, url.serialize())
+}
+
+fn header_value(headers: &ResponseHeaderCollection, name: &str) -> Option<String> {
+    headers.iter().find(|h| h.header_name().as_slice().eq_ignore_ascii_case(name))
+                  .map(|h| h.header_value())
+}
+
+/// Decide whether a response may be stored and compute its freshness, per
+/// http://tools.ietf.org/html/rfc7234#section-3 and section 4.2.
+fn cached_response(request: &CacheRequest, headers: ResponseHeaderCollection, body: Vec<u8>,
+                   now: i64) -> Option<CachedResponse> {
+    let mut no_cache = false;
+    let mut max_age = None;
+    match header_value(&headers, "cache-control") {
+        Some(cache_control) => {
+            for directive in cache_control.as_slice().split(',') {
+                let directive = directive.trim().to_ascii_lower();
+                let (name, argument) = match directive.as_slice().find('=') {
+                    Some(index) => (directive.as_slice().slice_to(index).trim(),
+                                    Some(directive.as_slice().slice_from(index + 1)
+                                                             .trim().trim_chars('"'))),
+                    None => (directive.as_slice(), None),
+                };
+                match name {
+                    "no-store" => return None,
+                    "no-cache" | "must-revalidate" => no_cache = true,
+                    "max-age" => max_age = argument.and_then(from_str::<i64>),
+                    _ => (),
+                }
+            }
+        }
+        None => (),
+    }
+
+    // Remember the request headers that the response varies on, so that it is only used for
+    // requests that agree with them. `Vary: *` matches no other request.
+    let mut vary = vec!();
+    match header_value(&headers, "vary") {
+        Some(fields) => {
+            for field in fields.as_slice().split(',') {
+                let name = field.trim().to_ascii_lower();
+                if name.as_slice() == "*" {
+                    return None;
+                }
+                if !name.is_empty() {
+                    let value = request.header_value(name.as_slice());
+                    vary.push((name, value));
+                }
+            }
+        }
+        None => (),
+    }
+
+    let date = header_value(&headers, "date").and_then(|d| parse_cookie_date(d.as_slice()));
+    let last_modified = header_value(&headers, "last-modified");
+    let has_validators = header_value(&headers, "etag").is_some() || last_modified.is_some();
+
+    // http://tools.ietf.org/html/rfc7234#section-4.2.1
+    let freshness_lifetime = match max_age {
+        Some(max_age) => max_age,
+        None => match header_value(&headers, "expires") {
+            // Invalid dates, such as "0", mean the response is already expired.
+            Some(expires) => parse_cookie_date(expires.as_slice()).map_or(0, |expires| {
+                expires - date.unwrap_or(now)
+            }),
+            // http://tools.ietf.org/html/rfc7234#section-4.2.2
+            None => last_modified.as_ref()
+                                 .and_then(|lm| parse_cookie_date(lm.as_slice()))
+                                 .map_or(0, |lm| (date.unwrap_or(now) - lm) / 10),
+        },
+    };
+
+    if freshness_lifetime <= 0 && !has_validators {
+        return None;
+    }
+
+    // http://tools.ietf.org/html/rfc7234#section-4.2.3
+    let age_value = header_value(&headers, "age").and_then(|age| from_str::<i64>(age.as_slice().trim()));
+    let apparent_age = date.map_or(0, |date| now - date);
+    let initial_age = [age_value.unwrap_or(0), apparent_age, 0].iter().map(|&a| a).max().unwrap();
+
+    Some(CachedResponse {
+        headers: headers,
+        body: body,
+        vary: vary,
+        stored_at: now,
+        freshness_lifetime: freshness_lifetime,
+        initial_age: initial_age,
+        no_cache: no_cache,
+    })
+}
+
+#[cfg(test)]
+fn headers_from(pairs: &[(&str, &str)]) -> ResponseHeaderCollection {
+    let mut headers = ResponseHeaderCollection::new();
+    for &(name, value) in pairs.iter() {
+        let _ = headers.insert_raw(name.to_string(), value.as_bytes());
+    }
+    headers
+}
+
+#[cfg(test)]
+fn request_for(method: Method, url: &str, pairs: &[(&str, &str)]) -> CacheRequest {
+    let mut headers = RequestHeaderCollection::new();
+    for &(name, value) in pairs.iter() {
+        let _ = headers.insert_raw(name.to_string(), value.as_bytes());
+    }
+    CacheRequest {
+        method: method,
+        url: Url::parse(url).unwrap(),
+        headers: headers,
+    }
+}
+
+#[test]
+fn test_freshness() {
+    use http::method::Get;
+
+    let now = 784111777; // Sun, 06 Nov 1994 08:49:37 GMT
+    let date = ("Date", "Sun, 06 Nov 1994 08:49:37 GMT");
+    let request = request_for(Get, "http://example.com/", &[]);
+
+    let response = cached_response(&request, headers_from(&[date, ("Cache-Control", "max-age=60")]),
+                                   vec!(), now).unwrap();
+    assert!(response.is_fresh(now + 59));
+    assert!(!response.is_fresh(now + 60));
+
+    let headers = headers_from(&[date, ("Expires", "Sun, 06 Nov 1994 08:50:37 GMT")]);
+    let response = cached_response(&request, headers, vec!(), now).unwrap();
+    assert_eq!(response.freshness_lifetime, 60);
+
+    let headers = headers_from(&[date, ("Cache-Control", "max-age=60"), ("Age", "30")]);
+    let response = cached_response(&request, headers, vec!(), now).unwrap();
+    assert!(!response.is_fresh(now + 30));
+
+    let headers = headers_from(&[("Cache-Control", "no-cache"), ("ETag", "\"a\"")]);
+    let response = cached_response(&request, headers, vec!(), now).unwrap();
+    assert!(!response.is_fresh(now));
+    assert_eq!(response.etag(), Some("\"a\"".to_string()));
+}
+
+#[test]
+fn test_uncacheable_responses() {
+    use http::method::Get;
+
+    let now = 784111777;
+    let request = request_for(Get, "http://example.com/", &[]);
+    assert!(cached_response(&request, headers_from(&[("Cache-Control", "no-store, max-age=60")]),
+                            vec!(), now).is_none());
+    let headers = headers_from(&[("Cache-Control", "max-age=60"), ("Vary", "*")]);
+    assert!(cached_response(&request, headers, vec!(), now).is_none());
+    assert!(cached_response(&request, headers_from(&[]), vec!(), now).is_none());
+}
+
+#[test]
+fn test_memory_eviction() {
+    use http::method::Get;
+
+    let now = 784111777;
+    let mut cache = HttpCache::new(10, None);
+    let first = request_for(Get, "http://example.com/first", &[]);
+    let second = request_for(Get, "http://example.com/second", &[]);
+    let third = request_for(Get, "http://example.com/third", &[]);
+    let headers = || headers_from(&[("Cache-Control", "max-age=60")]);
+
+    cache.store(&first, headers(), Vec::from_elem(4, 0u8), now);
+    cache.store(&second, headers(), Vec::from_elem(4, 0u8), now);
+    match cache.lookup(&first, now) { Fresh(_) => (), _ => panic!("expected a fresh entry") }
+
+    // `second` is now the least recently used entry.
+    cache.store(&third, headers(), Vec::from_elem(4, 0u8), now);
+    match cache.lookup(&second, now) { Miss => (), _ => panic!("expected an eviction") }
+    match cache.lookup(&first, now) { Fresh(_) => (), _ => panic!("expected a fresh entry") }
+    match cache.lookup(&third, now + 61) { Stale(_) => (), _ => panic!("expected a stale entry") }
+}
+
+#[test]
+fn test_method_and_vary() {
+    use http::method::{Get, Head};
+
+    let now = 784111777;
+    let mut cache = HttpCache::new(1024, None);
+    let english = request_for(Get, "http://example.com/", &[("Accept-Language", "en")]);
+    let headers = headers_from(&[("Cache-Control", "max-age=60"), ("Vary", "Accept-Language")]);
+    cache.store(&english, headers, vec!(), now);
+
+    match cache.lookup(&english, now) { Fresh(_) => (), _ => panic!("expected a fresh entry") }
+    let french = request_for(Get, "http://example.com/", &[("Accept-Language", "fr")]);
+    match cache.lookup(&french, now) { Miss => (), _ => panic!("expected a miss") }
+    let unspecified = request_for(Get, "http://example.com/", &[]);
+    match cache.lookup(&unspecified, now) { Miss => (), _ => panic!("expected a miss") }
+    let head = request_for(Head, "http://example.com/", &[("Accept-Language", "en")]);
+    match cache.lookup(&head, now) { Miss => (), _ => panic!("expected a miss") }
+}
+
+#[test]
+fn test_stored_headers_describe_decoded_body() {
+    use http::method::Get;
+
+    let now = 784111777;
+    let mut cache = HttpCache::new(1024, None);
+    let request = request_for(Get, "http://example.com/", &[]);
+    let headers = headers_from(&[("Cache-Control", "max-age=60"), ("Content-Encoding", "gzip"),
+                                 ("Content-Length", "31")]);
+    cache.store(&request, headers, b"hello world".to_vec(), now);
+
+    let response = match cache.lookup(&request, now) {
+        Fresh(response) => response,
+        _ => panic!("expected a fresh entry"),
+    };
+    assert_eq!(header_value(&response.headers, "content-encoding"), None);
+    assert_eq!(header_value(&response.headers, "content-length"), Some("11".to_string()));
+}
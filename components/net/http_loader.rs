@@ -2,18 +2,99 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use cookie::HTTP;
+use cookie_storage::{CookieStorageTask, GetCookiesForUrl, SetCookiesForUrl};
+use http_cache::{CacheRequest, CachedResponse, HttpCacheTask, Lookup, Fresh, Stale, Miss, Store};
+use http_cache::Revalidated;
+use load_interceptor::LoadInterceptors;
+use proxy;
+use proxy::ProxySettings;
 use resource_task::{Metadata, Payload, Done, TargetedLoadResponse, LoadData, start_sending_opt, ResponseSenders};
 
+use inflate::{Inflater, GzipFormat, ZlibOrRawFormat};
+
 use log;
+use std::ascii::AsciiExt;
 use std::collections::HashSet;
 use http::client::{RequestWriter, NetworkStream};
 use http::headers::HeaderEnum;
-use std::io::Reader;
+use http::headers::request::HeaderCollection as RequestHeaderCollection;
+use http::headers::response::HeaderCollection as ResponseHeaderCollection;
+use http::method::{Method, Get, Post};
+use http::status::Status;
+use std::io;
+use std::io::{IoResult, Reader};
+use std::io::timer::Timer;
+use std::time::duration::Duration;
+use servo_util::opts::NetworkOpts;
 use servo_util::task::spawn_named;
 use url::Url;
 
-pub fn factory(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
-    spawn_named("http_loader", proc() load(load_data, start_chan))
+pub fn factory(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>, network_opts: NetworkOpts,
+               proxy_settings: ProxySettings, cookie_storage: CookieStorageTask, http_cache: HttpCacheTask,
+               load_interceptors: LoadInterceptors) {
+    spawn_named("http_loader", proc() {
+        load(load_data, start_chan, network_opts, proxy_settings, cookie_storage, http_cache,
+             load_interceptors)
+    })
+}
+
+/// The head of a response, and its body, whether it came directly from the server or through
+/// a proxy.
+pub struct HttpResponse {
+    pub status: Status,
+    pub headers: ResponseHeaderCollection,
+    pub body: Box<Reader + Send>,
+}
+
+impl Reader for HttpResponse {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<uint> {
+        self.body.read(buf)
+    }
+}
+
+/// The content codings we know how to remove from a response body.
+#[deriving(PartialEq)]
+enum ContentCoding {
+    Identity,
+    Gzip,
+    Deflate,
+}
+
+fn content_coding(headers: &ResponseHeaderCollection) -> Result<ContentCoding, String> {
+    let header = headers.iter().find(|h| {
+        h.header_name().as_slice().eq_ignore_ascii_case("content-encoding")
+    });
+    let value = match header {
+        Some(h) => h.header_value(),
+        None => return Ok(Identity),
+    };
+    match value.as_slice().trim().to_ascii_lower().as_slice() {
+        "" | "identity" => Ok(Identity),
+        "gzip" | "x-gzip" => Ok(Gzip),
+        "deflate" => Ok(Deflate),
+        other => Err(format!("unsupported content coding: {:s}", other)),
+    }
+}
+
+/// Only plain GET requests whose body will be decoded are served from and
+/// stored in the HTTP cache.
+fn is_cacheable_request(load_data: &LoadData) -> bool {
+    load_data.method == Get && load_data.data.is_none() && !load_data.preserve_content_codings
+}
+
+fn send_cached_response(url: Url, cached: CachedResponse, senders: ResponseSenders) {
+    let mut metadata = Metadata::default(url);
+    metadata.set_content_type(&cached.headers.content_type);
+    metadata.headers = Some(cached.headers);
+
+    match start_sending_opt(senders, metadata) {
+        Ok(progress_chan) => {
+            let _ = progress_chan.send_opt(Payload(cached.body));
+            let _ = progress_chan.send_opt(Done(Ok(())));
+        }
+        Err(_) => {}
+    }
 }
 
 fn send_error(url: Url, err: String, senders: ResponseSenders) {
@@ -26,29 +107,83 @@ fn send_error(url: Url, err: String, senders: ResponseSenders) {
     };
 }
 
-fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
-    // FIXME: At the time of writing this FIXME, servo didn't have any central
-    //        location for configuration. If you're reading this and such a
-    //        repository DOES exist, please update this constant to use it.
-    let max_redirects = 50u;
-    let mut iters = 0u;
+/// Send a request directly to the server, and wait for the response headers.
+fn send_request(method: Method, url: Url, headers: RequestHeaderCollection, data: Option<Vec<u8>>)
+                -> Result<HttpResponse, String> {
+    let mut writer = match RequestWriter::<NetworkStream>::new(method, url) {
+        Ok(w) => w,
+        Err(e) => return Err(e.desc.to_string()),
+    };
+
+    // Preserve the `host` header set automatically by RequestWriter.
+    let host = writer.headers.host.clone();
+    writer.headers = headers;
+    writer.headers.host = host;
+
+    match data {
+        Some(data) => {
+            writer.headers.content_length = Some(data.len());
+            match writer.write(data.as_slice()) {
+                Err(e) => return Err(e.desc.to_string()),
+                _ => {}
+            }
+        },
+        None => {}
+    }
+    match writer.read_response() {
+        Ok(response) => {
+            Ok(HttpResponse {
+                status: response.status.clone(),
+                headers: response.headers.clone(),
+                body: box response as Box<Reader + Send>,
+            })
+        }
+        Err((_, e)) => Err(e.desc.to_string()),
+    }
+}
+
+/// Run `request`, but give up if the server has not responded within `timeout` milliseconds.
+fn send_request_with_timeout(request: proc():Send -> Result<HttpResponse, String>,
+                             timeout: Option<u64>) -> Result<HttpResponse, String> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return request(),
+    };
+    let mut timer = match Timer::new() {
+        Ok(timer) => timer,
+        Err(e) => return Err(e.desc.to_string()),
+    };
+    let (response_chan, response_port) = channel();
+    // If the timeout fires first, the request is left to fail or complete on its own.
+    spawn_named("http_loader request", proc() {
+        let _ = response_chan.send_opt(request());
+    });
+    let timeout_port = timer.oneshot(Duration::milliseconds(timeout as i64));
+    select! (
+        response = response_port.recv_opt() => {
+            match response {
+                Ok(response) => response,
+                Err(()) => Err("request failed".to_string()),
+            }
+        },
+        () = timeout_port.recv() => Err("request timed out".to_string())
+    )
+}
+
+fn load(mut load_data: LoadData, start_chan: Sender<TargetedLoadResponse>, network_opts: NetworkOpts,
+        proxy_settings: ProxySettings, cookie_storage: CookieStorageTask, http_cache: HttpCacheTask,
+        load_interceptors: LoadInterceptors) {
+    let mut redirect_count = 0u;
     let mut url = load_data.url.clone();
     let mut redirected_to = HashSet::new();
 
     let senders = ResponseSenders {
         immediate_consumer: start_chan,
-        eventual_consumer: load_data.consumer
+        eventual_consumer: load_data.consumer.clone()
     };
 
     // Loop to handle redirects.
     loop {
-        iters = iters + 1;
-
-        if iters > max_redirects {
-            send_error(url, "too many redirects".to_string(), senders);
-            return;
-        }
-
         if redirected_to.contains(&url) {
             send_error(url, "redirect loop".to_string(), senders);
             return;
@@ -65,42 +200,94 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
             }
         }
 
-        info!("requesting {:s}", url.serialize());
+        let mut headers = load_data.headers.clone();
+        if headers.accept_encoding.is_none() {
+            headers.accept_encoding = Some(String::from_str("gzip, deflate"))
+        }
 
-        let request = RequestWriter::<NetworkStream>::new(load_data.method.clone(), url.clone());
-        let mut writer = match request {
-            Ok(w) => box w,
-            Err(e) => {
-                send_error(url, e.desc.to_string(), senders);
+        for &(ref name, ref value) in network_opts.extra_headers.iter() {
+            let present = headers.iter().any(|header| {
+                header.header_name().as_slice().eq_ignore_ascii_case(name.as_slice())
+            });
+            if !present {
+                let _ = headers.insert_raw(name.clone(), value.as_bytes());
+            }
+        }
+
+        // Every hop, including redirects, carries the cookies that apply to its own URL.
+        if load_data.credentials_flag {
+            let (cookies_chan, cookies_port) = channel();
+            cookie_storage.send(GetCookiesForUrl(url.clone(), cookies_chan, HTTP));
+            match cookies_port.recv() {
+                Some(cookie_list) => {
+                    let _ = headers.insert_raw("Cookie".to_string(), cookie_list.as_bytes());
+                }
+                None => ()
+            }
+        }
+
+        // The cache selects responses by the headers that the request is sent with, before
+        // the conditional ones below are added.
+        let cache_request = CacheRequest {
+            method: load_data.method.clone(),
+            url: url.clone(),
+            headers: headers.clone(),
+        };
+        let cached = if is_cacheable_request(&load_data) {
+            let (cache_chan, cache_port) = channel();
+            http_cache.send(Lookup(cache_request.clone(), cache_chan));
+            cache_port.recv()
+        } else {
+            Miss
+        };
+        let cached = match cached {
+            Fresh(response) => {
+                info!("serving {:s} from the HTTP cache", url.serialize());
+                send_cached_response(url, response, senders);
                 return;
             }
+            Stale(response) => Some(response),
+            Miss => None,
         };
 
-        // Preserve the `host` header set automatically by RequestWriter.
-        let host = writer.headers.host.clone();
-        writer.headers = load_data.headers.clone();
-        writer.headers.host = host;
-        if writer.headers.accept_encoding.is_none() {
-            // We currently don't support HTTP Compression (FIXME #2587)
-            writer.headers.accept_encoding = Some(String::from_str("identity".as_slice()))
-        }
-        match load_data.data {
-            Some(ref data) => {
-                writer.headers.content_length = Some(data.len());
-                match writer.write(data.as_slice()) {
-                    Err(e) => {
-                        send_error(url, e.desc.to_string(), senders);
-                        return;
+        info!("requesting {:s}", url.serialize());
+
+        // Ask the server to confirm that our stale copy is still usable.
+        match cached {
+            Some(ref cached) => {
+                match cached.etag() {
+                    Some(etag) => {
+                        let _ = headers.insert_raw("If-None-Match".to_string(), etag.as_bytes());
+                    }
+                    None => ()
+                }
+                match cached.last_modified() {
+                    Some(date) => {
+                        let _ = headers.insert_raw("If-Modified-Since".to_string(), date.as_bytes());
                     }
-                    _ => {}
+                    None => ()
                 }
-            },
-            _ => {}
+            }
+            None => ()
         }
-        let mut response = match writer.read_response() {
+
+        let method = load_data.method.clone();
+        let data = load_data.data.clone();
+        let request_url = url.clone();
+        let request: proc():Send -> Result<HttpResponse, String> = match proxy_settings.proxy_for(&url) {
+            Some(p) => {
+                info!("using proxy {:s}:{:u}", p.host, p.port);
+                let p = p.clone();
+                let ca_file = proxy_settings.ca_file.clone();
+                proc() proxy::send_request(&p, &ca_file, method, &request_url, &headers, data)
+            }
+            None => proc() send_request(method, request_url, headers, data),
+        };
+
+        let mut response = match send_request_with_timeout(request, network_opts.timeout) {
             Ok(r) => r,
-            Err((_, e)) => {
-                send_error(url, e.desc.to_string(), senders);
+            Err(e) => {
+                send_error(url, e, senders);
                 return;
             }
         };
@@ -113,7 +300,29 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
             }
         }
 
-        if 3 == (response.status.code() / 100) {
+        if load_data.credentials_flag {
+            for header in response.headers.iter() {
+                if header.header_name().as_slice().eq_ignore_ascii_case("set-cookie") {
+                    cookie_storage.send(SetCookiesForUrl(url.clone(), header.header_value(), HTTP));
+                }
+            }
+        }
+
+        if response.status.code() == 304 {
+            match cached {
+                Some(cached) => {
+                    info!("{:s} was revalidated", url.serialize());
+                    http_cache.send(Revalidated(cache_request, response.headers.clone()));
+                    send_cached_response(url, cached, senders);
+                    return;
+                }
+                None => ()
+            }
+        }
+
+        let code = response.status.code();
+        let is_redirect = code == 301 || code == 302 || code == 303 || code == 307 || code == 308;
+        if is_redirect && !load_data.manual_redirect {
             match response.headers.location {
                 Some(new_url) => {
                     // CORS (http://fetch.spec.whatwg.org/#http-fetch, status section, point 9, 10)
@@ -130,14 +339,49 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
                         }
                         _ => {}
                     }
+                    if redirect_count == network_opts.max_redirects {
+                        send_error(url, "too many redirects".to_string(), senders);
+                        return;
+                    }
+                    redirect_count += 1;
+                    // http://fetch.spec.whatwg.org/#http-fetch, step 4.10. 307 and 308
+                    // redirects repeat the request with its method and body unchanged.
+                    if ((code == 301 || code == 302) && load_data.method == Post) || code == 303 {
+                        load_data.method = Get;
+                        load_data.data = None;
+                        load_data.headers.content_type = None;
+                        load_data.headers.content_length = None;
+                    }
                     info!("redirecting to {:s}", new_url.serialize());
-                    url = new_url;
+                    // Every hop is offered to the interceptors, like the first one was.
+                    load_data.url = new_url;
+                    load_data = match load_interceptors.run(load_data,
+                                                            senders.immediate_consumer.clone(),
+                                                            network_opts.max_redirects) {
+                        Some(load_data) => load_data,
+                        None => return,
+                    };
+                    url = load_data.url.clone();
                     continue;
                 }
                 None => ()
             }
         }
 
+        let coding = if load_data.preserve_content_codings {
+            Ok(Identity)
+        } else {
+            content_coding(&response.headers)
+        };
+        let coding = match coding {
+            Ok(coding) => coding,
+            Err(e) => {
+                send_error(url, e, senders);
+                return;
+            }
+        };
+
+        let store_in_cache = is_cacheable_request(&load_data) && response.status.code() == 200;
         let mut metadata = Metadata::default(url);
         metadata.set_content_type(&response.headers.content_type);
         metadata.headers = Some(response.headers.clone());
@@ -147,6 +391,16 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
             Ok(p) => p,
             _ => return
         };
+
+        // Encoded bodies are decoded a chunk at a time, as they arrive. Bodies destined for the
+        // cache are collected as well.
+        let mut decoder = match coding {
+            Identity => None,
+            Gzip => Some(Inflater::new(GzipFormat)),
+            Deflate => Some(Inflater::new(ZlibOrRawFormat)),
+        };
+        let mut body = vec!();
+        let mut encoded_len = 0u;
         loop {
             let mut buf = Vec::with_capacity(1024);
 
@@ -154,6 +408,25 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
             match response.read(buf.as_mut_slice()) {
                 Ok(len) => {
                     unsafe { buf.set_len(len); }
+                    encoded_len += len;
+                    let buf = match decoder {
+                        Some(ref mut decoder) => {
+                            let decoded = decoder.push(buf.as_slice());
+                            if decoder.has_failed() {
+                                let error = "failed to decode response body".to_string();
+                                let _ = progress_chan.send_opt(Done(Err(error)));
+                                return;
+                            }
+                            decoded
+                        }
+                        None => buf,
+                    };
+                    if buf.is_empty() {
+                        continue;
+                    }
+                    if store_in_cache {
+                        body.push_all(buf.as_slice());
+                    }
                     if progress_chan.send_opt(Payload(buf)).is_err() {
                         // The send errors when the receiver is out of scope,
                         // which will happen if the fetch has timed out (or has been aborted)
@@ -161,7 +434,22 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
                         return;
                     }
                 }
-                Err(_) => {
+                Err(e) => {
+                    // Responses without a body, such as those to HEAD requests and 204 and 304
+                    // responses, keep their Content-Encoding but have nothing to decode.
+                    let truncated = match decoder {
+                        Some(ref decoder) => encoded_len > 0 && !decoder.is_finished(),
+                        None => false,
+                    };
+                    if truncated {
+                        let error = "failed to decode response body".to_string();
+                        let _ = progress_chan.send_opt(Done(Err(error)));
+                        break;
+                    }
+                    // Anything but a clean end of stream means the body is truncated.
+                    if store_in_cache && e.kind == io::EndOfFile {
+                        http_cache.send(Store(cache_request, response.headers.clone(), body));
+                    }
                     let _ = progress_chan.send_opt(Done(Ok(())));
                     break;
                 }
@@ -172,3 +460,195 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
         break;
     }
 }
+
+#[test]
+fn test_gzip_body_is_decoded_as_it_arrives() {
+    use cookie_storage::{new_cookie_storage_task, Exit};
+    use http_cache::new_http_cache_task;
+    use http_cache::Exit as HttpCacheExit;
+    use servo_util::opts;
+    use std::io::{Acceptor, Listener};
+    use std::io::net::tcp::TcpListener;
+
+    let body = [31u8, 139, 8, 8, 0, 0, 0, 0, 2, 255, 97, 46, 116, 120, 116, 0, 203, 72, 205, 201,
+                201, 87, 40, 207, 47, 202, 73, 1, 0, 133, 17, 74, 13, 11, 0, 0, 0];
+    let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.socket_name().unwrap().port;
+    let mut acceptor = listener.listen().unwrap();
+    // The rest of the body is only sent once the start of it has been decoded.
+    let (continue_chan, continue_port) = channel();
+    spawn_named("test HTTP server", proc() {
+        let mut stream = acceptor.accept().unwrap();
+        read_test_request(&mut stream);
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\
+                            Connection: close\r\n\r\n", body.len());
+        stream.write_str(head.as_slice()).unwrap();
+        stream.write(body.slice_to(20)).unwrap();
+        stream.flush().unwrap();
+        continue_port.recv();
+        stream.write(body.slice_from(20)).unwrap();
+    });
+
+    let cookie_storage = new_cookie_storage_task();
+    let http_cache = new_http_cache_task(0, None);
+    let (consumer, _) = channel();
+    let url = Url::parse(format!("http://127.0.0.1:{}/", port).as_slice()).unwrap();
+    let (start_chan, start_port) = channel();
+    let network_opts = opts::default_network_opts();
+    let proxy_settings = ProxySettings::from_opts(&network_opts);
+    factory(LoadData::new(url, consumer), start_chan, network_opts, proxy_settings,
+            cookie_storage.clone(), http_cache.clone(), LoadInterceptors::new());
+    let response = start_port.recv().load_response;
+
+    let mut body = vec!();
+    while body.is_empty() {
+        match response.progress_port.recv() {
+            Payload(data) => body.push_all(data.as_slice()),
+            Done(_) => panic!("the response ended early"),
+        }
+    }
+    assert!(b"hello world".starts_with(body.as_slice()));
+    continue_chan.send(());
+    loop {
+        match response.progress_port.recv() {
+            Payload(data) => body.push_all(data.as_slice()),
+            Done(result) => {
+                assert!(result.is_ok());
+                break;
+            }
+        }
+    }
+    assert_eq!(body, b"hello world".to_vec());
+
+    cookie_storage.send(Exit);
+    http_cache.send(HttpCacheExit);
+}
+
+#[test]
+fn test_empty_gzip_body() {
+    use cookie_storage::{new_cookie_storage_task, Exit};
+    use http_cache::new_http_cache_task;
+    use http_cache::Exit as HttpCacheExit;
+    use servo_util::opts;
+    use std::io::{Acceptor, Listener};
+    use std::io::net::tcp::TcpListener;
+
+    let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.socket_name().unwrap().port;
+    let mut acceptor = listener.listen().unwrap();
+    spawn_named("test HTTP server", proc() {
+        let mut stream = acceptor.accept().unwrap();
+        read_test_request(&mut stream);
+        stream.write_str("HTTP/1.1 204 No Content\r\nContent-Encoding: gzip\r\n\
+                          Connection: close\r\n\r\n").unwrap();
+    });
+
+    let cookie_storage = new_cookie_storage_task();
+    let http_cache = new_http_cache_task(0, None);
+    let (consumer, _) = channel();
+    let url = Url::parse(format!("http://127.0.0.1:{}/", port).as_slice()).unwrap();
+    let (start_chan, start_port) = channel();
+    let network_opts = opts::default_network_opts();
+    let proxy_settings = ProxySettings::from_opts(&network_opts);
+    factory(LoadData::new(url, consumer), start_chan, network_opts, proxy_settings,
+            cookie_storage.clone(), http_cache.clone(), LoadInterceptors::new());
+    let response = start_port.recv().load_response;
+    loop {
+        match response.progress_port.recv() {
+            Payload(data) => assert!(data.is_empty()),
+            Done(result) => {
+                assert!(result.is_ok());
+                break;
+            }
+        }
+    }
+
+    cookie_storage.send(Exit);
+    http_cache.send(HttpCacheExit);
+}
+
+/// Read a request's head and body from a test client, returning its method, path and body.
+#[cfg(test)]
+fn read_test_request(stream: &mut io::net::tcp::TcpStream) -> (String, String, String) {
+    let mut head = vec!();
+    while !head.as_slice().ends_with(b"\r\n\r\n") {
+        head.push(stream.read_byte().unwrap());
+    }
+    let head = String::from_utf8(head).unwrap();
+    let mut lines = head.as_slice().lines();
+    let request_line: Vec<&str> = lines.next().unwrap().split(' ').collect();
+    let content_length = lines.filter_map(|line| {
+        let line = line.to_ascii_lower();
+        if line.as_slice().starts_with("content-length:") {
+            from_str::<uint>(line.as_slice().slice_from(15).trim())
+        } else {
+            None
+        }
+    }).next().unwrap_or(0);
+    let body = stream.read_exact(content_length).unwrap();
+    (request_line[0].to_string(), request_line[1].to_string(), String::from_utf8(body).unwrap())
+}
+
+#[test]
+fn test_redirect_method_rewriting() {
+    use cookie_storage::{new_cookie_storage_task, Exit};
+    use http_cache::new_http_cache_task;
+    use http_cache::Exit as HttpCacheExit;
+    use servo_util::opts;
+    use std::io::{Acceptor, Listener};
+    use std::io::net::tcp::TcpListener;
+
+    let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.socket_name().unwrap().port;
+    let mut acceptor = listener.listen().unwrap();
+    // Each redirect is followed by a request for the final URL.
+    spawn_named("test HTTP server", proc() {
+        for _ in range(0u, 4) {
+            let mut stream = acceptor.accept().unwrap();
+            let (method, path, body) = read_test_request(&mut stream);
+            let response = if path.as_slice() == "/final" {
+                let echo = format!("{} {}", method, body);
+                format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        echo.len(), echo)
+            } else {
+                format!("HTTP/1.1 {} Redirect\r\nLocation: http://127.0.0.1:{}/final\r\n\
+                         Content-Length: 0\r\nConnection: close\r\n\r\n",
+                        path.as_slice().slice_from(1), port)
+            };
+            stream.write_str(response.as_slice()).unwrap();
+        }
+    });
+
+    let cookie_storage = new_cookie_storage_task();
+    let http_cache = new_http_cache_task(0, None);
+    let post_after_redirect = |status: &str| -> String {
+        let (consumer, _) = channel();
+        let url = Url::parse(format!("http://127.0.0.1:{}/{}", port, status).as_slice()).unwrap();
+        let mut load_data = LoadData::new(url, consumer);
+        load_data.method = Post;
+        load_data.data = Some(b"a=b".to_vec());
+        let (start_chan, start_port) = channel();
+        let network_opts = opts::default_network_opts();
+        let proxy_settings = ProxySettings::from_opts(&network_opts);
+        load(load_data, start_chan, network_opts, proxy_settings, cookie_storage.clone(),
+             http_cache.clone(), LoadInterceptors::new());
+        let response = start_port.recv().load_response;
+        let mut body = vec!();
+        loop {
+            match response.progress_port.recv() {
+                Payload(data) => body.push_all(data.as_slice()),
+                Done(result) => {
+                    assert!(result.is_ok());
+                    break;
+                }
+            }
+        }
+        String::from_utf8(body).unwrap()
+    };
+
+    assert_eq!(post_after_redirect("303"), "GET ".to_string());
+    assert_eq!(post_after_redirect("307"), "POST a=b".to_string());
+
+    cookie_storage.send(Exit);
+    http_cache.send(HttpCacheExit);
+}
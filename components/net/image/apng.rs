@@ -0,0 +1,327 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Animated PNG support. libpng only decodes the default image, so every frame is rebuilt into a
+//! standalone PNG, decoded on its own and composited here.
+//!
+//! https://wiki.mozilla.org/APNG_Specification
+
+use image::base::{DecodedAnimation, canvas_size, clamp_frame_delay, push_frame, to_rgba};
+use png;
+
+static SIGNATURE: &'static [u8] = &[0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+
+static DISPOSE_OP_BACKGROUND: u8 = 1;
+static DISPOSE_OP_PREVIOUS: u8 = 2;
+static BLEND_OP_OVER: u8 = 1;
+
+struct Chunk<'a> {
+    kind: &'a [u8],
+    data: &'a [u8],
+}
+
+/// The frame control chunk that describes the region and timing of a frame.
+struct FrameControl {
+    width: uint,
+    height: uint,
+    x_offset: uint,
+    y_offset: uint,
+    delay: u32,
+    dispose_op: u8,
+    blend_op: u8,
+}
+
+struct Frame {
+    control: FrameControl,
+    data: Vec<Vec<u8>>,
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    (data[0] as u32 << 24) | (data[1] as u32 << 16) | (data[2] as u32 << 8) | data[3] as u32
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    (data[0] as u16 << 8) | data[1] as u16
+}
+
+fn chunks<'a>(buffer: &'a [u8]) -> Result<Vec<Chunk<'a>>, String> {
+    let mut chunks = vec!();
+    let mut position = SIGNATURE.len();
+    while position + 12 <= buffer.len() {
+        let length = read_u32(buffer.slice_from(position)) as uint;
+        if position + 12 + length > buffer.len() {
+            return Err("truncated PNG chunk".to_string());
+        }
+        let kind = buffer.slice(position + 4, position + 8);
+        chunks.push(Chunk {
+            kind: kind,
+            data: buffer.slice(position + 8, position + 8 + length),
+        });
+        if kind == b"IEND" {
+            break;
+        }
+        position += 12 + length;
+    }
+    Ok(chunks)
+}
+
+/// Returns true if the PNG has an animation control chunk before its image data.
+pub fn is_animated(buffer: &[u8]) -> bool {
+    match chunks(buffer) {
+        Ok(chunks) => {
+            chunks.iter().take_while(|chunk| chunk.kind != b"IDAT")
+                         .any(|chunk| chunk.kind == b"acTL")
+        }
+        Err(_) => false,
+    }
+}
+
+fn parse_frame_control(data: &[u8]) -> Result<FrameControl, String> {
+    if data.len() < 26 {
+        return Err("invalid fcTL chunk".to_string());
+    }
+    let delay_numerator = read_u16(data.slice_from(20)) as u32;
+    // A zero denominator means hundredths of a second.
+    let delay_denominator = match read_u16(data.slice_from(22)) as u32 {
+        0 => 100,
+        denominator => denominator,
+    };
+    Ok(FrameControl {
+        width: read_u32(data.slice_from(4)) as uint,
+        height: read_u32(data.slice_from(8)) as uint,
+        x_offset: read_u32(data.slice_from(12)) as uint,
+        y_offset: read_u32(data.slice_from(16)) as uint,
+        delay: delay_numerator * 1000 / delay_denominator,
+        dispose_op: data[24],
+        blend_op: data[25],
+    })
+}
+
+/// Decodes every frame of an animated PNG into a canvas the size of the image.
+pub fn decode(buffer: &[u8]) -> Result<DecodedAnimation, String> {
+    let chunks = try!(chunks(buffer));
+    let header = match chunks.iter().find(|chunk| chunk.kind == b"IHDR") {
+        Some(chunk) if chunk.data.len() == 13 => chunk.data,
+        _ => return Err("missing IHDR chunk".to_string()),
+    };
+    let width = read_u32(header) as uint;
+    let height = read_u32(header.slice_from(4)) as uint;
+
+    let mut loop_count = 0;
+    // Chunks such as PLTE and tRNS that every frame needs to be decoded.
+    let mut shared_chunks = vec!();
+    let mut frames: Vec<Frame> = vec!();
+    let mut seen_image_data = false;
+    for chunk in chunks.iter() {
+        if chunk.kind == b"acTL" && chunk.data.len() >= 8 {
+            loop_count = read_u32(chunk.data.slice_from(4));
+        } else if chunk.kind == b"fcTL" {
+            frames.push(Frame {
+                control: try!(parse_frame_control(chunk.data)),
+                data: vec!(),
+            });
+        } else if chunk.kind == b"IDAT" {
+            // The default image is only part of the animation if a fcTL chunk precedes it.
+            seen_image_data = true;
+            match frames.last_mut() {
+                Some(frame) => frame.data.push(chunk.data.to_vec()),
+                None => {}
+            }
+        } else if chunk.kind == b"fdAT" {
+            match frames.last_mut() {
+                Some(frame) if chunk.data.len() > 4 => {
+                    frame.data.push(chunk.data.slice_from(4).to_vec())
+                }
+                _ => {}
+            }
+        } else if !seen_image_data && chunk.kind != b"IHDR" && chunk.kind != b"acTL" {
+            shared_chunks.push(Chunk { kind: chunk.kind, data: chunk.data });
+        }
+    }
+    if frames.is_empty() {
+        return Err("APNG without frames".to_string());
+    }
+
+    let mut canvas = Vec::from_elem(try!(canvas_size(width, height)), 0u8);
+    let mut shown = vec!();
+    let mut decoded_frames = vec!();
+    for (index, frame) in frames.iter().enumerate() {
+        let control = &frame.control;
+        if control.width == 0 || control.height == 0 ||
+                control.x_offset + control.width > width ||
+                control.y_offset + control.height > height {
+            return Err("APNG frame outside of the image".to_string());
+        }
+
+        let png = build_png(header, control, shared_chunks.as_slice(), frame.data.as_slice());
+        let pixels = match png::load_png_from_memory(png.as_slice()) {
+            Ok(image) => to_rgba(image.pixels),
+            Err(err) => return Err(err),
+        };
+
+        // The first frame cannot restore a previous frame, so it clears instead.
+        let dispose_op = if index == 0 && control.dispose_op == DISPOSE_OP_PREVIOUS {
+            DISPOSE_OP_BACKGROUND
+        } else {
+            control.dispose_op
+        };
+        let previous = if dispose_op == DISPOSE_OP_PREVIOUS {
+            Some(canvas.clone())
+        } else {
+            None
+        };
+
+        for y in range(0, control.height) {
+            for x in range(0, control.width) {
+                let source = (y * control.width + x) * 4;
+                let destination = ((control.y_offset + y) * width + control.x_offset + x) * 4;
+                if control.blend_op == BLEND_OP_OVER {
+                    blend_over(canvas.slice_mut(destination, destination + 4),
+                               pixels.slice(source, source + 4));
+                } else {
+                    for i in range(0, 4) {
+                        canvas[destination + i] = pixels[source + i];
+                    }
+                }
+            }
+        }
+
+        push_frame(&mut decoded_frames, &mut shown, canvas.as_slice(), width, height,
+                   clamp_frame_delay(control.delay));
+
+        if dispose_op == DISPOSE_OP_BACKGROUND {
+            for y in range(control.y_offset, control.y_offset + control.height) {
+                for x in range(control.x_offset, control.x_offset + control.width) {
+                    let offset = (y * width + x) * 4;
+                    for i in range(0, 4) {
+                        canvas[offset + i] = 0;
+                    }
+                }
+            }
+        } else if dispose_op == DISPOSE_OP_PREVIOUS {
+            canvas = previous.unwrap();
+        }
+    }
+
+    Ok(DecodedAnimation {
+        width: width as u32,
+        height: height as u32,
+        frames: decoded_frames,
+        loop_count: loop_count,
+    })
+}
+
+/// Composites a straight RGBA pixel over another.
+pub fn blend_over(destination: &mut [u8], source: &[u8]) {
+    let source_alpha = source[3] as u32;
+    if source_alpha == 255 {
+        for i in range(0, 4) {
+            destination[i] = source[i];
+        }
+        return;
+    }
+    let destination_alpha = destination[3] as u32 * (255 - source_alpha) / 255;
+    let alpha = source_alpha + destination_alpha;
+    if alpha == 0 {
+        return;
+    }
+    for i in range(0, 3) {
+        destination[i] = ((source[i] as u32 * source_alpha +
+                           destination[i] as u32 * destination_alpha) / alpha) as u8;
+    }
+    destination[3] = alpha as u8;
+}
+
+/// Builds a standalone PNG out of the image data of one frame.
+fn build_png(header: &[u8], control: &FrameControl, shared_chunks: &[Chunk], data: &[Vec<u8>])
+             -> Vec<u8> {
+    let mut png = SIGNATURE.to_vec();
+    let mut frame_header = header.to_vec();
+    write_u32(frame_header.slice_mut(0, 4), control.width as u32);
+    write_u32(frame_header.slice_mut(4, 8), control.height as u32);
+    write_chunk(&mut png, b"IHDR", frame_header.as_slice());
+    for chunk in shared_chunks.iter() {
+        write_chunk(&mut png, chunk.kind, chunk.data);
+    }
+    for image_data in data.iter() {
+        write_chunk(&mut png, b"IDAT", image_data.as_slice());
+    }
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_u32(destination: &mut [u8], value: u32) {
+    destination[0] = (value >> 24) as u8;
+    destination[1] = (value >> 16) as u8;
+    destination[2] = (value >> 8) as u8;
+    destination[3] = value as u8;
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    let mut length = [0u8, ..4];
+    write_u32(&mut length, data.len() as u32);
+    png.push_all(&length);
+    let start = png.len();
+    png.push_all(kind);
+    png.push_all(data);
+    let mut crc = [0u8, ..4];
+    write_u32(&mut crc, crc32(png.slice_from(start)));
+    png.push_all(&crc);
+}
+
+/// The CRC that ends every PNG chunk.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in data.iter() {
+        crc ^= byte as u32;
+        for _ in range(0u, 8) {
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b"IEND"), 0xae426082);
+}
+
+#[test]
+fn test_blend_over() {
+    let mut destination = [0u8, 0, 255, 255];
+    blend_over(&mut destination, &[255, 0, 0, 0]);
+    assert_eq!(destination, [0, 0, 255, 255]);
+    blend_over(&mut destination, &[255, 0, 0, 255]);
+    assert_eq!(destination, [255, 0, 0, 255]);
+
+    let mut transparent = [0u8, 0, 0, 0];
+    blend_over(&mut transparent, &[0, 255, 0, 128]);
+    assert_eq!(transparent, [0, 255, 0, 128]);
+}
+
+#[test]
+fn test_plain_png_is_not_animated() {
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+    write_chunk(&mut png, b"IEND", &[]);
+    assert!(!is_animated(png.as_slice()));
+    assert!(decode(png.as_slice()).is_err());
+
+    let mut animated = SIGNATURE.to_vec();
+    write_chunk(&mut animated, b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+    write_chunk(&mut animated, b"acTL", &[0, 0, 0, 1, 0, 0, 0, 0]);
+    write_chunk(&mut animated, b"IEND", &[]);
+    assert!(is_animated(animated.as_slice()));
+}
+
+#[test]
+fn test_decode_oversized() {
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &[0, 1, 0, 0, 0, 1, 0, 0, 8, 6, 0, 0, 0]);
+    write_chunk(&mut png, b"acTL", &[0, 0, 0, 1, 0, 0, 0, 0]);
+    write_chunk(&mut png, b"fcTL", &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0,
+                                     0, 1, 0, 10, 0, 0]);
+    write_chunk(&mut png, b"IEND", &[]);
+    assert!(decode(png.as_slice()).is_err());
+}
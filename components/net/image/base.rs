@@ -2,13 +2,334 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use std::iter::range_step;
+use image::{apng, color, gif, ico, jpeg, progressive, webp};
+use image::color::ColorTransform;
+
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use std::cmp;
+use std::iter::{range_step, AdditiveIterator};
+use std::slice::bytes::copy_memory;
+use std::sync::Mutex;
+use std::uint;
 use stb_image::image as stb_image;
+use time;
 use png;
 
-// FIXME: Images must not be copied every frame. Instead we should atomically
-// reference count them.
-pub type Image = png::Image;
+/// Frames shown for 10ms or less are slowed down to this delay, as in other browsers.
+static DEFAULT_FRAME_DELAY: u32 = 100;
+
+/// The largest canvas, in pixels, that the decoders allocate.
+pub static MAX_IMAGE_PIXELS: uint = 1 << 25;
+
+/// A decoded image. For an animated image, `pixels` holds the first frame.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: png::PixelsByColorType,
+    pub animation: Option<Animation>,
+}
+
+/// The remaining frames of an animated image, and when to show them.
+pub struct Animation {
+    /// The frames after the first, each holding only the part that differs from the frame before.
+    pub frames: Vec<AnimationFrame>,
+    /// How long each frame is shown, in milliseconds, starting with the first frame.
+    pub delays: Vec<u32>,
+    /// How many times the animation plays, or zero if it repeats forever.
+    pub loop_count: u32,
+    /// When the animation started, in nanoseconds as returned by `time::precise_time_ns`.
+    pub start_time: u64,
+    /// The last frame put together out of the first frame and the changes after it.
+    composed: Mutex<ComposedFrame>,
+}
+
+/// The part of the canvas that changes when an animation moves on to a frame.
+pub struct AnimationFrame {
+    pub rect: Rect<u32>,
+    /// The pixels of `rect`, in the same format as `Image::pixels`.
+    pub pixels: Vec<u8>,
+}
+
+struct ComposedFrame {
+    index: uint,
+    pixels: png::PixelsByColorType,
+}
+
+impl Animation {
+    pub fn new(frames: Vec<AnimationFrame>, delays: Vec<u32>, loop_count: u32, start_time: u64,
+               first_frame: &png::PixelsByColorType)
+               -> Animation {
+        Animation {
+            frames: frames,
+            delays: delays,
+            loop_count: loop_count,
+            start_time: start_time,
+            composed: Mutex::new(ComposedFrame {
+                index: 0,
+                pixels: copy_pixels(first_frame),
+            }),
+        }
+    }
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, pixels: png::PixelsByColorType) -> Image {
+        Image {
+            width: width,
+            height: height,
+            pixels: pixels,
+            animation: None,
+        }
+    }
+
+    /// An image of the given size without any pixels, for when only the size of an image is
+    /// known. It is drawn as if it were transparent.
+    pub fn blank(width: u32, height: u32) -> Image {
+        Image::new(width, height, png::RGBA8(vec!()))
+    }
+
+    pub fn is_blank(&self) -> bool {
+        pixels_size(&self.pixels) == 0
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    /// Calls `f` with the pixels of the frame at `index`, as returned by `frame_at`. Frames are
+    /// put together from the changes since the last frame asked for, or from the first frame when
+    /// going back.
+    pub fn with_frame<R>(&self, index: uint, f: |&png::PixelsByColorType| -> R) -> R {
+        let animation = match self.animation {
+            Some(ref animation) if index > 0 => animation,
+            _ => return f(&self.pixels),
+        };
+        let mut composed = animation.composed.lock();
+        if index < composed.index {
+            composed.pixels = copy_pixels(&self.pixels);
+            composed.index = 0;
+        }
+        while composed.index < index {
+            let frame = &animation.frames[composed.index];
+            match composed.pixels {
+                png::RGBA8(ref mut canvas) => {
+                    apply_frame(canvas.as_mut_slice(), self.width, frame.rect,
+                                frame.pixels.as_slice())
+                }
+                _ => panic!("animated images are RGBA"),
+            }
+            composed.index += 1;
+        }
+        f(&composed.pixels)
+    }
+
+    /// Returns the index of the frame to show at `time`, and the time at which the frame after it
+    /// is due, or `None` if the animation has ended.
+    pub fn frame_at(&self, time: u64) -> (uint, Option<u64>) {
+        let animation = match self.animation {
+            Some(ref animation) => animation,
+            None => return (0, None),
+        };
+        let duration = animation.delays.iter().map(|&delay| delay as u64 * 1000000).sum();
+        if duration == 0 {
+            return (0, None);
+        }
+
+        let elapsed = if time > animation.start_time { time - animation.start_time } else { 0 };
+        if animation.loop_count != 0 && elapsed / duration >= animation.loop_count as u64 {
+            return (animation.delays.len() - 1, None);
+        }
+        let loop_start = time - elapsed % duration;
+        let mut frame_end = loop_start;
+        for (index, &delay) in animation.delays.iter().enumerate() {
+            frame_end += delay as u64 * 1000000;
+            if time < frame_end {
+                return (index, Some(frame_end));
+            }
+        }
+        (0, None)
+    }
+
+    /// The memory taken by the pixels of every frame, including the one put together for
+    /// drawing.
+    pub fn size_of_pixels(&self) -> uint {
+        let frames = match self.animation {
+            Some(ref animation) => {
+                animation.frames.iter().map(|frame| frame.pixels.len()).sum() +
+                    pixels_size(&self.pixels)
+            }
+            None => 0,
+        };
+        pixels_size(&self.pixels) + frames
+    }
+
+    /// Builds an image out of frames in straight RGBA, converting them as `load_from_memory`
+    /// converts still images.
+    fn from_animation(decoded: DecodedAnimation) -> Image {
+        let DecodedAnimation { width, height, frames, loop_count } = decoded;
+        let delays = frames.iter().map(|frame| frame.delay).collect();
+        let mut frames: Vec<AnimationFrame> = frames.into_iter().map(|frame| {
+            let mut pixels = frame.pixels;
+            byte_swap_and_premultiply(pixels.as_mut_slice());
+            AnimationFrame {
+                rect: frame.rect,
+                pixels: pixels,
+            }
+        }).collect();
+
+        let first_frame = png::RGBA8(frames.remove(0).unwrap().pixels);
+        let animation = if frames.is_empty() {
+            None
+        } else {
+            Some(Animation::new(frames, delays, loop_count, time::precise_time_ns(), &first_frame))
+        };
+        Image {
+            width: width,
+            height: height,
+            pixels: first_frame,
+            animation: animation,
+        }
+    }
+}
+
+fn copy_pixels(pixels: &png::PixelsByColorType) -> png::PixelsByColorType {
+    match *pixels {
+        png::K8(ref data) => png::K8(data.clone()),
+        png::KA8(ref data) => png::KA8(data.clone()),
+        png::RGB8(ref data) => png::RGB8(data.clone()),
+        png::RGBA8(ref data) => png::RGBA8(data.clone()),
+    }
+}
+
+fn pixels_size(pixels: &png::PixelsByColorType) -> uint {
+    match *pixels {
+        png::K8(ref data) | png::KA8(ref data) | png::RGB8(ref data) | png::RGBA8(ref data) => {
+            data.len()
+        }
+    }
+}
+
+/// Copies `pixels`, which cover `rect`, into `canvas`, a frame of an image `width` pixels wide.
+fn apply_frame(canvas: &mut [u8], width: u32, rect: Rect<u32>, pixels: &[u8]) {
+    let Rect(Point2D(left, top), Size2D(frame_width, frame_height)) = rect;
+    let row_length = frame_width as uint * 4;
+    for y in range(0, frame_height as uint) {
+        let offset = ((top as uint + y) * width as uint + left as uint) * 4;
+        copy_memory(canvas.slice_mut(offset, offset + row_length),
+                    pixels.slice(y * row_length, (y + 1) * row_length));
+    }
+}
+
+/// A frame produced by one of the decoders, in straight RGBA. The first frame covers the whole
+/// canvas; the others cover only `rect`, the part that differs from the frame before.
+pub struct DecodedFrame {
+    pub rect: Rect<u32>,
+    pub pixels: Vec<u8>,
+    /// In milliseconds.
+    pub delay: u32,
+}
+
+impl DecodedFrame {
+    /// A frame covering the whole of a `width` by `height` canvas.
+    pub fn whole(width: u32, height: u32, pixels: Vec<u8>, delay: u32) -> DecodedFrame {
+        DecodedFrame {
+            rect: Rect(Point2D(0, 0), Size2D(width, height)),
+            pixels: pixels,
+            delay: delay,
+        }
+    }
+
+    /// A frame holding the part of `canvas` that differs from `previous`, the canvas as the frame
+    /// before left it. Both are `width` pixels wide.
+    pub fn changed(previous: &[u8], canvas: &[u8], width: u32, delay: u32) -> DecodedFrame {
+        let width = width as uint;
+        let (mut left, mut top, mut right, mut bottom) = (width, uint::MAX, 0, 0);
+        for (i, (old, new)) in previous.chunks(4).zip(canvas.chunks(4)).enumerate() {
+            if old != new {
+                let (x, y) = (i % width, i / width);
+                left = cmp::min(left, x);
+                right = cmp::max(right, x + 1);
+                top = cmp::min(top, y);
+                bottom = y + 1;
+            }
+        }
+        if right == 0 {
+            return DecodedFrame {
+                rect: Rect(Point2D(0, 0), Size2D(0, 0)),
+                pixels: vec!(),
+                delay: delay,
+            }
+        }
+
+        let mut pixels = Vec::with_capacity((right - left) * (bottom - top) * 4);
+        for y in range(top, bottom) {
+            pixels.push_all(canvas.slice((y * width + left) * 4, (y * width + right) * 4));
+        }
+        DecodedFrame {
+            rect: Rect(Point2D(left as u32, top as u32),
+                       Size2D((right - left) as u32, (bottom - top) as u32)),
+            pixels: pixels,
+            delay: delay,
+        }
+    }
+}
+
+/// Every frame of an image, as produced by the decoders. Still images have a single frame.
+pub struct DecodedAnimation {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<DecodedFrame>,
+    pub loop_count: u32,
+}
+
+pub fn clamp_frame_delay(delay: u32) -> u32 {
+    if delay <= 10 { DEFAULT_FRAME_DELAY } else { delay }
+}
+
+/// Adds a frame showing `canvas`, an image `width` by `height` pixels, to `frames`. `shown` holds
+/// the canvas as the frame before showed it, and is updated to this one.
+pub fn push_frame(frames: &mut Vec<DecodedFrame>, shown: &mut Vec<u8>, canvas: &[u8],
+                  width: uint, height: uint, delay: u32) {
+    let frame = if frames.is_empty() {
+        DecodedFrame::whole(width as u32, height as u32, canvas.to_vec(), delay)
+    } else {
+        DecodedFrame::changed(shown.as_slice(), canvas, width as u32, delay)
+    };
+    frames.push(frame);
+    shown.clear();
+    shown.push_all(canvas);
+}
+
+/// Returns the number of bytes an RGBA canvas of `width` by `height` pixels takes, or an error if
+/// it has more than `MAX_IMAGE_PIXELS` pixels.
+pub fn canvas_size(width: uint, height: uint) -> Result<uint, String> {
+    match width.checked_mul(&height) {
+        Some(pixels) if pixels <= MAX_IMAGE_PIXELS => Ok(pixels * 4),
+        _ => Err(format!("a {}x{} image is too large", width, height)),
+    }
+}
+
+/// Converts the pixels libpng produced into straight RGBA.
+pub fn to_rgba(pixels: png::PixelsByColorType) -> Vec<u8> {
+    match pixels {
+        png::RGBA8(data) => data,
+        png::RGB8(data) => {
+            data.as_slice().chunks(3).flat_map(|pixel| {
+                vec!(pixel[0], pixel[1], pixel[2], 255).into_iter()
+            }).collect()
+        }
+        png::KA8(data) => {
+            data.as_slice().chunks(2).flat_map(|pixel| {
+                vec!(pixel[0], pixel[0], pixel[0], pixel[1]).into_iter()
+            }).collect()
+        }
+        png::K8(data) => {
+            data.iter().flat_map(|&gray| vec!(gray, gray, gray, 255).into_iter()).collect()
+        }
+    }
+}
 
 
 static TEST_IMAGE: &'static [u8] = include_bin!("test.jpeg");
@@ -18,7 +339,7 @@ pub fn test_image_bin() -> Vec<u8> {
 }
 
 // TODO(pcwalton): Speed up with SIMD, or better yet, find some way to not do this.
-fn byte_swap(data: &mut [u8]) {
+pub fn byte_swap(data: &mut [u8]) {
     let length = data.len();
     for i in range_step(0, length, 4) {
         let r = data[i + 2];
@@ -28,7 +349,7 @@ fn byte_swap(data: &mut [u8]) {
 }
 
 // TODO(pcwalton): Speed up with SIMD, or better yet, find some way to not do this.
-fn byte_swap_and_premultiply(data: &mut [u8]) {
+pub fn byte_swap_and_premultiply(data: &mut [u8]) {
     let length = data.len();
     for i in range_step(0, length, 4) {
         let r = data[i + 2];
@@ -46,17 +367,63 @@ pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
         return None;
     }
 
+    if png::is_png(buffer) && apng::is_animated(buffer) {
+        match apng::decode(buffer) {
+            Ok(mut animation) => {
+                match ColorTransform::from_png(buffer) {
+                    Some(transform) => {
+                        for frame in animation.frames.iter_mut() {
+                            transform.apply(frame.pixels.as_mut_slice());
+                        }
+                    }
+                    None => {}
+                }
+                return Some(Image::from_animation(animation));
+            }
+            // Fall back to the default image.
+            Err(e) => error!("APNG decoding failed: {}", e),
+        }
+    } else if gif::is_gif(buffer) {
+        match gif::decode(buffer) {
+            Ok(animation) => return Some(Image::from_animation(animation)),
+            Err(e) => error!("GIF decoding failed: {}", e),
+        }
+    } else if webp::is_webp(buffer) {
+        match webp::decode(buffer) {
+            Ok(image) => return Some(Image::from_animation(image)),
+            Err(e) => error!("WebP decoding failed: {}", e),
+        }
+    } else if ico::is_ico(buffer) {
+        match ico::decode(buffer) {
+            Ok(image) => return Some(Image::from_animation(image)),
+            Err(e) => error!("ICO decoding failed: {}", e),
+        }
+    }
+
     if png::is_png(buffer) {
-        match png::load_png_from_memory(buffer) {
-            Ok(mut png_image) => {
-                match png_image.pixels {
+        let png_image = match progressive::load_16_bit_png(buffer) {
+            Some(png_image) => Ok(png_image),
+            None => png::load_png_from_memory(buffer),
+        };
+        match png_image {
+            Ok(png_image) => {
+                let png::Image { width, height, mut pixels } = png_image;
+                match ColorTransform::from_png(buffer) {
+                    Some(transform) => {
+                        let mut rgba = to_rgba(pixels);
+                        transform.apply(rgba.as_mut_slice());
+                        pixels = png::RGBA8(rgba);
+                    }
+                    None => {}
+                }
+                match pixels {
                     png::RGB8(ref mut data) => byte_swap(data.as_mut_slice()),
                     png::RGBA8(ref mut data) => {
                         byte_swap_and_premultiply(data.as_mut_slice())
                     }
                     _ => {}
                 }
-                Some(png_image)
+                Some(Image::new(width, height, pixels))
             }
             Err(_err) => None,
         }
@@ -65,19 +432,24 @@ pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
         // Can't remember why we do this. Maybe it's what cairo wants
         static FORCE_DEPTH: uint = 4;
 
-        match stb_image::load_from_memory_with_depth(buffer, FORCE_DEPTH, true) {
-            stb_image::ImageU8(mut image) => {
+        match stb_image::load_from_memory_with_depth(buffer, FORCE_DEPTH, false) {
+            stb_image::ImageU8(image) => {
                 assert!(image.depth == 4);
-                byte_swap(image.data.as_mut_slice());
-                Some(png::Image {
-                    width: image.width as u32,
-                    height: image.height as u32,
-                    pixels: png::RGBA8(image.data)
-                })
+                Some(convert_stb_image(buffer, image))
             }
-            stb_image::ImageF32(_image) => {
-                error!("HDR images not implemented");
-                None
+            stb_image::ImageF32(image) => {
+                assert!(image.depth == 4);
+                // HDR images hold linear values, which are clipped to what sRGB can show. They
+                // have no alpha channel.
+                let mut pixels = Vec::with_capacity(image.data.len());
+                for pixel in image.data.as_slice().chunks(4) {
+                    for &value in pixel.slice_to(3).iter() {
+                        pixels.push(color::linear_to_srgb(value));
+                    }
+                    pixels.push(255);
+                }
+                byte_swap(pixels.as_mut_slice());
+                Some(Image::new(image.width as u32, image.height as u32, png::RGBA8(pixels)))
             }
             stb_image::Error(e) => {
                 error!("stb_image failed: {}", e);
@@ -86,3 +458,142 @@ pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
         }
     }
 }
+
+/// Converts an image that stb_image decoded to RGBA into BGRA. A JPEG is also converted to sRGB
+/// and turned as its EXIF orientation says.
+pub fn convert_stb_image(buffer: &[u8], image: stb_image::Image<u8>) -> Image {
+    let stb_image::Image { width, height, data: mut pixels, .. } = image;
+    match jpeg::color_transform(buffer) {
+        Some(transform) => transform.apply(pixels.as_mut_slice()),
+        None => {}
+    }
+    let (width, height, mut pixels) = jpeg::orient(jpeg::orientation(buffer), width, height,
+                                                   pixels);
+    byte_swap(pixels.as_mut_slice());
+    Image::new(width as u32, height as u32, png::RGBA8(pixels))
+}
+
+#[test]
+fn test_frame_at() {
+    let mut image = Image::new(1, 1, png::RGBA8(vec!(0, 0, 0, 255)));
+    assert_eq!(image.frame_at(0), (0, None));
+
+    let frame = AnimationFrame {
+        rect: Rect(Point2D(0, 0), Size2D(1, 1)),
+        pixels: vec!(255, 255, 255, 255),
+    };
+    image.animation = Some(Animation::new(vec!(frame), vec!(100, 50), 2, 1000, &image.pixels));
+    assert_eq!(image.frame_at(1000), (0, Some(100001000)));
+    assert_eq!(image.frame_at(120001000), (1, Some(150001000)));
+    assert_eq!(image.frame_at(160001000), (0, Some(250001000)));
+    // The animation stops on its last frame once it has played twice.
+    assert_eq!(image.frame_at(300001000), (1, None));
+}
+
+#[test]
+fn test_with_frame() {
+    let mut image = Image::new(2, 1, png::RGBA8(vec!(0, 0, 0, 255, 0, 0, 0, 255)));
+    let frames = vec!(
+        AnimationFrame {
+            rect: Rect(Point2D(1, 0), Size2D(1, 1)),
+            pixels: vec!(1, 1, 1, 255),
+        },
+        AnimationFrame {
+            rect: Rect(Point2D(0, 0), Size2D(1, 1)),
+            pixels: vec!(2, 2, 2, 255),
+        },
+        AnimationFrame {
+            rect: Rect(Point2D(0, 0), Size2D(0, 0)),
+            pixels: vec!(),
+        });
+    image.animation = Some(Animation::new(frames, vec!(10, 10, 10, 10), 0, 0, &image.pixels));
+
+    let pixels = |index| {
+        image.with_frame(index, |pixels| {
+            match *pixels {
+                png::RGBA8(ref pixels) => pixels.clone(),
+                _ => panic!("expected RGBA pixels"),
+            }
+        })
+    };
+    assert_eq!(pixels(2), vec!(2, 2, 2, 255, 1, 1, 1, 255));
+    assert_eq!(pixels(3), vec!(2, 2, 2, 255, 1, 1, 1, 255));
+    // Going back puts the frame together again from the first one.
+    assert_eq!(pixels(1), vec!(0, 0, 0, 255, 1, 1, 1, 255));
+    assert_eq!(pixels(0), vec!(0, 0, 0, 255, 0, 0, 0, 255));
+}
+
+#[test]
+fn test_changed_frame() {
+    let previous = vec!(0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0);
+    let canvas = vec!(0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9, 9, 9, 9);
+    let frame = DecodedFrame::changed(previous.as_slice(), canvas.as_slice(), 2, 100);
+    assert_eq!(frame.rect, Rect(Point2D(1, 1), Size2D(1, 1)));
+    assert_eq!(frame.pixels, vec!(9, 9, 9, 9));
+
+    let frame = DecodedFrame::changed(previous.as_slice(), previous.as_slice(), 2, 100);
+    assert_eq!(frame.rect.size, Size2D(0, 0));
+    assert!(frame.pixels.is_empty());
+}
+
+/// Puts together every frame of a decoded animation, for comparing whole frames.
+#[cfg(test)]
+pub fn compose_frames(animation: &DecodedAnimation) -> Vec<Vec<u8>> {
+    let mut canvas = animation.frames[0].pixels.clone();
+    animation.frames.iter().map(|frame| {
+        apply_frame(canvas.as_mut_slice(), animation.width, frame.rect, frame.pixels.as_slice());
+        canvas.clone()
+    }).collect()
+}
+
+/// A hash of decoded pixels, for comparing them against a reference decoder's.
+#[cfg(test)]
+pub fn checksum(pixels: &[u8]) -> u32 {
+    // FNV-1a.
+    pixels.iter().fold(2166136261, |hash, &byte| (hash ^ byte as u32) * 16777619)
+}
+
+/// Checks that a decoder rejects or decodes, without failing, every truncation of `data` and
+/// copies of it with a few bytes changed.
+#[cfg(test)]
+pub fn decode_corrupted_copies(data: &[u8],
+                               decode: fn(&[u8]) -> Result<DecodedAnimation, String>) {
+    for length in range(0, data.len()) {
+        let _ = decode(data.slice_to(length));
+    }
+    let mut seed = 1u32;
+    for _ in range(0u, 1000) {
+        let mut copy = data.to_vec();
+        for _ in range(0u, 3) {
+            seed = seed * 1103515245 + 12345;
+            let position = (seed >> 8) as uint % copy.len();
+            copy[position] = (seed >> 24) as u8;
+        }
+        let _ = decode(copy.as_slice());
+    }
+}
+
+#[test]
+fn test_load_webp() {
+    // WebP images are converted to premultiplied BGRA like the others.
+    let image = load_from_memory(include_bin!("corpus/lossy-alpha.webp")).unwrap();
+    match image.pixels {
+        png::RGBA8(ref pixels) => assert_eq!(pixels.slice_to(4).to_vec(), vec!(125, 125, 121, 131)),
+        _ => panic!("expected RGBA pixels"),
+    }
+}
+
+#[test]
+fn test_jpeg_orientation() {
+    let data = test_image_bin();
+    let upright = load_from_memory(data.as_slice()).unwrap();
+
+    // An EXIF segment that turns the image a quarter turn clockwise, which takes precedence over
+    // the one the image already has.
+    let mut turned = data.slice_to(2).to_vec();
+    turned.push_all(&[0xff, 0xe1, 0, 34]);
+    turned.push_all(b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0");
+    turned.push_all(data.slice_from(2));
+    let turned = load_from_memory(turned.as_slice()).unwrap();
+    assert_eq!((turned.width, turned.height), (upright.height, upright.width));
+}
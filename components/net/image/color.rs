@@ -0,0 +1,539 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Conversion of images that carry a color profile or a gamma to sRGB, which is what the rest of
+//! the engine assumes every image is in. Only ICC profiles made of tone curves and a matrix are
+//! supported, as in other browsers; images with any other profile are left as they are.
+//!
+//! http://www.color.org/specification/ICC1v43_2010-12.pdf
+
+use inflate::{Inflater, ZlibFormat};
+
+macro_rules! try_opt(
+    ($e:expr) => (match $e { Some(value) => value, None => return None })
+)
+
+/// A 3x3 matrix, stored by rows.
+type Matrix = [[f32, ..3], ..3];
+
+/// The colorants of sRGB, adapted to D50, the white point of the profile connection space.
+static SRGB_TO_XYZ: Matrix = [
+    [0.4360747, 0.3850649, 0.1430804],
+    [0.2225045, 0.7168786, 0.0606169],
+    [0.0139322, 0.0971045, 0.7141733],
+];
+
+static D50: [f32, ..3] = [0.9642, 1.0, 0.8249];
+
+/// The cone response matrix of the Bradford chromatic adaptation.
+static BRADFORD: Matrix = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+/// The number of entries of the table that encodes linear values as sRGB.
+static ENCODING_TABLE_SIZE: uint = 16384;
+
+/// The size of the header of an ICC profile and of the tag count that follows it.
+static PROFILE_HEADER_SIZE: uint = 132;
+
+/// A tone curve, which maps a device value between 0 and 1 to a linear one.
+#[deriving(Clone)]
+enum Curve {
+    Gamma(f32),
+    /// Values at evenly spaced points, between which the curve is interpolated.
+    Table(Vec<f32>),
+    /// The parameters g, a, b, c, d, e and f of the function that is (aX + b)^g + e from d
+    /// upwards and cX + f below d.
+    Parametric([f32, ..7]),
+}
+
+impl Curve {
+    fn evaluate(&self, x: f32) -> f32 {
+        match *self {
+            Gamma(gamma) => x.powf(gamma),
+            Table(ref table) => {
+                let position = x * (table.len() - 1) as f32;
+                let index = position as uint;
+                if index + 1 >= table.len() {
+                    return table[table.len() - 1];
+                }
+                let fraction = position - index as f32;
+                table[index] + (table[index + 1] - table[index]) * fraction
+            }
+            Parametric(parameters) => {
+                if x >= parameters[4] {
+                    let base = parameters[1] * x + parameters[2];
+                    let power = if base > 0.0 { base.powf(parameters[0]) } else { 0.0 };
+                    power + parameters[5]
+                } else {
+                    parameters[3] * x + parameters[6]
+                }
+            }
+        }
+    }
+}
+
+/// A conversion of 8-bit colors to sRGB.
+pub struct ColorTransform {
+    /// The linear value of each of the 256 values of red, green and blue in turn.
+    linear: Vec<f32>,
+    /// Converts linear colors to linear sRGB.
+    matrix: Matrix,
+    encoding: Vec<u8>,
+}
+
+impl ColorTransform {
+    /// Returns the transform for the profile or gamma of a PNG, or `None` if the image is in
+    /// sRGB or in an unsupported profile. A profile takes precedence over a gamma and primaries,
+    /// which only older encoders write on their own.
+    pub fn from_png(buffer: &[u8]) -> Option<ColorTransform> {
+        let mut gamma = None;
+        let mut chromaticities = None;
+        let mut profile = None;
+
+        let mut position = 8;
+        while position + 8 <= buffer.len() {
+            let length = read_u32(buffer.slice_from(position)) as uint;
+            let kind = buffer.slice(position + 4, position + 8);
+            let start = position + 8;
+            if kind == b"IDAT" || length > buffer.len() - start {
+                break;
+            }
+            let data = buffer.slice(start, start + length);
+            if kind == b"sRGB" {
+                return None;
+            } else if kind == b"iCCP" {
+                profile = Some(data);
+            } else if kind == b"gAMA" && length == 4 {
+                gamma = Some(read_u32(data));
+            } else if kind == b"cHRM" && length == 32 {
+                chromaticities = Some(data);
+            }
+            position = start + length + 4;
+        }
+
+        match profile {
+            // The profile name is followed by a zero byte and the compression method.
+            Some(data) => {
+                let name_end = try_opt!(data.iter().take(80).position(|&byte| byte == 0));
+                if name_end + 2 > data.len() || data[name_end + 1] != 0 {
+                    return None;
+                }
+                let mut inflater = Inflater::new(ZlibFormat);
+                let profile = inflater.push(data.slice_from(name_end + 2));
+                if !inflater.is_finished() {
+                    return None;
+                }
+                return ColorTransform::from_icc_profile(profile.as_slice());
+            }
+            None => {}
+        }
+
+        // The gamma is the exponent that encoded linear values, in units of 1/100000.
+        let gamma = match gamma {
+            Some(gamma) if gamma > 0 => 100000.0 / gamma as f32,
+            _ => return None,
+        };
+        let matrix = match chromaticities {
+            Some(data) => {
+                let point = |index: uint| -> (f32, f32) {
+                    (read_u32(data.slice_from(index * 8)) as f32 / 100000.0,
+                     read_u32(data.slice_from(index * 8 + 4)) as f32 / 100000.0)
+                };
+                try_opt!(matrix_from_chromaticities(point(0), [point(1), point(2), point(3)]))
+            }
+            None => SRGB_TO_XYZ,
+        };
+        let curve = Gamma(gamma);
+        ColorTransform::new([curve.clone(), curve.clone(), curve], &matrix)
+    }
+
+    /// Returns the transform for an RGB or grayscale ICC profile, or `None` if the profile is
+    /// unsupported or is sRGB.
+    pub fn from_icc_profile(profile: &[u8]) -> Option<ColorTransform> {
+        if profile.len() < PROFILE_HEADER_SIZE || profile.slice(36, 40) != b"acsp" ||
+                profile.slice(20, 24) != b"XYZ " {
+            return None;
+        }
+
+        let color_space = profile.slice(16, 20);
+        if color_space == b"RGB " {
+            let red = try_opt!(find_tag(profile, b"rTRC").and_then(parse_curve));
+            let green = try_opt!(find_tag(profile, b"gTRC").and_then(parse_curve));
+            let blue = try_opt!(find_tag(profile, b"bTRC").and_then(parse_curve));
+            let mut matrix = [[0.0, ..3], ..3];
+            for (column, tag) in [b"rXYZ", b"gXYZ", b"bXYZ"].iter().enumerate() {
+                let colorant = try_opt!(find_tag(profile, *tag).and_then(parse_xyz));
+                for row in range(0, 3) {
+                    matrix[row][column] = colorant[row];
+                }
+            }
+            ColorTransform::new([red, green, blue], &matrix)
+        } else if color_space == b"GRAY" {
+            // Gray maps to the white point of the profile connection space, which sRGB white
+            // also maps to.
+            let curve = try_opt!(find_tag(profile, b"kTRC").and_then(parse_curve));
+            ColorTransform::new([curve.clone(), curve.clone(), curve], &SRGB_TO_XYZ)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the transform from colors with the given tone curves and conversion to XYZ, or
+    /// `None` if the colors are already sRGB, as far as 8 bits can tell.
+    fn new(curves: [Curve, ..3], to_xyz: &Matrix) -> Option<ColorTransform> {
+        let mut linear = Vec::with_capacity(3 * 256);
+        for curve in curves.iter() {
+            for value in range(0u, 256) {
+                linear.push(curve.evaluate(value as f32 / 255.0));
+            }
+        }
+        let encoding = range(0, ENCODING_TABLE_SIZE).map(|index| {
+            linear_to_srgb(index as f32 / (ENCODING_TABLE_SIZE - 1) as f32)
+        }).collect();
+
+        let transform = ColorTransform {
+            linear: linear,
+            matrix: multiply(&try_opt!(invert(&SRGB_TO_XYZ)), to_xyz),
+            encoding: encoding,
+        };
+        if transform.is_identity() {
+            None
+        } else {
+            Some(transform)
+        }
+    }
+
+    /// Converts straight RGBA pixels to sRGB.
+    pub fn apply(&self, pixels: &mut [u8]) {
+        for pixel in pixels.chunks_mut(4) {
+            let color = self.convert(pixel[0], pixel[1], pixel[2]);
+            for i in range(0, 3) {
+                pixel[i] = color[i];
+            }
+        }
+    }
+
+    fn convert(&self, red: u8, green: u8, blue: u8) -> [u8, ..3] {
+        let linear = [
+            self.linear[red as uint],
+            self.linear[256 + green as uint],
+            self.linear[512 + blue as uint],
+        ];
+        let mut color = [0, ..3];
+        for row in range(0, 3) {
+            let value = self.matrix[row][0] * linear[0] + self.matrix[row][1] * linear[1] +
+                self.matrix[row][2] * linear[2];
+            // This also catches NaN.
+            color[row] = if !(value > 0.0) {
+                0
+            } else if value >= 1.0 {
+                255
+            } else {
+                self.encoding[(value * (ENCODING_TABLE_SIZE - 1) as f32 + 0.5) as uint]
+            };
+        }
+        color
+    }
+
+    /// Returns true if the transform leaves every primary and gray as it is.
+    fn is_identity(&self) -> bool {
+        range(0u, 256).all(|value| {
+            let value = value as u8;
+            self.convert(value, 0, 0) == [value, 0, 0] &&
+                self.convert(0, value, 0) == [0, value, 0] &&
+                self.convert(0, 0, value) == [0, 0, value] &&
+                self.convert(value, value, value) == [value, value, value]
+        })
+    }
+}
+
+/// Encodes a linear value with the sRGB transfer function, clipping it to the range of 8 bits.
+pub fn linear_to_srgb(value: f32) -> u8 {
+    if !(value > 0.0) {
+        return 0;
+    }
+    if value >= 1.0 {
+        return 255;
+    }
+    let encoded = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0 + 0.5) as u8
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    (data[0] as u32 << 24) | (data[1] as u32 << 16) | (data[2] as u32 << 8) | data[3] as u32
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    (data[0] as u16 << 8) | data[1] as u16
+}
+
+fn read_s15_fixed16(data: &[u8]) -> f32 {
+    read_u32(data) as i32 as f32 / 65536.0
+}
+
+/// Returns the data of the tag of a profile with the given signature.
+fn find_tag<'a>(profile: &'a [u8], signature: &[u8]) -> Option<&'a [u8]> {
+    let count = read_u32(profile.slice_from(128)) as uint;
+    for index in range(0, count) {
+        let entry = PROFILE_HEADER_SIZE + index * 12;
+        if entry + 12 > profile.len() {
+            return None;
+        }
+        if profile.slice(entry, entry + 4) == signature {
+            let offset = read_u32(profile.slice_from(entry + 4)) as uint;
+            let size = read_u32(profile.slice_from(entry + 8)) as uint;
+            if offset > profile.len() || size > profile.len() - offset {
+                return None;
+            }
+            return Some(profile.slice(offset, offset + size));
+        }
+    }
+    None
+}
+
+fn parse_xyz(data: &[u8]) -> Option<[f32, ..3]> {
+    if data.len() < 20 || data.slice_to(4) != b"XYZ " {
+        return None;
+    }
+    Some([read_s15_fixed16(data.slice_from(8)),
+          read_s15_fixed16(data.slice_from(12)),
+          read_s15_fixed16(data.slice_from(16))])
+}
+
+fn parse_curve(data: &[u8]) -> Option<Curve> {
+    if data.len() < 12 {
+        return None;
+    }
+    if data.slice_to(4) == b"curv" {
+        let count = read_u32(data.slice_from(8)) as uint;
+        if count > (data.len() - 12) / 2 {
+            return None;
+        }
+        return Some(match count {
+            0 => Gamma(1.0),
+            // A single entry is a gamma, with 8 fractional bits.
+            1 => Gamma(read_u16(data.slice_from(12)) as f32 / 256.0),
+            _ => {
+                Table(range(0, count).map(|index| {
+                    read_u16(data.slice_from(12 + index * 2)) as f32 / 65535.0
+                }).collect())
+            }
+        });
+    }
+    if data.slice_to(4) == b"para" {
+        let function = read_u16(data.slice_from(8));
+        let count = match function {
+            0 => 1,
+            1 => 3,
+            2 => 4,
+            3 => 5,
+            4 => 7,
+            _ => return None,
+        };
+        if data.len() < 12 + count * 4 {
+            return None;
+        }
+        let value = |index: uint| read_s15_fixed16(data.slice_from(12 + index * 4));
+        let gamma = value(0);
+        let parameters = match function {
+            0 => [gamma, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            // Below the point where aX + b reaches zero, the curve is zero, or c for the second
+            // function.
+            1 | 2 => {
+                let (a, b) = (value(1), value(2));
+                if a == 0.0 {
+                    return None;
+                }
+                let c = if function == 2 { value(3) } else { 0.0 };
+                [gamma, a, b, 0.0, -b / a, c, c]
+            }
+            3 => [gamma, value(1), value(2), value(3), value(4), 0.0, 0.0],
+            _ => [gamma, value(1), value(2), value(3), value(4), value(5), value(6)],
+        };
+        return Some(Parametric(parameters));
+    }
+    None
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [[0.0, ..3], ..3];
+    for row in range(0u, 3) {
+        for column in range(0u, 3) {
+            for i in range(0u, 3) {
+                product[row][column] += a[row][i] * b[i][column];
+            }
+        }
+    }
+    product
+}
+
+fn multiply_vector(matrix: &Matrix, vector: [f32, ..3]) -> [f32, ..3] {
+    let mut product = [0.0, ..3];
+    for row in range(0u, 3) {
+        for i in range(0u, 3) {
+            product[row] += matrix[row][i] * vector[i];
+        }
+    }
+    product
+}
+
+fn invert(matrix: &Matrix) -> Option<Matrix> {
+    // Taking the rows and columns cyclically gives each cofactor its sign.
+    let cofactor = |row: uint, column: uint| -> f32 {
+        let (row_0, row_1) = ((row + 1) % 3, (row + 2) % 3);
+        let (column_0, column_1) = ((column + 1) % 3, (column + 2) % 3);
+        matrix[row_0][column_0] * matrix[row_1][column_1] -
+            matrix[row_0][column_1] * matrix[row_1][column_0]
+    };
+    let determinant = range(0u, 3).fold(0.0, |sum, column| {
+        sum + matrix[0][column] * cofactor(0, column)
+    });
+    if determinant.abs() < 1e-9 {
+        return None;
+    }
+    let mut inverse = [[0.0, ..3], ..3];
+    for row in range(0u, 3) {
+        for column in range(0u, 3) {
+            inverse[row][column] = cofactor(column, row) / determinant;
+        }
+    }
+    Some(inverse)
+}
+
+/// Returns the matrix that converts colors with the given white point and red, green and blue
+/// primaries to XYZ relative to D50.
+fn matrix_from_chromaticities(white: (f32, f32), primaries: [(f32, f32), ..3]) -> Option<Matrix> {
+    let to_xyz = |point: (f32, f32)| -> Option<[f32, ..3]> {
+        let (x, y) = point;
+        if y > 0.0 { Some([x / y, 1.0, (1.0 - x - y) / y]) } else { None }
+    };
+    let mut matrix = [[0.0, ..3], ..3];
+    for column in range(0u, 3) {
+        let primary = try_opt!(to_xyz(primaries[column]));
+        for row in range(0u, 3) {
+            matrix[row][column] = primary[row];
+        }
+    }
+    // Scale the primaries so that together they make the white point.
+    let white = try_opt!(to_xyz(white));
+    let scale = multiply_vector(&try_opt!(invert(&matrix)), white);
+    for row in range(0u, 3) {
+        for column in range(0u, 3) {
+            matrix[row][column] *= scale[column];
+        }
+    }
+
+    // Adapt the white point to D50 in the Bradford cone response domain.
+    let cone_white = multiply_vector(&BRADFORD, white);
+    let cone_d50 = multiply_vector(&BRADFORD, D50);
+    let mut cone_scale = [[0.0, ..3], ..3];
+    for i in range(0u, 3) {
+        if cone_white[i] == 0.0 {
+            return None;
+        }
+        cone_scale[i][i] = cone_d50[i] / cone_white[i];
+    }
+    let adaptation = multiply(&try_opt!(invert(&BRADFORD)), &multiply(&cone_scale, &BRADFORD));
+    Some(multiply(&adaptation, &matrix))
+}
+
+/// A PNG with the given chunks before its image data, which is left out.
+#[cfg(test)]
+fn test_png(chunks: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let mut png = vec!(0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a);
+    for &(kind, data) in chunks.iter() {
+        png.push_all(&[0, 0, 0, data.len() as u8]);
+        png.push_all(kind);
+        png.push_all(data);
+        // The checksum is not verified.
+        png.push_all(&[0, 0, 0, 0]);
+    }
+    png
+}
+
+/// An RGB profile with sRGB colorants and the same tone curve for every channel.
+#[cfg(test)]
+fn test_profile(curve: &[u8]) -> Vec<u8> {
+    let mut profile = Vec::from_elem(16, 0u8);
+    profile.push_all(b"RGB XYZ ");
+    profile.grow(12, 0);
+    profile.push_all(b"acsp");
+    profile.grow(88, 0);
+    // The number of tags.
+    profile.push_all(&[0, 0, 0, 6]);
+
+    let colorants_offset = PROFILE_HEADER_SIZE + 6 * 12;
+    let curve_offset = colorants_offset + 3 * 20;
+    for (index, &tag) in [b"rXYZ", b"gXYZ", b"bXYZ"].iter().enumerate() {
+        let offset = colorants_offset + index * 20;
+        profile.push_all(tag);
+        profile.push_all(&[0, 0, (offset >> 8) as u8, offset as u8, 0, 0, 0, 20]);
+    }
+    for &tag in [b"rTRC", b"gTRC", b"bTRC"].iter() {
+        profile.push_all(tag);
+        profile.push_all(&[0, 0, (curve_offset >> 8) as u8, curve_offset as u8,
+                           0, 0, 0, curve.len() as u8]);
+    }
+    for column in range(0u, 3) {
+        profile.push_all(b"XYZ \0\0\0\0");
+        for row in range(0u, 3) {
+            let value = (SRGB_TO_XYZ[row][column] * 65536.0 + 0.5) as u32;
+            profile.push_all(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8,
+                               value as u8]);
+        }
+    }
+    profile.push_all(curve);
+    profile
+}
+
+#[test]
+fn test_png_gamma() {
+    // A gamma of 1 means the samples are linear.
+    let linear = test_png(&[(b"gAMA", b"\x00\x01\x86\xa0")]);
+    let mut pixels = vec!(64, 0, 255, 255);
+    ColorTransform::from_png(linear.as_slice()).unwrap().apply(pixels.as_mut_slice());
+    assert_eq!(pixels, vec!(137, 0, 255, 255));
+
+    // The sRGB chunk overrides the gamma.
+    let srgb = test_png(&[(b"sRGB", b"\x00"), (b"gAMA", b"\x00\x01\x86\xa0")]);
+    assert!(ColorTransform::from_png(srgb.as_slice()).is_none());
+    assert!(ColorTransform::from_png(test_png(&[]).as_slice()).is_none());
+}
+
+#[test]
+fn test_icc_profile() {
+    // A curve without entries is linear.
+    let linear = test_profile(b"curv\0\0\0\0\0\0\0\0");
+    let mut pixels = vec!(64, 64, 64, 128);
+    ColorTransform::from_icc_profile(linear.as_slice()).unwrap().apply(pixels.as_mut_slice());
+    assert_eq!(pixels, vec!(137, 137, 137, 128));
+
+    // The sRGB transfer function as a parametric curve, which leaves colors as they are.
+    let mut srgb_curve = b"para\0\0\0\0\0\x03\0\0".to_vec();
+    for &parameter in [2.4f32, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045].iter() {
+        let value = (parameter * 65536.0 + 0.5) as u32;
+        srgb_curve.push_all(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8,
+                              value as u8]);
+    }
+    assert!(ColorTransform::from_icc_profile(test_profile(srgb_curve.as_slice()).as_slice())
+            .is_none());
+
+    // A truncated profile is ignored.
+    assert!(ColorTransform::from_icc_profile(linear.slice_to(200)).is_none());
+}
+
+#[test]
+fn test_linear_to_srgb() {
+    assert_eq!(linear_to_srgb(-1.0), 0);
+    assert_eq!(linear_to_srgb(0.001), 3);
+    assert_eq!(linear_to_srgb(0.5), 188);
+    assert_eq!(linear_to_srgb(2.0), 255);
+}
@@ -0,0 +1,446 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A GIF decoder that keeps every frame of an animation, which stb_image does not.
+//!
+//! http://www.w3.org/Graphics/GIF/spec-gif89a.txt
+
+use image::base::{DecodedAnimation, MAX_IMAGE_PIXELS, canvas_size, clamp_frame_delay, push_frame};
+
+use std::cmp;
+use std::slice::bytes::copy_memory;
+
+static TRAILER: u8 = 0x3b;
+static IMAGE_DESCRIPTOR: u8 = 0x2c;
+static EXTENSION: u8 = 0x21;
+static GRAPHIC_CONTROL_LABEL: u8 = 0xf9;
+static APPLICATION_LABEL: u8 = 0xff;
+
+static MAX_CODE_SIZE: uint = 12;
+
+/// How the area of a frame is treated before the next frame is drawn.
+#[deriving(PartialEq)]
+enum Disposal {
+    Keep,
+    RestoreBackground,
+    RestorePrevious,
+}
+
+/// The graphic control extension that applies to the next image.
+struct GraphicControl {
+    disposal: Disposal,
+    delay: u32,
+    transparent_index: Option<u8>,
+}
+
+impl GraphicControl {
+    fn default() -> GraphicControl {
+        GraphicControl {
+            disposal: Keep,
+            delay: 0,
+            transparent_index: None,
+        }
+    }
+}
+
+/// Reads through the blocks of a GIF stream.
+struct Stream<'a> {
+    data: &'a [u8],
+    position: uint,
+}
+
+impl<'a> Stream<'a> {
+    fn read_u8(&mut self) -> Result<u8, String> {
+        if self.position >= self.data.len() {
+            return Err("unexpected end of GIF data".to_string());
+        }
+        self.position += 1;
+        Ok(self.data[self.position - 1])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, String> {
+        let low = try!(self.read_u8()) as u16;
+        let high = try!(self.read_u8()) as u16;
+        Ok(low | (high << 8))
+    }
+
+    fn read_slice(&mut self, length: uint) -> Result<&'a [u8], String> {
+        if self.position + length > self.data.len() {
+            return Err("unexpected end of GIF data".to_string());
+        }
+        self.position += length;
+        Ok(self.data.slice(self.position - length, self.position))
+    }
+
+    /// Reads a sequence of data sub-blocks, up to the block terminator.
+    fn read_sub_blocks(&mut self) -> Result<Vec<u8>, String> {
+        let mut data = vec!();
+        loop {
+            let length = try!(self.read_u8()) as uint;
+            if length == 0 {
+                return Ok(data);
+            }
+            data.push_all(try!(self.read_slice(length)));
+        }
+    }
+
+    fn read_color_table(&mut self, flags: u8) -> Result<&'a [u8], String> {
+        let size = 2u << (flags & 0x07) as uint;
+        self.read_slice(size * 3)
+    }
+}
+
+pub fn is_gif(buffer: &[u8]) -> bool {
+    buffer.starts_with(b"GIF87a") || buffer.starts_with(b"GIF89a")
+}
+
+/// Decodes every frame of a GIF into a canvas the size of the logical screen.
+pub fn decode(buffer: &[u8]) -> Result<DecodedAnimation, String> {
+    if !is_gif(buffer) {
+        return Err("not a GIF".to_string());
+    }
+    let mut stream = Stream {
+        data: buffer,
+        position: 6,
+    };
+
+    let width = try!(stream.read_u16()) as uint;
+    let height = try!(stream.read_u16()) as uint;
+    let flags = try!(stream.read_u8());
+    let _background_index = try!(stream.read_u8());
+    let _aspect_ratio = try!(stream.read_u8());
+    let global_color_table = if flags & 0x80 != 0 {
+        Some(try!(stream.read_color_table(flags)))
+    } else {
+        None
+    };
+    if width == 0 || height == 0 {
+        return Err("empty GIF".to_string());
+    }
+
+    // Frames are composited onto a canvas of straight RGBA pixels.
+    let mut canvas = Vec::from_elem(try!(canvas_size(width, height)), 0u8);
+    let mut shown = vec!();
+    let mut frames = vec!();
+    // Without a NETSCAPE2.0 extension, the animation plays once.
+    let mut loop_count = 1;
+    let mut control = GraphicControl::default();
+
+    loop {
+        match try!(stream.read_u8()) {
+            IMAGE_DESCRIPTOR => {
+                let left = try!(stream.read_u16()) as uint;
+                let top = try!(stream.read_u16()) as uint;
+                let frame_width = try!(stream.read_u16()) as uint;
+                let frame_height = try!(stream.read_u16()) as uint;
+                let flags = try!(stream.read_u8());
+                if frame_width == 0 || frame_height == 0 {
+                    return Err("empty GIF frame".to_string());
+                }
+                let frame_size = frame_width * frame_height;
+                if frame_size > MAX_IMAGE_PIXELS {
+                    return Err(format!("a {}x{} GIF frame is too large",
+                                       frame_width, frame_height));
+                }
+                // Only the part of the frame that lies on the canvas is drawn.
+                let right = cmp::min(left + frame_width, width);
+                let bottom = cmp::min(top + frame_height, height);
+                let color_table = if flags & 0x80 != 0 {
+                    try!(stream.read_color_table(flags))
+                } else {
+                    match global_color_table {
+                        Some(table) => table,
+                        None => return Err("GIF frame without a color table".to_string()),
+                    }
+                };
+                let min_code_size = try!(stream.read_u8()) as uint;
+                if min_code_size == 0 || min_code_size >= MAX_CODE_SIZE {
+                    return Err("invalid LZW code size".to_string());
+                }
+                let data = try!(stream.read_sub_blocks());
+                // Missing pixels are left transparent.
+                let mut indices = try!(lzw_decode(data.as_slice(), min_code_size, frame_size));
+                if flags & 0x40 != 0 {
+                    indices = deinterlace(indices.as_slice(), frame_width, frame_height);
+                }
+
+                let previous = if control.disposal == RestorePrevious {
+                    Some(canvas.clone())
+                } else {
+                    None
+                };
+
+                for y in range(top, bottom) {
+                    for x in range(left, right) {
+                        let i = (y - top) * frame_width + x - left;
+                        if i >= indices.len() {
+                            break;
+                        }
+                        let index = indices[i];
+                        if Some(index) == control.transparent_index {
+                            continue;
+                        }
+                        let color = index as uint * 3;
+                        if color + 3 > color_table.len() {
+                            continue;
+                        }
+                        let offset = (y * width + x) * 4;
+                        copy_memory(canvas.slice_mut(offset, offset + 3),
+                                    color_table.slice(color, color + 3));
+                        canvas[offset + 3] = 255;
+                    }
+                }
+
+                push_frame(&mut frames, &mut shown, canvas.as_slice(), width, height,
+                           clamp_frame_delay(control.delay));
+
+                match control.disposal {
+                    Keep => {}
+                    RestoreBackground => {
+                        // Browsers clear to transparent rather than to the background color.
+                        for y in range(top, bottom) {
+                            for x in range(left, right) {
+                                let offset = (y * width + x) * 4;
+                                copy_memory(canvas.slice_mut(offset, offset + 4), &[0u8, 0, 0, 0]);
+                            }
+                        }
+                    }
+                    RestorePrevious => canvas = previous.unwrap(),
+                }
+                control = GraphicControl::default();
+            }
+            EXTENSION => {
+                let label = try!(stream.read_u8());
+                let data = try!(stream.read_sub_blocks());
+                if label == GRAPHIC_CONTROL_LABEL && data.len() >= 4 {
+                    control = GraphicControl {
+                        disposal: match (data[0] >> 2) & 0x07 {
+                            2 => RestoreBackground,
+                            3 => RestorePrevious,
+                            _ => Keep,
+                        },
+                        // The delay is given in hundredths of a second.
+                        delay: (data[1] as u32 | (data[2] as u32 << 8)) * 10,
+                        transparent_index: if data[0] & 0x01 != 0 { Some(data[3]) } else { None },
+                    };
+                } else if label == APPLICATION_LABEL && data.len() >= 14 &&
+                        (data.slice_to(11) == b"NETSCAPE2.0" || data.slice_to(11) == b"ANIMEXTS1.0") &&
+                        data[11] == 1 {
+                    // The loop count is the number of repetitions after the first play.
+                    let repetitions = data[12] as u32 | (data[13] as u32 << 8);
+                    loop_count = if repetitions == 0 { 0 } else { repetitions + 1 };
+                }
+            }
+            TRAILER => break,
+            other => {
+                // Treat anything after the last frame as the end of the stream.
+                if frames.is_empty() {
+                    return Err(format!("unexpected GIF block {:x}", other));
+                }
+                break;
+            }
+        }
+    }
+
+    if frames.is_empty() {
+        return Err("GIF without frames".to_string());
+    }
+    Ok(DecodedAnimation {
+        width: width as u32,
+        height: height as u32,
+        frames: frames,
+        loop_count: loop_count,
+    })
+}
+
+/// Decompresses the variable-length LZW codes of an image into at most `limit` color indices.
+fn lzw_decode(data: &[u8], min_code_size: uint, limit: uint) -> Result<Vec<u8>, String> {
+    let clear_code = 1u << min_code_size;
+    let end_code = clear_code + 1;
+    let mut code_size = min_code_size + 1;
+    let mut next_code = end_code + 1;
+
+    // Each table entry is the code of its prefix and its last index.
+    let mut prefixes = Vec::from_elem(1 << MAX_CODE_SIZE, 0u);
+    let mut suffixes = Vec::from_elem(1 << MAX_CODE_SIZE, 0u8);
+    for code in range(0, clear_code) {
+        suffixes[code] = code as u8;
+    }
+
+    let mut output = vec!();
+    let mut string = vec!();
+    let mut previous: Option<uint> = None;
+    let mut bits = 0u;
+    let mut bit_count = 0u;
+
+    'codes: for &byte in data.iter() {
+        bits |= (byte as uint) << bit_count;
+        bit_count += 8;
+        while bit_count >= code_size {
+            // Codes past the end of the frame are ignored.
+            if output.len() >= limit {
+                break 'codes;
+            }
+            let code = bits & ((1 << code_size) - 1);
+            bits >>= code_size;
+            bit_count -= code_size;
+
+            if code == clear_code {
+                code_size = min_code_size + 1;
+                next_code = end_code + 1;
+                previous = None;
+                continue;
+            }
+            if code == end_code {
+                break 'codes;
+            }
+
+            let previous_code = match previous {
+                None => {
+                    if code >= clear_code {
+                        return Err("invalid first LZW code".to_string());
+                    }
+                    output.push(code as u8);
+                    previous = Some(code);
+                    continue;
+                }
+                Some(previous_code) => previous_code,
+            };
+
+            // A code that is not in the table yet stands for the previous string followed by
+            // its own first index.
+            let known = if code < next_code {
+                code
+            } else if code == next_code {
+                previous_code
+            } else {
+                return Err("invalid LZW code".to_string());
+            };
+            string.clear();
+            let mut current = known;
+            while current > end_code {
+                string.push(suffixes[current]);
+                current = prefixes[current];
+            }
+            let first = current as u8;
+            string.push(first);
+            output.extend(string.iter().rev().map(|&index| index));
+            if code == next_code {
+                output.push(first);
+            }
+
+            if next_code < 1 << MAX_CODE_SIZE {
+                prefixes[next_code] = previous_code;
+                suffixes[next_code] = first;
+                next_code += 1;
+                if next_code == 1 << code_size && code_size < MAX_CODE_SIZE {
+                    code_size += 1;
+                }
+            }
+            previous = Some(code);
+        }
+    }
+
+    output.truncate(limit);
+    // Tolerate a missing end code, as browsers do.
+    Ok(output)
+}
+
+/// Reorders the rows of an interlaced image, which are stored in four passes.
+fn deinterlace(indices: &[u8], width: uint, height: uint) -> Vec<u8> {
+    static PASSES: [(uint, uint), ..4] = [(0, 8), (4, 8), (2, 4), (1, 2)];
+
+    let mut result = Vec::from_elem(width * height, 0u8);
+    let mut rows = indices.chunks(width);
+    for &(start, step) in PASSES.iter() {
+        let mut y = start;
+        while y < height {
+            match rows.next() {
+                Some(row) => copy_memory(result.slice_mut(y * width, y * width + row.len()), row),
+                None => return result,
+            }
+            y += step;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+use image::base::compose_frames;
+
+#[cfg(test)]
+static TWO_FRAMES: &'static [u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61,             // GIF89a
+    0x02, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00,       // 2x1, two-color global table
+    0xff, 0x00, 0x00, 0x00, 0x00, 0xff,             // red, blue
+    0x21, 0xff, 0x0b, 0x4e, 0x45, 0x54, 0x53, 0x43, 0x41, 0x50, 0x45, 0x32, 0x2e, 0x30,
+    0x03, 0x01, 0x00, 0x00, 0x00,                   // NETSCAPE2.0, loop forever
+    0x21, 0xf9, 0x04, 0x00, 0x05, 0x00, 0x00, 0x00, // 50ms
+    0x2c, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00,
+    0x02, 0x02, 0x44, 0x0a, 0x00,                   // red, blue
+    0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, // no delay, index 0 is transparent
+    0x2c, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00,
+    0x02, 0x02, 0x44, 0x01, 0x00,                   // red, at (1, 0)
+    0x3b,
+];
+
+#[test]
+fn test_decode_animation() {
+    let animation = decode(TWO_FRAMES).unwrap();
+    assert_eq!((animation.width, animation.height), (2, 1));
+    assert_eq!(animation.loop_count, 0);
+    assert_eq!(animation.frames.len(), 2);
+    assert_eq!(animation.frames[0].pixels, vec!(255, 0, 0, 255, 0, 0, 255, 255));
+    assert_eq!(animation.frames[0].delay, 50);
+    // The second frame's only pixel is transparent, so the first frame shows through.
+    assert!(animation.frames[1].pixels.is_empty());
+    assert_eq!(compose_frames(&animation)[1], animation.frames[0].pixels);
+    assert_eq!(animation.frames[1].delay, 100);
+}
+
+#[test]
+fn test_decode_bad_sizes() {
+    // A frame without pixels.
+    let mut data = TWO_FRAMES.to_vec();
+    data[51] = 0;
+    data[52] = 0;
+    assert!(decode(data.as_slice()).is_err());
+
+    // A canvas too large to allocate.
+    let mut data = TWO_FRAMES.to_vec();
+    for i in range(6u, 10) {
+        data[i] = 0xff;
+    }
+    assert!(decode(data.as_slice()).is_err());
+
+    // A frame too large to decode, even though the canvas is small.
+    let mut data = TWO_FRAMES.to_vec();
+    for i in range(51u, 55) {
+        data[i] = 0xff;
+    }
+    assert!(decode(data.as_slice()).is_err());
+
+    // A frame hanging off the canvas is drawn where the two overlap.
+    let mut data = TWO_FRAMES.to_vec();
+    data[51] = 3;
+    data[53] = 2;
+    let animation = decode(data.as_slice()).unwrap();
+    assert_eq!(animation.frames[0].pixels, vec!(255, 0, 0, 255, 0, 0, 255, 255));
+}
+
+#[test]
+fn test_lzw_decode() {
+    // Clear, 1, 0, 0, 6 (the code added for "1 0"), end, with 2-bit indices.
+    let data = [0x0c, 0x60, 0x05];
+    assert_eq!(lzw_decode(&data, 2, 10), Ok(vec!(1, 0, 0, 1, 0)));
+    // Decoding stops at the end of the frame.
+    assert_eq!(lzw_decode(&data, 2, 3), Ok(vec!(1, 0, 0)));
+    assert!(lzw_decode(&[0x07], 2, 10).is_err());
+}
+
+#[test]
+fn test_deinterlace() {
+    let rows: Vec<u8> = vec!(0, 8, 4, 2, 6, 1, 3, 5, 7, 9);
+    assert_eq!(deinterlace(rows.as_slice(), 1, 10), vec!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9));
+}
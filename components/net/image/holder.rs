@@ -3,7 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use image::base::Image;
-use image_cache_task::{ImageReady, ImageNotReady, ImageFailed};
+use image_cache_task::{ImageReady, ImagePartial, ImageNotReady, ImageFailed};
 use local_image_cache::LocalImageCache;
 
 use geom::size::Size2D;
@@ -21,6 +21,8 @@ use url::Url;
 pub struct ImageHolder<NodeAddress> {
     url: Url,
     image: Option<Arc<Box<Image>>>,
+    /// Whether `image` is the complete image rather than one decoded from part of its data.
+    complete: bool,
     cached_size: Size2D<int>,
     local_image_cache: Arc<Mutex<LocalImageCache<NodeAddress>>>,
 }
@@ -32,6 +34,7 @@ impl<NodeAddress: Send> ImageHolder<NodeAddress> {
         let holder = ImageHolder {
             url: url,
             image: None,
+            complete: false,
             cached_size: Size2D(0,0),
             local_image_cache: local_image_cache.clone(),
         };
@@ -78,9 +81,9 @@ impl<NodeAddress: Send> ImageHolder<NodeAddress> {
     pub fn get_image(&mut self, node_address: NodeAddress) -> Option<Arc<Box<Image>>> {
         debug!("get_image() {}", self.url.serialize());
 
-        // If this is the first time we've called this function, load
-        // the image and store it for the future
-        if self.image.is_none() {
+        // Until the complete image has been loaded, ask for it again and store the
+        // most complete version for the future
+        if !self.complete {
             let port = {
                 let val = self.local_image_cache.lock();
                 let mut local_image_cache = val;
@@ -89,6 +92,11 @@ impl<NodeAddress: Send> ImageHolder<NodeAddress> {
             match port.recv() {
                 ImageReady(image) => {
                     self.image = Some(image);
+                    self.complete = true;
+                }
+                ImagePartial(image) => {
+                    debug!("image partially loaded for {:s}", self.url.serialize());
+                    self.image = Some(image);
                 }
                 ImageNotReady => {
                     debug!("image not ready for {:s}", self.url.serialize());
@@ -0,0 +1,237 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A decoder for ICO and CUR files, such as favicons. Of the images in a file, only the largest
+//! is decoded; it is either a PNG or a bitmap without its file header.
+//!
+//! http://msdn.microsoft.com/en-us/library/ms997538.aspx
+
+use image::base::{DecodedAnimation, DecodedFrame, to_rgba};
+use png;
+
+static ICON_TYPE: u16 = 1;
+static CURSOR_TYPE: u16 = 2;
+
+static DIRECTORY_ENTRY_SIZE: uint = 16;
+static BITMAP_HEADER_SIZE: uint = 40;
+static BI_RGB: u32 = 0;
+
+/// The largest bitmap width or height that is decoded. Icons are at most 256 pixels wide, so this
+/// only rejects corrupt sizes.
+static MAX_BITMAP_SIZE: uint = 4096;
+
+struct DirectoryEntry {
+    width: uint,
+    height: uint,
+    bit_count: u16,
+    length: uint,
+    offset: uint,
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    data[0] as u16 | (data[1] as u16 << 8)
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    data[0] as u32 | (data[1] as u32 << 8) | (data[2] as u32 << 16) | (data[3] as u32 << 24)
+}
+
+pub fn is_ico(buffer: &[u8]) -> bool {
+    if buffer.len() < 6 || read_u16(buffer) != 0 {
+        return false;
+    }
+    let kind = read_u16(buffer.slice_from(2));
+    (kind == ICON_TYPE || kind == CURSOR_TYPE) && read_u16(buffer.slice_from(4)) > 0
+}
+
+/// Decodes the largest image of an ICO or CUR file, preferring the deepest of images of the same
+/// size.
+pub fn decode(buffer: &[u8]) -> Result<DecodedAnimation, String> {
+    if !is_ico(buffer) {
+        return Err("not an ICO file".to_string());
+    }
+    let is_cursor = read_u16(buffer.slice_from(2)) == CURSOR_TYPE;
+    let count = read_u16(buffer.slice_from(4)) as uint;
+    if buffer.len() < 6 + count * DIRECTORY_ENTRY_SIZE {
+        return Err("truncated ICO directory".to_string());
+    }
+
+    let mut best: Option<DirectoryEntry> = None;
+    for index in range(0, count) {
+        let data = buffer.slice_from(6 + index * DIRECTORY_ENTRY_SIZE);
+        let entry = DirectoryEntry {
+            // A size of zero stands for 256 pixels.
+            width: if data[0] == 0 { 256 } else { data[0] as uint },
+            height: if data[1] == 0 { 256 } else { data[1] as uint },
+            // Cursors have their hotspot where icons have their bit depth.
+            bit_count: if is_cursor { 0 } else { read_u16(data.slice_from(6)) },
+            length: read_u32(data.slice_from(8)) as uint,
+            offset: read_u32(data.slice_from(12)) as uint,
+        };
+        let is_better = match best {
+            Some(ref best) => {
+                let (area, best_area) = (entry.width * entry.height, best.width * best.height);
+                area > best_area || (area == best_area && entry.bit_count > best.bit_count)
+            }
+            None => true,
+        };
+        if is_better {
+            best = Some(entry);
+        }
+    }
+
+    let entry = best.unwrap();
+    if entry.offset > buffer.len() || entry.length > buffer.len() - entry.offset {
+        return Err("ICO image outside of the file".to_string());
+    }
+    let data = buffer.slice(entry.offset, entry.offset + entry.length);
+    let (width, height, pixels) = if png::is_png(data) {
+        match png::load_png_from_memory(data) {
+            Ok(image) => (image.width, image.height, to_rgba(image.pixels)),
+            Err(err) => return Err(err),
+        }
+    } else {
+        try!(decode_bitmap(data))
+    };
+
+    Ok(DecodedAnimation {
+        width: width,
+        height: height,
+        frames: vec!(DecodedFrame::whole(width, height, pixels, 0)),
+        loop_count: 0,
+    })
+}
+
+/// Decodes a bitmap, which is followed by a mask of one bit per pixel that makes the pixels where
+/// it is set transparent. Bitmaps with an alpha channel only use the mask if their alpha channel
+/// is empty.
+fn decode_bitmap(data: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    if data.len() < BITMAP_HEADER_SIZE {
+        return Err("truncated ICO bitmap header".to_string());
+    }
+    let header_size = read_u32(data) as uint;
+    let width = read_u32(data.slice_from(4)) as uint;
+    // The height covers both the bitmap and the mask.
+    let height = read_u32(data.slice_from(8)) as uint / 2;
+    let bit_count = read_u16(data.slice_from(14)) as uint;
+    let compression = read_u32(data.slice_from(16));
+    let colors_used = read_u32(data.slice_from(32)) as uint;
+    if header_size < BITMAP_HEADER_SIZE || header_size > data.len() {
+        return Err("bad ICO bitmap header".to_string());
+    }
+    if width == 0 || height == 0 || width > MAX_BITMAP_SIZE || height > MAX_BITMAP_SIZE {
+        return Err("bad ICO bitmap size".to_string());
+    }
+    if compression != BI_RGB {
+        return Err("unsupported ICO bitmap compression".to_string());
+    }
+    match bit_count {
+        1 | 4 | 8 | 16 | 24 | 32 => {}
+        _ => return Err("unsupported ICO bitmap depth".to_string()),
+    }
+
+    let palette_size = if bit_count > 8 {
+        0
+    } else if colors_used == 0 || colors_used > 1 << bit_count {
+        1 << bit_count
+    } else {
+        colors_used
+    };
+    let palette = data.slice_from(header_size);
+    if palette.len() < palette_size * 4 {
+        return Err("truncated ICO bitmap palette".to_string());
+    }
+
+    // Rows are stored bottom up, and padded to four bytes.
+    let row_size = (width * bit_count + 31) / 32 * 4;
+    let rows = palette.slice_from(palette_size * 4);
+    if rows.len() < row_size * height {
+        return Err("truncated ICO bitmap".to_string());
+    }
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for y in range(0, height) {
+        let row = rows.slice_from((height - 1 - y) * row_size);
+        for x in range(0, width) {
+            if bit_count <= 8 {
+                let pixels_per_byte = 8 / bit_count;
+                let shift = (pixels_per_byte - 1 - x % pixels_per_byte) * bit_count;
+                let index = (row[x / pixels_per_byte] as uint >> shift) & ((1 << bit_count) - 1);
+                if index < palette_size {
+                    let color = palette.slice_from(index * 4);
+                    pixels.push_all(&[color[2], color[1], color[0], 255]);
+                } else {
+                    pixels.push_all(&[0, 0, 0, 255]);
+                }
+            } else if bit_count == 16 {
+                // Five bits for each of red, green and blue.
+                let color = read_u16(row.slice_from(x * 2));
+                for &shift in [10u, 5, 0].iter() {
+                    let value = ((color >> shift) & 0x1f) as u8;
+                    pixels.push((value << 3) | (value >> 2));
+                }
+                pixels.push(255);
+            } else {
+                let color = row.slice_from(x * bit_count / 8);
+                let alpha = if bit_count == 32 { color[3] } else { 255 };
+                pixels.push_all(&[color[2], color[1], color[0], alpha]);
+            }
+        }
+    }
+
+    let has_alpha = bit_count == 32 &&
+        range(0, width * height).any(|index| pixels[index * 4 + 3] != 0);
+    let mask_row_size = (width + 31) / 32 * 4;
+    let mask = rows.slice_from(row_size * height);
+    // Some icons leave the mask out, which leaves them opaque.
+    if !has_alpha && mask.len() >= mask_row_size * height {
+        for y in range(0, height) {
+            let row = mask.slice_from((height - 1 - y) * mask_row_size);
+            for x in range(0, width) {
+                let alpha = if (row[x / 8] >> (7 - x % 8)) & 1 == 1 { 0 } else { 255 };
+                pixels[(y * width + x) * 4 + 3] = alpha;
+            }
+        }
+    }
+    Ok((width as u32, height as u32, pixels))
+}
+
+#[cfg(test)]
+use image::base::{checksum, decode_corrupted_copies};
+
+#[cfg(test)]
+static ICON: &'static [u8] = include_bin!("corpus/icon.ico");
+#[cfg(test)]
+static CURSOR: &'static [u8] = include_bin!("corpus/cursor.cur");
+#[cfg(test)]
+static PNG_ICON: &'static [u8] = include_bin!("corpus/png.ico");
+
+#[test]
+fn test_decode_largest_image() {
+    // An 8x8 bitmap of 32 bits per pixel, and 16x16 ones of 4 and 8 bits per pixel.
+    let image = decode(ICON).unwrap();
+    assert_eq!((image.width, image.height), (16, 16));
+    let pixels = image.frames[0].pixels.as_slice();
+    // The first pixel is masked out.
+    assert_eq!(pixels.slice_to(8).to_vec(), vec!(105, 121, 220, 0, 112, 161, 173, 255));
+    assert_eq!(checksum(pixels), 0x05d77a6f);
+
+    // A 1 bit per pixel bitmap and a larger PNG.
+    let image = decode(PNG_ICON).unwrap();
+    assert_eq!((image.width, image.height), (20, 20));
+    assert_eq!(checksum(image.frames[0].pixels.as_slice()), 0x095c1295);
+}
+
+#[test]
+fn test_decode_cursor() {
+    let image = decode(CURSOR).unwrap();
+    assert_eq!((image.width, image.height), (12, 10));
+    assert_eq!(checksum(image.frames[0].pixels.as_slice()), 0xf83f3660);
+}
+
+#[test]
+fn test_decode_corrupted() {
+    for &data in [ICON, CURSOR, PNG_ICON].iter() {
+        decode_corrupted_copies(data, decode);
+    }
+}
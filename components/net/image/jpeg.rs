@@ -0,0 +1,197 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The metadata of JPEG files that stb_image ignores: the ICC profile, and the EXIF orientation
+//! that cameras write instead of turning the image itself.
+//!
+//! http://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf
+
+use image::color::ColorTransform;
+
+static APP1: u8 = 0xe1;
+static APP2: u8 = 0xe2;
+static START_OF_SCAN: u8 = 0xda;
+static END_OF_IMAGE: u8 = 0xd9;
+
+static ORIENTATION_TAG: uint = 0x0112;
+static SHORT_TYPE: uint = 3;
+
+fn read_u16(data: &[u8]) -> uint {
+    (data[0] as uint << 8) | data[1] as uint
+}
+
+fn read_tiff_u16(data: &[u8], little_endian: bool) -> uint {
+    if little_endian {
+        data[0] as uint | (data[1] as uint << 8)
+    } else {
+        read_u16(data)
+    }
+}
+
+fn read_tiff_u32(data: &[u8], little_endian: bool) -> uint {
+    if little_endian {
+        (read_tiff_u16(data.slice_from(2), true) << 16) | read_tiff_u16(data, true)
+    } else {
+        (read_u16(data) << 16) | read_u16(data.slice_from(2))
+    }
+}
+
+/// Returns the segments with the given marker that come before the first scan of a JPEG.
+fn segments<'a>(buffer: &'a [u8], marker: u8) -> Vec<&'a [u8]> {
+    let mut segments = vec!();
+    if !buffer.starts_with(&[0xff, 0xd8]) {
+        return segments;
+    }
+    let mut position = 2;
+    while position + 4 <= buffer.len() && buffer[position] == 0xff {
+        let kind = buffer[position + 1];
+        if kind == 0xff {
+            // A fill byte.
+            position += 1;
+            continue;
+        }
+        if kind == START_OF_SCAN || kind == END_OF_IMAGE {
+            break;
+        }
+        let length = read_u16(buffer.slice_from(position + 2));
+        if length < 2 || position + 2 + length > buffer.len() {
+            break;
+        }
+        if kind == marker {
+            segments.push(buffer.slice(position + 4, position + 2 + length));
+        }
+        position += 2 + length;
+    }
+    segments
+}
+
+/// Returns the transform to sRGB of the ICC profile of a JPEG, if it has one that is not sRGB.
+pub fn color_transform(buffer: &[u8]) -> Option<ColorTransform> {
+    // Profiles are split into numbered chunks, since a segment holds at most 64KB.
+    let mut chunks: Vec<(u8, &[u8])> = segments(buffer, APP2).into_iter().filter(|segment| {
+        segment.len() >= 14 && segment.slice_to(12) == b"ICC_PROFILE\0"
+    }).map(|segment| (segment[12], segment.slice_from(14))).collect();
+    if chunks.is_empty() {
+        return None;
+    }
+    chunks.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    let mut profile = vec!();
+    for &(_, data) in chunks.iter() {
+        profile.push_all(data);
+    }
+    ColorTransform::from_icc_profile(profile.as_slice())
+}
+
+/// Returns the EXIF orientation of a JPEG, from 1 to 8. 1, the default, leaves the image as it is,
+/// 2 to 4 flip it and 5 to 8 also transpose it.
+pub fn orientation(buffer: &[u8]) -> uint {
+    for segment in segments(buffer, APP1).iter() {
+        if segment.len() < 14 || segment.slice_to(6) != b"Exif\0\0" {
+            continue;
+        }
+        // The EXIF data is laid out as a TIFF file, in either byte order.
+        let tiff = segment.slice_from(6);
+        let little_endian = tiff.slice_to(2) == b"II";
+        if read_tiff_u16(tiff.slice_from(2), little_endian) != 42 {
+            continue;
+        }
+
+        let directory = read_tiff_u32(tiff.slice_from(4), little_endian);
+        if directory > tiff.len() - 2 {
+            continue;
+        }
+        for index in range(0, read_tiff_u16(tiff.slice_from(directory), little_endian)) {
+            let entry = directory + 2 + index * 12;
+            if entry + 12 > tiff.len() {
+                break;
+            }
+            if read_tiff_u16(tiff.slice_from(entry), little_endian) == ORIENTATION_TAG &&
+                    read_tiff_u16(tiff.slice_from(entry + 2), little_endian) == SHORT_TYPE {
+                let orientation = read_tiff_u16(tiff.slice_from(entry + 8), little_endian);
+                return if orientation >= 1 && orientation <= 8 { orientation } else { 1 };
+            }
+        }
+    }
+    1
+}
+
+/// Turns RGBA pixels as an EXIF orientation says. Returns the new width and height.
+pub fn orient(orientation: uint, width: uint, height: uint, pixels: Vec<u8>)
+              -> (uint, uint, Vec<u8>) {
+    if orientation < 2 || orientation > 8 {
+        return (width, height, pixels);
+    }
+    let (oriented_width, oriented_height) = if orientation >= 5 {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let mut oriented = Vec::with_capacity(pixels.len());
+    for y in range(0, oriented_height) {
+        for x in range(0, oriented_width) {
+            let (source_x, source_y) = match orientation {
+                2 => (width - 1 - x, y),
+                3 => (width - 1 - x, height - 1 - y),
+                4 => (x, height - 1 - y),
+                5 => (y, x),
+                6 => (y, height - 1 - x),
+                7 => (width - 1 - y, height - 1 - x),
+                _ => (width - 1 - y, x),
+            };
+            let offset = (source_y * width + source_x) * 4;
+            oriented.push_all(pixels.slice(offset, offset + 4));
+        }
+    }
+    (oriented_width, oriented_height, oriented)
+}
+
+/// A JPEG with no image but an EXIF segment that holds an orientation.
+#[cfg(test)]
+fn exif_test_jpeg(orientation: u8, little_endian: bool) -> Vec<u8> {
+    // The header, which points to the directory right after it, and a directory of one entry.
+    let tiff = if little_endian {
+        vec!(b'I', b'I', 42, 0, 8, 0, 0, 0, 1, 0,
+             0x12, 0x01, 3, 0, 1, 0, 0, 0, orientation, 0, 0, 0)
+    } else {
+        vec!(b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 1,
+             0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0)
+    };
+    let mut jpeg = vec!(0xff, 0xd8, 0xff, APP1, 0, (2 + 6 + tiff.len()) as u8);
+    jpeg.push_all(b"Exif\0\0");
+    jpeg.push_all(tiff.as_slice());
+    jpeg.push_all(&[0xff, END_OF_IMAGE]);
+    jpeg
+}
+
+#[test]
+fn test_orientation() {
+    use image::base::test_image_bin;
+
+    assert_eq!(orientation(exif_test_jpeg(6, true).as_slice()), 6);
+    assert_eq!(orientation(exif_test_jpeg(3, false).as_slice()), 3);
+    assert_eq!(orientation(exif_test_jpeg(9, false).as_slice()), 1);
+    assert_eq!(orientation(test_image_bin().as_slice()), 1);
+}
+
+#[test]
+fn test_orient() {
+    // A 3x2 image whose pixels are numbered in order.
+    let pixels: Vec<u8> = range(0u, 6).flat_map(|i| {
+        vec!(i as u8, i as u8, i as u8, 255).into_iter()
+    }).collect();
+    let numbers = |pixels: Vec<u8>| -> Vec<u8> {
+        pixels.as_slice().chunks(4).map(|pixel| pixel[0]).collect()
+    };
+
+    let (width, height, turned) = orient(6, 3, 2, pixels.clone());
+    assert_eq!((width, height), (2, 3));
+    assert_eq!(numbers(turned), vec!(3, 0, 4, 1, 5, 2));
+
+    let (width, height, flipped) = orient(4, 3, 2, pixels.clone());
+    assert_eq!((width, height), (3, 2));
+    assert_eq!(numbers(flipped), vec!(3, 4, 5, 0, 1, 2));
+
+    let (_, _, transposed) = orient(5, 3, 2, pixels);
+    assert_eq!(numbers(transposed), vec!(0, 3, 1, 4, 2, 5));
+}
@@ -0,0 +1,743 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Decoding of images that are still loading, so that layout learns their size early and can
+//! paint what has arrived so far: the passes of an interlaced PNG, the rows of any other PNG with
+//! 8 or 16 bits per sample and the scans of a progressive JPEG. Other images are shown as a
+//! transparent rectangle of the right size until they are complete.
+//!
+//! The PNG decoder also decodes complete PNGs with 16-bit samples, which libpng truncates.
+
+use image::base::{Image, byte_swap_and_premultiply, convert_stb_image};
+use image::{gif, jpeg};
+use inflate::{Inflater, ZlibFormat};
+
+use png;
+use std::cmp::min;
+use std::mem;
+use stb_image::image as stb_image;
+
+/// A PNG is only decoded again once this many more bytes of its image data have arrived.
+static PNG_PROGRESS_STEP: uint = 16384;
+
+/// Images with more pixels than this are not decoded before they are complete.
+static MAX_PARTIAL_PIXELS: uint = 1 << 26;
+
+/// The pixels of one pass over an image: every `dx`th pixel of every `dy`th row, starting at
+/// (`x`, `y`). Until the next passes arrive, each pixel is drawn as a block of `block_width` by
+/// `block_height` pixels.
+struct Pass {
+    x: uint,
+    y: uint,
+    dx: uint,
+    dy: uint,
+    block_width: uint,
+    block_height: uint,
+}
+
+impl Pass {
+    /// The number of pixels in each row of the pass, and the number of rows, over an image of
+    /// the given size.
+    fn size(&self, width: uint, height: uint) -> (uint, uint) {
+        let columns = if self.x < width { (width - self.x + self.dx - 1) / self.dx } else { 0 };
+        let rows = if self.y < height { (height - self.y + self.dy - 1) / self.dy } else { 0 };
+        (columns, rows)
+    }
+}
+
+static ADAM7_PASSES: [Pass, ..7] = [
+    Pass { x: 0, y: 0, dx: 8, dy: 8, block_width: 8, block_height: 8 },
+    Pass { x: 4, y: 0, dx: 8, dy: 8, block_width: 4, block_height: 8 },
+    Pass { x: 0, y: 4, dx: 4, dy: 8, block_width: 4, block_height: 4 },
+    Pass { x: 2, y: 0, dx: 4, dy: 4, block_width: 2, block_height: 4 },
+    Pass { x: 0, y: 2, dx: 2, dy: 4, block_width: 2, block_height: 2 },
+    Pass { x: 1, y: 0, dx: 2, dy: 2, block_width: 1, block_height: 2 },
+    Pass { x: 0, y: 1, dx: 1, dy: 2, block_width: 1, block_height: 1 },
+];
+
+static SEQUENTIAL_PASSES: [Pass, ..1] = [
+    Pass { x: 0, y: 0, dx: 1, dy: 1, block_width: 1, block_height: 1 },
+];
+
+/// What is known about an image from the data that has arrived.
+enum Format {
+    /// Too little has arrived to tell.
+    Undetermined,
+    PngFormat(Box<PartialPng>),
+    JpegFormat(PartialJpeg),
+    /// An image whose size is known, which is shown blank until it is complete.
+    SizeOnly(uint, uint),
+    /// An image that is not shown before it is complete.
+    Unsupported,
+}
+
+/// Decodes an image as its data arrives. Each piece of data is only read once, so that an image
+/// can be shown many times while it loads without decoding it from the start each time.
+pub struct ProgressiveDecoder {
+    /// The data received so far.
+    data: Vec<u8>,
+    format: Format,
+    /// The progress of the image when it was last decoded.
+    decoded_progress: uint,
+}
+
+impl ProgressiveDecoder {
+    pub fn new() -> ProgressiveDecoder {
+        ProgressiveDecoder {
+            data: vec!(),
+            format: Undetermined,
+            decoded_progress: 0,
+        }
+    }
+
+    /// Adds the next piece of the image's data.
+    pub fn push(&mut self, data: &[u8]) {
+        self.data.push_all(data);
+        match self.format {
+            Undetermined => self.format = detect_format(self.data.as_slice()),
+            _ => {}
+        }
+        match self.format {
+            PngFormat(ref mut png) => png.read_chunks(self.data.as_slice()),
+            JpegFormat(ref mut jpeg) => jpeg.read_segments(self.data.as_slice()),
+            Undetermined | SizeOnly(..) | Unsupported => {}
+        }
+    }
+
+    /// The number of bytes received so far.
+    pub fn len(&self) -> uint {
+        self.data.len()
+    }
+
+    /// A measure of how much of the image can be shown, which grows as more of it arrives.
+    /// Returns `None` if not even the size of the image is known yet.
+    pub fn progress(&self) -> Option<uint> {
+        match self.format {
+            PngFormat(ref png) => Some(1 + png.image_data_length / PNG_PROGRESS_STEP),
+            JpegFormat(ref jpeg) => {
+                jpeg.size.map(|_| if jpeg.progressive { 1 + jpeg.complete_scans } else { 1 })
+            }
+            SizeOnly(..) => Some(1),
+            Undetermined | Unsupported => None,
+        }
+    }
+
+    /// Whether more of the image can be shown than when it was last decoded.
+    pub fn has_progressed(&self) -> bool {
+        self.progress().map_or(false, |progress| progress > self.decoded_progress)
+    }
+
+    /// Decodes as much of the image as possible. Images of which nothing can be shown yet are
+    /// returned blank, to give layout their size.
+    pub fn decode(&mut self) -> Option<Image> {
+        self.decoded_progress = self.progress().unwrap_or(0);
+        let data = self.data.as_slice();
+        match self.format {
+            PngFormat(ref mut png) => {
+                if png.decode(data) {
+                    let mut pixels = png.pixels.clone();
+                    byte_swap_and_premultiply(pixels.as_mut_slice());
+                    Some(Image::new(png.width as u32, png.height as u32, png::RGBA8(pixels)))
+                } else {
+                    blank_image(png.width, png.height)
+                }
+            }
+            JpegFormat(ref jpeg) => {
+                let (width, height) = match jpeg.size {
+                    Some(size) => size,
+                    None => return None,
+                };
+                let image = if jpeg.progressive && jpeg.complete_scans > 0 {
+                    decode_complete_scans(data, jpeg.end_of_scans)
+                } else {
+                    None
+                };
+                image.or_else(|| {
+                    // The image is turned once it is decoded, so its size must be turned too.
+                    if jpeg::orientation(data) >= 5 {
+                        blank_image(height, width)
+                    } else {
+                        blank_image(width, height)
+                    }
+                })
+            }
+            SizeOnly(width, height) => blank_image(width, height),
+            Undetermined | Unsupported => None,
+        }
+    }
+}
+
+/// Tells what kind of image `buffer` starts, if enough of it has arrived.
+fn detect_format(buffer: &[u8]) -> Format {
+    if buffer.len() < 2 {
+        return Undetermined;
+    }
+    if is_jpeg(buffer) {
+        return JpegFormat(PartialJpeg::new());
+    }
+    if buffer.len() < 10 {
+        return Undetermined;
+    }
+    if png::is_png(buffer) {
+        return match PartialPng::new(buffer) {
+            Ok(Some(png)) => PngFormat(box png),
+            Ok(None) => Undetermined,
+            Err(()) => Unsupported,
+        };
+    }
+    match gif_size(buffer) {
+        Some((width, height)) => SizeOnly(width, height),
+        None => Unsupported,
+    }
+}
+
+/// A transparent image, to give layout the size of an image before any of it can be shown. It
+/// has no pixels, so that it takes no memory whatever its size.
+fn blank_image(width: uint, height: uint) -> Option<Image> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some(Image::blank(width as u32, height as u32))
+}
+
+fn read_u32(data: &[u8]) -> uint {
+    (data[0] as uint << 24) | (data[1] as uint << 16) | (data[2] as uint << 8) | data[3] as uint
+}
+
+fn read_u16(data: &[u8]) -> uint {
+    (data[0] as uint << 8) | data[1] as uint
+}
+
+/// The chunks of a PNG that has been received in part, and the rows decoded from them so far.
+struct PartialPng {
+    width: uint,
+    height: uint,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+    palette: Vec<u8>,
+    transparency: Vec<u8>,
+    /// The offset of the next chunk to read.
+    position: uint,
+    /// Whether the chunks after the image data have been reached.
+    ended: bool,
+    /// The parts of the data that hold image data. The last may still be growing.
+    image_data: Vec<(uint, uint)>,
+    /// The number of bytes of image data received.
+    image_data_length: uint,
+    /// The number of bytes of image data given to `inflater`.
+    inflated_length: uint,
+    inflater: Inflater,
+    /// Decompressed data that does not make up a whole row yet.
+    raw: Vec<u8>,
+    /// The pass, and the row in it, of the next row to decode.
+    pass: uint,
+    row: uint,
+    previous_row: Vec<u8>,
+    /// The pixels decoded so far, in straight RGBA. Empty until the image is first decoded.
+    pixels: Vec<u8>,
+    /// Whether decoding stopped at invalid data.
+    failed: bool,
+}
+
+impl PartialPng {
+    /// Reads the header of a PNG, which must be its first chunk. Returns `None` if the header has
+    /// not arrived in full, and an error if it is invalid.
+    fn new(buffer: &[u8]) -> Result<Option<PartialPng>, ()> {
+        if buffer.len() < 33 {
+            return Ok(None);
+        }
+        if read_u32(buffer.slice_from(8)) != 13 || buffer.slice(12, 16) != b"IHDR" {
+            return Err(());
+        }
+        let header = buffer.slice(16, 29);
+        Ok(Some(PartialPng {
+            width: read_u32(header),
+            height: read_u32(header.slice_from(4)),
+            bit_depth: header[8],
+            color_type: header[9],
+            interlaced: header[12] == 1,
+            palette: vec!(),
+            transparency: vec!(),
+            position: 33,
+            ended: false,
+            image_data: vec!(),
+            image_data_length: 0,
+            inflated_length: 0,
+            inflater: Inflater::new(ZlibFormat),
+            raw: vec!(),
+            pass: 0,
+            row: 0,
+            previous_row: vec!(),
+            pixels: vec!(),
+            failed: false,
+        }))
+    }
+
+    /// Reads the chunks that have arrived since the last call.
+    fn read_chunks(&mut self, buffer: &[u8]) {
+        while !self.ended && self.position + 8 <= buffer.len() {
+            let length = read_u32(buffer.slice_from(self.position));
+            let kind = buffer.slice(self.position + 4, self.position + 8);
+            let start = self.position + 8;
+            // The last chunk may have arrived only in part.
+            let complete = start + length <= buffer.len();
+            let end = min(start + length, buffer.len());
+
+            if kind == b"IDAT" {
+                let last_start = self.image_data.last().map(|&(last_start, _)| last_start);
+                if last_start == Some(start) {
+                    let (_, last_end) = self.image_data.pop().unwrap();
+                    self.image_data_length += end - last_end;
+                } else {
+                    self.image_data_length += end - start;
+                }
+                self.image_data.push((start, end));
+            } else if kind == b"PLTE" && complete {
+                self.palette = buffer.slice(start, end).to_vec();
+            } else if kind == b"tRNS" && complete {
+                self.transparency = buffer.slice(start, end).to_vec();
+            } else if kind == b"IEND" {
+                self.ended = true;
+            }
+
+            if !complete {
+                break;
+            }
+            self.position = start + length + 4;
+        }
+    }
+
+    /// The number of samples in a pixel and the number of bytes in a sample, if the image can be
+    /// decoded before it is complete.
+    fn sample_layout(&self) -> Option<(uint, uint)> {
+        let channels = match self.color_type {
+            0 | 3 => 1,
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => return None,
+        };
+        // Other bit depths are shown blank until the whole image is decoded.
+        match (self.bit_depth, self.color_type) {
+            (8, _) => Some((channels, 1)),
+            (16, color_type) if color_type != 3 => Some((channels, 2)),
+            _ => None,
+        }
+    }
+
+    fn passes(&self) -> &'static [Pass] {
+        if self.interlaced {
+            ADAM7_PASSES.as_slice()
+        } else {
+            SEQUENTIAL_PASSES.as_slice()
+        }
+    }
+
+    /// Whether every row has been decoded.
+    fn is_complete(&self) -> bool {
+        self.pass == self.passes().len()
+    }
+
+    /// Decompresses the image data that has arrived since the last call, and decodes the rows it
+    /// completes into `pixels`. Returns false if the image cannot be decoded before it is
+    /// complete.
+    fn decode(&mut self, buffer: &[u8]) -> bool {
+        let (width, height) = (self.width, self.height);
+        let (channels, sample_size) = match self.sample_layout() {
+            Some(layout) => layout,
+            None => return false,
+        };
+        if width == 0 || height == 0 || width * height > MAX_PARTIAL_PIXELS {
+            return false;
+        }
+        if self.pixels.is_empty() {
+            self.pixels = Vec::from_elem(width * height * 4, 0u8);
+        }
+
+        let mut offset = 0;
+        for &(start, end) in self.image_data.iter() {
+            if offset + end - start > self.inflated_length {
+                let skipped = self.inflated_length - offset;
+                let output = self.inflater.push(buffer.slice(start + skipped, end));
+                self.raw.push_all(output.as_slice());
+                self.inflated_length = offset + end - start;
+            }
+            offset += end - start;
+        }
+
+        let passes = self.passes();
+        let bytes_per_pixel = channels * sample_size;
+        let mut position = 0;
+        while self.pass < passes.len() && !self.failed {
+            let pass = &passes[self.pass];
+            let (pass_width, pass_height) = pass.size(width, height);
+            if pass_width == 0 || self.row == pass_height {
+                self.pass += 1;
+                self.row = 0;
+                continue;
+            }
+
+            let row_length = pass_width * bytes_per_pixel;
+            if self.row == 0 {
+                // The row before the first row of a pass is taken to be all zeros.
+                self.previous_row = Vec::from_elem(row_length, 0u8);
+            }
+            if position + 1 + row_length > self.raw.len() {
+                break;
+            }
+            let filter = self.raw[position];
+            let mut current_row = self.raw.slice(position + 1, position + 1 + row_length).to_vec();
+            position += 1 + row_length;
+            if !unfilter(filter, current_row.as_mut_slice(), self.previous_row.as_slice(),
+                         bytes_per_pixel) {
+                self.failed = true;
+                break;
+            }
+
+            let y = pass.y + self.row * pass.dy;
+            for column in range(0, pass_width) {
+                let x = pass.x + column * pass.dx;
+                let pixel = current_row.slice(column * bytes_per_pixel,
+                                              (column + 1) * bytes_per_pixel);
+                let rgba = to_rgba(self.color_type, self.bit_depth, self.palette.as_slice(),
+                                   self.transparency.as_slice(), pixel);
+                for block_y in range(y, min(y + pass.block_height, height)) {
+                    for block_x in range(x, min(x + pass.block_width, width)) {
+                        let offset = (block_y * width + block_x) * 4;
+                        for i in range(0, 4) {
+                            self.pixels[offset + i] = rgba[i];
+                        }
+                    }
+                }
+            }
+            self.previous_row = current_row;
+            self.row += 1;
+        }
+        self.raw = self.raw.slice_from(position).to_vec();
+        true
+    }
+}
+
+/// Decodes a complete PNG with 16-bit samples. libpng drops the low byte of each sample, which
+/// darkens the image slightly, so these are rounded to 8 bits here instead. Returns `None` for
+/// other PNGs, and for images that fail to decode, which are left to libpng.
+pub fn load_16_bit_png(buffer: &[u8]) -> Option<png::Image> {
+    let mut png = match PartialPng::new(buffer) {
+        Ok(Some(png)) => png,
+        _ => return None,
+    };
+    if png.bit_depth != 16 {
+        return None;
+    }
+    png.read_chunks(buffer);
+    if !png.decode(buffer) || !png.is_complete() {
+        return None;
+    }
+    Some(png::Image {
+        width: png.width as u32,
+        height: png.height as u32,
+        pixels: png::RGBA8(mem::replace(&mut png.pixels, vec!())),
+    })
+}
+
+/// Reverses the filter that the encoder applied to a row.
+fn unfilter(filter: u8, row: &mut [u8], previous_row: &[u8], bytes_per_pixel: uint) -> bool {
+    for i in range(0, row.len()) {
+        let left = if i >= bytes_per_pixel { row[i - bytes_per_pixel] as int } else { 0 };
+        let above = previous_row[i] as int;
+        let upper_left = if i >= bytes_per_pixel { previous_row[i - bytes_per_pixel] as int } else { 0 };
+        let predictor = match filter {
+            0 => 0,
+            1 => left,
+            2 => above,
+            3 => (left + above) / 2,
+            4 => {
+                let estimate = left + above - upper_left;
+                let (to_left, to_above, to_upper_left) =
+                    ((estimate - left).abs(), (estimate - above).abs(), (estimate - upper_left).abs());
+                if to_left <= to_above && to_left <= to_upper_left {
+                    left
+                } else if to_above <= to_upper_left {
+                    above
+                } else {
+                    upper_left
+                }
+            }
+            _ => return false,
+        };
+        row[i] = (row[i] as int + predictor) as u8;
+    }
+    true
+}
+
+/// Converts one pixel into straight RGBA, rounding 16-bit samples to 8 bits.
+fn to_rgba(color_type: u8, bit_depth: u8, palette: &[u8], transparency: &[u8], pixel: &[u8])
+           -> [u8, ..4] {
+    if color_type == 3 {
+        let index = pixel[0] as uint;
+        let alpha = if index < transparency.len() { transparency[index] } else { 255 };
+        return if index * 3 + 3 <= palette.len() {
+            [palette[index * 3], palette[index * 3 + 1], palette[index * 3 + 2], alpha]
+        } else {
+            [0, 0, 0, alpha]
+        };
+    }
+
+    let channels = if bit_depth == 16 { pixel.len() / 2 } else { pixel.len() };
+    // The transparent color of grayscale and RGB images is stored with 16 bits per sample
+    // whatever the depth of the image, and compared at full depth.
+    let transparent = (color_type == 0 || color_type == 2) &&
+        transparency.len() >= channels * 2 &&
+        range(0, channels).all(|i| {
+            read_u16(transparency.slice_from(i * 2)) == sample(bit_depth, pixel, i)
+        });
+    let alpha = if transparent { 0 } else { 255 };
+    let value = |i: uint| -> u8 {
+        if bit_depth == 16 {
+            ((sample(bit_depth, pixel, i) + 128) / 257) as u8
+        } else {
+            pixel[i]
+        }
+    };
+    match color_type {
+        0 => [value(0), value(0), value(0), alpha],
+        2 => [value(0), value(1), value(2), alpha],
+        4 => [value(0), value(0), value(0), value(1)],
+        _ => [value(0), value(1), value(2), value(3)],
+    }
+}
+
+/// Returns the sample of a pixel at `index`, at the depth of the image.
+fn sample(bit_depth: u8, pixel: &[u8], index: uint) -> uint {
+    if bit_depth == 16 {
+        read_u16(pixel.slice_from(index * 2))
+    } else {
+        pixel[index] as uint
+    }
+}
+
+fn is_jpeg(buffer: &[u8]) -> bool {
+    buffer.starts_with(&[0xff, 0xd8])
+}
+
+/// The segments of a JPEG that has been received in part.
+struct PartialJpeg {
+    size: Option<(uint, uint)>,
+    progressive: bool,
+    /// The number of scans that have been received in full.
+    complete_scans: uint,
+    /// The offset of the end of the last complete scan.
+    end_of_scans: uint,
+    /// The offset of the next segment to read.
+    position: uint,
+    /// How far the entropy-coded data of the scan being received has been read, if one is.
+    scan_end: Option<uint>,
+    /// Whether the end of the image, or data that is not a segment, has been reached.
+    ended: bool,
+}
+
+impl PartialJpeg {
+    fn new() -> PartialJpeg {
+        PartialJpeg {
+            size: None,
+            progressive: false,
+            complete_scans: 0,
+            end_of_scans: 0,
+            position: 2,
+            scan_end: None,
+            ended: false,
+        }
+    }
+
+    /// Reads the segments that have arrived since the last call.
+    fn read_segments(&mut self, buffer: &[u8]) {
+        while !self.ended {
+            match self.scan_end {
+                Some(mut scan_end) => {
+                    // The entropy-coded data of a scan runs up to the next marker that is not a
+                    // restart marker. Zero bytes after 0xff are stuffing.
+                    loop {
+                        if scan_end + 1 >= buffer.len() {
+                            self.scan_end = Some(scan_end);
+                            return;
+                        }
+                        let next = buffer[scan_end + 1];
+                        if buffer[scan_end] == 0xff && next != 0 && next != 0xff &&
+                                (next < 0xd0 || next > 0xd7) {
+                            break;
+                        }
+                        scan_end += 1;
+                    }
+                    self.complete_scans += 1;
+                    self.end_of_scans = scan_end;
+                    self.position = scan_end;
+                    self.scan_end = None;
+                    continue;
+                }
+                None => {}
+            }
+
+            let position = self.position;
+            if position + 4 > buffer.len() {
+                return;
+            }
+            if buffer[position] != 0xff {
+                self.ended = true;
+                return;
+            }
+            let marker = buffer[position + 1];
+            if marker == 0xff {
+                // A fill byte.
+                self.position += 1;
+                continue;
+            }
+            if marker == 0xd9 {
+                self.ended = true;
+                return;
+            }
+
+            let end = position + 2 + read_u16(buffer.slice_from(position + 2));
+            match marker {
+                0xc0 | 0xc1 | 0xc2 => {
+                    if position + 9 > buffer.len() {
+                        return;
+                    }
+                    self.size = Some((read_u16(buffer.slice_from(position + 7)),
+                                      read_u16(buffer.slice_from(position + 5))));
+                    self.progressive = marker == 0xc2;
+                }
+                0xda => self.scan_end = Some(end),
+                _ => {}
+            }
+            self.position = end;
+        }
+    }
+}
+
+/// Decodes the scans of a progressive JPEG that have arrived, as if the image ended after them.
+fn decode_complete_scans(buffer: &[u8], end_of_scans: uint) -> Option<Image> {
+    let mut data = buffer.slice_to(end_of_scans).to_vec();
+    data.push_all(&[0xff, 0xd9]);
+
+    // Decode to RGBA, as `load_from_memory` does.
+    match stb_image::load_from_memory_with_depth(data.as_slice(), 4, true) {
+        stb_image::ImageU8(image) => Some(convert_stb_image(data.as_slice(), image)),
+        stb_image::ImageF32(_) | stb_image::Error(_) => None,
+    }
+}
+
+fn gif_size(buffer: &[u8]) -> Option<(uint, uint)> {
+    if !gif::is_gif(buffer) || buffer.len() < 10 {
+        return None;
+    }
+    Some((buffer[6] as uint | (buffer[7] as uint << 8),
+          buffer[8] as uint | (buffer[9] as uint << 8)))
+}
+
+/// A PNG with the given header and raw image data, compressed without Huffman coding. Returns the
+/// PNG and the offset of its image data.
+#[cfg(test)]
+pub fn stored_test_png(header: &[u8], raw: &[u8]) -> (Vec<u8>, uint) {
+    // The checksums are not verified, so they are left as zeros.
+    let mut bytes = vec!(0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a);
+    bytes.push_all(&[0, 0, 0, 13]);
+    bytes.push_all(b"IHDR");
+    bytes.push_all(header);
+    bytes.push_all(&[0, 0, 0, 0]);
+
+    // The zlib header, a single stored block, and the Adler-32 checksum.
+    let idat_length = 2 + 5 + raw.len() + 4;
+    bytes.push_all(&[0, 0, (idat_length >> 8) as u8, idat_length as u8]);
+    bytes.push_all(b"IDAT");
+    bytes.push_all(&[0x78, 0x01, 0x01, raw.len() as u8, 0, !raw.len() as u8, 0xff]);
+    let offset = bytes.len();
+    bytes.push_all(raw);
+    bytes.push_all(&[0, 0, 0, 0, 0, 0, 0, 0]);
+    (bytes, offset)
+}
+
+/// An interlaced 8x8 grayscale PNG. The pixel of the first pass is 200, the pixel of the second
+/// 100 and the others 0. Returns the PNG and the offset of its image data.
+#[cfg(test)]
+pub fn interlaced_test_png() -> (Vec<u8>, uint) {
+    let mut raw = vec!(0, 200, 0, 100);
+    // The five remaining passes have 1, 2, 2, 4 and 4 rows of 2, 2, 4, 4 and 8 pixels.
+    for &(rows, columns) in [(1u, 2u), (2, 2), (2, 4), (4, 4), (4, 8)].iter() {
+        for _ in range(0, rows) {
+            raw.push(0);
+            raw.grow(columns, 0);
+        }
+    }
+    stored_test_png(&[0, 0, 0, 8, 0, 0, 0, 8, 8, 0, 0, 0, 1], raw.as_slice())
+}
+
+#[test]
+fn test_interlaced_png_fills_blocks_with_each_pass() {
+    let (bytes, offset) = interlaced_test_png();
+    let pixel = |image: &Image, x: uint, y: uint| -> u8 {
+        match image.pixels {
+            png::RGBA8(ref data) => data[(y * 8 + x) * 4],
+            _ => panic!("unexpected pixel format"),
+        }
+    };
+
+    // Nothing is known before the header has arrived.
+    let mut decoder = ProgressiveDecoder::new();
+    decoder.push(bytes.slice_to(20));
+    assert!(decoder.decode().is_none());
+
+    // Each piece of data is decoded onto the rows decoded before it.
+    decoder.push(bytes.slice(20, offset + 2));
+    let first_pass = decoder.decode().unwrap();
+    assert_eq!((first_pass.width, first_pass.height), (8, 8));
+    assert_eq!((pixel(&first_pass, 0, 0), pixel(&first_pass, 7, 7)), (200, 200));
+
+    decoder.push(bytes.slice(offset + 2, offset + 4));
+    let second_pass = decoder.decode().unwrap();
+    assert_eq!((pixel(&second_pass, 3, 7), pixel(&second_pass, 4, 0)), (200, 100));
+
+    decoder.push(bytes.slice_from(offset + 4));
+    let complete = decoder.decode().unwrap();
+    assert_eq!((pixel(&complete, 0, 0), pixel(&complete, 4, 0), pixel(&complete, 7, 7)),
+               (200, 100, 0));
+}
+
+#[test]
+fn test_jpeg_size_is_known_before_its_data() {
+    use image::base::{load_from_memory, test_image_bin};
+
+    let data = test_image_bin();
+    let mut decoder = ProgressiveDecoder::new();
+    decoder.push(data.slice_to(100));
+    assert_eq!(decoder.progress(), None);
+    assert!(!decoder.has_progressed());
+    assert!(decoder.decode().is_none());
+
+    // The test image is a baseline JPEG, which is shown blank until it is complete.
+    decoder.push(data.slice(100, 500));
+    assert_eq!(decoder.progress(), Some(1));
+    assert!(decoder.has_progressed());
+    let image = decoder.decode().unwrap();
+    assert!(!decoder.has_progressed());
+    let complete = load_from_memory(data.as_slice()).unwrap();
+    assert_eq!((image.width, image.height), (complete.width, complete.height));
+    assert!(image.is_blank());
+    assert_eq!(image.size_of_pixels(), 0);
+}
+
+#[test]
+fn test_16_bit_png_is_rounded() {
+    // A 2x1 grayscale image of 0x01ff and 0xffff, the first of which libpng would truncate to 1.
+    let header = [0, 0, 0, 2, 0, 0, 0, 1, 16, 0, 0, 0, 0];
+    let (bytes, _) = stored_test_png(&header, &[0, 0x01, 0xff, 0xff, 0xff]);
+    match load_16_bit_png(bytes.as_slice()).unwrap().pixels {
+        png::RGBA8(pixels) => assert_eq!(pixels, vec!(2, 2, 2, 255, 255, 255, 255, 255)),
+        _ => panic!("unexpected pixel format"),
+    }
+
+    // 8-bit images are left to libpng.
+    let (bytes, _) = interlaced_test_png();
+    assert!(load_16_bit_png(bytes.as_slice()).is_none());
+}
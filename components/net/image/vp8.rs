@@ -0,0 +1,1760 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A decoder for the VP8 key frames that lossy WebP images are made of. It produces the Y, U and
+//! V planes of the frame; `image::webp` converts them to RGBA.
+//!
+//! http://tools.ietf.org/html/rfc6386
+
+use std::cmp::{max, min};
+use std::slice::bytes::copy_memory;
+
+/// The stride of the buffer in which macroblocks are predicted, which holds the luma block, both
+/// chroma blocks and the edge samples they are predicted from.
+const BPS: uint = 32;
+const Y_OFFSET: uint = BPS + 8;
+const U_OFFSET: uint = Y_OFFSET + BPS * 16 + BPS;
+const V_OFFSET: uint = U_OFFSET + 16;
+const WORK_SIZE: uint = BPS * 17 + BPS * 9;
+
+// Prediction modes. The modes of whole luma and chroma blocks share their numbers with the
+// corresponding subblock modes, so that they can serve as context for subblock modes.
+const DC_PRED: u8 = 0;
+const TM_PRED: u8 = 1;
+const VE_PRED: u8 = 2;
+const HE_PRED: u8 = 3;
+const RD_PRED: u8 = 4;
+const VR_PRED: u8 = 5;
+const LD_PRED: u8 = 6;
+const VL_PRED: u8 = 7;
+const HD_PRED: u8 = 8;
+const HU_PRED: u8 = 9;
+
+/// The tree of subblock modes, indexed as in the specification: positive entries lead to another
+/// node and other entries are negated modes.
+static SUBBLOCK_MODE_TREE: [i8, ..18] = [
+    -(DC_PRED as i8), 1,
+    -(TM_PRED as i8), 2,
+    -(VE_PRED as i8), 3,
+    4, 6,
+    -(HE_PRED as i8), 5,
+    -(RD_PRED as i8), -(VR_PRED as i8),
+    -(LD_PRED as i8), 7,
+    -(VL_PRED as i8), 8,
+    -(HD_PRED as i8), -(HU_PRED as i8),
+];
+
+static BANDS: [uint, ..17] = [0, 1, 2, 3, 6, 4, 5, 6, 6, 6, 6, 6, 6, 6, 6, 7, 0];
+static ZIGZAG: [uint, ..16] = [0, 1, 4, 8, 5, 2, 3, 6, 9, 12, 13, 10, 7, 11, 14, 15];
+
+static CAT3: &'static [u8] = &[173, 148, 140];
+static CAT4: &'static [u8] = &[176, 155, 140, 135];
+static CAT5: &'static [u8] = &[180, 157, 141, 134, 130];
+static CAT6: &'static [u8] = &[254, 254, 243, 230, 196, 177, 153, 140, 133, 130, 129];
+
+// Token types, which select the coefficient probabilities.
+static TYPE_Y_AFTER_Y2: uint = 0;
+static TYPE_Y2: uint = 1;
+static TYPE_CHROMA: uint = 2;
+static TYPE_Y_WITH_DC: uint = 3;
+
+static NUM_SEGMENTS: uint = 4;
+
+/// A decoded frame. The planes cover whole macroblocks, so they can be larger than the image.
+pub struct Frame {
+    pub width: uint,
+    pub height: uint,
+    pub y: Vec<u8>,
+    pub u: Vec<u8>,
+    pub v: Vec<u8>,
+    pub y_stride: uint,
+    pub uv_stride: uint,
+}
+
+/// The boolean entropy decoder of section 7. Reading past the end of the data yields zeros.
+struct BoolDecoder<'a> {
+    data: &'a [u8],
+    position: uint,
+    value: u32,
+    range: u32,
+    bit_count: uint,
+}
+
+impl<'a> BoolDecoder<'a> {
+    fn new(data: &'a [u8]) -> BoolDecoder<'a> {
+        let mut decoder = BoolDecoder {
+            data: data,
+            position: 0,
+            value: 0,
+            range: 255,
+            bit_count: 0,
+        };
+        let high = decoder.next_byte();
+        decoder.value = (high << 8) | decoder.next_byte();
+        decoder
+    }
+
+    fn next_byte(&mut self) -> u32 {
+        let byte = if self.position < self.data.len() {
+            self.data[self.position] as u32
+        } else {
+            0
+        };
+        self.position += 1;
+        byte
+    }
+
+    /// Whether the decoder has needed more data than there was.
+    fn is_exhausted(&self) -> bool {
+        self.position > self.data.len() + 1
+    }
+
+    fn read_bool(&mut self, probability: u8) -> bool {
+        let split = 1 + (((self.range - 1) * probability as u32) >> 8);
+        let big_split = split << 8;
+        let bit = if self.value >= big_split {
+            self.range -= split;
+            self.value -= big_split;
+            true
+        } else {
+            self.range = split;
+            false
+        };
+        while self.range < 128 {
+            self.value <<= 1;
+            self.range <<= 1;
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bit_count = 0;
+                let byte = self.next_byte();
+                self.value |= byte;
+            }
+        }
+        bit
+    }
+
+    fn read_flag(&mut self) -> bool {
+        self.read_bool(128)
+    }
+
+    fn read_literal(&mut self, bits: uint) -> i32 {
+        let mut value = 0;
+        for _ in range(0, bits) {
+            value = (value << 1) | (self.read_flag() as i32);
+        }
+        value
+    }
+
+    fn read_signed(&mut self, bits: uint) -> i32 {
+        let value = self.read_literal(bits);
+        if self.read_flag() { -value } else { value }
+    }
+
+    /// Reads an optional signed value, which defaults to zero.
+    fn read_optional_signed(&mut self, bits: uint) -> i32 {
+        if self.read_flag() { self.read_signed(bits) } else { 0 }
+    }
+}
+
+struct SegmentHeader {
+    enabled: bool,
+    update_map: bool,
+    absolute_delta: bool,
+    quantizer: [i32, ..4],
+    filter_strength: [i32, ..4],
+    probabilities: [u8, ..3],
+}
+
+struct FilterHeader {
+    simple: bool,
+    level: i32,
+    sharpness: i32,
+    ref_delta: i32,
+    mode_delta: i32,
+}
+
+/// The dequantization factors of a segment, for the DC and AC coefficients of each block type.
+struct Quantizer {
+    y1: [i32, ..2],
+    y2: [i32, ..2],
+    uv: [i32, ..2],
+}
+
+/// The loop filter parameters of a macroblock. A limit of zero disables filtering.
+struct FilterParameters {
+    limit: i32,
+    interior_limit: i32,
+    hev_threshold: i32,
+    inner: bool,
+}
+
+struct Macroblock {
+    segment: uint,
+    skip: bool,
+    is_4x4: bool,
+    /// The luma mode, or the 16 subblock modes in raster order.
+    modes: [u8, ..16],
+    uv_mode: u8,
+    /// The dequantized coefficients of the 16 luma blocks, then 4 U and 4 V blocks.
+    coefficients: [i16, ..384],
+    /// Whether the inner edges are filtered.
+    filter_inner: bool,
+}
+
+impl Macroblock {
+    fn new() -> Macroblock {
+        Macroblock {
+            segment: 0,
+            skip: false,
+            is_4x4: false,
+            modes: [DC_PRED, ..16],
+            uv_mode: DC_PRED,
+            coefficients: [0, ..384],
+            filter_inner: false,
+        }
+    }
+}
+
+/// Whether the last block above or to the left had non-zero coefficients, per luma column or row
+/// (0 to 3), U (4 and 5) and V (6 and 7), and for the Y2 block.
+struct NonZeroContext {
+    blocks: [bool, ..8],
+    y2: bool,
+}
+
+impl NonZeroContext {
+    fn new() -> NonZeroContext {
+        NonZeroContext {
+            blocks: [false, ..8],
+            y2: false,
+        }
+    }
+}
+
+/// Returns the width and height of a VP8 key frame without decoding it.
+pub fn dimensions(data: &[u8]) -> Result<(uint, uint), String> {
+    if data.len() < 10 {
+        return Err("truncated VP8 frame header".to_string());
+    }
+    let tag = data[0] as u32 | (data[1] as u32 << 8) | (data[2] as u32 << 16);
+    if tag & 1 != 0 {
+        return Err("VP8 frame is not a key frame".to_string());
+    }
+    if (tag >> 1) & 7 > 3 {
+        return Err("unknown VP8 profile".to_string());
+    }
+    if (tag >> 4) & 1 == 0 {
+        return Err("VP8 frame is not meant to be shown".to_string());
+    }
+    if data[3] != 0x9d || data[4] != 0x01 || data[5] != 0x2a {
+        return Err("bad VP8 start code".to_string());
+    }
+    let width = (data[6] as uint | (data[7] as uint << 8)) & 0x3fff;
+    let height = (data[8] as uint | (data[9] as uint << 8)) & 0x3fff;
+    Ok((width, height))
+}
+
+pub fn decode(data: &[u8]) -> Result<Frame, String> {
+    let (width, height) = try!(dimensions(data));
+    if width == 0 || height == 0 {
+        return Err("VP8 frame is empty".to_string());
+    }
+    let tag = data[0] as uint | (data[1] as uint << 8) | (data[2] as uint << 16);
+    let first_partition_size = tag >> 5;
+    let data = data.slice_from(10);
+    if first_partition_size > data.len() {
+        return Err("truncated VP8 first partition".to_string());
+    }
+    let mut decoder = Decoder::new(width, height, data.slice_to(first_partition_size));
+    try!(decoder.decode(data.slice_from(first_partition_size)));
+    Ok(decoder.frame)
+}
+
+struct Decoder<'a> {
+    header: BoolDecoder<'a>,
+    mb_width: uint,
+    mb_height: uint,
+    segments: SegmentHeader,
+    filter: FilterHeader,
+    quantizers: Vec<Quantizer>,
+    /// The filter parameters of each segment, for 16x16 and 4x4 predicted macroblocks.
+    filter_parameters: Vec<[FilterParameters, ..2]>,
+    coefficient_probabilities: [[[[u8, ..11], ..3], ..8], ..4],
+    skip_probability: Option<u8>,
+    /// The subblock modes along the bottom of the previous row of macroblocks, used as context.
+    top_modes: Vec<u8>,
+    top_non_zero: Vec<NonZeroContext>,
+    /// The unfiltered bottom rows of the previous row of macroblocks.
+    top_y: Vec<u8>,
+    top_u: Vec<u8>,
+    top_v: Vec<u8>,
+    work: [u8, ..WORK_SIZE],
+    frame: Frame,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(width: uint, height: uint, first_partition: &'a [u8]) -> Decoder<'a> {
+        let mb_width = (width + 15) / 16;
+        let mb_height = (height + 15) / 16;
+        Decoder {
+            header: BoolDecoder::new(first_partition),
+            mb_width: mb_width,
+            mb_height: mb_height,
+            segments: SegmentHeader {
+                enabled: false,
+                update_map: false,
+                absolute_delta: true,
+                quantizer: [0, ..4],
+                filter_strength: [0, ..4],
+                probabilities: [255, ..3],
+            },
+            filter: FilterHeader {
+                simple: false,
+                level: 0,
+                sharpness: 0,
+                ref_delta: 0,
+                mode_delta: 0,
+            },
+            quantizers: vec!(),
+            filter_parameters: vec!(),
+            coefficient_probabilities: COEFF_PROBS,
+            skip_probability: None,
+            top_modes: Vec::from_elem(mb_width * 4, DC_PRED),
+            top_non_zero: range(0, mb_width).map(|_| NonZeroContext::new()).collect(),
+            top_y: Vec::from_elem(mb_width * 16, 0u8),
+            top_u: Vec::from_elem(mb_width * 8, 0u8),
+            top_v: Vec::from_elem(mb_width * 8, 0u8),
+            work: [0, ..WORK_SIZE],
+            frame: Frame {
+                width: width,
+                height: height,
+                y: Vec::from_elem(mb_width * 16 * mb_height * 16, 0u8),
+                u: Vec::from_elem(mb_width * 8 * mb_height * 8, 0u8),
+                v: Vec::from_elem(mb_width * 8 * mb_height * 8, 0u8),
+                y_stride: mb_width * 16,
+                uv_stride: mb_width * 8,
+            },
+        }
+    }
+
+    fn decode(&mut self, rest: &'a [u8]) -> Result<(), String> {
+        // The color space and clamping type, which have only one meaningful value each.
+        self.header.read_literal(2);
+        self.read_segment_header();
+        self.read_filter_header();
+        let mut partitions = try!(read_partitions(&mut self.header, rest));
+        self.read_quantizers();
+        // Whether to keep the probabilities for the next frame, which is irrelevant here.
+        self.header.read_flag();
+        self.read_coefficient_probabilities();
+        if self.header.is_exhausted() {
+            return Err("truncated VP8 frame header".to_string());
+        }
+        self.compute_filter_parameters();
+
+        let mut macroblocks: Vec<Macroblock> =
+            range(0, self.mb_width).map(|_| Macroblock::new()).collect();
+        let partition_count = partitions.len();
+        for mb_y in range(0, self.mb_height) {
+            let mut left_modes = [DC_PRED, ..4];
+            for mb_x in range(0, self.mb_width) {
+                self.read_modes(&mut macroblocks[mb_x], mb_x, &mut left_modes);
+            }
+            if self.header.is_exhausted() {
+                return Err("truncated VP8 first partition".to_string());
+            }
+
+            let partition = &mut partitions[mb_y & (partition_count - 1)];
+            let mut left_non_zero = NonZeroContext::new();
+            for mb_x in range(0, self.mb_width) {
+                self.read_residuals(&mut macroblocks[mb_x], partition, mb_x, &mut left_non_zero);
+            }
+            if partition.is_exhausted() {
+                return Err("truncated VP8 partition".to_string());
+            }
+
+            self.reconstruct_row(macroblocks.as_slice(), mb_y);
+            if self.filter.level != 0 {
+                for mb_x in range(0, self.mb_width) {
+                    self.filter_macroblock(&macroblocks[mb_x], mb_x, mb_y);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_segment_header(&mut self) {
+        self.segments.enabled = self.header.read_flag();
+        if !self.segments.enabled {
+            return;
+        }
+        self.segments.update_map = self.header.read_flag();
+        if self.header.read_flag() {
+            self.segments.absolute_delta = self.header.read_flag();
+            for i in range(0, NUM_SEGMENTS) {
+                self.segments.quantizer[i] = self.header.read_optional_signed(7);
+            }
+            for i in range(0, NUM_SEGMENTS) {
+                self.segments.filter_strength[i] = self.header.read_optional_signed(6);
+            }
+        }
+        if self.segments.update_map {
+            for i in range(0, 3) {
+                self.segments.probabilities[i] = if self.header.read_flag() {
+                    self.header.read_literal(8) as u8
+                } else {
+                    255
+                };
+            }
+        }
+    }
+
+    fn read_filter_header(&mut self) {
+        self.filter.simple = self.header.read_flag();
+        self.filter.level = self.header.read_literal(6);
+        self.filter.sharpness = self.header.read_literal(3);
+        if self.header.read_flag() && self.header.read_flag() {
+            // Only the deltas for intra frames and for 4x4 prediction matter to a key frame.
+            for i in range(0, 4) {
+                if self.header.read_flag() {
+                    let delta = self.header.read_signed(6);
+                    if i == 0 {
+                        self.filter.ref_delta = delta;
+                    }
+                }
+            }
+            for i in range(0, 4) {
+                if self.header.read_flag() {
+                    let delta = self.header.read_signed(6);
+                    if i == 0 {
+                        self.filter.mode_delta = delta;
+                    }
+                }
+            }
+        }
+    }
+
+    fn read_quantizers(&mut self) {
+        let base = self.header.read_literal(7);
+        let y1_dc = self.header.read_optional_signed(4);
+        let y2_dc = self.header.read_optional_signed(4);
+        let y2_ac = self.header.read_optional_signed(4);
+        let uv_dc = self.header.read_optional_signed(4);
+        let uv_ac = self.header.read_optional_signed(4);
+        for segment in range(0, NUM_SEGMENTS) {
+            let mut q = base;
+            if self.segments.enabled {
+                q = self.segments.quantizer[segment];
+                if !self.segments.absolute_delta {
+                    q += base;
+                }
+            }
+            self.quantizers.push(Quantizer {
+                y1: [DC_TABLE[clip(q + y1_dc, 127)], AC_TABLE[clip(q, 127)]],
+                y2: [DC_TABLE[clip(q + y2_dc, 127)] * 2,
+                     max(AC_TABLE[clip(q + y2_ac, 127)] * 101581 >> 16, 8)],
+                uv: [DC_TABLE[clip(q + uv_dc, 117)], AC_TABLE[clip(q + uv_ac, 127)]],
+            });
+        }
+    }
+
+    fn read_coefficient_probabilities(&mut self) {
+        for i in range(0, 4) {
+            for j in range(0, 8) {
+                for k in range(0, 3) {
+                    for l in range(0, 11) {
+                        if self.header.read_bool(COEFF_UPDATE_PROBS[i][j][k][l]) {
+                            self.coefficient_probabilities[i][j][k][l] =
+                                self.header.read_literal(8) as u8;
+                        }
+                    }
+                }
+            }
+        }
+        if self.header.read_flag() {
+            self.skip_probability = Some(self.header.read_literal(8) as u8);
+        }
+    }
+
+    fn compute_filter_parameters(&mut self) {
+        for segment in range(0, NUM_SEGMENTS) {
+            let mut base = self.filter.level;
+            if self.segments.enabled {
+                base = self.segments.filter_strength[segment];
+                if !self.segments.absolute_delta {
+                    base += self.filter.level;
+                }
+            }
+            let inner_4x4 = self.filter_parameters_for(base + self.filter.ref_delta +
+                                                       self.filter.mode_delta,
+                                                       true);
+            let inner_16x16 = self.filter_parameters_for(base + self.filter.ref_delta, false);
+            self.filter_parameters.push([inner_16x16, inner_4x4]);
+        }
+    }
+
+    fn filter_parameters_for(&self, level: i32, inner: bool) -> FilterParameters {
+        let level = clip(level, 63) as i32;
+        let mut interior_limit = level;
+        if self.filter.sharpness > 0 {
+            interior_limit >>= if self.filter.sharpness > 4 { 2 } else { 1 };
+            interior_limit = min(interior_limit, 9 - self.filter.sharpness);
+        }
+        let interior_limit = max(interior_limit, 1);
+        FilterParameters {
+            limit: if level == 0 { 0 } else { 2 * level + interior_limit },
+            interior_limit: interior_limit,
+            hev_threshold: if level >= 40 { 2 } else if level >= 15 { 1 } else { 0 },
+            inner: inner,
+        }
+    }
+
+    fn read_modes(&mut self, macroblock: &mut Macroblock, mb_x: uint, left_modes: &mut [u8, ..4]) {
+        let header = &mut self.header;
+        macroblock.segment = if !self.segments.update_map {
+            0
+        } else if !header.read_bool(self.segments.probabilities[0]) {
+            header.read_bool(self.segments.probabilities[1]) as uint
+        } else {
+            header.read_bool(self.segments.probabilities[2]) as uint + 2
+        };
+        macroblock.skip = match self.skip_probability {
+            Some(probability) => header.read_bool(probability),
+            None => false,
+        };
+
+        let top_modes = self.top_modes.slice_mut(mb_x * 4, mb_x * 4 + 4);
+        macroblock.is_4x4 = !header.read_bool(145);
+        if !macroblock.is_4x4 {
+            let mode = if header.read_bool(156) {
+                if header.read_bool(128) { TM_PRED } else { HE_PRED }
+            } else {
+                if header.read_bool(163) { VE_PRED } else { DC_PRED }
+            };
+            macroblock.modes[0] = mode;
+            for i in range(0, 4) {
+                top_modes[i] = mode;
+                left_modes[i] = mode;
+            }
+        } else {
+            for y in range(0, 4) {
+                let mut left = left_modes[y];
+                for x in range(0, 4) {
+                    let probabilities = &SUBBLOCK_MODE_PROBS[top_modes[x] as uint][left as uint];
+                    let mut node = SUBBLOCK_MODE_TREE[header.read_bool(probabilities[0]) as uint];
+                    while node > 0 {
+                        let bit = header.read_bool(probabilities[node as uint]) as uint;
+                        node = SUBBLOCK_MODE_TREE[2 * node as uint + bit];
+                    }
+                    left = -node as u8;
+                    top_modes[x] = left;
+                    macroblock.modes[y * 4 + x] = left;
+                }
+                left_modes[y] = left;
+            }
+        }
+
+        macroblock.uv_mode = if !header.read_bool(142) {
+            DC_PRED
+        } else if !header.read_bool(114) {
+            VE_PRED
+        } else if header.read_bool(183) {
+            TM_PRED
+        } else {
+            HE_PRED
+        };
+    }
+
+    fn read_residuals(&mut self,
+                      macroblock: &mut Macroblock,
+                      partition: &mut BoolDecoder,
+                      mb_x: uint,
+                      left: &mut NonZeroContext) {
+        let top = &mut self.top_non_zero[mb_x];
+        let coefficients = &mut macroblock.coefficients;
+        for coefficient in coefficients.iter_mut() {
+            *coefficient = 0;
+        }
+        if macroblock.skip {
+            top.blocks = [false, ..8];
+            left.blocks = [false, ..8];
+            if !macroblock.is_4x4 {
+                top.y2 = false;
+                left.y2 = false;
+            }
+            macroblock.filter_inner = macroblock.is_4x4;
+            return;
+        }
+
+        let probabilities = &self.coefficient_probabilities;
+        let quantizer = &self.quantizers[macroblock.segment];
+        let mut non_zero = false;
+        let (first, luma_type) = if !macroblock.is_4x4 {
+            let mut y2 = [0i16, ..16];
+            let context = top.y2 as uint + left.y2 as uint;
+            let count = read_coefficients(partition, &probabilities[TYPE_Y2], context,
+                                          &quantizer.y2, 0, &mut y2);
+            top.y2 = count > 0;
+            left.y2 = count > 0;
+            inverse_walsh_hadamard(&y2, coefficients);
+            (1, TYPE_Y_AFTER_Y2)
+        } else {
+            (0, TYPE_Y_WITH_DC)
+        };
+
+        for y in range(0, 4) {
+            for x in range(0, 4) {
+                let block = coefficients.slice_mut((y * 4 + x) * 16, (y * 4 + x + 1) * 16);
+                let context = top.blocks[x] as uint + left.blocks[y] as uint;
+                let count = read_coefficients(partition, &probabilities[luma_type], context,
+                                              &quantizer.y1, first, block);
+                top.blocks[x] = count > first;
+                left.blocks[y] = count > first;
+                non_zero = non_zero || count > 1 || block[0] != 0;
+            }
+        }
+
+        for plane in range(0, 2) {
+            for y in range(0, 2) {
+                for x in range(0, 2) {
+                    let index = 16 + plane * 4 + y * 2 + x;
+                    let block = coefficients.slice_mut(index * 16, (index + 1) * 16);
+                    let context = top.blocks[4 + plane * 2 + x] as uint +
+                                  left.blocks[4 + plane * 2 + y] as uint;
+                    let count = read_coefficients(partition, &probabilities[TYPE_CHROMA],
+                                                  context, &quantizer.uv, 0, block);
+                    top.blocks[4 + plane * 2 + x] = count > 0;
+                    left.blocks[4 + plane * 2 + y] = count > 0;
+                    non_zero = non_zero || count > 1 || block[0] != 0;
+                }
+            }
+        }
+        macroblock.filter_inner = macroblock.is_4x4 || non_zero;
+    }
+
+    /// Predicts and reconstructs a row of macroblocks, and copies them to the frame.
+    fn reconstruct_row(&mut self, macroblocks: &[Macroblock], mb_y: uint) {
+        let work = &mut self.work;
+        for j in range(0, 16) {
+            work[Y_OFFSET + j * BPS - 1] = 129;
+        }
+        for j in range(0, 8) {
+            work[U_OFFSET + j * BPS - 1] = 129;
+            work[V_OFFSET + j * BPS - 1] = 129;
+        }
+        if mb_y > 0 {
+            work[Y_OFFSET - BPS - 1] = 129;
+            work[U_OFFSET - BPS - 1] = 129;
+            work[V_OFFSET - BPS - 1] = 129;
+        } else {
+            // There is nothing above the first row, which is predicted from 127s instead.
+            for sample in work.slice_mut(Y_OFFSET - BPS - 1, Y_OFFSET - BPS + 20).iter_mut() {
+                *sample = 127;
+            }
+            for sample in work.slice_mut(U_OFFSET - BPS - 1, U_OFFSET - BPS + 8).iter_mut() {
+                *sample = 127;
+            }
+            for sample in work.slice_mut(V_OFFSET - BPS - 1, V_OFFSET - BPS + 8).iter_mut() {
+                *sample = 127;
+            }
+        }
+
+        for (mb_x, macroblock) in macroblocks.iter().enumerate() {
+            // The right column of the previous macroblock becomes the left edge of this one.
+            if mb_x > 0 {
+                for j in range(0, 17) {
+                    let row = Y_OFFSET + j * BPS - BPS;
+                    for i in range(0, 4) {
+                        work[row - 4 + i] = work[row + 12 + i];
+                    }
+                }
+                for j in range(0, 9) {
+                    for &offset in [U_OFFSET, V_OFFSET].iter() {
+                        let row = offset + j * BPS - BPS;
+                        for i in range(0, 4) {
+                            work[row - 4 + i] = work[row + 4 + i];
+                        }
+                    }
+                }
+            }
+            if mb_y > 0 {
+                copy_memory(work.slice_mut(Y_OFFSET - BPS, Y_OFFSET - BPS + 16),
+                            self.top_y.slice(mb_x * 16, mb_x * 16 + 16));
+                copy_memory(work.slice_mut(U_OFFSET - BPS, U_OFFSET - BPS + 8),
+                            self.top_u.slice(mb_x * 8, mb_x * 8 + 8));
+                copy_memory(work.slice_mut(V_OFFSET - BPS, V_OFFSET - BPS + 8),
+                            self.top_v.slice(mb_x * 8, mb_x * 8 + 8));
+            }
+
+            let coefficients = &macroblock.coefficients;
+            if macroblock.is_4x4 {
+                // Subblocks on the right edge are predicted from the samples above and to the
+                // right of the macroblock, replicated from the rightmost sample at the edge of
+                // the image.
+                let top_right = Y_OFFSET - BPS + 16;
+                if mb_y > 0 {
+                    for i in range(0, 4) {
+                        work[top_right + i] = if mb_x + 1 < self.mb_width {
+                            self.top_y[(mb_x + 1) * 16 + i]
+                        } else {
+                            self.top_y[mb_x * 16 + 15]
+                        };
+                    }
+                }
+                for j in range(1, 4) {
+                    for i in range(0, 4) {
+                        work[top_right + j * 4 * BPS + i] = work[top_right + i];
+                    }
+                }
+                for n in range(0, 16) {
+                    let destination = Y_OFFSET + (n & 3) * 4 + (n >> 2) * 4 * BPS;
+                    predict_subblock(work, destination, macroblock.modes[n]);
+                    inverse_dct(coefficients.slice(n * 16, n * 16 + 16), work, destination);
+                }
+            } else {
+                let mode = edge_mode(macroblock.modes[0], mb_x, mb_y);
+                predict_block(work, Y_OFFSET, 16, mode);
+                for n in range(0, 16) {
+                    let destination = Y_OFFSET + (n & 3) * 4 + (n >> 2) * 4 * BPS;
+                    inverse_dct(coefficients.slice(n * 16, n * 16 + 16), work, destination);
+                }
+            }
+
+            let mode = edge_mode(macroblock.uv_mode, mb_x, mb_y);
+            for (plane, &offset) in [U_OFFSET, V_OFFSET].iter().enumerate() {
+                predict_block(work, offset, 8, mode);
+                for n in range(0, 4) {
+                    let destination = offset + (n & 1) * 4 + (n >> 1) * 4 * BPS;
+                    let index = 16 + plane * 4 + n;
+                    inverse_dct(coefficients.slice(index * 16, index * 16 + 16), work,
+                                destination);
+                }
+            }
+
+            if mb_y + 1 < self.mb_height {
+                copy_memory(self.top_y.slice_mut(mb_x * 16, mb_x * 16 + 16),
+                            work.slice(Y_OFFSET + 15 * BPS, Y_OFFSET + 15 * BPS + 16));
+                copy_memory(self.top_u.slice_mut(mb_x * 8, mb_x * 8 + 8),
+                            work.slice(U_OFFSET + 7 * BPS, U_OFFSET + 7 * BPS + 8));
+                copy_memory(self.top_v.slice_mut(mb_x * 8, mb_x * 8 + 8),
+                            work.slice(V_OFFSET + 7 * BPS, V_OFFSET + 7 * BPS + 8));
+            }
+
+            let frame = &mut self.frame;
+            for j in range(0, 16) {
+                let start = (mb_y * 16 + j) * frame.y_stride + mb_x * 16;
+                copy_memory(frame.y.slice_mut(start, start + 16),
+                            work.slice(Y_OFFSET + j * BPS, Y_OFFSET + j * BPS + 16));
+            }
+            for j in range(0, 8) {
+                let start = (mb_y * 8 + j) * frame.uv_stride + mb_x * 8;
+                copy_memory(frame.u.slice_mut(start, start + 8),
+                            work.slice(U_OFFSET + j * BPS, U_OFFSET + j * BPS + 8));
+                copy_memory(frame.v.slice_mut(start, start + 8),
+                            work.slice(V_OFFSET + j * BPS, V_OFFSET + j * BPS + 8));
+            }
+        }
+    }
+
+    /// Applies the loop filter of section 15 to the edges of a macroblock.
+    fn filter_macroblock(&mut self, macroblock: &Macroblock, mb_x: uint, mb_y: uint) {
+        let parameters = &self.filter_parameters[macroblock.segment][macroblock.is_4x4 as uint];
+        if parameters.limit == 0 {
+            return;
+        }
+        let inner = parameters.inner || macroblock.filter_inner;
+        let frame = &mut self.frame;
+        let y_stride = frame.y_stride;
+        let uv_stride = frame.uv_stride;
+        let y = (mb_y * 16) * y_stride + mb_x * 16;
+        let uv = (mb_y * 8) * uv_stride + mb_x * 8;
+        let limit = parameters.limit;
+        let edge_limit = limit + 4;
+
+        if self.filter.simple {
+            let pixels = frame.y.as_mut_slice();
+            if mb_x > 0 {
+                simple_filter(pixels, y, 1, y_stride, edge_limit);
+            }
+            if inner {
+                for i in range(1, 4) {
+                    simple_filter(pixels, y + i * 4, 1, y_stride, limit);
+                }
+            }
+            if mb_y > 0 {
+                simple_filter(pixels, y, y_stride, 1, edge_limit);
+            }
+            if inner {
+                for i in range(1, 4) {
+                    simple_filter(pixels, y + i * 4 * y_stride, y_stride, 1, limit);
+                }
+            }
+            return;
+        }
+
+        let interior_limit = parameters.interior_limit;
+        let hev_threshold = parameters.hev_threshold;
+        if mb_x > 0 {
+            normal_filter(frame.y.as_mut_slice(), y, 1, y_stride, 16, edge_limit,
+                          interior_limit, hev_threshold, true);
+            normal_filter(frame.u.as_mut_slice(), uv, 1, uv_stride, 8, edge_limit,
+                          interior_limit, hev_threshold, true);
+            normal_filter(frame.v.as_mut_slice(), uv, 1, uv_stride, 8, edge_limit,
+                          interior_limit, hev_threshold, true);
+        }
+        if inner {
+            for i in range(1, 4) {
+                normal_filter(frame.y.as_mut_slice(), y + i * 4, 1, y_stride, 16, limit,
+                              interior_limit, hev_threshold, false);
+            }
+            normal_filter(frame.u.as_mut_slice(), uv + 4, 1, uv_stride, 8, limit,
+                          interior_limit, hev_threshold, false);
+            normal_filter(frame.v.as_mut_slice(), uv + 4, 1, uv_stride, 8, limit,
+                          interior_limit, hev_threshold, false);
+        }
+        if mb_y > 0 {
+            normal_filter(frame.y.as_mut_slice(), y, y_stride, 1, 16, edge_limit,
+                          interior_limit, hev_threshold, true);
+            normal_filter(frame.u.as_mut_slice(), uv, uv_stride, 1, 8, edge_limit,
+                          interior_limit, hev_threshold, true);
+            normal_filter(frame.v.as_mut_slice(), uv, uv_stride, 1, 8, edge_limit,
+                          interior_limit, hev_threshold, true);
+        }
+        if inner {
+            for i in range(1, 4) {
+                normal_filter(frame.y.as_mut_slice(), y + i * 4 * y_stride, y_stride, 1, 16,
+                              limit, interior_limit, hev_threshold, false);
+            }
+            normal_filter(frame.u.as_mut_slice(), uv + 4 * uv_stride, uv_stride, 1, 8, limit,
+                          interior_limit, hev_threshold, false);
+            normal_filter(frame.v.as_mut_slice(), uv + 4 * uv_stride, uv_stride, 1, 8, limit,
+                          interior_limit, hev_threshold, false);
+        }
+    }
+}
+
+/// Splits the data after the first partition into the partitions that hold the coefficients.
+fn read_partitions<'a>(header: &mut BoolDecoder, data: &'a [u8])
+                       -> Result<Vec<BoolDecoder<'a>>, String> {
+    let count = 1 << header.read_literal(2) as uint;
+    let sizes_length = 3 * (count - 1);
+    if data.len() < sizes_length {
+        return Err("truncated VP8 partition sizes".to_string());
+    }
+    let mut partitions = vec!();
+    let mut start = sizes_length;
+    for i in range(0, count - 1) {
+        let size = data[i * 3] as uint | (data[i * 3 + 1] as uint << 8) |
+                   (data[i * 3 + 2] as uint << 16);
+        let size = min(size, data.len() - start);
+        partitions.push(BoolDecoder::new(data.slice(start, start + size)));
+        start += size;
+    }
+    if start >= data.len() {
+        return Err("truncated VP8 partitions".to_string());
+    }
+    partitions.push(BoolDecoder::new(data.slice_from(start)));
+    Ok(partitions)
+}
+
+/// Reads the tokens of a block, starting at coefficient `first`, and returns the index after the
+/// last one that was coded.
+fn read_coefficients(decoder: &mut BoolDecoder,
+                     probabilities: &[[[u8, ..11], ..3], ..8],
+                     context: uint,
+                     quantizer: &[i32, ..2],
+                     first: uint,
+                     block: &mut [i16])
+                     -> uint {
+    let mut n = first;
+    let mut p = &probabilities[BANDS[n]][context];
+    while n < 16 {
+        if !decoder.read_bool(p[0]) {
+            return n;
+        }
+        while !decoder.read_bool(p[1]) {
+            n += 1;
+            if n == 16 {
+                return 16;
+            }
+            p = &probabilities[BANDS[n]][0];
+        }
+        let (value, next_context) = if !decoder.read_bool(p[2]) {
+            (1, 1)
+        } else {
+            (read_large_value(decoder, p), 2)
+        };
+        let value = if decoder.read_flag() { -value } else { value };
+        block[ZIGZAG[n]] = (value * quantizer[if n > 0 { 1 } else { 0 }]) as i16;
+        n += 1;
+        p = &probabilities[BANDS[n]][next_context];
+    }
+    16
+}
+
+fn read_large_value(decoder: &mut BoolDecoder, p: &[u8, ..11]) -> i32 {
+    if !decoder.read_bool(p[3]) {
+        if !decoder.read_bool(p[4]) {
+            2
+        } else {
+            3 + decoder.read_bool(p[5]) as i32
+        }
+    } else if !decoder.read_bool(p[6]) {
+        if !decoder.read_bool(p[7]) {
+            5 + decoder.read_bool(159) as i32
+        } else {
+            7 + 2 * decoder.read_bool(165) as i32 + decoder.read_bool(145) as i32
+        }
+    } else {
+        let high = decoder.read_bool(p[8]) as uint;
+        let low = decoder.read_bool(p[9 + high]) as uint;
+        let category = 2 * high + low;
+        let extra_probabilities = match category {
+            0 => CAT3,
+            1 => CAT4,
+            2 => CAT5,
+            _ => CAT6,
+        };
+        let mut value = 0;
+        for &probability in extra_probabilities.iter() {
+            value = value * 2 + decoder.read_bool(probability) as i32;
+        }
+        value + 3 + (8 << category)
+    }
+}
+
+/// Transforms the Y2 block into the DC coefficients of the 16 luma blocks.
+fn inverse_walsh_hadamard(input: &[i16, ..16], coefficients: &mut [i16, ..384]) {
+    let mut temp = [0i32, ..16];
+    for i in range(0, 4) {
+        let a0 = input[i] as i32 + input[12 + i] as i32;
+        let a1 = input[4 + i] as i32 + input[8 + i] as i32;
+        let a2 = input[4 + i] as i32 - input[8 + i] as i32;
+        let a3 = input[i] as i32 - input[12 + i] as i32;
+        temp[i] = a0 + a1;
+        temp[8 + i] = a0 - a1;
+        temp[4 + i] = a3 + a2;
+        temp[12 + i] = a3 - a2;
+    }
+    for i in range(0, 4) {
+        let dc = temp[i * 4] + 3;
+        let a0 = dc + temp[i * 4 + 3];
+        let a1 = temp[i * 4 + 1] + temp[i * 4 + 2];
+        let a2 = temp[i * 4 + 1] - temp[i * 4 + 2];
+        let a3 = dc - temp[i * 4 + 3];
+        coefficients[i * 64] = ((a0 + a1) >> 3) as i16;
+        coefficients[i * 64 + 16] = ((a3 + a2) >> 3) as i16;
+        coefficients[i * 64 + 32] = ((a0 - a1) >> 3) as i16;
+        coefficients[i * 64 + 48] = ((a3 - a2) >> 3) as i16;
+    }
+}
+
+/// Adds the inverse DCT of a block of coefficients to the predicted samples at `destination`.
+fn inverse_dct(input: &[i16], work: &mut [u8], destination: uint) {
+    if input.iter().all(|&coefficient| coefficient == 0) {
+        return;
+    }
+    fn multiply1(a: i32) -> i32 { ((a * 20091) >> 16) + a }
+    fn multiply2(a: i32) -> i32 { (a * 35468) >> 16 }
+
+    let mut temp = [0i32, ..16];
+    for i in range(0, 4) {
+        let a = input[i] as i32 + input[8 + i] as i32;
+        let b = input[i] as i32 - input[8 + i] as i32;
+        let c = multiply2(input[4 + i] as i32) - multiply1(input[12 + i] as i32);
+        let d = multiply1(input[4 + i] as i32) + multiply2(input[12 + i] as i32);
+        temp[i * 4] = a + d;
+        temp[i * 4 + 1] = b + c;
+        temp[i * 4 + 2] = b - c;
+        temp[i * 4 + 3] = a - d;
+    }
+    for i in range(0, 4) {
+        let dc = temp[i] + 4;
+        let a = dc + temp[8 + i];
+        let b = dc - temp[8 + i];
+        let c = multiply2(temp[4 + i]) - multiply1(temp[12 + i]);
+        let d = multiply1(temp[4 + i]) + multiply2(temp[12 + i]);
+        let row = destination + i * BPS;
+        for (x, &value) in [a + d, b + c, b - c, a - d].iter().enumerate() {
+            work[row + x] = clip_byte(work[row + x] as i32 + (value >> 3));
+        }
+    }
+}
+
+// Values of `edge_mode` for DC prediction without some of the edges.
+const DC_PRED_NO_TOP: u8 = 10;
+const DC_PRED_NO_LEFT: u8 = 11;
+const DC_PRED_NO_TOP_LEFT: u8 = 12;
+
+/// Replaces DC prediction by a variant that leaves out the edges of the image.
+fn edge_mode(mode: u8, mb_x: uint, mb_y: uint) -> u8 {
+    if mode != DC_PRED {
+        return mode;
+    }
+    match (mb_x == 0, mb_y == 0) {
+        (true, true) => DC_PRED_NO_TOP_LEFT,
+        (true, false) => DC_PRED_NO_LEFT,
+        (false, true) => DC_PRED_NO_TOP,
+        (false, false) => DC_PRED,
+    }
+}
+
+/// Predicts a 16x16 luma or 8x8 chroma block from its edges.
+fn predict_block(work: &mut [u8], destination: uint, size: uint, mode: u8) {
+    let shift = if size == 16 { 4 } else { 3 };
+    let top = destination - BPS;
+    if mode == VE_PRED {
+        for j in range(0, size) {
+            for i in range(0, size) {
+                work[destination + j * BPS + i] = work[top + i];
+            }
+        }
+        return;
+    }
+    if mode == HE_PRED {
+        for j in range(0, size) {
+            let left = work[destination + j * BPS - 1];
+            for i in range(0, size) {
+                work[destination + j * BPS + i] = left;
+            }
+        }
+        return;
+    }
+    if mode == TM_PRED {
+        true_motion(work, destination, size);
+        return;
+    }
+
+    let mut top_sum = 0u32;
+    let mut left_sum = 0u32;
+    for i in range(0, size) {
+        top_sum += work[top + i] as u32;
+        left_sum += work[destination + i * BPS - 1] as u32;
+    }
+    let dc = if mode == DC_PRED {
+        (top_sum + left_sum + size as u32) >> (shift + 1)
+    } else if mode == DC_PRED_NO_TOP {
+        (left_sum + (size as u32 >> 1)) >> shift
+    } else if mode == DC_PRED_NO_LEFT {
+        (top_sum + (size as u32 >> 1)) >> shift
+    } else {
+        0x80
+    };
+    for j in range(0, size) {
+        for i in range(0, size) {
+            work[destination + j * BPS + i] = dc as u8;
+        }
+    }
+}
+
+fn true_motion(work: &mut [u8], destination: uint, size: uint) {
+    let top = destination - BPS;
+    let top_left = work[top - 1] as i32;
+    for j in range(0, size) {
+        let left = work[destination + j * BPS - 1] as i32;
+        for i in range(0, size) {
+            work[destination + j * BPS + i] = clip_byte(left + work[top + i] as i32 - top_left);
+        }
+    }
+}
+
+/// Predicts a 4x4 luma subblock from its edges, as in section 12.3.
+fn predict_subblock(work: &mut [u8], destination: uint, mode: u8) {
+    fn average3(a: u8, b: u8, c: u8) -> u8 {
+        ((a as u32 + 2 * b as u32 + c as u32 + 2) >> 2) as u8
+    }
+    fn average2(a: u8, b: u8) -> u8 {
+        ((a as u32 + b as u32 + 1) >> 1) as u8
+    }
+
+    if mode == TM_PRED {
+        true_motion(work, destination, 4);
+        return;
+    }
+
+    // The edge, from the bottom left sample up to the top left corner and then right to the
+    // fourth sample beyond the top right.
+    let top = destination - BPS;
+    let (i, j, k, l) = (work[destination - 1], work[destination + BPS - 1],
+                        work[destination + 2 * BPS - 1], work[destination + 3 * BPS - 1]);
+    let x = work[top - 1];
+    let (a, b, c, d) = (work[top], work[top + 1], work[top + 2], work[top + 3]);
+    let (e, f, g, h) = (work[top + 4], work[top + 5], work[top + 6], work[top + 7]);
+
+    // The predicted rows, from top to bottom.
+    let rows: [[u8, ..4], ..4] = if mode == DC_PRED {
+        let sum = a as u32 + b as u32 + c as u32 + d as u32 + i as u32 + j as u32 + k as u32 +
+                  l as u32;
+        let dc = ((sum + 4) >> 3) as u8;
+        [[dc, ..4], ..4]
+    } else if mode == VE_PRED {
+        let row = [average3(x, a, b), average3(a, b, c), average3(b, c, d), average3(c, d, e)];
+        [row, row, row, row]
+    } else if mode == HE_PRED {
+        [[average3(x, i, j), ..4], [average3(i, j, k), ..4], [average3(j, k, l), ..4],
+         [average3(k, l, l), ..4]]
+    } else if mode == RD_PRED {
+        let edge = [average3(j, k, l), average3(i, j, k), average3(x, i, j), average3(a, x, i),
+                    average3(b, a, x), average3(c, b, a), average3(d, c, b)];
+        [[edge[3], edge[4], edge[5], edge[6]],
+         [edge[2], edge[3], edge[4], edge[5]],
+         [edge[1], edge[2], edge[3], edge[4]],
+         [edge[0], edge[1], edge[2], edge[3]]]
+    } else if mode == LD_PRED {
+        let edge = [average3(a, b, c), average3(b, c, d), average3(c, d, e), average3(d, e, f),
+                    average3(e, f, g), average3(f, g, h), average3(g, h, h)];
+        [[edge[0], edge[1], edge[2], edge[3]],
+         [edge[1], edge[2], edge[3], edge[4]],
+         [edge[2], edge[3], edge[4], edge[5]],
+         [edge[3], edge[4], edge[5], edge[6]]]
+    } else if mode == VR_PRED {
+        [[average2(x, a), average2(a, b), average2(b, c), average2(c, d)],
+         [average3(i, x, a), average3(x, a, b), average3(a, b, c), average3(b, c, d)],
+         [average3(j, i, x), average2(x, a), average2(a, b), average2(b, c)],
+         [average3(k, j, i), average3(i, x, a), average3(x, a, b), average3(a, b, c)]]
+    } else if mode == VL_PRED {
+        [[average2(a, b), average2(b, c), average2(c, d), average2(d, e)],
+         [average3(a, b, c), average3(b, c, d), average3(c, d, e), average3(d, e, f)],
+         [average2(b, c), average2(c, d), average2(d, e), average3(e, f, g)],
+         [average3(b, c, d), average3(c, d, e), average3(d, e, f), average3(f, g, h)]]
+    } else if mode == HD_PRED {
+        [[average2(i, x), average3(i, x, a), average3(x, a, b), average3(a, b, c)],
+         [average2(j, i), average3(j, i, x), average2(i, x), average3(i, x, a)],
+         [average2(k, j), average3(k, j, i), average2(j, i), average3(j, i, x)],
+         [average2(l, k), average3(l, k, j), average2(k, j), average3(k, j, i)]]
+    } else {
+        [[average2(i, j), average3(i, j, k), average2(j, k), average3(j, k, l)],
+         [average2(j, k), average3(j, k, l), average2(k, l), average3(k, l, l)],
+         [average2(k, l), average3(k, l, l), l, l],
+         [l, l, l, l]]
+    };
+    for (y, row) in rows.iter().enumerate() {
+        for (x, &value) in row.iter().enumerate() {
+            work[destination + y * BPS + x] = value;
+        }
+    }
+}
+
+/// Filters the 16 pixels across an edge of the luma plane with the simple filter. `step` crosses
+/// the edge and `stride` runs along it.
+fn simple_filter(pixels: &mut [u8], position: uint, step: uint, stride: uint, limit: i32) {
+    for n in range(0, 16) {
+        let p = position + n * stride;
+        if edge_difference(pixels, p, step) <= 2 * limit + 1 {
+            common_adjust(pixels, p, step, true);
+        }
+    }
+}
+
+/// Filters `size` pixels across an edge with the normal filter, which adjusts three pixels on
+/// each side of macroblock edges and two on each side of inner edges.
+fn normal_filter(pixels: &mut [u8],
+                 position: uint,
+                 step: uint,
+                 stride: uint,
+                 size: uint,
+                 limit: i32,
+                 interior_limit: i32,
+                 hev_threshold: i32,
+                 macroblock_edge: bool) {
+    for n in range(0, size) {
+        let p = position + n * stride;
+        if edge_difference(pixels, p, step) > 2 * limit + 1 {
+            continue;
+        }
+        let (p3, p2) = (pixels[p - 4 * step] as i32, pixels[p - 3 * step] as i32);
+        let (p1, p0) = (pixels[p - 2 * step] as i32, pixels[p - step] as i32);
+        let (q0, q1) = (pixels[p] as i32, pixels[p + step] as i32);
+        let (q2, q3) = (pixels[p + 2 * step] as i32, pixels[p + 3 * step] as i32);
+        if abs(p3 - p2) > interior_limit || abs(p2 - p1) > interior_limit ||
+                abs(p1 - p0) > interior_limit || abs(q3 - q2) > interior_limit ||
+                abs(q2 - q1) > interior_limit || abs(q1 - q0) > interior_limit {
+            continue;
+        }
+        if abs(p1 - p0) > hev_threshold || abs(q1 - q0) > hev_threshold {
+            common_adjust(pixels, p, step, true);
+        } else if macroblock_edge {
+            let w = clamp_signed(clamp_signed(p1 - q1) + 3 * (q0 - p0));
+            let a = (27 * w + 63) >> 7;
+            let b = (18 * w + 63) >> 7;
+            let c = (9 * w + 63) >> 7;
+            pixels[p - 3 * step] = clip_byte(p2 + c);
+            pixels[p - 2 * step] = clip_byte(p1 + b);
+            pixels[p - step] = clip_byte(p0 + a);
+            pixels[p] = clip_byte(q0 - a);
+            pixels[p + step] = clip_byte(q1 - b);
+            pixels[p + 2 * step] = clip_byte(q2 - c);
+        } else {
+            let a = common_adjust(pixels, p, step, false);
+            let a = (a + 1) >> 1;
+            pixels[p - 2 * step] = clip_byte(p1 + a);
+            pixels[p + step] = clip_byte(q1 - a);
+        }
+    }
+}
+
+fn edge_difference(pixels: &[u8], p: uint, step: uint) -> i32 {
+    let p1 = pixels[p - 2 * step] as i32;
+    let p0 = pixels[p - step] as i32;
+    let q0 = pixels[p] as i32;
+    let q1 = pixels[p + step] as i32;
+    4 * abs(p0 - q0) + abs(p1 - q1)
+}
+
+/// Moves the two pixels next to the edge towards each other, and returns the adjustment of the
+/// pixel after the edge.
+fn common_adjust(pixels: &mut [u8], p: uint, step: uint, use_outer_taps: bool) -> i32 {
+    let p1 = pixels[p - 2 * step] as i32;
+    let p0 = pixels[p - step] as i32;
+    let q0 = pixels[p] as i32;
+    let q1 = pixels[p + step] as i32;
+    let outer = if use_outer_taps { clamp_signed(p1 - q1) } else { 0 };
+    let a = 3 * (q0 - p0) + outer;
+    let b = clamp(a + 4 >> 3, -16, 15);
+    let c = clamp(a + 3 >> 3, -16, 15);
+    pixels[p - step] = clip_byte(p0 + c);
+    pixels[p] = clip_byte(q0 - b);
+    b
+}
+
+fn abs(value: i32) -> i32 {
+    if value < 0 { -value } else { value }
+}
+
+fn clamp(value: i32, low: i32, high: i32) -> i32 {
+    if value < low { low } else if value > high { high } else { value }
+}
+
+fn clamp_signed(value: i32) -> i32 {
+    clamp(value, -128, 127)
+}
+
+fn clip_byte(value: i32) -> u8 {
+    clamp(value, 0, 255) as u8
+}
+
+fn clip(value: i32, high: i32) -> uint {
+    clamp(value, 0, high) as uint
+}
+
+static COEFF_PROBS: [[[[u8, ..11], ..3], ..8], ..4] = [
+    [
+        [
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [253, 136, 254, 255, 228, 219, 128, 128, 128, 128, 128],
+            [189, 129, 242, 255, 227, 213, 255, 219, 128, 128, 128],
+            [106, 126, 227, 252, 214, 209, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 98, 248, 255, 236, 226, 255, 255, 128, 128, 128],
+            [181, 133, 238, 254, 221, 234, 255, 154, 128, 128, 128],
+            [78, 134, 202, 247, 198, 180, 255, 219, 128, 128, 128],
+        ],
+        [
+            [1, 185, 249, 255, 243, 255, 128, 128, 128, 128, 128],
+            [184, 150, 247, 255, 236, 224, 128, 128, 128, 128, 128],
+            [77, 110, 216, 255, 236, 230, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 101, 251, 255, 241, 255, 128, 128, 128, 128, 128],
+            [170, 139, 241, 252, 236, 209, 255, 255, 128, 128, 128],
+            [37, 116, 196, 243, 228, 255, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 204, 254, 255, 245, 255, 128, 128, 128, 128, 128],
+            [207, 160, 250, 255, 238, 128, 128, 128, 128, 128, 128],
+            [102, 103, 231, 255, 211, 171, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 152, 252, 255, 240, 255, 128, 128, 128, 128, 128],
+            [177, 135, 243, 255, 234, 225, 128, 128, 128, 128, 128],
+            [80, 129, 211, 255, 194, 224, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [246, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [255, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+    ],
+    [
+        [
+            [198, 35, 237, 223, 193, 187, 162, 160, 145, 155, 62],
+            [131, 45, 198, 221, 172, 176, 220, 157, 252, 221, 1],
+            [68, 47, 146, 208, 149, 167, 221, 162, 255, 223, 128],
+        ],
+        [
+            [1, 149, 241, 255, 221, 224, 255, 255, 128, 128, 128],
+            [184, 141, 234, 253, 222, 220, 255, 199, 128, 128, 128],
+            [81, 99, 181, 242, 176, 190, 249, 202, 255, 255, 128],
+        ],
+        [
+            [1, 129, 232, 253, 214, 197, 242, 196, 255, 255, 128],
+            [99, 121, 210, 250, 201, 198, 255, 202, 128, 128, 128],
+            [23, 91, 163, 242, 170, 187, 247, 210, 255, 255, 128],
+        ],
+        [
+            [1, 200, 246, 255, 234, 255, 128, 128, 128, 128, 128],
+            [109, 178, 241, 255, 231, 245, 255, 255, 128, 128, 128],
+            [44, 130, 201, 253, 205, 192, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 132, 239, 251, 219, 209, 255, 165, 128, 128, 128],
+            [94, 136, 225, 251, 218, 190, 255, 255, 128, 128, 128],
+            [22, 100, 174, 245, 186, 161, 255, 199, 128, 128, 128],
+        ],
+        [
+            [1, 182, 249, 255, 232, 235, 128, 128, 128, 128, 128],
+            [124, 143, 241, 255, 227, 234, 128, 128, 128, 128, 128],
+            [35, 77, 181, 251, 193, 211, 255, 205, 128, 128, 128],
+        ],
+        [
+            [1, 157, 247, 255, 236, 231, 255, 255, 128, 128, 128],
+            [121, 141, 235, 255, 225, 227, 255, 255, 128, 128, 128],
+            [45, 99, 188, 251, 195, 217, 255, 224, 128, 128, 128],
+        ],
+        [
+            [1, 1, 251, 255, 213, 255, 128, 128, 128, 128, 128],
+            [203, 1, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+            [137, 1, 177, 255, 224, 255, 128, 128, 128, 128, 128],
+        ],
+    ],
+    [
+        [
+            [253, 9, 248, 251, 207, 208, 255, 192, 128, 128, 128],
+            [175, 13, 224, 243, 193, 185, 249, 198, 255, 255, 128],
+            [73, 17, 171, 221, 161, 179, 236, 167, 255, 234, 128],
+        ],
+        [
+            [1, 95, 247, 253, 212, 183, 255, 255, 128, 128, 128],
+            [239, 90, 244, 250, 211, 209, 255, 255, 128, 128, 128],
+            [155, 77, 195, 248, 188, 195, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 24, 239, 251, 218, 219, 255, 205, 128, 128, 128],
+            [201, 51, 219, 255, 196, 186, 128, 128, 128, 128, 128],
+            [69, 46, 190, 239, 201, 218, 255, 228, 128, 128, 128],
+        ],
+        [
+            [1, 191, 251, 255, 255, 128, 128, 128, 128, 128, 128],
+            [223, 165, 249, 255, 213, 255, 128, 128, 128, 128, 128],
+            [141, 124, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 16, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+            [190, 36, 230, 255, 236, 255, 128, 128, 128, 128, 128],
+            [149, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 226, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [247, 192, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [240, 128, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 134, 252, 255, 255, 128, 128, 128, 128, 128, 128],
+            [213, 62, 250, 255, 255, 128, 128, 128, 128, 128, 128],
+            [55, 93, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+    ],
+    [
+        [
+            [202, 24, 213, 235, 186, 191, 220, 160, 240, 175, 255],
+            [126, 38, 182, 232, 169, 184, 228, 174, 255, 187, 128],
+            [61, 46, 138, 219, 151, 178, 240, 170, 255, 216, 128],
+        ],
+        [
+            [1, 112, 230, 250, 199, 191, 247, 159, 255, 255, 128],
+            [166, 109, 228, 252, 211, 215, 255, 174, 128, 128, 128],
+            [39, 77, 162, 232, 172, 180, 245, 178, 255, 255, 128],
+        ],
+        [
+            [1, 52, 220, 246, 198, 199, 249, 220, 255, 255, 128],
+            [124, 74, 191, 243, 183, 193, 250, 221, 255, 255, 128],
+            [24, 71, 130, 219, 154, 170, 243, 182, 255, 255, 128],
+        ],
+        [
+            [1, 182, 225, 249, 219, 240, 255, 224, 128, 128, 128],
+            [149, 150, 226, 252, 216, 205, 255, 171, 128, 128, 128],
+            [28, 108, 170, 242, 183, 194, 254, 223, 255, 255, 128],
+        ],
+        [
+            [1, 81, 230, 252, 204, 203, 255, 192, 128, 128, 128],
+            [123, 102, 209, 247, 188, 196, 255, 233, 128, 128, 128],
+            [20, 95, 153, 243, 164, 173, 255, 203, 128, 128, 128],
+        ],
+        [
+            [1, 222, 248, 255, 216, 213, 128, 128, 128, 128, 128],
+            [168, 175, 246, 252, 235, 205, 255, 255, 128, 128, 128],
+            [47, 116, 215, 255, 211, 212, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 121, 236, 253, 212, 214, 255, 255, 128, 128, 128],
+            [141, 84, 213, 252, 201, 202, 255, 219, 128, 128, 128],
+            [42, 80, 160, 240, 162, 185, 255, 205, 128, 128, 128],
+        ],
+        [
+            [1, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [244, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [238, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+    ],
+];
+
+static COEFF_UPDATE_PROBS: [[[[u8, ..11], ..3], ..8], ..4] = [
+    [
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [176, 246, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [223, 241, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+            [249, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 244, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+            [234, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 246, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [239, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 248, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [251, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [251, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 253, 255, 254, 255, 255, 255, 255, 255, 255],
+            [250, 255, 254, 255, 254, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+    [
+        [
+            [217, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [225, 252, 241, 253, 255, 255, 254, 255, 255, 255, 255],
+            [234, 250, 241, 250, 253, 255, 253, 254, 255, 255, 255],
+        ],
+        [
+            [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [223, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [238, 253, 254, 254, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 248, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [249, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [247, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [252, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [250, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+    [
+        [
+            [186, 251, 250, 255, 255, 255, 255, 255, 255, 255, 255],
+            [234, 251, 244, 254, 255, 255, 255, 255, 255, 255, 255],
+            [251, 251, 243, 253, 254, 255, 254, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [236, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [251, 253, 253, 254, 254, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+    [
+        [
+            [248, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [250, 254, 252, 254, 255, 255, 255, 255, 255, 255, 255],
+            [248, 254, 249, 253, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [246, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [252, 254, 251, 254, 254, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+            [248, 254, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 255, 254, 254, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 251, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [245, 251, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 251, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [252, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 252, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [249, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [250, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+];
+
+static SUBBLOCK_MODE_PROBS: [[[u8, ..9], ..10], ..10] = [
+    [
+        [231, 120, 48, 89, 115, 113, 120, 152, 112],
+        [152, 179, 64, 126, 170, 118, 46, 70, 95],
+        [175, 69, 143, 80, 85, 82, 72, 155, 103],
+        [56, 58, 10, 171, 218, 189, 17, 13, 152],
+        [114, 26, 17, 163, 44, 195, 21, 10, 173],
+        [121, 24, 80, 195, 26, 62, 44, 64, 85],
+        [144, 71, 10, 38, 171, 213, 144, 34, 26],
+        [170, 46, 55, 19, 136, 160, 33, 206, 71],
+        [63, 20, 8, 114, 114, 208, 12, 9, 226],
+        [81, 40, 11, 96, 182, 84, 29, 16, 36],
+    ],
+    [
+        [134, 183, 89, 137, 98, 101, 106, 165, 148],
+        [72, 187, 100, 130, 157, 111, 32, 75, 80],
+        [66, 102, 167, 99, 74, 62, 40, 234, 128],
+        [41, 53, 9, 178, 241, 141, 26, 8, 107],
+        [74, 43, 26, 146, 73, 166, 49, 23, 157],
+        [65, 38, 105, 160, 51, 52, 31, 115, 128],
+        [104, 79, 12, 27, 217, 255, 87, 17, 7],
+        [87, 68, 71, 44, 114, 51, 15, 186, 23],
+        [47, 41, 14, 110, 182, 183, 21, 17, 194],
+        [66, 45, 25, 102, 197, 189, 23, 18, 22],
+    ],
+    [
+        [88, 88, 147, 150, 42, 46, 45, 196, 205],
+        [43, 97, 183, 117, 85, 38, 35, 179, 61],
+        [39, 53, 200, 87, 26, 21, 43, 232, 171],
+        [56, 34, 51, 104, 114, 102, 29, 93, 77],
+        [39, 28, 85, 171, 58, 165, 90, 98, 64],
+        [34, 22, 116, 206, 23, 34, 43, 166, 73],
+        [107, 54, 32, 26, 51, 1, 81, 43, 31],
+        [68, 25, 106, 22, 64, 171, 36, 225, 114],
+        [34, 19, 21, 102, 132, 188, 16, 76, 124],
+        [62, 18, 78, 95, 85, 57, 50, 48, 51],
+    ],
+    [
+        [193, 101, 35, 159, 215, 111, 89, 46, 111],
+        [60, 148, 31, 172, 219, 228, 21, 18, 111],
+        [112, 113, 77, 85, 179, 255, 38, 120, 114],
+        [40, 42, 1, 196, 245, 209, 10, 25, 109],
+        [88, 43, 29, 140, 166, 213, 37, 43, 154],
+        [61, 63, 30, 155, 67, 45, 68, 1, 209],
+        [100, 80, 8, 43, 154, 1, 51, 26, 71],
+        [142, 78, 78, 16, 255, 128, 34, 197, 171],
+        [41, 40, 5, 102, 211, 183, 4, 1, 221],
+        [51, 50, 17, 168, 209, 192, 23, 25, 82],
+    ],
+    [
+        [138, 31, 36, 171, 27, 166, 38, 44, 229],
+        [67, 87, 58, 169, 82, 115, 26, 59, 179],
+        [63, 59, 90, 180, 59, 166, 93, 73, 154],
+        [40, 40, 21, 116, 143, 209, 34, 39, 175],
+        [47, 15, 16, 183, 34, 223, 49, 45, 183],
+        [46, 17, 33, 183, 6, 98, 15, 32, 183],
+        [57, 46, 22, 24, 128, 1, 54, 17, 37],
+        [65, 32, 73, 115, 28, 128, 23, 128, 205],
+        [40, 3, 9, 115, 51, 192, 18, 6, 223],
+        [87, 37, 9, 115, 59, 77, 64, 21, 47],
+    ],
+    [
+        [104, 55, 44, 218, 9, 54, 53, 130, 226],
+        [64, 90, 70, 205, 40, 41, 23, 26, 57],
+        [54, 57, 112, 184, 5, 41, 38, 166, 213],
+        [30, 34, 26, 133, 152, 116, 10, 32, 134],
+        [39, 19, 53, 221, 26, 114, 32, 73, 255],
+        [31, 9, 65, 234, 2, 15, 1, 118, 73],
+        [75, 32, 12, 51, 192, 255, 160, 43, 51],
+        [88, 31, 35, 67, 102, 85, 55, 186, 85],
+        [56, 21, 23, 111, 59, 205, 45, 37, 192],
+        [55, 38, 70, 124, 73, 102, 1, 34, 98],
+    ],
+    [
+        [125, 98, 42, 88, 104, 85, 117, 175, 82],
+        [95, 84, 53, 89, 128, 100, 113, 101, 45],
+        [75, 79, 123, 47, 51, 128, 81, 171, 1],
+        [57, 17, 5, 71, 102, 57, 53, 41, 49],
+        [38, 33, 13, 121, 57, 73, 26, 1, 85],
+        [41, 10, 67, 138, 77, 110, 90, 47, 114],
+        [115, 21, 2, 10, 102, 255, 166, 23, 6],
+        [101, 29, 16, 10, 85, 128, 101, 196, 26],
+        [57, 18, 10, 102, 102, 213, 34, 20, 43],
+        [117, 20, 15, 36, 163, 128, 68, 1, 26],
+    ],
+    [
+        [102, 61, 71, 37, 34, 53, 31, 243, 192],
+        [69, 60, 71, 38, 73, 119, 28, 222, 37],
+        [68, 45, 128, 34, 1, 47, 11, 245, 171],
+        [62, 17, 19, 70, 146, 85, 55, 62, 70],
+        [37, 43, 37, 154, 100, 163, 85, 160, 1],
+        [63, 9, 92, 136, 28, 64, 32, 201, 85],
+        [75, 15, 9, 9, 64, 255, 184, 119, 16],
+        [86, 6, 28, 5, 64, 255, 25, 248, 1],
+        [56, 8, 17, 132, 137, 255, 55, 116, 128],
+        [58, 15, 20, 82, 135, 57, 26, 121, 40],
+    ],
+    [
+        [164, 50, 31, 137, 154, 133, 25, 35, 218],
+        [51, 103, 44, 131, 131, 123, 31, 6, 158],
+        [86, 40, 64, 135, 148, 224, 45, 183, 128],
+        [22, 26, 17, 131, 240, 154, 14, 1, 209],
+        [45, 16, 21, 91, 64, 222, 7, 1, 197],
+        [56, 21, 39, 155, 60, 138, 23, 102, 213],
+        [83, 12, 13, 54, 192, 255, 68, 47, 28],
+        [85, 26, 85, 85, 128, 128, 32, 146, 171],
+        [18, 11, 7, 63, 144, 171, 4, 4, 246],
+        [35, 27, 10, 146, 174, 171, 12, 26, 128],
+    ],
+    [
+        [190, 80, 35, 99, 180, 80, 126, 54, 45],
+        [85, 126, 47, 87, 176, 51, 41, 20, 32],
+        [101, 75, 128, 139, 118, 146, 116, 128, 85],
+        [56, 41, 15, 176, 236, 85, 37, 9, 62],
+        [71, 30, 17, 119, 118, 255, 17, 18, 138],
+        [101, 38, 60, 138, 55, 70, 43, 26, 142],
+        [146, 36, 19, 30, 171, 255, 97, 27, 20],
+        [138, 45, 61, 62, 219, 1, 81, 188, 64],
+        [32, 41, 20, 117, 151, 142, 20, 21, 163],
+        [112, 19, 12, 61, 195, 128, 48, 4, 24],
+    ],
+];
+
+static DC_TABLE: [i32, ..128] = [
+    4, 5, 6, 7, 8, 9, 10, 10, 11, 12, 13, 14, 15, 16, 17, 17, 18, 19, 20, 20, 21, 21, 22, 22, 23,
+    23, 24, 25, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 37, 38, 39, 40, 41, 42, 43, 44,
+    45, 46, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67,
+    68, 69, 70, 71, 72, 73, 74, 75, 76, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 91,
+    93, 95, 96, 98, 100, 101, 102, 104, 106, 108, 110, 112, 114, 116, 118, 122, 124, 126, 128, 130,
+    132, 134, 136, 138, 140, 143, 145, 148, 151, 154, 157,
+];
+
+static AC_TABLE: [i32, ..128] = [
+    4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28,
+    29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52,
+    53, 54, 55, 56, 57, 58, 60, 62, 64, 66, 68, 70, 72, 74, 76, 78, 80, 82, 84, 86, 88, 90, 92, 94,
+    96, 98, 100, 102, 104, 106, 108, 110, 112, 114, 116, 119, 122, 125, 128, 131, 134, 137, 140,
+    143, 146, 149, 152, 155, 158, 161, 164, 167, 170, 173, 177, 181, 185, 189, 193, 197, 201, 205,
+    209, 213, 217, 221, 225, 229, 234, 239, 245, 249, 254, 259, 264, 269, 274, 279, 284,
+];
+
+#[test]
+fn test_dimensions() {
+    // The start of a 33x31 key frame.
+    let header = [0x90, 0x0f, 0x00, 0x9d, 0x01, 0x2a, 0x21, 0x00, 0x1f, 0x00];
+    assert_eq!(dimensions(&header), Ok((33, 31)));
+    assert!(dimensions(header.slice_to(9)).is_err());
+
+    let mut inter_frame = header;
+    inter_frame[0] |= 1;
+    assert!(dimensions(&inter_frame).is_err());
+}
+
+#[test]
+fn test_inverse_walsh_hadamard() {
+    let mut y2 = [0i16, ..16];
+    y2[0] = 80;
+    let mut coefficients = [0i16, ..384];
+    inverse_walsh_hadamard(&y2, &mut coefficients);
+    for block in range(0, 16) {
+        assert_eq!(coefficients[block * 16], 10);
+    }
+}
+
+#[test]
+fn test_inverse_dct() {
+    // A block with only a DC coefficient adds the same value to each of its samples.
+    let mut block = [0i16, ..16];
+    block[0] = 80;
+    let mut work = [100u8, ..WORK_SIZE];
+    inverse_dct(&block, &mut work, Y_OFFSET);
+    for y in range(0, 4) {
+        for x in range(0, 4) {
+            assert_eq!(work[Y_OFFSET + y * BPS + x], 110);
+        }
+        assert_eq!(work[Y_OFFSET + y * BPS + 4], 100);
+    }
+}
@@ -0,0 +1,752 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A decoder for the lossless bitstream of WebP images, which is also used to compress the alpha
+//! channel of lossy images. It produces pixels as ARGB words.
+//!
+//! https://developers.google.com/speed/webp/docs/webp_lossless_bitstream_specification
+
+static SIGNATURE: u8 = 0x2f;
+
+static PREDICTOR_TRANSFORM: uint = 0;
+static CROSS_COLOR_TRANSFORM: uint = 1;
+static SUBTRACT_GREEN_TRANSFORM: uint = 2;
+static COLOR_INDEXING_TRANSFORM: uint = 3;
+
+static MAX_CACHE_BITS: uint = 11;
+static MAX_CODE_LENGTH: uint = 15;
+static NUM_LITERAL_CODES: uint = 256;
+static NUM_LENGTH_CODES: uint = 24;
+static NUM_DISTANCE_CODES: uint = 40;
+static DEFAULT_CODE_LENGTH: u8 = 8;
+
+static CODE_LENGTH_ORDER: [uint, ..19] = [
+    17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+/// The offsets of the 120 short distance codes, as a row above (high nibble) and a column to the
+/// left of 8 (low nibble).
+static DISTANCE_MAP: [u8, ..120] = [
+    0x18, 0x07, 0x17, 0x19, 0x28, 0x06, 0x27, 0x29, 0x16, 0x1a, 0x26, 0x2a, 0x38, 0x05, 0x37,
+    0x39, 0x15, 0x1b, 0x36, 0x3a, 0x25, 0x2b, 0x48, 0x04, 0x47, 0x49, 0x14, 0x1c, 0x35, 0x3b,
+    0x46, 0x4a, 0x24, 0x2c, 0x58, 0x45, 0x4b, 0x34, 0x3c, 0x03, 0x57, 0x59, 0x13, 0x1d, 0x56,
+    0x5a, 0x23, 0x2d, 0x44, 0x4c, 0x55, 0x5b, 0x33, 0x3d, 0x68, 0x02, 0x67, 0x69, 0x12, 0x1e,
+    0x66, 0x6a, 0x22, 0x2e, 0x54, 0x5c, 0x43, 0x4d, 0x65, 0x6b, 0x32, 0x3e, 0x78, 0x01, 0x77,
+    0x79, 0x53, 0x5d, 0x11, 0x1f, 0x64, 0x6c, 0x42, 0x4e, 0x76, 0x7a, 0x21, 0x2f, 0x75, 0x7b,
+    0x31, 0x3f, 0x63, 0x6d, 0x52, 0x5e, 0x00, 0x74, 0x7c, 0x41, 0x4f, 0x10, 0x20, 0x62, 0x6e,
+    0x30, 0x73, 0x7d, 0x51, 0x5f, 0x40, 0x72, 0x7e, 0x61, 0x6f, 0x50, 0x71, 0x7f, 0x60, 0x70,
+];
+
+/// The number of bits that Huffman codes look up at once; longer codes are decoded bit by bit.
+static TABLE_BITS: uint = 8;
+
+/// Reads bits least significant first. Reading past the end yields zeros and marks the reader as
+/// exhausted.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: uint,
+    buffer: u64,
+    buffered_bits: uint,
+    exhausted: bool,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data: data,
+            position: 0,
+            buffer: 0,
+            buffered_bits: 0,
+            exhausted: false,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.buffered_bits <= 56 && self.position < self.data.len() {
+            self.buffer |= (self.data[self.position] as u64) << self.buffered_bits;
+            self.buffered_bits += 8;
+            self.position += 1;
+        }
+    }
+
+    /// Returns the next bits without consuming them, padded with zeros at the end of the data.
+    fn peek(&mut self, count: uint) -> u32 {
+        if self.buffered_bits < count {
+            self.fill();
+        }
+        (self.buffer & ((1 << count) - 1)) as u32
+    }
+
+    fn consume(&mut self, count: uint) {
+        if count > self.buffered_bits {
+            self.exhausted = true;
+            self.buffer = 0;
+            self.buffered_bits = 0;
+        } else {
+            self.buffer >>= count;
+            self.buffered_bits -= count;
+        }
+    }
+
+    fn read_bits(&mut self, count: uint) -> u32 {
+        let value = self.peek(count);
+        self.consume(count);
+        value
+    }
+}
+
+/// A canonical Huffman code. Codes of up to `TABLE_BITS` bits are decoded with a lookup table
+/// whose entries hold the symbol and the code length; longer codes fall back to the counts of
+/// codes of each length and the symbols in code order.
+struct HuffmanCode {
+    /// The symbol of a code with only one symbol, which takes no bits.
+    single_symbol: Option<u16>,
+    table: Vec<u16>,
+    counts: [u16, ..16],
+    symbols: Vec<u16>,
+}
+
+impl HuffmanCode {
+    fn new(lengths: &[u8]) -> Result<HuffmanCode, String> {
+        let mut counts = [0u16, ..16];
+        for &length in lengths.iter() {
+            counts[length as uint] += 1;
+        }
+        counts[0] = 0;
+        let used = counts.iter().fold(0, |sum, &count| sum + count as uint);
+        if used == 0 {
+            return Err("empty Huffman code in WebP data".to_string());
+        }
+        if used == 1 {
+            let symbol = lengths.iter().position(|&length| length != 0).unwrap();
+            return Ok(HuffmanCode {
+                single_symbol: Some(symbol as u16),
+                table: vec!(),
+                counts: counts,
+                symbols: vec!(),
+            });
+        }
+
+        // The code must be complete: every sequence of bits must start with a code.
+        let mut open = 1i32;
+        for length in range(1, MAX_CODE_LENGTH + 1) {
+            open = open * 2 - counts[length] as i32;
+            if open < 0 {
+                return Err("oversubscribed Huffman code in WebP data".to_string());
+            }
+        }
+        if open != 0 {
+            return Err("incomplete Huffman code in WebP data".to_string());
+        }
+
+        let mut offsets = [0u16, ..16];
+        for length in range(1, MAX_CODE_LENGTH) {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = Vec::from_elem(used, 0u16);
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as uint] as uint] = symbol as u16;
+                offsets[length as uint] += 1;
+            }
+        }
+
+        // Bits arrive least significant first, so the table is indexed by reversed codes.
+        let mut table = Vec::from_elem(1 << TABLE_BITS, 0u16);
+        let mut code = 0u32;
+        let mut index = 0;
+        for length in range(1, TABLE_BITS + 1) {
+            for _ in range(0, counts[length] as uint) {
+                let mut reversed = 0;
+                for bit in range(0, length) {
+                    reversed |= ((code >> bit) & 1) << (length - 1 - bit);
+                }
+                let entry = (symbols[index] << 4) | length as u16;
+                let mut i = reversed as uint;
+                while i < table.len() {
+                    table[i] = entry;
+                    i += 1 << length;
+                }
+                code += 1;
+                index += 1;
+            }
+            code <<= 1;
+        }
+
+        Ok(HuffmanCode {
+            single_symbol: None,
+            table: table,
+            counts: counts,
+            symbols: symbols,
+        })
+    }
+
+    fn read_symbol(&self, reader: &mut BitReader) -> uint {
+        match self.single_symbol {
+            Some(symbol) => return symbol as uint,
+            None => {}
+        }
+        let entry = self.table[reader.peek(TABLE_BITS) as uint];
+        if entry & 0xf != 0 {
+            reader.consume((entry & 0xf) as uint);
+            return (entry >> 4) as uint;
+        }
+
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for length in range(1, MAX_CODE_LENGTH + 1) {
+            code |= reader.read_bits(1) as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return self.symbols[(index + code - first) as uint] as uint;
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        // Complete codes always end within the maximum length.
+        0
+    }
+}
+
+/// The five codes that decode a pixel: green and backward reference lengths and color cache
+/// indices, red, blue, alpha and backward reference distances.
+struct HuffmanGroup {
+    codes: Vec<HuffmanCode>,
+}
+
+/// The Huffman codes of an image, possibly varying between tiles of `1 << tile_bits` pixels.
+struct HuffmanCodes {
+    groups: Vec<HuffmanGroup>,
+    /// The group of each tile, or nothing if the whole image uses the first group.
+    tiles: Option<Vec<u32>>,
+    tile_bits: uint,
+    tiles_per_row: uint,
+}
+
+impl HuffmanCodes {
+    fn group_at(&self, x: uint, y: uint) -> &HuffmanGroup {
+        match self.tiles {
+            Some(ref tiles) => {
+                let tile = (y >> self.tile_bits) * self.tiles_per_row + (x >> self.tile_bits);
+                &self.groups[tiles[tile] as uint]
+            }
+            None => &self.groups[0],
+        }
+    }
+}
+
+struct Transform {
+    kind: uint,
+    /// The width of the image that this transform produces.
+    width: uint,
+    bits: uint,
+    data: Vec<u32>,
+}
+
+/// Returns the width and height of a lossless bitstream and whether it uses alpha.
+pub fn header(data: &[u8]) -> Result<(uint, uint, bool), String> {
+    if data.len() < 5 || data[0] != SIGNATURE {
+        return Err("bad VP8L signature".to_string());
+    }
+    let mut reader = BitReader::new(data.slice_from(1));
+    let width = reader.read_bits(14) as uint + 1;
+    let height = reader.read_bits(14) as uint + 1;
+    let has_alpha = reader.read_bits(1) == 1;
+    if reader.read_bits(3) != 0 {
+        return Err("unknown VP8L version".to_string());
+    }
+    Ok((width, height, has_alpha))
+}
+
+/// Decodes a lossless image, with its header.
+pub fn decode(data: &[u8]) -> Result<(uint, uint, Vec<u32>), String> {
+    let (width, height, _) = try!(header(data));
+    let pixels = try!(decode_headerless(data.slice_from(5), width, height));
+    Ok((width, height, pixels))
+}
+
+/// Decodes a lossless image stream whose size is known, as in alpha chunks.
+pub fn decode_headerless(data: &[u8], width: uint, height: uint) -> Result<Vec<u32>, String> {
+    let mut reader = BitReader::new(data);
+    let mut transforms: Vec<Transform> = vec!();
+    let mut encoded_width = width;
+    while reader.read_bits(1) == 1 {
+        let transform = try!(read_transform(&mut reader, encoded_width, height));
+        if transforms.iter().any(|other| other.kind == transform.kind) {
+            return Err("repeated VP8L transform".to_string());
+        }
+        if transform.kind == COLOR_INDEXING_TRANSFORM {
+            encoded_width = subsample_size(encoded_width, transform.bits);
+        }
+        transforms.push(transform);
+    }
+
+    let mut pixels = try!(decode_image_stream(&mut reader, encoded_width, height, true));
+    for transform in transforms.iter().rev() {
+        pixels = apply_inverse_transform(transform, pixels.as_slice(), height);
+    }
+    Ok(pixels)
+}
+
+fn subsample_size(size: uint, bits: uint) -> uint {
+    (size + (1 << bits) - 1) >> bits
+}
+
+fn read_transform(reader: &mut BitReader, width: uint, height: uint)
+                  -> Result<Transform, String> {
+    let kind = reader.read_bits(2) as uint;
+    let mut transform = Transform {
+        kind: kind,
+        width: width,
+        bits: 0,
+        data: vec!(),
+    };
+    if kind == PREDICTOR_TRANSFORM || kind == CROSS_COLOR_TRANSFORM {
+        transform.bits = reader.read_bits(3) as uint + 2;
+        transform.data = try!(decode_image_stream(reader,
+                                                  subsample_size(width, transform.bits),
+                                                  subsample_size(height, transform.bits),
+                                                  false));
+    } else if kind == COLOR_INDEXING_TRANSFORM {
+        let color_count = reader.read_bits(8) as uint + 1;
+        transform.bits = if color_count > 16 {
+            0
+        } else if color_count > 4 {
+            1
+        } else if color_count > 2 {
+            2
+        } else {
+            3
+        };
+        let palette = try!(decode_image_stream(reader, color_count, 1, false));
+        // The palette is delta coded, and padded with transparent black so that any index that
+        // fits in the packed bits is valid.
+        let mut colors = Vec::from_elem(1 << (8 >> transform.bits), 0u32);
+        colors[0] = palette[0];
+        for i in range(1, color_count) {
+            colors[i] = add_pixels(palette[i], colors[i - 1]);
+        }
+        transform.data = colors;
+    }
+    Ok(transform)
+}
+
+/// Decodes an entropy coded image. Only the main image may use a color cache and vary its
+/// Huffman codes between tiles.
+fn decode_image_stream(reader: &mut BitReader, width: uint, height: uint, is_main_image: bool)
+                       -> Result<Vec<u32>, String> {
+    let cache_bits = if reader.read_bits(1) == 1 {
+        let bits = reader.read_bits(4) as uint;
+        if bits < 1 || bits > MAX_CACHE_BITS {
+            return Err("bad VP8L color cache size".to_string());
+        }
+        bits
+    } else {
+        0
+    };
+    let codes = try!(read_huffman_codes(reader, width, height, cache_bits, is_main_image));
+    if reader.exhausted {
+        return Err("truncated VP8L data".to_string());
+    }
+    let pixels = try!(decode_pixels(reader, width, height, cache_bits, &codes));
+    if reader.exhausted {
+        return Err("truncated VP8L data".to_string());
+    }
+    Ok(pixels)
+}
+
+fn read_huffman_codes(reader: &mut BitReader,
+                      width: uint,
+                      height: uint,
+                      cache_bits: uint,
+                      is_main_image: bool)
+                      -> Result<HuffmanCodes, String> {
+    let mut codes = HuffmanCodes {
+        groups: vec!(),
+        tiles: None,
+        tile_bits: 0,
+        tiles_per_row: 0,
+    };
+    // Maps the groups that the tiles use to their index in `codes.groups`; unused groups are
+    // still read, but not kept.
+    let mut group_indices = vec!(Some(0u));
+    if is_main_image && reader.read_bits(1) == 1 {
+        codes.tile_bits = reader.read_bits(3) as uint + 2;
+        codes.tiles_per_row = subsample_size(width, codes.tile_bits);
+        let mut tiles = try!(decode_image_stream(reader,
+                                                 codes.tiles_per_row,
+                                                 subsample_size(height, codes.tile_bits),
+                                                 false));
+        let max_group = tiles.iter().fold(0, |max, &tile| {
+            let group = (tile >> 8) & 0xffff;
+            if group > max { group } else { max }
+        });
+        group_indices = Vec::from_elem(max_group as uint + 1, None);
+        for &tile in tiles.iter() {
+            group_indices[((tile >> 8) & 0xffff) as uint] = Some(0);
+        }
+        let mut group_count = 0;
+        for index in group_indices.iter_mut() {
+            if index.is_some() {
+                *index = Some(group_count);
+                group_count += 1;
+            }
+        }
+        for tile in tiles.iter_mut() {
+            *tile = group_indices[((*tile >> 8) & 0xffff) as uint].unwrap() as u32;
+        }
+        codes.tiles = Some(tiles);
+    }
+
+    let alphabet_sizes = [NUM_LITERAL_CODES + NUM_LENGTH_CODES +
+                              if cache_bits > 0 { 1 << cache_bits } else { 0 },
+                          NUM_LITERAL_CODES,
+                          NUM_LITERAL_CODES,
+                          NUM_LITERAL_CODES,
+                          NUM_DISTANCE_CODES];
+    for index in group_indices.iter() {
+        let mut group = HuffmanGroup {
+            codes: vec!(),
+        };
+        for &alphabet_size in alphabet_sizes.iter() {
+            group.codes.push(try!(read_huffman_code(reader, alphabet_size)));
+        }
+        if index.is_some() {
+            codes.groups.push(group);
+        }
+    }
+    Ok(codes)
+}
+
+fn read_huffman_code(reader: &mut BitReader, alphabet_size: uint) -> Result<HuffmanCode, String> {
+    let mut lengths = Vec::from_elem(alphabet_size, 0u8);
+    if reader.read_bits(1) == 1 {
+        // A simple code of one or two symbols.
+        let symbol_count = reader.read_bits(1) + 1;
+        let first_bits = if reader.read_bits(1) == 0 { 1 } else { 8 };
+        let first = reader.read_bits(first_bits) as uint;
+        if first < alphabet_size {
+            lengths[first] = 1;
+        }
+        if symbol_count == 2 {
+            let second = reader.read_bits(8) as uint;
+            if second < alphabet_size {
+                lengths[second] = 1;
+            }
+        }
+    } else {
+        let mut code_length_lengths = [0u8, ..19];
+        let count = reader.read_bits(4) as uint + 4;
+        for &index in CODE_LENGTH_ORDER.slice_to(count).iter() {
+            code_length_lengths[index] = reader.read_bits(3) as u8;
+        }
+        let code_length_code = try!(HuffmanCode::new(&code_length_lengths));
+
+        // The number of code length codes to read, if fewer than the symbols.
+        let mut remaining_codes = if reader.read_bits(1) == 1 {
+            let bits = 2 + 2 * reader.read_bits(3) as uint;
+            let count = 2 + reader.read_bits(bits) as uint;
+            if count > alphabet_size {
+                return Err("bad VP8L code length count".to_string());
+            }
+            count
+        } else {
+            alphabet_size
+        };
+
+        let mut symbol = 0;
+        let mut previous_length = DEFAULT_CODE_LENGTH;
+        while symbol < alphabet_size && remaining_codes > 0 {
+            remaining_codes -= 1;
+            let code = code_length_code.read_symbol(reader);
+            if code < 16 {
+                lengths[symbol] = code as u8;
+                symbol += 1;
+                if code != 0 {
+                    previous_length = code as u8;
+                }
+                continue;
+            }
+            let (length, repeat) = match code {
+                16 => (previous_length, 3 + reader.read_bits(2) as uint),
+                17 => (0, 3 + reader.read_bits(3) as uint),
+                _ => (0, 11 + reader.read_bits(7) as uint),
+            };
+            if symbol + repeat > alphabet_size {
+                return Err("bad VP8L code lengths".to_string());
+            }
+            for _ in range(0, repeat) {
+                lengths[symbol] = length;
+                symbol += 1;
+            }
+        }
+    }
+    if reader.exhausted {
+        return Err("truncated VP8L data".to_string());
+    }
+    HuffmanCode::new(lengths.as_slice())
+}
+
+fn decode_pixels(reader: &mut BitReader,
+                 width: uint,
+                 height: uint,
+                 cache_bits: uint,
+                 codes: &HuffmanCodes)
+                 -> Result<Vec<u32>, String> {
+    let pixel_count = width * height;
+    let mut pixels = Vec::with_capacity(pixel_count);
+    let mut cache = Vec::from_elem(if cache_bits > 0 { 1 << cache_bits } else { 0 }, 0u32);
+    // The number of pixels that have been added to the color cache.
+    let mut cached = 0;
+    let (mut x, mut y) = (0, 0);
+    while pixels.len() < pixel_count {
+        let group = codes.group_at(x, y);
+        let green = group.codes[0].read_symbol(reader);
+        if green < NUM_LITERAL_CODES {
+            let red = group.codes[1].read_symbol(reader) as u32;
+            let blue = group.codes[2].read_symbol(reader) as u32;
+            let alpha = group.codes[3].read_symbol(reader) as u32;
+            pixels.push((alpha << 24) | (red << 16) | ((green as u32) << 8) | blue);
+            x += 1;
+        } else if green < NUM_LITERAL_CODES + NUM_LENGTH_CODES {
+            let length = read_lz77_value(reader, green - NUM_LITERAL_CODES);
+            let distance_prefix = group.codes[4].read_symbol(reader);
+            let distance_code = read_lz77_value(reader, distance_prefix);
+            let distance = plane_code_to_distance(width, distance_code);
+            if reader.exhausted {
+                break;
+            }
+            if distance > pixels.len() || length > pixel_count - pixels.len() {
+                return Err("bad VP8L backward reference".to_string());
+            }
+            for _ in range(0, length) {
+                let pixel = pixels[pixels.len() - distance];
+                pixels.push(pixel);
+            }
+            x += length;
+        } else {
+            let index = green - NUM_LITERAL_CODES - NUM_LENGTH_CODES;
+            pixels.push(cache[index]);
+            x += 1;
+        }
+        while x >= width {
+            x -= width;
+            y += 1;
+        }
+        if cache_bits > 0 {
+            while cached < pixels.len() {
+                insert_into_cache(cache.as_mut_slice(), cache_bits, pixels[cached]);
+                cached += 1;
+            }
+        }
+        if reader.exhausted {
+            break;
+        }
+    }
+    Ok(pixels)
+}
+
+fn insert_into_cache(cache: &mut [u32], cache_bits: uint, pixel: u32) {
+    let key = (pixel as u64 * 0x1e35a7bd) as u32 >> (32 - cache_bits);
+    cache[key as uint] = pixel;
+}
+
+/// Reads a backward reference length or distance from its prefix symbol and extra bits.
+fn read_lz77_value(reader: &mut BitReader, prefix: uint) -> uint {
+    if prefix < 4 {
+        return prefix + 1;
+    }
+    let extra_bits = (prefix - 2) >> 1;
+    let offset = (2 + (prefix & 1)) << extra_bits;
+    offset + reader.read_bits(extra_bits) as uint + 1
+}
+
+fn plane_code_to_distance(width: uint, code: uint) -> uint {
+    if code > DISTANCE_MAP.len() {
+        return code - DISTANCE_MAP.len();
+    }
+    let offset = DISTANCE_MAP[code - 1] as int;
+    let distance = (offset >> 4) * width as int + 8 - (offset & 0xf);
+    if distance >= 1 { distance as uint } else { 1 }
+}
+
+fn apply_inverse_transform(transform: &Transform, pixels: &[u32], height: uint) -> Vec<u32> {
+    let width = transform.width;
+    if transform.kind == PREDICTOR_TRANSFORM {
+        let mut output = pixels.to_vec();
+        let tiles_per_row = subsample_size(width, transform.bits);
+        for y in range(0, height) {
+            for x in range(0, width) {
+                let i = y * width + x;
+                let prediction = if y == 0 {
+                    if x == 0 { 0xff000000 } else { output[i - 1] }
+                } else if x == 0 {
+                    output[i - width]
+                } else {
+                    let tile = (y >> transform.bits) * tiles_per_row + (x >> transform.bits);
+                    let mode = (transform.data[tile] >> 8) & 0xf;
+                    predict(mode, output.as_slice(), i, width)
+                };
+                output[i] = add_pixels(output[i], prediction);
+            }
+        }
+        output
+    } else if transform.kind == CROSS_COLOR_TRANSFORM {
+        let tiles_per_row = subsample_size(width, transform.bits);
+        let mut output = Vec::with_capacity(pixels.len());
+        for (i, &pixel) in pixels.iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            let tile = (y >> transform.bits) * tiles_per_row + (x >> transform.bits);
+            let multipliers = transform.data[tile];
+            let green = (pixel >> 8) as i8;
+            let red = (channel(pixel, 16) + color_delta(multipliers as i8, green)) & 0xff;
+            let blue = channel(pixel, 0) + color_delta((multipliers >> 8) as i8, green) +
+                       color_delta((multipliers >> 16) as i8, red as i8);
+            output.push((pixel & 0xff00ff00) | ((red as u32) << 16) | (blue as u32 & 0xff));
+        }
+        output
+    } else if transform.kind == SUBTRACT_GREEN_TRANSFORM {
+        pixels.iter().map(|&pixel| {
+            let green = (pixel >> 8) & 0xff;
+            let red_blue = ((pixel & 0x00ff00ff) + ((green << 16) | green)) & 0x00ff00ff;
+            (pixel & 0xff00ff00) | red_blue
+        }).collect()
+    } else {
+        // Color indexing, where several indices can be packed into one pixel.
+        let packed_width = subsample_size(width, transform.bits);
+        let bits_per_index = 8 >> transform.bits;
+        let mut output = Vec::with_capacity(width * height);
+        for y in range(0, height) {
+            for x in range(0, width) {
+                let packed = pixels[y * packed_width + (x >> transform.bits)] >> 8;
+                let shift = (x & ((1 << transform.bits) - 1)) * bits_per_index;
+                let index = (packed >> shift) & ((1 << bits_per_index) - 1);
+                output.push(transform.data[index as uint]);
+            }
+        }
+        output
+    }
+}
+
+/// Predicts the pixel at `i` from those above and to the left, as in section 4.1.
+fn predict(mode: u32, pixels: &[u32], i: uint, width: uint) -> u32 {
+    let left = pixels[i - 1];
+    let top = pixels[i - width];
+    let top_left = pixels[i - width - 1];
+    // The pixel to the top right of the last pixel of a row is the first pixel of the row.
+    let top_right = pixels[i - width + 1];
+    match mode {
+        1 => left,
+        2 => top,
+        3 => top_right,
+        4 => top_left,
+        5 => average2(average2(left, top_right), top),
+        6 => average2(left, top_left),
+        7 => average2(left, top),
+        8 => average2(top_left, top),
+        9 => average2(top, top_right),
+        10 => average2(average2(left, top_left), average2(top, top_right)),
+        11 => select(top, left, top_left),
+        12 => clamped_add_subtract_full(left, top, top_left),
+        13 => clamped_add_subtract_half(average2(left, top), top_left),
+        _ => 0xff000000,
+    }
+}
+
+fn channel(pixel: u32, shift: uint) -> i32 {
+    ((pixel >> shift) & 0xff) as i32
+}
+
+fn add_pixels(a: u32, b: u32) -> u32 {
+    let alpha_green = ((a >> 8) & 0x00ff00ff) + ((b >> 8) & 0x00ff00ff);
+    let red_blue = (a & 0x00ff00ff) + (b & 0x00ff00ff);
+    ((alpha_green & 0x00ff00ff) << 8) | (red_blue & 0x00ff00ff)
+}
+
+fn average2(a: u32, b: u32) -> u32 {
+    (((a ^ b) & 0xfefefefe) >> 1) + (a & b)
+}
+
+fn select(top: u32, left: u32, top_left: u32) -> u32 {
+    let mut difference = 0;
+    for &shift in [24u, 16, 8, 0].iter() {
+        let t = channel(top, shift);
+        let l = channel(left, shift);
+        let c = channel(top_left, shift);
+        difference += abs(l - c) - abs(t - c);
+    }
+    if difference <= 0 { top } else { left }
+}
+
+fn clamped_add_subtract_full(a: u32, b: u32, c: u32) -> u32 {
+    let mut result = 0;
+    for &shift in [24u, 16, 8, 0].iter() {
+        let value = channel(a, shift) + channel(b, shift) - channel(c, shift);
+        result |= (clamp_byte(value) as u32) << shift;
+    }
+    result
+}
+
+fn clamped_add_subtract_half(a: u32, b: u32) -> u32 {
+    let mut result = 0;
+    for &shift in [24u, 16, 8, 0].iter() {
+        let (x, y) = (channel(a, shift), channel(b, shift));
+        result |= (clamp_byte(x + (x - y) / 2) as u32) << shift;
+    }
+    result
+}
+
+fn color_delta(multiplier: i8, color: i8) -> i32 {
+    (multiplier as i32 * color as i32) >> 5
+}
+
+fn abs(value: i32) -> i32 {
+    if value < 0 { -value } else { value }
+}
+
+fn clamp_byte(value: i32) -> u8 {
+    if value < 0 { 0 } else if value > 255 { 255 } else { value as u8 }
+}
+
+#[test]
+fn test_huffman_code() {
+    // Symbols 1 and 3 have the codes 0 and 1.
+    let code = HuffmanCode::new(&[0, 1, 0, 1]).unwrap();
+    let mut reader = BitReader::new(&[0b10]);
+    assert_eq!(code.read_symbol(&mut reader), 1);
+    assert_eq!(code.read_symbol(&mut reader), 3);
+
+    // Codes longer than the lookup table are decoded bit by bit.
+    let code = HuffmanCode::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 10]).unwrap();
+    let mut reader = BitReader::new(&[0xff, 0x03]);
+    assert_eq!(code.read_symbol(&mut reader), 10);
+    assert!(!reader.exhausted);
+
+    // A code of one symbol takes no bits.
+    let code = HuffmanCode::new(&[0, 0, 5]).unwrap();
+    let mut reader = BitReader::new(&[]);
+    assert_eq!(code.read_symbol(&mut reader), 2);
+    assert!(!reader.exhausted);
+
+    assert!(HuffmanCode::new(&[0, 0]).is_err());
+    assert!(HuffmanCode::new(&[1, 1, 1]).is_err());
+    assert!(HuffmanCode::new(&[1, 2]).is_err());
+}
+
+#[test]
+fn test_plane_code_to_distance() {
+    // The pixel above, the pixel to the left, and plain distances after the 120 short codes.
+    assert_eq!(plane_code_to_distance(100, 1), 100);
+    assert_eq!(plane_code_to_distance(100, 2), 1);
+    assert_eq!(plane_code_to_distance(100, 125), 5);
+    // Offsets that fall before the start of the image become the pixel to the left.
+    assert_eq!(plane_code_to_distance(1, 4), 1);
+}
+
+#[test]
+fn test_add_pixels() {
+    assert_eq!(add_pixels(0xff010203, 0x02ff0405), 0x01000608);
+}
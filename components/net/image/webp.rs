@@ -0,0 +1,425 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A decoder for WebP images, lossy or lossless, with or without an alpha channel, and animated
+//! or not. This reads the RIFF container; `image::vp8` and `image::vp8l` decode the bitstreams.
+//!
+//! https://developers.google.com/speed/webp/docs/riff_container
+
+use image::apng::blend_over;
+use image::base::{DecodedAnimation, DecodedFrame, canvas_size, clamp_frame_delay, push_frame};
+use image::{vp8, vp8l};
+
+static ANIMATION_FLAG: u8 = 0x02;
+static DISPOSE_TO_BACKGROUND_FLAG: u8 = 0x01;
+static NO_BLEND_FLAG: u8 = 0x02;
+
+static ALPHA_UNCOMPRESSED: u8 = 0;
+static ALPHA_LOSSLESS: u8 = 1;
+
+static ALPHA_FILTER_NONE: u8 = 0;
+static ALPHA_FILTER_HORIZONTAL: u8 = 1;
+static ALPHA_FILTER_VERTICAL: u8 = 2;
+
+/// The largest canvas width or height that is decoded, which is also the largest image that the
+/// bitstreams can hold.
+static MAX_CANVAS_SIZE: uint = 16384;
+
+struct Chunk<'a> {
+    kind: &'a [u8],
+    data: &'a [u8],
+}
+
+fn read_u24(data: &[u8]) -> uint {
+    data[0] as uint | (data[1] as uint << 8) | (data[2] as uint << 16)
+}
+
+fn read_u32(data: &[u8]) -> uint {
+    read_u24(data) | (data[3] as uint << 24)
+}
+
+/// Splits a sequence of RIFF chunks, which are padded to an even size.
+fn chunks<'a>(mut data: &'a [u8]) -> Result<Vec<Chunk<'a>>, String> {
+    let mut chunks = vec!();
+    while data.len() > 0 {
+        if data.len() < 8 {
+            return Err("truncated WebP chunk header".to_string());
+        }
+        let length = read_u32(data.slice_from(4));
+        if length > data.len() - 8 {
+            return Err("truncated WebP chunk".to_string());
+        }
+        chunks.push(Chunk {
+            kind: data.slice_to(4),
+            data: data.slice(8, 8 + length),
+        });
+        // The padding of the last chunk may be missing.
+        let padded_length = length + (length & 1);
+        if 8 + padded_length >= data.len() {
+            break;
+        }
+        data = data.slice_from(8 + padded_length);
+    }
+    Ok(chunks)
+}
+
+pub fn is_webp(buffer: &[u8]) -> bool {
+    buffer.len() >= 12 && buffer.slice_to(4) == b"RIFF" && buffer.slice(8, 12) == b"WEBP"
+}
+
+/// Decodes a WebP image. Still images are decoded into a single frame.
+pub fn decode(buffer: &[u8]) -> Result<DecodedAnimation, String> {
+    if !is_webp(buffer) {
+        return Err("not a WebP image".to_string());
+    }
+    // Anything after the RIFF chunk is ignored.
+    let riff_length = read_u32(buffer.slice_from(4));
+    let end = if riff_length < buffer.len() - 8 { riff_length + 8 } else { buffer.len() };
+    let chunks = try!(chunks(buffer.slice(12, end)));
+    if chunks.is_empty() {
+        return Err("WebP without chunks".to_string());
+    }
+
+    if chunks[0].kind != b"VP8X" {
+        let (width, height, pixels) = try!(decode_image(chunks.as_slice()));
+        return Ok(still_image(width, height, pixels));
+    }
+
+    let header = chunks[0].data;
+    if header.len() < 10 {
+        return Err("truncated VP8X chunk".to_string());
+    }
+    let canvas_width = read_u24(header.slice_from(4)) + 1;
+    let canvas_height = read_u24(header.slice_from(7)) + 1;
+    if canvas_width > MAX_CANVAS_SIZE || canvas_height > MAX_CANVAS_SIZE {
+        return Err("WebP canvas is too large".to_string());
+    }
+    if header[0] & ANIMATION_FLAG != 0 {
+        return decode_animation(chunks.slice_from(1), canvas_width, canvas_height);
+    }
+
+    let (width, height, pixels) = try!(decode_image(chunks.slice_from(1)));
+    if width != canvas_width || height != canvas_height {
+        return Err("WebP image does not match the canvas size".to_string());
+    }
+    Ok(still_image(width, height, pixels))
+}
+
+fn still_image(width: uint, height: uint, pixels: Vec<u8>) -> DecodedAnimation {
+    DecodedAnimation {
+        width: width as u32,
+        height: height as u32,
+        frames: vec!(DecodedFrame::whole(width as u32, height as u32, pixels, 0)),
+        loop_count: 0,
+    }
+}
+
+/// Composites the frames of an animation onto a canvas that starts out transparent. Like other
+/// browsers, this ignores the background color of the animation.
+fn decode_animation(chunks: &[Chunk], width: uint, height: uint)
+                    -> Result<DecodedAnimation, String> {
+    let mut loop_count = 0;
+    let mut canvas = Vec::from_elem(try!(canvas_size(width, height)), 0u8);
+    let mut shown = vec!();
+    let mut frames = vec!();
+    for chunk in chunks.iter() {
+        if chunk.kind == b"ANIM" && chunk.data.len() >= 6 {
+            loop_count = chunk.data[4] as u32 | (chunk.data[5] as u32 << 8);
+        }
+        if chunk.kind != b"ANMF" {
+            continue;
+        }
+
+        let data = chunk.data;
+        if data.len() < 16 {
+            return Err("truncated ANMF chunk".to_string());
+        }
+        let x_offset = read_u24(data) * 2;
+        let y_offset = read_u24(data.slice_from(3)) * 2;
+        let frame_width = read_u24(data.slice_from(6)) + 1;
+        let frame_height = read_u24(data.slice_from(9)) + 1;
+        let delay = read_u24(data.slice_from(12)) as u32;
+        let flags = data[15];
+        if x_offset + frame_width > width || y_offset + frame_height > height {
+            return Err("WebP frame outside of the canvas".to_string());
+        }
+
+        let (image_width, image_height, pixels) =
+            try!(decode_image(try!(self::chunks(data.slice_from(16))).as_slice()));
+        if image_width != frame_width || image_height != frame_height {
+            return Err("WebP frame does not match its size".to_string());
+        }
+
+        for y in range(0, frame_height) {
+            for x in range(0, frame_width) {
+                let source = (y * frame_width + x) * 4;
+                let destination = ((y_offset + y) * width + x_offset + x) * 4;
+                if flags & NO_BLEND_FLAG == 0 {
+                    blend_over(canvas.slice_mut(destination, destination + 4),
+                               pixels.slice(source, source + 4));
+                } else {
+                    for i in range(0, 4) {
+                        canvas[destination + i] = pixels[source + i];
+                    }
+                }
+            }
+        }
+
+        push_frame(&mut frames, &mut shown, canvas.as_slice(), width, height,
+                   clamp_frame_delay(delay));
+
+        if flags & DISPOSE_TO_BACKGROUND_FLAG != 0 {
+            for y in range(y_offset, y_offset + frame_height) {
+                for x in range(x_offset, x_offset + frame_width) {
+                    let offset = (y * width + x) * 4;
+                    for i in range(0, 4) {
+                        canvas[offset + i] = 0;
+                    }
+                }
+            }
+        }
+    }
+    if frames.is_empty() {
+        return Err("animated WebP without frames".to_string());
+    }
+
+    Ok(DecodedAnimation {
+        width: width as u32,
+        height: height as u32,
+        frames: frames,
+        loop_count: loop_count,
+    })
+}
+
+/// Decodes the first image in a sequence of chunks, with the alpha chunk that may precede a lossy
+/// image, into straight RGBA.
+fn decode_image(chunks: &[Chunk]) -> Result<(uint, uint, Vec<u8>), String> {
+    let mut alpha = None;
+    for chunk in chunks.iter() {
+        if chunk.kind == b"ALPH" {
+            alpha = Some(chunk.data);
+        } else if chunk.kind == b"VP8 " {
+            let frame = try!(vp8::decode(chunk.data));
+            let mut pixels = to_rgba(&frame);
+            match alpha {
+                Some(data) => {
+                    let alpha = try!(decode_alpha(data, frame.width, frame.height));
+                    for (i, &value) in alpha.iter().enumerate() {
+                        pixels[i * 4 + 3] = value;
+                    }
+                }
+                None => {}
+            }
+            return Ok((frame.width, frame.height, pixels));
+        } else if chunk.kind == b"VP8L" {
+            let (width, height, argb) = try!(vp8l::decode(chunk.data));
+            let mut pixels = Vec::with_capacity(argb.len() * 4);
+            for &pixel in argb.iter() {
+                pixels.push((pixel >> 16) as u8);
+                pixels.push((pixel >> 8) as u8);
+                pixels.push(pixel as u8);
+                pixels.push((pixel >> 24) as u8);
+            }
+            return Ok((width, height, pixels));
+        }
+    }
+    Err("WebP without image data".to_string())
+}
+
+/// Decodes the alpha plane of a lossy image, section "Alpha" of the container specification.
+fn decode_alpha(data: &[u8], width: uint, height: uint) -> Result<Vec<u8>, String> {
+    if data.len() < 1 {
+        return Err("truncated ALPH chunk".to_string());
+    }
+    let method = data[0] & 3;
+    let filter = (data[0] >> 2) & 3;
+    let preprocessing = (data[0] >> 4) & 3;
+    if method > ALPHA_LOSSLESS || preprocessing > 1 || data[0] >> 6 != 0 {
+        return Err("bad ALPH header".to_string());
+    }
+
+    let data = data.slice_from(1);
+    let mut alpha = if method == ALPHA_UNCOMPRESSED {
+        if data.len() < width * height {
+            return Err("truncated ALPH chunk".to_string());
+        }
+        data.slice_to(width * height).to_vec()
+    } else {
+        // The alpha values are stored in the green channel of a lossless image.
+        let pixels = try!(vp8l::decode_headerless(data, width, height));
+        pixels.iter().map(|&pixel| (pixel >> 8) as u8).collect()
+    };
+
+    if filter != ALPHA_FILTER_NONE {
+        for y in range(0, height) {
+            let row = y * width;
+            if y == 0 || filter == ALPHA_FILTER_HORIZONTAL {
+                // The first row is always filtered horizontally, starting from zero.
+                let mut left = if y == 0 { 0 } else { alpha[row - width] };
+                for x in range(0, width) {
+                    left = (left as u32 + alpha[row + x] as u32) as u8;
+                    alpha[row + x] = left;
+                }
+            } else if filter == ALPHA_FILTER_VERTICAL {
+                for x in range(0, width) {
+                    let top = alpha[row - width + x];
+                    alpha[row + x] = (alpha[row + x] as u32 + top as u32) as u8;
+                }
+            } else {
+                // Gradient filtering predicts from the left, top and top left values.
+                let mut left = alpha[row - width];
+                let mut top_left = left;
+                for x in range(0, width) {
+                    let top = alpha[row - width + x];
+                    let prediction = left as i32 + top as i32 - top_left as i32;
+                    let prediction = if prediction < 0 {
+                        0
+                    } else if prediction > 255 {
+                        255
+                    } else {
+                        prediction as u8
+                    };
+                    left = (alpha[row + x] as u32 + prediction as u32) as u8;
+                    alpha[row + x] = left;
+                    top_left = top;
+                }
+            }
+        }
+    }
+    Ok(alpha)
+}
+
+/// Converts a VP8 frame to RGBA, upsampling its chroma planes bilinearly as libwebp does, so that
+/// images look the same as in other browsers.
+fn to_rgba(frame: &vp8::Frame) -> Vec<u8> {
+    let (width, height) = (frame.width, frame.height);
+    let chroma_height = (height + 1) / 2;
+    let mut u_row = Vec::from_elem(width, 0u8);
+    let mut v_row = Vec::from_elem(width, 0u8);
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for row in range(0, height) {
+        // Each row is upsampled from the chroma row that covers it and the nearest other one.
+        let near = row / 2;
+        let far = if row % 2 == 1 {
+            if near + 1 < chroma_height { near + 1 } else { near }
+        } else {
+            if near > 0 { near - 1 } else { 0 }
+        };
+        let (near, far) = (near * frame.uv_stride, far * frame.uv_stride);
+        upsample_row(frame.u.slice_from(near), frame.u.slice_from(far), u_row.as_mut_slice());
+        upsample_row(frame.v.slice_from(near), frame.v.slice_from(far), v_row.as_mut_slice());
+
+        let y_row = frame.y.slice_from(row * frame.y_stride);
+        for x in range(0, width) {
+            let (y, u, v) = (y_row[x] as i32, u_row[x] as i32, v_row[x] as i32);
+            let y = multiply_high(y, 19077);
+            pixels.push(clip_fixed_point(y + multiply_high(v, 26149) - 14234));
+            pixels.push(clip_fixed_point(y - multiply_high(u, 6419) - multiply_high(v, 13320) +
+                                         8708));
+            pixels.push(clip_fixed_point(y + multiply_high(u, 33050) - 17685));
+            pixels.push(255);
+        }
+    }
+    pixels
+}
+
+/// Interpolates a row of chroma samples to full width, weighting samples of the `near` row three
+/// times as much as those of the `far` row, and nearer columns three times as much as farther
+/// ones.
+fn upsample_row(near: &[u8], far: &[u8], output: &mut [u8]) {
+    let width = output.len();
+    output[0] = ((3 * near[0] as u32 + far[0] as u32 + 2) >> 2) as u8;
+    let mut x = 1;
+    while 2 * x < width {
+        let (near_left, near_right) = (near[x - 1] as u32, near[x] as u32);
+        let (far_left, far_right) = (far[x - 1] as u32, far[x] as u32);
+        let sum = near_left + near_right + far_left + far_right + 8;
+        let left = (sum + 2 * (near_right + far_left)) >> 3;
+        let right = (sum + 2 * (near_left + far_right)) >> 3;
+        output[2 * x - 1] = ((left + near_left) >> 1) as u8;
+        output[2 * x] = ((right + near_right) >> 1) as u8;
+        x += 1;
+    }
+    if width % 2 == 0 {
+        let last = width / 2 - 1;
+        output[width - 1] = ((3 * near[last] as u32 + far[last] as u32 + 2) >> 2) as u8;
+    }
+}
+
+fn multiply_high(value: i32, coefficient: i32) -> i32 {
+    (value * coefficient) >> 8
+}
+
+/// Clips a color value with six fractional bits to a byte.
+fn clip_fixed_point(value: i32) -> u8 {
+    if value & !16383 == 0 {
+        (value >> 6) as u8
+    } else if value < 0 {
+        0
+    } else {
+        255
+    }
+}
+
+#[cfg(test)]
+use image::base::{checksum, compose_frames, decode_corrupted_copies};
+
+#[cfg(test)]
+static LOSSY: &'static [u8] = include_bin!("corpus/lossy.webp");
+#[cfg(test)]
+static LOSSY_WITH_ALPHA: &'static [u8] = include_bin!("corpus/lossy-alpha.webp");
+#[cfg(test)]
+static LOSSLESS: &'static [u8] = include_bin!("corpus/lossless.webp");
+#[cfg(test)]
+static ANIMATED: &'static [u8] = include_bin!("corpus/animated.webp");
+
+// The checksums are those of the pixels that libwebp decodes from the same images.
+
+#[test]
+fn test_decode_lossy() {
+    let image = decode(LOSSY).unwrap();
+    assert_eq!((image.width, image.height), (33, 31));
+    assert_eq!(image.frames.len(), 1);
+    let pixels = image.frames[0].pixels.as_slice();
+    assert_eq!(pixels.slice_to(8).to_vec(), vec!(229, 248, 248, 255, 0, 13, 14, 255));
+    assert_eq!(checksum(pixels), 0x4f25457d);
+
+    let image = decode(LOSSY_WITH_ALPHA).unwrap();
+    assert_eq!((image.width, image.height), (33, 31));
+    assert_eq!(checksum(image.frames[0].pixels.as_slice()), 0x35262c8a);
+}
+
+#[test]
+fn test_decode_lossless() {
+    let image = decode(LOSSLESS).unwrap();
+    assert_eq!((image.width, image.height), (33, 31));
+    assert_eq!(checksum(image.frames[0].pixels.as_slice()), 0x6c86b031);
+}
+
+#[test]
+fn test_decode_animation() {
+    let animation = decode(ANIMATED).unwrap();
+    assert_eq!((animation.width, animation.height), (24, 16));
+    assert_eq!(animation.loop_count, 2);
+    let delays: Vec<u32> = animation.frames.iter().map(|frame| frame.delay).collect();
+    assert_eq!(delays, vec!(40, 70, 120));
+    let checksums: Vec<u32> = compose_frames(&animation).iter().map(|pixels| {
+        checksum(pixels.as_slice())
+    }).collect();
+    assert_eq!(checksums, vec!(0x6226663b, 0x1618218a, 0x087b4004));
+}
+
+#[test]
+fn test_decode_corrupted() {
+    for &data in [LOSSY, LOSSY_WITH_ALPHA, LOSSLESS, ANIMATED].iter() {
+        decode_corrupted_copies(data, decode);
+    }
+}
+
+#[test]
+fn test_upsample_row() {
+    let mut output = [0u8, ..4];
+    upsample_row(&[0, 64], &[128, 192], &mut output);
+    assert_eq!(output, [32, 48, 80, 96]);
+}
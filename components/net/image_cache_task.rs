@@ -3,13 +3,17 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use image::base::{Image, load_from_memory};
+use image::progressive::ProgressiveDecoder;
 use resource_task;
 use resource_task::{LoadData, ResourceTask};
 
+use servo_util::memory::MemoryReporter;
+use servo_util::opts;
 use servo_util::task::spawn_named;
 use servo_util::taskpool::TaskPool;
 use std::comm::{channel, Receiver, Sender};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::iter::AdditiveIterator;
 use std::collections::hash_map::{Occupied, Vacant};
 use std::mem::replace;
 use std::result;
@@ -17,10 +21,14 @@ use sync::{Arc, Mutex};
 use serialize::{Encoder, Encodable};
 use url::Url;
 
+/// Identifies the pipeline on whose behalf an image is loaded, so that the image can be
+/// released when the pipeline exits. This is the value of its `PipelineId`.
+pub type ImageOwner = uint;
+
 pub enum Msg {
     /// Tell the cache that we may need a particular image soon. Must be posted
     /// before Decode
-    Prefetch(Url),
+    Prefetch(Url, ImageOwner),
 
     /// Tell the cache to decode an image. Must be posted before GetImage/WaitForImage
     Decode(Url),
@@ -32,15 +40,40 @@ pub enum Msg {
     /// Wait for an image to become available (or fail to load).
     WaitForImage(Url, Sender<ImageResponseMsg>),
 
+    /// Wait for more of an image to be decoded. The response is the next partially decoded
+    /// image, or the final response once the image is complete.
+    WaitForProgress(Url, Sender<ImageResponseMsg>),
+
+    /// Tell the cache that a pipeline has exited. Images that no other pipeline has prefetched
+    /// are dropped.
+    ReleaseOwner(ImageOwner),
+
+    /// Ask to be sent the URL of every decoded image that is evicted to stay within the budget,
+    /// so that copies of the image held elsewhere can be dropped too.
+    AddEvictionListener(Sender<Url>),
+
+    /// Report the number of bytes held in decoded images and in image data.
+    ReportMemoryUsage(Sender<(uint, uint)>),
+
+    /// Describe every image the cache knows of, for about:cache.
+    ListEntries(Sender<Vec<ImageCacheEntry>>),
+
     /// Clients must wait for a response before shutting down the ResourceTask
     Exit(Sender<()>),
 
+    /// Used by the prefetch tasks to post back image data as it arrives
+    StorePrefetchedImageChunk(Url, Vec<u8>),
+
     /// Used by the prefetch tasks to post back image binaries
     StorePrefetchedImageData(Url, Result<Vec<u8>, ()>),
 
     /// Used by the decoder tasks to post decoded images back to the cache
     StoreImage(Url, Option<Arc<Box<Image>>>),
 
+    /// Used by the decoder tasks to post back images decoded from part of their data, along with
+    /// the decoder to go on with once more data arrives
+    StorePartialImage(Url, Option<Arc<Box<Image>>>, ProgressiveDecoder),
+
     /// For testing
     WaitForStore(Sender<()>),
 
@@ -51,6 +84,9 @@ pub enum Msg {
 #[deriving(Clone)]
 pub enum ImageResponseMsg {
     ImageReady(Arc<Box<Image>>),
+    /// The image is still loading, but part of it has been decoded. The partial image has the
+    /// final size, so layout can use it until the complete image is ready.
+    ImagePartial(Arc<Box<Image>>),
     ImageNotReady,
     ImageFailed
 }
@@ -59,14 +95,29 @@ impl PartialEq for ImageResponseMsg {
     fn eq(&self, other: &ImageResponseMsg) -> bool {
         match (self, other) {
             (&ImageReady(..), &ImageReady(..)) => panic!("unimplemented comparison"),
+            (&ImagePartial(..), &ImagePartial(..)) => panic!("unimplemented comparison"),
             (&ImageNotReady, &ImageNotReady) => true,
             (&ImageFailed, &ImageFailed) => true,
 
-            (&ImageReady(..), _) | (&ImageNotReady, _) | (&ImageFailed, _) => false
+            (&ImageReady(..), _) | (&ImagePartial(..), _) | (&ImageNotReady, _) |
+            (&ImageFailed, _) => false
         }
     }
 }
 
+/// The state of an image in the cache, as described by `ListEntries`.
+pub struct ImageCacheEntry {
+    pub url: Url,
+    /// How far along the image is, such as "prefetching" or "decoded".
+    pub state: &'static str,
+    /// The number of bytes of image data held.
+    pub data_size: uint,
+    /// The number of bytes held in the decoded image.
+    pub decoded_size: uint,
+    /// The number of pipelines that have prefetched the image.
+    pub owners: uint,
+}
+
 #[deriving(Clone)]
 pub struct ImageCacheTask {
     chan: Sender<Msg>,
@@ -82,6 +133,12 @@ type DecoderFactory = fn() -> (proc(&[u8]) : 'static -> Option<Image>);
 
 impl ImageCacheTask {
     pub fn new(resource_task: ResourceTask, task_pool: TaskPool) -> ImageCacheTask {
+        ImageCacheTask::new_with_budget(resource_task, task_pool, opts::get().image_cache_size)
+    }
+
+    /// Creates a cache that keeps at most `budget` bytes of decoded images.
+    fn new_with_budget(resource_task: ResourceTask, task_pool: TaskPool, budget: uint)
+                       -> ImageCacheTask {
         let (chan, port) = channel();
         let chan_clone = chan.clone();
 
@@ -92,6 +149,13 @@ impl ImageCacheTask {
                 chan: chan_clone,
                 state_map: HashMap::new(),
                 wait_map: HashMap::new(),
+                partial_decodes: HashMap::new(),
+                progress_waiters: HashMap::new(),
+                owners: HashMap::new(),
+                decoded_lru: vec!(),
+                decoded_size: 0,
+                budget: budget,
+                eviction_listeners: vec!(),
                 need_exit: None,
                 task_pool: task_pool,
             };
@@ -142,6 +206,20 @@ struct ImageCache {
     state_map: HashMap<Url, ImageState>,
     /// List of clients waiting on a WaitForImage response
     wait_map: HashMap<Url, Arc<Mutex<Vec<Sender<ImageResponseMsg>>>>>,
+    /// The images that are decoded while their data is still arriving
+    partial_decodes: HashMap<Url, PartialDecode>,
+    /// List of clients waiting on a WaitForProgress response
+    progress_waiters: HashMap<Url, Vec<Sender<ImageResponseMsg>>>,
+    /// The pipelines that have prefetched each image
+    owners: HashMap<Url, HashSet<ImageOwner>>,
+    /// The URLs of the decoded images, least recently used first
+    decoded_lru: Vec<Url>,
+    /// The number of bytes held in decoded images, including partial ones
+    decoded_size: uint,
+    /// The number of bytes of decoded images to keep before evicting the least recently used
+    budget: uint,
+    /// The clients to tell about evicted images
+    eviction_listeners: Vec<Sender<Url>>,
     need_exit: Option<Sender<()>>,
     task_pool: TaskPool,
 }
@@ -150,9 +228,12 @@ struct ImageCache {
 enum ImageState {
     Init,
     Prefetching(AfterPrefetch),
-    Prefetched(Vec<u8>),
-    Decoding,
-    Decoded(Arc<Box<Image>>),
+    Prefetched(Arc<Vec<u8>>),
+    Decoding(Arc<Vec<u8>>),
+    Decoded(Arc<Box<Image>>, Arc<Vec<u8>>),
+    /// Decoded before, but dropped to stay within the budget. The image is decoded again from
+    /// the kept data when it is next requested.
+    Evicted(Arc<Vec<u8>>),
     Failed
 }
 
@@ -162,6 +243,46 @@ enum AfterPrefetch {
     DoNotDecode
 }
 
+/// The decoding of an image whose data is still arriving.
+struct PartialDecode {
+    /// Holds the data received so far and decodes it. It is handed to a decoder task while a
+    /// partial decode runs, and dropped once all of the data has arrived.
+    decoder: Option<ProgressiveDecoder>,
+    /// The data that arrived while a partial decode was running.
+    pending: Vec<u8>,
+    /// The number of bytes received so far.
+    data_size: uint,
+    /// Whether a partial decode is running.
+    decoding: bool,
+    /// The result of the last partial decode.
+    image: Option<Arc<Box<Image>>>,
+}
+
+impl PartialDecode {
+    fn new() -> PartialDecode {
+        PartialDecode {
+            decoder: Some(ProgressiveDecoder::new()),
+            pending: vec!(),
+            data_size: 0,
+            decoding: false,
+            image: None,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        self.data_size += data.len();
+        match self.decoder {
+            Some(ref mut decoder) => decoder.push(data),
+            None => self.pending.push_all(data),
+        }
+    }
+
+    /// The memory taken by the partial image.
+    fn image_size(&self) -> uint {
+        self.image.as_ref().map_or(0, |image| image.size_of_pixels())
+    }
+}
+
 impl ImageCache {
     pub fn run(&mut self) {
         let mut store_chan: Option<Sender<()>> = None;
@@ -171,7 +292,10 @@ impl ImageCache {
             let msg = self.port.recv();
 
             match msg {
-                Prefetch(url) => self.prefetch(url),
+                Prefetch(url, owner) => self.prefetch(url, owner),
+                StorePrefetchedImageChunk(url, data) => {
+                    self.store_prefetched_image_chunk(url, data)
+                }
                 StorePrefetchedImageData(url, data) => {
                     store_prefetched_chan.map(|chan| {
                         chan.send(());
@@ -189,10 +313,20 @@ impl ImageCache {
 
                     self.store_image(url, image)
                 }
+                StorePartialImage(url, image, decoder) => {
+                    self.store_partial_image(url, image, decoder)
+                }
                 GetImage(url, response) => self.get_image(url, response),
                 WaitForImage(url, response) => {
                     self.wait_for_image(url, response)
                 }
+                WaitForProgress(url, response) => self.wait_for_progress(url, response),
+                ReleaseOwner(owner) => self.release_owner(owner),
+                AddEvictionListener(listener) => self.eviction_listeners.push(listener),
+                ReportMemoryUsage(response) => {
+                    response.send((self.decoded_size, self.encoded_size()));
+                }
+                ListEntries(response) => response.send(self.list_entries()),
                 WaitForStore(chan) => store_chan = Some(chan),
                 WaitForStorePrefetched(chan) => store_prefetched_chan = Some(chan),
                 Exit(response) => {
@@ -211,9 +345,9 @@ impl ImageCache {
                 for (_, state) in self.state_map.iter() {
                     match *state {
                         Prefetching(..) => can_exit = false,
-                        Decoding => can_exit = false,
+                        Decoding(..) => can_exit = false,
 
-                        Init | Prefetched(..) | Decoded(..) | Failed => ()
+                        Init | Prefetched(..) | Decoded(..) | Evicted(..) | Failed => ()
                     }
                 }
 
@@ -240,7 +374,18 @@ impl ImageCache {
         self.state_map.insert(url, state);
     }
 
-    fn prefetch(&mut self, url: Url) {
+    fn prefetch(&mut self, url: Url, owner: ImageOwner) {
+        match self.owners.entry(url.clone()) {
+            Occupied(mut entry) => {
+                entry.get_mut().insert(owner);
+            }
+            Vacant(entry) => {
+                let mut owners = HashSet::new();
+                owners.insert(owner);
+                entry.set(owners);
+            }
+        }
+
         match self.get_state(&url) {
             Init => {
                 let to_cache = self.chan.clone();
@@ -251,7 +396,9 @@ impl ImageCache {
                     let url = url_clone;
                     debug!("image_cache_task: started fetch for {:s}", url.serialize());
 
-                    let image = load_image_data(url.clone(), resource_task.clone());
+                    let image = load_image_data(url.clone(), resource_task.clone(), |data| {
+                        to_cache.send(StorePrefetchedImageChunk(url.clone(), data.to_vec()));
+                    });
                     to_cache.send(StorePrefetchedImageData(url.clone(), image));
                     debug!("image_cache_task: ended fetch for {:s}", url.serialize());
                 });
@@ -259,18 +406,113 @@ impl ImageCache {
                 self.set_state(url, Prefetching(DoNotDecode));
             }
 
-            Prefetching(..) | Prefetched(..) | Decoding | Decoded(..) | Failed => {
+            Prefetching(..) | Prefetched(..) | Decoding(..) | Decoded(..) | Evicted(..) | Failed => {
                 // We've already begun working on this image
             }
         }
     }
 
+    fn store_prefetched_image_chunk(&mut self, url: Url, data: Vec<u8>) {
+        match self.get_state(&url) {
+            Prefetching(..) => (),
+            _ => panic!("wrong state for storing prefetched image data"),
+        }
+
+        match self.partial_decodes.entry(url.clone()) {
+            Occupied(mut entry) => entry.get_mut().push(data.as_slice()),
+            Vacant(entry) => entry.set(PartialDecode::new()).push(data.as_slice()),
+        }
+        self.decode_partially(url);
+    }
+
+    /// Decodes the data of an image that has arrived so far, if the image is to be decoded and
+    /// more of it can be shown than after the last partial decode.
+    fn decode_partially(&mut self, url: Url) {
+        match self.get_state(&url) {
+            Prefetching(DoDecode) => (),
+            _ => return,
+        }
+
+        let decoder = match self.partial_decodes.get_mut(&url) {
+            Some(partial_decode) => {
+                let has_progressed = match partial_decode.decoder {
+                    Some(ref decoder) => decoder.has_progressed(),
+                    None => false,
+                };
+                if partial_decode.decoding || !has_progressed {
+                    return;
+                }
+                partial_decode.decoding = true;
+                partial_decode.decoder.take().unwrap()
+            }
+            None => return,
+        };
+
+        let to_cache = self.chan.clone();
+        self.task_pool.execute(proc() {
+            debug!("image_cache_task: started partial decode for {:s}", url.serialize());
+            let mut decoder = decoder;
+            let image = decoder.decode().map(|image| Arc::new(box image));
+            // The cache may have exited once the complete image was stored.
+            let _ = to_cache.send_opt(StorePartialImage(url.clone(), image, decoder));
+            debug!("image_cache_task: ended partial decode for {:s}", url.serialize());
+        });
+    }
+
+    fn store_partial_image(&mut self, url: Url, image: Option<Arc<Box<Image>>>,
+                           mut decoder: ProgressiveDecoder) {
+        let unused = self.is_unused(&url);
+        let loading = match self.get_state(&url) {
+            Prefetching(..) => true,
+            _ => false,
+        };
+        let (old_size, new_size) = match self.partial_decodes.get_mut(&url) {
+            Some(partial_decode) => {
+                partial_decode.decoding = false;
+                // The decoder is no longer needed once all of the data has arrived.
+                if loading {
+                    decoder.push(partial_decode.pending.as_slice());
+                    partial_decode.pending = vec!();
+                    partial_decode.decoder = Some(decoder);
+                }
+                let old_size = partial_decode.image_size();
+                if image.is_some() && !unused {
+                    partial_decode.image = image.clone();
+                }
+                (old_size, partial_decode.image_size())
+            }
+            // The image has been completed, has failed or has been released meanwhile.
+            None => return,
+        };
+        self.decoded_size = self.decoded_size - old_size + new_size;
+        self.evict_over_budget();
+
+        match image {
+            Some(image) => self.purge_progress_waiters(&url, || ImagePartial(image.clone())),
+            None => (),
+        }
+        self.decode_partially(url);
+    }
+
     fn store_prefetched_image_data(&mut self, url: Url, data: Result<Vec<u8>, ()>) {
         match self.get_state(&url) {
           Prefetching(next_step) => {
             match data {
               Ok(data) => {
-                self.set_state(url.clone(), Prefetched(data));
+                if self.is_unused(&url) {
+                    self.release(url);
+                    return;
+                }
+                // Keep showing the last partial image until the complete one is decoded.
+                match self.partial_decodes.get_mut(&url) {
+                    Some(partial_decode) => {
+                        partial_decode.decoder = None;
+                        partial_decode.pending = vec!();
+                        partial_decode.data_size = 0;
+                    }
+                    None => (),
+                }
+                self.set_state(url.clone(), Prefetched(Arc::new(data)));
                 match next_step {
                   DoDecode => self.decode(url),
                   _ => ()
@@ -278,6 +520,8 @@ impl ImageCache {
               }
               Err(..) => {
                 self.set_state(url.clone(), Failed);
+                self.remove_partial_decode(&url);
+                self.purge_progress_waiters(&url, || ImageFailed);
                 self.purge_waiters(url, || ImageFailed);
               }
             }
@@ -285,8 +529,9 @@ impl ImageCache {
 
           Init
           | Prefetched(..)
-          | Decoding
+          | Decoding(..)
           | Decoded(..)
+          | Evicted(..)
           | Failed => {
             panic!("wrong state for storing prefetched image")
           }
@@ -298,31 +543,34 @@ impl ImageCache {
             Init => panic!("decoding image before prefetch"),
 
             Prefetching(DoNotDecode) => {
-                // We don't have the data yet, queue up the decode
-                self.set_state(url, Prefetching(DoDecode))
+                // We don't have the data yet, queue up the decode and start with the data
+                // that has arrived
+                self.set_state(url.clone(), Prefetching(DoDecode));
+                self.decode_partially(url);
             }
 
             Prefetching(DoDecode) => {
                 // We don't have the data yet, but the decode request is queued up
             }
 
-            Prefetched(data) => {
+            Prefetched(data) | Evicted(data) => {
                 let to_cache = self.chan.clone();
                 let url_clone = url.clone();
+                let data_clone = data.clone();
 
                 self.task_pool.execute(proc() {
                     let url = url_clone;
                     debug!("image_cache_task: started image decode for {:s}", url.serialize());
-                    let image = load_from_memory(data.as_slice());
+                    let image = load_from_memory(data_clone.as_slice());
                     let image = image.map(|image| Arc::new(box image));
                     to_cache.send(StoreImage(url.clone(), image));
                     debug!("image_cache_task: ended image decode for {:s}", url.serialize());
                 });
 
-                self.set_state(url, Decoding);
+                self.set_state(url, Decoding(data));
             }
 
-            Decoding | Decoded(..) | Failed => {
+            Decoding(..) | Decoded(..) | Failed => {
                 // We've already begun decoding
             }
         }
@@ -331,14 +579,24 @@ impl ImageCache {
     fn store_image(&mut self, url: Url, image: Option<Arc<Box<Image>>>) {
 
         match self.get_state(&url) {
-          Decoding => {
+          Decoding(data) => {
+            self.remove_partial_decode(&url);
+            if self.is_unused(&url) {
+                self.release(url);
+                return;
+            }
             match image {
               Some(image) => {
-                self.set_state(url.clone(), Decoded(image.clone()));
+                self.set_state(url.clone(), Decoded(image.clone(), data));
+                self.decoded_size += image.size_of_pixels();
+                self.decoded_lru.push(url.clone());
+                self.purge_progress_waiters(&url, || ImageReady(image.clone()));
                 self.purge_waiters(url, || ImageReady(image.clone()) );
+                self.evict_over_budget();
               }
               None => {
                 self.set_state(url.clone(), Failed);
+                self.purge_progress_waiters(&url, || ImageFailed);
                 self.purge_waiters(url, || ImageFailed );
               }
             }
@@ -348,6 +606,7 @@ impl ImageCache {
           | Prefetching(..)
           | Prefetched(..)
           | Decoded(..)
+          | Evicted(..)
           | Failed => {
             panic!("incorrect state in store_image")
           }
@@ -355,6 +614,166 @@ impl ImageCache {
 
     }
 
+    /// Marks a decoded image as the most recently used.
+    fn touch(&mut self, url: &Url) {
+        match self.decoded_lru.iter().position(|decoded_url| decoded_url == url) {
+            Some(index) => {
+                let url = self.decoded_lru.remove(index).unwrap();
+                self.decoded_lru.push(url);
+            }
+            None => ()
+        }
+    }
+
+    /// Drops the least recently used decoded images until the rest fit in the budget. The most
+    /// recently used image is kept even if it is larger than the budget on its own. Images that
+    /// a client still holds stay alive until the client drops them.
+    fn evict_over_budget(&mut self) {
+        while self.decoded_size > self.budget && self.decoded_lru.len() > 1 {
+            let url = self.decoded_lru.remove(0).unwrap();
+            match self.get_state(&url) {
+                Decoded(image, data) => {
+                    debug!("image_cache_task: evicting decoded image for {:s}", url.serialize());
+                    self.decoded_size -= image.size_of_pixels();
+                    self.set_state(url.clone(), Evicted(data));
+                    self.eviction_listeners.retain(|listener| {
+                        listener.send_opt(url.clone()).is_ok()
+                    });
+                }
+                _ => panic!("non-decoded image in the LRU list"),
+            }
+        }
+    }
+
+    /// Whether nothing needs an image any more: every pipeline that prefetched it has exited, and
+    /// no client is waiting for it.
+    fn is_unused(&self, url: &Url) -> bool {
+        !self.owners.contains_key(url) && !self.wait_map.contains_key(url) &&
+            !self.progress_waiters.contains_key(url)
+    }
+
+    /// Forgets the partial decode of an image, along with its partial image.
+    fn remove_partial_decode(&mut self, url: &Url) {
+        match self.partial_decodes.remove(url) {
+            Some(partial_decode) => self.decoded_size -= partial_decode.image_size(),
+            None => (),
+        }
+    }
+
+    /// Forgets an image, along with its data and decoded copies.
+    fn release(&mut self, url: Url) {
+        debug!("image_cache_task: releasing {:s}", url.serialize());
+        self.remove_partial_decode(&url);
+        match self.state_map.remove(&url) {
+            Some(Decoded(image, _)) => {
+                self.decoded_size -= image.size_of_pixels();
+                let index = self.decoded_lru.iter().position(|decoded_url| *decoded_url == url);
+                self.decoded_lru.remove(index.unwrap());
+            }
+            _ => ()
+        }
+    }
+
+    /// Drops every image that was prefetched only by `owner`. Images that are still loading or
+    /// decoding drop their partial image at once, and the rest as soon as they finish.
+    fn release_owner(&mut self, owner: ImageOwner) {
+        let mut unowned = vec!();
+        for (url, owners) in self.owners.iter_mut() {
+            owners.remove(&owner);
+            if owners.is_empty() {
+                unowned.push(url.clone());
+            }
+        }
+        for url in unowned.iter() {
+            self.owners.remove(url);
+        }
+
+        let mut released = vec!();
+        let mut unfinished = vec!();
+        for (url, state) in self.state_map.iter() {
+            if !self.is_unused(url) {
+                continue;
+            }
+            match *state {
+                Prefetching(..) | Decoding(..) => unfinished.push(url.clone()),
+                Init | Prefetched(..) | Decoded(..) | Evicted(..) | Failed => released.push(url.clone()),
+            }
+        }
+        for url in unfinished.into_iter() {
+            match self.get_state(&url) {
+                Prefetching(..) => {
+                    // The data is still needed if another pipeline prefetches the image meanwhile.
+                    let image = match self.partial_decodes.get_mut(&url) {
+                        Some(partial_decode) => partial_decode.image.take(),
+                        None => None,
+                    };
+                    match image {
+                        Some(image) => self.decoded_size -= image.size_of_pixels(),
+                        None => (),
+                    }
+                    self.set_state(url, Prefetching(DoNotDecode));
+                }
+                _ => self.remove_partial_decode(&url),
+            }
+        }
+        for url in released.into_iter() {
+            self.release(url);
+        }
+    }
+
+    /// Describes every image in the cache, including those that are still loading, along with
+    /// the memory each one holds and how many pipelines use it.
+    fn list_entries(&self) -> Vec<ImageCacheEntry> {
+        self.state_map.iter().map(|(url, state)| {
+            let (state, data_size, decoded_size) = match *state {
+                Init => ("initial", 0, 0),
+                Prefetching(..) => {
+                    let (data_size, decoded_size) = match self.partial_decodes.get(url) {
+                        Some(partial_decode) => {
+                            (partial_decode.data_size, partial_decode.image_size())
+                        }
+                        None => (0, 0),
+                    };
+                    ("prefetching", data_size, decoded_size)
+                }
+                Prefetched(ref data) => ("prefetched", data.len(), 0),
+                Decoding(ref data) => {
+                    let decoded_size = self.partial_decodes.get(url).map_or(0, |partial_decode| {
+                        partial_decode.image_size()
+                    });
+                    ("decoding", data.len(), decoded_size)
+                }
+                Decoded(ref image, ref data) => {
+                    ("decoded", data.len(), image.size_of_pixels())
+                }
+                Evicted(ref data) => ("evicted", data.len(), 0),
+                Failed => ("failed", 0, 0),
+            };
+            ImageCacheEntry {
+                url: url.clone(),
+                state: state,
+                data_size: data_size,
+                decoded_size: decoded_size,
+                owners: self.owners.get(url).map_or(0, |owners| owners.len()),
+            }
+        }).collect()
+    }
+
+    /// The number of bytes of image data kept in order to decode the images.
+    fn encoded_size(&self) -> uint {
+        let complete = self.state_map.values().map(|state| {
+            match *state {
+                Prefetched(ref data) | Decoding(ref data) | Decoded(_, ref data) |
+                Evicted(ref data) => data.len(),
+                Init | Prefetching(..) | Failed => 0,
+            }
+        }).sum();
+        let partial = self.partial_decodes.values().map(|partial_decode| {
+            partial_decode.data_size
+        }).sum();
+        complete + partial
+    }
+
     fn purge_waiters(&mut self, url: Url, f: || -> ImageResponseMsg) {
         match self.wait_map.remove(&url) {
             Some(waiters) => {
@@ -367,13 +786,39 @@ impl ImageCache {
         }
     }
 
-    fn get_image(&self, url: Url, response: Sender<ImageResponseMsg>) {
+    fn purge_progress_waiters(&mut self, url: &Url, f: || -> ImageResponseMsg) {
+        match self.progress_waiters.remove(url) {
+            Some(waiters) => {
+                for response in waiters.iter() {
+                    response.send(f());
+                }
+            }
+            None => ()
+        }
+    }
+
+    /// The response for an image that is not decoded yet: the last partial image, if any.
+    fn partial_response(&self, url: &Url) -> ImageResponseMsg {
+        match self.partial_decodes.get(url) {
+            Some(&PartialDecode { image: Some(ref image), .. }) => ImagePartial(image.clone()),
+            _ => ImageNotReady,
+        }
+    }
+
+    fn get_image(&mut self, url: Url, response: Sender<ImageResponseMsg>) {
         match self.get_state(&url) {
             Init => panic!("request for image before prefetch"),
-            Prefetching(DoDecode) => response.send(ImageNotReady),
+            Prefetching(DoDecode) => response.send(self.partial_response(&url)),
             Prefetching(DoNotDecode) | Prefetched(..) => panic!("request for image before decode"),
-            Decoding => response.send(ImageNotReady),
-            Decoded(image) => response.send(ImageReady(image)),
+            Decoding(..) => response.send(self.partial_response(&url)),
+            Decoded(image, _) => {
+                self.touch(&url);
+                response.send(ImageReady(image));
+            }
+            Evicted(..) => {
+                self.decode(url);
+                response.send(ImageNotReady);
+            }
             Failed => response.send(ImageFailed),
         }
     }
@@ -384,8 +829,9 @@ impl ImageCache {
 
             Prefetching(DoNotDecode) | Prefetched(..) => panic!("request for image before decode"),
 
-            Prefetching(DoDecode) | Decoding => {
-                // We don't have this image yet
+            Prefetching(DoDecode) | Decoding(..) | Evicted(..) => {
+                // We don't have this image yet. An evicted image is decoded again.
+                self.decode(url.clone());
                 match self.wait_map.entry(url) {
                     Occupied(mut entry) => {
                         entry.get_mut().lock().push(response);
@@ -396,7 +842,8 @@ impl ImageCache {
                 }
             }
 
-            Decoded(image) => {
+            Decoded(image, _) => {
+                self.touch(&url);
                 response.send(ImageReady(image));
             }
 
@@ -406,6 +853,26 @@ impl ImageCache {
         }
     }
 
+    fn wait_for_progress(&mut self, url: Url, response: Sender<ImageResponseMsg>) {
+        match self.get_state(&url) {
+            Init => panic!("request for image before prefetch"),
+
+            Prefetching(DoNotDecode) | Prefetched(..) => panic!("request for image before decode"),
+
+            Prefetching(DoDecode) | Decoding(..) => {
+                match self.progress_waiters.entry(url) {
+                    Occupied(mut entry) => {
+                        entry.get_mut().push(response);
+                    }
+                    Vacant(entry) => {
+                        entry.set(vec!(response));
+                    }
+                }
+            }
+
+            Decoded(..) | Evicted(..) | Failed => self.wait_for_image(url, response),
+        }
+    }
 }
 
 
@@ -421,6 +888,23 @@ impl ImageCacheTaskClient for ImageCacheTask {
     }
 }
 
+impl MemoryReporter for ImageCacheTask {
+    fn collect_reports(&self) -> Vec<(String, Option<u64>)> {
+        // The cache may already have exited.
+        let (response_chan, response_port) = channel();
+        if self.chan.send_opt(ReportMemoryUsage(response_chan)).is_err() {
+            return vec!();
+        }
+        match response_port.recv_opt() {
+            Ok((decoded, encoded)) => {
+                vec!(("image-decoded".to_string(), Some(decoded as u64)),
+                     ("image-data".to_string(), Some(encoded as u64)))
+            }
+            Err(()) => vec!(),
+        }
+    }
+}
+
 impl ImageCacheTask {
     pub fn send(&self, msg: Msg) {
         self.chan.send(msg);
@@ -441,7 +925,9 @@ impl ImageCacheTask {
     }
 }
 
-fn load_image_data(url: Url, resource_task: ResourceTask) -> Result<Vec<u8>, ()> {
+/// Loads the data of an image, passing each part of it to `on_data` as it arrives.
+fn load_image_data(url: Url, resource_task: ResourceTask, on_data: |&[u8]|)
+                   -> Result<Vec<u8>, ()> {
     let (response_chan, response_port) = channel();
     resource_task.send(resource_task::Load(LoadData::new(url, response_chan)));
 
@@ -451,6 +937,7 @@ fn load_image_data(url: Url, resource_task: ResourceTask) -> Result<Vec<u8>, ()>
     loop {
         match progress_port.recv() {
             resource_task::Payload(data) => {
+                on_data(data.as_slice());
                 image_data.push_all(data.as_slice());
             }
             resource_task::Done(result::Ok(..)) => {
@@ -484,10 +971,13 @@ mod tests {
     use resource_task::{ResourceTask, Metadata, start_sending, ResponseSenders};
     use sniffer_task;
     use image::base::test_image_bin;
+    use image::progressive;
     use servo_util::taskpool::TaskPool;
     use std::comm;
     use url::Url;
 
+    static TEST_OWNER: ImageOwner = 1;
+
     trait Closure {
         fn invoke(&self, _response: Sender<resource_task::ProgressMsg>) { }
     }
@@ -554,6 +1044,35 @@ mod tests {
         }
     }
 
+    struct WaitSendRestOfTestImage {
+        wait_port: Receiver<()>,
+    }
+    impl Closure for WaitSendRestOfTestImage {
+        fn invoke(&self, response: Sender<resource_task::ProgressMsg>) {
+            // Send the headers of the image, but not the rest of the data until after the
+            // client has seen the partial image
+            let data = test_image_bin();
+            response.send(resource_task::Payload(data.slice_to(500).to_vec()));
+            self.wait_port.recv();
+            response.send(resource_task::Payload(data.slice_from(500).to_vec()));
+            response.send(resource_task::Done(Ok(())));
+        }
+    }
+
+    struct WaitSendRestOfImage {
+        data: Vec<u8>,
+        split: uint,
+        wait_port: Receiver<()>,
+    }
+    impl Closure for WaitSendRestOfImage {
+        fn invoke(&self, response: Sender<resource_task::ProgressMsg>) {
+            response.send(resource_task::Payload(self.data.slice_to(self.split).to_vec()));
+            self.wait_port.recv();
+            response.send(resource_task::Payload(self.data.slice_from(self.split).to_vec()));
+            response.send(resource_task::Done(Ok(())));
+        }
+    }
+
     fn mock_resource_task<T: Closure+Send>(on_load: Box<T>) -> ResourceTask {
         spawn_listener(proc(port: Receiver<resource_task::ControlMsg>) {
             loop {
@@ -568,7 +1087,8 @@ mod tests {
                             Url::parse("file:///fake").unwrap()));
                         on_load.invoke(chan);
                     }
-                    resource_task::Exit => break
+                    resource_task::Exit => break,
+                    _ => {}
                 }
             }
         })
@@ -606,7 +1126,7 @@ mod tests {
         let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
         let url = Url::parse("file:///").unwrap();
 
-        image_cache_task.send(Prefetch(url));
+        image_cache_task.send(Prefetch(url, TEST_OWNER));
         url_requested.recv();
         image_cache_task.exit();
         mock_resource_task.send(resource_task::Exit);
@@ -621,8 +1141,8 @@ mod tests {
         let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
         let url = Url::parse("file:///").unwrap();
 
-        image_cache_task.send(Prefetch(url.clone()));
-        image_cache_task.send(Prefetch(url));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
+        image_cache_task.send(Prefetch(url, TEST_OWNER));
         url_requested.recv();
         image_cache_task.exit();
         mock_resource_task.send(resource_task::Exit);
@@ -641,7 +1161,7 @@ mod tests {
         let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
         let url = Url::parse("file:///").unwrap();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
         let (response_chan, response_port) = comm::channel();
         image_cache_task.send(GetImage(url, response_chan));
@@ -660,7 +1180,7 @@ mod tests {
 
         let join_port = image_cache_task.wait_for_store();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         // Wait until our mock resource task has sent the image to the image cache
@@ -686,7 +1206,7 @@ mod tests {
 
         let join_port = image_cache_task.wait_for_store();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         // Wait until our mock resource task has sent the image to the image cache
@@ -730,6 +1250,7 @@ mod tests {
                         resource_task_exited_chan.send(());
                         break
                     }
+                    _ => {}
                 }
             }
         });
@@ -737,12 +1258,12 @@ mod tests {
         let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
         let url = Url::parse("file:///").unwrap();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
 
         // Wait until our mock resource task has sent the image to the image cache
         image_bin_sent.recv();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
 
         image_cache_task.exit();
         mock_resource_task.send(resource_task::Exit);
@@ -782,6 +1303,7 @@ mod tests {
                         resource_task_exited_chan.send(());
                         break
                     }
+                    _ => {}
                 }
             }
         });
@@ -789,13 +1311,13 @@ mod tests {
         let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
         let url = Url::parse("file:///").unwrap();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         // Wait until our mock resource task has sent the image to the image cache
         image_bin_sent.recv();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         image_cache_task.exit();
@@ -820,7 +1342,7 @@ mod tests {
 
         let join_port = image_cache_task.wait_for_store_prefetched();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         // Wait until our mock resource task has sent the image to the image cache
@@ -846,7 +1368,7 @@ mod tests {
 
         let join_port = image_cache_task.wait_for_store_prefetched();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         // Wait until our mock resource task has sent the image to the image cache
@@ -880,7 +1402,7 @@ mod tests {
 
         let join_port = image_cache_task.wait_for_store();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         // Wait until our mock resource task has sent the image to the image cache
@@ -908,7 +1430,7 @@ mod tests {
 
         let join_port = image_cache_task.wait_for_store();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         // Wait until our mock resource task has sent the image to the image cache
@@ -934,7 +1456,7 @@ mod tests {
         let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
         let url = Url::parse("file:///").unwrap();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         let (response_chan, response_port) = comm::channel();
@@ -951,6 +1473,45 @@ mod tests {
         mock_resource_task.send(resource_task::Exit);
     }
 
+    #[test]
+    fn should_return_partial_image_on_progress_before_image_is_loaded() {
+        let (wait_chan, wait_port) = comm::channel();
+
+        let mock_resource_task = mock_resource_task(box WaitSendRestOfTestImage {wait_port: wait_port});
+
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let url = Url::parse("file:///").unwrap();
+
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
+        image_cache_task.send(Decode(url.clone()));
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(WaitForProgress(url.clone(), response_chan));
+        let (width, height) = match response_port.recv() {
+            ImagePartial(image) => (image.width, image.height),
+            _ => panic!("expected a partial image")
+        };
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(GetImage(url.clone(), response_chan));
+        match response_port.recv() {
+            ImagePartial(..) => (),
+            _ => panic!("expected the partial image to be kept")
+        }
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(WaitForProgress(url, response_chan));
+        wait_chan.send(());
+
+        match response_port.recv() {
+            ImageReady(image) => assert!((image.width, image.height) == (width, height)),
+            _ => panic!("expected the complete image")
+        }
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
     #[test]
     fn should_return_image_failed_on_wait_if_image_fails_to_load() {
         let (wait_chan, wait_port) = comm::channel();
@@ -960,7 +1521,7 @@ mod tests {
         let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
         let url = Url::parse("file:///").unwrap();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         let (response_chan, response_port) = comm::channel();
@@ -984,7 +1545,7 @@ mod tests {
         let image_cache_task = ImageCacheTask::new_sync(mock_resource_task.clone(), TaskPool::new(4));
         let url = Url::parse("file:///").unwrap();
 
-        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
         image_cache_task.send(Decode(url.clone()));
 
         let (response_chan, response_port) = comm::channel();
@@ -997,4 +1558,175 @@ mod tests {
         image_cache_task.exit();
         mock_resource_task.send(resource_task::Exit);
     }
+
+    fn memory_usage(image_cache_task: &ImageCacheTask) -> (uint, uint) {
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(ReportMemoryUsage(response_chan));
+        response_port.recv()
+    }
+
+    fn load_and_decode(image_cache_task: &ImageCacheTask, url: &Url, owner: ImageOwner) {
+        let join_port = image_cache_task.wait_for_store();
+        image_cache_task.send(Prefetch(url.clone(), owner));
+        image_cache_task.send(Decode(url.clone()));
+        join_port.recv();
+    }
+
+    #[test]
+    fn should_evict_least_recently_used_image_over_budget() {
+        let mock_resource_task = mock_resource_task(box SendTestImage);
+
+        let image_cache_task = ImageCacheTask::new_with_budget(mock_resource_task.clone(),
+                                                               TaskPool::new(4), 1);
+        let first = Url::parse("file:///first").unwrap();
+        let second = Url::parse("file:///second").unwrap();
+
+        load_and_decode(&image_cache_task, &first, TEST_OWNER);
+        let (decoded, encoded) = memory_usage(&image_cache_task);
+        assert!(decoded > 0);
+
+        // Only the most recently used image stays decoded, but the data of both is kept.
+        load_and_decode(&image_cache_task, &second, TEST_OWNER);
+        assert_eq!(memory_usage(&image_cache_task), (decoded, 2 * encoded));
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(GetImage(first.clone(), response_chan));
+        assert!(response_port.recv() == ImageNotReady);
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(WaitForImage(first, response_chan));
+        match response_port.recv() {
+          ImageReady(_) => (),
+          _ => panic!("bleh")
+        }
+        assert_eq!(memory_usage(&image_cache_task), (decoded, 2 * encoded));
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_tell_eviction_listeners_about_evicted_images() {
+        let mock_resource_task = mock_resource_task(box SendTestImage);
+
+        let image_cache_task = ImageCacheTask::new_with_budget(mock_resource_task.clone(),
+                                                               TaskPool::new(4), 1);
+        let (evicted_chan, evicted_port) = comm::channel();
+        image_cache_task.send(AddEvictionListener(evicted_chan));
+        let first = Url::parse("file:///first").unwrap();
+        let second = Url::parse("file:///second").unwrap();
+
+        load_and_decode(&image_cache_task, &first, TEST_OWNER);
+        load_and_decode(&image_cache_task, &second, TEST_OWNER);
+        assert_eq!(evicted_port.recv(), first);
+        assert!(evicted_port.try_recv().is_err());
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_release_images_when_their_owners_exit() {
+        let mock_resource_task = mock_resource_task(box SendTestImage);
+
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let shared = Url::parse("file:///shared").unwrap();
+        let private = Url::parse("file:///private").unwrap();
+
+        load_and_decode(&image_cache_task, &shared, 1);
+        image_cache_task.send(Prefetch(shared.clone(), 2));
+        load_and_decode(&image_cache_task, &private, 1);
+        let (decoded, encoded) = memory_usage(&image_cache_task);
+
+        image_cache_task.send(ReleaseOwner(1));
+        assert_eq!(memory_usage(&image_cache_task), (decoded / 2, encoded / 2));
+
+        image_cache_task.send(ReleaseOwner(2));
+        assert_eq!(memory_usage(&image_cache_task), (0, 0));
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_release_loading_images_once_loaded() {
+        let (wait_chan, wait_port) = comm::channel();
+        let mock_resource_task = mock_resource_task(box WaitSendTestImage { wait_port: wait_port });
+
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let url = Url::parse("file:///").unwrap();
+
+        let join_port = image_cache_task.wait_for_store_prefetched();
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
+        image_cache_task.send(Decode(url.clone()));
+        image_cache_task.send(ReleaseOwner(TEST_OWNER));
+        wait_chan.send(());
+        join_port.recv();
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(ListEntries(response_chan));
+        assert!(response_port.recv().is_empty());
+        assert_eq!(memory_usage(&image_cache_task), (0, 0));
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_count_partial_images_in_memory_usage() {
+        let (wait_chan, wait_port) = comm::channel();
+        let (data, offset) = progressive::interlaced_test_png();
+        let mock_resource_task = mock_resource_task(box WaitSendRestOfImage {
+            data: data,
+            split: offset + 2,
+            wait_port: wait_port,
+        });
+
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let url = Url::parse("file:///").unwrap();
+        image_cache_task.send(Prefetch(url.clone(), TEST_OWNER));
+        image_cache_task.send(Decode(url.clone()));
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(WaitForProgress(url.clone(), response_chan));
+        let image = match response_port.recv() {
+            ImagePartial(image) => image,
+            _ => panic!("expected a partial image")
+        };
+        assert!(!image.is_blank());
+        let (decoded, _) = memory_usage(&image_cache_task);
+        assert_eq!(decoded, image.size_of_pixels());
+
+        // The partial image is dropped along with the pipeline that loaded it.
+        image_cache_task.send(ReleaseOwner(TEST_OWNER));
+        let (decoded, _) = memory_usage(&image_cache_task);
+        assert_eq!(decoded, 0);
+
+        wait_chan.send(());
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_list_entries() {
+        let mock_resource_task = mock_resource_task(box SendTestImage);
+
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let url = Url::parse("file:///").unwrap();
+        load_and_decode(&image_cache_task, &url, 1);
+        image_cache_task.send(Prefetch(url.clone(), 2));
+        let (decoded, encoded) = memory_usage(&image_cache_task);
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(ListEntries(response_chan));
+        let entries = response_port.recv();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].url, url);
+        assert_eq!(entries[0].state, "decoded");
+        assert_eq!((entries[0].decoded_size, entries[0].data_size), (decoded, encoded));
+        assert_eq!(entries[0].owners, 2);
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
 }
@@ -0,0 +1,558 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A DEFLATE decompressor that, unlike `flate`, can be fed a stream a piece at a time and returns
+//! whatever it could decompress from the pieces received so far. It is used both to remove the
+//! content coding of HTTP responses as they stream in and to decode images while they load.
+//!
+//! The checksums in zlib and gzip trailers are not verified.
+//!
+//! http://tools.ietf.org/html/rfc1950 (zlib), http://tools.ietf.org/html/rfc1951 (DEFLATE) and
+//! http://tools.ietf.org/html/rfc1952 (gzip)
+
+use std::cmp;
+
+static LENGTH_BASE: [uint, ..29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+static LENGTH_EXTRA_BITS: [uint, ..29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+static DISTANCE_BASE: [uint, ..30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+static DISTANCE_EXTRA_BITS: [uint, ..30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+static CODE_LENGTH_ORDER: [uint, ..19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+static MAX_CODE_LENGTH: uint = 15;
+
+/// How far back a DEFLATE stream can refer to output it has already produced.
+static WINDOW_SIZE: uint = 32768;
+
+/// The wrapper around a DEFLATE stream.
+#[deriving(PartialEq, Show)]
+pub enum Format {
+    /// A zlib stream, as found in PNGs.
+    ZlibFormat,
+    /// A gzip member.
+    GzipFormat,
+    /// A zlib stream or a raw DEFLATE stream, told apart by their first two bytes. This is what
+    /// `Content-Encoding: deflate` means in practice, as enough servers send raw streams that
+    /// every browser accepts both.
+    ZlibOrRawFormat,
+}
+
+/// Why decompression stopped.
+#[deriving(PartialEq, Show)]
+enum Stop {
+    /// The data received so far ends in the middle of something.
+    OutOfData,
+    /// The data is not a valid stream.
+    InvalidData,
+}
+
+/// Where an `Inflater` is in the stream.
+#[deriving(PartialEq, Show)]
+enum State {
+    /// Before the zlib or gzip header.
+    Header,
+    /// Before the header of a block.
+    BlockHeader,
+    /// In a stored block, with this many bytes of it left to copy.
+    Stored(uint),
+    /// In a compressed block, whose codes are `Inflater::literals` and `Inflater::distances`.
+    Codes,
+    /// Past the last block.
+    Finished,
+    /// After invalid data.
+    Failed,
+}
+
+struct BitReader {
+    data: Vec<u8>,
+    position: uint,
+    bit: uint,
+}
+
+impl BitReader {
+    /// Reads `count` bits, least significant first.
+    fn bits(&mut self, count: uint) -> Result<uint, Stop> {
+        let mut value = 0;
+        for i in range(0, count) {
+            if self.position >= self.data.len() {
+                return Err(OutOfData);
+            }
+            let bit = (self.data[self.position] >> self.bit) & 1;
+            value |= (bit as uint) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.position += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<uint, Stop> {
+        self.bits(8)
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+
+    fn checkpoint(&self) -> (uint, uint) {
+        (self.position, self.bit)
+    }
+
+    /// Goes back to a checkpoint, so that something that was only partially received can be
+    /// read again once the rest of it arrives.
+    fn rewind(&mut self, (position, bit): (uint, uint)) {
+        self.position = position;
+        self.bit = bit;
+    }
+
+    /// Forgets the bytes that have been read in full.
+    fn discard_read_bytes(&mut self) {
+        self.data = self.data.slice_from(cmp::min(self.position, self.data.len())).to_vec();
+        self.position = 0;
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length and the symbols in
+/// code order.
+struct Huffman {
+    counts: [u16, ..16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16, ..16];
+        for &length in lengths.iter() {
+            counts[length as uint] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16, ..16];
+        for length in range(1, MAX_CODE_LENGTH) {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = Vec::from_elem(lengths.len(), 0u16);
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as uint] as uint] = symbol as u16;
+                offsets[length as uint] += 1;
+            }
+        }
+
+        Huffman {
+            counts: counts,
+            symbols: symbols,
+        }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<uint, Stop> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for length in range(1, MAX_CODE_LENGTH + 1) {
+            code |= try!(reader.bits(1)) as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as uint] as uint);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InvalidData)
+    }
+}
+
+/// A decompressor for a stream that arrives a piece at a time.
+pub struct Inflater {
+    format: Format,
+    state: State,
+    /// Whether the current block is the last one.
+    last_block: bool,
+    literals: Huffman,
+    distances: Huffman,
+    reader: BitReader,
+    /// The output so far, of which only the last `WINDOW_SIZE` bytes are kept between pieces.
+    window: Vec<u8>,
+}
+
+impl Inflater {
+    pub fn new(format: Format) -> Inflater {
+        Inflater {
+            format: format,
+            state: Header,
+            last_block: false,
+            literals: Huffman::new(&[]),
+            distances: Huffman::new(&[]),
+            reader: BitReader {
+                data: vec!(),
+                position: 0,
+                bit: 0,
+            },
+            window: vec!(),
+        }
+    }
+
+    /// Decompresses as much of the stream as possible, given its next piece, and returns the
+    /// bytes that were decompressed. Decompression stops for good at the first error, returning
+    /// what it decompressed before it.
+    pub fn push(&mut self, data: &[u8]) -> Vec<u8> {
+        self.reader.data.push_all(data);
+        let start = self.window.len();
+        self.inflate();
+        let output = self.window.slice_from(start).to_vec();
+
+        if self.window.len() > WINDOW_SIZE {
+            self.window = self.window.slice_from(self.window.len() - WINDOW_SIZE).to_vec();
+        }
+        self.reader.discard_read_bytes();
+        output
+    }
+
+    /// Whether the whole stream has been decompressed.
+    pub fn is_finished(&self) -> bool {
+        self.state == Finished
+    }
+
+    /// Whether the stream turned out to be invalid.
+    pub fn has_failed(&self) -> bool {
+        self.state == Failed
+    }
+
+    fn inflate(&mut self) {
+        loop {
+            // Headers are only read once they have arrived in full, while the contents of blocks
+            // are decompressed a symbol at a time.
+            let checkpoint = self.reader.checkpoint();
+            let result = match self.state {
+                Header => read_header(&mut self.reader, self.format).map(|()| BlockHeader),
+                BlockHeader => self.read_block_header(),
+                Stored(remaining) => self.copy_stored(remaining),
+                Codes => {
+                    inflate_codes(&mut self.reader,
+                                  &mut self.window,
+                                  &self.literals,
+                                  &self.distances).map(|()| self.end_of_block())
+                }
+                Finished | Failed => return,
+            };
+            match result {
+                Ok(state) => self.state = state,
+                Err(OutOfData) => {
+                    match self.state {
+                        Header | BlockHeader => self.reader.rewind(checkpoint),
+                        _ => {}
+                    }
+                    return
+                }
+                Err(InvalidData) => {
+                    self.state = Failed;
+                    return
+                }
+            }
+        }
+    }
+
+    fn end_of_block(&self) -> State {
+        if self.last_block {
+            Finished
+        } else {
+            BlockHeader
+        }
+    }
+
+    fn read_block_header(&mut self) -> Result<State, Stop> {
+        let last_block = try!(self.reader.bits(1)) == 1;
+        match try!(self.reader.bits(2)) {
+            0 => {
+                self.reader.align_to_byte();
+                let length = try!(self.reader.bits(16));
+                let inverted_length = try!(self.reader.bits(16));
+                if length != !inverted_length & 0xffff {
+                    return Err(InvalidData);
+                }
+                self.last_block = last_block;
+                Ok(Stored(length))
+            }
+            1 => {
+                let (literals, distances) = fixed_tables();
+                self.literals = literals;
+                self.distances = distances;
+                self.last_block = last_block;
+                Ok(Codes)
+            }
+            2 => {
+                let (literals, distances) = try!(dynamic_tables(&mut self.reader));
+                self.literals = literals;
+                self.distances = distances;
+                self.last_block = last_block;
+                Ok(Codes)
+            }
+            _ => Err(InvalidData),
+        }
+    }
+
+    fn copy_stored(&mut self, remaining: uint) -> Result<State, Stop> {
+        let position = self.reader.position;
+        let copied = cmp::min(remaining, self.reader.data.len() - position);
+        self.window.push_all(self.reader.data.slice(position, position + copied));
+        self.reader.position += copied;
+        if copied == remaining {
+            Ok(self.end_of_block())
+        } else if copied == 0 {
+            Err(OutOfData)
+        } else {
+            Ok(Stored(remaining - copied))
+        }
+    }
+}
+
+fn read_header(reader: &mut BitReader, format: Format) -> Result<(), Stop> {
+    match format {
+        ZlibFormat => read_zlib_header(reader, false),
+        ZlibOrRawFormat => read_zlib_header(reader, true),
+        GzipFormat => read_gzip_header(reader),
+    }
+}
+
+/// Reads the header of a zlib stream. If `optional` is true, a stream that does not start with
+/// one is read as a raw DEFLATE stream.
+fn read_zlib_header(reader: &mut BitReader, optional: bool) -> Result<(), Stop> {
+    let checkpoint = reader.checkpoint();
+    let method = try!(reader.byte());
+    let flags = try!(reader.byte());
+    // Streams that need a preset dictionary are not supported.
+    if method & 0x0f == 8 && ((method << 8) | flags) % 31 == 0 && flags & 0x20 == 0 {
+        Ok(())
+    } else if optional {
+        reader.rewind(checkpoint);
+        Ok(())
+    } else {
+        Err(InvalidData)
+    }
+}
+
+fn read_gzip_header(reader: &mut BitReader) -> Result<(), Stop> {
+    static FHCRC: uint = 0x02;
+    static FEXTRA: uint = 0x04;
+    static FNAME: uint = 0x08;
+    static FCOMMENT: uint = 0x10;
+
+    if try!(reader.byte()) != 0x1f || try!(reader.byte()) != 0x8b || try!(reader.byte()) != 8 {
+        return Err(InvalidData);
+    }
+    let flags = try!(reader.byte());
+    // The modification time, the extra flags and the operating system.
+    for _ in range(0u, 6) {
+        try!(reader.byte());
+    }
+    if flags & FEXTRA != 0 {
+        let length = try!(reader.bits(16));
+        for _ in range(0, length) {
+            try!(reader.byte());
+        }
+    }
+    for &flag in [FNAME, FCOMMENT].iter() {
+        if flags & flag != 0 {
+            while try!(reader.byte()) != 0 {}
+        }
+    }
+    if flags & FHCRC != 0 {
+        try!(reader.bits(16));
+    }
+    Ok(())
+}
+
+/// Decompresses the rest of a compressed block, stopping after the last symbol that has been
+/// received in full.
+fn inflate_codes(reader: &mut BitReader,
+                 output: &mut Vec<u8>,
+                 literals: &Huffman,
+                 distances: &Huffman)
+                 -> Result<(), Stop> {
+    loop {
+        let checkpoint = reader.checkpoint();
+        match inflate_symbol(reader, output, literals, distances) {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(OutOfData) => {
+                reader.rewind(checkpoint);
+                return Err(OutOfData);
+            }
+            Err(InvalidData) => return Err(InvalidData),
+        }
+    }
+}
+
+/// Decompresses a literal or a back-reference, and returns whether it was not the end of the
+/// block. Nothing is output unless the whole symbol has been received.
+fn inflate_symbol(reader: &mut BitReader,
+                  output: &mut Vec<u8>,
+                  literals: &Huffman,
+                  distances: &Huffman)
+                  -> Result<bool, Stop> {
+    let symbol = try!(literals.decode(reader));
+    if symbol < 256 {
+        output.push(symbol as u8);
+        return Ok(true);
+    }
+    if symbol == 256 {
+        return Ok(false);
+    }
+
+    let symbol = symbol - 257;
+    if symbol >= LENGTH_BASE.len() {
+        return Err(InvalidData);
+    }
+    let length = LENGTH_BASE[symbol] + try!(reader.bits(LENGTH_EXTRA_BITS[symbol]));
+    let symbol = try!(distances.decode(reader));
+    if symbol >= DISTANCE_BASE.len() {
+        return Err(InvalidData);
+    }
+    let distance = DISTANCE_BASE[symbol] + try!(reader.bits(DISTANCE_EXTRA_BITS[symbol]));
+    if distance > output.len() {
+        return Err(InvalidData);
+    }
+    for _ in range(0, length) {
+        let byte = output[output.len() - distance];
+        output.push(byte);
+    }
+    Ok(true)
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8, ..288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = if symbol < 144 {
+            8
+        } else if symbol < 256 {
+            9
+        } else if symbol < 280 {
+            7
+        } else {
+            8
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5u8, ..30]))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), Stop> {
+    let literal_count = try!(reader.bits(5)) + 257;
+    let distance_count = try!(reader.bits(5)) + 1;
+    let code_length_count = try!(reader.bits(4)) + 4;
+
+    let mut code_length_lengths = [0u8, ..19];
+    for &index in CODE_LENGTH_ORDER.slice_to(code_length_count).iter() {
+        code_length_lengths[index] = try!(reader.bits(3)) as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths);
+
+    let count = literal_count + distance_count;
+    let mut lengths = vec!();
+    while lengths.len() < count {
+        let (length, repeat) = match try!(code_lengths.decode(reader)) {
+            symbol if symbol < 16 => (symbol as u8, 1),
+            16 => {
+                let previous = match lengths.last() {
+                    Some(&previous) => previous,
+                    None => return Err(InvalidData),
+                };
+                (previous, 3 + try!(reader.bits(2)))
+            }
+            17 => (0, 3 + try!(reader.bits(3))),
+            18 => (0, 11 + try!(reader.bits(7))),
+            _ => return Err(InvalidData),
+        };
+        if lengths.len() + repeat > count {
+            return Err(InvalidData);
+        }
+        lengths.grow(repeat, length);
+    }
+
+    Ok((Huffman::new(lengths.slice_to(literal_count)),
+        Huffman::new(lengths.slice_from(literal_count))))
+}
+
+/// Decompresses as much of a zlib stream as possible. Decompression stops quietly at the end of
+/// the data or at the first error.
+pub fn inflate_partial(data: &[u8]) -> Vec<u8> {
+    Inflater::new(ZlibFormat).push(data)
+}
+
+/// Feeds `data` to a new `Inflater` a byte at a time, and returns everything it decompressed.
+#[cfg(test)]
+fn inflate_bytewise(format: Format, data: &[u8]) -> (Vec<u8>, Inflater) {
+    let mut inflater = Inflater::new(format);
+    let mut output = vec!();
+    for byte in data.iter() {
+        output.push_all(inflater.push(&[*byte]).as_slice());
+    }
+    (output, inflater)
+}
+
+#[test]
+fn test_inflate_stored() {
+    let data = [0x78, 0x01, 0x01, 0x03, 0x00, 0xfc, 0xff, 1, 2, 3, 0, 0, 0, 0];
+    assert_eq!(inflate_partial(&data), vec!(1, 2, 3));
+    // A truncated block still yields the bytes received so far.
+    assert_eq!(inflate_partial(data.slice_to(9)), vec!(1, 2));
+}
+
+#[test]
+fn test_inflate_fixed() {
+    // "abcabcabc", compressed by zlib with a fixed Huffman code and one back-reference.
+    let data = [0x78, 0x9c, 0x4b, 0x4c, 0x4a, 0x4e, 0x04, 0x23, 0x00, 0x11, 0x3d, 0x03, 0x73];
+    assert_eq!(inflate_partial(&data), b"abcabcabc".to_vec());
+    assert_eq!(inflate_partial(data.slice_to(5)), b"ab".to_vec());
+
+    let (output, inflater) = inflate_bytewise(ZlibFormat, &data);
+    assert_eq!(output, b"abcabcabc".to_vec());
+    assert!(inflater.is_finished());
+}
+
+#[test]
+fn test_inflate_gzip() {
+    let data = [31u8, 139, 8, 8, 0, 0, 0, 0, 2, 255, 97, 46, 116, 120, 116, 0, 203, 72, 205, 201,
+                201, 87, 40, 207, 47, 202, 73, 1, 0, 133, 17, 74, 13, 11, 0, 0, 0];
+    let (output, inflater) = inflate_bytewise(GzipFormat, &data);
+    assert_eq!(output, b"hello world".to_vec());
+    assert!(inflater.is_finished());
+
+    let (output, inflater) = inflate_bytewise(GzipFormat, data.slice_to(20));
+    assert_eq!(output, b"hel".to_vec());
+    assert!(!inflater.is_finished() && !inflater.has_failed());
+
+    let mut inflater = Inflater::new(GzipFormat);
+    assert_eq!(inflater.push(data.slice_from(1)), vec!());
+    assert!(inflater.has_failed());
+}
+
+#[test]
+fn test_inflate_zlib_or_raw() {
+    let data = [120u8, 156, 203, 72, 205, 201, 201, 87, 40, 207, 47, 202, 73, 1, 0, 26, 11, 4, 93];
+    let (output, inflater) = inflate_bytewise(ZlibOrRawFormat, &data);
+    assert_eq!(output, b"hello world".to_vec());
+    assert!(inflater.is_finished());
+
+    let (output, inflater) = inflate_bytewise(ZlibOrRawFormat, data.slice(2, data.len() - 4));
+    assert_eq!(output, b"hello world".to_vec());
+    assert!(inflater.is_finished());
+}
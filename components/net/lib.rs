@@ -10,9 +10,11 @@
 extern crate collections;
 extern crate geom;
 extern crate http;
+extern crate "msg" as servo_msg;
 extern crate png;
 #[phase(plugin, link)]
 extern crate log;
+extern crate openssl;
 extern crate serialize;
 extern crate "util" as servo_util;
 extern crate stb_image;
@@ -26,18 +28,36 @@ extern crate url;
 /// However, image handling is generally very integrated with the network stack (especially where
 /// caching is involved) and as a result it must live in here.
 pub mod image {
+    pub mod apng;
     pub mod base;
+    pub mod color;
+    pub mod gif;
     pub mod holder;
+    pub mod ico;
+    pub mod jpeg;
+    pub mod progressive;
+    pub mod vp8;
+    pub mod vp8l;
+    pub mod webp;
 }
 
 pub mod about_loader;
+pub mod blob_loader;
+pub mod cookie;
+pub mod cookie_storage;
 pub mod file_loader;
+pub mod http_cache;
 pub mod http_loader;
 pub mod data_loader;
 pub mod image_cache_task;
+pub mod inflate;
+pub mod load_interceptor;
 pub mod local_image_cache;
+pub mod mime_classifier;
+pub mod proxy;
 pub mod resource_task;
 pub mod storage_task;
+pub mod websocket;
 mod sniffer_task;
 
 /// An implementation of the [Fetch spec](http://fetch.spec.whatwg.org/)
@@ -0,0 +1,230 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Lets embedders observe and rewrite loads before the resource task hands them to a loader,
+//! for instance to block requests or to stand in for the servers that a test talks to.
+
+use resource_task::{Done, LoadData, Metadata, Payload, ResponseSenders, TargetedLoadResponse};
+use resource_task::start_sending;
+
+use sync::{Arc, Mutex};
+use url::Url;
+
+/// What the resource task does with a load once an interceptor has seen it.
+pub enum InterceptedLoad {
+    /// Passes the load, with any changes made to it, on to the next interceptor and then to the
+    /// loader of its scheme.
+    ContinueLoad,
+    /// Fails the load with the given error.
+    BlockLoad(String),
+    /// Loads the given URL instead. The interceptors run again for the new URL.
+    RedirectLoad(Url),
+    /// Answers the load with the given metadata and body, without loading anything.
+    RespondToLoad(Metadata, Vec<u8>),
+}
+
+/// Something that sees every load before it starts. It may change the load, for example to add
+/// headers to it, or deal with it itself. It runs on a task of its own for the first request of
+/// a load and on the HTTP loader's task for every redirect, so it may block, but it may also see
+/// several loads at once.
+pub trait LoadInterceptor {
+    fn intercept(&self, load_data: &mut LoadData) -> InterceptedLoad;
+}
+
+/// The interceptors registered with the resource task, in the order that they run. Clones share
+/// the interceptors, so that the HTTP loader can run them again on every redirect.
+#[deriving(Clone)]
+pub struct LoadInterceptors {
+    interceptors: Arc<Mutex<Vec<Arc<Box<LoadInterceptor + Send + Sync>>>>>,
+}
+
+impl LoadInterceptors {
+    pub fn new() -> LoadInterceptors {
+        LoadInterceptors {
+            interceptors: Arc::new(Mutex::new(vec!())),
+        }
+    }
+
+    /// Adds an interceptor, which runs after those registered before it.
+    pub fn register(&self, interceptor: Box<LoadInterceptor + Send + Sync>) {
+        self.interceptors.lock().push(Arc::new(interceptor));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.interceptors.lock().is_empty()
+    }
+
+    /// Runs the interceptors on a load, stopping at the first one that does not let it continue.
+    /// Returns the load if it should go on to a loader; otherwise it has been answered already.
+    pub fn run(&self, load_data: LoadData, start_chan: Sender<TargetedLoadResponse>,
+               max_redirects: uint) -> Option<LoadData> {
+        let mut load_data = load_data;
+        let mut redirect_count = 0u;
+        // The lock is only held to take a snapshot, so that an interceptor that blocks holds up
+        // neither other loads nor the registration of further interceptors.
+        let interceptors = self.interceptors.lock().clone();
+        'redirects: loop {
+            for interceptor in interceptors.iter() {
+                let senders = ResponseSenders {
+                    immediate_consumer: start_chan.clone(),
+                    eventual_consumer: load_data.consumer.clone(),
+                };
+                match interceptor.intercept(&mut load_data) {
+                    ContinueLoad => (),
+                    BlockLoad(message) => {
+                        debug!("load_interceptor: blocked {:s}", load_data.url.serialize());
+                        start_sending(senders, Metadata::default(load_data.url))
+                            .send(Done(Err(message)));
+                        return None
+                    }
+                    RedirectLoad(url) => {
+                        if redirect_count == max_redirects {
+                            start_sending(senders, Metadata::default(load_data.url))
+                                .send(Done(Err("too many redirects".to_string())));
+                            return None
+                        }
+                        redirect_count += 1;
+                        debug!("load_interceptor: redirecting {:s} to {:s}",
+                               load_data.url.serialize(), url.serialize());
+                        load_data.url = url;
+                        continue 'redirects
+                    }
+                    RespondToLoad(metadata, body) => {
+                        let progress_chan = start_sending(senders, metadata);
+                        progress_chan.send(Payload(body));
+                        progress_chan.send(Done(Ok(())));
+                        return None
+                    }
+                }
+            }
+            return Some(load_data)
+        }
+    }
+}
+
+/// Answers loads of one URL with a fixed body, and blocks loads of another.
+#[cfg(test)]
+struct TestInterceptor {
+    mocked: Url,
+    blocked: Url,
+}
+
+#[cfg(test)]
+impl LoadInterceptor for TestInterceptor {
+    fn intercept(&self, load_data: &mut LoadData) -> InterceptedLoad {
+        use http::headers::HeaderEnum;
+
+        if load_data.url == self.blocked {
+            return BlockLoad("blocked".to_string())
+        }
+        if load_data.url != self.mocked {
+            return ContinueLoad
+        }
+        let mut metadata = Metadata::default(load_data.url.clone());
+        metadata.content_type = Some(("text".to_string(), "plain".to_string()));
+        let injected = load_data.headers.iter().any(|header| {
+            header.header_name().as_slice() == "X-Injected"
+        });
+        RespondToLoad(metadata, format!("injected: {}", injected).into_bytes())
+    }
+}
+
+/// Adds a header to every load, and redirects the given URL.
+#[cfg(test)]
+struct TestRedirector {
+    from: Url,
+    to: Url,
+}
+
+#[cfg(test)]
+impl LoadInterceptor for TestRedirector {
+    fn intercept(&self, load_data: &mut LoadData) -> InterceptedLoad {
+        let _ = load_data.headers.insert_raw("X-Injected".to_string(), b"1");
+        if load_data.url == self.from {
+            RedirectLoad(self.to.clone())
+        } else {
+            ContinueLoad
+        }
+    }
+}
+
+#[test]
+fn test_load_interceptors() {
+    use resource_task::{Exit, RegisterLoadInterceptor, load_whole_resource, new_resource_task};
+    use servo_util::opts;
+
+    let mocked = Url::parse("http://mocked.test/").unwrap();
+    let blocked = Url::parse("http://blocked.test/").unwrap();
+    let moved = Url::parse("http://moved.test/").unwrap();
+    let resource_task = new_resource_task(opts::default_network_opts());
+    resource_task.send(RegisterLoadInterceptor(box TestRedirector {
+        from: moved.clone(),
+        to: mocked.clone(),
+    }));
+    resource_task.send(RegisterLoadInterceptor(box TestInterceptor {
+        mocked: mocked.clone(),
+        blocked: blocked.clone(),
+    }));
+
+    let (metadata, body) = load_whole_resource(&resource_task, mocked.clone()).unwrap();
+    assert_eq!(metadata.content_type, Some(("text".to_string(), "plain".to_string())));
+    assert_eq!(body, b"injected: true".to_vec());
+
+    let (metadata, body) = load_whole_resource(&resource_task, moved).unwrap();
+    assert_eq!(metadata.final_url, mocked);
+    assert_eq!(body, b"injected: true".to_vec());
+
+    assert!(load_whole_resource(&resource_task, blocked).is_err());
+    resource_task.send(Exit);
+}
+
+#[test]
+fn test_redirect_loop() {
+    use resource_task::{Exit, RegisterLoadInterceptor, load_whole_resource, new_resource_task};
+    use servo_util::opts;
+
+    let url = Url::parse("http://loop.test/").unwrap();
+    let resource_task = new_resource_task(opts::default_network_opts());
+    resource_task.send(RegisterLoadInterceptor(box TestRedirector {
+        from: url.clone(),
+        to: url.clone(),
+    }));
+    assert!(load_whole_resource(&resource_task, url).is_err());
+    resource_task.send(Exit);
+}
+
+#[test]
+fn test_redirects_are_intercepted() {
+    use resource_task::{Exit, RegisterLoadInterceptor, load_whole_resource, new_resource_task};
+    use servo_util::opts;
+    use servo_util::task::spawn_named;
+    use std::io::{Acceptor, Listener};
+    use std::io::net::tcp::TcpListener;
+
+    let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.socket_name().unwrap().port;
+    let mut acceptor = listener.listen().unwrap();
+    spawn_named("test HTTP server", proc() {
+        let mut stream = acceptor.accept().unwrap();
+        let mut head = vec!();
+        while !head.as_slice().ends_with(b"\r\n\r\n") {
+            head.push(stream.read_byte().unwrap());
+        }
+        stream.write_str("HTTP/1.1 302 Found\r\nLocation: http://mocked.test/\r\n\
+                          Content-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+    });
+
+    let mocked = Url::parse("http://mocked.test/").unwrap();
+    let resource_task = new_resource_task(opts::default_network_opts());
+    resource_task.send(RegisterLoadInterceptor(box TestInterceptor {
+        mocked: mocked.clone(),
+        blocked: Url::parse("http://blocked.test/").unwrap(),
+    }));
+
+    let url = Url::parse(format!("http://127.0.0.1:{}/", port).as_slice()).unwrap();
+    let (metadata, body) = load_whole_resource(&resource_task, url).unwrap();
+    assert_eq!(metadata.final_url, mocked);
+    assert_eq!(body, b"injected: false".to_vec());
+    resource_task.send(Exit);
+}
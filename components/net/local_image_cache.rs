@@ -8,8 +8,9 @@ extra message traffic, it also avoids waiting on the same image
 multiple times and thus triggering reflows multiple times.
 */
 
-use image_cache_task::{Decode, GetImage, ImageCacheTask, ImageFailed, ImageNotReady, ImageReady};
-use image_cache_task::{ImageResponseMsg, Prefetch, WaitForImage};
+use image_cache_task::{AddEvictionListener, Decode, GetImage, ImageCacheTask, ImageFailed};
+use image_cache_task::{ImageNotReady, ImageReady};
+use image_cache_task::{ImageOwner, ImagePartial, ImageResponseMsg, Prefetch, WaitForProgress};
 
 use std::comm::{Receiver, channel};
 use std::collections::HashMap;
@@ -23,18 +24,26 @@ pub trait ImageResponder<NodeAddress: Send> {
 
 pub struct LocalImageCache<NodeAddress> {
     image_cache_task: ImageCacheTask,
+    /// The pipeline on whose behalf images are prefetched
+    owner: ImageOwner,
     round_number: uint,
     on_image_available: Option<Box<ImageResponder<NodeAddress>+Send>>,
-    state_map: HashMap<Url, ImageState>
+    state_map: HashMap<Url, ImageState>,
+    /// The images that the image cache task has evicted, whose copies must be dropped here too
+    evicted: Receiver<Url>,
 }
 
 impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
-    pub fn new(image_cache_task: ImageCacheTask) -> LocalImageCache<NodeAddress> {
+    pub fn new(image_cache_task: ImageCacheTask, owner: ImageOwner) -> LocalImageCache<NodeAddress> {
+        let (evicted_chan, evicted_port) = channel();
+        image_cache_task.send(AddEvictionListener(evicted_chan));
         LocalImageCache {
             image_cache_task: image_cache_task,
+            owner: owner,
             round_number: 1,
             on_image_available: None,
-            state_map: HashMap::new()
+            state_map: HashMap::new(),
+            evicted: evicted_port,
         }
     }
 }
@@ -65,7 +74,7 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
             state.prefetched = true;
         }
 
-        self.image_cache_task.send(Prefetch((*url).clone()));
+        self.image_cache_task.send(Prefetch((*url).clone(), self.owner));
     }
 
     pub fn decode(&mut self, url: &Url) {
@@ -82,6 +91,8 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
 
     // FIXME: Should return a Future
     pub fn get_image(&mut self, node_address: NodeAddress, url: &Url) -> Receiver<ImageResponseMsg> {
+        self.forget_evicted();
+
         {
             let round_number = self.round_number;
             let state = self.get_state(url);
@@ -97,10 +108,10 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
                     chan.send(ImageReady(image.clone()));
                     return port;
                 }
-                ImageNotReady => {
+                ImageNotReady | ImagePartial(..) => {
                     if last_round == round_number {
                         let (chan, port) = channel();
-                        chan.send(ImageNotReady);
+                        chan.send(state.last_response.clone());
                         return port;
                     } else {
                         // We haven't requested the image from the
@@ -120,8 +131,8 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
 
         let response = response_port.recv();
         match response {
-            ImageNotReady => {
-                // Need to reflow when the image is available
+            ImageNotReady | ImagePartial(..) => {
+                // Need to reflow when more of the image is available
                 // FIXME: Instead we should be just passing a Future
                 // to the caller, then to the display list. Finally,
                 // the compositor should be resonsible for waiting
@@ -133,7 +144,7 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
                 let url = (*url).clone();
                 spawn_named("LocalImageCache", proc() {
                     let (response_chan, response_port) = channel();
-                    image_cache_task.send(WaitForImage(url, response_chan));
+                    image_cache_task.send(WaitForProgress(url, response_chan));
                     on_image_available(response_port.recv(), node_address);
                 });
             }
@@ -143,6 +154,7 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
         // Put a copy of the response in the cache
         let response_copy = match response {
             ImageReady(ref image) => ImageReady(image.clone()),
+            ImagePartial(ref image) => ImagePartial(image.clone()),
             ImageNotReady => ImageNotReady,
             ImageFailed => ImageFailed
         };
@@ -153,6 +165,24 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
         return port;
     }
 
+    /// Drops the responses holding images that the image cache task has evicted, so that their
+    /// memory is freed. The images are requested again the next time they are needed.
+    fn forget_evicted(&mut self) {
+        loop {
+            let url = match self.evicted.try_recv() {
+                Ok(url) => url,
+                Err(_) => return,
+            };
+            match self.state_map.get_mut(&url) {
+                Some(state) => {
+                    state.last_response = ImageNotReady;
+                    state.last_request_round = 0;
+                }
+                None => (),
+            }
+        }
+    }
+
     fn get_state<'a>(&'a mut self, url: &Url) -> &'a mut ImageState {
         match self.state_map.entry((*url).clone()) {
             Occupied(entry) => entry.into_mut(),
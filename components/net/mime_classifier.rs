@@ -0,0 +1,427 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An implementation of the [MIME Sniffing standard](http://mimesniff.spec.whatwg.org/).
+
+use std::ascii::AsciiExt;
+
+/// The number of bytes the sniffing algorithms look at.
+/// http://mimesniff.spec.whatwg.org/#reading-the-resource-header
+pub static RESOURCE_HEADER_SIZE: uint = 512;
+
+pub type MimeType = (String, String);
+
+/// A byte pattern with a mask, as used throughout the spec.
+/// http://mimesniff.spec.whatwg.org/#matching-a-mime-type-pattern
+struct BytePattern {
+    pattern: &'static [u8],
+    mask: &'static [u8],
+    /// Whether leading whitespace bytes in the resource are skipped.
+    ignore_leading_whitespace: bool,
+    mime_type: (&'static str, &'static str),
+}
+
+impl BytePattern {
+    fn matches(&self, data: &[u8]) -> bool {
+        let data = if self.ignore_leading_whitespace {
+            let start = data.iter().position(|&b| !is_whitespace_byte(b)).unwrap_or(data.len());
+            data.slice_from(start)
+        } else {
+            data
+        };
+        if data.len() < self.pattern.len() {
+            return false;
+        }
+        self.pattern.iter().zip(self.mask.iter()).zip(data.iter()).all(|((&p, &m), &d)| {
+            d & m == p
+        })
+    }
+}
+
+/// http://mimesniff.spec.whatwg.org/#whitespace-byte
+fn is_whitespace_byte(b: u8) -> bool {
+    match b {
+        0x09 | 0x0A | 0x0C | 0x0D | 0x20 => true,
+        _ => false,
+    }
+}
+
+/// http://mimesniff.spec.whatwg.org/#tag-terminating-byte
+fn is_tag_terminating_byte(b: u8) -> bool {
+    b == 0x20 || b == 0x3E
+}
+
+/// http://mimesniff.spec.whatwg.org/#binary-data-byte
+fn is_binary_data_byte(b: u8) -> bool {
+    match b {
+        0x00...0x08 | 0x0B | 0x0E...0x1A | 0x1C...0x1F => true,
+        _ => false,
+    }
+}
+
+fn mime(mime_type: (&'static str, &'static str)) -> MimeType {
+    let (top_level, sub_level) = mime_type;
+    (top_level.to_string(), sub_level.to_string())
+}
+
+/// Tags identifying HTML documents. Each must be followed by a tag-terminating byte.
+/// http://mimesniff.spec.whatwg.org/#identifying-a-resource-with-an-unknown-mime-type
+static HTML_TAGS: &'static [&'static [u8]] = &[
+    b"<!DOCTYPE HTML", b"<HTML", b"<HEAD", b"<SCRIPT", b"<IFRAME", b"<H1", b"<DIV",
+    b"<FONT", b"<TABLE", b"<A", b"<STYLE", b"<TITLE", b"<B", b"<BODY", b"<BR", b"<P", b"<!--",
+];
+
+fn sniff_html(data: &[u8]) -> bool {
+    let start = data.iter().position(|&b| !is_whitespace_byte(b)).unwrap_or(data.len());
+    let data = data.slice_from(start);
+    HTML_TAGS.iter().any(|tag| {
+        if data.len() <= tag.len() {
+            return false;
+        }
+        let matches = tag.iter().zip(data.iter()).all(|(&t, &d)| {
+            // Letters in the tags match case-insensitively.
+            if t >= b'A' && t <= b'Z' { d & 0xDF == t } else { d == t }
+        });
+        matches && is_tag_terminating_byte(data[tag.len()])
+    })
+}
+
+static SCRIPTABLE_PATTERNS: &'static [BytePattern] = &[
+    BytePattern { pattern: b"<?xml", mask: b"\xFF\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: true, mime_type: ("text", "xml") },
+    BytePattern { pattern: b"%PDF-", mask: b"\xFF\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("application", "pdf") },
+];
+
+static NON_SCRIPTABLE_PATTERNS: &'static [BytePattern] = &[
+    BytePattern { pattern: b"%!PS-Adobe-", mask: b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("application", "postscript") },
+    BytePattern { pattern: b"\xFE\xFF\x00\x00", mask: b"\xFF\xFF\x00\x00",
+                  ignore_leading_whitespace: false, mime_type: ("text", "plain") },
+    BytePattern { pattern: b"\xFF\xFE\x00\x00", mask: b"\xFF\xFF\x00\x00",
+                  ignore_leading_whitespace: false, mime_type: ("text", "plain") },
+    BytePattern { pattern: b"\xEF\xBB\xBF\x00", mask: b"\xFF\xFF\xFF\x00",
+                  ignore_leading_whitespace: false, mime_type: ("text", "plain") },
+];
+
+/// http://mimesniff.spec.whatwg.org/#matching-an-image-type-pattern
+static IMAGE_PATTERNS: &'static [BytePattern] = &[
+    BytePattern { pattern: b"\x00\x00\x01\x00", mask: b"\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("image", "x-icon") },
+    BytePattern { pattern: b"\x00\x00\x02\x00", mask: b"\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("image", "x-icon") },
+    BytePattern { pattern: b"BM", mask: b"\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("image", "bmp") },
+    BytePattern { pattern: b"GIF87a", mask: b"\xFF\xFF\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("image", "gif") },
+    BytePattern { pattern: b"GIF89a", mask: b"\xFF\xFF\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("image", "gif") },
+    BytePattern { pattern: b"RIFF\x00\x00\x00\x00WEBPVP",
+                  mask: b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("image", "webp") },
+    BytePattern { pattern: b"\x89PNG\r\n\x1A\n", mask: b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("image", "png") },
+    BytePattern { pattern: b"\xFF\xD8\xFF", mask: b"\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("image", "jpeg") },
+];
+
+/// http://mimesniff.spec.whatwg.org/#matching-an-audio-or-video-type-pattern
+static MEDIA_PATTERNS: &'static [BytePattern] = &[
+    BytePattern { pattern: b"FORM\x00\x00\x00\x00AIFF",
+                  mask: b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("audio", "aiff") },
+    BytePattern { pattern: b"ID3", mask: b"\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("audio", "mpeg") },
+    BytePattern { pattern: b"OggS\x00", mask: b"\xFF\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("application", "ogg") },
+    BytePattern { pattern: b"MThd\x00\x00\x00\x06", mask: b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("audio", "midi") },
+    BytePattern { pattern: b"RIFF\x00\x00\x00\x00AVI ",
+                  mask: b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("video", "avi") },
+    BytePattern { pattern: b"RIFF\x00\x00\x00\x00WAVE",
+                  mask: b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("audio", "wave") },
+];
+
+/// Font signatures other than embedded OpenType.
+static FONT_PATTERNS: &'static [BytePattern] = &[
+    BytePattern { pattern: b"\x00\x01\x00\x00", mask: b"\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("application", "font-sfnt") },
+    BytePattern { pattern: b"OTTO", mask: b"\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("application", "font-sfnt") },
+    BytePattern { pattern: b"ttcf", mask: b"\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("application", "font-sfnt") },
+    BytePattern { pattern: b"wOFF", mask: b"\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("application", "font-woff") },
+];
+
+/// http://mimesniff.spec.whatwg.org/#matching-an-archive-type-pattern
+static ARCHIVE_PATTERNS: &'static [BytePattern] = &[
+    BytePattern { pattern: b"\x1F\x8B\x08", mask: b"\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("application", "x-gzip") },
+    BytePattern { pattern: b"PK\x03\x04", mask: b"\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("application", "zip") },
+    BytePattern { pattern: b"Rar \x1A\x07\x00", mask: b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF",
+                  ignore_leading_whitespace: false, mime_type: ("application", "x-rar-compressed") },
+];
+
+/// http://mimesniff.spec.whatwg.org/#matching-a-font-type-pattern
+///
+/// Embedded OpenType fonts are identified by the "LP" magic number following
+/// 34 bytes of header.
+fn sniff_font(data: &[u8]) -> Option<MimeType> {
+    if data.len() >= 36 && data.slice(34, 36) == b"LP" {
+        return Some(mime(("application", "vnd.ms-fontobject")));
+    }
+    match_patterns(FONT_PATTERNS, data)
+}
+
+fn match_patterns(patterns: &[BytePattern], data: &[u8]) -> Option<MimeType> {
+    patterns.iter().find(|p| p.matches(data)).map(|p| mime(p.mime_type))
+}
+
+fn read_u32_be(data: &[u8], offset: uint) -> uint {
+    (data[offset] as uint << 24) | (data[offset + 1] as uint << 16) |
+        (data[offset + 2] as uint << 8) | data[offset + 3] as uint
+}
+
+/// http://mimesniff.spec.whatwg.org/#signature-for-mp4
+fn is_mp4(data: &[u8]) -> bool {
+    if data.len() < 12 {
+        return false;
+    }
+    let box_size = read_u32_be(data, 0);
+    if data.len() < box_size || box_size % 4 != 0 || data.slice(4, 8) != b"ftyp" {
+        return false;
+    }
+    if data.slice(8, 11) == b"mp4" {
+        return true;
+    }
+    let mut offset = 16;
+    while offset + 3 <= box_size {
+        if data.slice(offset, offset + 3) == b"mp4" {
+            return true;
+        }
+        offset += 4;
+    }
+    false
+}
+
+/// An approximation of http://mimesniff.spec.whatwg.org/#signature-for-webm that
+/// looks for the "webm" DocType within the EBML header.
+fn is_webm(data: &[u8]) -> bool {
+    data.len() >= 4 && data.slice_to(4) == b"\x1A\x45\xDF\xA3" &&
+        data.slice_from(4).windows(4).any(|w| w == b"webm")
+}
+
+fn sniff_media(data: &[u8]) -> Option<MimeType> {
+    match match_patterns(MEDIA_PATTERNS, data) {
+        Some(mime_type) => Some(mime_type),
+        None if is_mp4(data) => Some(mime(("video", "mp4"))),
+        None if is_webm(data) => Some(mime(("video", "webm"))),
+        None => None,
+    }
+}
+
+/// http://mimesniff.spec.whatwg.org/#rules-for-text-or-binary
+fn sniff_text_or_binary(data: &[u8]) -> MimeType {
+    let has_bom = data.starts_with(b"\xFE\xFF") || data.starts_with(b"\xFF\xFE") ||
+                  data.starts_with(b"\xEF\xBB\xBF");
+    if has_bom || !data.iter().any(|&b| is_binary_data_byte(b)) {
+        mime(("text", "plain"))
+    } else {
+        mime(("application", "octet-stream"))
+    }
+}
+
+/// http://mimesniff.spec.whatwg.org/#rules-for-identifying-an-unknown-mime-type
+///
+/// Font signatures are also recognized here; the spec only applies them in
+/// a font context, but fonts loaded from `file://` URLs have no other way of
+/// being identified.
+fn sniff_unknown(data: &[u8], sniff_scriptable: bool) -> MimeType {
+    if sniff_scriptable {
+        if sniff_html(data) {
+            return mime(("text", "html"));
+        }
+        match match_patterns(SCRIPTABLE_PATTERNS, data) {
+            Some(mime_type) => return mime_type,
+            None => (),
+        }
+    }
+    match match_patterns(NON_SCRIPTABLE_PATTERNS, data)
+            .or_else(|| match_patterns(IMAGE_PATTERNS, data))
+            .or_else(|| sniff_media(data))
+            .or_else(|| match_patterns(ARCHIVE_PATTERNS, data))
+            .or_else(|| sniff_font(data)) {
+        Some(mime_type) => return mime_type,
+        None => (),
+    }
+    if !data.iter().any(|&b| is_binary_data_byte(b)) {
+        mime(("text", "plain"))
+    } else {
+        mime(("application", "octet-stream"))
+    }
+}
+
+fn is_unknown_type(mime_type: &Option<MimeType>) -> bool {
+    match *mime_type {
+        None => true,
+        Some((ref top_level, ref sub_level)) => {
+            match (top_level.as_slice(), sub_level.as_slice()) {
+                ("unknown", "unknown") | ("application", "unknown") | ("*", "*") => true,
+                _ => false,
+            }
+        }
+    }
+}
+
+fn is_xml_type(top_level: &str, sub_level: &str) -> bool {
+    sub_level.ends_with("+xml") ||
+        (sub_level == "xml" && (top_level == "text" || top_level == "application"))
+}
+
+/// The information about a response that, besides its bytes, drives sniffing.
+pub struct SniffingContext {
+    /// The MIME type supplied by the server, if any.
+    pub supplied_type: Option<MimeType>,
+    /// Whether the response carried `X-Content-Type-Options: nosniff`.
+    pub no_sniff: bool,
+    /// Whether the raw `Content-Type` header is one of the values Apache sends
+    /// by default, which often mislabel binary files as text.
+    /// http://mimesniff.spec.whatwg.org/#check-for-apache-bug-flag
+    pub check_for_apache_bug: bool,
+}
+
+impl SniffingContext {
+    /// Whether `classify` may look at the resource header at all. When it
+    /// returns false, the supplied type is final and no bytes need to be read.
+    pub fn needs_resource_header(&self) -> bool {
+        if is_unknown_type(&self.supplied_type) {
+            return true;
+        }
+        if self.no_sniff {
+            return false;
+        }
+        if self.check_for_apache_bug {
+            return true;
+        }
+        match self.supplied_type {
+            Some((ref top_level, _)) => match top_level.as_slice() {
+                "image" | "audio" | "video" => true,
+                _ => false,
+            },
+            None => true,
+        }
+    }
+
+    /// Compute the sniffed MIME type of a resource whose first bytes are `data`.
+    /// http://mimesniff.spec.whatwg.org/#mime-type-sniffing-algorithm
+    pub fn classify(&self, data: &[u8]) -> MimeType {
+        let data = if data.len() > RESOURCE_HEADER_SIZE {
+            data.slice_to(RESOURCE_HEADER_SIZE)
+        } else {
+            data
+        };
+
+        let (top_level, sub_level) = match self.supplied_type {
+            ref supplied if is_unknown_type(supplied) => {
+                return sniff_unknown(data, !self.no_sniff);
+            }
+            Some(ref supplied) => supplied.clone(),
+            None => unreachable!(),
+        };
+        if self.no_sniff {
+            return (top_level, sub_level);
+        }
+        if self.check_for_apache_bug {
+            return sniff_text_or_binary(data);
+        }
+        if is_xml_type(top_level.as_slice(), sub_level.as_slice()) {
+            return (top_level, sub_level);
+        }
+        let sniffed = match top_level.as_slice() {
+            "image" => match_patterns(IMAGE_PATTERNS, data),
+            "audio" | "video" => sniff_media(data),
+            _ => None,
+        };
+        sniffed.unwrap_or((top_level, sub_level))
+    }
+}
+
+/// Whether a raw `Content-Type` header value triggers the check-for-apache-bug flag.
+pub fn is_apache_bug_content_type(content_type: &str) -> bool {
+    match content_type {
+        "text/plain" | "text/plain; charset=ISO-8859-1" |
+        "text/plain; charset=iso-8859-1" | "text/plain; charset=UTF-8" => true,
+        _ => false,
+    }
+}
+
+/// Whether an `X-Content-Type-Options` header value requests no sniffing.
+pub fn is_nosniff(value: &str) -> bool {
+    value.trim().eq_ignore_ascii_case("nosniff")
+}
+
+#[cfg(test)]
+fn sniff(supplied_type: Option<(&str, &str)>, data: &[u8]) -> MimeType {
+    let context = SniffingContext {
+        supplied_type: supplied_type.map(|(t, s)| (t.to_string(), s.to_string())),
+        no_sniff: false,
+        check_for_apache_bug: false,
+    };
+    context.classify(data)
+}
+
+#[test]
+fn test_sniff_unknown() {
+    assert_eq!(sniff(None, b"  <!DOCTYPE html>"), mime(("text", "html")));
+    assert_eq!(sniff(None, b"<p>hello</p>"), mime(("text", "html")));
+    assert_eq!(sniff(None, b"<pre>"), mime(("text", "plain")));
+    assert_eq!(sniff(None, b"<?xml version=\"1.0\"?>"), mime(("text", "xml")));
+    assert_eq!(sniff(None, b"%PDF-1.4"), mime(("application", "pdf")));
+    assert_eq!(sniff(None, b"\x89PNG\r\n\x1A\n\x00\x00"), mime(("image", "png")));
+    assert_eq!(sniff(None, b"GIF89a\x01\x00"), mime(("image", "gif")));
+    assert_eq!(sniff(None, b"\xFF\xD8\xFF\xE0"), mime(("image", "jpeg")));
+    assert_eq!(sniff(None, b"PK\x03\x04\x14\x00"), mime(("application", "zip")));
+    assert_eq!(sniff(None, b"wOFF\x00\x01"), mime(("application", "font-woff")));
+    assert_eq!(sniff(None, b"plain old text"), mime(("text", "plain")));
+    assert_eq!(sniff(None, b"\x00\x01\x02\x03\x04"), mime(("application", "octet-stream")));
+    assert_eq!(sniff(Some(("application", "unknown")), b"<html>"), mime(("text", "html")));
+}
+
+#[test]
+fn test_sniff_supplied() {
+    assert_eq!(sniff(Some(("text", "html")), b"\x89PNG\r\n\x1A\n"), mime(("text", "html")));
+    assert_eq!(sniff(Some(("image", "gif")), b"\x89PNG\r\n\x1A\n"), mime(("image", "png")));
+    assert_eq!(sniff(Some(("image", "gif")), b"not an image"), mime(("image", "gif")));
+    assert_eq!(sniff(Some(("image", "svg+xml")), b"<svg>"), mime(("image", "svg+xml")));
+    assert_eq!(sniff(Some(("video", "mp4")), b"\x1A\x45\xDF\xA3\x42\x82\x84webm"),
+               mime(("video", "webm")));
+}
+
+#[test]
+fn test_sniff_flags() {
+    let apache = SniffingContext {
+        supplied_type: Some(mime(("text", "plain"))),
+        no_sniff: false,
+        check_for_apache_bug: true,
+    };
+    assert!(apache.needs_resource_header());
+    assert_eq!(apache.classify(b"\x00\x01binary"), mime(("application", "octet-stream")));
+    assert_eq!(apache.classify(b"just text"), mime(("text", "plain")));
+
+    let no_sniff = SniffingContext {
+        supplied_type: Some(mime(("image", "gif"))),
+        no_sniff: true,
+        check_for_apache_bug: false,
+    };
+    assert!(!no_sniff.needs_resource_header());
+    assert_eq!(no_sniff.classify(b"\x89PNG\r\n\x1A\n"), mime(("image", "gif")));
+
+    // Without a supplied type, nosniff only prevents scriptable types.
+    let unknown = SniffingContext { supplied_type: None, no_sniff: true, check_for_apache_bug: false };
+    assert_eq!(unknown.classify(b"<html>"), mime(("text", "plain")));
+}
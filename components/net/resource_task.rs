@@ -5,9 +5,19 @@
 //! A task that takes a URL and streams back the binary data.
 
 use about_loader;
+use about_loader::{AboutPage, AboutPages};
+use blob_loader;
+use blob_loader::{BlobData, BlobStore};
+use cookie::CookieSource;
+use cookie_storage;
+use cookie_storage::CookieStorageTask;
 use data_loader;
 use file_loader;
+use http_cache;
+use http_cache::HttpCacheTask;
 use http_loader;
+use load_interceptor::{LoadInterceptor, LoadInterceptors};
+use proxy::ProxySettings;
 use sniffer_task;
 use sniffer_task::SnifferTask;
 
@@ -21,11 +31,27 @@ use url::Url;
 use http::status::Ok as StatusOk;
 use http::status::Status;
 
+use servo_util::opts;
+use servo_util::opts::NetworkOpts;
 use servo_util::task::spawn_named;
 
 pub enum ControlMsg {
     /// Request the data associated with a particular URL
     Load(LoadData),
+    /// Store a set of cookies for a given originating URL
+    SetCookiesForUrl(Url, String, CookieSource),
+    /// Retrieve the stored cookies for a given URL
+    GetCookiesForUrl(Url, Sender<Option<String>>, CookieSource),
+    /// Add a page that about: URLs of the given name load
+    RegisterAboutPage(String, Box<AboutPage + Send>),
+    /// Make a blob: URL point to the given blob
+    AddBlobUrl(Url, BlobData),
+    /// Forget the blob a blob: URL points to
+    RevokeBlobUrl(Url),
+    /// Add an interceptor that sees every load before it starts
+    RegisterLoadInterceptor(Box<LoadInterceptor + Send + Sync>),
+    /// Retrieve the network settings the resource task was started with
+    GetNetworkOpts(Sender<NetworkOpts>),
     Exit
 }
 
@@ -36,6 +62,14 @@ pub struct LoadData {
     pub headers: RequestHeaderCollection,
     pub data: Option<Vec<u8>>,
     pub cors: Option<ResourceCORSData>,
+    /// Whether the body should be handed to the consumer still content-encoded
+    /// (http://fetch.spec.whatwg.org/#concept-request-preserve-content-codings-flag)
+    pub preserve_content_codings: bool,
+    /// Whether cookies are sent with the request and stored from the response
+    /// (http://fetch.spec.whatwg.org/#concept-http-network-or-cache-fetch)
+    pub credentials_flag: bool,
+    /// Whether redirect responses are handed to the consumer instead of being followed
+    pub manual_redirect: bool,
     pub consumer: Sender<LoadResponse>,
 }
 
@@ -47,6 +81,9 @@ impl LoadData {
             headers: RequestHeaderCollection::new(),
             data: None,
             cors: None,
+            preserve_content_codings: false,
+            credentials_flag: true,
+            manual_redirect: false,
             consumer: consumer,
         }
     }
@@ -181,41 +218,100 @@ pub fn load_whole_resource(resource_task: &ResourceTask, url: Url)
 /// Handle to a resource task
 pub type ResourceTask = Sender<ControlMsg>;
 
-/// Create a ResourceTask
-pub fn new_resource_task(user_agent: Option<String>) -> ResourceTask {
+/// Create a ResourceTask whose loaders follow the given network settings
+pub fn new_resource_task(network_opts: NetworkOpts) -> ResourceTask {
     let (setup_chan, setup_port) = channel();
     let sniffer_task = sniffer_task::new_sniffer_task();
     spawn_named("ResourceManager", proc() {
-        ResourceManager::new(setup_port, user_agent, sniffer_task).start();
+        let mut resource_manager = ResourceManager::new(setup_port, network_opts, sniffer_task);
+        resource_manager.start();
     });
     setup_chan
 }
 
 struct ResourceManager {
     from_client: Receiver<ControlMsg>,
-    user_agent: Option<String>,
+    network_opts: NetworkOpts,
+    proxy_settings: ProxySettings,
     sniffer_task: SnifferTask,
+    cookie_storage: CookieStorageTask,
+    http_cache: HttpCacheTask,
+    about_pages: AboutPages,
+    blob_store: BlobStore,
+    load_interceptors: LoadInterceptors,
+    /// The loads that the interceptors, running on tasks of their own, let through.
+    intercepted_chan: Sender<LoadData>,
+    intercepted_port: Receiver<LoadData>,
 }
 
 impl ResourceManager {
-    fn new(from_client: Receiver<ControlMsg>, user_agent: Option<String>, sniffer_task: SnifferTask) -> ResourceManager {
+    fn new(from_client: Receiver<ControlMsg>, network_opts: NetworkOpts, sniffer_task: SnifferTask) -> ResourceManager {
+        let opts = opts::get();
+        let http_cache_dir = opts.http_cache_dir.as_ref().map(|dir| Path::new(dir.as_slice()));
+        let (intercepted_chan, intercepted_port) = channel();
         ResourceManager {
             from_client: from_client,
-            user_agent: user_agent,
+            intercepted_chan: intercepted_chan,
+            intercepted_port: intercepted_port,
+            proxy_settings: ProxySettings::from_opts(&network_opts),
+            about_pages: AboutPages::new(&network_opts),
+            blob_store: BlobStore::new(),
+            load_interceptors: LoadInterceptors::new(),
+            network_opts: network_opts,
             sniffer_task: sniffer_task,
+            cookie_storage: cookie_storage::new_cookie_storage_task(),
+            http_cache: http_cache::new_http_cache_task(opts.http_cache_size, http_cache_dir),
         }
     }
 }
 
 
 impl ResourceManager {
-    fn start(&self) {
+    fn start(&mut self) {
         loop {
-            match self.from_client.recv() {
+            let msg = {
+                let from_client = &self.from_client;
+                let intercepted_port = &self.intercepted_port;
+                select! (
+                    msg = from_client.recv() => Ok(msg),
+                    load_data = intercepted_port.recv() => Err(load_data)
+                )
+            };
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(load_data) => {
+                    self.start_loader(load_data);
+                    continue
+                }
+            };
+            match msg {
               Load(load_data) => {
                 self.load(load_data)
               }
+              SetCookiesForUrl(request, cookie_list, source) => {
+                self.cookie_storage.send(cookie_storage::SetCookiesForUrl(request, cookie_list, source));
+              }
+              GetCookiesForUrl(url, consumer, source) => {
+                self.cookie_storage.send(cookie_storage::GetCookiesForUrl(url, consumer, source));
+              }
+              RegisterAboutPage(name, page) => {
+                self.about_pages.register(name, page);
+              }
+              AddBlobUrl(url, data) => {
+                self.blob_store.add(&url, data);
+              }
+              RevokeBlobUrl(url) => {
+                self.blob_store.revoke(&url);
+              }
+              RegisterLoadInterceptor(interceptor) => {
+                self.load_interceptors.register(interceptor);
+              }
+              GetNetworkOpts(sender) => {
+                sender.send(self.network_opts.clone());
+              }
               Exit => {
+                self.cookie_storage.send(cookie_storage::Exit);
+                self.http_cache.send(http_cache::Exit);
                 break
               }
             }
@@ -224,7 +320,30 @@ impl ResourceManager {
 
     fn load(&self, load_data: LoadData) {
         let mut load_data = load_data;
-        load_data.headers.user_agent = self.user_agent.clone();
+        load_data.headers.user_agent = self.network_opts.user_agent.clone();
+        if self.load_interceptors.is_empty() {
+            self.start_loader(load_data);
+            return
+        }
+
+        // Interceptors may block, for instance on an embedder that answers loads itself, so they
+        // run on a task of their own and hand the load back once they let it through.
+        let load_interceptors = self.load_interceptors.clone();
+        let start_chan = self.sniffer_task.clone();
+        let max_redirects = self.network_opts.max_redirects;
+        let intercepted_chan = self.intercepted_chan.clone();
+        spawn_named("load_interceptor", proc() {
+            match load_interceptors.run(load_data, start_chan, max_redirects) {
+                Some(load_data) => {
+                    let _ = intercepted_chan.send_opt(load_data);
+                }
+                None => (),
+            }
+        });
+    }
+
+    /// Hands a load that the interceptors let through to the loader of its scheme.
+    fn start_loader(&self, load_data: LoadData) {
         let senders = ResponseSenders {
             immediate_consumer: self.sniffer_task.clone(),
             eventual_consumer: load_data.consumer.clone(),
@@ -232,9 +351,22 @@ impl ResourceManager {
 
         let loader = match load_data.url.scheme.as_slice() {
             "file" => file_loader::factory,
-            "http" | "https" => http_loader::factory,
+            "http" | "https" => {
+                debug!("resource_task: loading url: {:s}", load_data.url.serialize());
+                http_loader::factory(load_data, self.sniffer_task.clone(), self.network_opts.clone(),
+                                     self.proxy_settings.clone(), self.cookie_storage.clone(), self.http_cache.clone(),
+                                     self.load_interceptors.clone());
+                return
+            }
             "data" => data_loader::factory,
-            "about" => about_loader::factory,
+            "about" => {
+                about_loader::factory(load_data, self.sniffer_task.clone(), &self.about_pages);
+                return
+            }
+            "blob" => {
+                blob_loader::factory(load_data, self.sniffer_task.clone(), &self.blob_store);
+                return
+            }
             _ => {
                 debug!("resource_task: no loader for scheme {:s}", load_data.url.scheme);
                 start_sending(senders, Metadata::default(load_data.url))
@@ -278,13 +410,24 @@ impl Iterator<Vec<u8>> for ProgressMsgPortIterator {
 
 #[test]
 fn test_exit() {
-    let resource_task = new_resource_task(None);
+    let resource_task = new_resource_task(opts::default_network_opts());
+    resource_task.send(Exit);
+}
+
+#[test]
+fn test_get_network_opts() {
+    let mut network_opts = opts::default_network_opts();
+    network_opts.max_redirects = 3;
+    let resource_task = new_resource_task(network_opts);
+    let (sender, receiver) = channel();
+    resource_task.send(GetNetworkOpts(sender));
+    assert_eq!(receiver.recv().max_redirects, 3);
     resource_task.send(Exit);
 }
 
 #[test]
 fn test_bad_scheme() {
-    let resource_task = new_resource_task(None);
+    let resource_task = new_resource_task(opts::default_network_opts());
     let (start_chan, start) = channel();
     let url = Url::parse("bogus://whatever").unwrap();
     resource_task.send(Load(LoadData::new(url, start_chan)));
@@ -3,9 +3,14 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 //! A task that sniffs data
+use std::ascii::AsciiExt;
 use std::comm::{channel, Receiver, Sender};
 use std::task::TaskBuilder;
-use resource_task::{TargetedLoadResponse};
+use http::headers::HeaderEnum;
+use mime_classifier::{SniffingContext, RESOURCE_HEADER_SIZE};
+use mime_classifier::{is_apache_bug_content_type, is_nosniff};
+use resource_task::{TargetedLoadResponse, LoadResponse, Metadata, ProgressMsg, Payload, Done};
+use servo_util::task::spawn_named;
 
 pub type SnifferTask = Sender<TargetedLoadResponse>;
 
@@ -35,10 +40,104 @@ impl SnifferManager {
         loop {
             match self.data_receiver.recv_opt() {
                 Ok(snif_data) => {
-                    let _ = snif_data.consumer.send_opt(snif_data.load_response);
+                    let context = sniffing_context(&snif_data.load_response.metadata);
+                    if !context.needs_resource_header() {
+                        let _ = snif_data.consumer.send_opt(snif_data.load_response);
+                        continue;
+                    }
+                    // Waiting for the resource header must not hold up other loads.
+                    spawn_named("MimeSniffer", proc() {
+                        sniff_and_forward(context, snif_data);
+                    });
                 }
                 Err(_) => break,
             }
         }
     }
 }
+
+fn sniffing_context(metadata: &Metadata) -> SniffingContext {
+    let mut no_sniff = false;
+    let mut check_for_apache_bug = false;
+    match metadata.headers {
+        Some(ref headers) => {
+            for header in headers.iter() {
+                let name = header.header_name();
+                if name.as_slice().eq_ignore_ascii_case("x-content-type-options") {
+                    no_sniff = is_nosniff(header.header_value().as_slice());
+                } else if name.as_slice().eq_ignore_ascii_case("content-type") {
+                    check_for_apache_bug = is_apache_bug_content_type(header.header_value().as_slice());
+                }
+            }
+        }
+        None => (),
+    }
+    SniffingContext {
+        supplied_type: metadata.content_type.clone(),
+        no_sniff: no_sniff,
+        check_for_apache_bug: check_for_apache_bug,
+    }
+}
+
+/// Read the resource header, correct the content type, and then relay the
+/// whole response to its consumer.
+fn sniff_and_forward(context: SniffingContext, snif_data: TargetedLoadResponse) {
+    let TargetedLoadResponse {
+        load_response: LoadResponse { mut metadata, progress_port },
+        consumer
+    } = snif_data;
+
+    let mut header = vec!();
+    let mut buffered: Vec<ProgressMsg> = vec!();
+    let mut finished = false;
+    while header.len() < RESOURCE_HEADER_SIZE {
+        match progress_port.recv_opt() {
+            Ok(Payload(data)) => {
+                header.push_all(data.as_slice());
+                buffered.push(Payload(data));
+            }
+            Ok(Done(result)) => {
+                buffered.push(Done(result));
+                finished = true;
+                break;
+            }
+            Err(_) => {
+                finished = true;
+                break;
+            }
+        }
+    }
+
+    metadata.content_type = Some(context.classify(header.as_slice()));
+
+    let (progress_chan, new_progress_port) = channel();
+    let load_response = LoadResponse {
+        metadata: metadata,
+        progress_port: new_progress_port,
+    };
+    if consumer.send_opt(load_response).is_err() {
+        return;
+    }
+    for msg in buffered.into_iter() {
+        if progress_chan.send_opt(msg).is_err() {
+            return;
+        }
+    }
+    if finished {
+        return;
+    }
+    loop {
+        match progress_port.recv_opt() {
+            Ok(msg) => {
+                let done = match msg {
+                    Done(_) => true,
+                    Payload(_) => false,
+                };
+                if progress_chan.send_opt(msg).is_err() || done {
+                    return;
+                }
+            }
+            Err(_) => return,
+        }
+    }
+}
@@ -4,36 +4,79 @@
 use std::comm::{channel, Receiver, Sender};
 use std::collections::HashMap;
 use std::collections::TreeMap;
+use std::hash::hash;
+use std::io;
+use std::io::File;
+use std::io::fs;
+use std::io::fs::PathExtensions;
+use serialize::json;
 use url::Url;
 
+use servo_msg::constellation_msg::{PipelineId, TopLevelBrowsingContextId};
+use servo_util::opts;
 use servo_util::str::DOMString;
 use servo_util::task::spawn_named;
 
+/// The maximum number of bytes, counting both keys and values, that a single origin may keep in
+/// each kind of storage.
+pub static QUOTA_SIZE_LIMIT: uint = 5 * 1024 * 1024;
+
+/// Which of an origin's storage areas a message refers to.
+#[deriving(Clone, PartialEq, Show)]
+pub enum StorageType {
+    /// `sessionStorage`, which is kept in memory, separately for each top-level browsing context.
+    SessionStorage(TopLevelBrowsingContextId),
+    /// `localStorage`, which is written to disk when a profile directory is given.
+    LocalStorage,
+}
+
 /// Request operations on the storage data associated with a particular url
 pub enum StorageTaskMsg {
     /// gets the number of key/value pairs present in the associated storage data
-    Length(Sender<u32>, Url),
+    Length(Sender<u32>, Url, StorageType),
 
     /// gets the name of the key at the specified index in the associated storage data
-    Key(Sender<Option<DOMString>>, Url, u32),
+    Key(Sender<Option<DOMString>>, Url, StorageType, u32),
 
     /// gets the value associated with the given key in the associated storage data
-    GetItem(Sender<Option<DOMString>>, Url, DOMString),
+    GetItem(Sender<Option<DOMString>>, Url, StorageType, DOMString),
 
-    /// sets the value of the given key in the associated storage data
-    /// TODO throw QuotaExceededError in case of error
-    SetItem(Sender<bool>, Url, DOMString, DOMString),
+    /// sets the value of the given key in the associated storage data; replies with whether the
+    /// data changed and the previous value, or an error if the origin's quota would be exceeded
+    SetItem(Sender<Result<(bool, Option<DOMString>), ()>>, Url, StorageType, DOMString, DOMString),
 
-    /// removes the key/value pair for the given key in the associated storage data
-    RemoveItem(Sender<bool>, Url, DOMString),
+    /// removes the key/value pair for the given key in the associated storage data; replies
+    /// with the removed value, if any
+    RemoveItem(Sender<Option<DOMString>>, Url, StorageType, DOMString),
 
     /// clears the associated storage data by removing all the key/value pairs
-    Clear(Sender<bool>, Url),
+    Clear(Sender<bool>, Url, StorageType),
+
+    /// registers a channel on which every subsequent storage notification is sent
+    AddListener(Sender<StorageNotification>),
+
+    /// sends a storage notification to every registered listener
+    Notify(StorageNotification),
+
+    /// frees the sessionStorage of a top-level browsing context whose pipelines have exited
+    ClearSessionStorage(TopLevelBrowsingContextId),
 
     /// shut down this task
     Exit
 }
 
+/// A change made to a storage area, to be announced to the other windows of its origin.
+#[deriving(Clone)]
+pub struct StorageNotification {
+    /// The pipeline whose window made the change, which does not receive the event.
+    pub source: PipelineId,
+    pub storage_type: StorageType,
+    pub url: Url,
+    pub key: Option<DOMString>,
+    pub old_value: Option<DOMString>,
+    pub new_value: Option<DOMString>,
+}
+
 /// Handle to a storage task
 pub type StorageTask = Sender<StorageTaskMsg>;
 
@@ -42,26 +85,58 @@ pub trait StorageTaskFactory {
 }
 
 impl StorageTaskFactory for StorageTask {
-    /// Create a StorageTask
+    /// Create a StorageTask, persisting localStorage under the profile directory if one was given
     fn new() -> StorageTask {
-        let (chan, port) = channel();
-        spawn_named("StorageManager", proc() {
-            StorageManager::new(port).start();
+        let local_data_dir = opts::get().profile_dir.as_ref().map(|dir| {
+            Path::new(dir.as_slice()).join("localstorage")
         });
-        chan
+        new_storage_task(local_data_dir)
     }
 }
 
+/// Create a StorageTask that keeps localStorage in `local_data_dir`, or only in memory if `None`.
+pub fn new_storage_task(local_data_dir: Option<Path>) -> StorageTask {
+    let (chan, port) = channel();
+    spawn_named("StorageManager", proc() {
+        StorageManager::new(port, local_data_dir).start();
+    });
+    chan
+}
+
+/// The on-disk form of an origin's localStorage data.
+#[deriving(Encodable, Decodable)]
+struct StoredOrigin {
+    origin: String,
+    items: TreeMap<DOMString, DOMString>,
+}
+
 struct StorageManager {
     port: Receiver<StorageTaskMsg>,
-    data: HashMap<String, TreeMap<DOMString, DOMString>>,
+    session_data: HashMap<(TopLevelBrowsingContextId, String), TreeMap<DOMString, DOMString>>,
+    local_data: HashMap<String, TreeMap<DOMString, DOMString>>,
+    /// Directory holding one file per origin, loaded the first time that origin is used.
+    local_data_dir: Option<Path>,
+    /// Channels of the script tasks that fire storage events.
+    listeners: Vec<Sender<StorageNotification>>,
 }
 
 impl StorageManager {
-    fn new(port: Receiver<StorageTaskMsg>) -> StorageManager {
+    fn new(port: Receiver<StorageTaskMsg>, local_data_dir: Option<Path>) -> StorageManager {
+        match local_data_dir {
+            Some(ref path) if !path.exists() => {
+                match fs::mkdir_recursive(path, io::USER_RWX) {
+                    Ok(()) => (),
+                    Err(e) => error!("unable to create localStorage directory: {}", e),
+                }
+            }
+            _ => (),
+        }
         StorageManager {
             port: port,
-            data: HashMap::new(),
+            session_data: HashMap::new(),
+            local_data: HashMap::new(),
+            local_data_dir: local_data_dir,
+            listeners: vec!(),
         }
     }
 }
@@ -70,23 +145,32 @@ impl StorageManager {
     fn start(&mut self) {
         loop {
             match self.port.recv() {
-                Length(sender, url) => {
-                    self.length(sender, url)
+                Length(sender, url, storage_type) => {
+                    self.length(sender, url, storage_type)
+                }
+                Key(sender, url, storage_type, index) => {
+                    self.key(sender, url, storage_type, index)
                 }
-                Key(sender, url, index) => {
-                    self.key(sender, url, index)
+                SetItem(sender, url, storage_type, name, value) => {
+                    self.set_item(sender, url, storage_type, name, value)
                 }
-                SetItem(sender, url, name, value) => {
-                    self.set_item(sender, url, name, value)
+                GetItem(sender, url, storage_type, name) => {
+                    self.get_item(sender, url, storage_type, name)
                 }
-                GetItem(sender, url, name) => {
-                    self.get_item(sender, url, name)
+                RemoveItem(sender, url, storage_type, name) => {
+                    self.remove_item(sender, url, storage_type, name)
                 }
-                RemoveItem(sender, url, name) => {
-                    self.remove_item(sender, url, name)
+                Clear(sender, url, storage_type) => {
+                    self.clear(sender, url, storage_type)
                 }
-                Clear(sender, url) => {
-                    self.clear(sender, url)
+                AddListener(listener) => {
+                    self.listeners.push(listener)
+                }
+                Notify(notification) => {
+                    self.notify(notification)
+                }
+                ClearSessionStorage(browsing_context) => {
+                    self.clear_session_storage(browsing_context)
                 }
                 Exit => {
                     break
@@ -95,71 +179,309 @@ impl StorageManager {
         }
     }
 
-    fn length(&self, sender: Sender<u32>, url: Url) {
+    fn length(&mut self, sender: Sender<u32>, url: Url, storage_type: StorageType) {
         let origin = self.get_origin_as_string(url);
-        sender.send(self.data.get(&origin).map_or(0u, |entry| entry.len()) as u32);
+        sender.send(self.data(storage_type, &origin).len() as u32);
     }
 
-    fn key(&self, sender: Sender<Option<DOMString>>, url: Url, index: u32) {
+    fn key(&mut self, sender: Sender<Option<DOMString>>, url: Url, storage_type: StorageType, index: u32) {
         let origin = self.get_origin_as_string(url);
-        sender.send(self.data.get(&origin)
-                    .and_then(|entry| entry.keys().nth(index as uint))
+        sender.send(self.data(storage_type, &origin)
+                    .keys().nth(index as uint)
                     .map(|key| key.clone()));
     }
 
-    fn set_item(&mut self, sender: Sender<bool>, url: Url, name: DOMString, value: DOMString) {
+    fn set_item(&mut self, sender: Sender<Result<(bool, Option<DOMString>), ()>>, url: Url,
+                storage_type: StorageType, name: DOMString, value: DOMString) {
         let origin = self.get_origin_as_string(url);
-        if !self.data.contains_key(&origin) {
-            self.data.insert(origin.clone(), TreeMap::new());
-        }
-
-        let updated = self.data.get_mut(&origin).map(|entry| {
-            if entry.get(&origin).map_or(true, |item| item.as_slice() != value.as_slice()) {
-                entry.insert(name.clone(), value.clone());
-                true
+        let result = {
+            let data = self.data(storage_type, &origin);
+            let old_value = data.get(&name).map(|old| old.clone());
+            let unchanged = old_value.as_ref().map_or(false, |old| old.as_slice() == value.as_slice());
+            if unchanged {
+                Ok((false, old_value))
             } else {
-                false
+                let old_size = old_value.as_ref().map_or(0, |old| name.len() + old.len());
+                if storage_size(data) - old_size + name.len() + value.len() > QUOTA_SIZE_LIMIT {
+                    Err(())
+                } else {
+                    data.insert(name, value);
+                    Ok((true, old_value))
+                }
             }
-        }).unwrap();
+        };
 
-        sender.send(updated);
+        match result {
+            Ok((true, _)) => self.save(storage_type, &origin),
+            _ => (),
+        }
+        sender.send(result);
     }
 
-    fn get_item(&self, sender: Sender<Option<DOMString>>, url: Url, name: DOMString) {
+    fn get_item(&mut self, sender: Sender<Option<DOMString>>, url: Url, storage_type: StorageType,
+                name: DOMString) {
         let origin = self.get_origin_as_string(url);
-        sender.send(self.data.get(&origin)
-                    .and_then(|entry| entry.get(&name))
+        sender.send(self.data(storage_type, &origin)
+                    .get(&name)
                     .map(|value| value.to_string()));
     }
 
-    fn remove_item(&mut self, sender: Sender<bool>, url: Url, name: DOMString) {
+    fn remove_item(&mut self, sender: Sender<Option<DOMString>>, url: Url, storage_type: StorageType,
+                   name: DOMString) {
         let origin = self.get_origin_as_string(url);
-        sender.send(self.data.get_mut(&origin)
-                    .map_or(false, |entry| entry.remove(&name).is_some()));
+        let old_value = self.data(storage_type, &origin).remove(&name);
+        if old_value.is_some() {
+            self.save(storage_type, &origin);
+        }
+        sender.send(old_value);
     }
 
-    fn clear(&mut self, sender: Sender<bool>, url: Url) {
+    fn clear(&mut self, sender: Sender<bool>, url: Url, storage_type: StorageType) {
         let origin = self.get_origin_as_string(url);
-        sender.send(self.data.get_mut(&origin)
-                    .map_or(false, |entry| {
-                        if !entry.is_empty() {
-                            entry.clear();
-                            true
-                        } else {
-                            false
-                        }}));
+        let cleared = {
+            let data = self.data(storage_type, &origin);
+            if !data.is_empty() {
+                data.clear();
+                true
+            } else {
+                false
+            }
+        };
+        if cleared {
+            self.save(storage_type, &origin);
+        }
+        sender.send(cleared);
+    }
+
+    /// Send a notification to every listener, forgetting those whose script task has gone away.
+    fn notify(&mut self, notification: StorageNotification) {
+        self.listeners.retain(|listener| listener.send_opt(notification.clone()).is_ok());
+    }
+
+    /// Drop every origin's sessionStorage for a browsing context that has gone away.
+    fn clear_session_storage(&mut self, browsing_context: TopLevelBrowsingContextId) {
+        let keys: Vec<(TopLevelBrowsingContextId, String)> = self.session_data.keys()
+            .filter(|&&(ref context, _)| *context == browsing_context)
+            .map(|key| key.clone())
+            .collect();
+        for key in keys.iter() {
+            self.session_data.remove(key);
+        }
+    }
+
+    /// The storage area of the given type for `origin`, read from disk if it is not yet loaded.
+    fn data<'a>(&'a mut self, storage_type: StorageType, origin: &String)
+                -> &'a mut TreeMap<DOMString, DOMString> {
+        match storage_type {
+            SessionStorage(browsing_context) => {
+                let key = (browsing_context, origin.clone());
+                if !self.session_data.contains_key(&key) {
+                    self.session_data.insert(key.clone(), TreeMap::new());
+                }
+                self.session_data.get_mut(&key).unwrap()
+            }
+            LocalStorage => {
+                if !self.local_data.contains_key(origin) {
+                    let items = self.load(origin);
+                    self.local_data.insert(origin.clone(), items);
+                }
+                self.local_data.get_mut(origin).unwrap()
+            }
+        }
+    }
+
+    fn path_for(&self, origin: &String) -> Option<Path> {
+        self.local_data_dir.as_ref().map(|dir| dir.join(format!("{:016x}", hash(origin))))
     }
 
+    fn load(&self, origin: &String) -> TreeMap<DOMString, DOMString> {
+        let path = match self.path_for(origin) {
+            Some(ref path) if path.exists() => path.clone(),
+            _ => return TreeMap::new(),
+        };
+        let contents = match File::open(&path).read_to_string() {
+            Ok(contents) => contents,
+            Err(e) => {
+                error!("unable to read localStorage for {}: {}", origin, e);
+                return TreeMap::new();
+            }
+        };
+        match json::decode::<StoredOrigin>(contents.as_slice()) {
+            // A different origin whose name hashes to the same file is treated as empty.
+            Ok(stored) => if stored.origin == *origin { stored.items } else { TreeMap::new() },
+            Err(e) => {
+                error!("unable to parse localStorage for {}: {}", origin, e);
+                TreeMap::new()
+            }
+        }
+    }
+
+    /// Write an origin's localStorage through to disk, removing the file once it is empty.
+    fn save(&self, storage_type: StorageType, origin: &String) {
+        if storage_type != LocalStorage {
+            return;
+        }
+        let path = match self.path_for(origin) {
+            Some(path) => path,
+            None => return,
+        };
+        let items = match self.local_data.get(origin) {
+            Some(items) => items,
+            None => return,
+        };
+        if items.is_empty() {
+            if path.exists() {
+                match fs::unlink(&path) {
+                    Ok(()) => (),
+                    Err(e) => error!("unable to remove localStorage for {}: {}", origin, e),
+                }
+            }
+            return;
+        }
+        let stored = StoredOrigin {
+            origin: origin.clone(),
+            items: items.clone(),
+        };
+        match File::create(&path).and_then(|mut file| file.write_str(json::encode(&stored).as_slice())) {
+            Ok(()) => (),
+            Err(e) => error!("unable to write localStorage for {}: {}", origin, e),
+        }
+    }
+
+    /// [ASCII serialization of an origin](https://tools.ietf.org/html/rfc6454#section-6.2)
     fn get_origin_as_string(&self, url: Url) -> String {
         let mut origin = "".to_string();
         origin.push_str(url.scheme.as_slice());
         origin.push_str("://");
-        url.domain().map(|domain| origin.push_str(domain.as_slice()));
+        url.serialize_host().map(|host| origin.push_str(host.as_slice()));
         url.port().map(|port| {
             origin.push_str(":");
             origin.push_str(port.to_string().as_slice());
         });
-        origin.push_str("/");
         origin
     }
 }
+
+/// The number of bytes an origin's storage area counts against its quota.
+fn storage_size(data: &TreeMap<DOMString, DOMString>) -> uint {
+    data.iter().fold(0, |size, (key, value)| size + key.len() + value.len())
+}
+
+#[cfg(test)]
+fn set(task: &StorageTask, url: &Url, storage_type: StorageType, name: &str, value: &str)
+       -> Result<(bool, Option<DOMString>), ()> {
+    let (sender, receiver) = channel();
+    task.send(SetItem(sender, url.clone(), storage_type, name.to_string(), value.to_string()));
+    receiver.recv()
+}
+
+#[cfg(test)]
+fn get(task: &StorageTask, url: &Url, storage_type: StorageType, name: &str) -> Option<DOMString> {
+    let (sender, receiver) = channel();
+    task.send(GetItem(sender, url.clone(), storage_type, name.to_string()));
+    receiver.recv()
+}
+
+#[test]
+fn test_origin_keyed_storage() {
+    let task = new_storage_task(None);
+    let page = Url::parse("http://example.com/a/page.html").unwrap();
+    let other_page = Url::parse("http://example.com/b/other.html").unwrap();
+    let other_origin = Url::parse("http://example.com:8000/a/page.html").unwrap();
+
+    assert_eq!(set(&task, &page, LocalStorage, "key", "value"), Ok((true, None)));
+    assert_eq!(set(&task, &page, LocalStorage, "key", "value"), Ok((false, Some("value".to_string()))));
+    assert_eq!(get(&task, &other_page, LocalStorage, "key"), Some("value".to_string()));
+    assert_eq!(get(&task, &other_origin, LocalStorage, "key"), None);
+    assert_eq!(get(&task, &page, SessionStorage(TopLevelBrowsingContextId(0)), "key"), None);
+
+    let (sender, receiver) = channel();
+    task.send(RemoveItem(sender, other_page.clone(), LocalStorage, "key".to_string()));
+    assert_eq!(receiver.recv(), Some("value".to_string()));
+    task.send(Exit);
+}
+
+#[test]
+fn test_quota() {
+    let task = new_storage_task(None);
+    let url = Url::parse("http://example.com/").unwrap();
+    let half = String::from_char(QUOTA_SIZE_LIMIT / 2, 'a');
+
+    assert!(set(&task, &url, LocalStorage, "a", half.as_slice()).is_ok());
+    assert!(set(&task, &url, LocalStorage, "b", half.as_slice()).is_err());
+    // Replacing a value only counts the difference in size.
+    assert!(set(&task, &url, LocalStorage, "a", half.as_slice().slice_from(1)).is_ok());
+    // Each storage area has its own quota.
+    let session = SessionStorage(TopLevelBrowsingContextId(0));
+    assert!(set(&task, &url, session, "b", half.as_slice()).is_ok());
+    task.send(Exit);
+}
+
+#[test]
+fn test_local_storage_persists() {
+    let dir = io::TempDir::new("servo-localstorage").unwrap();
+    let url = Url::parse("http://example.com/").unwrap();
+    let session = SessionStorage(TopLevelBrowsingContextId(0));
+
+    let task = new_storage_task(Some(dir.path().clone()));
+    assert!(set(&task, &url, LocalStorage, "local", "1").is_ok());
+    assert!(set(&task, &url, session, "session", "1").is_ok());
+    task.send(Exit);
+
+    let task = new_storage_task(Some(dir.path().clone()));
+    assert_eq!(get(&task, &url, LocalStorage, "local"), Some("1".to_string()));
+    assert_eq!(get(&task, &url, session, "session"), None);
+    let (sender, receiver) = channel();
+    task.send(Clear(sender, url.clone(), LocalStorage));
+    assert!(receiver.recv());
+    task.send(Exit);
+
+    let task = new_storage_task(Some(dir.path().clone()));
+    assert_eq!(get(&task, &url, LocalStorage, "local"), None);
+    task.send(Exit);
+}
+
+#[test]
+fn test_session_storage_per_browsing_context() {
+    let task = new_storage_task(None);
+    let url = Url::parse("http://example.com/").unwrap();
+    let first = SessionStorage(TopLevelBrowsingContextId(0));
+    let second = SessionStorage(TopLevelBrowsingContextId(1));
+
+    assert!(set(&task, &url, first, "key", "first").is_ok());
+    assert_eq!(get(&task, &url, first, "key"), Some("first".to_string()));
+    assert_eq!(get(&task, &url, second, "key"), None);
+
+    assert!(set(&task, &url, second, "key", "second").is_ok());
+    task.send(ClearSessionStorage(TopLevelBrowsingContextId(0)));
+    assert_eq!(get(&task, &url, first, "key"), None);
+    assert_eq!(get(&task, &url, second, "key"), Some("second".to_string()));
+    task.send(Exit);
+}
+
+#[test]
+fn test_notify_listeners() {
+    let task = new_storage_task(None);
+    let (first, first_port) = channel();
+    let (second, second_port) = channel();
+    task.send(AddListener(first));
+    task.send(AddListener(second));
+    drop(second_port);
+
+    let notification = StorageNotification {
+        source: PipelineId(0),
+        storage_type: LocalStorage,
+        url: Url::parse("http://example.com/").unwrap(),
+        key: Some("key".to_string()),
+        old_value: None,
+        new_value: Some("value".to_string()),
+    };
+    task.send(Notify(notification.clone()));
+    task.send(Notify(notification));
+    for _ in range(0u, 2) {
+        let received = first_port.recv();
+        assert_eq!(received.source, PipelineId(0));
+        assert_eq!(received.new_value, Some("value".to_string()));
+    }
+    task.send(Exit);
+}
@@ -0,0 +1,680 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A client for the [WebSocket protocol](http://tools.ietf.org/html/rfc6455).
+//!
+//! `connect` spawns a task that performs the opening handshake and then reads
+//! frames from the server, reporting what happens as `WebSocketEvent`s. Frames
+//! for the server are written by a second task, fed through the returned
+//! `WebSocketConnection`.
+
+use cookie::HTTP;
+use resource_task::{ResourceTask, GetCookiesForUrl, SetCookiesForUrl};
+use servo_util::task::spawn_named;
+
+use serialize::base64::{ToBase64, STANDARD};
+use std::ascii::AsciiExt;
+use std::io::{BufferedReader, IoError, IoResult};
+use std::io::net::tcp::TcpStream;
+use std::num::Int;
+use std::rand::{Rng, task_rng};
+use url::Url;
+
+/// The GUID servers append to the handshake key (section 1.3).
+static WEBSOCKET_GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Messages larger than this fail the connection.
+static MAX_MESSAGE_SIZE: u64 = 1 << 26;
+
+/// Close codes, from section 7.4.1.
+pub static CLOSE_NORMAL: u16 = 1000;
+pub static CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub static CLOSE_NO_STATUS: u16 = 1005;
+pub static CLOSE_ABNORMAL: u16 = 1006;
+pub static CLOSE_INVALID_DATA: u16 = 1007;
+pub static CLOSE_TOO_BIG: u16 = 1009;
+
+/// What the connection reports to its client, in the order it happens.
+pub enum WebSocketEvent {
+    /// The opening handshake succeeded; carries the subprotocol the server
+    /// selected, or the empty string.
+    Opened(String),
+    TextMessage(String),
+    BinaryMessage(Vec<u8>),
+    /// The connection had to be failed. `Closed` always follows.
+    Failed,
+    /// The connection is gone. Carries whether the closing handshake completed,
+    /// and the code and reason the server closed it with.
+    Closed(bool, u16, String),
+}
+
+enum WriterMsg {
+    SendText(String),
+    SendBinary(Vec<u8>),
+    SendClose(Option<u16>, String),
+    SendPong(Vec<u8>),
+    Shutdown,
+}
+
+/// The client's handle on a connection, used to send it messages.
+#[deriving(Clone)]
+pub struct WebSocketConnection {
+    chan: Sender<WriterMsg>,
+}
+
+impl WebSocketConnection {
+    pub fn send_text(&self, data: String) {
+        let _ = self.chan.send_opt(SendText(data));
+    }
+
+    pub fn send_binary(&self, data: Vec<u8>) {
+        let _ = self.chan.send_opt(SendBinary(data));
+    }
+
+    /// Start the closing handshake. Nothing is sent after this.
+    pub fn close(&self, code: Option<u16>, reason: String) {
+        let _ = self.chan.send_opt(SendClose(code, reason));
+    }
+}
+
+/// Open a connection to `url`, a `ws:` URL, on behalf of a document whose
+/// origin serializes to `origin`.
+pub fn connect(url: Url, origin: String, protocols: Vec<String>, resource_task: ResourceTask,
+               events: Sender<WebSocketEvent>) -> WebSocketConnection {
+    let (chan, port) = channel();
+    let connection = WebSocketConnection { chan: chan.clone() };
+    spawn_named("WebSocket", proc() {
+        let (mut reader, stream, protocol) =
+            match open(&url, origin.as_slice(), protocols.as_slice(), &resource_task) {
+                Ok(opened) => opened,
+                Err(message) => {
+                    debug!("WebSocket handshake with {:s} failed: {:s}", url.serialize(), message);
+                    events.send(Failed);
+                    events.send(Closed(false, CLOSE_ABNORMAL, String::new()));
+                    return;
+                }
+            };
+
+        spawn_named("WebSocketWriter", proc() write_messages(stream, port));
+
+        events.send(Opened(protocol));
+        let (clean, code, reason) = read_messages(&mut reader, &chan, &events);
+        let _ = chan.send_opt(Shutdown);
+        events.send(Closed(clean, code, reason));
+    });
+    connection
+}
+
+/// The opening handshake (section 4.1). Returns the stream to read frames
+/// from, the stream to write them to, and the selected subprotocol.
+fn open(url: &Url, origin: &str, protocols: &[String], resource_task: &ResourceTask)
+        -> Result<(BufferedReader<TcpStream>, TcpStream, String), String> {
+    if url.scheme.as_slice() != "ws" {
+        // FIXME: wss: needs a TLS stream that can be read and written from
+        // separate tasks. The WebSocket constructor refuses such URLs.
+        return Err(format!("unsupported scheme {:s}", url.scheme));
+    }
+    let host = match url.serialize_host() {
+        Some(host) => host,
+        None => return Err("no host".to_string())
+    };
+    let port = url.port().unwrap_or(80);
+    let mut stream = match TcpStream::connect(format!("{:s}:{:u}", host, port).as_slice()) {
+        Ok(stream) => stream,
+        Err(e) => return Err(e.desc.to_string())
+    };
+
+    let mut nonce = [0u8, ..16];
+    task_rng().fill_bytes(nonce.as_mut_slice());
+    let key = nonce.as_slice().to_base64(STANDARD);
+
+    let mut path = url.serialize_path().unwrap_or("/".to_string());
+    match url.query {
+        Some(ref query) => {
+            path.push('?');
+            path.push_str(query.as_slice());
+        }
+        None => ()
+    }
+    let mut request = format!("GET {:s} HTTP/1.1\r\n", path);
+    match url.port() {
+        Some(port) => request.push_str(format!("Host: {:s}:{:u}\r\n", host, port).as_slice()),
+        None => request.push_str(format!("Host: {:s}\r\n", host).as_slice()),
+    }
+    request.push_str("Upgrade: websocket\r\n");
+    request.push_str("Connection: Upgrade\r\n");
+    request.push_str(format!("Sec-WebSocket-Key: {:s}\r\n", key).as_slice());
+    request.push_str("Sec-WebSocket-Version: 13\r\n");
+    request.push_str(format!("Origin: {:s}\r\n", origin).as_slice());
+    if !protocols.is_empty() {
+        request.push_str(format!("Sec-WebSocket-Protocol: {:s}\r\n",
+                                 protocols.connect(", ")).as_slice());
+    }
+    let (cookies_chan, cookies_port) = channel();
+    resource_task.send(GetCookiesForUrl(url.clone(), cookies_chan, HTTP));
+    match cookies_port.recv() {
+        Some(cookies) => request.push_str(format!("Cookie: {:s}\r\n", cookies).as_slice()),
+        None => ()
+    }
+    request.push_str("\r\n");
+    match stream.write(request.as_bytes()).and_then(|_| stream.flush()) {
+        Ok(()) => (),
+        Err(e) => return Err(e.desc.to_string())
+    }
+
+    let mut reader = BufferedReader::new(stream.clone());
+    let status_line = match reader.read_line() {
+        Ok(line) => line,
+        Err(e) => return Err(e.desc.to_string())
+    };
+    match status_line.as_slice().split(' ').nth(1) {
+        Some("101") => (),
+        _ => return Err(format!("unexpected status line {:s}", status_line.as_slice().trim_right()))
+    }
+
+    let mut headers = vec!();
+    loop {
+        let line = match reader.read_line() {
+            Ok(line) => line,
+            Err(e) => return Err(e.desc.to_string())
+        };
+        let line = line.as_slice().trim_right_chars(['\r', '\n'].as_slice());
+        if line.is_empty() {
+            break;
+        }
+        match line.find(':') {
+            Some(index) => headers.push((line.slice_to(index).trim().to_ascii_lower(),
+                                         line.slice_from(index + 1).trim().to_string())),
+            None => return Err(format!("malformed header {:s}", line))
+        }
+    }
+    match find_header(headers.as_slice(), "upgrade") {
+        Some(ref value) if value.as_slice().eq_ignore_ascii_case("websocket") => (),
+        _ => return Err("missing Upgrade header".to_string())
+    }
+    match find_header(headers.as_slice(), "connection") {
+        Some(ref value) if value.as_slice().split(',').any(|token| {
+            token.trim().eq_ignore_ascii_case("upgrade")
+        }) => (),
+        _ => return Err("missing Connection header".to_string())
+    }
+    match find_header(headers.as_slice(), "sec-websocket-accept") {
+        Some(ref value) if *value == accept_key(key.as_slice()) => (),
+        _ => return Err("bad Sec-WebSocket-Accept header".to_string())
+    }
+    // No extensions are ever requested, so the server may not use any.
+    if find_header(headers.as_slice(), "sec-websocket-extensions").is_some() {
+        return Err("unrequested extension".to_string());
+    }
+    let protocol = match find_header(headers.as_slice(), "sec-websocket-protocol") {
+        Some(protocol) => {
+            if !protocols.iter().any(|p| *p == protocol) {
+                return Err(format!("unrequested subprotocol {:s}", protocol));
+            }
+            protocol
+        }
+        None => String::new()
+    };
+
+    for &(ref name, ref value) in headers.iter() {
+        if name.as_slice() == "set-cookie" {
+            resource_task.send(SetCookiesForUrl(url.clone(), value.clone(), HTTP));
+        }
+    }
+
+    Ok((reader, stream, protocol))
+}
+
+fn find_header(headers: &[(String, String)], name: &str) -> Option<String> {
+    headers.iter().find(|&&(ref n, _)| n.as_slice() == name).map(|&(_, ref v)| v.clone())
+}
+
+/// The value a server must send back in `Sec-WebSocket-Accept` for `key`.
+fn accept_key(key: &str) -> String {
+    let mut input = key.to_string();
+    input.push_str(WEBSOCKET_GUID);
+    sha1(input.as_bytes()).as_slice().to_base64(STANDARD)
+}
+
+/// Read frames until the connection closes. Returns whether it closed
+/// cleanly, with the code and reason the server gave.
+fn read_messages<R: Reader>(reader: &mut R, writer: &Sender<WriterMsg>,
+                            events: &Sender<WebSocketEvent>) -> (bool, u16, String) {
+    // The opcode and data of a fragmented message being received.
+    let mut message: Option<(Opcode, Vec<u8>)> = None;
+    loop {
+        let frame = match read_frame(reader, false) {
+            Ok(frame) => frame,
+            Err(FrameError(code)) if code == CLOSE_ABNORMAL => return (false, code, String::new()),
+            Err(FrameError(code)) => return fail(code, writer, events),
+        };
+
+        let (opcode, payload) = match frame.opcode {
+            PingOp => {
+                let _ = writer.send_opt(SendPong(frame.payload));
+                continue;
+            }
+            PongOp => continue,
+            CloseOp => {
+                if frame.payload.len() == 1 {
+                    return fail(CLOSE_PROTOCOL_ERROR, writer, events);
+                }
+                if frame.payload.is_empty() {
+                    let _ = writer.send_opt(SendClose(None, String::new()));
+                    return (true, CLOSE_NO_STATUS, String::new());
+                }
+                let code = (frame.payload[0] as u16 << 8) | frame.payload[1] as u16;
+                let reason = match String::from_utf8(frame.payload.slice_from(2).to_vec()) {
+                    Ok(reason) => reason,
+                    Err(_) => return fail(CLOSE_INVALID_DATA, writer, events)
+                };
+                let _ = writer.send_opt(SendClose(Some(code), String::new()));
+                return (true, code, reason);
+            }
+            ContinuationOp => match message.take() {
+                Some((opcode, mut data)) => {
+                    if (data.len() + frame.payload.len()) as u64 > MAX_MESSAGE_SIZE {
+                        return fail(CLOSE_TOO_BIG, writer, events);
+                    }
+                    data.push_all(frame.payload.as_slice());
+                    (opcode, data)
+                }
+                None => return fail(CLOSE_PROTOCOL_ERROR, writer, events)
+            },
+            opcode => {
+                if message.is_some() {
+                    return fail(CLOSE_PROTOCOL_ERROR, writer, events);
+                }
+                (opcode, frame.payload)
+            }
+        };
+
+        if !frame.fin {
+            message = Some((opcode, payload));
+            continue;
+        }
+        match opcode {
+            TextOp => match String::from_utf8(payload) {
+                Ok(text) => events.send(TextMessage(text)),
+                Err(_) => return fail(CLOSE_INVALID_DATA, writer, events)
+            },
+            _ => events.send(BinaryMessage(payload)),
+        }
+    }
+}
+
+/// Fail the connection (section 7.1.7) because of a protocol error.
+fn fail(code: u16, writer: &Sender<WriterMsg>, events: &Sender<WebSocketEvent>) -> (bool, u16, String) {
+    let _ = writer.send_opt(SendClose(Some(code), String::new()));
+    events.send(Failed);
+    (false, CLOSE_ABNORMAL, String::new())
+}
+
+/// Write the messages received on `port` to the server, until the reader
+/// shuts the connection down.
+fn write_messages(mut stream: TcpStream, port: Receiver<WriterMsg>) {
+    let mut close_sent = false;
+    loop {
+        let msg = match port.recv_opt() {
+            Ok(msg) => msg,
+            Err(()) => break
+        };
+        let result = match msg {
+            Shutdown => break,
+            _ if close_sent => continue,
+            SendText(text) => write_frame(&mut stream, TextOp, text.as_bytes(), Some(mask_key())),
+            SendBinary(data) => write_frame(&mut stream, BinaryOp, data.as_slice(), Some(mask_key())),
+            SendPong(data) => write_frame(&mut stream, PongOp, data.as_slice(), Some(mask_key())),
+            SendClose(code, reason) => {
+                close_sent = true;
+                let mut payload = vec!();
+                match code {
+                    Some(code) => {
+                        payload.push((code >> 8) as u8);
+                        payload.push(code as u8);
+                        payload.push_all(reason.as_bytes());
+                    }
+                    None => ()
+                }
+                write_frame(&mut stream, CloseOp, payload.as_slice(), Some(mask_key()))
+            }
+        };
+        match result {
+            Ok(()) => (),
+            // The reader will notice the connection is gone.
+            Err(e) => debug!("WebSocket write failed: {:s}", e.desc),
+        }
+    }
+    let _ = stream.close_write();
+}
+
+fn mask_key() -> [u8, ..4] {
+    let mut key = [0u8, ..4];
+    task_rng().fill_bytes(key.as_mut_slice());
+    key
+}
+
+#[deriving(PartialEq, Show)]
+enum Opcode {
+    ContinuationOp,
+    TextOp,
+    BinaryOp,
+    CloseOp,
+    PingOp,
+    PongOp,
+}
+
+impl Opcode {
+    fn from_u8(value: u8) -> Option<Opcode> {
+        match value {
+            0x0 => Some(ContinuationOp),
+            0x1 => Some(TextOp),
+            0x2 => Some(BinaryOp),
+            0x8 => Some(CloseOp),
+            0x9 => Some(PingOp),
+            0xA => Some(PongOp),
+            _ => None
+        }
+    }
+
+    fn to_u8(&self) -> u8 {
+        match *self {
+            ContinuationOp => 0x0,
+            TextOp => 0x1,
+            BinaryOp => 0x2,
+            CloseOp => 0x8,
+            PingOp => 0x9,
+            PongOp => 0xA,
+        }
+    }
+
+    fn is_control(&self) -> bool {
+        self.to_u8() & 0x8 != 0
+    }
+}
+
+/// A frame (section 5.2), with its payload already unmasked.
+struct Frame {
+    fin: bool,
+    opcode: Opcode,
+    payload: Vec<u8>,
+}
+
+/// A frame could not be read; carries the close code to fail the
+/// connection with, or `CLOSE_ABNORMAL` if the stream itself broke.
+#[deriving(PartialEq, Show)]
+struct FrameError(u16);
+
+fn io_error(_: IoError) -> FrameError {
+    FrameError(CLOSE_ABNORMAL)
+}
+
+/// Read one frame. `masked` says whether the peer must mask its frames: a
+/// client's frames are masked, and a server's must not be (section 5.1).
+fn read_frame<R: Reader>(reader: &mut R, masked: bool) -> Result<Frame, FrameError> {
+    let first = try!(reader.read_byte().map_err(io_error));
+    let second = try!(reader.read_byte().map_err(io_error));
+    let fin = first & 0x80 != 0;
+    // No extensions are negotiated, so the reserved bits must be clear.
+    if first & 0x70 != 0 {
+        return Err(FrameError(CLOSE_PROTOCOL_ERROR));
+    }
+    let opcode = match Opcode::from_u8(first & 0x0F) {
+        Some(opcode) => opcode,
+        None => return Err(FrameError(CLOSE_PROTOCOL_ERROR))
+    };
+    let length = match second & 0x7F {
+        126 => try!(reader.read_be_u16().map_err(io_error)) as u64,
+        127 => try!(reader.read_be_u64().map_err(io_error)),
+        length => length as u64
+    };
+    if opcode.is_control() && (length > 125 || !fin) {
+        return Err(FrameError(CLOSE_PROTOCOL_ERROR));
+    }
+    if length > MAX_MESSAGE_SIZE {
+        return Err(FrameError(CLOSE_TOO_BIG));
+    }
+    if (second & 0x80 != 0) != masked {
+        return Err(FrameError(CLOSE_PROTOCOL_ERROR));
+    }
+    let mask = if masked {
+        Some(try!(reader.read_exact(4).map_err(io_error)))
+    } else {
+        None
+    };
+    let mut payload = try!(reader.read_exact(length as uint).map_err(io_error));
+    match mask {
+        Some(key) => apply_mask(payload.as_mut_slice(), key.as_slice()),
+        None => ()
+    }
+    Ok(Frame {
+        fin: fin,
+        opcode: opcode,
+        payload: payload,
+    })
+}
+
+/// Write one final frame. Frames sent by a client must be masked.
+fn write_frame<W: Writer>(writer: &mut W, opcode: Opcode, payload: &[u8],
+                          mask: Option<[u8, ..4]>) -> IoResult<()> {
+    let mut header = vec!(0x80 | opcode.to_u8());
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    let length = payload.len();
+    if length < 126 {
+        header.push(mask_bit | length as u8);
+    } else if length <= 0xFFFF {
+        header.push(mask_bit | 126);
+        header.push((length >> 8) as u8);
+        header.push(length as u8);
+    } else {
+        header.push(mask_bit | 127);
+        for i in range(0u, 8).rev() {
+            header.push((length as u64 >> (i * 8)) as u8);
+        }
+    }
+    let mut body = payload.to_vec();
+    match mask {
+        Some(key) => {
+            header.push_all(key.as_slice());
+            apply_mask(body.as_mut_slice(), key.as_slice());
+        }
+        None => ()
+    }
+    try!(writer.write(header.as_slice()));
+    try!(writer.write(body.as_slice()));
+    writer.flush()
+}
+
+fn apply_mask(data: &mut [u8], key: &[u8]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[i % 4];
+    }
+}
+
+/// SHA-1 (RFC 3174), which the handshake needs for `Sec-WebSocket-Accept`.
+fn sha1(data: &[u8]) -> [u8, ..20] {
+    let mut h: [u32, ..5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    let bit_length = data.len() as u64 * 8;
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    for i in range(0u, 8).rev() {
+        message.push((bit_length >> (i * 8)) as u8);
+    }
+
+    for block in message.as_slice().chunks(64) {
+        let mut w = [0u32, ..80];
+        for i in range(0u, 16) {
+            w[i] = (block[4 * i] as u32 << 24) | (block[4 * i + 1] as u32 << 16) |
+                   (block[4 * i + 2] as u32 << 8) | block[4 * i + 3] as u32;
+        }
+        for i in range(16u, 80) {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for i in range(0u, 80) {
+            let (f, k) = if i < 20 {
+                ((b & c) | (!b & d), 0x5A827999u32)
+            } else if i < 40 {
+                (b ^ c ^ d, 0x6ED9EBA1)
+            } else if i < 60 {
+                ((b & c) | (b & d) | (c & d), 0x8F1BBCDC)
+            } else {
+                (b ^ c ^ d, 0xCA62C1D6)
+            };
+            let temp = a.rotate_left(5) + f + e + k + w[i];
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        h[0] += a;
+        h[1] += b;
+        h[2] += c;
+        h[3] += d;
+        h[4] += e;
+    }
+
+    let mut digest = [0u8, ..20];
+    for i in range(0u, 20) {
+        digest[i] = (h[i / 4] >> (24 - (i % 4) * 8)) as u8;
+    }
+    digest
+}
+
+#[cfg(test)]
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", *b)).collect::<Vec<String>>().concat()
+}
+
+#[test]
+fn test_sha1() {
+    assert_eq!(to_hex(sha1(b"").as_slice()).as_slice(),
+               "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    assert_eq!(to_hex(sha1(b"abc").as_slice()).as_slice(),
+               "a9993e364706816aba3e25717850c26c9cd0d89d");
+    assert_eq!(to_hex(sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq").as_slice()).as_slice(),
+               "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+}
+
+#[test]
+fn test_accept_key() {
+    // The example from section 1.3.
+    assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ==").as_slice(), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+}
+
+#[test]
+fn test_frame_round_trip() {
+    use std::io::{MemReader, MemWriter};
+
+    for &length in [0u, 125, 126, 0xFFFF, 0x10000].iter() {
+        let payload = Vec::from_fn(length, |i| i as u8);
+        let mut writer = MemWriter::new();
+        write_frame(&mut writer, BinaryOp, payload.as_slice(), Some([1, 2, 3, 4])).unwrap();
+        let mut reader = MemReader::new(writer.unwrap());
+        let frame = read_frame(&mut reader, true).unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, BinaryOp);
+        assert_eq!(frame.payload, payload);
+    }
+}
+
+#[test]
+fn test_frame_errors() {
+    use std::io::MemReader;
+
+    // A reserved bit is set.
+    let mut reader = MemReader::new(vec!(0xC1, 0x00));
+    assert_eq!(read_frame(&mut reader, false).err(), Some(FrameError(CLOSE_PROTOCOL_ERROR)));
+    // A fragmented ping.
+    let mut reader = MemReader::new(vec!(0x09, 0x00));
+    assert_eq!(read_frame(&mut reader, false).err(), Some(FrameError(CLOSE_PROTOCOL_ERROR)));
+    // The stream ends inside the payload.
+    let mut reader = MemReader::new(vec!(0x81, 0x05, 0x61));
+    assert_eq!(read_frame(&mut reader, false).err(), Some(FrameError(CLOSE_ABNORMAL)));
+    // A masked frame from the server.
+    let mut reader = MemReader::new(vec!(0x81, 0x81, 1, 2, 3, 4, 0x60));
+    assert_eq!(read_frame(&mut reader, false).err(), Some(FrameError(CLOSE_PROTOCOL_ERROR)));
+    // An unmasked frame from a client.
+    let mut reader = MemReader::new(vec!(0x81, 0x01, 0x61));
+    assert_eq!(read_frame(&mut reader, true).err(), Some(FrameError(CLOSE_PROTOCOL_ERROR)));
+}
+
+#[test]
+fn test_echo_server() {
+    use resource_task::{new_resource_task, Exit};
+    use servo_util::opts;
+    use std::io::{Acceptor, Listener};
+    use std::io::net::tcp::TcpListener;
+
+    let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.socket_name().unwrap().port;
+    let mut acceptor = listener.listen().unwrap();
+
+    // A server that completes the handshake, then echoes each data frame
+    // and answers a close frame with one of its own.
+    spawn_named("WebSocketEchoServer", proc() {
+        let stream = acceptor.accept().unwrap();
+        let mut writer = stream.clone();
+        let mut reader = BufferedReader::new(stream);
+        let mut key = String::new();
+        loop {
+            let line = reader.read_line().unwrap();
+            let line = line.as_slice().trim_right();
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("Sec-WebSocket-Key: ") {
+                key = line.slice_from("Sec-WebSocket-Key: ".len()).to_string();
+            }
+        }
+        let response = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                                Connection: Upgrade\r\nSec-WebSocket-Accept: {:s}\r\n\r\n",
+                               accept_key(key.as_slice()));
+        writer.write(response.as_bytes()).unwrap();
+        loop {
+            let frame = read_frame(&mut reader, true).unwrap();
+            write_frame(&mut writer, frame.opcode, frame.payload.as_slice(), None).unwrap();
+            if frame.opcode == CloseOp {
+                break;
+            }
+        }
+    });
+
+    let resource_task = new_resource_task(opts::default_network_opts());
+    let url = Url::parse(format!("ws://127.0.0.1:{:u}/echo", port).as_slice()).unwrap();
+    let (events_chan, events) = channel();
+    let connection = connect(url, "http://127.0.0.1".to_string(), vec!(),
+                             resource_task.clone(), events_chan);
+
+    match events.recv() {
+        Opened(protocol) => assert_eq!(protocol.as_slice(), ""),
+        _ => panic!("expected the connection to open")
+    }
+    connection.send_text("hello".to_string());
+    match events.recv() {
+        TextMessage(text) => assert_eq!(text.as_slice(), "hello"),
+        _ => panic!("expected a text message")
+    }
+    connection.send_binary(vec!(0, 1, 2));
+    match events.recv() {
+        BinaryMessage(data) => assert_eq!(data, vec!(0, 1, 2)),
+        _ => panic!("expected a binary message")
+    }
+    connection.close(Some(CLOSE_NORMAL), "bye".to_string());
+    match events.recv() {
+        Closed(clean, code, reason) => {
+            assert!(clean);
+            assert_eq!(code, CLOSE_NORMAL);
+            assert_eq!(reason.as_slice(), "bye");
+        }
+        _ => panic!("expected the connection to close")
+    }
+    resource_task.send(Exit);
+}
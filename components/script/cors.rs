@@ -25,6 +25,8 @@ use http::headers::content_type::MediaType;
 use http::headers::request::{Accept, AcceptLanguage, ContentLanguage, ContentType};
 use http::method::{Method, Get, Head, Post, Options};
 
+use servo_net::blob_loader::blob_url_origin;
+use servo_net::fetch::request::serialize_origin;
 use url::{RelativeSchemeData, Url, UrlParser};
 
 #[deriving(Clone)]
@@ -58,6 +60,14 @@ impl CORSRequest {
             return Ok(None); // Not cross-origin, proceed with a normal fetch
         }
         match destination.scheme.as_slice() {
+            // Blob URLs are only readable from the origin that created them.
+            "blob" => {
+                if blob_url_origin(&destination) == Some(serialize_origin(&referer)) {
+                    Ok(None)
+                } else {
+                    Err(())
+                }
+            },
             // Todo: If the request's same origin data url flag is set (which isn't the case for XHR)
             // we can fetch a data URL normally. about:blank can also be fetched by XHR
             "http" | "https" => {
@@ -579,7 +579,24 @@ def getJSToNativeConversionTemplate(type, descriptorProvider, failureCode=None,
         raise TypeError("Can't handle array arguments yet")
 
     if type.isSequence():
-        raise TypeError("Can't handle sequence arguments yet")
+        # Only elements that convert without options are supported for now.
+        innerType = type.inner.inner if type.nullable() else type.inner
+        if not (innerType.isAny() or innerType.isPrimitive() or innerType.isUnion() or
+                innerType.isByteString() or innerType.isSequence()):
+            raise TypeError("Can't handle sequences of %s yet" % innerType)
+
+        (_, _, innerDeclType, _) = getJSToNativeConversionTemplate(
+            innerType, descriptorProvider, isMember=True)
+        declType = CGWrapper(innerDeclType, pre="Vec<", post=">")
+        if type.nullable():
+            declType = CGWrapper(declType, pre="Option<", post=" >")
+
+        templateBody = ("match FromJSValConvertible::from_jsval(cx, ${val}, ()) {\n"
+                        "    Ok(value) => value,\n"
+                        "    Err(()) => { %s },\n"
+                        "}" % exceptionCode)
+
+        return handleOptional(templateBody, declType, handleDefaultNull("None"))
 
     if type.isUnion():
         declType = CGGeneric(union_native_type(type))
@@ -1676,6 +1693,7 @@ def UnionTypes(descriptors, dictionaries, callbacks, config):
         'dom::bindings::conversions::Default',
         'dom::bindings::error::throw_not_in_union',
         'dom::bindings::js::JS',
+        'dom::bindings::str::ByteString',
         'dom::types::*',
         'js::jsapi::JSContext',
         'js::jsval::JSVal',
@@ -2283,7 +2301,7 @@ class CGPerSignatureCall(CGThing):
                          i in range(argConversionStartsAt, self.argCount)])
 
         cgThings.append(CGCallGenerator(
-                    ' false as JSBool' if self.isFallible() else None,
+                    self.getErrorResult() if self.isFallible() else None,
                     self.getArguments(), self.argsPre, returnType,
                     self.extendedAttributes, descriptor, nativeMethodName,
                     static))
@@ -2306,6 +2324,9 @@ class CGPerSignatureCall(CGThing):
     def isFallible(self):
         return not 'infallible' in self.extendedAttributes
 
+    def getErrorResult(self):
+        return ' false as JSBool'
+
     def wrap_return_value(self):
         return wrapForType('*vp')
 
@@ -2461,7 +2482,11 @@ class CGAbstractStaticBindingMethod(CGAbstractMethod):
         CGAbstractMethod.__init__(self, descriptor, name, "JSBool", args, extern=True)
 
     def definition_body(self):
-        return self.generate_code()
+        preamble = CGGeneric("""\
+let global = global_object_for_js_object(JS_CALLEE(cx, vp).to_object());
+let global = global.root();
+""")
+        return CGList([preamble, self.generate_code()])
 
     def generate_code(self):
         assert False  # Override me
@@ -2518,7 +2543,8 @@ class CGStaticMethod(CGAbstractStaticBindingMethod):
     def generate_code(self):
         nativeName = CGSpecializedMethod.makeNativeName(self.descriptor,
                                                         self.method)
-        return CGMethodCall([], nativeName, True, self.descriptor, self.method)
+        return CGMethodCall(["&global.root_ref()"], nativeName, True,
+                            self.descriptor, self.method)
 
 
 class CGGenericGetter(CGAbstractBindingMethod):
@@ -2591,8 +2617,8 @@ class CGStaticGetter(CGAbstractStaticBindingMethod):
     def generate_code(self):
         nativeName = CGSpecializedGetter.makeNativeName(self.descriptor,
                                                         self.attr)
-        return CGGetterCall([], self.attr.type, nativeName, self.descriptor,
-                            self.attr)
+        return CGGetterCall(["&global.root_ref()"], self.attr.type, nativeName,
+                            self.descriptor, self.attr)
 
 
 class CGGenericSetter(CGAbstractBindingMethod):
@@ -2669,8 +2695,8 @@ class CGStaticSetter(CGAbstractStaticBindingMethod):
             "  throw_type_error(cx, \"Not enough arguments to %s setter.\");\n"
             "  return 0;\n"
             "}\n" % self.attr.identifier.name)
-        call = CGSetterCall([], self.attr.type, nativeName, self.descriptor,
-                            self.attr)
+        call = CGSetterCall(["&global.root_ref()"], self.attr.type, nativeName,
+                            self.descriptor, self.attr)
         return CGList([checkForArg, call])
 
 
@@ -2816,12 +2842,11 @@ class CGConstant(CGThing):
         return CGIndenter(CGList(stringDecl(m) for m in self.constants)).define()
 
 def getUnionTypeTemplateVars(type, descriptorProvider):
-    # For dictionaries and sequences we need to pass None as the failureCode
-    # for getJSToNativeConversionTemplate.
-    # Also, for dictionaries we would need to handle conversion of
-    # null/undefined to the dictionary correctly.
-    if type.isDictionary() or type.isSequence():
-        raise TypeError("Can't handle dictionaries or sequences in unions")
+    # For dictionaries we need to pass None as the failureCode for
+    # getJSToNativeConversionTemplate, and we would need to handle conversion
+    # of null/undefined to the dictionary correctly.
+    if type.isDictionary():
+        raise TypeError("Can't handle dictionaries in unions")
 
     if type.isGeckoInterface():
         name = type.inner.identifier.name
@@ -2829,10 +2854,15 @@ def getUnionTypeTemplateVars(type, descriptorProvider):
     elif type.isEnum():
         name = type.inner.identifier.name
         typeName = name
-    elif type.isArray() or type.isSequence():
+    elif type.isArray():
         name = str(type)
         #XXXjdm dunno about typeName here
         typeName = "/*" + type.name + "*/"
+    elif type.isSequence():
+        name = str(type)
+        _, _, declType, _ = getJSToNativeConversionTemplate(
+            type, descriptorProvider, isMember=True)
+        typeName = declType.define()
     elif type.isDOMString():
         name = type.name
         typeName = "DOMString"
@@ -2935,7 +2965,17 @@ class CGUnionConversionStruct(CGThing):
         arrayObjectMemberTypes = filter(lambda t: t.isArray() or t.isSequence(), memberTypes)
         if len(arrayObjectMemberTypes) > 0:
             assert len(arrayObjectMemberTypes) == 1
-            raise TypeError("Can't handle arrays or sequences in unions.")
+            memberType = arrayObjectMemberTypes[0]
+            if memberType.isArray():
+                raise TypeError("Can't handle arrays in unions.")
+            name = str(memberType)
+            arrayObject = CGGeneric(
+                "match %s::TryConvertTo%s(cx, value) {\n"
+                "    Err(_) => return Err(()),\n"
+                "    Ok(Some(value)) => return Ok(e%s(value)),\n"
+                "    Ok(None) => (),\n"
+                "}\n" % (self.type, name, name))
+            names.append(name)
         else:
             arrayObject = None
 
@@ -2973,8 +3013,8 @@ class CGUnionConversionStruct(CGThing):
 
         hasObjectTypes = interfaceObject or arrayObject or dateObject or nonPlatformObject or object
         if hasObjectTypes:
-            assert interfaceObject
-            templateBody = CGList([interfaceObject], "\n")
+            assert interfaceObject or arrayObject
+            templateBody = CGList(filter(None, [interfaceObject, arrayObject]), "\n")
             conversions.append(CGIfWrapper(templateBody, "value.is_object()"))
 
         otherMemberTypes = [
@@ -3015,7 +3055,8 @@ class CGUnionConversionStruct(CGThing):
 
         return CGWrapper(
             CGIndenter(jsConversion, 4),
-            pre="fn TryConvertTo%s(cx: *mut JSContext, value: JSVal) -> %s {\n" % (t.name, returnType),
+            pre="fn TryConvertTo%s(cx: *mut JSContext, value: JSVal) -> %s {\n" % (
+                templateVars["name"], returnType),
             post="\n}")
 
     def define(self):
@@ -3535,6 +3576,10 @@ class CGProxySpecialOperation(CGPerSignatureCall):
                          "&mut found"))
         return args
 
+    def getErrorResult(self):
+        # The proxy handler hooks return a plain bool.
+        return ' false'
+
     def wrap_return_value(self):
         if not self.idlNode.isGetter() or self.templateValues is None:
             return ""
@@ -6,6 +6,7 @@
 
 //! Conversions of Rust values to and from `JSVal`.
 
+use dom::bindings::error::throw_type_error;
 use dom::bindings::js::{JS, JSRef, Root};
 use dom::bindings::str::ByteString;
 use dom::bindings::utils::{Reflectable, Reflector};
@@ -20,7 +21,7 @@ use js::jsapi::{JS_ValueToECMAUint32, JS_ValueToECMAInt32};
 use js::jsapi::{JS_ValueToUint16, JS_ValueToNumber, JS_ValueToBoolean};
 use js::jsapi::{JS_ValueToString, JS_GetStringCharsAndLength};
 use js::jsapi::{JS_NewUCStringCopyN, JS_NewStringCopyN};
-use js::jsapi::{JS_WrapValue};
+use js::jsapi::{JS_WrapValue, JS_GetArrayLength, JS_GetElement, JS_NewArrayObject};
 use js::jsval::JSVal;
 use js::jsval::{UndefinedValue, NullValue, BooleanValue, Int32Value, UInt32Value};
 use js::jsval::{StringValue, ObjectValue, ObjectOrNullValue};
@@ -75,6 +76,12 @@ impl ToJSValConvertible for JSVal {
     }
 }
 
+impl FromJSValConvertible<()> for JSVal {
+    fn from_jsval(_cx: *mut JSContext, value: JSVal, _option: ()) -> Result<JSVal, ()> {
+        Ok(value)
+    }
+}
+
 unsafe fn convert_from_jsval<T: default::Default>(
     cx: *mut JSContext, value: JSVal,
     convert_fn: unsafe extern "C" fn(*mut JSContext, JSVal, *mut T) -> JSBool) -> Result<T, ()> {
@@ -324,7 +331,7 @@ impl FromJSValConvertible<()> for ByteString {
             let chars = JS_GetStringCharsAndLength(cx, string, &mut length);
             slice::raw::buf_as_slice(chars, length as uint, |char_vec| {
                 if char_vec.iter().any(|&c| c > 0xFF) {
-                    // XXX Throw
+                    throw_type_error(cx, "Value is not a ByteString.");
                     Err(())
                 } else {
                     Ok(ByteString::new(char_vec.iter().map(|&c| c as u8).collect()))
@@ -396,6 +403,44 @@ impl<X: default::Default, T: FromJSValConvertible<X>> FromJSValConvertible<()> f
     }
 }
 
+// http://www.w3.org/TR/WebIDL/#es-sequence
+impl<T: FromJSValConvertible<()>> FromJSValConvertible<()> for Vec<T> {
+    fn from_jsval(cx: *mut JSContext, value: JSVal, _option: ()) -> Result<Vec<T>, ()> {
+        if !value.is_object() {
+            throw_type_error(cx, "Value is not an object.");
+            return Err(());
+        }
+        let object = value.to_object();
+        let mut length = 0;
+        // JS_GetArrayLength actually works on all objects.
+        if unsafe { JS_GetArrayLength(cx, object, &mut length) } == 0 {
+            return Err(());
+        }
+        let mut sequence = Vec::with_capacity(length as uint);
+        for index in range(0, length) {
+            let mut element = UndefinedValue();
+            if unsafe { JS_GetElement(cx, object, index, &mut element) } == 0 {
+                return Err(());
+            }
+            sequence.push(try!(FromJSValConvertible::from_jsval(cx, element, ())));
+        }
+        Ok(sequence)
+    }
+}
+
+impl<T: ToJSValConvertible> ToJSValConvertible for Vec<T> {
+    fn to_jsval(&self, cx: *mut JSContext) -> JSVal {
+        let mut elements: Vec<JSVal> = self.iter().map(|e| e.to_jsval(cx)).collect();
+        let array = unsafe {
+            JS_NewArrayObject(cx, elements.len() as libc::c_int, elements.as_mut_ptr())
+        };
+        if array.is_null() {
+            panic!("JS_NewArrayObject failed");
+        }
+        ObjectValue(unsafe { &*array })
+    }
+}
+
 impl ToJSValConvertible for *mut JSObject {
     fn to_jsval(&self, cx: *mut JSContext) -> JSVal {
         let mut wrapped = ObjectOrNullValue(*self);
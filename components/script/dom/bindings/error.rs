@@ -35,7 +35,11 @@ pub enum Error {
     Network,
     Abort,
     Timeout,
-    DataClone
+    DataClone,
+    QuotaExceeded,
+    /// A JavaScript exception is already pending, for example one thrown while
+    /// converting a value.
+    JSFailed
 }
 
 /// The return type for IDL operations that can throw DOM exceptions.
@@ -48,7 +52,13 @@ pub type ErrorResult = Fallible<()>;
 /// Set a pending DOM exception for the given `result` on `cx`.
 pub fn throw_dom_exception(cx: *mut JSContext, global: &GlobalRef,
                            result: Error) {
-    assert!(unsafe { JS_IsExceptionPending(cx) } == 0);
+    match result {
+        JSFailed => {
+            assert!(unsafe { JS_IsExceptionPending(cx) } != 0);
+            return;
+        }
+        _ => assert!(unsafe { JS_IsExceptionPending(cx) } == 0),
+    }
     let exception = DOMException::new_from_error(global, result).root();
     let thrown = exception.to_jsval(cx);
     unsafe {
@@ -72,6 +72,14 @@ impl<'a> GlobalRef<'a> {
         }
     }
 
+    /// Records a blob: URL created by this global, to revoke when it goes away.
+    pub fn add_blob_url(&self, url: Url) {
+        match *self {
+            Window(ref window) => window.add_blob_url(url),
+            Worker(ref worker) => worker.add_blob_url(url),
+        }
+    }
+
     pub fn get_url(&self) -> Url {
         match *self {
             Window(ref window) => window.get_url(),
@@ -49,6 +49,9 @@ use dom::bindings::utils::{Reflector, Reflectable};
 use dom::node::Node;
 use dom::xmlhttprequest::{XMLHttpRequest, TrustedXHRAddress};
 use dom::worker::{Worker, TrustedWorkerAddress};
+use dom::promise::{Promise, TrustedPromiseAddress};
+use dom::filereader::{FileReader, TrustedFileReaderAddress};
+use dom::websocket::{WebSocket, TrustedWebSocketAddress};
 use js::jsapi::JSObject;
 use layout_interface::TrustedNodeAddress;
 use script_task::StackRoots;
@@ -158,6 +161,33 @@ impl JS<Worker> {
     }
 }
 
+impl JS<Promise> {
+    pub unsafe fn from_trusted_promise_address(inner: TrustedPromiseAddress) -> JS<Promise> {
+        let TrustedPromiseAddress(addr) = inner;
+        JS {
+            ptr: addr as *const Promise
+        }
+    }
+}
+
+impl JS<FileReader> {
+    pub unsafe fn from_trusted_filereader_address(inner: TrustedFileReaderAddress) -> JS<FileReader> {
+        let TrustedFileReaderAddress(addr) = inner;
+        JS {
+            ptr: addr as *const FileReader
+        }
+    }
+}
+
+impl JS<WebSocket> {
+    pub unsafe fn from_trusted_websocket_address(inner: TrustedWebSocketAddress) -> JS<WebSocket> {
+        let TrustedWebSocketAddress(addr) = inner;
+        JS {
+            ptr: addr as *const WebSocket
+        }
+    }
+}
+
 impl<T: Reflectable> JS<T> {
     /// Create a new JS-owned value wrapped from a raw Rust pointer.
     pub unsafe fn from_raw(raw: *const T) -> JS<T> {
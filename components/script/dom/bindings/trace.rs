@@ -42,8 +42,10 @@ use js::jsval::JSVal;
 use js::rust::Cx;
 use layout_interface::{LayoutRPC, LayoutChan};
 use libc;
-use msg::constellation_msg::{PipelineId, SubpageId, WindowSizeData};
+use msg::constellation_msg::{PipelineId, SubpageId, TopLevelBrowsingContextId, WindowSizeData};
 use net::image_cache_task::ImageCacheTask;
+use net::storage_task::StorageType;
+use net::websocket::WebSocketConnection;
 use script_traits::ScriptControlChan;
 use script_traits::UntrustedNodeAddress;
 use servo_msg::compositor_msg::ScriptListener;
@@ -56,6 +58,7 @@ use std::comm::{Receiver, Sender};
 use std::io::timer::Timer;
 use std::rc::Rc;
 use string_cache::{Atom, Namespace};
+use sync::Arc;
 use style::PropertyDeclarationBlock;
 use url::Url;
 
@@ -206,8 +209,15 @@ no_jsmanaged_fields!(Atom, Namespace, Timer)
 no_jsmanaged_fields!(PropertyDeclarationBlock)
 // These three are interdependent, if you plan to put jsmanaged data
 // in one of these make sure it is propagated properly to containing structs
-no_jsmanaged_fields!(SubpageId, WindowSizeData, PipelineId)
+no_jsmanaged_fields!(SubpageId, WindowSizeData, PipelineId, TopLevelBrowsingContextId)
 no_jsmanaged_fields!(QuirksMode)
+
+impl JSTraceable for Arc<Vec<u8>> {
+    #[inline]
+    fn trace(&self, _: *mut JSTracer) {
+        // Do nothing
+    }
+}
 no_jsmanaged_fields!(Cx)
 no_jsmanaged_fields!(ResponseHeaderCollection, RequestHeaderCollection, Method)
 no_jsmanaged_fields!(ConstellationChan)
@@ -215,6 +225,8 @@ no_jsmanaged_fields!(LayoutChan)
 no_jsmanaged_fields!(WindowProxyHandler)
 no_jsmanaged_fields!(UntrustedNodeAddress)
 no_jsmanaged_fields!(LengthOrPercentageOrAuto)
+no_jsmanaged_fields!(WebSocketConnection)
+no_jsmanaged_fields!(StorageType)
 
 impl<'a> JSTraceable for &'a str {
     #[inline]
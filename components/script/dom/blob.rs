@@ -3,11 +3,34 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use dom::bindings::codegen::InheritTypes::FileDerived;
-use dom::bindings::global::GlobalRef;
-use dom::bindings::js::Temporary;
+use dom::bindings::conversions::FromJSValConvertible;
+use dom::bindings::global::{GlobalField, GlobalRef};
+use dom::bindings::js::{JS, JSRef, Temporary};
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
-use dom::bindings::error::Fallible;
+use dom::bindings::error::{Fallible, JSFailed};
 use dom::bindings::codegen::Bindings::BlobBinding;
+use dom::bindings::codegen::Bindings::BlobBinding::{BlobMethods, BlobPropertyBag};
+use dom::bindings::codegen::Bindings::BlobBinding::{EndingTypes, EndingTypesValues};
+
+use servo_net::blob_loader::BlobData;
+use servo_util::str::DOMString;
+
+use js::jsapi::JSContext;
+use js::jsfriendapi::bindgen::{JS_IsArrayBufferObject, JS_GetArrayBufferByteLength};
+use js::jsfriendapi::bindgen::{JS_GetArrayBufferData, JS_IsArrayBufferViewObject};
+use js::jsfriendapi::bindgen::{JS_GetArrayBufferViewByteLength, JS_GetArrayBufferViewData};
+use js::jsval::JSVal;
+
+use std::ascii::AsciiExt;
+use std::cmp::{max, min};
+use std::default::Default;
+use std::slice;
+use sync::Arc;
+
+#[cfg(windows)]
+static NATIVE_LINE_ENDING: &'static str = "\r\n";
+#[cfg(not(windows))]
+static NATIVE_LINE_ENDING: &'static str = "\n";
 
 #[jstraceable]
 pub enum BlobType {
@@ -18,25 +41,158 @@ pub enum BlobType {
 #[dom_struct]
 pub struct Blob {
     reflector_: Reflector,
-    type_: BlobType
+    type_: BlobType,
+    /// The bytes of the blob, which are never modified once it is created.
+    bytes: Arc<Vec<u8>>,
+    type_string: DOMString,
+    global: GlobalField,
 }
 
 impl Blob {
-    pub fn new_inherited() -> Blob {
+    pub fn new_inherited(global: &GlobalRef, type_: BlobType, bytes: Arc<Vec<u8>>,
+                         type_string: DOMString) -> Blob {
         Blob {
             reflector_: Reflector::new(),
-            type_: BlobTypeId
+            type_: type_,
+            bytes: bytes,
+            type_string: type_string,
+            global: GlobalField::from_rooted(global),
         }
     }
 
-    pub fn new(global: &GlobalRef) -> Temporary<Blob> {
-        reflect_dom_object(box Blob::new_inherited(),
+    pub fn new(global: &GlobalRef, bytes: Vec<u8>, type_string: DOMString) -> Temporary<Blob> {
+        reflect_dom_object(box Blob::new_inherited(global, BlobTypeId, Arc::new(bytes),
+                                                   type_string),
                            global,
                            BlobBinding::Wrap)
     }
 
-    pub fn Constructor(global: &GlobalRef) -> Fallible<Temporary<Blob>> {
-        Ok(Blob::new(global))
+    // http://dev.w3.org/2006/webapi/FileAPI/#constructorBlob
+    pub fn Constructor(cx: *mut JSContext, global: &GlobalRef, blobParts: Option<Vec<JSVal>>,
+                       options: &BlobPropertyBag) -> Fallible<Temporary<Blob>> {
+        let mut bytes = vec!();
+        for part in blobParts.unwrap_or(vec!()).into_iter() {
+            if append_part(cx, part, options.endings, &mut bytes).is_err() {
+                return Err(JSFailed);
+            }
+        }
+        Ok(Blob::new(global, bytes, normalize_type(options.type_.as_slice())))
+    }
+
+    /// The contents of the blob, to hand to the resource task.
+    pub fn data(&self) -> BlobData {
+        BlobData {
+            bytes: self.bytes.clone(),
+            content_type: self.type_string.clone(),
+        }
+    }
+}
+
+/// Appends the bytes of a part passed to the constructor: an ArrayBuffer, a view on one, a blob,
+/// or else a string, which is encoded as UTF-8. Fails if a JavaScript exception was thrown while
+/// converting it to a string.
+fn append_part(cx: *mut JSContext, part: JSVal, endings: EndingTypes, bytes: &mut Vec<u8>)
+               -> Result<(), ()> {
+    if part.is_object() {
+        let object = part.to_object();
+        unsafe {
+            if JS_IsArrayBufferObject(object, cx) != 0 {
+                let length = JS_GetArrayBufferByteLength(object, cx) as uint;
+                let data = JS_GetArrayBufferData(object, cx) as *const u8;
+                slice::raw::buf_as_slice(data, length, |data| bytes.push_all(data));
+                return Ok(());
+            }
+            if JS_IsArrayBufferViewObject(object, cx) != 0 {
+                let length = JS_GetArrayBufferViewByteLength(object, cx) as uint;
+                let data = JS_GetArrayBufferViewData(object, cx) as *const u8;
+                slice::raw::buf_as_slice(data, length, |data| bytes.push_all(data));
+                return Ok(());
+            }
+        }
+        let blob: Result<JS<Blob>, ()> = FromJSValConvertible::from_jsval(cx, part, ());
+        match blob {
+            Ok(blob) => {
+                bytes.push_all(blob.root().bytes.as_slice());
+                return Ok(());
+            }
+            Err(()) => ()
+        }
+    }
+
+    let text: DOMString = try!(FromJSValConvertible::from_jsval(cx, part, Default::default()));
+    match endings {
+        EndingTypesValues::Transparent => bytes.push_all(text.as_bytes()),
+        EndingTypesValues::Native => {
+            bytes.push_all(convert_line_endings(text.as_slice()).as_bytes())
+        }
+    }
+    Ok(())
+}
+
+/// Replaces every line ending with the one of the platform.
+fn convert_line_endings(text: &str) -> String {
+    let mut converted = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    loop {
+        match chars.next() {
+            Some('\r') => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                converted.push_str(NATIVE_LINE_ENDING);
+            }
+            Some('\n') => converted.push_str(NATIVE_LINE_ENDING),
+            Some(c) => converted.push(c),
+            None => return converted,
+        }
+    }
+}
+
+/// Returns the type of a new blob: the given type in lowercase, or the empty string if it has
+/// characters outside of printable ASCII.
+fn normalize_type(type_: &str) -> DOMString {
+    if type_.chars().all(|c| c >= '\x20' && c <= '\x7E') {
+        type_.to_ascii_lower()
+    } else {
+        "".to_string()
+    }
+}
+
+/// Resolves a `start` or `end` argument of `slice`, which counts from the end if it is negative.
+fn relative_position(position: Option<i64>, default: i64, size: i64) -> i64 {
+    match position {
+        None => default,
+        Some(position) if position < 0 => max(size + position, 0),
+        Some(position) => min(position, size),
+    }
+}
+
+impl<'a> BlobMethods for JSRef<'a, Blob> {
+    fn Size(self) -> u64 {
+        self.bytes.len() as u64
+    }
+
+    fn Type(self) -> DOMString {
+        self.type_string.clone()
+    }
+
+    // http://dev.w3.org/2006/webapi/FileAPI/#slice-method-algo
+    fn Slice(self, start: Option<i64>, end: Option<i64>,
+             contentType: Option<DOMString>) -> Temporary<Blob> {
+        let size = self.bytes.len() as i64;
+        let relative_start = relative_position(start, 0, size);
+        let relative_end = relative_position(end, size, size);
+        let type_string = match contentType {
+            Some(content_type) => normalize_type(content_type.as_slice()),
+            None => "".to_string(),
+        };
+        let bytes = if relative_start < relative_end {
+            self.bytes.slice(relative_start as uint, relative_end as uint).to_vec()
+        } else {
+            vec!()
+        };
+        let global = self.global.root();
+        Blob::new(&global.root_ref(), bytes, type_string)
     }
 }
 
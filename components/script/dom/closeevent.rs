@@ -0,0 +1,79 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::Bindings::CloseEventBinding;
+use dom::bindings::codegen::Bindings::CloseEventBinding::CloseEventMethods;
+use dom::bindings::codegen::Bindings::EventBinding::EventMethods;
+use dom::bindings::codegen::InheritTypes::{EventCast, CloseEventDerived};
+use dom::bindings::error::Fallible;
+use dom::bindings::global::GlobalRef;
+use dom::bindings::js::{JSRef, Temporary};
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::event::{Event, CloseEventTypeId};
+use servo_util::str::DOMString;
+
+#[dom_struct]
+pub struct CloseEvent {
+    event: Event,
+    was_clean: bool,
+    code: u16,
+    reason: DOMString,
+}
+
+impl CloseEventDerived for Event {
+    fn is_closeevent(&self) -> bool {
+        *self.type_id() == CloseEventTypeId
+    }
+}
+
+impl CloseEvent {
+    fn new_inherited(was_clean: bool, code: u16, reason: DOMString) -> CloseEvent {
+        CloseEvent {
+            event: Event::new_inherited(CloseEventTypeId),
+            was_clean: was_clean,
+            code: code,
+            reason: reason,
+        }
+    }
+
+    pub fn new(global: &GlobalRef, type_: DOMString,
+               can_bubble: bool, cancelable: bool,
+               was_clean: bool, code: u16, reason: DOMString) -> Temporary<CloseEvent> {
+        let ev = reflect_dom_object(box CloseEvent::new_inherited(was_clean, code, reason),
+                                    global,
+                                    CloseEventBinding::Wrap).root();
+        let event: JSRef<Event> = EventCast::from_ref(*ev);
+        event.InitEvent(type_, can_bubble, cancelable);
+        Temporary::from_rooted(*ev)
+    }
+
+    pub fn Constructor(global: &GlobalRef,
+                       type_: DOMString,
+                       init: &CloseEventBinding::CloseEventInit)
+                       -> Fallible<Temporary<CloseEvent>> {
+        let ev = CloseEvent::new(global, type_, init.parent.bubbles, init.parent.cancelable,
+                                 init.wasClean, init.code, init.reason.clone());
+        Ok(ev)
+    }
+}
+
+impl<'a> CloseEventMethods for JSRef<'a, CloseEvent> {
+    fn WasClean(self) -> bool {
+        self.was_clean
+    }
+
+    fn Code(self) -> u16 {
+        self.code
+    }
+
+    fn Reason(self) -> DOMString {
+        self.reason.clone()
+    }
+}
+
+impl Reflectable for CloseEvent {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        self.event.reflector()
+    }
+}
@@ -13,14 +13,18 @@ use dom::bindings::js::{JSRef, Temporary, RootCollection};
 use dom::bindings::utils::{Reflectable, Reflector};
 use dom::eventtarget::{EventTarget, EventTargetHelpers};
 use dom::eventtarget::WorkerGlobalScopeTypeId;
+use dom::promise::Promise;
+use dom::filereader::FileReader;
 use dom::messageevent::MessageEvent;
+use dom::websocket::WebSocket;
 use dom::worker::{Worker, TrustedWorkerAddress};
 use dom::workerglobalscope::DedicatedGlobalScope;
 use dom::workerglobalscope::{WorkerGlobalScope, WorkerGlobalScopeHelpers};
 use dom::xmlhttprequest::XMLHttpRequest;
 use script_task::{ScriptTask, ScriptChan};
 use script_task::{ScriptMsg, FromWorker,  DOMMessage, FireTimerMsg, XHRProgressMsg, XHRReleaseMsg, WorkerRelease};
-use script_task::WorkerPostMessage;
+use script_task::{WorkerPostMessage, FetchResponseMsg, PromiseJobsMsg, WebSocketEventMsg};
+use script_task::FileReaderEventMsg;
 use script_task::StackRootTLS;
 
 use servo_net::resource_task::{ResourceTask, load_whole_resource};
@@ -145,6 +149,18 @@ impl DedicatedWorkerGlobalScope {
                     Ok(WorkerRelease(addr)) => {
                         Worker::handle_release(addr)
                     },
+                    Ok(FetchResponseMsg(addr, response)) => {
+                        Promise::handle_fetch_response(addr, response)
+                    },
+                    Ok(PromiseJobsMsg(addr)) => {
+                        Promise::handle_jobs(addr)
+                    },
+                    Ok(WebSocketEventMsg(addr, event)) => {
+                        WebSocket::handle_event(addr, event)
+                    },
+                    Ok(FileReaderEventMsg(addr, generation, event)) => {
+                        FileReader::handle_event(addr, generation, event)
+                    },
                     Ok(FireTimerMsg(FromWorker, timer_id)) => {
                         scope.handle_fire_timer(timer_id);
                     }
@@ -152,6 +168,7 @@ impl DedicatedWorkerGlobalScope {
                     Err(_) => break,
                 }
             }
+            scope.revoke_blob_urls();
         });
     }
 }
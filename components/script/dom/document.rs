@@ -56,6 +56,8 @@ use dom::range::Range;
 use dom::treewalker::TreeWalker;
 use dom::uievent::UIEvent;
 use dom::window::{Window, WindowHelpers};
+use servo_net::cookie::NonHTTP;
+use servo_net::resource_task::{GetCookiesForUrl, SetCookiesForUrl};
 use servo_util::namespace;
 use servo_util::str::{DOMString, split_html_space_chars};
 
@@ -460,6 +462,15 @@ trait PrivateDocumentHelpers {
     fn get_html_element(self) -> Option<Temporary<HTMLHtmlElement>>;
 }
 
+/// Documents whose URL does not use a network scheme never have cookies.
+/// https://html.spec.whatwg.org/multipage/dom.html#cookie-averse-document-object
+fn is_cookie_averse_url(url: &Url) -> bool {
+    match url.scheme.as_slice() {
+        "http" | "https" => false,
+        _ => true,
+    }
+}
+
 impl<'a> PrivateDocumentHelpers for JSRef<'a, Document> {
     fn createNodeList(self, callback: |node: JSRef<Node>| -> bool) -> Temporary<NodeList> {
         let window = self.window.root();
@@ -720,6 +731,29 @@ impl<'a> DocumentMethods for JSRef<'a, Document> {
         }
     }
 
+    // https://html.spec.whatwg.org/multipage/dom.html#dom-document-cookie
+    fn Cookie(self) -> DOMString {
+        if !is_cookie_averse_url(self.url()) {
+            let window = self.window().root();
+            let (tx, rx) = channel();
+            window.page().resource_task.send(GetCookiesForUrl(self.url().clone(), tx, NonHTTP));
+            match rx.recv() {
+                Some(cookies) => return cookies,
+                None => ()
+            }
+        }
+        "".to_string()
+    }
+
+    // https://html.spec.whatwg.org/multipage/dom.html#dom-document-cookie
+    fn SetCookie(self, cookie: DOMString) {
+        if is_cookie_averse_url(self.url()) {
+            return;
+        }
+        let window = self.window().root();
+        window.page().resource_task.send(SetCookiesForUrl(self.url().clone(), cookie, NonHTTP));
+    }
+
     // http://www.whatwg.org/html/#dom-document-lastmodified
     fn LastModified(self) -> DOMString {
         match *self.last_modified.borrow() {
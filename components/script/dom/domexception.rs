@@ -56,7 +56,9 @@ impl DOMErrorName {
             error::Abort => AbortError,
             error::Timeout => TimeoutError,
             error::DataClone => DataCloneError,
+            error::QuotaExceeded => QuotaExceededError,
             error::FailureUnknown => panic!(),
+            error::JSFailed => panic!(),
         }
     }
 }
@@ -27,12 +27,16 @@ pub enum EventPhase {
 #[deriving(PartialEq)]
 #[jstraceable]
 pub enum EventTypeId {
+    AnimationEventTypeId,
+    CloseEventTypeId,
     CustomEventTypeId,
     HTMLEventTypeId,
     KeyboardEventTypeId,
     MessageEventTypeId,
     MouseEventTypeId,
     ProgressEventTypeId,
+    StorageEventTypeId,
+    TransitionEventTypeId,
     UIEventTypeId
 }
 
@@ -37,6 +37,7 @@ pub enum ListenerPhase {
 #[deriving(PartialEq)]
 #[jstraceable]
 pub enum EventTargetTypeId {
+    FileReaderTypeId,
     NodeTargetTypeId(NodeTypeId),
     WebSocketTypeId,
     WindowTypeId,
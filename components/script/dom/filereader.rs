@@ -0,0 +1,317 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Reads the contents of blobs. The contents are decoded on a task of their own, which reports
+//! back to the event loop of the reader, where the progress events are fired.
+
+use dom::bindings::codegen::Bindings::EventHandlerBinding::EventHandlerNonNull;
+use dom::bindings::codegen::Bindings::FileReaderBinding;
+use dom::bindings::codegen::Bindings::FileReaderBinding::FileReaderMethods;
+use dom::bindings::codegen::InheritTypes::{EventCast, EventTargetCast};
+use dom::bindings::conversions::ToJSValConvertible;
+use dom::bindings::error::{ErrorResult, Fallible, InvalidState};
+use dom::bindings::global::{GlobalRef, GlobalField};
+use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::blob::Blob;
+use dom::event::Event;
+use dom::eventtarget::{EventTarget, EventTargetHelpers, FileReaderTypeId};
+use dom::progressevent::ProgressEvent;
+use script_task::{ScriptChan, FileReaderEventMsg};
+
+use servo_util::str::DOMString;
+use servo_util::task::spawn_named;
+
+use encoding::all::{UTF_8, UTF_16BE, UTF_16LE};
+use encoding::label::encoding_from_whatwg_label;
+use encoding::types::{DecodeReplace, EncodingRef};
+use js::jsapi::{JSContext, JS_AddObjectRoot, JS_RemoveObjectRoot};
+use js::jsfriendapi::bindgen::{JS_NewArrayBuffer, JS_GetArrayBufferData};
+use js::jsval::{JSVal, NullValue, ObjectValue};
+use serialize::base64::{ToBase64, STANDARD};
+
+use libc;
+use libc::c_void;
+use std::cell::Cell;
+use std::ptr;
+
+#[deriving(PartialEq)]
+#[jstraceable]
+enum FileReaderReadyState {
+    Empty = 0,
+    Loading = 1,
+    Done = 2,
+}
+
+/// What a read turns the blob into.
+enum ReadFormat {
+    ArrayBufferFormat,
+    /// Text, in the encoding of the given label if it is not overridden by a byte order mark.
+    TextFormat(Option<DOMString>),
+    DataURLFormat,
+}
+
+/// The result of a read, before it is handed to script.
+pub enum ReadResult {
+    BytesResult(Vec<u8>),
+    TextResult(DOMString),
+}
+
+/// Progress of a read, sent to the event loop of the reader.
+pub enum FileReaderEvent {
+    /// The read started on a blob of the given size.
+    ReadStarted(u64),
+    /// The blob was read and decoded.
+    ReadFinished(u64, ReadResult),
+}
+
+pub struct TrustedFileReaderAddress(pub *const c_void);
+
+#[dom_struct]
+pub struct FileReader {
+    eventtarget: EventTarget,
+    global: GlobalField,
+    ready_state: Cell<FileReaderReadyState>,
+    result: Cell<JSVal>,
+    /// Counts the reads, so that the events of a read that was aborted are ignored.
+    generation: Cell<uint>,
+    pinned_count: Cell<uint>,
+}
+
+impl FileReader {
+    fn new_inherited(global: &GlobalRef) -> FileReader {
+        FileReader {
+            eventtarget: EventTarget::new_inherited(FileReaderTypeId),
+            global: GlobalField::from_rooted(global),
+            ready_state: Cell::new(Empty),
+            result: Cell::new(NullValue()),
+            generation: Cell::new(0),
+            pinned_count: Cell::new(0),
+        }
+    }
+
+    pub fn new(global: &GlobalRef) -> Temporary<FileReader> {
+        reflect_dom_object(box FileReader::new_inherited(global),
+                           global,
+                           FileReaderBinding::Wrap)
+    }
+
+    pub fn Constructor(global: &GlobalRef) -> Fallible<Temporary<FileReader>> {
+        Ok(FileReader::new(global))
+    }
+
+    pub fn handle_event(addr: TrustedFileReaderAddress, generation: uint, event: FileReaderEvent) {
+        let reader = unsafe { JS::from_trusted_filereader_address(addr).root() };
+        match event {
+            ReadStarted(total) => {
+                if reader.is_current_read(generation) {
+                    reader.dispatch_progress_event("loadstart", 0, Some(total));
+                }
+            }
+            ReadFinished(total, result) => {
+                if reader.is_current_read(generation) {
+                    reader.dispatch_progress_event("progress", total, Some(total));
+                }
+                // The progress event handler may have aborted the read.
+                if reader.is_current_read(generation) {
+                    reader.ready_state.set(Done);
+                    let result = reader.result_value(result);
+                    reader.result.set(result);
+                    reader.dispatch_progress_event("load", total, Some(total));
+                    // The load event handler may have started another read.
+                    if reader.ready_state.get() != Loading {
+                        reader.dispatch_progress_event("loadend", total, Some(total));
+                    }
+                }
+                reader.release_once();
+            }
+        }
+    }
+}
+
+/// Decodes the contents of a blob as text. A byte order mark wins over the label passed to
+/// `readAsText`, which wins over the charset in the type of the blob; the default is UTF-8.
+fn decode_text(bytes: &[u8], label: Option<DOMString>, content_type: &str) -> DOMString {
+    let (encoding, bytes) = if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        (UTF_8 as EncodingRef, bytes.slice_from(3))
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
+        (UTF_16BE as EncodingRef, bytes.slice_from(2))
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
+        (UTF_16LE as EncodingRef, bytes.slice_from(2))
+    } else {
+        let encoding = label.and_then(|label| encoding_from_whatwg_label(label.as_slice()))
+                            .or_else(|| charset_encoding(content_type))
+                            .unwrap_or(UTF_8 as EncodingRef);
+        (encoding, bytes)
+    };
+    encoding.decode(bytes, DecodeReplace).unwrap()
+}
+
+/// Returns the encoding named by the charset parameter of a MIME type.
+fn charset_encoding(content_type: &str) -> Option<EncodingRef> {
+    content_type.split(';').skip(1).filter_map(|parameter| {
+        let parts: Vec<&str> = parameter.splitn(1, '=').map(|part| part.trim()).collect();
+        if parts.len() == 2 && parts[0] == "charset" {
+            encoding_from_whatwg_label(parts[1].trim_chars('"'))
+        } else {
+            None
+        }
+    }).next()
+}
+
+impl<'a> FileReaderMethods for JSRef<'a, FileReader> {
+    event_handler!(loadstart, GetOnloadstart, SetOnloadstart)
+    event_handler!(progress, GetOnprogress, SetOnprogress)
+    event_handler!(load, GetOnload, SetOnload)
+    event_handler!(abort, GetOnabort, SetOnabort)
+    event_handler!(error, GetOnerror, SetOnerror)
+    event_handler!(loadend, GetOnloadend, SetOnloadend)
+
+    fn ReadAsArrayBuffer(self, blob: JSRef<Blob>) -> ErrorResult {
+        self.read(blob, ArrayBufferFormat)
+    }
+
+    fn ReadAsText(self, blob: JSRef<Blob>, label: Option<DOMString>) -> ErrorResult {
+        self.read(blob, TextFormat(label))
+    }
+
+    fn ReadAsDataURL(self, blob: JSRef<Blob>) -> ErrorResult {
+        self.read(blob, DataURLFormat)
+    }
+
+    // http://dev.w3.org/2006/webapi/FileAPI/#dfn-abort
+    fn Abort(self) {
+        // Step 1.
+        if self.ready_state.get() != Loading {
+            self.result.set(NullValue());
+            return;
+        }
+        // Steps 2-4. The events of the read are ignored from now on.
+        self.ready_state.set(Done);
+        self.result.set(NullValue());
+        self.generation.set(self.generation.get() + 1);
+        // Steps 5-6.
+        self.dispatch_progress_event("abort", 0, None);
+        if self.ready_state.get() != Loading {
+            self.dispatch_progress_event("loadend", 0, None);
+        }
+    }
+
+    fn ReadyState(self) -> u16 {
+        self.ready_state.get() as u16
+    }
+
+    fn Result(self, _cx: *mut JSContext) -> JSVal {
+        self.result.get()
+    }
+}
+
+impl Reflectable for FileReader {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        self.eventtarget.reflector()
+    }
+}
+
+trait PrivateFileReaderHelpers {
+    unsafe fn to_trusted(self) -> TrustedFileReaderAddress;
+    fn release_once(self);
+    fn read(self, blob: JSRef<Blob>, format: ReadFormat) -> ErrorResult;
+    fn is_current_read(self, generation: uint) -> bool;
+    fn result_value(self, result: ReadResult) -> JSVal;
+    fn dispatch_progress_event(self, type_: &str, loaded: u64, total: Option<u64>);
+}
+
+impl<'a> PrivateFileReaderHelpers for JSRef<'a, FileReader> {
+    // Creates a trusted address to the object, and roots it. Always pair this with a release()
+    unsafe fn to_trusted(self) -> TrustedFileReaderAddress {
+        if self.pinned_count.get() == 0 {
+            JS_AddObjectRoot(self.global.root().root_ref().get_cx(), self.reflector().rootable());
+        }
+        let pinned_count = self.pinned_count.get();
+        self.pinned_count.set(pinned_count + 1);
+        TrustedFileReaderAddress(self.deref() as *const FileReader as *const libc::c_void)
+    }
+
+    fn release_once(self) {
+        assert!(self.pinned_count.get() > 0)
+        let pinned_count = self.pinned_count.get();
+        self.pinned_count.set(pinned_count - 1);
+        if self.pinned_count.get() == 0 {
+            let cx = self.global.root().root_ref().get_cx();
+            unsafe {
+                JS_RemoveObjectRoot(cx, self.reflector().rootable());
+            }
+        }
+    }
+
+    // http://dev.w3.org/2006/webapi/FileAPI/#readAsArrayBuffer
+    fn read(self, blob: JSRef<Blob>, format: ReadFormat) -> ErrorResult {
+        // Step 1.
+        if self.ready_state.get() == Loading {
+            return Err(InvalidState);
+        }
+        // Step 3.
+        self.ready_state.set(Loading);
+        self.result.set(NullValue());
+        let generation = self.generation.get() + 1;
+        self.generation.set(generation);
+
+        // Step 4. The reader stays alive until the read has finished.
+        let addr = unsafe { self.to_trusted() };
+        let data = blob.data();
+        let global = self.global.root();
+        let ScriptChan(script_chan) = global.root_ref().script_chan().clone();
+        spawn_named("FileReader", proc() {
+            let total = data.bytes.len() as u64;
+            script_chan.send(FileReaderEventMsg(addr, generation, ReadStarted(total)));
+            let result = match format {
+                ArrayBufferFormat => BytesResult((*data.bytes).clone()),
+                TextFormat(label) => {
+                    TextResult(decode_text(data.bytes.as_slice(), label,
+                                           data.content_type.as_slice()))
+                }
+                DataURLFormat => {
+                    let content_type = if data.content_type.is_empty() {
+                        "application/octet-stream"
+                    } else {
+                        data.content_type.as_slice()
+                    };
+                    TextResult(format!("data:{};base64,{}", content_type,
+                                       data.bytes.as_slice().to_base64(STANDARD)))
+                }
+            };
+            script_chan.send(FileReaderEventMsg(addr, generation, ReadFinished(total, result)));
+        });
+        Ok(())
+    }
+
+    fn is_current_read(self, generation: uint) -> bool {
+        self.generation.get() == generation && self.ready_state.get() == Loading
+    }
+
+    fn result_value(self, result: ReadResult) -> JSVal {
+        let global = self.global.root();
+        let cx = global.root_ref().get_cx();
+        match result {
+            TextResult(text) => text.to_jsval(cx),
+            BytesResult(bytes) => unsafe {
+                let buffer = JS_NewArrayBuffer(cx, bytes.len() as u32);
+                assert!(buffer.is_not_null());
+                let data = JS_GetArrayBufferData(buffer, cx);
+                ptr::copy_nonoverlapping_memory(data, bytes.as_ptr(), bytes.len());
+                ObjectValue(&*buffer)
+            }
+        }
+    }
+
+    fn dispatch_progress_event(self, type_: &str, loaded: u64, total: Option<u64>) {
+        let global = self.global.root();
+        let progressevent = ProgressEvent::new(&global.root_ref(), type_.to_string(),
+                                               false, false, total.is_some(), loaded,
+                                               total.unwrap_or(0)).root();
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        let event: JSRef<Event> = EventCast::from_ref(*progressevent);
+        target.dispatch_event_with_target(None, event).ok();
+    }
+}
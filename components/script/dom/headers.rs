@@ -0,0 +1,237 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::cell::DOMRefCell;
+use dom::bindings::codegen::Bindings::HeadersBinding;
+use dom::bindings::codegen::Bindings::HeadersBinding::HeadersMethods;
+use dom::bindings::codegen::UnionTypes::HeadersOrByteStringSequenceSequence::{HeadersOrByteStringSequenceSequence, eHeaders, eByteStringSequenceSequence};
+use dom::bindings::error::{ErrorResult, Fallible, InvalidState, JSFailed, Syntax};
+use dom::bindings::error::throw_type_error;
+use dom::bindings::global::GlobalRef;
+use dom::bindings::js::{JSRef, Temporary};
+use dom::bindings::str::ByteString;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+
+use http::headers::HeaderEnum;
+use http::headers::request::HeaderCollection as RequestHeaderCollection;
+use http::headers::response::HeaderCollection as ResponseHeaderCollection;
+
+use std::cell::Cell;
+use std::mem;
+
+/// http://fetch.spec.whatwg.org/#concept-headers-guard
+#[deriving(PartialEq)]
+#[jstraceable]
+pub enum Guard {
+    ImmutableGuard,
+    RequestGuard,
+    ResponseGuard,
+    NoGuard,
+}
+
+#[dom_struct]
+pub struct Headers {
+    reflector_: Reflector,
+    guard: Cell<Guard>,
+    header_list: DOMRefCell<Vec<(ByteString, ByteString)>>,
+}
+
+impl Headers {
+    fn new_inherited(guard: Guard) -> Headers {
+        Headers {
+            reflector_: Reflector::new(),
+            guard: Cell::new(guard),
+            header_list: DOMRefCell::new(vec!()),
+        }
+    }
+
+    pub fn new(global: &GlobalRef, guard: Guard) -> Temporary<Headers> {
+        reflect_dom_object(box Headers::new_inherited(guard), global, HeadersBinding::Wrap)
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-headers
+    pub fn Constructor(global: &GlobalRef, init: Option<HeadersOrByteStringSequenceSequence>)
+                       -> Fallible<Temporary<Headers>> {
+        let headers = Headers::new(global, NoGuard).root();
+        match init {
+            Some(ref init) => try!(headers.fill(global, init)),
+            None => {}
+        }
+        Ok(Temporary::from_rooted(*headers))
+    }
+}
+
+/// http://fetch.spec.whatwg.org/#forbidden-header-name
+fn is_forbidden_header_name(name: &ByteString) -> bool {
+    match name.to_lower().as_str() {
+        Some(name) => match name {
+            "accept-charset" | "accept-encoding" |
+            "access-control-request-headers" |
+            "access-control-request-method" |
+            "connection" | "content-length" |
+            "cookie" | "cookie2" | "date" | "dnt" |
+            "expect" | "host" | "keep-alive" | "origin" |
+            "referer" | "te" | "trailer" | "transfer-encoding" |
+            "upgrade" | "user-agent" | "via" => true,
+            _ => name.starts_with("proxy-") || name.starts_with("sec-")
+        },
+        None => true
+    }
+}
+
+/// http://fetch.spec.whatwg.org/#forbidden-response-header-name
+fn is_forbidden_response_header_name(name: &ByteString) -> bool {
+    match name.to_lower().as_str() {
+        Some("set-cookie") | Some("set-cookie2") => true,
+        _ => false
+    }
+}
+
+pub trait HeadersHelpers {
+    fn fill(self, global: &GlobalRef, init: &HeadersOrByteStringSequenceSequence) -> ErrorResult;
+    fn set_guard(self, guard: Guard);
+    fn copy_from(self, other: JSRef<Headers>);
+    fn fill_from_response(self, headers: &ResponseHeaderCollection);
+    fn to_request_headers(self) -> RequestHeaderCollection;
+}
+
+impl<'a> HeadersHelpers for JSRef<'a, Headers> {
+    /// http://fetch.spec.whatwg.org/#concept-headers-fill
+    /// Goes through `append`, so the guard applies to every header.
+    fn fill(self, global: &GlobalRef, init: &HeadersOrByteStringSequenceSequence) -> ErrorResult {
+        let pairs = match *init {
+            eHeaders(ref other) => {
+                let other = other.root();
+                let list = other.header_list.borrow().clone();
+                list
+            }
+            eByteStringSequenceSequence(ref pairs) => {
+                let mut list = vec!();
+                for pair in pairs.iter() {
+                    if pair.len() != 2 {
+                        throw_type_error(global.get_cx(), "Header pairs must have two items.");
+                        return Err(JSFailed);
+                    }
+                    list.push((pair[0].clone(), pair[1].clone()));
+                }
+                list
+            }
+        };
+        for (name, value) in pairs.into_iter() {
+            try!(self.Append(name, value));
+        }
+        Ok(())
+    }
+
+    fn set_guard(self, guard: Guard) {
+        self.guard.set(guard);
+    }
+
+    fn copy_from(self, other: JSRef<Headers>) {
+        *self.header_list.borrow_mut() = other.header_list.borrow().clone();
+    }
+
+    /// Used for responses, so the guard does not apply.
+    fn fill_from_response(self, headers: &ResponseHeaderCollection) {
+        let mut list = self.header_list.borrow_mut();
+        for header in headers.iter() {
+            list.push((ByteString::new(header.header_name().into_bytes()),
+                       ByteString::new(header.header_value().into_bytes())));
+        }
+    }
+
+    fn to_request_headers(self) -> RequestHeaderCollection {
+        let mut headers = RequestHeaderCollection::new();
+        for &(ref name, ref value) in self.header_list.borrow().iter() {
+            // Names are tokens, and so always ASCII.
+            let name = name.as_str().unwrap().to_string();
+            let _ = headers.insert_raw(name, value.as_slice());
+        }
+        headers
+    }
+}
+
+trait PrivateHeadersHelpers {
+    fn check_mutable(self, name: &ByteString, value: Option<&ByteString>) -> Fallible<bool>;
+}
+
+impl<'a> PrivateHeadersHelpers for JSRef<'a, Headers> {
+    /// Steps shared by append, delete and set. Returns whether the header
+    /// list may be changed, or an error if the arguments are invalid.
+    fn check_mutable(self, name: &ByteString, value: Option<&ByteString>) -> Fallible<bool> {
+        if !name.is_token() || value.map_or(false, |v| !v.is_field_value()) {
+            return Err(Syntax);
+        }
+        match self.guard.get() {
+            ImmutableGuard => Err(InvalidState),
+            RequestGuard => Ok(!is_forbidden_header_name(name)),
+            ResponseGuard => Ok(!is_forbidden_response_header_name(name)),
+            NoGuard => Ok(true)
+        }
+    }
+}
+
+impl<'a> HeadersMethods for JSRef<'a, Headers> {
+    // http://fetch.spec.whatwg.org/#dom-headers-append
+    fn Append(self, name: ByteString, value: ByteString) -> ErrorResult {
+        if try!(self.check_mutable(&name, Some(&value))) {
+            self.header_list.borrow_mut().push((name, value));
+        }
+        Ok(())
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-headers-delete
+    fn Delete(self, name: ByteString) -> ErrorResult {
+        if try!(self.check_mutable(&name, None)) {
+            self.header_list.borrow_mut().retain(|&(ref n, _)| !n.eq_ignore_case(&name));
+        }
+        Ok(())
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-headers-get
+    fn Get(self, name: ByteString) -> Fallible<Option<ByteString>> {
+        if !name.is_token() {
+            return Err(Syntax);
+        }
+        Ok(self.header_list.borrow().iter()
+               .find(|&&(ref n, _)| n.eq_ignore_case(&name))
+               .map(|&(_, ref value)| value.clone()))
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-headers-has
+    fn Has(self, name: ByteString) -> Fallible<bool> {
+        if !name.is_token() {
+            return Err(Syntax);
+        }
+        Ok(self.header_list.borrow().iter().any(|&(ref n, _)| n.eq_ignore_case(&name)))
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-headers-set
+    fn Set(self, name: ByteString, value: ByteString) -> ErrorResult {
+        if !try!(self.check_mutable(&name, Some(&value))) {
+            return Ok(());
+        }
+        let mut list = self.header_list.borrow_mut();
+        let old_list = mem::replace(&mut *list, vec!());
+        let mut found = false;
+        for (n, v) in old_list.into_iter() {
+            if !n.eq_ignore_case(&name) {
+                list.push((n, v));
+            } else if !found {
+                list.push((name.clone(), value.clone()));
+                found = true;
+            }
+        }
+        if !found {
+            list.push((name, value));
+        }
+        Ok(())
+    }
+}
+
+impl Reflectable for Headers {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+}
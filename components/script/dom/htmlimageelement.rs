@@ -17,6 +17,7 @@ use dom::eventtarget::{EventTarget, NodeTargetTypeId};
 use dom::htmlelement::HTMLElement;
 use dom::node::{Node, ElementNodeTypeId, NodeHelpers, window_from_node};
 use dom::virtualmethods::VirtualMethods;
+use servo_msg::constellation_msg::PipelineId;
 use servo_net::image_cache_task;
 use servo_util::geometry::to_px;
 use servo_util::str::DOMString;
@@ -60,7 +61,8 @@ impl<'a> PrivateHTMLImageElementHelpers for JSRef<'a, HTMLImageElement> {
 
                 // inform the image cache to load this, but don't store a
                 // handle.
-                image_cache.send(image_cache_task::Prefetch(img_url));
+                let PipelineId(owner) = window.page().id;
+                image_cache.send(image_cache_task::Prefetch(img_url, owner));
             }
         }
     }
@@ -20,8 +20,9 @@ use dom::node::{Node, ElementNodeTypeId, NodeHelpers, window_from_node};
 use dom::validitystate::ValidityState;
 use dom::virtualmethods::VirtualMethods;
 
+use servo_msg::constellation_msg::PipelineId;
 use servo_net::image_cache_task;
-use servo_net::image_cache_task::ImageCacheTask;
+use servo_net::image_cache_task::{ImageCacheTask, ImageOwner};
 use servo_util::str::DOMString;
 use string_cache::Atom;
 
@@ -53,13 +54,13 @@ impl HTMLObjectElement {
 }
 
 trait ProcessDataURL {
-    fn process_data_url(&self, image_cache: ImageCacheTask);
+    fn process_data_url(&self, image_cache: ImageCacheTask, owner: ImageOwner);
 }
 
 impl<'a> ProcessDataURL for JSRef<'a, HTMLObjectElement> {
     // Makes the local `data` member match the status of the `data` attribute and starts
     /// prefetching the image. This method must be called after `data` is changed.
-    fn process_data_url(&self, image_cache: ImageCacheTask) {
+    fn process_data_url(&self, image_cache: ImageCacheTask, owner: ImageOwner) {
         let elem: JSRef<Element> = ElementCast::from_ref(*self);
 
         // TODO: support other values
@@ -69,7 +70,7 @@ impl<'a> ProcessDataURL for JSRef<'a, HTMLObjectElement> {
                 if is_image_data(uri.as_slice()) {
                     let data_url = Url::parse(uri.as_slice()).unwrap();
                     // Issue #84
-                    image_cache.send(image_cache_task::Prefetch(data_url));
+                    image_cache.send(image_cache_task::Prefetch(data_url, owner));
                 }
             }
             _ => { }
@@ -110,7 +111,8 @@ impl<'a> VirtualMethods for JSRef<'a, HTMLObjectElement> {
         match attr.local_name() {
             &atom!("data") => {
                 let window = window_from_node(*self).root();
-                self.process_data_url(window.image_cache_task().clone());
+                let PipelineId(owner) = window.page().id;
+                self.process_data_url(window.image_cache_task().clone(), owner);
             },
             _ => ()
         }
@@ -29,7 +29,7 @@ impl NavigatorInfo {
     }
 
     pub fn UserAgent() -> DOMString {
-        match opts::get().user_agent {
+        match opts::get().network.user_agent {
             Some(ref user_agent) => user_agent.clone(),
             None => "".to_string(),
         }
@@ -223,6 +223,17 @@ impl LayoutDataRef {
         }
     }
 
+    /// Sends the layout data, if present, back to the layout task to be destroyed.
+    pub fn dispose(&self) {
+        let layout_data = LayoutDataRef {
+            data_cell: RefCell::new(self.data_cell.borrow_mut().take()),
+        };
+        match layout_data.take_chan() {
+            None => {}
+            Some(LayoutChan(chan)) => chan.send(ReapLayoutDataMsg(layout_data)),
+        }
+    }
+
     /// Borrows the layout data immutably, *asserting that there are no mutators*. Bad things will
     /// happen if you try to mutate the layout data while this is held. This is the only thread-
     /// safe layout data accessor.
@@ -289,9 +300,14 @@ impl<'a> PrivateNodeHelpers for JSRef<'a, Node> {
     fn node_removed(self, parent_in_doc: bool) {
         assert!(self.parent_node().is_none());
         let document = document_from_node(self).root();
+        let window = window_from_node(self).root();
+        let page = window.page();
 
         for node in self.traverse_preorder() {
             vtable_for(&node).unbind_from_tree(parent_in_doc);
+            // Layout no longer styles the node, so its transitions and animations end.
+            node.layout_data.dispose();
+            page.animating_nodes.borrow_mut().remove(&node.to_untrusted_node_address());
         }
 
         document.content_changed(self);
@@ -444,6 +460,8 @@ pub trait NodeHelpers<'a> {
     fn inclusively_following_siblings(self) -> NodeChildrenIterator<'a>;
 
     fn to_trusted_node_address(self) -> TrustedNodeAddress;
+    /// The address layout refers to this node by in the messages it sends to script.
+    fn to_untrusted_node_address(self) -> UntrustedNodeAddress;
 
     fn get_bounding_content_box(self) -> Rect<Au>;
     fn get_content_boxes(self) -> Vec<Rect<Au>>;
@@ -691,6 +709,10 @@ impl<'a> NodeHelpers<'a> for JSRef<'a, Node> {
         TrustedNodeAddress(self.deref() as *const Node as *const libc::c_void)
     }
 
+    fn to_untrusted_node_address(self) -> UntrustedNodeAddress {
+        self.reflector().get_jsobject() as UntrustedNodeAddress
+    }
+
     fn get_bounding_content_box(self) -> Rect<Au> {
         window_from_node(self).root().page().content_box_query(self.to_trusted_node_address())
     }
@@ -1576,17 +1598,7 @@ impl Node {
 
     /// Sends layout data, if any, back to the layout task to be destroyed.
     unsafe fn reap_layout_data(&mut self) {
-        if self.layout_data.is_present() {
-            let layout_data = mem::replace(&mut self.layout_data, LayoutDataRef::new());
-            let layout_chan = layout_data.take_chan();
-            match layout_chan {
-                None => {}
-                Some(chan) => {
-                    let LayoutChan(chan) = chan;
-                    chan.send(ReapLayoutDataMsg(layout_data))
-                },
-            }
-        }
+        self.layout_data.dispose()
     }
 
     pub fn collect_text_contents<'a, T: Iterator<JSRef<'a, Node>>>(mut iterator: T) -> String {
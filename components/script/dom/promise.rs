@@ -0,0 +1,366 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! ES6 promises. SpiderMonkey does not give us promises yet, so this
+//! implements them as a DOM interface. Reactions always run from the script
+//! task's event loop, never synchronously from `then` or from the code that
+//! settles the promise.
+
+use dom::bindings::callback::RethrowExceptions;
+use dom::bindings::cell::DOMRefCell;
+use dom::bindings::codegen::Bindings::PromiseBinding;
+use dom::bindings::codegen::Bindings::PromiseBinding::{AnyCallback, PromiseInit, PromiseMethods};
+use dom::bindings::conversions::{FromJSValConvertible, ToJSValConvertible};
+use dom::bindings::error::{Fallible, Network};
+use dom::bindings::global::{GlobalField, GlobalRef};
+use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::utils::{NonNullJSNative, Reflectable, Reflector, reflect_dom_object};
+use dom::domexception::DOMException;
+use dom::response::Response;
+use script_task::{ScriptChan, PromiseJobsMsg};
+
+use servo_net::fetch::response::Response as NetResponse;
+
+use js::JS_ARGV;
+use js::jsapi::{JSBool, JSContext, JS_AddObjectRoot, JS_RemoveObjectRoot};
+use js::jsapi::{JS_NewFunction, JS_GetFunctionObject, JS_CallFunctionName};
+use js::jsapi::{JS_GetPendingException, JS_ClearPendingException};
+use js::jsval::{JSVal, NullValue, UndefinedValue};
+
+use libc;
+use libc::{c_uint, c_void};
+use std::cell::Cell;
+
+#[deriving(PartialEq)]
+#[jstraceable]
+enum PromiseState {
+    Pending,
+    Fulfilled,
+    Rejected,
+}
+
+/// The callbacks registered by one call to `then`, and the promise that
+/// call returned.
+#[jstraceable]
+#[must_root]
+struct Reaction {
+    on_fulfilled: Option<AnyCallback>,
+    on_rejected: Option<AnyCallback>,
+    derived: JS<Promise>,
+}
+
+#[dom_struct]
+pub struct Promise {
+    reflector_: Reflector,
+    global: GlobalField,
+    state: Cell<PromiseState>,
+    /// Whether the promise has been resolved or rejected, including by
+    /// adopting the state of another promise that is still pending.
+    is_resolved: Cell<bool>,
+    value: Cell<JSVal>,
+    reactions: DOMRefCell<Vec<Reaction>>,
+    jobs_queued: Cell<bool>,
+    pinned_count: Cell<uint>,
+}
+
+impl Promise {
+    fn new_inherited(global: &GlobalRef) -> Promise {
+        Promise {
+            reflector_: Reflector::new(),
+            global: GlobalField::from_rooted(global),
+            state: Cell::new(Pending),
+            is_resolved: Cell::new(false),
+            value: Cell::new(UndefinedValue()),
+            reactions: DOMRefCell::new(vec!()),
+            jobs_queued: Cell::new(false),
+            pinned_count: Cell::new(0),
+        }
+    }
+
+    pub fn new(global: &GlobalRef) -> Temporary<Promise> {
+        reflect_dom_object(box Promise::new_inherited(global), global, PromiseBinding::Wrap)
+    }
+
+    // https://people.mozilla.org/~jorendorff/es6-draft.html#sec-promise-executor
+    pub fn Constructor(global: &GlobalRef, init: PromiseInit) -> Fallible<Temporary<Promise>> {
+        let promise = Promise::new(global).root();
+        let cx = global.get_cx();
+        let resolve = resolving_function(cx, global, *promise, resolve_native, "resolve");
+        let reject = resolving_function(cx, global, *promise, reject_native, "reject");
+        match init.Call__(resolve, reject, RethrowExceptions) {
+            Ok(()) => {}
+            Err(_) => promise.reject(take_pending_exception(cx)),
+        }
+        Ok(Temporary::from_rooted(*promise))
+    }
+
+    // https://people.mozilla.org/~jorendorff/es6-draft.html#sec-promise.resolve
+    pub fn Resolve(cx: *mut JSContext, global: &GlobalRef, value: JSVal) -> Temporary<Promise> {
+        let existing: Result<JS<Promise>, ()> = FromJSValConvertible::from_jsval(cx, value, ());
+        match existing {
+            Ok(existing) => return Temporary::new(existing),
+            Err(()) => {}
+        }
+        let promise = Promise::new(global).root();
+        promise.resolve(value);
+        Temporary::from_rooted(*promise)
+    }
+
+    // https://people.mozilla.org/~jorendorff/es6-draft.html#sec-promise.reject
+    pub fn Reject(_cx: *mut JSContext, global: &GlobalRef, value: JSVal) -> Temporary<Promise> {
+        let promise = Promise::new(global).root();
+        promise.reject(value);
+        Temporary::from_rooted(*promise)
+    }
+
+    pub fn handle_jobs(addr: TrustedPromiseAddress) {
+        unsafe {
+            let promise = JS::from_trusted_promise_address(addr).root();
+            promise.run_reactions();
+            promise.release_once();
+        }
+    }
+
+    /// Settle the promise of a `fetch()` call with the response the fetch
+    /// task produced.
+    pub fn handle_fetch_response(addr: TrustedPromiseAddress, response: NetResponse) {
+        let promise = unsafe { JS::from_trusted_promise_address(addr).root() };
+        {
+            let global = promise.global.root();
+            let cx = global.root_ref().get_cx();
+            if response.is_network_error() {
+                let error = DOMException::new_from_error(&global.root_ref(), Network).root();
+                promise.reject(error.to_jsval(cx));
+            } else {
+                let dom_response = Response::new(&global.root_ref(), response).root();
+                promise.resolve(dom_response.to_jsval(cx));
+            }
+        }
+        promise.release_once();
+    }
+}
+
+/// Creates one of the resolving functions handed to a promise's executor.
+/// The function is bound to the promise, which the native receives as its
+/// first argument.
+fn resolving_function(cx: *mut JSContext, global: &GlobalRef, promise: JSRef<Promise>,
+                      native: NonNullJSNative, name: &str) -> JSVal {
+    let name = name.to_c_str();
+    let bind = "bind".to_c_str();
+    unsafe {
+        let fun = JS_NewFunction(cx, Some(native), 1, 0,
+                                 global.reflector().get_jsobject(), name.as_ptr());
+        assert!(fun.is_not_null());
+        let fun = JS_GetFunctionObject(fun);
+        assert!(fun.is_not_null());
+        let mut args = [NullValue(), promise.to_jsval(cx)];
+        let mut bound = UndefinedValue();
+        assert!(JS_CallFunctionName(cx, fun, bind.as_ptr(), 2, args.as_mut_ptr(), &mut bound) != 0);
+        bound
+    }
+}
+
+unsafe extern "C" fn resolve_native(cx: *mut JSContext, argc: c_uint, vp: *mut JSVal) -> JSBool {
+    settle_from_native(cx, argc, vp, true)
+}
+
+unsafe extern "C" fn reject_native(cx: *mut JSContext, argc: c_uint, vp: *mut JSVal) -> JSBool {
+    settle_from_native(cx, argc, vp, false)
+}
+
+unsafe fn settle_from_native(cx: *mut JSContext, argc: c_uint, vp: *mut JSVal,
+                             fulfill: bool) -> JSBool {
+    let argv = JS_ARGV(cx, vp);
+    let value = if argc > 1 { *argv.offset(1) } else { UndefinedValue() };
+    let promise: Result<JS<Promise>, ()> = if argc > 0 {
+        FromJSValConvertible::from_jsval(cx, *argv, ())
+    } else {
+        Err(())
+    };
+    match promise {
+        Ok(promise) => {
+            let promise = promise.root();
+            if fulfill {
+                promise.resolve(value);
+            } else {
+                promise.reject(value);
+            }
+        }
+        Err(()) => {}
+    }
+    *vp = UndefinedValue();
+    1
+}
+
+/// Takes the exception a callback left pending on `cx`, so that it can be
+/// used as a rejection reason.
+fn take_pending_exception(cx: *mut JSContext) -> JSVal {
+    let mut exception = UndefinedValue();
+    unsafe {
+        if JS_GetPendingException(cx, &mut exception) != 0 {
+            JS_ClearPendingException(cx);
+        }
+    }
+    exception
+}
+
+pub trait PromiseHelpers {
+    fn resolve(self, value: JSVal);
+    fn reject(self, value: JSVal);
+    unsafe fn to_trusted(self) -> TrustedPromiseAddress;
+    fn release_once(self);
+}
+
+impl<'a> PromiseHelpers for JSRef<'a, Promise> {
+    #[allow(unrooted_must_root)]
+    fn resolve(self, value: JSVal) {
+        if self.is_resolved.get() {
+            return;
+        }
+        self.is_resolved.set(true);
+        // A promise resolved with another promise takes on its eventual state.
+        let cx = self.global.root().root_ref().get_cx();
+        let other: Result<JS<Promise>, ()> = FromJSValConvertible::from_jsval(cx, value, ());
+        match other {
+            Ok(ref other) if *other == JS::from_rooted(self) => {
+                self.settle(Rejected, UndefinedValue())
+            }
+            Ok(other) => {
+                let other = other.root();
+                other.reactions.borrow_mut().push(Reaction {
+                    on_fulfilled: None,
+                    on_rejected: None,
+                    derived: JS::from_rooted(self),
+                });
+                if other.state.get() != Pending {
+                    other.queue_jobs();
+                }
+            }
+            Err(()) => self.settle(Fulfilled, value)
+        }
+    }
+
+    fn reject(self, value: JSVal) {
+        if self.is_resolved.get() {
+            return;
+        }
+        self.is_resolved.set(true);
+        self.settle(Rejected, value);
+    }
+
+    // Creates a trusted address to the object, and roots it. Always pair this with a release()
+    unsafe fn to_trusted(self) -> TrustedPromiseAddress {
+        if self.pinned_count.get() == 0 {
+            JS_AddObjectRoot(self.global.root().root_ref().get_cx(), self.reflector().rootable());
+        }
+        let pinned_count = self.pinned_count.get();
+        self.pinned_count.set(pinned_count + 1);
+        TrustedPromiseAddress(self.deref() as *const Promise as *const libc::c_void)
+    }
+
+    fn release_once(self) {
+        assert!(self.pinned_count.get() > 0)
+        let pinned_count = self.pinned_count.get();
+        self.pinned_count.set(pinned_count - 1);
+        if self.pinned_count.get() == 0 {
+            unsafe {
+                JS_RemoveObjectRoot(self.global.root().root_ref().get_cx(), self.reflector().rootable());
+            }
+        }
+    }
+}
+
+trait PrivatePromiseHelpers {
+    fn settle(self, state: PromiseState, value: JSVal);
+    fn queue_jobs(self);
+    fn run_reactions(self);
+}
+
+impl<'a> PrivatePromiseHelpers for JSRef<'a, Promise> {
+    fn settle(self, state: PromiseState, value: JSVal) {
+        self.state.set(state);
+        self.value.set(value);
+        if !self.reactions.borrow().is_empty() {
+            self.queue_jobs();
+        }
+    }
+
+    /// Ask the event loop to run the reactions. The promise stays pinned
+    /// until they have run.
+    fn queue_jobs(self) {
+        if self.jobs_queued.get() {
+            return;
+        }
+        self.jobs_queued.set(true);
+        let addr = unsafe { self.to_trusted() };
+        let global = self.global.root();
+        let global_ref = global.root_ref();
+        let ScriptChan(ref chan) = *global_ref.script_chan();
+        chan.send(PromiseJobsMsg(addr));
+    }
+
+    #[allow(unrooted_must_root)]
+    fn run_reactions(self) {
+        self.jobs_queued.set(false);
+        let fulfilled = self.state.get() == Fulfilled;
+        let value = self.value.get();
+        loop {
+            // Take one reaction at a time, so that each derived promise is
+            // rooted before any script runs.
+            let reaction = {
+                let mut reactions = self.reactions.borrow_mut();
+                if reactions.is_empty() {
+                    break;
+                }
+                reactions.remove(0).unwrap()
+            };
+            let derived = reaction.derived.root();
+            let handler = if fulfilled { reaction.on_fulfilled } else { reaction.on_rejected };
+            match handler {
+                Some(callback) => match callback.Call__(value, RethrowExceptions) {
+                    Ok(result) => derived.resolve(result),
+                    Err(_) => {
+                        let cx = self.global.root().root_ref().get_cx();
+                        derived.reject(take_pending_exception(cx));
+                    }
+                },
+                // Settle directly, as a promise adopting this one's state
+                // already counts as resolved.
+                None if fulfilled => derived.settle(Fulfilled, value),
+                None => derived.settle(Rejected, value),
+            }
+        }
+    }
+}
+
+impl<'a> PromiseMethods for JSRef<'a, Promise> {
+    // https://people.mozilla.org/~jorendorff/es6-draft.html#sec-promise.prototype.then
+    fn Then(self, on_fulfilled: Option<AnyCallback>,
+            on_rejected: Option<AnyCallback>) -> Temporary<Promise> {
+        let global = self.global.root();
+        let derived = Promise::new(&global.root_ref()).root();
+        self.reactions.borrow_mut().push(Reaction {
+            on_fulfilled: on_fulfilled,
+            on_rejected: on_rejected,
+            derived: JS::from_rooted(*derived),
+        });
+        if self.state.get() != Pending {
+            self.queue_jobs();
+        }
+        Temporary::from_rooted(*derived)
+    }
+
+    // https://people.mozilla.org/~jorendorff/es6-draft.html#sec-promise.prototype.catch
+    fn Catch(self, on_rejected: Option<AnyCallback>) -> Temporary<Promise> {
+        self.Then(None, on_rejected)
+    }
+}
+
+impl Reflectable for Promise {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+}
+
+pub struct TrustedPromiseAddress(pub *const c_void);
@@ -0,0 +1,231 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::Bindings::HeadersBinding::HeadersMethods;
+use dom::bindings::codegen::Bindings::RequestBinding;
+use dom::bindings::codegen::Bindings::RequestBinding::{RequestInit, RequestMethods};
+use dom::bindings::codegen::Bindings::RequestBinding::{RequestCredentials, RequestCredentialsValues};
+use dom::bindings::codegen::Bindings::RequestBinding::{RequestMode, RequestModeValues};
+use dom::bindings::codegen::UnionTypes::RequestOrString::{RequestOrString, eRequest, eString};
+use dom::bindings::error::{Fallible, InvalidState, Security, Syntax};
+use dom::bindings::global::GlobalRef;
+use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::str::ByteString;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::promise::{Promise, PromiseHelpers};
+use dom::headers::{Headers, HeadersHelpers, RequestGuard};
+use script_task::{ScriptChan, FetchResponseMsg};
+
+use servo_net::fetch::request::{Fetch, RefererUrl};
+use servo_net::fetch::request::{SameOrigin, NoCORS, CORSMode};
+use servo_net::fetch::request::{Omit, CredentialsSameOrigin, Include};
+use servo_net::fetch::request::Request as NetRequest;
+
+use http::method::{Method, Get, Head, Connect, Trace, ExtensionMethod};
+use servo_util::str::DOMString;
+use servo_util::task::spawn_named;
+
+use std::ascii::AsciiExt;
+use std::cell::Cell;
+use url::{Url, UrlParser};
+
+#[dom_struct]
+pub struct Request {
+    reflector_: Reflector,
+    method: Method,
+    url: Url,
+    headers: JS<Headers>,
+    mode: RequestMode,
+    credentials: RequestCredentials,
+    body: Option<Vec<u8>>,
+    body_used: Cell<bool>,
+}
+
+impl Request {
+    fn new_inherited(method: Method, url: Url, headers: JSRef<Headers>, mode: RequestMode,
+                     credentials: RequestCredentials, body: Option<Vec<u8>>) -> Request {
+        Request {
+            reflector_: Reflector::new(),
+            method: method,
+            url: url,
+            headers: JS::from_rooted(headers),
+            mode: mode,
+            credentials: credentials,
+            body: body,
+            body_used: Cell::new(false),
+        }
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-request
+    pub fn Constructor(global: &GlobalRef, input: RequestOrString, init: &RequestInit)
+                       -> Fallible<Temporary<Request>> {
+        let headers = Headers::new(global, RequestGuard).root();
+        let (mut method, url, mut mode, mut credentials, mut body, input_headers) = match input {
+            eRequest(request) => {
+                let request = request.root();
+                if request.body_used.get() {
+                    return Err(InvalidState);
+                }
+                if request.body.is_some() {
+                    request.body_used.set(true);
+                }
+                (request.method.clone(), request.url.clone(), request.mode,
+                 request.credentials, request.body.clone(), Some(request.headers.clone()))
+            }
+            eString(url) => {
+                let base = global.get_url();
+                let url = match UrlParser::new().base_url(&base).parse(url.as_slice()) {
+                    Ok(url) => url,
+                    Err(_) => return Err(Syntax)
+                };
+                (Get, url, RequestModeValues::Cors, RequestCredentialsValues::Omit, None, None)
+            }
+        };
+
+        match init.method {
+            Some(ref m) => method = try!(normalize_method(m)),
+            None => {}
+        }
+        match init.mode {
+            Some(m) => mode = m,
+            None => {}
+        }
+        match init.credentials {
+            Some(c) => credentials = c,
+            None => {}
+        }
+        match (&init.headers, input_headers) {
+            (&Some(ref init_headers), _) => try!(headers.fill(global, init_headers)),
+            (&None, Some(input_headers)) => headers.copy_from(*input_headers.root()),
+            (&None, None) => {}
+        }
+        match init.body {
+            Some(ref b) => {
+                if method == Get || method == Head {
+                    return Err(Syntax);
+                }
+                body = Some(b.clone().into_bytes());
+                let content_type = ByteString::new(b"Content-Type".to_vec());
+                if !try!(headers.Has(content_type.clone())) {
+                    try!(headers.Append(content_type,
+                                        ByteString::new(b"text/plain;charset=UTF-8".to_vec())));
+                }
+            }
+            None => {}
+        }
+
+        Ok(reflect_dom_object(box Request::new_inherited(method, url, *headers, mode, credentials, body),
+                              global, RequestBinding::Wrap))
+    }
+}
+
+/// Steps 1-3 of http://fetch.spec.whatwg.org/#dom-request for `init.method`.
+fn normalize_method(method: &ByteString) -> Fallible<Method> {
+    if !method.is_token() {
+        return Err(Syntax);
+    }
+    let name = match method.as_str() {
+        Some(name) => name,
+        None => return Err(Syntax)
+    };
+    let upper = name.to_ascii_upper();
+    let name = match upper.as_slice() {
+        "DELETE" | "GET" | "HEAD" | "OPTIONS" |
+        "POST" | "PUT" | "CONNECT" | "TRACE" |
+        "TRACK" => upper.as_slice(),
+        _ => name
+    };
+    match Method::from_str_or_new(name) {
+        Some(Connect) | Some(Trace) => Err(Security),
+        Some(ExtensionMethod(ref t)) if t.as_slice() == "TRACK" => Err(Security),
+        Some(m) => Ok(m),
+        None => Err(Syntax)
+    }
+}
+
+pub trait RequestHelpers {
+    fn fetch(self, global: &GlobalRef) -> Fallible<Temporary<Promise>>;
+}
+
+impl<'a> RequestHelpers for JSRef<'a, Request> {
+    /// http://fetch.spec.whatwg.org/#dom-global-fetch
+    /// The fetch itself runs on its own task; the returned promise settles
+    /// once the script task receives its result.
+    fn fetch(self, global: &GlobalRef) -> Fallible<Temporary<Promise>> {
+        if self.body_used.get() {
+            return Err(InvalidState);
+        }
+        if self.body.is_some() {
+            self.body_used.set(true);
+        }
+
+        let promise = Promise::new(global).root();
+        let addr = unsafe { promise.to_trusted() };
+
+        let url = self.url.clone();
+        let method = self.method.clone();
+        let headers = self.headers.root().to_request_headers();
+        let body = self.body.clone();
+        let mode = match self.mode {
+            RequestModeValues::Same_origin => SameOrigin,
+            RequestModeValues::No_cors => NoCORS,
+            RequestModeValues::Cors => CORSMode,
+        };
+        let credentials_mode = match self.credentials {
+            RequestCredentialsValues::Omit => Omit,
+            RequestCredentialsValues::Same_origin => CredentialsSameOrigin,
+            RequestCredentialsValues::Include => Include,
+        };
+        let client_url = global.get_url();
+        let resource_task = global.resource_task();
+        let script_chan = global.script_chan().clone();
+        spawn_named("FetchTask", proc() {
+            let mut request = NetRequest::new(url, Fetch);
+            request.method = method;
+            request.headers = headers;
+            request.body = body;
+            request.unsafe_request = true;
+            request.mode = mode;
+            request.credentials_mode = credentials_mode;
+            request.origin = Some(client_url.clone());
+            request.referer = RefererUrl(client_url);
+            let response = request.fetch(&resource_task, false);
+            let ScriptChan(ref chan) = script_chan;
+            chan.send(FetchResponseMsg(addr, response));
+        });
+        Ok(Temporary::from_rooted(*promise))
+    }
+}
+
+impl<'a> RequestMethods for JSRef<'a, Request> {
+    fn Method(self) -> ByteString {
+        ByteString::new(self.method.http_value().into_bytes())
+    }
+
+    fn Url(self) -> DOMString {
+        self.url.serialize()
+    }
+
+    fn Headers(self) -> Temporary<Headers> {
+        Temporary::new(self.headers.clone())
+    }
+
+    fn Mode(self) -> RequestMode {
+        self.mode
+    }
+
+    fn Credentials(self) -> RequestCredentials {
+        self.credentials
+    }
+
+    fn BodyUsed(self) -> bool {
+        self.body_used.get()
+    }
+}
+
+impl Reflectable for Request {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+}
@@ -0,0 +1,165 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::Bindings::ResponseBinding;
+use dom::bindings::codegen::Bindings::ResponseBinding::{ResponseMethods, ResponseType, ResponseTypeValues};
+use dom::bindings::conversions::ToJSValConvertible;
+use dom::bindings::error::{Fallible, InvalidState, Syntax};
+use dom::bindings::global::{GlobalField, GlobalRef};
+use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::str::ByteString;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::domexception::DOMException;
+use dom::promise::{Promise, PromiseHelpers};
+use dom::headers::{Headers, HeadersHelpers, ImmutableGuard};
+
+use servo_net::fetch::response::{Basic, CORS, Opaque};
+use servo_net::fetch::response::Default as DefaultResponse;
+use servo_net::fetch::response::Error as ErrorResponse;
+use servo_net::fetch::response::{Done, Receiving};
+use servo_net::fetch::response::Response as NetResponse;
+
+use encoding::all::UTF_8;
+use encoding::types::{DecodeReplace, Encoding};
+use js::jsapi::{JS_ParseJSON, JS_ClearPendingException};
+use js::jsval::{JSVal, UndefinedValue};
+use servo_util::str::DOMString;
+
+use std::cell::Cell;
+
+#[dom_struct]
+pub struct Response {
+    reflector_: Reflector,
+    global: GlobalField,
+    response_type: ResponseType,
+    url: DOMString,
+    status: u16,
+    status_text: ByteString,
+    headers: JS<Headers>,
+    body: Vec<u8>,
+    body_used: Cell<bool>,
+}
+
+impl Response {
+    fn new_inherited(global: &GlobalRef, headers: JSRef<Headers>, response: NetResponse) -> Response {
+        let response_type = match response.response_type {
+            Basic => ResponseTypeValues::Basic,
+            CORS => ResponseTypeValues::Cors,
+            DefaultResponse => ResponseTypeValues::Default,
+            ErrorResponse => ResponseTypeValues::Error,
+            Opaque => ResponseTypeValues::Opaque,
+        };
+        let body = match response.body {
+            Done(bytes) | Receiving(bytes) => bytes,
+            _ => vec!()
+        };
+        Response {
+            reflector_: Reflector::new(),
+            global: GlobalField::from_rooted(global),
+            response_type: response_type,
+            url: response.url.map_or("".to_string(), |url| url.serialize()),
+            status: response.status.code(),
+            status_text: ByteString::new(response.status.reason().into_bytes()),
+            headers: JS::from_rooted(headers),
+            body: body,
+            body_used: Cell::new(false),
+        }
+    }
+
+    pub fn new(global: &GlobalRef, response: NetResponse) -> Temporary<Response> {
+        let headers = Headers::new(global, ImmutableGuard).root();
+        headers.fill_from_response(&response.headers);
+        reflect_dom_object(box Response::new_inherited(global, *headers, response),
+                           global, ResponseBinding::Wrap)
+    }
+}
+
+trait PrivateResponseHelpers {
+    fn consume_body(self, parse: |&[u8]| -> Result<JSVal, ()>) -> Fallible<Temporary<Promise>>;
+}
+
+impl<'a> PrivateResponseHelpers for JSRef<'a, Response> {
+    /// http://fetch.spec.whatwg.org/#concept-body-consume-body
+    /// The body has already been read in full, so the returned promise is
+    /// settled straight away; its callbacks still run asynchronously.
+    fn consume_body(self, parse: |&[u8]| -> Result<JSVal, ()>) -> Fallible<Temporary<Promise>> {
+        let global = self.global.root();
+        let promise = Promise::new(&global.root_ref()).root();
+        let cx = global.root_ref().get_cx();
+        if self.body_used.get() {
+            let error = DOMException::new_from_error(&global.root_ref(), InvalidState).root();
+            promise.reject(error.to_jsval(cx));
+            return Ok(Temporary::from_rooted(*promise));
+        }
+        self.body_used.set(true);
+        match parse(self.body.as_slice()) {
+            Ok(value) => promise.resolve(value),
+            Err(()) => {
+                let error = DOMException::new_from_error(&global.root_ref(), Syntax).root();
+                promise.reject(error.to_jsval(cx));
+            }
+        }
+        Ok(Temporary::from_rooted(*promise))
+    }
+}
+
+impl<'a> ResponseMethods for JSRef<'a, Response> {
+    fn Type(self) -> ResponseType {
+        self.response_type
+    }
+
+    fn Url(self) -> DOMString {
+        self.url.clone()
+    }
+
+    fn Status(self) -> u16 {
+        self.status
+    }
+
+    fn Ok(self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    fn StatusText(self) -> ByteString {
+        self.status_text.clone()
+    }
+
+    fn Headers(self) -> Temporary<Headers> {
+        Temporary::new(self.headers.clone())
+    }
+
+    fn BodyUsed(self) -> bool {
+        self.body_used.get()
+    }
+
+    fn Text(self) -> Fallible<Temporary<Promise>> {
+        let cx = self.global.root().root_ref().get_cx();
+        self.consume_body(|body| {
+            let text = UTF_8.decode(body, DecodeReplace).unwrap();
+            Ok(text.to_jsval(cx))
+        })
+    }
+
+    fn Json(self) -> Fallible<Temporary<Promise>> {
+        let cx = self.global.root().root_ref().get_cx();
+        self.consume_body(|body| {
+            let decoded = UTF_8.decode(body, DecodeReplace).unwrap();
+            let decoded: Vec<u16> = decoded.as_slice().utf16_units().collect();
+            let mut vp = UndefinedValue();
+            unsafe {
+                if JS_ParseJSON(cx, decoded.as_ptr(), decoded.len() as u32, &mut vp) == 0 {
+                    JS_ClearPendingException(cx);
+                    return Err(());
+                }
+            }
+            Ok(vp)
+        })
+    }
+}
+
+impl Reflectable for Response {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+}
@@ -7,9 +7,9 @@ use dom::bindings::codegen::Bindings::StorageBinding::StorageMethods;
 use dom::bindings::global::{GlobalRef, GlobalField};
 use dom::bindings::js::{JSRef, Temporary};
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
-use dom::bindings::error::Fallible;
+use dom::bindings::error::{ErrorResult, QuotaExceeded};
 use servo_util::str::DOMString;
-use servo_net::storage_task::StorageTask;
+use servo_net::storage_task::{StorageTask, StorageType, StorageNotification};
 use servo_net::storage_task::StorageTaskMsg;
 use std::comm::channel;
 use url::Url;
@@ -18,22 +18,20 @@ use url::Url;
 pub struct Storage {
     reflector_: Reflector,
     global: GlobalField,
+    storage_type: StorageType,
 }
 
 impl Storage {
-    fn new_inherited(global: &GlobalRef) -> Storage {
+    fn new_inherited(global: &GlobalRef, storage_type: StorageType) -> Storage {
         Storage {
             reflector_: Reflector::new(),
             global: GlobalField::from_rooted(global),
+            storage_type: storage_type,
         }
     }
 
-    pub fn new(global: &GlobalRef) -> Temporary<Storage> {
-        reflect_dom_object(box Storage::new_inherited(global), global, StorageBinding::Wrap)
-    }
-
-    pub fn Constructor(global: &GlobalRef) -> Fallible<Temporary<Storage>> {
-        Ok(Storage::new(global))
+    pub fn new(global: &GlobalRef, storage_type: StorageType) -> Temporary<Storage> {
+        reflect_dom_object(box Storage::new_inherited(global, storage_type), global, StorageBinding::Wrap)
     }
 
     fn get_url(&self) -> Url {
@@ -48,27 +46,46 @@ impl Storage {
         global_ref.as_window().storage_task()
     }
 
+    /// https://html.spec.whatwg.org/multipage/webstorage.html#send-a-storage-notification
+    /// The storage task passes the notification on to every script task, whose event loops fire
+    /// the events at the other windows of the same origin.
+    fn send_storage_notification(&self, key: Option<DOMString>, old_value: Option<DOMString>,
+                                 new_value: Option<DOMString>) {
+        let global_root = self.global.root();
+        let global_ref = global_root.root_ref();
+        let window = global_ref.as_window();
+        self.get_storage_task().send(StorageTaskMsg::Notify(StorageNotification {
+            source: window.page().id,
+            storage_type: self.storage_type,
+            url: self.get_url(),
+            key: key,
+            old_value: old_value,
+            new_value: new_value,
+        }));
+    }
 }
 
 impl<'a> StorageMethods for JSRef<'a, Storage> {
     fn Length(self) -> u32 {
         let (sender, receiver) = channel();
 
-        self.get_storage_task().send(StorageTaskMsg::Length(sender, self.get_url()));
+        self.get_storage_task().send(StorageTaskMsg::Length(sender, self.get_url(), self.storage_type));
         receiver.recv()
     }
 
     fn Key(self, index: u32) -> Option<DOMString> {
         let (sender, receiver) = channel();
 
-        self.get_storage_task().send(StorageTaskMsg::Key(sender, self.get_url(), index));
+        self.get_storage_task().send(StorageTaskMsg::Key(sender, self.get_url(), self.storage_type,
+                                                         index));
         receiver.recv()
     }
 
     fn GetItem(self, name: DOMString) -> Option<DOMString> {
         let (sender, receiver) = channel();
 
-        self.get_storage_task().send(StorageTaskMsg::GetItem(sender, self.get_url(), name));
+        self.get_storage_task().send(StorageTaskMsg::GetItem(sender, self.get_url(), self.storage_type,
+                                                             name));
         receiver.recv()
     }
 
@@ -78,29 +95,37 @@ impl<'a> StorageMethods for JSRef<'a, Storage> {
         item
     }
 
-    fn SetItem(self, name: DOMString, value: DOMString) {
+    fn SetItem(self, name: DOMString, value: DOMString) -> ErrorResult {
         let (sender, receiver) = channel();
 
-        self.get_storage_task().send(StorageTaskMsg::SetItem(sender, self.get_url(), name, value));
-        if receiver.recv() {
-            //TODO send notification
+        self.get_storage_task().send(StorageTaskMsg::SetItem(sender, self.get_url(), self.storage_type,
+                                                             name.clone(), value.clone()));
+        match receiver.recv() {
+            Ok((true, old_value)) => {
+                self.send_storage_notification(Some(name), old_value, Some(value));
+                Ok(())
+            }
+            Ok((false, _)) => Ok(()),
+            Err(()) => Err(QuotaExceeded)
         }
     }
 
-    fn NamedSetter(self, name: DOMString, value: DOMString) {
-        self.SetItem(name, value);
+    fn NamedSetter(self, name: DOMString, value: DOMString) -> ErrorResult {
+        self.SetItem(name, value)
     }
 
-    fn NamedCreator(self, name: DOMString, value: DOMString) {
-        self.SetItem(name, value);
+    fn NamedCreator(self, name: DOMString, value: DOMString) -> ErrorResult {
+        self.SetItem(name, value)
     }
 
     fn RemoveItem(self, name: DOMString) {
         let (sender, receiver) = channel();
 
-        self.get_storage_task().send(StorageTaskMsg::RemoveItem(sender, self.get_url(), name));
-        if receiver.recv() {
-            //TODO send notification
+        self.get_storage_task().send(StorageTaskMsg::RemoveItem(sender, self.get_url(), self.storage_type,
+                                                                name.clone()));
+        match receiver.recv() {
+            Some(old_value) => self.send_storage_notification(Some(name), Some(old_value), None),
+            None => ()
         }
     }
 
@@ -111,9 +136,9 @@ impl<'a> StorageMethods for JSRef<'a, Storage> {
     fn Clear(self) {
         let (sender, receiver) = channel();
 
-        self.get_storage_task().send(StorageTaskMsg::Clear(sender, self.get_url()));
+        self.get_storage_task().send(StorageTaskMsg::Clear(sender, self.get_url(), self.storage_type));
         if receiver.recv() {
-            //TODO send notification
+            self.send_storage_notification(None, None, None);
         }
     }
 }
@@ -10,7 +10,7 @@ use dom::bindings::codegen::Bindings::FunctionBinding::Function;
 use dom::bindings::codegen::UnionTypes::BlobOrString::BlobOrString;
 use dom::bindings::codegen::UnionTypes::EventOrString::{EventOrString, eString};
 use dom::bindings::codegen::UnionTypes::HTMLElementOrLong::{HTMLElementOrLong, eLong};
-use dom::bindings::global::GlobalField;
+use dom::bindings::global::{GlobalField, GlobalRef};
 use dom::bindings::js::{JSRef, Temporary};
 use dom::bindings::str::ByteString;
 use dom::bindings::utils::{Reflector, Reflectable};
@@ -57,7 +57,7 @@ impl<'a> TestBindingMethods for JSRef<'a, TestBinding> {
     fn SetEnumAttribute(self, _: TestEnum) {}
     fn InterfaceAttribute(self) -> Temporary<Blob> {
         let global = self.global.root();
-        Blob::new(&global.root_ref())
+        Blob::new(&global.root_ref(), vec!(), "".to_string())
     }
     fn SetInterfaceAttribute(self, _: JSRef<Blob>) {}
     fn UnionAttribute(self) -> HTMLElementOrLong { eLong(0) }
@@ -96,7 +96,7 @@ impl<'a> TestBindingMethods for JSRef<'a, TestBinding> {
     fn GetEnumAttributeNullable(self) -> Option<TestEnum> { Some(_empty) }
     fn GetInterfaceAttributeNullable(self) -> Option<Temporary<Blob>> {
         let global = self.global.root();
-        Some(Blob::new(&global.root_ref()))
+        Some(Blob::new(&global.root_ref(), vec!(), "".to_string()))
     }
     fn SetInterfaceAttributeNullable(self, _: Option<JSRef<Blob>>) {}
     fn GetUnionAttributeNullable(self) -> Option<HTMLElementOrLong> { Some(eLong(0)) }
@@ -120,7 +120,7 @@ impl<'a> TestBindingMethods for JSRef<'a, TestBinding> {
     fn ReceiveEnum(self) -> TestEnum { _empty }
     fn ReceiveInterface(self) -> Temporary<Blob> {
         let global = self.global.root();
-        Blob::new(&global.root_ref())
+        Blob::new(&global.root_ref(), vec!(), "".to_string())
     }
     fn ReceiveAny(self, _: *mut JSContext) -> JSVal { NullValue() }
     fn ReceiveUnion(self) -> HTMLElementOrLong { eLong(0) }
@@ -142,7 +142,7 @@ impl<'a> TestBindingMethods for JSRef<'a, TestBinding> {
     fn ReceiveNullableEnum(self) -> Option<TestEnum> { Some(_empty) }
     fn ReceiveNullableInterface(self) -> Option<Temporary<Blob>> {
         let global = self.global.root();
-        Some(Blob::new(&global.root_ref()))
+        Some(Blob::new(&global.root_ref(), vec!(), "".to_string()))
     }
     fn ReceiveNullableUnion(self) -> Option<HTMLElementOrLong> { Some(eLong(0)) }
     fn ReceiveNullableUnion2(self) -> Option<EventOrString> { Some(eString("".to_string())) }
@@ -299,9 +299,9 @@ impl<'a> TestBindingMethods for JSRef<'a, TestBinding> {
 }
 
 impl TestBinding {
-    pub fn BooleanAttributeStatic() -> bool { false }
-    pub fn SetBooleanAttributeStatic(_: bool) {}
-    pub fn ReceiveVoidStatic() {}
+    pub fn BooleanAttributeStatic(_: &GlobalRef) -> bool { false }
+    pub fn SetBooleanAttributeStatic(_: &GlobalRef, _: bool) {}
+    pub fn ReceiveVoidStatic(_: &GlobalRef) {}
 }
 
 impl Reflectable for TestBinding {
@@ -0,0 +1,50 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::global::GlobalRef;
+use dom::bindings::js::JSRef;
+use dom::bindings::utils::{Reflectable, Reflector};
+use dom::blob::Blob;
+
+use servo_net::blob_loader::{blob_url_origin, new_blob_url};
+use servo_net::fetch::request::serialize_origin;
+use servo_net::resource_task::{AddBlobUrl, RevokeBlobUrl};
+use servo_util::str::DOMString;
+
+use url::Url;
+use uuid;
+
+#[dom_struct]
+pub struct URL {
+    reflector_: Reflector,
+}
+
+impl URL {
+    // http://dev.w3.org/2006/webapi/FileAPI/#dfn-createObjectURL
+    pub fn CreateObjectURL(global: &GlobalRef, blob: JSRef<Blob>) -> DOMString {
+        let id = uuid::Uuid::new_v4().to_hyphenated_string();
+        let url = new_blob_url(&global.get_url(), id.as_slice());
+        global.resource_task().send(AddBlobUrl(url.clone(), blob.data()));
+        global.add_blob_url(url.clone());
+        url.serialize()
+    }
+
+    // http://dev.w3.org/2006/webapi/FileAPI/#dfn-revokeObjectURL
+    pub fn RevokeObjectURL(global: &GlobalRef, url: DOMString) {
+        // URLs that are not blob: URLs of this origin are ignored.
+        let url = match Url::parse(url.as_slice()) {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        if blob_url_origin(&url) == Some(serialize_origin(&global.get_url())) {
+            global.resource_task().send(RevokeBlobUrl(url));
+        }
+    }
+}
+
+impl Reflectable for URL {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+}
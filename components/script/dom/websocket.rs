@@ -2,37 +2,281 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use dom::bindings::cell::DOMRefCell;
+use dom::bindings::codegen::Bindings::EventHandlerBinding::EventHandlerNonNull;
 use dom::bindings::codegen::Bindings::WebSocketBinding;
-use dom::bindings::codegen::Bindings::WebSocketBinding::WebSocketMethods;
-use dom::bindings::error::Fallible;
-use dom::bindings::global::GlobalRef;
-use dom::bindings::js::{Temporary, JSRef};
+use dom::bindings::codegen::Bindings::WebSocketBinding::{WebSocketMethods, BinaryType, BinaryTypeValues};
+use dom::bindings::codegen::InheritTypes::{EventCast, EventTargetCast};
+use dom::bindings::conversions::{FromJSValConvertible, ToJSValConvertible};
+use dom::bindings::error::{ErrorResult, Fallible, InvalidAccess, InvalidState, JSFailed, Syntax};
+use dom::bindings::global::{GlobalRef, GlobalField};
+use dom::bindings::js::{JS, JSRef, Temporary};
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
-use dom::eventtarget::{EventTarget, WebSocketTypeId};
+use dom::blob::Blob;
+use dom::closeevent::CloseEvent;
+use dom::event::{Event, DoesNotBubble, NotCancelable};
+use dom::eventtarget::{EventTarget, EventTargetHelpers, WebSocketTypeId};
+use dom::messageevent::MessageEvent;
+use script_task::{ScriptChan, WebSocketEventMsg};
+
+use servo_net::websocket;
+use servo_net::websocket::{WebSocketConnection, WebSocketEvent};
+use servo_net::websocket::{Opened, TextMessage, BinaryMessage, Failed};
+use servo_net::websocket::Closed as ConnectionClosed;
+use servo_net::fetch::request::serialize_origin;
 use servo_util::str::DOMString;
+use servo_util::task::spawn_named;
+
+use js::jsapi::{JSContext, JS_AddObjectRoot, JS_RemoveObjectRoot};
+use js::jsfriendapi::bindgen::{JS_NewArrayBuffer, JS_GetArrayBufferData};
+use js::jsfriendapi::bindgen::{JS_IsArrayBufferObject, JS_GetArrayBufferByteLength};
+use js::jsfriendapi::bindgen::{JS_IsArrayBufferViewObject, JS_GetArrayBufferViewByteLength};
+use js::jsfriendapi::bindgen::JS_GetArrayBufferViewData;
+use js::jsval::{JSVal, ObjectValue};
+use url::{Url, UrlParser};
+
+use libc;
+use libc::c_void;
+use std::cell::Cell;
+use std::default::Default;
+use std::ptr;
+use std::slice;
+
+#[deriving(PartialEq)]
+#[jstraceable]
+enum WebSocketRequestState {
+    Connecting = 0,
+    Open = 1,
+    Closing = 2,
+    Closed = 3,
+}
 
 #[dom_struct]
 pub struct WebSocket {
     eventtarget: EventTarget,
-    url: DOMString
+    global: GlobalField,
+    url: Url,
+    ready_state: Cell<WebSocketRequestState>,
+    protocol: DOMRefCell<DOMString>,
+    binary_type: Cell<BinaryType>,
+    connection: DOMRefCell<Option<WebSocketConnection>>,
+    pinned_count: Cell<uint>,
 }
 
 impl WebSocket {
-    pub fn new_inherited(url: DOMString) -> WebSocket {
+    fn new_inherited(global: &GlobalRef, url: Url) -> WebSocket {
         WebSocket {
             eventtarget: EventTarget::new_inherited(WebSocketTypeId),
-            url: url
+            global: GlobalField::from_rooted(global),
+            url: url,
+            ready_state: Cell::new(Connecting),
+            protocol: DOMRefCell::new("".to_string()),
+            binary_type: Cell::new(BinaryTypeValues::Blob),
+            connection: DOMRefCell::new(None),
+            pinned_count: Cell::new(0),
         }
     }
 
-    pub fn new(global: &GlobalRef, url: DOMString) -> Temporary<WebSocket> {
-        reflect_dom_object(box WebSocket::new_inherited(url),
+    pub fn new(global: &GlobalRef, url: Url) -> Temporary<WebSocket> {
+        reflect_dom_object(box WebSocket::new_inherited(global, url),
                            global,
                            WebSocketBinding::Wrap)
     }
 
-    pub fn Constructor(global: &GlobalRef, url: DOMString) -> Fallible<Temporary<WebSocket>> {
-        Ok(WebSocket::new(global, url))
+    // http://www.whatwg.org/html/#dom-websocket
+    pub fn Constructor(global: &GlobalRef, url: DOMString, protocols: Option<DOMString>)
+                       -> Fallible<Temporary<WebSocket>> {
+        // Steps 1-2.
+        let url = match UrlParser::new().base_url(&global.get_url()).parse(url.as_slice()) {
+            Ok(url) => url,
+            Err(_) => return Err(Syntax)
+        };
+        match url.scheme.as_slice() {
+            "ws" => (),
+            // FIXME: The connection cannot speak TLS yet, so wss: URLs are
+            // refused up front rather than failed once the object exists.
+            _ => return Err(Syntax)
+        }
+        if url.fragment.is_some() {
+            return Err(Syntax);
+        }
+
+        // Step 5.
+        let protocols = match protocols {
+            Some(protocol) => {
+                if protocol.is_empty() || !protocol.as_slice().chars().all(is_token_char) {
+                    return Err(Syntax);
+                }
+                vec!(protocol)
+            }
+            None => vec!()
+        };
+
+        let ws = WebSocket::new(global, url.clone()).root();
+
+        // Step 8. The connection holds the object alive until it is closed.
+        let addr = unsafe { ws.to_trusted() };
+        let (events_chan, events_port) = channel();
+        let connection = websocket::connect(url, serialize_origin(&global.get_url()), protocols,
+                                            global.resource_task(), events_chan);
+        *ws.connection.borrow_mut() = Some(connection);
+
+        let ScriptChan(script_chan) = global.script_chan().clone();
+        spawn_named("WebSocketEvents", proc() {
+            for event in events_port.iter() {
+                script_chan.send(WebSocketEventMsg(addr, event));
+            }
+        });
+
+        Ok(Temporary::from_rooted(*ws))
+    }
+
+    pub fn handle_event(addr: TrustedWebSocketAddress, event: WebSocketEvent) {
+        let ws = unsafe { JS::from_trusted_websocket_address(addr).root() };
+        match event {
+            // http://www.whatwg.org/html/#feedback-from-the-protocol
+            Opened(protocol) => {
+                // close() may already have been called.
+                if ws.ready_state.get() == Connecting {
+                    ws.ready_state.set(Open);
+                    *ws.protocol.borrow_mut() = protocol;
+                    ws.dispatch_simple_event("open");
+                }
+            }
+            TextMessage(text) => {
+                if ws.ready_state.get() == Open {
+                    let global = ws.global.root();
+                    let data = text.to_jsval(global.root_ref().get_cx());
+                    ws.dispatch_message(data);
+                }
+            }
+            BinaryMessage(bytes) => {
+                if ws.ready_state.get() == Open {
+                    let data = ws.binary_message_data(bytes);
+                    ws.dispatch_message(data);
+                }
+            }
+            Failed => {
+                ws.ready_state.set(Closed);
+                ws.dispatch_simple_event("error");
+            }
+            ConnectionClosed(clean, code, reason) => {
+                ws.ready_state.set(Closed);
+                *ws.connection.borrow_mut() = None;
+                let global = ws.global.root();
+                let event = CloseEvent::new(&global.root_ref(), "close".to_string(),
+                                            false, false, clean, code, reason).root();
+                let target: JSRef<EventTarget> = EventTargetCast::from_ref(*ws);
+                target.dispatch_event_with_target(None, EventCast::from_ref(*event)).ok();
+                ws.release_once();
+            }
+        }
+    }
+}
+
+/// What `send()` was given: the contents of an ArrayBuffer, a view on one or
+/// a blob, which go out as a binary message, or else a string.
+enum OutgoingData {
+    Text(DOMString),
+    Binary(Vec<u8>),
+}
+
+/// Sorts out the argument of `send()`. Fails if a JavaScript exception was
+/// thrown while converting it to a string.
+fn outgoing_data(cx: *mut JSContext, data: JSVal) -> Result<OutgoingData, ()> {
+    if data.is_object() {
+        let object = data.to_object();
+        unsafe {
+            if JS_IsArrayBufferObject(object, cx) != 0 {
+                let length = JS_GetArrayBufferByteLength(object, cx) as uint;
+                let data = JS_GetArrayBufferData(object, cx) as *const u8;
+                return Ok(Binary(slice::raw::buf_as_slice(data, length, |data| data.to_vec())));
+            }
+            if JS_IsArrayBufferViewObject(object, cx) != 0 {
+                let length = JS_GetArrayBufferViewByteLength(object, cx) as uint;
+                let data = JS_GetArrayBufferViewData(object, cx) as *const u8;
+                return Ok(Binary(slice::raw::buf_as_slice(data, length, |data| data.to_vec())));
+            }
+        }
+        let blob: Result<JS<Blob>, ()> = FromJSValConvertible::from_jsval(cx, data, ());
+        match blob {
+            Ok(blob) => return Ok(Binary((*blob.root().data().bytes).clone())),
+            Err(()) => ()
+        }
+    }
+    let text: DOMString = try!(FromJSValConvertible::from_jsval(cx, data, Default::default()));
+    Ok(Text(text))
+}
+
+/// Whether `c` may appear in a subprotocol name (RFC 2616 token).
+fn is_token_char(c: char) -> bool {
+    c >= '\x21' && c <= '\x7E' && !"()<>@,;:\\\"/[]?={}".contains_char(c)
+}
+
+pub struct TrustedWebSocketAddress(pub *const c_void);
+
+trait PrivateWebSocketHelpers {
+    unsafe fn to_trusted(self) -> TrustedWebSocketAddress;
+    fn release_once(self);
+    fn dispatch_simple_event(self, type_: &str);
+    fn dispatch_message(self, data: JSVal);
+    fn binary_message_data(self, bytes: Vec<u8>) -> JSVal;
+}
+
+impl<'a> PrivateWebSocketHelpers for JSRef<'a, WebSocket> {
+    // Creates a trusted address to the object, and roots it. Always pair this with a release()
+    unsafe fn to_trusted(self) -> TrustedWebSocketAddress {
+        if self.pinned_count.get() == 0 {
+            JS_AddObjectRoot(self.global.root().root_ref().get_cx(), self.reflector().rootable());
+        }
+        let pinned_count = self.pinned_count.get();
+        self.pinned_count.set(pinned_count + 1);
+        TrustedWebSocketAddress(self.deref() as *const WebSocket as *const libc::c_void)
+    }
+
+    fn release_once(self) {
+        assert!(self.pinned_count.get() > 0)
+        let pinned_count = self.pinned_count.get();
+        self.pinned_count.set(pinned_count - 1);
+        if self.pinned_count.get() == 0 {
+            unsafe {
+                JS_RemoveObjectRoot(self.global.root().root_ref().get_cx(), self.reflector().rootable());
+            }
+        }
+    }
+
+    fn dispatch_simple_event(self, type_: &str) {
+        let global = self.global.root();
+        let event = Event::new(&global.root_ref(), type_.to_string(),
+                               DoesNotBubble, NotCancelable).root();
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        target.dispatch_event_with_target(None, *event).ok();
+    }
+
+    fn dispatch_message(self, data: JSVal) {
+        let global = self.global.root();
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        MessageEvent::dispatch_jsval(target, &global.root_ref(), data);
+    }
+
+    fn binary_message_data(self, bytes: Vec<u8>) -> JSVal {
+        let global = self.global.root();
+        match self.binary_type.get() {
+            BinaryTypeValues::Arraybuffer => {
+                let cx = global.root_ref().get_cx();
+                unsafe {
+                    let buffer = JS_NewArrayBuffer(cx, bytes.len() as u32);
+                    assert!(buffer.is_not_null());
+                    let data = JS_GetArrayBufferData(buffer, cx);
+                    ptr::copy_nonoverlapping_memory(data, bytes.as_ptr(), bytes.len());
+                    ObjectValue(&*buffer)
+                }
+            }
+            BinaryTypeValues::Blob => {
+                let blob = Blob::new(&global.root_ref(), bytes, "".to_string()).root();
+                blob.to_jsval(global.root_ref().get_cx())
+            }
+        }
     }
 }
 
@@ -43,7 +287,76 @@ impl Reflectable for WebSocket {
 }
 
 impl<'a> WebSocketMethods for JSRef<'a, WebSocket> {
+    event_handler!(open, GetOnopen, SetOnopen)
+    event_handler!(error, GetOnerror, SetOnerror)
+    event_handler!(close, GetOnclose, SetOnclose)
+    event_handler!(message, GetOnmessage, SetOnmessage)
+
     fn Url(self) -> DOMString {
-        self.url.clone()
+        self.url.serialize()
+    }
+
+    fn ReadyState(self) -> u16 {
+        self.ready_state.get() as u16
+    }
+
+    fn Protocol(self) -> DOMString {
+        self.protocol.borrow().clone()
+    }
+
+    fn BinaryType(self) -> BinaryType {
+        self.binary_type.get()
+    }
+
+    fn SetBinaryType(self, binary_type: BinaryType) {
+        self.binary_type.set(binary_type)
+    }
+
+    // http://www.whatwg.org/html/#dom-websocket-send
+    fn Send(self, cx: *mut JSContext, data: JSVal) -> ErrorResult {
+        match self.ready_state.get() {
+            Connecting => return Err(InvalidState),
+            Open => (),
+            // The data is discarded once the closing handshake has started.
+            Closing | Closed => return Ok(())
+        }
+        let data = match outgoing_data(cx, data) {
+            Ok(data) => data,
+            Err(()) => return Err(JSFailed)
+        };
+        match *self.connection.borrow() {
+            Some(ref connection) => match data {
+                Text(text) => connection.send_text(text),
+                Binary(bytes) => connection.send_binary(bytes),
+            },
+            None => ()
+        }
+        Ok(())
+    }
+
+    // http://www.whatwg.org/html/#dom-websocket-close
+    fn Close(self, code: Option<u16>, reason: Option<DOMString>) -> ErrorResult {
+        // Step 1.
+        match code {
+            Some(code) if code != websocket::CLOSE_NORMAL && (code < 3000 || code > 4999) => {
+                return Err(InvalidAccess);
+            }
+            _ => ()
+        }
+        // Step 2.
+        let reason = reason.unwrap_or("".to_string());
+        if reason.len() > 123 {
+            return Err(Syntax);
+        }
+        // Steps 3-6.
+        match self.ready_state.get() {
+            Closing | Closed => return Ok(()),
+            Connecting | Open => self.ready_state.set(Closing)
+        }
+        match *self.connection.borrow() {
+            Some(ref connection) => connection.close(code, reason),
+            None => ()
+        }
+        Ok(())
     }
 }
@@ -5,9 +5,11 @@
 use dom::bindings::cell::DOMRefCell;
 use dom::bindings::codegen::Bindings::EventHandlerBinding::{OnErrorEventHandlerNonNull, EventHandlerNonNull};
 use dom::bindings::codegen::Bindings::FunctionBinding::Function;
+use dom::bindings::codegen::Bindings::RequestBinding::RequestInit;
 use dom::bindings::codegen::Bindings::WindowBinding;
 use dom::bindings::codegen::Bindings::WindowBinding::WindowMethods;
 use dom::bindings::codegen::InheritTypes::EventTargetCast;
+use dom::bindings::codegen::UnionTypes::RequestOrString::RequestOrString;
 use dom::bindings::error::{Fallible, InvalidCharacter};
 use dom::bindings::global;
 use dom::bindings::js::{MutNullableJS, JSRef, Temporary, OptionalSettable};
@@ -16,9 +18,11 @@ use dom::browsercontext::BrowserContext;
 use dom::console::Console;
 use dom::document::Document;
 use dom::eventtarget::{EventTarget, WindowTypeId, EventTargetHelpers};
+use dom::promise::Promise;
 use dom::location::Location;
 use dom::navigator::Navigator;
 use dom::performance::Performance;
+use dom::request::{Request, RequestHelpers};
 use dom::screen::Screen;
 use dom::storage::Storage;
 use layout_interface::NoQuery;
@@ -31,7 +35,8 @@ use timers::{Interval, NonInterval, TimerId, TimerManager};
 use servo_msg::compositor_msg::ScriptListener;
 use servo_msg::constellation_msg::LoadData;
 use servo_net::image_cache_task::ImageCacheTask;
-use servo_net::storage_task::StorageTask;
+use servo_net::resource_task::RevokeBlobUrl;
+use servo_net::storage_task::{StorageTask, SessionStorage, LocalStorage};
 use servo_util::str::{DOMString,HTML_SPACE_CHARACTERS};
 
 use js::jsapi::JS_EvaluateUCScript;
@@ -45,6 +50,7 @@ use libc;
 use serialize::base64::{FromBase64, ToBase64, STANDARD};
 use std::cell::{Ref, RefMut};
 use std::default::Default;
+use std::mem;
 use std::rc::Rc;
 use time;
 
@@ -65,7 +71,10 @@ pub struct Window {
     navigation_start_precise: f64,
     screen: MutNullableJS<Screen>,
     session_storage: MutNullableJS<Storage>,
-    timers: TimerManager
+    local_storage: MutNullableJS<Storage>,
+    timers: TimerManager,
+    /// The blob: URLs that the document created, which are revoked when it is unloaded.
+    blob_urls: DOMRefCell<Vec<Url>>,
 }
 
 impl Window {
@@ -113,6 +122,18 @@ impl Window {
     pub fn storage_task(&self) -> StorageTask {
         self.page().storage_task.clone()
     }
+
+    pub fn add_blob_url(&self, url: Url) {
+        self.blob_urls.borrow_mut().push(url);
+    }
+
+    /// Revokes the blob: URLs that the document created, as it is unloaded.
+    pub fn revoke_blob_urls(&self) {
+        let urls = mem::replace(&mut *self.blob_urls.borrow_mut(), vec!());
+        for url in urls.into_iter() {
+            self.page().resource_task.send(RevokeBlobUrl(url));
+        }
+    }
 }
 
 // http://www.whatwg.org/html/#atob
@@ -217,12 +238,21 @@ impl<'a> WindowMethods for JSRef<'a, Window> {
 
     fn SessionStorage(self) -> Temporary<Storage> {
         if self.session_storage.get().is_none() {
-            let session_storage = Storage::new(&global::Window(self));
+            let session_storage = Storage::new(&global::Window(self),
+                                               SessionStorage(self.page().browsing_context_id));
             self.session_storage.assign(Some(session_storage));
         }
         self.session_storage.get().unwrap()
     }
 
+    fn LocalStorage(self) -> Temporary<Storage> {
+        if self.local_storage.get().is_none() {
+            let local_storage = Storage::new(&global::Window(self), LocalStorage);
+            self.local_storage.assign(Some(local_storage));
+        }
+        self.local_storage.get().unwrap()
+    }
+
     fn Console(self) -> Temporary<Console> {
         if self.console.get().is_none() {
             let console = Console::new(&global::Window(self));
@@ -321,6 +351,13 @@ impl<'a> WindowMethods for JSRef<'a, Window> {
     fn Atob(self, atob: DOMString) -> Fallible<DOMString> {
         base64_atob(atob)
     }
+
+    // http://fetch.spec.whatwg.org/#dom-global-fetch
+    fn Fetch(self, input: RequestOrString, init: &RequestInit) -> Fallible<Temporary<Promise>> {
+        let global = global::Window(self);
+        let request = try!(Request::Constructor(&global, input, init)).root();
+        request.fetch(&global)
+    }
 }
 
 impl Reflectable for Window {
@@ -428,7 +465,9 @@ impl Window {
             navigation_start_precise: time::precise_time_s(),
             screen: Default::default(),
             session_storage: Default::default(),
-            timers: TimerManager::new()
+            local_storage: Default::default(),
+            timers: TimerManager::new(),
+            blob_urls: DOMRefCell::new(vec!()),
         };
 
         WindowBinding::Wrap(cx, win)
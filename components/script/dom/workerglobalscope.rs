@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use dom::bindings::cell::DOMRefCell;
 use dom::bindings::codegen::Bindings::WorkerGlobalScopeBinding::WorkerGlobalScopeMethods;
 use dom::bindings::codegen::Bindings::FunctionBinding::Function;
 use dom::bindings::error::{ErrorResult, Fallible, Syntax, Network, FailureUnknown};
@@ -16,7 +17,7 @@ use dom::window::{base64_atob, base64_btoa};
 use script_task::{ScriptChan, FromWorker};
 use timers::{Interval, NonInterval, TimerId, TimerManager};
 
-use servo_net::resource_task::{ResourceTask, load_whole_resource};
+use servo_net::resource_task::{ResourceTask, RevokeBlobUrl, load_whole_resource};
 use servo_util::str::DOMString;
 
 use js::jsapi::JSContext;
@@ -24,6 +25,7 @@ use js::jsval::JSVal;
 use js::rust::Cx;
 
 use std::default::Default;
+use std::mem;
 use std::rc::Rc;
 use url::{Url, UrlParser};
 
@@ -44,6 +46,8 @@ pub struct WorkerGlobalScope {
     navigator: MutNullableJS<WorkerNavigator>,
     console: MutNullableJS<Console>,
     timers: TimerManager,
+    /// The blob: URLs that the worker created, which are revoked when it closes.
+    blob_urls: DOMRefCell<Vec<Url>>,
 }
 
 impl WorkerGlobalScope {
@@ -61,7 +65,8 @@ impl WorkerGlobalScope {
             location: Default::default(),
             navigator: Default::default(),
             console: Default::default(),
-            timers: TimerManager::new()
+            timers: TimerManager::new(),
+            blob_urls: DOMRefCell::new(vec!()),
         }
     }
 
@@ -85,6 +90,18 @@ impl WorkerGlobalScope {
     pub fn script_chan<'a>(&'a self) -> &'a ScriptChan {
         &self.script_chan
     }
+
+    pub fn add_blob_url(&self, url: Url) {
+        self.blob_urls.borrow_mut().push(url);
+    }
+
+    /// Revokes the blob: URLs that the worker created, as it closes.
+    pub fn revoke_blob_urls(&self) {
+        let urls = mem::replace(&mut *self.blob_urls.borrow_mut(), vec!());
+        for url in urls.into_iter() {
+            self.resource_task.send(RevokeBlobUrl(url));
+        }
+    }
 }
 
 impl<'a> WorkerGlobalScopeMethods for JSRef<'a, WorkerGlobalScope> {
@@ -29,6 +29,14 @@ pub enum Msg {
     /// Adds the given stylesheet to the document.
     LoadStylesheetMsg(Url),
 
+    /// Adds a stylesheet that an `@import` rule of an earlier stylesheet loaded. Layout sends
+    /// this to itself once the load is done.
+    AddImportedStylesheetMsg(ImportedStylesheet),
+
+    /// Has script reflow the nodes whose transitions and animations are running. Layout sends
+    /// this to itself every frame while there are any.
+    TickAnimationsMsg,
+
     /// Requests a reflow.
     ReflowMsg(Box<Reflow>),
 
@@ -50,6 +58,16 @@ pub enum Msg {
     ExitNowMsg,
 }
 
+/// A stylesheet that was loaded for an `@import` rule.
+pub struct ImportedStylesheet {
+    pub stylesheet: Stylesheet,
+    /// Where the `@import` rule is, as `Stylist::add_imported_stylesheet` expects it.
+    pub rule_path: Vec<uint>,
+    /// The URLs of the stylesheets that import this one, directly or not, so that import cycles
+    /// can be detected.
+    pub ancestors: Vec<Url>,
+}
+
 /// Synchronous messages that script can send to layout.
 ///
 /// In general, you should use messages to talk to Layout. Use the RPC interface
@@ -82,6 +82,7 @@ pub mod dom {
     #[path="bindings/codegen/InterfaceTypes.rs"]
     pub mod types;
 
+    pub mod animationevent;
     pub mod attr;
     pub mod blob;
     pub mod browsercontext;
@@ -90,6 +91,7 @@ pub mod dom {
     pub mod domrect;
     pub mod domrectlist;
     pub mod domstringmap;
+    pub mod closeevent;
     pub mod comment;
     pub mod console;
     mod create;
@@ -107,7 +109,9 @@ pub mod dom {
     pub mod eventdispatcher;
     pub mod eventtarget;
     pub mod file;
+    pub mod filereader;
     pub mod formdata;
+    pub mod headers;
     pub mod htmlanchorelement;
     pub mod htmlappletelement;
     pub mod htmlareaelement;
@@ -192,13 +196,19 @@ pub mod dom {
     pub mod performance;
     pub mod performancetiming;
     pub mod progressevent;
+    pub mod promise;
     pub mod range;
+    pub mod request;
+    pub mod response;
     pub mod screen;
     pub mod servohtmlparser;
     pub mod storage;
+    pub mod storageevent;
     pub mod text;
+    pub mod transitionevent;
     pub mod treewalker;
     pub mod uievent;
+    pub mod url;
     pub mod urlhelper;
     pub mod urlsearchparams;
     pub mod validitystate;
@@ -24,7 +24,7 @@ use js::rust::Cx;
 use servo_msg::compositor_msg::PerformingLayout;
 use servo_msg::compositor_msg::ScriptListener;
 use servo_msg::constellation_msg::{ConstellationChan, WindowSizeData};
-use servo_msg::constellation_msg::{PipelineId, SubpageId};
+use servo_msg::constellation_msg::{PipelineId, SubpageId, TopLevelBrowsingContextId};
 use servo_net::resource_task::ResourceTask;
 use servo_net::storage_task::StorageTask;
 use servo_util::geometry::{Au, MAX_RECT};
@@ -32,6 +32,7 @@ use servo_util::geometry;
 use servo_util::str::DOMString;
 use servo_util::smallvec::{SmallVec1, SmallVec};
 use std::cell::{Cell, Ref, RefMut};
+use std::collections::HashMap;
 use std::comm::{channel, Receiver, Empty, Disconnected};
 use std::mem::replace;
 use std::num::abs;
@@ -47,6 +48,9 @@ pub struct Page {
     /// Subpage id associated with this page, if any.
     pub subpage_id: Option<SubpageId>,
 
+    /// The top-level browsing context this page belongs to, which keys its `sessionStorage`.
+    pub browsing_context_id: TopLevelBrowsingContextId,
+
     /// Unique id for last reflow request; used for confirming completion reply.
     pub last_reflow_id: Cell<uint>,
 
@@ -81,6 +85,11 @@ pub struct Page {
     /// Any nodes that need to be dirtied before the next reflow.
     pub pending_dirty_nodes: DOMRefCell<SmallVec1<UntrustedNodeAddress>>,
 
+    /// The nodes of the document that layout started transitions or animations on, by the
+    /// address layout refers to them with. They are kept alive, and so can be sent end events,
+    /// until they are removed from the document.
+    pub animating_nodes: DOMRefCell<HashMap<UntrustedNodeAddress, JS<Node>>>,
+
     /// Pending scroll to fragment event, if any
     pub fragment_name: DOMRefCell<Option<String>>,
 
@@ -138,6 +147,7 @@ impl IterablePage for Rc<Page> {
 
 impl Page {
     pub fn new(id: PipelineId, subpage_id: Option<SubpageId>,
+           browsing_context_id: TopLevelBrowsingContextId,
            layout_chan: LayoutChan,
            window_size: WindowSizeData,
            resource_task: ResourceTask,
@@ -157,6 +167,7 @@ impl Page {
         Page {
             id: id,
             subpage_id: subpage_id,
+            browsing_context_id: browsing_context_id,
             frame: DOMRefCell::new(None),
             layout_chan: layout_chan,
             layout_rpc: layout_rpc,
@@ -167,6 +178,7 @@ impl Page {
             next_subpage_id: Cell::new(SubpageId(0)),
             resize_event: Cell::new(None),
             pending_dirty_nodes: DOMRefCell::new(SmallVec1::new()),
+            animating_nodes: DOMRefCell::new(HashMap::new()),
             fragment_name: DOMRefCell::new(None),
             last_reflow_id: Cell::new(0),
             resource_task: resource_task,
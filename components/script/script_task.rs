@@ -5,6 +5,7 @@
 //! The script task is the task that owns the DOM in memory, runs JavaScript, and spawns parsing
 //! and layout tasks.
 
+use dom::animationevent::AnimationEvent;
 use dom::bindings::cell::DOMRefCell;
 use dom::bindings::codegen::Bindings::DocumentBinding::{DocumentMethods, DocumentReadyStateValues};
 use dom::bindings::codegen::Bindings::DOMRectBinding::DOMRectMethods;
@@ -23,11 +24,16 @@ use dom::document::{Document, HTMLDocument, DocumentHelpers, FromParser};
 use dom::element::{Element, HTMLButtonElementTypeId, HTMLInputElementTypeId};
 use dom::element::{HTMLSelectElementTypeId, HTMLTextAreaElementTypeId, HTMLOptionElementTypeId};
 use dom::event::{Event, Bubbles, DoesNotBubble, Cancelable, NotCancelable};
+use dom::promise::{Promise, TrustedPromiseAddress};
+use dom::filereader::{FileReader, FileReaderEvent, TrustedFileReaderAddress};
 use dom::uievent::UIEvent;
 use dom::eventtarget::{EventTarget, EventTargetHelpers};
 use dom::keyboardevent::KeyboardEvent;
 use dom::node;
 use dom::node::{ElementNodeTypeId, Node, NodeHelpers};
+use dom::storageevent::StorageEvent;
+use dom::transitionevent::TransitionEvent;
+use dom::websocket::{WebSocket, TrustedWebSocketAddress};
 use dom::window::{Window, WindowHelpers};
 use dom::worker::{Worker, TrustedWorkerAddress};
 use dom::xmlhttprequest::{TrustedXHRAddress, XMLHttpRequest, XHRProgress};
@@ -46,19 +52,23 @@ use script_traits::{MouseMoveEvent, MouseUpEvent, ConstellationControlMsg, Scrip
 use script_traits::{ResizeMsg, AttachLayoutMsg, LoadMsg, ViewportMsg, SendEventMsg};
 use script_traits::{ResizeInactiveMsg, ExitPipelineMsg, NewLayoutInfo, OpaqueScriptLayoutChannel};
 use script_traits::{ScriptControlChan, ReflowCompleteMsg, UntrustedNodeAddress, KeyEvent};
+use script_traits::{AnimationsStartedMsg, TransitionEndMsg, AnimationEndMsg};
 use servo_msg::compositor_msg::{FinishedLoading, LayerId, Loading};
 use servo_msg::compositor_msg::{ScriptListener};
 use servo_msg::constellation_msg::{ConstellationChan, LoadCompleteMsg, LoadUrlMsg, NavigationDirection};
 use servo_msg::constellation_msg::{LoadData, PipelineId, Failure, FailureMsg, WindowSizeData, Key, KeyState};
 use servo_msg::constellation_msg::{KeyModifiers, SUPER, SHIFT, CONTROL, ALT, Repeated, Pressed};
-use servo_msg::constellation_msg::{Released};
+use servo_msg::constellation_msg::{Released, TopLevelBrowsingContextId};
 use servo_msg::constellation_msg;
+use servo_net::fetch::response::Response as NetResponse;
 use servo_net::image_cache_task::ImageCacheTask;
 use servo_net::resource_task::ResourceTask;
-use servo_net::storage_task::StorageTask;
+use servo_net::storage_task::{StorageTask, StorageNotification, SessionStorage, LocalStorage};
+use servo_net::storage_task::AddListener;
+use servo_net::websocket::WebSocketEvent;
 use servo_util::geometry::to_frac_px;
 use servo_util::smallvec::{SmallVec1, SmallVec};
-use servo_util::task::spawn_named_with_send_on_failure;
+use servo_util::task::{spawn_named, spawn_named_with_send_on_failure};
 use servo_util::task_state;
 
 use geom::point::Point2D;
@@ -115,6 +125,20 @@ pub enum ScriptMsg {
     WorkerPostMessage(TrustedWorkerAddress, *mut u64, size_t),
     /// Releases one reference to the Worker object (dispatched to all tasks).
     WorkerRelease(TrustedWorkerAddress),
+    /// Settles the promise returned by a fetch() call (dispatched to all tasks).
+    FetchResponseMsg(TrustedPromiseAddress, NetResponse),
+    /// Runs the callbacks registered on a settled promise (dispatched to all
+    /// tasks).
+    PromiseJobsMsg(TrustedPromiseAddress),
+    /// Notifies a WebSocket object of activity on its connection (dispatched
+    /// to all tasks).
+    WebSocketEventMsg(TrustedWebSocketAddress, WebSocketEvent),
+    /// Notifies a FileReader object of the progress of one of its reads
+    /// (dispatched to all tasks).
+    FileReaderEventMsg(TrustedFileReaderAddress, uint, FileReaderEvent),
+    /// Fires storage events at the other windows of the origin whose storage area changed
+    /// (only dispatched to ScriptTask).
+    StorageEventMsg(StorageNotification),
 }
 
 /// Encapsulates internal communication within the script task.
@@ -256,6 +280,7 @@ impl ScriptTaskFactory for ScriptTask {
 
     fn create<C>(_phantom: Option<&mut ScriptTask>,
                  id: PipelineId,
+                 browsing_context_id: TopLevelBrowsingContextId,
                  compositor: C,
                  layout_chan: &OpaqueScriptLayoutChannel,
                  control_chan: ScriptControlChan,
@@ -273,6 +298,7 @@ impl ScriptTaskFactory for ScriptTask {
         let layout_chan = LayoutChan(layout_chan.sender());
         spawn_named_with_send_on_failure("ScriptTask", task_state::SCRIPT, proc() {
             let script_task = ScriptTask::new(id,
+                                              browsing_context_id,
                                               box compositor as Box<ScriptListener>,
                                               layout_chan,
                                               script_port,
@@ -305,6 +331,7 @@ unsafe extern "C" fn debug_gc_callback(_rt: *mut JSRuntime, status: JSGCStatus)
 impl ScriptTask {
     /// Creates a new script task.
     pub fn new(id: PipelineId,
+               browsing_context_id: TopLevelBrowsingContextId,
                compositor: Box<ScriptListener+'static>,
                layout_chan: LayoutChan,
                port: Receiver<ScriptMsg>,
@@ -334,7 +361,21 @@ impl ScriptTask {
                                       Some(pre_wrap));
         }
 
-        let page = Page::new(id, None, layout_chan, window_size,
+        // Windows of the same origin may belong to other script tasks, so storage events are
+        // announced through the storage task.
+        let (storage_sender, storage_receiver) = channel();
+        storage_task.send(AddListener(storage_sender));
+        let ScriptChan(ref script_chan) = chan;
+        let script_chan = script_chan.clone();
+        spawn_named("ScriptTask storage events", proc() {
+            for notification in storage_receiver.iter() {
+                if script_chan.send_opt(StorageEventMsg(notification)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let page = Page::new(id, None, browsing_context_id, layout_chan, window_size,
                              resource_task.clone(),
                              storage_task,
                              constellation_chan.clone(),
@@ -544,6 +585,12 @@ impl ScriptTask {
                 FromScript(NavigateMsg(direction)) => self.handle_navigate_msg(direction),
                 FromConstellation(ReflowCompleteMsg(id, reflow_id)) => self.handle_reflow_complete_msg(id, reflow_id),
                 FromConstellation(ResizeInactiveMsg(id, new_size)) => self.handle_resize_inactive_msg(id, new_size),
+                FromConstellation(AnimationsStartedMsg(id, nodes)) =>
+                    self.handle_animations_started_msg(id, nodes),
+                FromConstellation(TransitionEndMsg(id, node, property_name, elapsed_time)) =>
+                    self.handle_transition_end_msg(id, node, property_name, elapsed_time),
+                FromConstellation(AnimationEndMsg(id, node, animation_name, elapsed_time)) =>
+                    self.handle_animation_end_msg(id, node, animation_name, elapsed_time),
                 FromConstellation(ExitPipelineMsg(id)) => if self.handle_exit_pipeline_msg(id) { return false },
                 FromConstellation(ViewportMsg(..)) => panic!("should have handled ViewportMsg already"),
                 FromScript(ExitWindowMsg(id)) => self.handle_exit_window_msg(id),
@@ -553,6 +600,13 @@ impl ScriptTask {
                 FromScript(DOMMessage(..)) => panic!("unexpected message"),
                 FromScript(WorkerPostMessage(addr, data, nbytes)) => Worker::handle_message(addr, data, nbytes),
                 FromScript(WorkerRelease(addr)) => Worker::handle_release(addr),
+                FromScript(FetchResponseMsg(addr, response)) => Promise::handle_fetch_response(addr, response),
+                FromScript(PromiseJobsMsg(addr)) => Promise::handle_jobs(addr),
+                FromScript(WebSocketEventMsg(addr, event)) => WebSocket::handle_event(addr, event),
+                FromScript(FileReaderEventMsg(addr, generation, event)) =>
+                    FileReader::handle_event(addr, generation, event),
+                FromScript(StorageEventMsg(notification)) =>
+                    self.handle_storage_event_msg(notification),
                 FromDevtools(EvaluateJS(id, s, reply)) => self.handle_evaluate_js(id, s, reply),
                 FromDevtools(GetRootNode(id, reply)) => self.handle_get_root_node(id, reply),
                 FromDevtools(GetDocumentElement(id, reply)) => self.handle_get_document_element(id, reply),
@@ -653,7 +707,7 @@ impl ScriptTask {
             task's page tree. This is a bug.");
         let new_page = {
             let window_size = parent_page.window_size.get();
-            Page::new(new_pipeline_id, Some(subpage_id),
+            Page::new(new_pipeline_id, Some(subpage_id), parent_page.browsing_context_id,
                       LayoutChan(layout_chan.downcast_ref::<Sender<layout_interface::Msg>>().unwrap().clone()),
                       window_size,
                       parent_page.resource_task.clone(),
@@ -674,6 +728,98 @@ impl ScriptTask {
         window.handle_fire_timer(timer_id);
     }
 
+    /// https://html.spec.whatwg.org/multipage/webstorage.html#send-a-storage-notification
+    fn handle_storage_event_msg(&self, notification: StorageNotification) {
+        let StorageNotification {
+            source, storage_type, url, key, old_value, new_value
+        } = notification;
+        let page = self.page.borrow();
+        for it_page in page.iter() {
+            let frame = it_page.frame();
+            let window = match *frame {
+                Some(ref frame) => frame.window.root(),
+                None => continue,
+            };
+            if it_page.id == source || !is_same_origin(&window.get_url(), &url) {
+                continue;
+            }
+            let storage = match storage_type {
+                SessionStorage(browsing_context_id) => {
+                    if it_page.browsing_context_id != browsing_context_id {
+                        continue;
+                    }
+                    window.SessionStorage()
+                }
+                LocalStorage => window.LocalStorage(),
+            }.root();
+            let event = StorageEvent::new(&global::Window(*window), "storage".to_string(),
+                                          false, false, key.clone(), old_value.clone(),
+                                          new_value.clone(), url.serialize(), Some(*storage)).root();
+            let target: JSRef<EventTarget> = EventTargetCast::from_ref(*window);
+            target.dispatch_event_with_target(None, EventCast::from_ref(*event)).ok();
+        }
+    }
+
+    /// Keeps the nodes that layout started transitions or animations on alive while they are in
+    /// the document. They may have been removed and freed since, so they are looked up among the
+    /// nodes of the document rather than through their addresses.
+    fn handle_animations_started_msg(&self, pipeline_id: PipelineId,
+                                     node_addresses: Vec<UntrustedNodeAddress>) {
+        let page = get_page(&*self.page.borrow(), pipeline_id);
+        let frame = page.frame();
+        let document = match *frame {
+            Some(ref frame) => frame.document.root(),
+            None => return,
+        };
+        let root: JSRef<Node> = NodeCast::from_ref(*document);
+        let mut animating_nodes = page.animating_nodes.borrow_mut();
+        for node in root.traverse_preorder() {
+            let address = node.to_untrusted_node_address();
+            if node_addresses.contains(&address) {
+                animating_nodes.insert(address, JS::from_rooted(node));
+            }
+        }
+    }
+
+    /// http://dev.w3.org/csswg/css-transitions/#transitionend
+    fn handle_transition_end_msg(&self, pipeline_id: PipelineId, node_address: UntrustedNodeAddress,
+                                 property_name: String, elapsed_time: f64) {
+        let page = get_page(&*self.page.borrow(), pipeline_id);
+        let frame = page.frame();
+        let window = match *frame {
+            Some(ref frame) => frame.window.root(),
+            None => return,
+        };
+        // Nodes that have since left the document get no end events.
+        let node = match page.animating_nodes.borrow().get(&node_address) {
+            Some(node) => node.root(),
+            None => return,
+        };
+        let event = TransitionEvent::new(&global::Window(*window), "transitionend".to_string(),
+                                         true, true, property_name, elapsed_time as f32).root();
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(*node);
+        target.dispatch_event_with_target(None, EventCast::from_ref(*event)).ok();
+    }
+
+    /// http://dev.w3.org/csswg/css-animations/#animationend
+    fn handle_animation_end_msg(&self, pipeline_id: PipelineId, node_address: UntrustedNodeAddress,
+                                animation_name: String, elapsed_time: f64) {
+        let page = get_page(&*self.page.borrow(), pipeline_id);
+        let frame = page.frame();
+        let window = match *frame {
+            Some(ref frame) => frame.window.root(),
+            None => return,
+        };
+        let node = match page.animating_nodes.borrow().get(&node_address) {
+            Some(node) => node.root(),
+            None => return,
+        };
+        let event = AnimationEvent::new(&global::Window(*window), "animationend".to_string(),
+                                        true, false, animation_name, elapsed_time as f32).root();
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(*node);
+        target.dispatch_event_with_target(None, EventCast::from_ref(*event)).ok();
+    }
+
     /// Handles a notification that reflow completed.
     fn handle_reflow_complete_msg(&self, pipeline_id: PipelineId, reflow_id: uint) {
         debug!("Script: Reflow {} complete for {}", reflow_id, pipeline_id);
@@ -815,7 +961,8 @@ impl ScriptTask {
         };
 
         {
-            // Create the root frame.
+            // Create the root frame, unloading the previous document.
+            revoke_blob_urls(&*page);
             let mut frame = page.mut_frame();
             *frame = Some(Frame {
                 document: JS::from_rooted(*document),
@@ -1188,6 +1335,7 @@ fn shut_down_layout(page_tree: &Rc<Page>, rt: *mut JSRuntime) {
 
     // Remove our references to the DOM objects in this page tree.
     for page in page_tree.iter() {
+        revoke_blob_urls(&*page);
         *page.mut_frame() = None;
     }
 
@@ -1210,6 +1358,18 @@ fn shut_down_layout(page_tree: &Rc<Page>, rt: *mut JSRuntime) {
 }
 
 
+/// Revokes the blob: URLs created by the document that is loaded in the given page.
+fn revoke_blob_urls(page: &Page) {
+    match *page.frame() {
+        Some(ref frame) => frame.window.root().revoke_blob_urls(),
+        None => (),
+    }
+}
+
+fn is_same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme == b.scheme && a.host() == b.host() && a.port_or_default() == b.port_or_default()
+}
+
 fn get_page(page: &Rc<Page>, pipeline_id: PipelineId) -> Rc<Page> {
     page.find(pipeline_id).expect("ScriptTask: received an event \
         message for a layout channel that is not associated with this script task.\
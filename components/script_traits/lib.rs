@@ -26,6 +26,7 @@ use devtools_traits::DevtoolsControlChan;
 use libc::c_void;
 use servo_msg::constellation_msg::{ConstellationChan, PipelineId, Failure, WindowSizeData};
 use servo_msg::constellation_msg::{LoadData, SubpageId, Key, KeyState, KeyModifiers};
+use servo_msg::constellation_msg::TopLevelBrowsingContextId;
 use servo_msg::compositor_msg::ScriptListener;
 use servo_net::image_cache_task::ImageCacheTask;
 use servo_net::resource_task::ResourceTask;
@@ -66,6 +67,15 @@ pub enum ConstellationControlMsg {
     /// Notifies script that reflow is finished.
     ReflowCompleteMsg(PipelineId, uint),
     ViewportMsg(PipelineId, Rect<f32>),
+    /// Notifies script that layout started transitions or animations on the given nodes, which
+    /// script keeps alive from then on so that their end events can be fired at them.
+    AnimationsStartedMsg(PipelineId, Vec<UntrustedNodeAddress>),
+    /// Fires a `transitionend` event at a node, with the name of the property and the elapsed
+    /// time of the transition in seconds.
+    TransitionEndMsg(PipelineId, UntrustedNodeAddress, String, f64),
+    /// Fires an `animationend` event at a node, with the name of the animation and its elapsed
+    /// time in seconds.
+    AnimationEndMsg(PipelineId, UntrustedNodeAddress, String, f64),
 }
 
 /// Events from the compositor that the script task needs to know about
@@ -96,6 +106,7 @@ impl<S: Encoder<E>, E> Encodable<S, E> for ScriptControlChan {
 pub trait ScriptTaskFactory {
     fn create<C>(_phantom: Option<&mut Self>,
                  id: PipelineId,
+                 browsing_context_id: TopLevelBrowsingContextId,
                  compositor: C,
                  layout_chan: &OpaqueScriptLayoutChannel,
                  control_chan: ScriptControlChan,
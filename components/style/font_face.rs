@@ -9,7 +9,8 @@ use std::ascii::AsciiExt;
 use parsing_utils::{BufferedIter, ParserIter, parse_slice_comma_separated};
 use properties::longhands::font_family::parse_one_family;
 use properties::computed_values::font_family::FamilyName;
-use stylesheets::{CSSRule, CSSFontFaceRule, CSSStyleRule, CSSMediaRule};
+use stylesheets::{CSSRule, CSSFontFaceRule, CSSImportRule, CSSStyleRule, CSSMediaRule};
+use stylesheets::CSSKeyframesRule;
 use media_queries::Device;
 use url::{Url, UrlParser};
 
@@ -18,7 +19,14 @@ pub fn iter_font_face_rules_inner(rules: &[CSSRule], device: &Device,
                                     callback: |family: &str, source: &Source|) {
     for rule in rules.iter() {
         match *rule {
-            CSSStyleRule(_) => {},
+            CSSImportRule(ref rule) => if rule.media_queries.evaluate(device) {
+                match rule.stylesheet {
+                    Some(ref stylesheet) => iter_font_face_rules_inner(
+                        stylesheet.rules.as_slice(), device, |f, s| callback(f, s)),
+                    None => {}
+                }
+            },
+            CSSStyleRule(_) | CSSKeyframesRule(_) => {},
             CSSMediaRule(ref rule) => if rule.media_queries.evaluate(device) {
                 iter_font_face_rules_inner(rule.rules.as_slice(), device, |f, s| callback(f, s))
             },
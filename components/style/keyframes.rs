@@ -0,0 +1,156 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use cssparser::ast::*;
+use cssparser::parse_rule_list;
+use errors::{ErrorLoggerIterator, log_css_error};
+use std::ascii::AsciiExt;
+use sync::Arc;
+use parsing_utils::{BufferedIter, ParserIter, parse_comma_separated};
+use properties::{CSSFloat, PropertyDeclaration, parse_property_declaration_list};
+use stylesheets::{CSSRule, CSSKeyframesRule, CSSFontFaceRule, CSSImportRule, CSSStyleRule};
+use stylesheets::CSSMediaRule;
+use media_queries::Device;
+use url::Url;
+
+
+pub fn iter_keyframes_rules_inner(rules: &[CSSRule], device: &Device,
+                                  callback: |&KeyframesRule|) {
+    for rule in rules.iter() {
+        match *rule {
+            CSSImportRule(ref rule) => if rule.media_queries.evaluate(device) {
+                match rule.stylesheet {
+                    Some(ref stylesheet) => iter_keyframes_rules_inner(
+                        stylesheet.rules.as_slice(), device, |r| callback(r)),
+                    None => {}
+                }
+            },
+            CSSStyleRule(_) | CSSFontFaceRule(_) => {},
+            CSSMediaRule(ref rule) => if rule.media_queries.evaluate(device) {
+                iter_keyframes_rules_inner(rule.rules.as_slice(), device, |r| callback(r))
+            },
+            CSSKeyframesRule(ref rule) => callback(rule),
+        }
+    }
+}
+
+/// A `@keyframes` rule, according to CSS-ANIMATIONS § 4.
+#[deriving(Clone)]
+pub struct KeyframesRule {
+    pub name: String,
+    /// The keyframes, in the order they were found.
+    pub keyframes: Vec<Keyframe>,
+}
+
+#[deriving(Clone)]
+pub struct Keyframe {
+    /// Where this keyframe is in the animation, between 0 (`from`) and 1 (`to`).
+    pub offsets: Vec<CSSFloat>,
+    /// The declarations of the keyframe. `!important` ones are ignored, as the spec says.
+    pub declarations: Arc<Vec<PropertyDeclaration>>,
+}
+
+pub fn parse_keyframes_rule(rule: AtRule, parent_rules: &mut Vec<CSSRule>, base_url: &Url) {
+    let name = {
+        let iter = &mut BufferedIter::new(rule.prelude.as_slice().skip_whitespace());
+        let name = match iter.next() {
+            Some(&Ident(ref name)) if !name.as_slice().eq_ignore_ascii_case("none") => {
+                name.clone()
+            }
+            Some(&QuotedString(ref name)) => name.clone(),
+            _ => {
+                log_css_error(rule.location, "Invalid @keyframes name");
+                return
+            }
+        };
+        if !iter.is_eof() {
+            log_css_error(rule.location, "@keyframes prelude contains unexpected characters");
+            return
+        }
+        name
+    };
+
+    let block = match rule.block {
+        Some(block) => block,
+        None => {
+            log_css_error(rule.location, "Invalid @keyframes rule");
+            return
+        }
+    };
+
+    let mut keyframes = vec!();
+    for item in ErrorLoggerIterator(parse_rule_list(block.into_iter())) {
+        match item {
+            AtRule_(rule) => log_css_error(
+                rule.location,
+                format!("Unsupported at-rule in @keyframes: @{:s}", rule.name).as_slice()),
+            QualifiedRule_(rule) => {
+                let offsets = parse_comma_separated(
+                    &mut BufferedIter::new(rule.prelude.as_slice().skip_whitespace()),
+                    parse_one_keyframe_selector);
+                match offsets {
+                    Ok(offsets) => keyframes.push(Keyframe {
+                        offsets: offsets,
+                        declarations: parse_property_declaration_list(
+                            rule.block.into_iter(), base_url).normal,
+                    }),
+                    Err(()) => log_css_error(rule.location, "Invalid keyframe selector"),
+                }
+            }
+        }
+    }
+
+    parent_rules.push(CSSKeyframesRule(KeyframesRule {
+        name: name,
+        keyframes: keyframes,
+    }))
+}
+
+/// <keyframe-selector> = from | to | <percentage>
+fn parse_one_keyframe_selector(iter: ParserIter) -> Result<CSSFloat, ()> {
+    match iter.next() {
+        Some(&Ident(ref keyword)) => {
+            if keyword.as_slice().eq_ignore_ascii_case("from") {
+                Ok(0.0)
+            } else if keyword.as_slice().eq_ignore_ascii_case("to") {
+                Ok(1.0)
+            } else {
+                Err(())
+            }
+        }
+        Some(&Percentage(ref value)) if value.value >= 0.0 && value.value <= 100.0 => {
+            Ok(value.value / 100.0)
+        }
+        _ => Err(()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use geom::size::TypedSize2D;
+    use media_queries::{Device, Screen};
+    use selector_matching::AuthorOrigin;
+    use stylesheets::{Stylesheet, iter_keyframes_rules};
+    use url::Url;
+
+    #[test]
+    fn test_parse_keyframes_rule() {
+        let url = Url::parse("http://localhost/").unwrap();
+        let css = "@keyframes slide { from { left: 0 } 50%, to { left: 10px } 150% { top: 0 } } \
+                   @keyframes none { to { left: 0 } } \
+                   @media print { @keyframes hidden { to { left: 0 } } }";
+        let stylesheet = Stylesheet::from_str(css, url, AuthorOrigin);
+        let device = Device::new(Screen, TypedSize2D(800.0, 600.0));
+        let mut rules = vec!();
+        iter_keyframes_rules(&stylesheet, &device, |rule| rules.push(rule.clone()));
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].name.as_slice(), "slide");
+        let offsets: Vec<Vec<f64>> = rules[0].keyframes.iter().map(|keyframe| {
+            keyframe.offsets.clone()
+        }).collect();
+        assert_eq!(offsets, vec!(vec!(0.0), vec!(0.5, 1.0)));
+        assert_eq!(rules[0].keyframes[1].declarations.len(), 1);
+    }
+}
@@ -35,7 +35,7 @@ extern crate "util" as servo_util;
 
 // Public API
 pub use media_queries::{Device, Screen};
-pub use stylesheets::{Stylesheet, iter_font_face_rules};
+pub use stylesheets::{Stylesheet, ImportRule, iter_font_face_rules, iter_stylesheet_import_rules};
 pub use selector_matching::{Stylist, StylesheetOrigin, UserAgentOrigin, AuthorOrigin, UserOrigin};
 pub use selector_matching::{DeclarationBlock, CommonStyleAffectingAttributes};
 pub use selector_matching::{CommonStyleAffectingAttributeInfo, CommonStyleAffectingAttributeMode};
@@ -52,9 +52,10 @@ pub use node::{TElement, TElementAttributes, TNode};
 pub use selectors::{PseudoElement, Before, After, SelectorList, parse_selector_list_from_str};
 pub use selectors::{AttrSelector, NamespaceConstraint, SpecificNamespace, AnyNamespace};
 pub use selectors::{SimpleSelector,LocalNameSelector};
-pub use cssparser::{Color, RGBA};
+pub use cssparser::{Color, RGBA, RGBAColor};
 pub use legacy::{IntegerAttribute, LengthAttribute, SizeIntegerAttribute, WidthLengthAttribute};
 pub use font_face::{Source, LocalSource, UrlSource_};
+pub use keyframes::{KeyframesRule, Keyframe};
 
 mod stylesheets;
 mod errors;
@@ -66,4 +67,5 @@ mod node;
 mod media_queries;
 mod parsing_utils;
 mod font_face;
+mod keyframes;
 mod legacy;
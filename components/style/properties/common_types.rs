@@ -224,6 +224,17 @@ pub mod specified {
     static GRAD_TO_RAD: CSSFloat = PI / 200.0;
 
     impl Angle {
+        /// Parses an angle. As in transform functions, a unitless zero is accepted too.
+        pub fn parse(input: &ComponentValue) -> Result<Angle, ()> {
+            match input {
+                &Dimension(ref value, ref unit) => {
+                    Angle::parse_dimension(value.value, unit.as_slice())
+                }
+                &Number(ref value) if value.value == 0. => Ok(Angle(0.)),
+                _ => Err(())
+            }
+        }
+
         /// Parses an angle according to CSS-VALUES § 6.1.
         fn parse_dimension(value: CSSFloat, unit: &str) -> Result<Angle,()> {
             if unit.eq_ignore_ascii_case("deg") {
@@ -240,6 +251,40 @@ pub mod specified {
         }
     }
 
+    /// A time, in seconds, according to CSS-VALUES § 6.2.
+    #[deriving(Clone, PartialEq, PartialOrd, Show)]
+    pub struct Time(pub CSSFloat);
+
+    impl Time {
+        pub fn seconds(self) -> CSSFloat {
+            let Time(seconds) = self;
+            seconds
+        }
+
+        pub fn parse(input: &ComponentValue) -> Result<Time, ()> {
+            match input {
+                &Dimension(ref value, ref unit) => {
+                    if unit.as_slice().eq_ignore_ascii_case("s") {
+                        Ok(Time(value.value))
+                    } else if unit.as_slice().eq_ignore_ascii_case("ms") {
+                        Ok(Time(value.value / 1000.0))
+                    } else {
+                        Err(())
+                    }
+                }
+                _ => Err(())
+            }
+        }
+
+        /// Parses a time that is not negative, as animation and transition durations are.
+        pub fn parse_non_negative(input: &ComponentValue) -> Result<Time, ()> {
+            match Time::parse(input) {
+                Ok(Time(seconds)) if seconds < 0.0 => Err(()),
+                result => result,
+            }
+        }
+    }
+
     /// Specified values for an image according to CSS-IMAGES.
     #[deriving(Clone)]
     pub enum Image {
@@ -447,7 +492,7 @@ pub mod specified {
 
 pub mod computed {
     pub use super::specified::{Angle, AngleAoc, AngleOrCorner, CornerAoc, HorizontalDirection};
-    pub use super::specified::{VerticalDirection};
+    pub use super::specified::{Time, VerticalDirection};
     pub use cssparser::Color as CSSColor;
     pub use super::super::longhands::computed_as_specified as compute_CSSColor;
     use super::*;
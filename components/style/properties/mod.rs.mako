@@ -284,6 +284,215 @@ pub mod longhands {
         </%self:longhand>
     % endfor
 
+    // CSS Backgrounds and Borders Module Level 3
+    // http://dev.w3.org/csswg/css-backgrounds/#border-radius
+    <%self:longhand name="border-top-left-radius">
+        pub mod computed_value {
+            use super::super::super::common_types::computed::LengthOrPercentage;
+
+            /// The horizontal and vertical radii of the corner. They differ if the corner is
+            /// elliptical.
+            #[deriving(PartialEq, Clone)]
+            pub struct T {
+                pub horizontal: LengthOrPercentage,
+                pub vertical: LengthOrPercentage,
+            }
+        }
+
+        #[deriving(Clone)]
+        pub struct SpecifiedValue {
+            pub horizontal: specified::LengthOrPercentage,
+            pub vertical: specified::LengthOrPercentage,
+        }
+
+        #[inline]
+        pub fn get_initial_value() -> computed_value::T {
+            computed_value::T {
+                horizontal: computed::LP_Length(Au(0)),
+                vertical: computed::LP_Length(Au(0)),
+            }
+        }
+
+        #[inline]
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                                 -> computed_value::T {
+            computed_value::T {
+                horizontal: computed::compute_LengthOrPercentage(value.horizontal, context),
+                vertical: computed::compute_LengthOrPercentage(value.vertical, context),
+            }
+        }
+
+        /// One value gives a circular corner; a second one makes it elliptical.
+        pub fn parse(input: &[ComponentValue], _: &Url) -> Result<SpecifiedValue, ()> {
+            let mut input_iter = input.skip_whitespace();
+            let horizontal = match input_iter.next() {
+                Some(value) => try!(specified::LengthOrPercentage::parse_non_negative(value)),
+                None => return Err(()),
+            };
+            let vertical = match input_iter.next() {
+                Some(value) => try!(specified::LengthOrPercentage::parse_non_negative(value)),
+                None => horizontal.clone(),
+            };
+            if input_iter.next().is_some() {
+                return Err(())
+            }
+            Ok(SpecifiedValue {
+                horizontal: horizontal,
+                vertical: vertical,
+            })
+        }
+    </%self:longhand>
+
+    % for corner in ["top-right", "bottom-right", "bottom-left"]:
+        <%self:longhand name="border-${corner}-radius">
+            pub use super::border_top_left_radius::{get_initial_value, parse, to_computed_value};
+            pub type SpecifiedValue = super::border_top_left_radius::SpecifiedValue;
+            pub mod computed_value {
+                pub type T = super::super::border_top_left_radius::computed_value::T;
+            }
+        </%self:longhand>
+    % endfor
+
+    // http://dev.w3.org/csswg/css-backgrounds/#box-shadow
+    <%self:longhand name="box-shadow">
+        pub mod computed_value {
+            use super::super::Au;
+            use super::super::super::common_types::computed::CSSColor;
+
+            #[deriving(PartialEq, Clone)]
+            pub struct BoxShadow {
+                pub offset_x: Au,
+                pub offset_y: Au,
+                pub blur_radius: Au,
+                pub spread_radius: Au,
+                pub color: CSSColor,
+                /// Whether the shadow is cast inside the padding box rather than outside the
+                /// border box.
+                pub inset: bool,
+            }
+
+            /// The shadows, from the topmost one down. `none` is an empty list.
+            pub type T = Vec<BoxShadow>;
+        }
+
+        #[deriving(Clone)]
+        pub struct SpecifiedBoxShadow {
+            pub offset_x: specified::Length,
+            pub offset_y: specified::Length,
+            pub blur_radius: specified::Length,
+            pub spread_radius: specified::Length,
+            pub color: Option<specified::CSSColor>,
+            pub inset: bool,
+        }
+
+        pub type SpecifiedValue = Vec<SpecifiedBoxShadow>;
+
+        #[inline]
+        pub fn get_initial_value() -> computed_value::T {
+            vec![]
+        }
+
+        /// Shadows without a color use the value of the `color` property.
+        #[inline]
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                                 -> computed_value::T {
+            value.into_iter().map(|shadow| {
+                computed_value::BoxShadow {
+                    offset_x: computed::compute_Au(shadow.offset_x, context),
+                    offset_y: computed::compute_Au(shadow.offset_y, context),
+                    blur_radius: computed::compute_Au(shadow.blur_radius, context),
+                    spread_radius: computed::compute_Au(shadow.spread_radius, context),
+                    color: shadow.color.unwrap_or(CurrentColor),
+                    inset: shadow.inset,
+                }
+            }).collect()
+        }
+
+        pub fn parse(input: &[ComponentValue], _: &Url) -> Result<SpecifiedValue, ()> {
+            match one_component_value(input).and_then(|value| get_ident_lower(value)) {
+                Ok(ref keyword) if keyword.as_slice() == "none" => return Ok(vec![]),
+                _ => {}
+            }
+            parse_slice_comma_separated(input, |iter| parse_one_box_shadow(iter))
+        }
+
+        /// <shadow> = inset? && <length>{2,4} && <color>?
+        fn parse_one_box_shadow(iter: ParserIter) -> Result<SpecifiedBoxShadow, ()> {
+            let mut lengths = vec![];
+            let mut lengths_done = false;
+            let mut color = None;
+            let mut inset = false;
+            loop {
+                let component_value = match iter.next() {
+                    Some(component_value) => component_value,
+                    None => break,
+                };
+                match *component_value {
+                    Comma => {
+                        iter.push_back(component_value);
+                        break
+                    }
+                    _ => {}
+                }
+                if !inset {
+                    match get_ident_lower(component_value) {
+                        Ok(ref keyword) if keyword.as_slice() == "inset" => {
+                            inset = true;
+                            lengths_done = !lengths.is_empty();
+                            continue
+                        }
+                        _ => {}
+                    }
+                }
+                if !lengths_done && lengths.len() < 4 {
+                    // The third length is the blur radius, which may not be negative.
+                    let length = if lengths.len() == 2 {
+                        specified::Length::parse_non_negative(component_value)
+                    } else {
+                        specified::Length::parse(component_value)
+                    };
+                    match length {
+                        Ok(length) => {
+                            lengths.push(length);
+                            continue
+                        }
+                        Err(()) => lengths_done = !lengths.is_empty(),
+                    }
+                }
+                if color.is_none() {
+                    match specified::CSSColor::parse(component_value) {
+                        Ok(parsed_color) => {
+                            color = Some(parsed_color);
+                            lengths_done = !lengths.is_empty();
+                            continue
+                        }
+                        Err(()) => {}
+                    }
+                }
+                return Err(())
+            }
+
+            if lengths.len() < 2 {
+                return Err(())
+            }
+            let length_or_zero = |index: uint| {
+                if lengths.len() > index {
+                    lengths[index].clone()
+                } else {
+                    specified::Au_(Au(0))
+                }
+            };
+            Ok(SpecifiedBoxShadow {
+                offset_x: lengths[0].clone(),
+                offset_y: lengths[1].clone(),
+                blur_radius: length_or_zero(2),
+                spread_radius: length_or_zero(3),
+                color: color,
+                inset: inset,
+            })
+        }
+    </%self:longhand>
+
     ${new_style_struct("PositionOffsets", is_inherited=False)}
 
     % for side in ["top", "right", "bottom", "left"]:
@@ -1137,6 +1346,643 @@ pub mod longhands {
     ${switch_to_style_struct("Box")}
 
     ${single_keyword("box-sizing", "content-box border-box")}
+
+    // CSS Transforms Module Level 1
+    // http://dev.w3.org/csswg/css-transforms/
+    ${switch_to_style_struct("Box")}
+
+    <%self:longhand name="transform">
+        pub mod computed_value {
+            use super::super::{Au, CSSFloat};
+            use super::super::super::common_types::computed::{Angle, LengthOrPercentage};
+
+            /// A 4x4 matrix. The elements are named by row and column as in `matrix3d()`, so
+            /// `m41`, `m42` and `m43` hold the translation.
+            #[deriving(PartialEq, Clone)]
+            pub struct ComputedMatrix {
+                pub m11: CSSFloat, pub m12: CSSFloat, pub m13: CSSFloat, pub m14: CSSFloat,
+                pub m21: CSSFloat, pub m22: CSSFloat, pub m23: CSSFloat, pub m24: CSSFloat,
+                pub m31: CSSFloat, pub m32: CSSFloat, pub m33: CSSFloat, pub m34: CSSFloat,
+                pub m41: CSSFloat, pub m42: CSSFloat, pub m43: CSSFloat, pub m44: CSSFloat,
+            }
+
+            impl ComputedMatrix {
+                /// Returns true if this matrix can be written with `matrix()`.
+                pub fn is_2d(&self) -> bool {
+                    self.m13 == 0.0 && self.m14 == 0.0 &&
+                    self.m23 == 0.0 && self.m24 == 0.0 &&
+                    self.m31 == 0.0 && self.m32 == 0.0 && self.m33 == 1.0 && self.m34 == 0.0 &&
+                    self.m43 == 0.0 && self.m44 == 1.0
+                }
+            }
+
+            /// A transform function. Percentages in translations refer to the size of the border
+            /// box, so they are only resolved during layout.
+            #[deriving(PartialEq, Clone)]
+            pub enum ComputedOperation {
+                Matrix(ComputedMatrix),
+                Translate(LengthOrPercentage, LengthOrPercentage, Au),
+                Scale(CSSFloat, CSSFloat, CSSFloat),
+                /// A rotation by the angle about the axis given by the first three values.
+                Rotate(CSSFloat, CSSFloat, CSSFloat, Angle),
+                Skew(Angle, Angle),
+                Perspective(Au),
+            }
+
+            impl ComputedOperation {
+                /// Returns true if this operation moves content out of the plane of the page.
+                pub fn is_3d(&self) -> bool {
+                    match *self {
+                        Matrix(ref matrix) => !matrix.is_2d(),
+                        Translate(_, _, z) => z != Au(0),
+                        Scale(_, _, z) => z != 1.0,
+                        Rotate(x, y, _, _) => x != 0.0 || y != 0.0,
+                        Skew(..) => false,
+                        Perspective(_) => true,
+                    }
+                }
+            }
+
+            /// `None` stands for `none`.
+            pub type T = Option<Vec<ComputedOperation>>;
+        }
+
+        #[deriving(Clone)]
+        pub enum SpecifiedOperation {
+            SpecifiedMatrix(ComputedMatrix),
+            SpecifiedTranslate(specified::LengthOrPercentage,
+                               specified::LengthOrPercentage,
+                               specified::Length),
+            SpecifiedScale(CSSFloat, CSSFloat, CSSFloat),
+            SpecifiedRotate(CSSFloat, CSSFloat, CSSFloat, specified::Angle),
+            SpecifiedSkew(specified::Angle, specified::Angle),
+            SpecifiedPerspective(specified::Length),
+        }
+
+        pub type SpecifiedValue = Option<Vec<SpecifiedOperation>>;
+
+        #[inline]
+        pub fn get_initial_value() -> computed_value::T {
+            None
+        }
+
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                                 -> computed_value::T {
+            value.map(|operations| {
+                operations.into_iter().map(|operation| {
+                    match operation {
+                        SpecifiedMatrix(matrix) => Matrix(matrix),
+                        SpecifiedTranslate(x, y, z) => {
+                            Translate(computed::compute_LengthOrPercentage(x, context),
+                                      computed::compute_LengthOrPercentage(y, context),
+                                      computed::compute_Au(z, context))
+                        }
+                        SpecifiedScale(x, y, z) => Scale(x, y, z),
+                        SpecifiedRotate(x, y, z, angle) => Rotate(x, y, z, angle),
+                        SpecifiedSkew(x, y) => Skew(x, y),
+                        SpecifiedPerspective(depth) => {
+                            Perspective(computed::compute_Au(depth, context))
+                        }
+                    }
+                }).collect()
+            })
+        }
+
+        /// Splits the arguments of a transform function at the commas between them.
+        fn split_arguments(args: &[ComponentValue]) -> Result<Vec<&ComponentValue>, ()> {
+            let mut result = vec!();
+            let mut iter = args.skip_whitespace();
+            loop {
+                match iter.next() {
+                    Some(&Comma) | None => return Err(()),
+                    Some(value) => result.push(value),
+                }
+                match iter.next() {
+                    Some(&Comma) => {}
+                    Some(_) => return Err(()),
+                    None => return Ok(result),
+                }
+            }
+        }
+
+        fn parse_number(input: &ComponentValue) -> Result<CSSFloat, ()> {
+            match *input {
+                ast::Number(ref value) => Ok(value.value),
+                _ => Err(()),
+            }
+        }
+
+        fn parse_numbers(args: &[&ComponentValue], count: uint) -> Result<Vec<CSSFloat>, ()> {
+            if args.len() != count {
+                return Err(())
+            }
+            let mut numbers = vec!();
+            for arg in args.iter() {
+                numbers.push(try!(parse_number(*arg)));
+            }
+            Ok(numbers)
+        }
+
+        fn matrix_from_numbers(n: &[CSSFloat]) -> ComputedMatrix {
+            ComputedMatrix {
+                m11: n[0], m12: n[1], m13: n[2], m14: n[3],
+                m21: n[4], m22: n[5], m23: n[6], m24: n[7],
+                m31: n[8], m32: n[9], m33: n[10], m34: n[11],
+                m41: n[12], m42: n[13], m43: n[14], m44: n[15],
+            }
+        }
+
+        fn parse_operation(name: &str, args: &[ComponentValue])
+                           -> Result<SpecifiedOperation, ()> {
+            let args = try!(split_arguments(args));
+            let args = args.as_slice();
+            let zero_length = specified::Au_(Au(0));
+            let zero = specified::LP_Length(zero_length);
+            match name.to_ascii_lower().as_slice() {
+                "matrix" => {
+                    let n = try!(parse_numbers(args, 6));
+                    Ok(SpecifiedMatrix(matrix_from_numbers(&[
+                        n[0], n[1], 0.0, 0.0,
+                        n[2], n[3], 0.0, 0.0,
+                        0.0, 0.0, 1.0, 0.0,
+                        n[4], n[5], 0.0, 1.0,
+                    ])))
+                }
+                "matrix3d" => {
+                    let n = try!(parse_numbers(args, 16));
+                    Ok(SpecifiedMatrix(matrix_from_numbers(n.as_slice())))
+                }
+                "translate" if args.len() == 1 || args.len() == 2 => {
+                    let x = try!(specified::LengthOrPercentage::parse(args[0]));
+                    let y = if args.len() == 2 {
+                        try!(specified::LengthOrPercentage::parse(args[1]))
+                    } else {
+                        zero
+                    };
+                    Ok(SpecifiedTranslate(x, y, zero_length))
+                }
+                "translatex" if args.len() == 1 => {
+                    let x = try!(specified::LengthOrPercentage::parse(args[0]));
+                    Ok(SpecifiedTranslate(x, zero, zero_length))
+                }
+                "translatey" if args.len() == 1 => {
+                    let y = try!(specified::LengthOrPercentage::parse(args[0]));
+                    Ok(SpecifiedTranslate(zero, y, zero_length))
+                }
+                "translatez" if args.len() == 1 => {
+                    let z = try!(specified::Length::parse(args[0]));
+                    Ok(SpecifiedTranslate(zero, zero, z))
+                }
+                "translate3d" if args.len() == 3 => {
+                    let x = try!(specified::LengthOrPercentage::parse(args[0]));
+                    let y = try!(specified::LengthOrPercentage::parse(args[1]));
+                    let z = try!(specified::Length::parse(args[2]));
+                    Ok(SpecifiedTranslate(x, y, z))
+                }
+                "scale" if args.len() == 1 || args.len() == 2 => {
+                    let x = try!(parse_number(args[0]));
+                    let y = if args.len() == 2 {
+                        try!(parse_number(args[1]))
+                    } else {
+                        x
+                    };
+                    Ok(SpecifiedScale(x, y, 1.0))
+                }
+                "scalex" => parse_numbers(args, 1).map(|n| SpecifiedScale(n[0], 1.0, 1.0)),
+                "scaley" => parse_numbers(args, 1).map(|n| SpecifiedScale(1.0, n[0], 1.0)),
+                "scalez" => parse_numbers(args, 1).map(|n| SpecifiedScale(1.0, 1.0, n[0])),
+                "scale3d" => parse_numbers(args, 3).map(|n| SpecifiedScale(n[0], n[1], n[2])),
+                "rotate" | "rotatez" if args.len() == 1 => {
+                    specified::Angle::parse(args[0]).map(|a| SpecifiedRotate(0.0, 0.0, 1.0, a))
+                }
+                "rotatex" if args.len() == 1 => {
+                    specified::Angle::parse(args[0]).map(|a| SpecifiedRotate(1.0, 0.0, 0.0, a))
+                }
+                "rotatey" if args.len() == 1 => {
+                    specified::Angle::parse(args[0]).map(|a| SpecifiedRotate(0.0, 1.0, 0.0, a))
+                }
+                "rotate3d" if args.len() == 4 => {
+                    let n = try!(parse_numbers(args.slice_to(3), 3));
+                    let angle = try!(specified::Angle::parse(args[3]));
+                    Ok(SpecifiedRotate(n[0], n[1], n[2], angle))
+                }
+                "skew" if args.len() == 1 || args.len() == 2 => {
+                    let x = try!(specified::Angle::parse(args[0]));
+                    let y = if args.len() == 2 {
+                        try!(specified::Angle::parse(args[1]))
+                    } else {
+                        specified::Angle(0.0)
+                    };
+                    Ok(SpecifiedSkew(x, y))
+                }
+                "skewx" if args.len() == 1 => {
+                    let x = try!(specified::Angle::parse(args[0]));
+                    Ok(SpecifiedSkew(x, specified::Angle(0.0)))
+                }
+                "skewy" if args.len() == 1 => {
+                    let y = try!(specified::Angle::parse(args[0]));
+                    Ok(SpecifiedSkew(specified::Angle(0.0), y))
+                }
+                "perspective" if args.len() == 1 => {
+                    specified::Length::parse_non_negative(args[0]).map(SpecifiedPerspective)
+                }
+                _ => Err(()),
+            }
+        }
+
+        // none | <transform-function>+
+        pub fn parse(input: &[ComponentValue], _: &Url) -> Result<SpecifiedValue, ()> {
+            match one_component_value(input) {
+                Ok(&Ident(ref keyword)) if keyword.as_slice().eq_ignore_ascii_case("none") => {
+                    return Ok(None)
+                }
+                _ => {}
+            }
+            let mut operations = vec!();
+            for component_value in input.skip_whitespace() {
+                match *component_value {
+                    Function(ref name, ref args) => {
+                        operations.push(try!(parse_operation(name.as_slice(), args.as_slice())))
+                    }
+                    _ => return Err(()),
+                }
+            }
+            if operations.is_empty() {
+                return Err(())
+            }
+            Ok(Some(operations))
+        }
+    </%self:longhand>
+
+    <%self:longhand name="transform-origin">
+        pub mod computed_value {
+            use super::super::Au;
+            use super::super::super::common_types::computed::LengthOrPercentage;
+
+            #[deriving(PartialEq, Clone)]
+            pub struct T {
+                pub horizontal: LengthOrPercentage,
+                pub vertical: LengthOrPercentage,
+                pub depth: Au,
+            }
+        }
+
+        #[deriving(Clone)]
+        pub struct SpecifiedValue {
+            pub horizontal: specified::LengthOrPercentage,
+            pub vertical: specified::LengthOrPercentage,
+            pub depth: specified::Length,
+        }
+
+        #[inline]
+        pub fn get_initial_value() -> computed_value::T {
+            computed_value::T {
+                horizontal: computed::LP_Percentage(0.5),
+                vertical: computed::LP_Percentage(0.5),
+                depth: Au(0),
+            }
+        }
+
+        #[inline]
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                                 -> computed_value::T {
+            computed_value::T {
+                horizontal: computed::compute_LengthOrPercentage(value.horizontal, context),
+                vertical: computed::compute_LengthOrPercentage(value.vertical, context),
+                depth: computed::compute_Au(value.depth, context),
+            }
+        }
+
+        // The position in the plane is parsed like `background-position`, and may be followed by
+        // a length along the z axis.
+        pub fn parse(input: &[ComponentValue], _: &Url) -> Result<SpecifiedValue, ()> {
+            let mut input_iter = input.skip_whitespace();
+            let first = input_iter.next();
+            let second = input_iter.next();
+            let third = input_iter.next();
+            if input_iter.next().is_some() {
+                return Err(())
+            }
+            let position = match (first, second) {
+                (Some(first), Some(second)) => {
+                    try!(super::background_position::parse_two(first, second))
+                }
+                (Some(first), None) => try!(super::background_position::parse_one(first)),
+                _ => return Err(()),
+            };
+            let depth = match third {
+                Some(third) => try!(specified::Length::parse(third)),
+                None => specified::Au_(Au(0)),
+            };
+            Ok(SpecifiedValue {
+                horizontal: position.horizontal,
+                vertical: position.vertical,
+                depth: depth,
+            })
+        }
+    </%self:longhand>
+
+    // CSS Color Module Level 3
+    // http://dev.w3.org/csswg/css-color/
+    ${switch_to_style_struct("Box")}
+
+    <%self:single_component_value name="opacity">
+        pub type SpecifiedValue = CSSFloat;
+        pub mod computed_value {
+            use super::super::CSSFloat;
+            pub type T = CSSFloat;
+        }
+        #[inline]
+        pub fn get_initial_value() -> computed_value::T {
+            1.0
+        }
+        /// Values outside of [0, 1] are valid, but are clamped at computed-value time.
+        #[inline]
+        pub fn to_computed_value(value: SpecifiedValue, _: &computed::Context)
+                                 -> computed_value::T {
+            value.max(0.0).min(1.0)
+        }
+        fn from_component_value(input: &ComponentValue, _: &Url) -> Result<SpecifiedValue, ()> {
+            match *input {
+                ast::Number(ref value) => Ok(value.value),
+                _ => Err(())
+            }
+        }
+    </%self:single_component_value>
+
+    // CSS Transitions and CSS Animations
+    // http://dev.w3.org/csswg/css-transitions/
+    // http://dev.w3.org/csswg/css-animations/
+
+    <%def name="time_list(name, initial_value, non_negative)">
+        <%self:longhand name="${name}">
+            pub use super::computed_as_specified as to_computed_value;
+            pub mod computed_value {
+                pub type T = Vec<super::super::computed::Time>;
+            }
+            pub type SpecifiedValue = computed_value::T;
+            #[inline] pub fn get_initial_value() -> computed_value::T {
+                vec![specified::Time(${initial_value})]
+            }
+            pub fn parse(input: &[ComponentValue], _: &Url) -> Result<SpecifiedValue, ()> {
+                parse_slice_comma_separated(input, |iter| {
+                    % if non_negative:
+                        specified::Time::parse_non_negative(try!(iter.next_as_result()))
+                    % else:
+                        specified::Time::parse(try!(iter.next_as_result()))
+                    % endif
+                })
+            }
+        </%self:longhand>
+    </%def>
+
+    <%def name="keyword_list(name, values)">
+        <%self:longhand name="${name}">
+            pub use super::computed_as_specified as to_computed_value;
+            pub mod computed_value {
+                #[allow(non_camel_case_types)]
+                #[deriving(PartialEq, Clone, Show)]
+                pub enum SingleComputedValue {
+                    % for value in values.split():
+                        ${to_rust_ident(value)},
+                    % endfor
+                }
+                pub type T = Vec<SingleComputedValue>;
+            }
+            pub type SpecifiedValue = computed_value::T;
+            #[inline] pub fn get_initial_value() -> computed_value::T {
+                vec![${to_rust_ident(values.split()[0])}]
+            }
+            pub fn parse(input: &[ComponentValue], _: &Url) -> Result<SpecifiedValue, ()> {
+                parse_slice_comma_separated(input, |iter| {
+                    parse_one_value(try!(iter.next_as_result()))
+                })
+            }
+            pub fn parse_one_value(input: &ComponentValue) -> Result<SingleComputedValue, ()> {
+                get_ident_lower(input).and_then(|keyword| {
+                    match keyword.as_slice() {
+                        % for value in values.split():
+                            "${value}" => Ok(${to_rust_ident(value)}),
+                        % endfor
+                        _ => Err(()),
+                    }
+                })
+            }
+        </%self:longhand>
+    </%def>
+
+    <%self:longhand name="transition-property">
+        pub use super::computed_as_specified as to_computed_value;
+        pub mod computed_value {
+            #[deriving(PartialEq, Clone, Show)]
+            pub enum SingleComputedValue {
+                TransitionAll,
+                /// The name of a property, in lower case.
+                TransitionProperty(String),
+            }
+            /// An empty list stands for `none`.
+            pub type T = Vec<SingleComputedValue>;
+        }
+        pub type SpecifiedValue = computed_value::T;
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            vec![TransitionAll]
+        }
+        /// none | <single-transition-property>#
+        pub fn parse(input: &[ComponentValue], _: &Url) -> Result<SpecifiedValue, ()> {
+            match one_component_value(input).and_then(|value| get_ident_lower(value)) {
+                Ok(ref keyword) if keyword.as_slice() == "none" => return Ok(vec![]),
+                _ => {}
+            }
+            parse_slice_comma_separated(input, |iter| {
+                parse_one_value(try!(iter.next_as_result()))
+            })
+        }
+        /// <single-transition-property> = all | <IDENT>
+        pub fn parse_one_value(input: &ComponentValue) -> Result<SingleComputedValue, ()> {
+            let keyword = try!(get_ident_lower(input));
+            match keyword.as_slice() {
+                "all" => Ok(TransitionAll),
+                "none" | "initial" | "inherit" | "unset" | "default" => Err(()),
+                _ => Ok(TransitionProperty(keyword)),
+            }
+        }
+    </%self:longhand>
+
+    ${time_list("transition-duration", "0.0", non_negative=True)}
+
+    <%self:longhand name="transition-timing-function">
+        pub use super::computed_as_specified as to_computed_value;
+        pub mod computed_value {
+            use super::super::CSSFloat;
+            #[deriving(PartialEq, Clone, Show)]
+            pub enum StepPosition {
+                StepStart,
+                StepEnd,
+            }
+            #[deriving(PartialEq, Clone, Show)]
+            pub enum TimingFunction {
+                /// The control points (x1, y1) and (x2, y2) of a cubic Bézier curve.
+                CubicBezier(CSSFloat, CSSFloat, CSSFloat, CSSFloat),
+                Steps(u32, StepPosition),
+            }
+            pub type T = Vec<TimingFunction>;
+        }
+        pub type SpecifiedValue = computed_value::T;
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            vec![CubicBezier(0.25, 0.1, 0.25, 1.0)]
+        }
+        pub fn parse(input: &[ComponentValue], _: &Url) -> Result<SpecifiedValue, ()> {
+            parse_slice_comma_separated(input, |iter| {
+                parse_one_value(try!(iter.next_as_result()))
+            })
+        }
+        /// Parses a <single-timing-function>, according to CSS-TRANSITIONS § 2.3.
+        pub fn parse_one_value(input: &ComponentValue) -> Result<TimingFunction, ()> {
+            match *input {
+                Ident(ref keyword) => {
+                    match keyword.as_slice().to_ascii_lower().as_slice() {
+                        "ease" => Ok(CubicBezier(0.25, 0.1, 0.25, 1.0)),
+                        "linear" => Ok(CubicBezier(0.0, 0.0, 1.0, 1.0)),
+                        "ease-in" => Ok(CubicBezier(0.42, 0.0, 1.0, 1.0)),
+                        "ease-out" => Ok(CubicBezier(0.0, 0.0, 0.58, 1.0)),
+                        "ease-in-out" => Ok(CubicBezier(0.42, 0.0, 0.58, 1.0)),
+                        "step-start" => Ok(Steps(1, StepStart)),
+                        "step-end" => Ok(Steps(1, StepEnd)),
+                        _ => Err(()),
+                    }
+                }
+                Function(ref name, ref arguments) => {
+                    match name.as_slice().to_ascii_lower().as_slice() {
+                        "cubic-bezier" => {
+                            let points = try!(parse_slice_comma_separated(
+                                    arguments.as_slice(), |iter| {
+                                match iter.next() {
+                                    Some(&Number(ref value)) => Ok(value.value),
+                                    _ => Err(()),
+                                }
+                            }));
+                            // The x coordinates must be in the [0, 1] range.
+                            if points.len() != 4 || points[0] < 0.0 || points[0] > 1.0 ||
+                                    points[2] < 0.0 || points[2] > 1.0 {
+                                return Err(())
+                            }
+                            Ok(CubicBezier(points[0], points[1], points[2], points[3]))
+                        }
+                        "steps" => {
+                            let iter = &mut BufferedIter::new(
+                                arguments.as_slice().skip_whitespace());
+                            let steps = match iter.next() {
+                                Some(&Number(ref value)) => match value.int_value {
+                                    Some(steps) if steps > 0 => steps as u32,
+                                    _ => return Err(()),
+                                },
+                                _ => return Err(()),
+                            };
+                            let position = match iter.next() {
+                                None => StepEnd,
+                                Some(&Comma) => {
+                                    let keyword = try!(get_ident_lower(
+                                        try!(iter.next_as_result())));
+                                    match keyword.as_slice() {
+                                        "start" => StepStart,
+                                        "end" => StepEnd,
+                                        _ => return Err(()),
+                                    }
+                                }
+                                Some(_) => return Err(()),
+                            };
+                            if !iter.is_eof() {
+                                return Err(())
+                            }
+                            Ok(Steps(steps, position))
+                        }
+                        _ => Err(()),
+                    }
+                }
+                _ => Err(()),
+            }
+        }
+    </%self:longhand>
+
+    ${time_list("transition-delay", "0.0", non_negative=False)}
+
+    <%self:longhand name="animation-name">
+        pub use super::computed_as_specified as to_computed_value;
+        pub mod computed_value {
+            /// An empty list stands for `none`.
+            pub type T = Vec<String>;
+        }
+        pub type SpecifiedValue = computed_value::T;
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            vec![]
+        }
+        /// none | <single-animation-name>#
+        ///
+        /// A `none` within a longer list is kept as is: no `@keyframes` rule can have that name.
+        pub fn parse(input: &[ComponentValue], _: &Url) -> Result<SpecifiedValue, ()> {
+            match one_component_value(input).and_then(|value| get_ident_lower(value)) {
+                Ok(ref keyword) if keyword.as_slice() == "none" => return Ok(vec![]),
+                _ => {}
+            }
+            parse_slice_comma_separated(input, |iter| {
+                parse_one_value(try!(iter.next_as_result()))
+            })
+        }
+        pub fn parse_one_value(input: &ComponentValue) -> Result<String, ()> {
+            match *input {
+                Ident(ref name) => Ok(name.clone()),
+                _ => Err(()),
+            }
+        }
+    </%self:longhand>
+
+    ${time_list("animation-duration", "0.0", non_negative=True)}
+
+    <%self:longhand name="animation-timing-function">
+        pub use super::computed_as_specified as to_computed_value;
+        pub mod computed_value {
+            pub use super::super::transition_timing_function::computed_value::T;
+        }
+        pub type SpecifiedValue = computed_value::T;
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            super::transition_timing_function::get_initial_value()
+        }
+        pub fn parse(input: &[ComponentValue], base_url: &Url) -> Result<SpecifiedValue, ()> {
+            super::transition_timing_function::parse(input, base_url)
+        }
+    </%self:longhand>
+
+    <%self:longhand name="animation-iteration-count">
+        pub use super::computed_as_specified as to_computed_value;
+        pub mod computed_value {
+            use super::super::CSSFloat;
+            /// `infinite` is represented as an infinite count.
+            pub type T = Vec<CSSFloat>;
+        }
+        pub type SpecifiedValue = computed_value::T;
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            vec![1.0]
+        }
+        pub fn parse(input: &[ComponentValue], _: &Url) -> Result<SpecifiedValue, ()> {
+            parse_slice_comma_separated(input, |iter| {
+                parse_one_value(try!(iter.next_as_result()))
+            })
+        }
+        /// <single-animation-iteration-count> = infinite | <number>
+        pub fn parse_one_value(input: &ComponentValue) -> Result<CSSFloat, ()> {
+            match *input {
+                Number(ref value) if value.value >= 0.0 => Ok(value.value),
+                Ident(ref keyword) if keyword.as_slice().eq_ignore_ascii_case("infinite") => {
+                    Ok(std::f64::INFINITY)
+                }
+                _ => Err(()),
+            }
+        }
+    </%self:longhand>
+
+    ${keyword_list("animation-direction", "normal reverse alternate alternate-reverse")}
+    ${keyword_list("animation-fill-mode", "none forwards backwards both")}
+    ${time_list("animation-delay", "0.0", non_negative=False)}
 }
 
 
@@ -1383,6 +2229,71 @@ pub mod shorthands {
         })
     </%self:shorthand>
 
+    <%self:shorthand name="border-radius" sub_properties="${' '.join(
+        'border-%s-radius' % corner
+        for corner in ['top-left', 'top-right', 'bottom-right', 'bottom-left']
+    )}">
+        // <length-percentage>{1,4} [ / <length-percentage>{1,4} ]?
+        //
+        // The values before the slash are the horizontal radii, and those after it the vertical
+        // ones. Either list is expanded to the four corners like the sides of `margin`.
+        let mut horizontal = vec![];
+        let mut vertical = vec![];
+        let mut seen_slash = false;
+        for component_value in input.skip_whitespace() {
+            match *component_value {
+                Delim('/') if !seen_slash && !horizontal.is_empty() => seen_slash = true,
+                _ => {
+                    let value =
+                        try!(specified::LengthOrPercentage::parse_non_negative(component_value));
+                    if seen_slash {
+                        vertical.push(value)
+                    } else {
+                        horizontal.push(value)
+                    }
+                }
+            }
+        }
+        if seen_slash && vertical.is_empty() {
+            return Err(())
+        }
+        if !seen_slash {
+            vertical = horizontal.clone()
+        }
+        let horizontal = try!(expand_corners(horizontal));
+        let vertical = try!(expand_corners(vertical));
+
+        fn expand_corners(values: Vec<specified::LengthOrPercentage>)
+                          -> Result<[specified::LengthOrPercentage, ..4], ()> {
+            let (top_left, top_right, bottom_right, bottom_left) = match values.as_slice() {
+                [ref all] => (all, all, all, all),
+                [ref top_left_and_bottom_right, ref top_right_and_bottom_left] => {
+                    (top_left_and_bottom_right,
+                     top_right_and_bottom_left,
+                     top_left_and_bottom_right,
+                     top_right_and_bottom_left)
+                }
+                [ref top_left, ref top_right_and_bottom_left, ref bottom_right] => {
+                    (top_left, top_right_and_bottom_left, bottom_right, top_right_and_bottom_left)
+                }
+                [ref top_left, ref top_right, ref bottom_right, ref bottom_left] => {
+                    (top_left, top_right, bottom_right, bottom_left)
+                }
+                _ => return Err(()),
+            };
+            Ok([top_left.clone(), top_right.clone(), bottom_right.clone(), bottom_left.clone()])
+        }
+
+        Ok(Longhands {
+            % for i, corner in enumerate(["top_left", "top_right", "bottom_right", "bottom_left"]):
+                border_${corner}_radius: Some(border_top_left_radius::SpecifiedValue {
+                    horizontal: horizontal[${i}].clone(),
+                    vertical: vertical[${i}].clone(),
+                }),
+            % endfor
+        })
+    </%self:shorthand>
+
     <%self:shorthand name="font" sub_properties="font-style font-variant font-weight
                                                  font-size line-height font-family">
         let mut iter = input.skip_whitespace();
@@ -1460,6 +2371,216 @@ pub mod shorthands {
         })
     </%self:shorthand>
 
+    <%self:shorthand name="transition" sub_properties="transition-property transition-duration
+                                                       transition-timing-function transition-delay">
+        // <single-transition> = [ none | <single-transition-property> ] || <time> ||
+        //                       <single-transition-timing-function> || <time>
+        let transitions = try!(parse_slice_comma_separated(input, |iter| {
+            let mut property = None;
+            let mut duration = None;
+            let mut timing_function = None;
+            let mut delay = None;
+            loop {
+                let component_value = match iter.next() {
+                    Some(component_value) => component_value,
+                    None => break,
+                };
+                match *component_value {
+                    Comma => {
+                        iter.push_back(component_value);
+                        break
+                    }
+                    _ => {}
+                }
+                match specified::Time::parse(component_value) {
+                    Ok(time) => {
+                        if duration.is_none() {
+                            if time.seconds() < 0.0 {
+                                return Err(())
+                            }
+                            duration = Some(time)
+                        } else if delay.is_none() {
+                            delay = Some(time)
+                        } else {
+                            return Err(())
+                        }
+                        continue
+                    }
+                    Err(()) => {}
+                }
+                if timing_function.is_none() {
+                    match transition_timing_function::parse_one_value(component_value) {
+                        Ok(value) => { timing_function = Some(value); continue },
+                        Err(()) => {}
+                    }
+                }
+                if property.is_none() {
+                    match get_ident_lower(component_value) {
+                        Ok(ref keyword) if keyword.as_slice() == "none" => {
+                            property = Some(None);
+                            continue
+                        }
+                        _ => {}
+                    }
+                    match transition_property::parse_one_value(component_value) {
+                        Ok(value) => { property = Some(Some(value)); continue },
+                        Err(()) => {}
+                    }
+                }
+                return Err(())
+            }
+            Ok((property.unwrap_or(Some(transition_property::TransitionAll)),
+                duration.unwrap_or(specified::Time(0.0)),
+                timing_function.unwrap_or_else(|| {
+                    transition_timing_function::get_initial_value()[0].clone()
+                }),
+                delay.unwrap_or(specified::Time(0.0))))
+        }));
+
+        // `none` is only valid when it is the only transition.
+        let mut properties = vec![];
+        let mut durations = vec![];
+        let mut timing_functions = vec![];
+        let mut delays = vec![];
+        let transition_count = transitions.len();
+        for (property, duration, timing_function, delay) in transitions.into_iter() {
+            match property {
+                Some(property) => properties.push(property),
+                None if transition_count == 1 => {}
+                None => return Err(()),
+            }
+            durations.push(duration);
+            timing_functions.push(timing_function);
+            delays.push(delay);
+        }
+        Ok(Longhands {
+            transition_property: Some(properties),
+            transition_duration: Some(durations),
+            transition_timing_function: Some(timing_functions),
+            transition_delay: Some(delays),
+        })
+    </%self:shorthand>
+
+    <%self:shorthand name="animation" sub_properties="animation-name animation-duration
+                                                      animation-timing-function animation-delay
+                                                      animation-iteration-count
+                                                      animation-direction animation-fill-mode">
+        // <single-animation> = <time> || <single-timing-function> || <time> ||
+        //                      <single-animation-iteration-count> ||
+        //                      <single-animation-direction> || <single-animation-fill-mode> ||
+        //                      [ none | <keyframes-name> ]
+        let animations = try!(parse_slice_comma_separated(input, |iter| {
+            let mut name = None;
+            let mut duration = None;
+            let mut timing_function = None;
+            let mut delay = None;
+            let mut iteration_count = None;
+            let mut direction = None;
+            let mut fill_mode = None;
+            loop {
+                let component_value = match iter.next() {
+                    Some(component_value) => component_value,
+                    None => break,
+                };
+                match *component_value {
+                    Comma => {
+                        iter.push_back(component_value);
+                        break
+                    }
+                    _ => {}
+                }
+                match specified::Time::parse(component_value) {
+                    Ok(time) => {
+                        if duration.is_none() {
+                            if time.seconds() < 0.0 {
+                                return Err(())
+                            }
+                            duration = Some(time)
+                        } else if delay.is_none() {
+                            delay = Some(time)
+                        } else {
+                            return Err(())
+                        }
+                        continue
+                    }
+                    Err(()) => {}
+                }
+                // Keywords of the other sub-properties take precedence over animation names.
+                if timing_function.is_none() {
+                    match transition_timing_function::parse_one_value(component_value) {
+                        Ok(value) => { timing_function = Some(value); continue },
+                        Err(()) => {}
+                    }
+                }
+                if iteration_count.is_none() {
+                    match animation_iteration_count::parse_one_value(component_value) {
+                        Ok(value) => { iteration_count = Some(value); continue },
+                        Err(()) => {}
+                    }
+                }
+                if direction.is_none() {
+                    match animation_direction::parse_one_value(component_value) {
+                        Ok(value) => { direction = Some(value); continue },
+                        Err(()) => {}
+                    }
+                }
+                if fill_mode.is_none() {
+                    match animation_fill_mode::parse_one_value(component_value) {
+                        Ok(value) => { fill_mode = Some(value); continue },
+                        Err(()) => {}
+                    }
+                }
+                if name.is_none() {
+                    match animation_name::parse_one_value(component_value) {
+                        Ok(value) => { name = Some(value); continue },
+                        Err(()) => {}
+                    }
+                }
+                return Err(())
+            }
+            Ok((name.unwrap_or("none".to_string()),
+                duration.unwrap_or(specified::Time(0.0)),
+                timing_function.unwrap_or_else(|| {
+                    transition_timing_function::get_initial_value()[0].clone()
+                }),
+                delay.unwrap_or(specified::Time(0.0)),
+                iteration_count.unwrap_or(1.0),
+                direction.unwrap_or(animation_direction::normal),
+                fill_mode.unwrap_or(animation_fill_mode::none)))
+        }));
+
+        let mut names = vec![];
+        let mut durations = vec![];
+        let mut timing_functions = vec![];
+        let mut delays = vec![];
+        let mut iteration_counts = vec![];
+        let mut directions = vec![];
+        let mut fill_modes = vec![];
+        let animation_count = animations.len();
+        for (name, duration, timing_function, delay, iteration_count, direction, fill_mode)
+                in animations.into_iter() {
+            // A lone `none` leaves the list of names empty, as in the `animation-name` longhand.
+            if !(animation_count == 1 && name.as_slice() == "none") {
+                names.push(name)
+            }
+            durations.push(duration);
+            timing_functions.push(timing_function);
+            delays.push(delay);
+            iteration_counts.push(iteration_count);
+            directions.push(direction);
+            fill_modes.push(fill_mode);
+        }
+        Ok(Longhands {
+            animation_name: Some(names),
+            animation_duration: Some(durations),
+            animation_timing_function: Some(timing_functions),
+            animation_delay: Some(delays),
+            animation_iteration_count: Some(iteration_counts),
+            animation_direction: Some(directions),
+            animation_fill_mode: Some(fill_modes),
+        })
+    </%self:shorthand>
+
 }
 
 
@@ -1827,6 +2948,15 @@ impl ComputedValues {
             &*self.${style_struct.ident}
         }
     % endfor
+
+    % for style_struct in STYLE_STRUCTS:
+        /// Returns a mutable reference to the style struct, copying it first if it is shared.
+        #[inline]
+        pub fn mutate_${style_struct.name.lower()}
+                <'a>(&'a mut self) -> &'a mut style_structs::${style_struct.name} {
+            self.${style_struct.ident}.make_unique()
+        }
+    % endfor
 }
 
 
@@ -24,7 +24,9 @@ use node::{TElement, TElementAttributes, TNode};
 use properties::{PropertyDeclaration, PropertyDeclarationBlock, SpecifiedValue, WidthDeclaration};
 use properties::{specified};
 use selectors::*;
-use stylesheets::{Stylesheet, iter_stylesheet_media_rules, iter_stylesheet_style_rules};
+use stylesheets::{Stylesheet, iter_stylesheet_media_queries, iter_stylesheet_style_rules};
+use stylesheets::{iter_keyframes_rules, set_imported_stylesheet};
+use keyframes::KeyframesRule;
 
 pub enum StylesheetOrigin {
     UserAgentOrigin,
@@ -280,6 +282,10 @@ pub struct Stylist {
     before_map: PerPseudoElementSelectorMap,
     after_map: PerPseudoElementSelectorMap,
     rules_source_order: uint,
+
+    // The `@keyframes` rules that apply to the current device, by name. A later rule
+    // replaces an earlier one with the same name.
+    keyframes: HashMap<String, KeyframesRule>,
 }
 
 impl Stylist {
@@ -294,6 +300,7 @@ impl Stylist {
             before_map: PerPseudoElementSelectorMap::new(),
             after_map: PerPseudoElementSelectorMap::new(),
             rules_source_order: 0u,
+            keyframes: HashMap::new(),
         };
         // FIXME: Add quirks-mode.css in quirks mode.
         // FIXME: Add iso-8859-9.css when the document’s encoding is ISO-8859-8.
@@ -370,6 +377,14 @@ impl Stylist {
                 self.rules_source_order = rules_source_order;
             }
 
+            self.keyframes.clear();
+            let keyframes = &mut self.keyframes;
+            for stylesheet in self.stylesheets.iter() {
+                iter_keyframes_rules(stylesheet, &self.device, |rule| {
+                    keyframes.insert(rule.name.clone(), rule.clone());
+                });
+            }
+
             self.is_dirty = false;
             return true;
         }
@@ -380,9 +395,9 @@ impl Stylist {
     pub fn set_device(&mut self, device: Device) {
         let is_dirty = self.is_dirty || self.stylesheets.iter().any(|stylesheet| {
             let mut stylesheet_dirty = false;
-            iter_stylesheet_media_rules(stylesheet, |rule| {
-                stylesheet_dirty |= rule.media_queries.evaluate(&self.device) !=
-                                    rule.media_queries.evaluate(&device);
+            iter_stylesheet_media_queries(stylesheet, |media_queries| {
+                stylesheet_dirty |= media_queries.evaluate(&self.device) !=
+                                    media_queries.evaluate(&device);
             });
             stylesheet_dirty
         });
@@ -391,9 +406,28 @@ impl Stylist {
         self.is_dirty |= is_dirty;
     }
 
-    pub fn add_stylesheet(&mut self, stylesheet: Stylesheet) {
+    /// Adds a stylesheet, and returns its index for `add_imported_stylesheet`.
+    pub fn add_stylesheet(&mut self, stylesheet: Stylesheet) -> uint {
         self.stylesheets.push(stylesheet);
         self.is_dirty = true;
+        self.stylesheets.len() - 1
+    }
+
+    /// Adds a stylesheet that an `@import` rule loaded. The path gives the index of a stylesheet
+    /// that was added, then the index of the `@import` rule in it, then that of the rule in the
+    /// stylesheet it imports, and so on. Returns false if there is no such rule.
+    pub fn add_imported_stylesheet(&mut self, path: &[uint], stylesheet: Stylesheet) -> bool {
+        let added = match path.head().and_then(|&index| self.stylesheets.get_mut(index)) {
+            Some(parent) => set_imported_stylesheet(parent, path.tail(), stylesheet),
+            None => false,
+        };
+        self.is_dirty |= added;
+        added
+    }
+
+    /// Returns the `@keyframes` rule with the given name, as of the last `update`.
+    pub fn get_keyframes<'a>(&'a self, name: &str) -> Option<&'a KeyframesRule> {
+        self.keyframes.find_equiv(name)
     }
 
     /// Returns the applicable CSS declarations for the given element. This corresponds to
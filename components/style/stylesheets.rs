@@ -4,7 +4,7 @@
 
 use std::iter::Iterator;
 use std::ascii::AsciiExt;
-use url::Url;
+use url::{Url, UrlParser};
 
 use encoding::EncodingRef;
 
@@ -14,24 +14,35 @@ use selectors;
 use properties;
 use errors::{ErrorLoggerIterator, log_css_error};
 use namespaces::{NamespaceMap, parse_namespace_rule};
-use media_queries::{Device, MediaRule, parse_media_rule};
+use media_queries::{Device, MediaQueryList, MediaRule, parse_media_query_list, parse_media_rule};
 use media_queries;
 use font_face::{FontFaceRule, Source, parse_font_face_rule, iter_font_face_rules_inner};
+use keyframes::{KeyframesRule, parse_keyframes_rule, iter_keyframes_rules_inner};
 use selector_matching::StylesheetOrigin;
 
 
 pub struct Stylesheet {
     /// List of rules in the order they were found (important for
     /// cascading order)
-    rules: Vec<CSSRule>,
+    pub rules: Vec<CSSRule>,
     pub origin: StylesheetOrigin,
 }
 
 
 pub enum CSSRule {
+    CSSImportRule(ImportRule),
     CSSStyleRule(StyleRule),
     CSSMediaRule(MediaRule),
     CSSFontFaceRule(FontFaceRule),
+    CSSKeyframesRule(KeyframesRule),
+}
+
+
+pub struct ImportRule {
+    pub url: Url,
+    pub media_queries: MediaQueryList,
+    /// The imported stylesheet, once it has been loaded.
+    pub stylesheet: Option<Box<Stylesheet>>,
 }
 
 
@@ -96,8 +107,7 @@ impl Stylesheet {
                                               "@import must be before any rule but @charset")
                             } else {
                                 next_state = STATE_IMPORTS;
-                                // TODO: support @import
-                                log_css_error(rule.location, "@import is not supported yet")
+                                parse_import_rule(rule, &mut rules, &base_url)
                             }
                         },
                         "namespace" => {
@@ -145,12 +155,53 @@ pub fn parse_style_rule(rule: QualifiedRule, parent_rules: &mut Vec<CSSRule>,
 }
 
 
+/// Parses an `@import` rule. The stylesheet it imports is loaded later, by the layout task.
+pub fn parse_import_rule(rule: AtRule, parent_rules: &mut Vec<CSSRule>, base_url: &Url) {
+    let AtRule { location, prelude, block, .. } = rule;
+    if block.is_some() {
+        log_css_error(location, "Invalid @import rule");
+        return
+    }
+    let position = prelude.iter().position(|value| {
+        match *value {
+            WhiteSpace => false,
+            _ => true,
+        }
+    });
+    let (url, media_queries) = match position {
+        Some(position) => match prelude[position] {
+            URL(ref url) | QuotedString(ref url) => {
+                (url, parse_media_query_list(prelude.slice_from(position + 1)))
+            }
+            _ => {
+                log_css_error(location, "Invalid @import rule");
+                return
+            }
+        },
+        None => {
+            log_css_error(location, "Invalid @import rule");
+            return
+        }
+    };
+    match UrlParser::new().base_url(base_url).parse(url.as_slice()) {
+        Ok(url) => parent_rules.push(CSSImportRule(ImportRule {
+            url: url,
+            media_queries: media_queries,
+            stylesheet: None,
+        })),
+        Err(_) => log_css_error(location, format!(
+            "Invalid @import URL: {}", url).as_slice()),
+    }
+}
+
+
 // lower_name is passed explicitly to avoid computing it twice.
 pub fn parse_nested_at_rule(lower_name: &str, rule: AtRule,
                             parent_rules: &mut Vec<CSSRule>, namespaces: &NamespaceMap, base_url: &Url) {
     match lower_name {
         "media" => parse_media_rule(rule, parent_rules, namespaces, base_url),
         "font-face" => parse_font_face_rule(rule, parent_rules, base_url),
+        "keyframes" => parse_keyframes_rule(rule, parent_rules, base_url),
         _ => log_css_error(rule.location,
                            format!("Unsupported at-rule: @{:s}", lower_name).as_slice())
     }
@@ -161,15 +212,68 @@ pub fn iter_style_rules<'a>(rules: &[CSSRule], device: &media_queries::Device,
                             callback: |&StyleRule|) {
     for rule in rules.iter() {
         match *rule {
+            CSSImportRule(ref rule) => if rule.media_queries.evaluate(device) {
+                match rule.stylesheet {
+                    Some(ref stylesheet) => {
+                        iter_style_rules(stylesheet.rules.as_slice(), device, |s| callback(s))
+                    }
+                    None => {}
+                }
+            },
             CSSStyleRule(ref rule) => callback(rule),
             CSSMediaRule(ref rule) => if rule.media_queries.evaluate(device) {
                 iter_style_rules(rule.rules.as_slice(), device, |s| callback(s))
             },
-            CSSFontFaceRule(_) => {},
+            CSSFontFaceRule(_) | CSSKeyframesRule(_) => {},
+        }
+    }
+}
+
+/// Calls the callback with the index of each `@import` rule of the stylesheet. The rules of
+/// imported stylesheets are not included.
+pub fn iter_stylesheet_import_rules(stylesheet: &Stylesheet, callback: |uint, &ImportRule|) {
+    for (index, rule) in stylesheet.rules.iter().enumerate() {
+        match *rule {
+            CSSImportRule(ref rule) => callback(index, rule),
+            _ => {}
+        }
+    }
+}
+
+/// Sets the stylesheet that an `@import` rule imports. The rule is found by its index in the
+/// given stylesheet, then by its index in the stylesheets imported from there, and so on.
+/// Returns false if there is no such rule.
+pub fn set_imported_stylesheet(stylesheet: &mut Stylesheet, rule_path: &[uint],
+                               imported: Stylesheet) -> bool {
+    let rule = match rule_path.head().and_then(|&index| stylesheet.rules.get_mut(index)) {
+        Some(&mut CSSImportRule(ref mut rule)) => rule,
+        _ => return false,
+    };
+    if rule_path.len() == 1 {
+        rule.stylesheet = Some(box imported);
+        return true
+    }
+    match rule.stylesheet {
+        Some(ref mut stylesheet) => {
+            set_imported_stylesheet(&mut **stylesheet, rule_path.tail(), imported)
         }
+        None => false,
     }
 }
 
+/// Calls the callback with the media queries of every `@import` and `@media` rule of the
+/// stylesheet, including those of the stylesheets it imports.
+pub fn iter_stylesheet_media_queries(stylesheet: &Stylesheet, callback: |&MediaQueryList|) {
+    iter_stylesheet_media_rules(stylesheet, |rule| callback(&rule.media_queries));
+    iter_stylesheet_import_rules(stylesheet, |_, rule| {
+        callback(&rule.media_queries);
+        match rule.stylesheet {
+            Some(ref stylesheet) => iter_stylesheet_media_queries(&**stylesheet, |q| callback(q)),
+            None => {}
+        }
+    });
+}
+
 pub fn iter_stylesheet_media_rules(stylesheet: &Stylesheet, callback: |&MediaRule|) {
     for rule in stylesheet.rules.iter() {
         match *rule {
@@ -191,3 +295,64 @@ pub fn iter_font_face_rules(stylesheet: &Stylesheet, device: &Device,
                             callback: |family: &str, source: &Source|) {
     iter_font_face_rules_inner(stylesheet.rules.as_slice(), device, callback)
 }
+
+
+#[inline]
+pub fn iter_keyframes_rules(stylesheet: &Stylesheet, device: &Device,
+                            callback: |&KeyframesRule|) {
+    iter_keyframes_rules_inner(stylesheet.rules.as_slice(), device, callback)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use geom::size::TypedSize2D;
+    use media_queries::{Device, Print, Screen};
+    use selector_matching::AuthorOrigin;
+    use super::*;
+    use url::Url;
+
+    fn import_urls(stylesheet: &Stylesheet) -> Vec<String> {
+        let mut urls = vec!();
+        iter_stylesheet_import_rules(stylesheet, |_, rule| urls.push(rule.url.serialize()));
+        urls
+    }
+
+    fn style_rule_count(stylesheet: &Stylesheet, device: &Device) -> uint {
+        let mut count = 0u;
+        iter_stylesheet_style_rules(stylesheet, device, |_| count += 1);
+        count
+    }
+
+    #[test]
+    fn test_parse_import_rules() {
+        let url = Url::parse("http://localhost/css/main.css").unwrap();
+        let css = "@charset \"utf-8\"; @import url(a.css); @import \"/b.css\" print; \
+                   @import; p { color: red } @import \"c.css\";";
+        let stylesheet = Stylesheet::from_str(css, url, AuthorOrigin);
+        assert_eq!(import_urls(&stylesheet), vec!("http://localhost/css/a.css".to_string(),
+                                                  "http://localhost/b.css".to_string()));
+    }
+
+    #[test]
+    fn test_imported_stylesheets() {
+        let url = Url::parse("http://localhost/").unwrap();
+        let mut stylesheet = Stylesheet::from_str("@import \"a.css\"; @import \"b.css\" print; p {}",
+                                                  url.clone(), AuthorOrigin);
+        let screen = Device::new(Screen, TypedSize2D(800.0, 600.0));
+        let print = Device::new(Print, TypedSize2D(800.0, 600.0));
+        assert_eq!(style_rule_count(&stylesheet, &screen), 1);
+
+        let a = Stylesheet::from_str("@import \"c.css\"; a {} b {}", url.clone(), AuthorOrigin);
+        assert!(set_imported_stylesheet(&mut stylesheet, &[0], a));
+        let b = Stylesheet::from_str("b {}", url.clone(), AuthorOrigin);
+        assert!(set_imported_stylesheet(&mut stylesheet, &[1], b));
+        let c = Stylesheet::from_str("c {}", url.clone(), AuthorOrigin);
+        assert!(set_imported_stylesheet(&mut stylesheet, &[0, 0], c));
+        let d = Stylesheet::from_str("d {}", url.clone(), AuthorOrigin);
+        assert!(!set_imported_stylesheet(&mut stylesheet, &[2], d));
+
+        assert_eq!(style_rule_count(&stylesheet, &screen), 4);
+        assert_eq!(style_rule_count(&stylesheet, &print), 5);
+    }
+}
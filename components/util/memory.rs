@@ -17,6 +17,7 @@ use task::spawn_named;
 #[cfg(target_os="macos")]
 use task_info::task_basic_info::{virtual_size,resident_size};
 
+#[deriving(Clone)]
 pub struct MemoryProfilerChan(pub Sender<MemoryProfilerMsg>);
 
 impl MemoryProfilerChan {
@@ -26,20 +27,35 @@ impl MemoryProfilerChan {
     }
 }
 
+/// A source of measurements, such as a cache, that the memory profiler prints along with its
+/// own.
+pub trait MemoryReporter {
+    /// Returns the measurements as pairs of a path and a size in bytes. Called from the memory
+    /// profiler task.
+    fn collect_reports(&self) -> Vec<(String, Option<u64>)>;
+}
+
 pub enum MemoryProfilerMsg {
     /// Message used to force print the memory profiling metrics.
     PrintMsg,
+    /// Adds a reporter whose measurements are printed with every report.
+    RegisterReporterMsg(Box<MemoryReporter + Send>),
+    /// Sends the measurements of a report, as pairs of a path and a size in bytes, instead of
+    /// printing them.
+    CollectReportsMsg(Sender<Vec<(String, Option<u64>)>>),
     /// Tells the memory profiler to shut down.
     ExitMsg,
 }
 
 pub struct MemoryProfiler {
     pub port: Receiver<MemoryProfilerMsg>,
+    reporters: Vec<Box<MemoryReporter + Send>>,
 }
 
 impl MemoryProfiler {
     pub fn create(period: Option<f64>) -> MemoryProfilerChan {
         let (chan, port) = channel();
+        // Without a period nothing is printed, but reports can still be collected.
         match period {
             Some(period) => {
                 let period = Duration::milliseconds((period * 1000f64) as i64);
@@ -52,36 +68,27 @@ impl MemoryProfiler {
                         }
                     }
                 });
-                // Spawn the memory profiler.
-                spawn_named("Memory profiler", proc() {
-                    let memory_profiler = MemoryProfiler::new(port);
-                    memory_profiler.start();
-                });
-            }
-            None => {
-                // No-op to handle messages when the memory profiler is
-                // inactive.
-                spawn_named("Memory profiler", proc() {
-                    loop {
-                        match port.recv_opt() {
-                            Err(_) | Ok(ExitMsg) => break,
-                            _ => {}
-                        }
-                    }
-                });
             }
+            None => {}
         }
 
+        // Spawn the memory profiler.
+        spawn_named("Memory profiler", proc() {
+            let mut memory_profiler = MemoryProfiler::new(port);
+            memory_profiler.start();
+        });
+
         MemoryProfilerChan(chan)
     }
 
     pub fn new(port: Receiver<MemoryProfilerMsg>) -> MemoryProfiler {
         MemoryProfiler {
-            port: port
+            port: port,
+            reporters: vec!(),
         }
     }
 
-    pub fn start(&self) {
+    pub fn start(&mut self) {
         loop {
             match self.port.recv_opt() {
                Ok(msg) => {
@@ -94,12 +101,20 @@ impl MemoryProfiler {
         }
     }
 
-    fn handle_msg(&self, msg: MemoryProfilerMsg) -> bool {
+    fn handle_msg(&mut self, msg: MemoryProfilerMsg) -> bool {
         match msg {
             PrintMsg => {
                 self.handle_print_msg();
                 true
             },
+            RegisterReporterMsg(reporter) => {
+                self.reporters.push(reporter);
+                true
+            },
+            CollectReportsMsg(response) => {
+                response.send_opt(self.collect_measurements()).ok();
+                true
+            },
             ExitMsg => false
         }
     }
@@ -118,28 +133,37 @@ impl MemoryProfiler {
 
     fn handle_print_msg(&self) {
         println!("{:16s}: {:12s}", "_category_", "_size (MiB)_");
+        for &(ref path, nbytes) in self.collect_measurements().iter() {
+            MemoryProfiler::print_measurement(path.as_slice(), nbytes);
+        }
+        println!("");
+    }
 
+    fn collect_measurements(&self) -> Vec<(String, Option<u64>)> {
         // Virtual and physical memory usage, as reported by the OS.
-        MemoryProfiler::print_measurement("vsize",          get_vsize());
-        MemoryProfiler::print_measurement("resident",       get_resident());
+        let mut measurements = vec!(("vsize".to_string(), get_vsize()),
+                                    ("resident".to_string(), get_resident()));
 
         // The descriptions of the jemalloc measurements are taken directly
         // from the jemalloc documentation.
 
         // Total number of bytes allocated by the application.
-        MemoryProfiler::print_measurement("heap-allocated", get_jemalloc_stat("stats.allocated"));
+        measurements.push(("heap-allocated".to_string(), get_jemalloc_stat("stats.allocated")));
 
         // Total number of bytes in active pages allocated by the application.
         // This is a multiple of the page size, and greater than or equal to
         // |stats.allocated|.
-        MemoryProfiler::print_measurement("heap-active",    get_jemalloc_stat("stats.active"));
+        measurements.push(("heap-active".to_string(), get_jemalloc_stat("stats.active")));
 
         // Total number of bytes in chunks mapped on behalf of the application.
         // This is a multiple of the chunk size, and is at least as large as
         // |stats.active|. This does not include inactive chunks.
-        MemoryProfiler::print_measurement("heap-mapped",    get_jemalloc_stat("stats.mapped"));
+        measurements.push(("heap-mapped".to_string(), get_jemalloc_stat("stats.mapped")));
 
-        println!("");
+        for reporter in self.reporters.iter() {
+            measurements.extend(reporter.collect_reports().into_iter());
+        }
+        measurements
     }
 }
 
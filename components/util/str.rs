@@ -204,3 +204,23 @@ impl Str for LowercaseString {
         self.inner.as_slice()
     }
 }
+
+/// Escapes text for use in HTML, either as text or as a quoted attribute value.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[test]
+fn test_escape_html() {
+    assert_eq!(escape_html("<a href=\"?a&b\">").as_slice(), "&lt;a href=&quot;?a&amp;b&quot;&gt;");
+}
@@ -27,9 +27,9 @@ impl TimeProfilerChan {
 
 #[deriving(PartialEq, Clone, PartialOrd, Eq, Ord)]
 pub struct TimerMetadata {
-    url:         String,
-    iframe:      bool,
-    incremental: bool,
+    pub url:         String,
+    pub iframe:      bool,
+    pub incremental: bool,
 }
 
 pub trait Formatable {
@@ -63,6 +63,8 @@ pub enum TimeProfilerMsg {
     TimeMsg((TimeProfilerCategory, Option<TimerMetadata>), f64),
     /// Message used to force print the profiling metrics
     PrintMsg,
+    /// Sends the statistics of every bucket instead of printing them
+    CollectMsg(Sender<Vec<TimeProfilerBucket>>),
     /// Tells the profiler to shut down.
     ExitMsg,
 }
@@ -127,6 +129,18 @@ impl Formatable for TimeProfilerCategory {
     }
 }
 
+/// The statistics of the times recorded for a category, and for a page if it has metadata.
+#[deriving(Clone)]
+pub struct TimeProfilerBucket {
+    pub category: TimeProfilerCategory,
+    pub metadata: Option<TimerMetadata>,
+    pub mean: f64,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+    pub events: uint,
+}
+
 type TimeProfilerBuckets = TreeMap<(TimeProfilerCategory, Option<TimerMetadata>), Vec<f64>>;
 
 // back end of the profiler that handles data aggregation and performance metrics
@@ -158,7 +172,8 @@ impl TimeProfiler {
                 });
             }
             None => {
-                // No-op to handle messages when the time profiler is inactive.
+                // No-op to handle messages when the time profiler is inactive. Nothing is
+                // recorded, so collecting drops the response channel.
                 spawn_named("Time profiler", proc() {
                     loop {
                         match port.recv_opt() {
@@ -212,6 +227,11 @@ impl TimeProfiler {
                 Some(TimeMsg(..)) => self.print_buckets(),
                 _ => ()
             },
+            CollectMsg(response) => {
+                // Collecting is not a printout, so it leaves `last_msg` alone.
+                response.send_opt(self.collect_buckets()).ok();
+                return true
+            }
             ExitMsg => return false,
         };
         self.last_msg = Some(msg);
@@ -223,6 +243,16 @@ impl TimeProfiler {
                  "_category_", "_incremental?_", "_iframe?_",
                  "            _url_", "    _mean (ms)_", "  _median (ms)_",
                  "     _min (ms)_", "     _max (ms)_", "      _events_");
+        for bucket in self.collect_buckets().iter() {
+            println!("{:-35s}{} {:15.4f} {:15.4f} {:15.4f} {:15.4f} {:15u}",
+                     bucket.category.format(), bucket.metadata.format(), bucket.mean,
+                     bucket.median, bucket.min, bucket.max, bucket.events);
+        }
+        println!("");
+    }
+
+    fn collect_buckets(&mut self) -> Vec<TimeProfilerBucket> {
+        let mut buckets = vec!();
         for (&(ref category, ref meta), ref mut data) in self.buckets.iter_mut() {
             data.sort_by(|a, b| {
                 if a < b {
@@ -233,16 +263,18 @@ impl TimeProfiler {
             });
             let data_len = data.len();
             if data_len > 0 {
-                let (mean, median, min, max) =
-                    (data.iter().map(|&x|x).sum() / (data_len as f64),
-                     data.as_slice()[data_len / 2],
-                     data.iter().fold(f64::INFINITY, |a, &b| a.min(b)),
-                     data.iter().fold(-f64::INFINITY, |a, &b| a.max(b)));
-                println!("{:-35s}{} {:15.4f} {:15.4f} {:15.4f} {:15.4f} {:15u}",
-                         category.format(), meta.format(), mean, median, min, max, data_len);
+                buckets.push(TimeProfilerBucket {
+                    category: category.clone(),
+                    metadata: meta.clone(),
+                    mean: data.iter().map(|&x|x).sum() / (data_len as f64),
+                    median: data.as_slice()[data_len / 2],
+                    min: data.iter().fold(f64::INFINITY, |a, &b| a.min(b)),
+                    max: data.iter().fold(-f64::INFINITY, |a, &b| a.max(b)),
+                    events: data_len,
+                });
             }
         }
-        println!("");
+        buckets
     }
 }
 
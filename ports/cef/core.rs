@@ -5,6 +5,7 @@
 use browser::{GLOBAL_BROWSERS, browser_callback_after_created};
 use command_line::command_line_init;
 use interfaces::cef_app_t;
+use request_handler::load_interceptors;
 use eutil::Downcast;
 use switches::{KPROCESSTYPE, KWAITFORDEBUGGER};
 use types::{cef_main_args_t, cef_settings_t};
@@ -59,9 +60,9 @@ pub extern "C" fn cef_run_message_loop() {
                 *active_browser.downcast().window.borrow_mut() =
                     Some(glfw_app::create_window());
                 *active_browser.downcast().servo_browser.borrow_mut() =
-                    Some(Browser::new((*active_browser.downcast()
-                                                      .window
-                                                      .borrow()).clone()));
+                    Some(Browser::new_with_load_interceptors(
+                        (*active_browser.downcast().window.borrow()).clone(),
+                        load_interceptors(active_browser)));
                 if !active_browser.downcast().callback_executed.get() {
                     browser_callback_after_created((*active_browser).clone());
                 }
@@ -0,0 +1,51 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use interfaces::{CefBrowser, CefFrame, cef_browser_t, cef_frame_t};
+use types::cef_string_userfree_t;
+
+use eutil::Downcast;
+
+use libc::c_int;
+
+/// The main frame of a browser. Servo does not expose its subframes to embedders yet.
+pub struct ServoCefFrame {
+    pub browser: CefBrowser,
+}
+
+impl ServoCefFrame {
+    pub fn new(browser: CefBrowser) -> ServoCefFrame {
+        ServoCefFrame {
+            browser: browser,
+        }
+    }
+}
+
+cef_class_impl! {
+    ServoCefFrame : CefFrame, cef_frame_t {
+        fn is_valid(&this) -> c_int {
+            1i32
+        }
+
+        fn is_main(&this) -> c_int {
+            1i32
+        }
+
+        fn is_focused(&this) -> c_int {
+            1i32
+        }
+
+        fn get_name(&this) -> cef_string_userfree_t {
+            String::new()
+        }
+
+        fn get_identifier(&this) -> i64 {
+            0i64
+        }
+
+        fn get_browser(&this) -> *mut cef_browser_t {
+            this.downcast().browser.clone()
+        }
+    }
+}
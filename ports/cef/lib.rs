@@ -23,6 +23,7 @@ extern crate geom;
 extern crate gfx;
 extern crate glfw;
 extern crate glfw_app;
+extern crate http;
 extern crate js;
 extern crate layers;
 extern crate png;
@@ -53,11 +54,13 @@ pub mod cookie;
 pub mod core;
 pub mod drag_data;
 pub mod eutil;
+pub mod frame;
 pub mod interfaces;
 pub mod print_settings;
 pub mod process_message;
 pub mod request;
 pub mod request_context;
+pub mod request_handler;
 pub mod response;
 pub mod stream;
 pub mod string;
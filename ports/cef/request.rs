@@ -2,11 +2,80 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use interfaces::{cef_post_data_element_t, cef_post_data_t, cef_request_t};
+use interfaces::{CefRequest, cef_post_data_element_t, cef_post_data_t, cef_request_t};
+use string_multimap::{cef_string_multimap_clear, string_multimap_append};
+use string_multimap::string_multimap_entries;
+use types::{cef_string_multimap_t, cef_string_t, cef_string_userfree_t};
+
+use eutil::Downcast;
+
+use libc::c_int;
+use std::cell::RefCell;
+
+/// A request that Servo is about to load, as seen by the request handler of an embedder.
+pub struct ServoCefRequest {
+    pub url: RefCell<String>,
+    pub method: RefCell<String>,
+    /// The names and values of the request headers, in order.
+    pub headers: RefCell<Vec<(String, String)>>,
+}
+
+impl ServoCefRequest {
+    pub fn new(url: String, method: String, headers: Vec<(String, String)>) -> ServoCefRequest {
+        ServoCefRequest {
+            url: RefCell::new(url),
+            method: RefCell::new(method),
+            headers: RefCell::new(headers),
+        }
+    }
+}
+
+cef_class_impl! {
+    ServoCefRequest : CefRequest, cef_request_t {
+        fn is_read_only(&this) -> c_int {
+            0i32
+        }
+
+        fn get_url(&this) -> cef_string_userfree_t {
+            this.downcast().url.borrow().clone()
+        }
+
+        fn set_url(&this, url: *const cef_string_t) -> () {
+            let url: &[u16] = url;
+            *this.downcast().url.borrow_mut() = String::from_utf16_lossy(url)
+        }
+
+        fn get_method(&this) -> cef_string_userfree_t {
+            this.downcast().method.borrow().clone()
+        }
+
+        fn set_method(&this, method: *const cef_string_t) -> () {
+            let method: &[u16] = method;
+            *this.downcast().method.borrow_mut() = String::from_utf16_lossy(method)
+        }
+
+        fn get_header_map(&this, header_map: cef_string_multimap_t) -> () {
+            let header_map: cef_string_multimap_t = header_map;
+            cef_string_multimap_clear(header_map);
+            for &(ref name, ref value) in this.downcast().headers.borrow().iter() {
+                string_multimap_append(header_map, name.as_slice(), value.as_slice());
+            }
+        }
+
+        fn set_header_map(&this, header_map: cef_string_multimap_t) -> () {
+            let header_map: cef_string_multimap_t = header_map;
+            *this.downcast().headers.borrow_mut() = string_multimap_entries(header_map)
+        }
+    }
+}
+
+cef_static_method_impls! {
+    fn cef_request_create() -> *mut cef_request_t {
+        ServoCefRequest::new(String::new(), "GET".to_string(), vec!()).as_cef_interface()
+    }
+}
 
 cef_stub_static_method_impls! {
-    fn cef_request_create() -> *mut cef_request_t;
     fn cef_post_data_create() -> *mut cef_post_data_t;
     fn cef_post_data_element_create() -> *mut cef_post_data_element_t;
 }
-
@@ -0,0 +1,174 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Maps the request handler of a CEF client onto the load interceptors of the resource task.
+
+use frame::ServoCefFrame;
+use interfaces::{CefBrowser, CefCallback, CefFrame, CefRequest, CefRequestHandler};
+use interfaces::{CefResourceHandler, cef_callback_t};
+use request::ServoCefRequest;
+use response::ServoCefResponse;
+use string::empty_utf16_string;
+use wrappers::CefWrap;
+
+use eutil::Downcast;
+use http::headers::HeaderEnum;
+use http::headers::content_type::MediaType;
+use http::headers::request::HeaderCollection as RequestHeaderCollection;
+use http::headers::response::HeaderCollection as ResponseHeaderCollection;
+use http::headers::test_utils::from_stream_with_str;
+use http::method::Method;
+use http::status::Status;
+use servo_net::load_interceptor::{BlockLoad, ContinueLoad, InterceptedLoad, LoadInterceptor};
+use servo_net::load_interceptor::{RedirectLoad, RespondToLoad};
+use servo_net::resource_task::{LoadData, Metadata};
+use std_url::Url;
+
+/// Tells the resource task whether a resource handler continued or canceled the load.
+struct ServoCefCallback {
+    sender: Sender<bool>,
+}
+
+cef_class_impl! {
+    ServoCefCallback : CefCallback, cef_callback_t {
+        fn cont(&this) -> () {
+            let _ = this.downcast().sender.send_opt(true);
+        }
+
+        fn cancel(&this) -> () {
+            let _ = this.downcast().sender.send_opt(false);
+        }
+    }
+}
+
+/// Offers every load to `on_before_resource_load`, which may cancel it or change its URL, method
+/// or headers, and then to `get_resource_handler`, which may answer it.
+struct CefRequestInterceptor {
+    browser: CefBrowser,
+    frame: CefFrame,
+    handler: CefRequestHandler,
+}
+
+impl LoadInterceptor for CefRequestInterceptor {
+    fn intercept(&self, load_data: &mut LoadData) -> InterceptedLoad {
+        let headers: Vec<(String, String)> = load_data.headers.iter().map(|header| {
+            (header.header_name(), header.header_value())
+        }).collect();
+        let request = ServoCefRequest::new(load_data.url.serialize(),
+                                           load_data.method.to_string(),
+                                           headers.clone()).as_cef_interface();
+        if self.handler.on_before_resource_load(self.browser.clone(), self.frame.clone(),
+                                                request.clone()) != 0 {
+            return BlockLoad("The load was canceled by the request handler.".to_string())
+        }
+
+        let method = request.downcast().method.borrow().clone();
+        if method != load_data.method.to_string() {
+            match Method::from_str_or_new(method.as_slice()) {
+                Some(method) => load_data.method = method,
+                None => return BlockLoad(format!("Invalid method {:s}.", method)),
+            }
+        }
+        if *request.downcast().headers.borrow() != headers {
+            let mut headers = RequestHeaderCollection::new();
+            for &(ref name, ref value) in request.downcast().headers.borrow().iter() {
+                let _ = headers.insert_raw(name.clone(), value.as_bytes());
+            }
+            load_data.headers = headers;
+        }
+        let url = request.downcast().url.borrow().clone();
+        if url != load_data.url.serialize() {
+            return match Url::parse(url.as_slice()) {
+                Ok(url) => RedirectLoad(url),
+                Err(_) => BlockLoad(format!("Invalid URL {:s}.", url)),
+            }
+        }
+
+        let resource_handler = self.handler.get_resource_handler(self.browser.clone(),
+                                                                 self.frame.clone(),
+                                                                 request.clone());
+        if resource_handler.is_null_cef_object() {
+            return ContinueLoad
+        }
+        respond(resource_handler, request, load_data.url.clone())
+    }
+}
+
+/// Answers a load with the response that a resource handler provides.
+fn respond(handler: CefResourceHandler, request: CefRequest, url: Url) -> InterceptedLoad {
+    let (sender, receiver) = channel();
+    let callback = ServoCefCallback {
+        sender: sender,
+    }.as_cef_interface();
+    let canceled = "The load was canceled by the resource handler.".to_string();
+    if handler.process_request(request, callback.clone()) == 0 || !receiver.recv() {
+        return BlockLoad(canceled)
+    }
+
+    let response = ServoCefResponse::new().as_cef_interface();
+    let mut length = -1i64;
+    let mut redirect_url = empty_utf16_string();
+    handler.get_response_headers(response.clone(), &mut length, &mut redirect_url);
+    let redirect_url: String = unsafe {
+        CefWrap::to_rust(redirect_url)
+    };
+    if !redirect_url.is_empty() {
+        return match Url::parse(redirect_url.as_slice()) {
+            Ok(url) => RedirectLoad(url),
+            Err(_) => BlockLoad(format!("Invalid URL {:s}.", redirect_url)),
+        }
+    }
+
+    let mut body = vec!();
+    while length < 0 || (body.len() as i64) < length {
+        let mut buffer = Vec::from_elem(4096, 0u8);
+        let mut bytes_read = 0;
+        let data_out = unsafe {
+            &mut *(buffer.as_mut_ptr() as *mut ())
+        };
+        if handler.read_response(data_out, buffer.len() as i32, &mut bytes_read,
+                                 callback.clone()) == 0 {
+            break
+        }
+        if bytes_read == 0 {
+            // The handler calls back once more data is available.
+            if !receiver.recv() {
+                return BlockLoad(canceled)
+            }
+            continue
+        }
+        body.push_all(buffer.slice_to(bytes_read as uint));
+    }
+
+    let response = response.downcast();
+    let mut metadata = Metadata::default(url);
+    let content_type: Option<MediaType> =
+        from_stream_with_str(response.mime_type.borrow().as_slice());
+    metadata.set_content_type(&content_type);
+    let mut headers = ResponseHeaderCollection::new();
+    for &(ref name, ref value) in response.headers.borrow().iter() {
+        let _ = headers.insert_raw(name.clone(), value.as_bytes());
+    }
+    metadata.headers = Some(headers);
+    metadata.status = Some(Status::from_code_and_reason(response.status.get() as u16,
+                                                        response.status_text.borrow().clone()));
+    RespondToLoad(metadata, body)
+}
+
+/// Returns the interceptors that hand the loads of the given browser to its client.
+pub fn load_interceptors(browser: &CefBrowser) -> Vec<Box<LoadInterceptor + Send + Sync>> {
+    let client = browser.downcast().client.clone();
+    if client.is_null_cef_object() {
+        return vec!()
+    }
+    let handler = client.get_request_handler();
+    if handler.is_null_cef_object() {
+        return vec!()
+    }
+    vec!(box CefRequestInterceptor {
+        browser: browser.clone(),
+        frame: ServoCefFrame::new(browser.clone()).as_cef_interface(),
+        handler: handler,
+    } as Box<LoadInterceptor + Send + Sync>)
+}
@@ -2,9 +2,95 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use interfaces::cef_response_t;
+use interfaces::{CefResponse, cef_response_t};
+use string_multimap::{cef_string_multimap_clear, string_multimap_append};
+use string_multimap::string_multimap_entries;
+use types::{cef_string_multimap_t, cef_string_t, cef_string_userfree_t};
 
-cef_stub_static_method_impls! {
-    fn cef_response_create() -> *mut cef_response_t;
+use eutil::Downcast;
+
+use libc::c_int;
+use std::ascii::AsciiExt;
+use std::cell::{Cell, RefCell};
+
+/// A response that the resource handler of an embedder gives in place of loading a resource.
+pub struct ServoCefResponse {
+    pub status: Cell<c_int>,
+    pub status_text: RefCell<String>,
+    pub mime_type: RefCell<String>,
+    /// The names and values of the response headers, in order.
+    pub headers: RefCell<Vec<(String, String)>>,
+}
+
+impl ServoCefResponse {
+    pub fn new() -> ServoCefResponse {
+        ServoCefResponse {
+            status: Cell::new(200),
+            status_text: RefCell::new("OK".to_string()),
+            mime_type: RefCell::new(String::new()),
+            headers: RefCell::new(vec!()),
+        }
+    }
+}
+
+cef_class_impl! {
+    ServoCefResponse : CefResponse, cef_response_t {
+        fn is_read_only(&this) -> c_int {
+            0i32
+        }
+
+        fn get_status(&this) -> c_int {
+            this.downcast().status.get()
+        }
+
+        fn set_status(&this, status: c_int) -> () {
+            this.downcast().status.set(status)
+        }
+
+        fn get_status_text(&this) -> cef_string_userfree_t {
+            this.downcast().status_text.borrow().clone()
+        }
+
+        fn set_status_text(&this, status_text: *const cef_string_t) -> () {
+            let status_text: &[u16] = status_text;
+            *this.downcast().status_text.borrow_mut() = String::from_utf16_lossy(status_text)
+        }
+
+        fn get_mime_type(&this) -> cef_string_userfree_t {
+            this.downcast().mime_type.borrow().clone()
+        }
+
+        fn set_mime_type(&this, mime_type: *const cef_string_t) -> () {
+            let mime_type: &[u16] = mime_type;
+            *this.downcast().mime_type.borrow_mut() = String::from_utf16_lossy(mime_type)
+        }
+
+        fn get_header(&this, name: *const cef_string_t) -> cef_string_userfree_t {
+            let name: &[u16] = name;
+            let name = String::from_utf16_lossy(name);
+            let headers = this.downcast().headers.borrow();
+            headers.iter().find(|&&(ref header, _)| {
+                header.as_slice().eq_ignore_ascii_case(name.as_slice())
+            }).map(|&(_, ref value)| value.clone()).unwrap_or(String::new())
+        }
+
+        fn get_header_map(&this, header_map: cef_string_multimap_t) -> () {
+            let header_map: cef_string_multimap_t = header_map;
+            cef_string_multimap_clear(header_map);
+            for &(ref name, ref value) in this.downcast().headers.borrow().iter() {
+                string_multimap_append(header_map, name.as_slice(), value.as_slice());
+            }
+        }
+
+        fn set_header_map(&this, header_map: cef_string_multimap_t) -> () {
+            let header_map: cef_string_multimap_t = header_map;
+            *this.downcast().headers.borrow_mut() = string_multimap_entries(header_map)
+        }
+    }
 }
 
+cef_static_method_impls! {
+    fn cef_response_create() -> *mut cef_response_t {
+        ServoCefResponse::new().as_cef_interface()
+    }
+}
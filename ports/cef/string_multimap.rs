@@ -2,23 +2,67 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use eutil::slice_to_str;
-use libc::{c_int};
+use libc::{c_int, c_ushort, size_t};
 use std::collections::TreeMap;
 use std::iter::AdditiveIterator;
 use std::mem;
+use std::ptr;
+use std::slice;
 use std::string::String;
 use string::{cef_string_userfree_utf16_alloc,cef_string_userfree_utf16_free,cef_string_utf16_set};
+use string::empty_utf16_string;
 use types::{cef_string_multimap_t,cef_string_t};
 
-fn string_multimap_to_treemap(smm: *mut cef_string_multimap_t) -> *mut TreeMap<String, Vec<*mut cef_string_t>> {
+fn string_multimap_to_treemap(smm: cef_string_multimap_t) -> *mut TreeMap<String, Vec<*mut cef_string_t>> {
     smm as *mut TreeMap<String, Vec<*mut cef_string_t>>
 }
 
+unsafe fn cef_string_to_string(cs: *const cef_string_t) -> String {
+    slice::raw::buf_as_slice((*cs).str as *const u16, (*cs).length as uint, |result| {
+        String::from_utf16_lossy(result)
+    })
+}
+
+unsafe fn append(smm: cef_string_multimap_t, key: String, value: *const c_ushort,
+                 value_len: size_t) {
+    let v = string_multimap_to_treemap(smm);
+    let csv = cef_string_userfree_utf16_alloc();
+    ptr::write(csv, empty_utf16_string());
+    cef_string_utf16_set(value, value_len, csv, 1);
+    match (*v).get_mut(&key) {
+        Some(vc) => (*vc).push(csv),
+        None => { (*v).insert(key, vec!(csv)); }
+    }
+}
+
+/// Appends a value to a string multimap on behalf of Servo.
+pub fn string_multimap_append(smm: cef_string_multimap_t, key: &str, value: &str) {
+    if smm.is_null() { return; }
+    let value: Vec<u16> = value.utf16_units().collect();
+    unsafe {
+        append(smm, key.to_string(), value.as_ptr(), value.len() as size_t);
+    }
+}
+
+/// Returns the keys and values of a string multimap, in order.
+pub fn string_multimap_entries(smm: cef_string_multimap_t) -> Vec<(String, String)> {
+    let mut entries = vec!();
+    if smm.is_null() { return entries; }
+    unsafe {
+        let v = string_multimap_to_treemap(smm);
+        for (key, val) in (*v).iter() {
+            for cs in val.iter() {
+                entries.push((key.clone(), cef_string_to_string(*cs as *const cef_string_t)));
+            }
+        }
+    }
+    entries
+}
+
 //cef_string_multimap
 
 #[no_mangle]
-pub extern "C" fn cef_string_multimap_alloc() -> *mut cef_string_multimap_t {
+pub extern "C" fn cef_string_multimap_alloc() -> cef_string_multimap_t {
     unsafe {
          let smm: Box<TreeMap<String, Vec<*mut cef_string_t>>> = box TreeMap::new();
          mem::transmute(smm)
@@ -26,7 +70,7 @@ pub extern "C" fn cef_string_multimap_alloc() -> *mut cef_string_multimap_t {
 }
 
 #[no_mangle]
-pub extern "C" fn cef_string_multimap_size(smm: *mut cef_string_multimap_t) -> c_int {
+pub extern "C" fn cef_string_multimap_size(smm: cef_string_multimap_t) -> c_int {
     unsafe {
         if smm.is_null() { return 0; }
         let v = string_multimap_to_treemap(smm);
@@ -35,59 +79,46 @@ pub extern "C" fn cef_string_multimap_size(smm: *mut cef_string_multimap_t) -> c
 }
 
 #[no_mangle]
-pub extern "C" fn cef_string_multimap_find_count(smm: *mut cef_string_multimap_t, key: *const cef_string_t) -> c_int {
+pub extern "C" fn cef_string_multimap_find_count(smm: cef_string_multimap_t, key: *const cef_string_t) -> c_int {
     unsafe {
         if smm.is_null() { return 0; }
         let v = string_multimap_to_treemap(smm);
-        slice_to_str((*key).str as *const u8, (*key).length as uint, |result| {
-            match (*v).get(&String::from_str(result)) {
-                Some(s) =>  s.len() as c_int,
-                None => 0
-            }
-        })
+        match (*v).get(&cef_string_to_string(key)) {
+            Some(s) =>  s.len() as c_int,
+            None => 0
+        }
     }
 }
 
 #[no_mangle]
-pub extern "C" fn cef_string_multimap_append(smm: *mut cef_string_multimap_t, key: *const cef_string_t, value: *const cef_string_t) -> c_int {
+pub extern "C" fn cef_string_multimap_append(smm: cef_string_multimap_t, key: *const cef_string_t, value: *const cef_string_t) -> c_int {
     unsafe {
         if smm.is_null() { return 0; }
-        let v = string_multimap_to_treemap(smm);
-        slice_to_str((*key).str as *const u8, (*key).length as uint, |result| {
-            let s = String::from_str(result);
-            let csv = cef_string_userfree_utf16_alloc();
-            cef_string_utf16_set((*value).str as *const u16, (*value).length, csv, 1);
-            match (*v).get_mut(&s) {
-                Some(vc) => (*vc).push(csv),
-                None => { (*v).insert(s, vec!(csv)); }
-            }
-            1
-        })
+        append(smm, cef_string_to_string(key), (*value).str as *const u16, (*value).length);
+        1
     }
 }
 
 #[no_mangle]
-pub extern "C" fn cef_string_multimap_enumerate(smm: *mut cef_string_multimap_t, key: *const cef_string_t, index: c_int, value: *mut cef_string_t) -> c_int {
+pub extern "C" fn cef_string_multimap_enumerate(smm: cef_string_multimap_t, key: *const cef_string_t, index: c_int, value: *mut cef_string_t) -> c_int {
     unsafe {
         if smm.is_null() { return 0; }
         let v = string_multimap_to_treemap(smm);
-        slice_to_str((*key).str as *const u8, (*key).length as uint, |result| {
-            match (*v).get(&String::from_str(result)) {
-                Some(s) => {
-                    if (*s).len() <= index as uint {
-                        return 0;
-                    }
-                    let cs = (*s)[index as uint];
-                    cef_string_utf16_set((*cs).str as *const u16, (*cs).length, value, 1)
+        match (*v).get(&cef_string_to_string(key)) {
+            Some(s) => {
+                if (*s).len() <= index as uint {
+                    return 0;
                 }
-                None => 0
+                let cs = (*s)[index as uint];
+                cef_string_utf16_set((*cs).str as *const u16, (*cs).length, value, 1)
             }
-        })
+            None => 0
+        }
     }
 }
 
 #[no_mangle]
-pub extern "C" fn cef_string_multimap_key(smm: *mut cef_string_multimap_t, index: c_int, value: *mut cef_string_t) -> c_int {
+pub extern "C" fn cef_string_multimap_key(smm: cef_string_multimap_t, index: c_int, value: *mut cef_string_t) -> c_int {
     unsafe {
         if index < 0 || smm.is_null() { return 0; }
         let v = string_multimap_to_treemap(smm);
@@ -95,10 +126,8 @@ pub extern "C" fn cef_string_multimap_key(smm: *mut cef_string_multimap_t, index
 
         for (key, val) in (*v).iter() {
             if rem < (*val).len() {
-                return cef_string_utf16_set((*key).as_bytes().as_ptr() as *const u16,
-                                            (*key).len() as u64,
-                                            value,
-                                            1);
+                let key: Vec<u16> = key.as_slice().utf16_units().collect();
+                return cef_string_utf16_set(key.as_ptr(), key.len() as u64, value, 1);
             } else {
                 rem -= (*val).len();
             }
@@ -108,7 +137,7 @@ pub extern "C" fn cef_string_multimap_key(smm: *mut cef_string_multimap_t, index
 }
 
 #[no_mangle]
-pub extern "C" fn cef_string_multimap_value(smm: *mut cef_string_multimap_t, index: c_int, value: *mut cef_string_t) -> c_int {
+pub extern "C" fn cef_string_multimap_value(smm: cef_string_multimap_t, index: c_int, value: *mut cef_string_t) -> c_int {
     unsafe {
         if index < 0 || smm.is_null() { return 0; }
         let v = string_multimap_to_treemap(smm);
@@ -127,7 +156,7 @@ pub extern "C" fn cef_string_multimap_value(smm: *mut cef_string_multimap_t, ind
 }
 
 #[no_mangle]
-pub extern "C" fn cef_string_multimap_clear(smm: *mut cef_string_multimap_t) {
+pub extern "C" fn cef_string_multimap_clear(smm: cef_string_multimap_t) {
     unsafe {
         if smm.is_null() { return; }
         let v = string_multimap_to_treemap(smm);
@@ -143,7 +172,7 @@ pub extern "C" fn cef_string_multimap_clear(smm: *mut cef_string_multimap_t) {
 }
 
 #[no_mangle]
-pub extern "C" fn cef_string_multimap_free(smm: *mut cef_string_multimap_t) {
+pub extern "C" fn cef_string_multimap_free(smm: cef_string_multimap_t) {
     unsafe {
         if smm.is_null() { return; }
         let v: Box<TreeMap<String, Vec<*mut cef_string_t>>> = mem::transmute(smm);
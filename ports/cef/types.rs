@@ -12,7 +12,8 @@ use libc::types::os::arch::c95::wchar_t;
 pub use self::cef_rect as cef_rect_t;
 
 pub enum cef_string_map_t {}
-pub enum cef_string_multimap_t {}
+pub enum cef_string_multimap {}
+pub type cef_string_multimap_t = *mut cef_string_multimap;
 pub enum cef_string_list_t {}
 pub enum cef_text_input_context_t {}
 pub enum cef_event_handle_t {}
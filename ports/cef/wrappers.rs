@@ -34,6 +34,7 @@ use libc::{mod, c_char, c_int, c_ushort, c_void};
 use std::collections::HashMap;
 use std::mem;
 use std::ptr;
+use std::slice;
 
 pub trait CefWrap<CObject> {
     fn to_c(rust_object: Self) -> CObject;
@@ -129,6 +130,7 @@ cef_noop_wrapper!(*mut cef_load_handler_t)
 cef_noop_wrapper!(*mut cef_request_context_t)
 cef_noop_wrapper!(*mut cef_request_handler_t)
 cef_noop_wrapper!(*mut cef_string_list_t)
+cef_noop_wrapper!(cef_string_multimap_t)
 cef_noop_wrapper!(*mut cef_string_utf16)
 cef_noop_wrapper!(c_int)
 cef_noop_wrapper!(cef_color_model_t)
@@ -176,7 +178,6 @@ cef_unimplemented_wrapper!(*mut *mut cef_post_data_element_t, *mut CefPostDataEl
 cef_unimplemented_wrapper!(cef_string_list_t, Vec<String>)
 cef_unimplemented_wrapper!(cef_string_map_t, HashMap<String,String>)
 cef_unimplemented_wrapper!(cef_string_multimap_t, HashMap<String,Vec<String>>)
-cef_unimplemented_wrapper!(cef_string_t, String)
 
 impl<'a> CefWrap<*const cef_string_t> for &'a [u16] {
     fn to_c(buffer: &'a [u16]) -> *const cef_string_t {
@@ -203,6 +204,30 @@ impl<'a> CefWrap<*const cef_string_t> for &'a [u16] {
     }
 }
 
+impl CefWrap<cef_string_t> for String {
+    fn to_c(string: String) -> cef_string_t {
+        let buffer: Vec<u16> = string.as_slice().utf16_units().collect();
+        unsafe {
+            let ptr: *mut c_ushort = mem::transmute(libc::malloc(((buffer.len() + 1) * 2) as u64));
+            ptr::copy_memory(ptr, buffer.as_ptr(), buffer.len());
+            *ptr.offset(buffer.len() as int) = 0;
+            cef_string_utf16 {
+                str: ptr,
+                length: buffer.len() as u64,
+                dtor: Some(free_boxed_utf16_string),
+            }
+        }
+    }
+    unsafe fn to_rust(cef_string: cef_string_t) -> String {
+        let string = slice::raw::buf_as_slice(cef_string.str as *const u16,
+                                              cef_string.length as uint,
+                                              |buffer| String::from_utf16_lossy(buffer));
+        // The string is handed over to us, so we are the ones to free it.
+        cef_string.dtor.map(|dtor| dtor(cef_string.str));
+        string
+    }
+}
+
 extern "C" fn free_boxed_utf16_string(string: *mut c_ushort) {
     unsafe {
         libc::free(string as *mut c_void)
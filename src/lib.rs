@@ -42,7 +42,9 @@ use script::dom::bindings::codegen::RegisterBindings;
 #[cfg(not(test))]
 use servo_net::image_cache_task::ImageCacheTask;
 #[cfg(not(test))]
-use servo_net::resource_task::new_resource_task;
+use servo_net::load_interceptor::LoadInterceptor;
+#[cfg(not(test))]
+use servo_net::resource_task::{new_resource_task, RegisterAboutPage, RegisterLoadInterceptor};
 #[cfg(not(test))]
 use servo_net::storage_task::StorageTaskFactory;
 #[cfg(not(test))]
@@ -50,7 +52,7 @@ use gfx::font_cache_task::FontCacheTask;
 #[cfg(not(test))]
 use servo_util::time::TimeProfiler;
 #[cfg(not(test))]
-use servo_util::memory::MemoryProfiler;
+use servo_util::memory::{MemoryProfiler, RegisterReporterMsg};
 #[cfg(not(test))]
 use servo_util::opts;
 #[cfg(not(test))]
@@ -73,6 +75,14 @@ pub struct Browser<Window> {
 impl<Window> Browser<Window> where Window: WindowMethods + 'static {
     #[cfg(not(test))]
     pub fn new(window: Option<Rc<Window>>) -> Browser<Window> {
+        Browser::new_with_load_interceptors(window, vec!())
+    }
+
+    /// Creates a browser whose loads first go through the given interceptors, in order.
+    #[cfg(not(test))]
+    pub fn new_with_load_interceptors(window: Option<Rc<Window>>,
+                                      load_interceptors: Vec<Box<LoadInterceptor + Send + Sync>>)
+                                      -> Browser<Window> {
         use rustuv::EventLoop;
         fn event_loop() -> Box<green::EventLoop + Send> {
             box EventLoop::new().unwrap() as Box<green::EventLoop + Send>
@@ -97,6 +107,7 @@ impl<Window> Browser<Window> where Window: WindowMethods + 'static {
 
         let opts_clone = opts.clone();
         let time_profiler_chan_clone = time_profiler_chan.clone();
+        let memory_profiler_chan_clone = memory_profiler_chan.clone();
 
         let (result_chan, result_port) = channel();
         let compositor_proxy_for_constellation = compositor_proxy.clone_compositor_proxy();
@@ -105,7 +116,7 @@ impl<Window> Browser<Window> where Window: WindowMethods + 'static {
             .spawn(proc() {
             let opts = &opts_clone;
             // Create a Servo instance.
-            let resource_task = new_resource_task(opts.user_agent.clone());
+            let resource_task = new_resource_task(opts.network.clone());
             // If we are emitting an output file, then we need to block on
             // image load or we risk emitting an output file missing the
             // image.
@@ -114,6 +125,16 @@ impl<Window> Browser<Window> where Window: WindowMethods + 'static {
             } else {
                 ImageCacheTask::new(resource_task.clone(), shared_task_pool)
             };
+            memory_profiler_chan_clone.send(RegisterReporterMsg(box image_cache_task.clone()));
+            resource_task.send(RegisterAboutPage("memory".to_string(),
+                                                 box memory_profiler_chan_clone.clone()));
+            resource_task.send(RegisterAboutPage("profiling".to_string(),
+                                                 box time_profiler_chan_clone.clone()));
+            resource_task.send(RegisterAboutPage("cache".to_string(),
+                                                 box image_cache_task.clone()));
+            for interceptor in load_interceptors.into_iter() {
+                resource_task.send(RegisterLoadInterceptor(interceptor));
+            }
             let font_cache_task = FontCacheTask::new(resource_task.clone());
             let storage_task = StorageTaskFactory::new();
             let constellation_chan = Constellation::<layout::layout_task::LayoutTask,